@@ -0,0 +1,156 @@
+//! The consensus-critical paths, on deterministic inputs from `tiny_crypto::fixtures`.
+//! Run with `cargo bench`; `cargo bench -- merkle` runs one group.
+
+use std::{hint::black_box, thread};
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use tiny_crypto::{
+    config::MemPoolConfig,
+    crypto::{SignatureExt, verify_batch},
+    fixtures,
+    mem_pool::MemPool,
+    params::NetworkParams,
+    transaction::Transaction,
+    utxo_set::UTXOSet,
+};
+
+const BATCH_SIGNATURES: usize = 1_000;
+const BLOCK_TRANSACTIONS: usize = 1_000;
+const PENDING_TRANSACTIONS: usize = 1_000;
+
+fn header_hashing(c: &mut Criterion) {
+    let header = fixtures::header(1, 0);
+    let mut buf = Vec::new();
+
+    let mut group = c.benchmark_group("header_hash");
+    group.bench_function("hash", |b| b.iter(|| black_box(&header).hash().unwrap()));
+    group.bench_function("hash_with_buf", |b| {
+        b.iter(|| black_box(&header).hash_with_buf(&mut buf).unwrap())
+    });
+    group.finish();
+}
+
+fn nonce_search(c: &mut Criterion) {
+    // about 257 hashes a search, and a different number for each seed
+    let headers = (0..64)
+        .map(|seed| fixtures::header(seed, 1))
+        .collect::<Vec<_>>();
+    let mut seeds = headers.iter().cycle();
+
+    c.bench_function("nonce_search/difficulty_1", |b| {
+        b.iter(|| seeds.next().unwrap().compute_nonce_naive().unwrap())
+    });
+}
+
+fn signature_verification(c: &mut Criterion) {
+    let signed = fixtures::signed_digests(BATCH_SIGNATURES, 1);
+    let items = signed
+        .iter()
+        .map(|(digest, signature, public_key)| (*digest, signature, public_key))
+        .collect::<Vec<_>>();
+    let mut thread_counts = vec![
+        1,
+        thread::available_parallelism().map_or(1, |threads| threads.get()),
+    ];
+    thread_counts.dedup();
+
+    let transaction = &fixtures::coinbases(1, 1, &NetworkParams::regtest()).unwrap()[0];
+    let bytes = transaction.body.as_bytes().unwrap();
+    let signing_info = &transaction.signing_info;
+
+    let mut group = c.benchmark_group("ecdsa_verify");
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            assert!(
+                signing_info
+                    .signature
+                    .verify(black_box(&bytes), &signing_info.public_key)
+            )
+        })
+    });
+
+    group.throughput(Throughput::Elements(items.len() as u64));
+    for threads in thread_counts {
+        group.bench_with_input(
+            BenchmarkId::new("batch", threads),
+            &threads,
+            |b, &threads| b.iter(|| verify_batch(black_box(&items), threads).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn merkle_tree(c: &mut Criterion) {
+    let params = NetworkParams::regtest();
+
+    let mut group = c.benchmark_group("merkle_tree");
+    for leaves in [100, 1_000, 10_000] {
+        let transactions = fixtures::coinbases(leaves, 1, &params).unwrap();
+        group.throughput(Throughput::Elements(leaves as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(leaves),
+            &transactions,
+            |b, transactions| {
+                b.iter(|| Transaction::build_merkle_tree(transactions).unwrap().root())
+            },
+        );
+    }
+    group.finish();
+}
+
+fn apply_block(c: &mut Criterion) {
+    let params = NetworkParams::regtest();
+    let key_pair = fixtures::key_pair(1);
+    let genesis = params.genesis_block().unwrap();
+    let (funding, utxo_set) =
+        fixtures::funding(BLOCK_TRANSACTIONS, 100, &key_pair, &params).unwrap();
+    let spends = fixtures::spends(&funding, &key_pair, 1).unwrap();
+    let block = fixtures::block(&genesis, &key_pair, spends, 0, &params).unwrap();
+
+    let mut group = c.benchmark_group("utxo_set");
+    group.throughput(Throughput::Elements(block.transactions.len() as u64));
+    group.bench_function(BenchmarkId::new("apply_block", BLOCK_TRANSACTIONS), |b| {
+        b.iter_batched(
+            || utxo_set.clone(),
+            |mut utxo_set: UTXOSet| utxo_set.apply_block(black_box(&block)).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn mempool_add(c: &mut Criterion) {
+    let params = NetworkParams::regtest();
+    let key_pair = fixtures::key_pair(1);
+    let (funding, utxo_set) =
+        fixtures::funding(PENDING_TRANSACTIONS + 1, 100, &key_pair, &params).unwrap();
+    let mut spends = fixtures::spends(&funding, &key_pair, 1).unwrap();
+    let next = spends.pop().unwrap();
+
+    let mut mempool = MemPool::new(MemPoolConfig::default());
+    for spend in spends {
+        mempool.add(&utxo_set, spend, 2).unwrap();
+    }
+
+    c.bench_function(
+        &format!("mempool/add_with_{PENDING_TRANSACTIONS}_pending"),
+        |b| {
+            b.iter_batched(
+                || (mempool.clone(), next.clone()),
+                |(mut mempool, next)| mempool.add(&utxo_set, next, 2).unwrap(),
+                BatchSize::LargeInput,
+            )
+        },
+    );
+}
+
+criterion_group!(
+    benches,
+    header_hashing,
+    nonce_search,
+    signature_verification,
+    merkle_tree,
+    apply_block,
+    mempool_add
+);
+criterion_main!(benches);
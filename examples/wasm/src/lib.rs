@@ -0,0 +1,41 @@
+//! Transaction and merkle proof checks for the browser, on the same consensus code the
+//! node runs. Build with `cargo build --release --target wasm32-unknown-unknown` in this
+//! directory, then run `wasm-bindgen --target web` over the `.wasm` it produces.
+
+use std::fmt::Display;
+
+use tiny_crypto::{
+    block::TransactionProof,
+    crypto::{Hash, HashExt},
+    transaction::{Transaction, TxId},
+};
+use wasm_bindgen::prelude::*;
+
+/// Checks a raw transaction's signature and output values, returning its txid, or
+/// throws saying why it's invalid.
+#[wasm_bindgen]
+pub fn verify_transaction_hex(raw: &str) -> Result<String, JsError> {
+    let transaction = Transaction::from_hex(raw).map_err(js_error)?;
+    transaction.validate()?;
+    Ok(transaction.id().map_err(js_error)?.to_string())
+}
+
+/// Checks a `TransactionProof` in hex, as `merkle-proof` prints it, against a block's
+/// merkle root and transaction count. Throws if any of them don't parse.
+#[wasm_bindgen]
+pub fn verify_merkle_proof(
+    root: &str,
+    txid: &str,
+    proof: &str,
+    transaction_count: usize,
+) -> Result<bool, JsError> {
+    let root =
+        Hash::from_hex(root).map_err(|error| js_error(format!("Invalid merkle root: {error}")))?;
+    let id = Hash::from_hex(txid).map_err(|error| js_error(format!("Invalid txid: {error}")))?;
+    let proof = TransactionProof::from_hex(proof).map_err(js_error)?;
+    Ok(proof.verify(&root, &TxId(id), transaction_count))
+}
+
+fn js_error(error: impl Display) -> JsError {
+    JsError::new(&error.to_string())
+}
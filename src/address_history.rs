@@ -0,0 +1,344 @@
+//! Every payment to and spend from each address on the active chain, indexed when
+//! `index_address_history` is set so explorers can page through an address's history
+//! without scanning blocks. Like the mempool, it's saved at shutdown and reloaded at
+//! startup, and rebuilt from the stored chain when the saved copy is missing or stale.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::Block,
+    crypto::{Address, Hash, serde_hex},
+    node::NodeState,
+    transaction::{TransactionOutput, TxId},
+    utxo_set::BlockUndo,
+};
+
+/// An output a confirmed transaction created for or spent from an address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub txid: TxId,
+    #[serde(with = "serde_hex")]
+    pub block_hash: Hash,
+    pub height: u32,
+    /// The output's value, negative when it was spent.
+    pub delta: i64,
+}
+
+/// One page of an address's history, and how many entries it has in all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryPage {
+    pub total: usize,
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// Each address's history, in the order its blocks and their transactions connected.
+#[derive(Debug, Clone, Default)]
+pub struct AddressHistory {
+    entries: HashMap<Address, Vec<HistoryEntry>>,
+    /// The addresses each connected block touched, so disconnecting it needs no undo data.
+    blocks: HashMap<Hash, Vec<Address>>,
+}
+
+/// The index as saved, with the tip it was saved at.
+#[derive(Serialize, Deserialize)]
+struct SavedHistory<A, E> {
+    #[serde(with = "serde_hex")]
+    tip: Hash,
+    entries: Vec<(A, E)>,
+}
+
+impl AddressHistory {
+    /// Indexes the active chain's blocks from the genesis block up. Blocks without undo
+    /// data, which a restored snapshot lacks below its tip, are left out.
+    pub fn build(state: &NodeState) -> Result<Self> {
+        let mut history = Self::default();
+
+        for node in state.chain.nodes.values() {
+            let hash = node.header.hash()?;
+            if let (Some(block), Some(undo)) =
+                (state.block_manager.get_block(&hash), state.undo.get(&hash))
+            {
+                history.connect_block(block, undo)?;
+            }
+        }
+
+        Ok(history)
+    }
+
+    /// The index saved at `path` if it was saved at the state's tip, otherwise one built
+    /// from the state's chain.
+    pub fn load_or_build(path: &Path, state: &NodeState) -> Result<Self> {
+        if path.exists() {
+            let tip = state
+                .chain
+                .tail()
+                .map(|node| node.header.hash())
+                .transpose()?;
+            match Self::load(path) {
+                Ok((history, saved_tip)) if Some(saved_tip) == tip => return Ok(history),
+                Ok(_) => {}
+                Err(error) => {
+                    tracing::warn!(path = %path.display(), %error, "Rebuilding address history")
+                }
+            }
+        }
+
+        Self::build(state)
+    }
+
+    pub fn save(&self, path: &Path, tip: &Hash) -> Result<()> {
+        let saved = SavedHistory {
+            tip: *tip,
+            entries: self.entries.iter().collect(),
+        };
+        fs::write(path, serde_json::to_vec(&saved)?)?;
+        Ok(())
+    }
+
+    /// The saved index and the tip it was saved at.
+    pub fn load(path: &Path) -> Result<(Self, Hash)> {
+        let saved: SavedHistory<Address, Vec<HistoryEntry>> =
+            serde_json::from_slice(&fs::read(path)?)?;
+        let mut history = Self::default();
+
+        for (address, entries) in saved.entries {
+            for entry in &entries {
+                let touched = history.blocks.entry(entry.block_hash).or_default();
+                if !touched.contains(&address) {
+                    touched.push(address.clone());
+                }
+            }
+            history.entries.insert(address, entries);
+        }
+
+        Ok((history, saved.tip))
+    }
+
+    /// Records the outputs each of the block's transactions spent, found in `undo`, and
+    /// then the outputs it created.
+    pub fn connect_block(&mut self, block: &Block, undo: &BlockUndo) -> Result<()> {
+        let hash = block.header.hash()?;
+        if self.blocks.contains_key(&hash) {
+            return Ok(());
+        }
+
+        let mut touched = Vec::new();
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let txid = tx.id()?;
+            let spent = match undo.spent.get(index).and_then(Option::as_ref) {
+                Some(spent) => {
+                    let output = spent
+                        .transaction
+                        .body
+                        .outputs
+                        .get(spent.outpoint.index)
+                        .ok_or(anyhow::anyhow!(
+                            "Undo data has no output {}",
+                            spent.outpoint
+                        ))?;
+                    Some((output, -i64::try_from(output.value)?))
+                }
+                None => None,
+            };
+            let created = tx
+                .body
+                .outputs
+                .iter()
+                .map(|output| Ok((output, i64::try_from(output.value)?)))
+                .collect::<Result<Vec<_>>>()?;
+
+            for (output, delta) in spent.into_iter().chain(created) {
+                self.record(
+                    output,
+                    &mut touched,
+                    HistoryEntry {
+                        txid: txid.clone(),
+                        block_hash: hash,
+                        height: block.height,
+                        delta,
+                    },
+                );
+            }
+        }
+
+        self.blocks.insert(hash, touched);
+        Ok(())
+    }
+
+    fn record(
+        &mut self,
+        output: &TransactionOutput,
+        touched: &mut Vec<Address>,
+        entry: HistoryEntry,
+    ) {
+        self.entries
+            .entry(output.address.clone())
+            .or_default()
+            .push(entry);
+        if !touched.contains(&output.address) {
+            touched.push(output.address.clone());
+        }
+    }
+
+    /// Forgets every entry the block hashing to `hash` recorded.
+    pub fn disconnect_block(&mut self, hash: &Hash) {
+        for address in self.blocks.remove(hash).unwrap_or_default() {
+            if let Some(entries) = self.entries.get_mut(&address) {
+                entries.retain(|entry| entry.block_hash != *hash);
+                if entries.is_empty() {
+                    self.entries.remove(&address);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, address: &Address) -> &[HistoryEntry] {
+        self.entries
+            .get(address)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+impl NodeState {
+    /// Up to `limit` of the address's history entries from `offset` on, oldest first, or
+    /// `None` when the node isn't indexing address history.
+    pub fn address_history(
+        &self,
+        address: &Address,
+        offset: usize,
+        limit: usize,
+    ) -> Option<HistoryPage> {
+        let entries = self.address_history.as_ref()?.get(address);
+        Some(HistoryPage {
+            total: entries.len(),
+            entries: entries.iter().skip(offset).take(limit).cloned().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::KeyPair,
+        fixtures,
+        node::{Message, Node},
+        params::Network,
+    };
+
+    /// A regtest node indexing address history, with its chain in `data_dir`.
+    fn indexing_node(seed: u64, data_dir: &Path) -> Node {
+        Node::new(
+            fixtures::regtest_config(seed)
+                .data_dir(data_dir)
+                .index_address_history(true)
+                .build(),
+        )
+        .unwrap()
+    }
+
+    /// `(height, delta)` for each of the address's entries.
+    fn deltas(node: &Node, address: &Address) -> Vec<(u32, i64)> {
+        node.state
+            .address_history(address, 0, usize::MAX)
+            .unwrap()
+            .entries
+            .iter()
+            .map(|entry| (entry.height, entry.delta))
+            .collect()
+    }
+
+    #[test]
+    fn test_fund_spend_reorg() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let fork_dir = tempfile::tempdir().unwrap();
+        let mut node = indexing_node(1, data_dir.path());
+        let mut fork = indexing_node(2, fork_dir.path());
+        let address = node.address();
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+
+        let funding = fixtures::mine_block(&mut node).unwrap();
+        fork.handle_message(Message::NewBlock(funding.clone()))
+            .unwrap();
+        let funded = funding.transactions[0].output_value() as i64;
+        assert_eq!(deltas(&node, &address), vec![(2, funded)]);
+
+        let id = node.send(recipient.clone(), 10, 1).unwrap();
+        let spending = fixtures::mine_block(&mut node).unwrap();
+        let reward = spending.transactions[0].output_value() as i64;
+        assert_eq!(
+            deltas(&node, &address),
+            vec![(2, funded), (3, reward), (3, -funded), (3, funded - 11)]
+        );
+        let history = node.state.address_history(&recipient, 0, 10).unwrap();
+        assert_eq!(history.total, 1);
+        assert_eq!(history.entries[0].txid, id);
+        assert_eq!(
+            history.entries[0].block_hash,
+            spending.header.hash().unwrap()
+        );
+        assert_eq!(history.entries[0].delta, 10);
+
+        let page = node.state.address_history(&address, 1, 2).unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(page.entries.len(), 2);
+        assert_eq!(page.entries[0].delta, reward);
+
+        // a heavier fork without the spend leaves only the funding
+        let mut fork_blocks = Vec::new();
+        while fork.state.chain.height() < 5 {
+            fork_blocks.push(fixtures::mine_block(&mut fork).unwrap());
+        }
+        for block in fork_blocks {
+            node.handle_message(Message::NewBlock(block)).unwrap();
+        }
+        assert_eq!(node.tip_hash().unwrap(), fork.tip_hash().unwrap());
+        assert_eq!(deltas(&node, &address), vec![(2, funded)]);
+        assert_eq!(
+            node.state.address_history(&recipient, 0, 10).unwrap().total,
+            0
+        );
+        assert_eq!(
+            deltas(&node, &fork.address())
+                .iter()
+                .map(|(height, _)| *height)
+                .collect::<Vec<_>>(),
+            vec![3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_saved_and_rebuilt() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = indexing_node(1, data_dir.path());
+        let address = node.address();
+        fixtures::mine_block(&mut node).unwrap();
+        fixtures::mine_block(&mut node).unwrap();
+        let expected = deltas(&node, &address);
+        node.flush().unwrap();
+        drop(node);
+
+        let path = data_dir.path().join("address_history.json");
+        let (_, tip) = AddressHistory::load(&path).unwrap();
+        let node = indexing_node(1, data_dir.path());
+        assert_eq!(node.tip_hash().unwrap(), Some(tip));
+        assert_eq!(deltas(&node, &address), expected);
+
+        // a saved copy behind the tip is rebuilt from the blocks
+        let mut node = node;
+        fixtures::mine_block(&mut node).unwrap();
+        let expected = deltas(&node, &address);
+        drop(node);
+        let node = indexing_node(1, data_dir.path());
+        assert_eq!(deltas(&node, &address), expected);
+        assert_eq!(expected.len(), 3);
+
+        let unindexed = fixtures::regtest_node(3).unwrap();
+        assert!(unindexed.state.address_history(&address, 0, 10).is_none());
+    }
+}
@@ -0,0 +1,290 @@
+//! Block archives: a chain's blocks in height order in one file, to move a chain between
+//! data dirs or seed a node without the network. After a magic and version byte, each
+//! block is its transport encoding behind a little-endian u32 length.
+
+use std::io::{self, Read, Write};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    block::Block, block_manager::BlockManager, chain::Blockchain, constants::MAX_MESSAGE_SIZE,
+    crypto::HashExt, node::NodeState, outcome::HandleOutcome,
+};
+
+const MAGIC: &[u8; 4] = b"TCBA";
+/// 2 gave inputs relative locks and blocks a validator signature, changing how both
+/// encode, so version 1 archives can't be read.
+const VERSION: u8 = 2;
+
+pub struct ArchiveWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(mut out: W) -> Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        Ok(Self { out })
+    }
+
+    pub fn write_block(&mut self, block: &Block) -> Result<()> {
+        let bytes = block.to_bytes()?;
+        self.out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.out.write_all(&bytes)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Yields an archive's blocks in turn, failing on a truncated or corrupted one.
+pub struct ArchiveReader<R: Read> {
+    input: R,
+    offset: u64,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    pub fn new(mut input: R) -> Result<Self> {
+        let mut header = [0; 5];
+        input
+            .read_exact(&mut header)
+            .map_err(|_| anyhow::anyhow!("Not a block archive"))?;
+        if &header[..4] != MAGIC {
+            anyhow::bail!("Not a block archive");
+        }
+        if header[4] != VERSION {
+            anyhow::bail!(
+                "Unsupported block archive version {}, this build reads version {VERSION}",
+                header[4]
+            );
+        }
+
+        Ok(Self {
+            input,
+            offset: header.len() as u64,
+        })
+    }
+
+    fn read_block(&mut self) -> Result<Option<Block>> {
+        let mut length = [0; 4];
+        match self.input.read(&mut length[..1])? {
+            0 => return Ok(None),
+            _ => self.input.read_exact(&mut length[1..]).map_err(|error| {
+                anyhow::anyhow!("Archive is truncated at offset {}: {error}", self.offset)
+            })?,
+        }
+
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_MESSAGE_SIZE {
+            anyhow::bail!(
+                "Block of {length} bytes at offset {} exceeds the maximum of {MAX_MESSAGE_SIZE}",
+                self.offset
+            );
+        }
+        let mut bytes = vec![0; length];
+        self.input.read_exact(&mut bytes).map_err(|error| {
+            anyhow::anyhow!("Archive is truncated at offset {}: {error}", self.offset)
+        })?;
+        let block = Block::from_bytes(&bytes).map_err(|error| {
+            anyhow::anyhow!("Corrupted block at offset {}: {error}", self.offset)
+        })?;
+
+        self.offset += 4 + length as u64;
+        Ok(Some(block))
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_block().transpose()
+    }
+}
+
+impl BlockManager {
+    /// Writes the blocks of `chain` up to `to_height`, or its tip, to `out`, calling
+    /// `progress` with the count written after each. Returns the count.
+    pub fn export_archive(
+        &self,
+        chain: &Blockchain,
+        out: impl Write,
+        to_height: Option<u32>,
+        mut progress: impl FnMut(u32),
+    ) -> Result<u32> {
+        let mut writer = ArchiveWriter::new(io::BufWriter::new(out))?;
+        let mut count = 0;
+
+        for node in chain
+            .nodes
+            .range(..=to_height.unwrap_or(u32::MAX))
+            .map(|(_, node)| node)
+        {
+            let hash = node.header.hash()?;
+            let block = self.get_block(&hash).ok_or(anyhow::anyhow!(
+                "No block 0x{} in the data dir",
+                hash.display()
+            ))?;
+            writer.write_block(block)?;
+            count += 1;
+            progress(count);
+        }
+
+        writer.finish()?;
+        Ok(count)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImportSummary {
+    pub imported: u32,
+    /// Blocks the node already had.
+    pub skipped: u32,
+    pub rejected: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_rejection: Option<String>,
+}
+
+impl NodeState {
+    /// Adds an archive's blocks in turn as if a peer had sent them, calling `progress`
+    /// after each. Stops at the first one that's rejected, or builds on an unknown block,
+    /// unless `continue_on_error`.
+    pub fn import_archive(
+        &mut self,
+        input: impl Read,
+        continue_on_error: bool,
+        mut progress: impl FnMut(&ImportSummary),
+    ) -> Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+
+        for block in ArchiveReader::new(io::BufReader::new(input))? {
+            let block = block?;
+            let height = block.height;
+            let hash = block.header.hash()?;
+
+            let rejection = match self.add_block(block)? {
+                HandleOutcome::Accepted => {
+                    summary.imported += 1;
+                    None
+                }
+                HandleOutcome::Duplicate => {
+                    summary.skipped += 1;
+                    None
+                }
+                HandleOutcome::Orphaned { missing_parent } => Some(format!(
+                    "Block {height} 0x{} builds on unknown block 0x{}",
+                    hash.display(),
+                    missing_parent.display()
+                )),
+                HandleOutcome::Rejected(reason) => {
+                    Some(format!("Block {height} 0x{}: {reason}", hash.display()))
+                }
+            };
+            if let Some(rejection) = rejection {
+                summary.rejected += 1;
+                summary.first_rejection.get_or_insert(rejection);
+            }
+
+            progress(&summary);
+            if summary.rejected > 0 && !continue_on_error {
+                break;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto::KeyPair, params::NetworkParams};
+
+    fn regtest_chain(blocks: usize) -> NodeState {
+        let params = NetworkParams::regtest();
+        let mut state = NodeState {
+            params: params.clone(),
+            ..NodeState::default()
+        };
+        state.add_block(params.genesis_block().unwrap()).unwrap();
+
+        let key_pair = KeyPair::generate();
+        for _ in 0..blocks {
+            let tail = state.chain.tail().unwrap().header.hash().unwrap();
+            let previous = state.block_manager.get_block(&tail).unwrap();
+            let mut block = Block::new(&key_pair, previous, vec![], 0, &params).unwrap();
+            block.mine().unwrap();
+            assert_eq!(state.add_block(block).unwrap(), HandleOutcome::Accepted);
+        }
+        state
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let state = regtest_chain(3);
+        let mut archive = Vec::new();
+        let count = state
+            .block_manager
+            .export_archive(&state.chain, &mut archive, None, |_| {})
+            .unwrap();
+        assert_eq!(count, 4);
+
+        let mut imported = NodeState {
+            params: NetworkParams::regtest(),
+            ..NodeState::default()
+        };
+        let summary = imported
+            .import_archive(archive.as_slice(), false, |_| {})
+            .unwrap();
+        assert_eq!(summary.imported, 4);
+        assert_eq!(
+            imported.chain.tail().unwrap().header,
+            state.chain.tail().unwrap().header
+        );
+        assert_eq!(
+            imported.utxo_set.commitment().unwrap(),
+            state.utxo_set.commitment().unwrap()
+        );
+
+        // importing again only skips
+        let summary = imported
+            .import_archive(archive.as_slice(), false, |_| {})
+            .unwrap();
+        assert_eq!(summary.skipped, 4);
+
+        let mut partial = Vec::new();
+        state
+            .block_manager
+            .export_archive(&state.chain, &mut partial, Some(2), |_| {})
+            .unwrap();
+        assert_eq!(ArchiveReader::new(partial.as_slice()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_corrupted_archive() {
+        let state = regtest_chain(1);
+        let mut archive = Vec::new();
+        state
+            .block_manager
+            .export_archive(&state.chain, &mut archive, None, |_| {})
+            .unwrap();
+
+        let read = |bytes: &[u8]| -> Result<Vec<Block>> { ArchiveReader::new(bytes)?.collect() };
+        assert!(read(&archive).is_ok());
+        let error = read(&archive[..archive.len() - 1]).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{error}");
+        assert!(read(b"TCBX\x02").is_err());
+        let error = read(b"TCBA\x01").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Unsupported block archive version 1"),
+            "{error}"
+        );
+        assert!(read(b"").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, ready},
+};
+
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::muxing::{StreamMuxer, StreamMuxerBox, StreamMuxerEvent, SubstreamBox};
+
+/// Bytes read from and written to a peer, shared by every substream of its connections.
+#[derive(Debug, Default)]
+pub struct ByteCounters {
+    inbound: AtomicU64,
+    outbound: AtomicU64,
+}
+
+impl ByteCounters {
+    pub fn inbound(&self) -> u64 {
+        self.inbound.load(Ordering::Relaxed)
+    }
+
+    pub fn outbound(&self) -> u64 {
+        self.outbound.load(Ordering::Relaxed)
+    }
+
+    /// Adds `other`'s counts to these, to keep them once `other` is dropped.
+    pub fn absorb(&self, other: &ByteCounters) {
+        self.inbound.fetch_add(other.inbound(), Ordering::Relaxed);
+        self.outbound.fetch_add(other.outbound(), Ordering::Relaxed);
+    }
+}
+
+/// Wraps an authenticated connection's muxer so what's read and written on its substreams
+/// is added to the peer's counters. Framing and encryption overhead below the substreams
+/// isn't counted, and neither are connections through a relay, which the relay transport
+/// upgrades itself.
+pub struct CountingMuxer {
+    inner: StreamMuxerBox,
+    counters: Arc<ByteCounters>,
+}
+
+impl CountingMuxer {
+    pub fn new(inner: StreamMuxerBox, counters: Arc<ByteCounters>) -> Self {
+        Self { inner, counters }
+    }
+
+    fn wrap(&self, stream: SubstreamBox) -> CountingStream {
+        CountingStream {
+            inner: stream,
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl StreamMuxer for CountingMuxer {
+    type Substream = CountingStream;
+    type Error = io::Error;
+
+    fn poll_inbound(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let stream = ready!(Pin::new(&mut self.inner).poll_inbound(cx))?;
+        Poll::Ready(Ok(self.wrap(stream)))
+    }
+
+    fn poll_outbound(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let stream = ready!(Pin::new(&mut self.inner).poll_outbound(cx))?;
+        Poll::Ready(Ok(self.wrap(stream)))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        Pin::new(&mut self.inner).poll(cx)
+    }
+}
+
+pub struct CountingStream {
+    inner: SubstreamBox,
+    counters: Arc<ByteCounters>,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.counters
+            .inbound
+            .fetch_add(read as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.counters
+            .outbound
+            .fetch_add(written as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
@@ -0,0 +1,147 @@
+//! Measures what the local hardware can do: header hashes, signature checks, and merkle
+//! roots per second, and the difficulty its hash rate suggests for a block time.
+
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{
+    block::BlockHeader, constants::BENCH_SAMPLES, crypto::KeyPair, params::NetworkParams,
+    transaction::Transaction,
+};
+
+/// Runs `op` on `threads` threads for a warmup sample, then for `BENCH_SAMPLES` samples
+/// over `duration` in all, and returns the median rate in operations per second. `op`
+/// is passed a counter unique across the threads.
+fn measure(
+    duration: Duration,
+    threads: usize,
+    op: impl Fn(u64) -> Result<()> + Sync,
+) -> Result<f64> {
+    let threads = threads.max(1) as u64;
+    let sample = duration / BENCH_SAMPLES as u32;
+    let mut rates = Vec::with_capacity(BENCH_SAMPLES);
+
+    for i in 0..=BENCH_SAMPLES {
+        let started = Instant::now();
+        let ops = thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|thread| {
+                    let op = &op;
+                    scope.spawn(move || {
+                        let start = thread * (u64::MAX / threads);
+                        let mut ops = 0;
+                        while started.elapsed() < sample {
+                            op(start.wrapping_add(ops))?;
+                            ops += 1;
+                        }
+                        Ok(ops)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("Benchmark thread panicked")))
+                })
+                .sum::<Result<u64>>()
+        })?;
+
+        // the first sample is only run to warm caches and the clock speed up
+        if i > 0 {
+            rates.push(ops as f64 / started.elapsed().as_secs_f64());
+        }
+    }
+
+    rates.sort_by(f64::total_cmp);
+    Ok(rates[rates.len() / 2])
+}
+
+/// Header hashes per second across `threads` threads, as the miner computes them.
+pub fn header_hash_rate(duration: Duration, threads: usize) -> Result<f64> {
+    let header = BlockHeader::default();
+    measure(duration, threads, |nonce| {
+        let header = BlockHeader {
+            nonce,
+            ..header.clone()
+        };
+        black_box(header.hash()?);
+        Ok(())
+    })
+}
+
+/// Transaction signature checks per second on one thread.
+pub fn signature_verification_rate(duration: Duration) -> Result<f64> {
+    let transaction =
+        Transaction::new_coinbase(&KeyPair::generate(), 1, &NetworkParams::default())?;
+    let bytes = transaction.body.as_bytes()?;
+    measure(duration, 1, |_| {
+        black_box(transaction.signing_info.verify_signature_bytes(&bytes)?);
+        Ok(())
+    })
+}
+
+/// Merkle roots per second for a block of `transactions`, computing their txids across
+/// the available cores and hashing them as block validation does.
+pub fn merkle_root_rate(duration: Duration, transactions: usize) -> Result<f64> {
+    let key_pair = KeyPair::generate();
+    let block = (0..transactions as u32)
+        .map(|height| Transaction::new_coinbase(&key_pair, height, &NetworkParams::default()))
+        .collect::<Result<Vec<_>>>()?;
+
+    measure(duration, 1, |_| {
+        black_box(Transaction::build_merkle_tree(&block)?.root());
+        Ok(())
+    })
+}
+
+/// Hashes expected to find a block at `difficulty`: its target admits 255 in every
+/// 256^(difficulty + 1) hashes.
+pub fn expected_hashes(difficulty: u8) -> f64 {
+    256f64.powi(difficulty as i32) * 256.0 / 255.0
+}
+
+/// The difficulty whose expected block time at `hash_rate` is nearest `block_time`.
+/// Each step is 256 times harder, so nearest is on a log scale.
+pub fn suggested_difficulty(hash_rate: f64, block_time: Duration) -> u8 {
+    let hashes = hash_rate * block_time.as_secs_f64();
+    if hashes <= 1.0 {
+        return 0;
+    }
+    (hashes.log(256.0).round() as u8).min(31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let duration = Duration::from_millis(50);
+        assert!(header_hash_rate(duration, 2).unwrap() > 0.0);
+        assert!(signature_verification_rate(duration).unwrap() > 0.0);
+        assert!(merkle_root_rate(duration, 10).unwrap() > 0.0);
+
+        let failing = measure(duration, 2, |_| Err(anyhow::anyhow!("Failed")));
+        assert!(failing.is_err());
+    }
+
+    #[test]
+    fn test_suggested_difficulty() {
+        let minute = Duration::from_secs(60);
+        assert_eq!(suggested_difficulty(0.0, minute), 0);
+        // 60 million hashes a minute is nearest 256^3
+        assert_eq!(suggested_difficulty(1_000_000.0, minute), 3);
+        assert_eq!(suggested_difficulty(f64::MAX, minute), 31);
+
+        let seconds = expected_hashes(3) / 1_000_000.0;
+        assert!((16.0..17.0).contains(&seconds));
+    }
+}
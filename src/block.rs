@@ -1,18 +1,90 @@
-use std::{cmp::Ordering, collections::HashSet};
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+    },
+    thread,
+};
 
 use anyhow::Result;
-use bincode::Encode;
-use hex;
+use bincode::{Decode, Encode};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    crypto::{Hash, KeyPair, sha256d},
-    transaction::Transaction,
-    utxo_set::UTXOSet,
+    constants::{BLOCK_SIZE_LIMIT, MAX_FUTURE_BLOCK_TIME_SECS, MINING_INTERRUPT_INTERVAL},
+    crypto::{
+        Hash, HashExt, KeyPair, MerkleProof, PublicKeyOnly, decode_config, from_hex, serde_hex,
+        sha256d, to_hex, verify_batch,
+    },
+    params::{ConsensusMode, NetworkParams},
+    transaction::{SigningInfo, Transaction, TxId, TxValidationError},
+    utxo_set::{BlockUndo, UTXOSet, UtxoError},
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Default)]
+/// Why a block breaks the consensus rules. Validation returns these wrapped in
+/// `anyhow::Error`; `RejectReason::InvalidBlock` carries them to peers and RPC clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[serde(tag = "kind", content = "details", rename_all = "snake_case")]
+pub enum BlockValidationError {
+    #[error("Block hash does not meet difficulty target")]
+    InvalidProofOfWork,
+    #[error("Merkle root mismatch")]
+    MerkleRootMismatch,
+    #[error("Transaction {txid} appears multiple times in the block")]
+    DuplicateTransaction { txid: TxId },
+    /// The block is empty or doesn't start with a coinbase.
+    #[error("First transaction must be a coinbase transaction")]
+    MissingCoinbase,
+    #[error("Transaction {index} is a coinbase, but only the first may be")]
+    DuplicateCoinbase { index: usize },
+    #[error("Coinbase pays {value} instead of {expected}")]
+    BadCoinbaseValue { value: u64, expected: u64 },
+    #[error("Block timestamp {timestamp} is after {max}")]
+    TimestampTooFar { timestamp: u32, max: u32 },
+    #[error("Block is not the genesis block of this network")]
+    NotGenesis,
+    #[error("Block builds on unknown block 0x{}", parent.display())]
+    UnknownParent {
+        #[serde(with = "serde_hex")]
+        parent: Hash,
+    },
+    #[error("Block claims height {claimed}, not {expected}")]
+    WrongHeight { claimed: u32, expected: u32 },
+    #[error("Transaction {index} ({txid}) is invalid: {error}")]
+    Transaction {
+        index: usize,
+        txid: TxId,
+        error: UtxoError,
+    },
+    #[error("Block is signed, but blocks on this network are mined")]
+    UnexpectedSignature,
+    #[error("Block is not signed by a validator")]
+    MissingSignature,
+    #[error("Block is signed by 0x{}, which is not a validator", to_hex(.public_key.0.serialize()))]
+    UnauthorizedValidator { public_key: PublicKeyOnly },
+    #[error("Validator signature does not match the block")]
+    BadValidatorSignature,
+    #[error("Block has difficulty {difficulty}, but signed blocks have difficulty 0")]
+    NonZeroDifficulty { difficulty: u8 },
+    #[error("Block's subsidy and fees total more than {}", u64::MAX)]
+    ValueOverflow,
+    #[error("Block holds {transactions} transactions, over the limit of {limit}")]
+    Oversized { transactions: usize, limit: usize },
+}
+
+/// Encoded three ways, all derived from these fields in this order: the bincode `Encode`
+/// that's hashed (`as_bytes`, hashes as raw bytes), bincode through serde for the wire
+/// (`Block::to_bytes`, hashes length-prefixed), and JSON for the block files (hashes as
+/// hex). Reordering or renaming a field changes every block hash and breaks stored blocks;
+/// `tests/fixtures/block.json` pins the JSON form.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Default, Serialize, Deserialize)]
 pub struct BlockHeader {
+    #[serde(with = "serde_hex")]
     pub previous_block_hash: Hash,
+    #[serde(with = "serde_hex")]
     pub merkle_root: Hash,
     pub timestamp: u32,
     pub difficulty: u8,
@@ -24,21 +96,40 @@ impl BlockHeader {
         Ok(bincode::encode_to_vec(self, bincode::config::standard())?)
     }
 
+    /// Parses `as_bytes`, which must be exactly one header.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (header, read) = bincode::decode_from_slice(bytes, decode_config())?;
+        if read != bytes.len() {
+            anyhow::bail!("Unexpected data after the header");
+        }
+        Ok(header)
+    }
+
     pub fn hash(&self) -> Result<Hash> {
         Ok(sha256d(&self.as_bytes()?))
     }
 
+    /// `hash`, encoding into `buf` instead of a new allocation, so a loop hashing many
+    /// headers can reuse one buffer. `buf` is cleared first.
+    pub fn hash_with_buf(&self, buf: &mut Vec<u8>) -> Result<Hash> {
+        buf.clear();
+        bincode::encode_into_std_write(self, buf, bincode::config::standard())?;
+        Ok(sha256d(buf))
+    }
+
+    /// Whether `hash`, this header's own, meets its difficulty target.
+    pub fn hash_meets_target(&self, hash: &Hash) -> Result<bool> {
+        Ok(self.target_met(hash, &self.difficulty_target()?))
+    }
+
     pub fn difficulty_target(&self) -> Result<Hash> {
         if self.difficulty >= 32 {
             return Err(anyhow::anyhow!("Difficultly target is too high"));
         }
 
-        let mut target = Hash::default();
-
-        target[0] = u8::MAX;
-        target.rotate_right(self.difficulty as usize);
-
-        Ok(target)
+        // 0xff, `difficulty` zero bytes from the top
+        let target = BigUint::from(u8::MAX) << (8 * (31 - self.difficulty as usize));
+        Hash::from_biguint(&target)
     }
 
     fn target_met(&self, hash: &Hash, target: &Hash) -> bool {
@@ -48,51 +139,279 @@ impl BlockHeader {
     pub fn compute_nonce_naive(&self) -> Result<u64> {
         let target = self.difficulty_target()?;
 
-        let mut header = self.clone();
-        header.nonce = 0;
+        let mut hasher = NonceHasher::new(self)?;
+        let mut nonce = 0;
 
         loop {
-            let hash = header.hash()?;
+            let hash = hasher.hash(nonce)?;
 
             if self.target_met(&hash, &target) {
                 break;
             }
 
-            header.nonce += 1;
+            nonce += 1;
 
-            if header.nonce % 1_000_000 == 0 {
-                println!("Nonce: {}, Hash: 0x{}", header.nonce, hex::encode(hash));
+            if nonce.is_multiple_of(1_000_000) {
+                tracing::trace!(nonce, hash = %hash.display(), "Searching for a nonce");
             }
         }
 
-        Ok(header.nonce)
+        Ok(nonce)
     }
 
-    pub fn validate_hash(&self) -> Result<bool> {
-        let hash = self.hash()?;
+    /// Searches for a nonce meeting the difficulty target, starting at `start_nonce`.
+    ///
+    /// `interrupt` is called with the number of hashes computed since the previous call
+    /// every `MINING_INTERRUPT_INTERVAL` nonces; returning `true` abandons the search and
+    /// yields `None`.
+    pub fn compute_nonce_interruptible(
+        &self,
+        start_nonce: u64,
+        mut interrupt: impl FnMut(u64) -> bool,
+    ) -> Result<Option<u64>> {
         let target = self.difficulty_target()?;
 
-        Ok(self.target_met(&hash, &target))
+        let mut hasher = NonceHasher::new(self)?;
+        let mut nonce = start_nonce;
+
+        let mut hashes = 0;
+
+        loop {
+            let hash = hasher.hash(nonce)?;
+            hashes += 1;
+
+            if self.target_met(&hash, &target) {
+                interrupt(hashes);
+                return Ok(Some(nonce));
+            }
+
+            if hashes == MINING_INTERRUPT_INTERVAL {
+                if interrupt(hashes) {
+                    return Ok(None);
+                }
+                hashes = 0;
+            }
+
+            nonce = nonce.wrapping_add(1);
+        }
+    }
+
+    /// `compute_nonce_interruptible` across `threads` threads, each searching its own
+    /// share of the nonce space. `interrupt` is called from all of them.
+    pub fn compute_nonce_parallel(
+        &self,
+        threads: usize,
+        interrupt: impl Fn(u64) -> bool + Sync,
+    ) -> Result<Option<u64>> {
+        let threads = threads.max(1) as u64;
+        let found = Mutex::new(None);
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|i| {
+                    let (found, done, interrupt) = (&found, &done, &interrupt);
+                    scope.spawn(move || {
+                        let start = i * (u64::MAX / threads);
+                        let nonce = self.compute_nonce_interruptible(start, |hashes| {
+                            interrupt(hashes) || done.load(AtomicOrdering::Relaxed)
+                        })?;
+
+                        if let Some(nonce) = nonce {
+                            found.lock().unwrap().get_or_insert(nonce);
+                        }
+                        done.store(true, AtomicOrdering::Relaxed);
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers.into_iter().try_for_each(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("Miner thread panicked")))
+            })
+        })?;
+
+        Ok(found.into_inner().unwrap())
+    }
+
+    pub fn validate_hash(&self) -> Result<bool> {
+        self.hash_meets_target(&self.hash()?)
+    }
+}
+
+/// Hashes a header under different nonces. The nonce is encoded last, so everything
+/// before it is encoded once and each hash only re-encodes the nonce.
+struct NonceHasher {
+    buf: Vec<u8>,
+    prefix_len: usize,
+}
+
+impl NonceHasher {
+    fn new(header: &BlockHeader) -> Result<Self> {
+        let header = BlockHeader {
+            nonce: 0,
+            ..header.clone()
+        };
+        let buf = header.as_bytes()?;
+        let nonce_len = bincode::encode_to_vec(0u64, bincode::config::standard())?.len();
+
+        Ok(Self {
+            prefix_len: buf.len() - nonce_len,
+            buf,
+        })
+    }
+
+    fn hash(&mut self, nonce: u64) -> Result<Hash> {
+        self.buf.truncate(self.prefix_len);
+        bincode::encode_into_std_write(nonce, &mut self.buf, bincode::config::standard())?;
+        Ok(sha256d(&self.buf))
     }
 }
 
-#[derive(Debug, Clone)]
+/// Crosses the wire as bincode through serde (`to_bytes`) and is stored as JSON, both in
+/// this field order; only the header has a consensus encoding of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Block {
     pub height: u32,
     pub header: BlockHeader,
-    pub transactions: Vec<Transaction>,
+    /// Shared with the UTXO set and mempool rather than copied into them. Serialized as
+    /// the transactions themselves.
+    pub transactions: Vec<Arc<Transaction>>,
+    /// A validator's signature over the header hash, on proof of authority networks.
+    /// Block files written before there were signatures leave it out.
+    #[serde(default)]
+    pub signature: Option<SigningInfo>,
+}
+
+/// The height and hash, the rest of the header indented below them, then each
+/// transaction indented further. There's no trailing newline.
+impl std::fmt::Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = &self.header;
+        let hash = header.hash().map_err(|_| std::fmt::Error)?;
+        let timestamp = chrono::DateTime::from_timestamp(header.timestamp as i64, 0)
+            .map(|timestamp| timestamp.to_rfc3339())
+            .unwrap_or_else(|| header.timestamp.to_string());
+
+        write!(f, "Block {} {:#x}", self.height, hash.display())?;
+        write!(
+            f,
+            "\n  Previous: {:#x}",
+            header.previous_block_hash.display()
+        )?;
+        write!(f, "\n  Merkle Root: {:#x}", header.merkle_root.display())?;
+        write!(f, "\n  Timestamp: {timestamp}")?;
+        write!(
+            f,
+            "\n  Difficulty: {}, Nonce: {}",
+            header.difficulty, header.nonce
+        )?;
+        write!(f, "\n  Transactions: {}", self.transactions.len())?;
+        for transaction in &self.transactions {
+            for line in transaction.to_string().lines() {
+                write!(f, "\n    {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `MerkleProof` for one transaction along with its position in the block, which is
+/// all a light client needs besides the header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionProof {
+    pub index: u32,
+    pub proof: MerkleProof,
+}
+
+impl TransactionProof {
+    pub fn verify(&self, merkle_root: &Hash, id: &TxId, transaction_count: usize) -> bool {
+        self.proof.verify(
+            merkle_root,
+            &[(self.index as usize, id.0)],
+            transaction_count,
+        )
+    }
+
+    /// The proof in the binary codec, as hex.
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(to_hex(bincode::serde::encode_to_vec(
+            self,
+            bincode::config::standard(),
+        )?))
+    }
+
+    pub fn from_hex(raw: &str) -> Result<Self> {
+        let bytes =
+            from_hex(raw.trim()).map_err(|error| anyhow::anyhow!("Invalid hex: {error}"))?;
+        let (proof, read) = bincode::serde::decode_from_slice(&bytes, decode_config())
+            .map_err(|error| anyhow::anyhow!("Invalid proof: {error}"))?;
+        if read != bytes.len() {
+            anyhow::bail!("Unexpected data after the proof");
+        }
+        Ok(proof)
+    }
 }
 
 impl Block {
+    /// Binary serialization used for transport.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serde::encode_to_vec(
+            self,
+            bincode::config::standard(),
+        )?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (block, _) = bincode::serde::decode_from_slice(bytes, decode_config())?;
+        Ok(block)
+    }
+
+    /// The raw block, `to_bytes` as hex.
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(to_hex(self.to_bytes()?))
+    }
+
+    /// Parses a raw block, which must be exactly one block.
+    pub fn from_hex(raw: &str) -> Result<Self> {
+        let bytes =
+            from_hex(raw.trim()).map_err(|error| anyhow::anyhow!("Invalid hex: {error}"))?;
+        let (block, read) = bincode::serde::decode_from_slice(&bytes, decode_config())
+            .map_err(|error| anyhow::anyhow!("Invalid block: {error}"))?;
+        if read != bytes.len() {
+            anyhow::bail!("Unexpected data after the block");
+        }
+        Ok(block)
+    }
+
+    /// Builds an unmined block on `previous` whose coinbase claims the block reward of
+    /// `params` plus `fees` for `keypair`'s address on its network, stamped with the clock.
+    #[cfg(feature = "clock")]
     pub fn new(
         keypair: &KeyPair,
         previous: &Block,
-        input_transactions: Vec<Transaction>,
+        input_transactions: Vec<Arc<Transaction>>,
+        fees: u64,
+        params: &NetworkParams,
+    ) -> Result<Self> {
+        Self::new_at(keypair, previous, input_transactions, fees, now(), params)
+    }
+
+    /// `new`, stamped with `timestamp` rather than the clock.
+    pub fn new_at(
+        keypair: &KeyPair,
+        previous: &Block,
+        input_transactions: Vec<Arc<Transaction>>,
+        fees: u64,
+        timestamp: u32,
+        params: &NetworkParams,
     ) -> Result<Self> {
         let height = previous.height + 1;
-        let coinbase_tx = Transaction::new_coinbase(keypair, height)?;
+        let coinbase_tx = Transaction::new_coinbase_with_fees(keypair, height, fees, params)?;
 
-        let mut transactions = vec![coinbase_tx];
+        let mut transactions = vec![Arc::new(coinbase_tx)];
         transactions.extend(input_transactions);
 
         let merkle_tree = Transaction::build_merkle_tree(&transactions)?;
@@ -103,7 +422,7 @@ impl Block {
         let header = BlockHeader {
             previous_block_hash: previous.header.hash()?,
             merkle_root,
-            timestamp: chrono::Utc::now().timestamp() as u32,
+            timestamp,
             difficulty: previous.header.difficulty,
             nonce: 0,
         };
@@ -112,6 +431,7 @@ impl Block {
             height,
             header,
             transactions,
+            signature: None,
         })
     }
 
@@ -120,96 +440,331 @@ impl Block {
         Ok(())
     }
 
-    fn validate_merkle_root(&self) -> Result<()> {
-        let merkle_tree = Transaction::build_merkle_tree(&self.transactions)?;
+    /// Signs the header hash with `keypair`, as a validator on a proof of authority network.
+    pub fn sign(&mut self, keypair: &KeyPair) -> Result<()> {
+        self.signature = Some(SigningInfo::sign(keypair, &self.header.hash()?));
+        Ok(())
+    }
+
+    /// Finishes the block as `params` has blocks finished: mined, or signed with `keypair`.
+    pub fn seal(&mut self, keypair: &KeyPair, params: &NetworkParams) -> Result<()> {
+        match params.consensus {
+            ConsensusMode::ProofOfWork => self.mine(),
+            ConsensusMode::ProofOfAuthority { .. } => self.sign(keypair),
+        }
+    }
+
+    /// Checks the header commits to exactly these transactions. The tree promotes an
+    /// unpaired node instead of pairing it with itself, so repeating the last
+    /// transactions changes the root rather than forging another block with the same
+    /// header (CVE-2012-2459). Repeated transactions are also rejected outright, before
+    /// the root is computed, so neither construction detail is relied on alone.
+    pub fn validate_merkle_root(&self) -> Result<()> {
+        let tx_ids = Transaction::ids_parallel(&self.transactions)?;
+        let mut seen = HashSet::new();
+        if let Some(txid) = tx_ids.iter().find(|&txid| !seen.insert(txid)) {
+            return Err(BlockValidationError::DuplicateTransaction { txid: txid.clone() }.into());
+        }
+
+        let merkle_tree = Transaction::merkle_tree_from_ids(&tx_ids);
         let merkle_root = merkle_tree
             .root()
             .ok_or(anyhow::anyhow!("Failed to compute merkle root"))?;
 
         if self.header.merkle_root != merkle_root {
-            return Err(anyhow::anyhow!("Merkle root mismatch"));
+            return Err(BlockValidationError::MerkleRootMismatch.into());
         }
 
         Ok(())
     }
 
-    fn validate_transactions(&self) -> Result<()> {
-        let mut tx_ids = HashSet::new();
+    /// A proof that the transaction `id` is in this block, checked against the header's
+    /// merkle root and the transaction count alone.
+    pub fn transaction_proof(&self, id: &TxId) -> Result<TransactionProof> {
+        let ids = Transaction::ids_parallel(&self.transactions)?;
+        let index = ids
+            .iter()
+            .position(|tx_id| tx_id == id)
+            .ok_or(anyhow::anyhow!("Transaction {id} isn't in the block"))?;
+
+        let tree = Transaction::merkle_tree_from_ids(&ids);
+        Ok(TransactionProof {
+            index: index as u32,
+            proof: tree.proof(&[index])?,
+        })
+    }
 
+    fn validate_transactions(&self, params: &NetworkParams) -> Result<()> {
         let (first_txs, remaining_txs) = self.transactions.split_at(1);
 
-        let coinbase_tx = first_txs.first().ok_or(anyhow::anyhow!(
-            "Block must contain at least one transaction"
-        ))?;
+        let coinbase_tx = first_txs
+            .first()
+            .filter(|tx| tx.body.input.is_coinbase())
+            .ok_or(BlockValidationError::MissingCoinbase)?;
 
-        tx_ids.insert(coinbase_tx.id()?);
+        coinbase_tx
+            .validate_network(params.network)
+            .map_err(|error| Self::invalid_transaction(0, coinbase_tx, error))?;
 
-        if !coinbase_tx.body.input.is_coinbase() {
-            return Err(anyhow::anyhow!(
-                "First transaction must be a coinbase transaction"
-            ));
+        // the coinbase may also claim transaction fees, which are checked against the
+        // UTXO set in `validate_transaction_inputs`
+        let expected_block_reward = params
+            .coinbase_subsidy(self.height)
+            .ok_or(BlockValidationError::ValueOverflow)?;
+        let block_reward = coinbase_tx
+            .checked_output_value()
+            .map_err(|error| Self::invalid_transaction(0, coinbase_tx, error))?;
+
+        if block_reward < expected_block_reward {
+            return Err(BlockValidationError::BadCoinbaseValue {
+                value: block_reward,
+                expected: expected_block_reward,
+            }
+            .into());
         }
 
-        let expected_block_reward = Transaction::block_reward(self.height);
-        let block_reward = coinbase_tx
-            .body
-            .outputs
-            .iter()
-            .map(|o| o.value)
-            .sum::<u64>();
+        for (index, block_tx) in remaining_txs.iter().enumerate() {
+            let index = index + 1;
+            if block_tx.body.input.is_coinbase() {
+                return Err(BlockValidationError::DuplicateCoinbase { index }.into());
+            }
 
-        if block_reward != expected_block_reward {
-            return Err(anyhow::anyhow!(
-                "Block reward for coinbase transaction does not match expected block reward: {block_reward} != {expected_block_reward}"
+            block_tx
+                .validate_network(params.network)
+                .map_err(|error| Self::invalid_transaction(index, block_tx, error))?;
+        }
+
+        Ok(())
+    }
+
+    /// `error` as the block's error for its transaction at `index`, or the failure to
+    /// compute the transaction's id.
+    fn invalid_transaction(
+        index: usize,
+        transaction: &Transaction,
+        error: impl Into<UtxoError>,
+    ) -> anyhow::Error {
+        match transaction.id() {
+            Ok(txid) => BlockValidationError::Transaction {
+                index,
+                txid,
+                error: error.into(),
+            }
+            .into(),
+            Err(e) => e,
+        }
+    }
+
+    /// Checks every signature but the coinbase's, as one batch across the available cores.
+    pub fn verify_signatures(&self) -> Result<()> {
+        let digests = self
+            .transactions
+            .iter()
+            .skip(1)
+            .map(|tx| Ok(sha256d(&tx.body.as_bytes()?)))
+            .collect::<Result<Vec<_>>>()?;
+        let items = self
+            .transactions
+            .iter()
+            .skip(1)
+            .zip(digests)
+            .map(|(tx, digest)| {
+                (
+                    digest,
+                    &tx.signing_info.signature,
+                    &tx.signing_info.public_key,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        if let Err(error) = verify_batch(&items, threads) {
+            let index = error.index + 1;
+            return Err(Self::invalid_transaction(
+                index,
+                &self.transactions[index],
+                TxValidationError::BadSignature,
             ));
         }
 
-        for block_tx in remaining_txs {
-            let id = block_tx.id()?;
+        Ok(())
+    }
 
-            if tx_ids.contains(&id) {
-                return Err(anyhow::anyhow!(
-                    "Transaction appears multiple times in the block"
-                ));
+    #[cfg(feature = "clock")]
+    pub fn validate(&self, params: &NetworkParams) -> Result<()> {
+        self.validate_at(params, now())
+    }
+
+    /// `validate` as of `now`, in seconds since the epoch, rather than the clock.
+    pub fn validate_at(&self, params: &NetworkParams, now: u32) -> Result<()> {
+        let _span = self.validation_span().entered();
+        self.check_without_signatures(params, now)?;
+        self.verify_signatures()
+    }
+
+    /// Context-free validation short of the signature checks, which initial block download
+    /// defers until the block is connected.
+    #[cfg(feature = "clock")]
+    pub fn validate_without_signatures(&self, params: &NetworkParams) -> Result<()> {
+        self.validate_without_signatures_at(params, now())
+    }
+
+    /// `validate_without_signatures` as of `now` rather than the clock.
+    pub fn validate_without_signatures_at(&self, params: &NetworkParams, now: u32) -> Result<()> {
+        let _span = self.validation_span().entered();
+        self.check_without_signatures(params, now)
+    }
+
+    fn validation_span(&self) -> tracing::Span {
+        tracing::debug_span!(
+            "block.validate",
+            block_hash = %format_args!("{:#x}", self.header.hash().unwrap_or_default().display()),
+            height = self.height,
+        )
+    }
+
+    fn check_without_signatures(&self, params: &NetworkParams, now: u32) -> Result<()> {
+        self.validate_seal(params)?;
+        let max = now.saturating_add(MAX_FUTURE_BLOCK_TIME_SECS);
+        if self.header.timestamp > max {
+            return Err(BlockValidationError::TimestampTooFar {
+                timestamp: self.header.timestamp,
+                max,
+            }
+            .into());
+        }
+        if self.transactions.len() > BLOCK_SIZE_LIMIT {
+            return Err(BlockValidationError::Oversized {
+                transactions: self.transactions.len(),
+                limit: BLOCK_SIZE_LIMIT,
             }
+            .into());
+        }
+        self.validate_merkle_root()?;
+        self.validate_transactions(params)?;
+        Ok(())
+    }
 
-            if block_tx.body.input.is_coinbase() {
-                return Err(anyhow::anyhow!(
-                    "Only one coinbase transactions is allowed per block"
-                ));
+    /// Checks the block meets its difficulty target or, on a proof of authority network,
+    /// is signed by one of the authorities. The genesis block is built rather than sealed
+    /// by a validator, so it's the one block left unsigned.
+    fn validate_seal(&self, params: &NetworkParams) -> Result<()> {
+        if params.consensus.is_proof_of_work() {
+            if self.signature.is_some() {
+                return Err(BlockValidationError::UnexpectedSignature.into());
+            }
+            // an out of range difficulty has no valid hash either
+            if !self.header.validate_hash().unwrap_or(false) {
+                return Err(BlockValidationError::InvalidProofOfWork.into());
             }
+            return Ok(());
+        }
 
-            if !block_tx.verify_signature()? {
-                return Err(anyhow::anyhow!("Transaction signature is invalid"));
+        if self.header.difficulty != 0 {
+            return Err(BlockValidationError::NonZeroDifficulty {
+                difficulty: self.header.difficulty,
             }
+            .into());
+        }
 
-            tx_ids.insert(id);
+        let Some(signature) = &self.signature else {
+            if self.header.previous_block_hash == Hash::default() {
+                return Ok(());
+            }
+            return Err(BlockValidationError::MissingSignature.into());
+        };
+        if !params.consensus.can_seal(&signature.public_key) {
+            return Err(BlockValidationError::UnauthorizedValidator {
+                public_key: PublicKeyOnly(signature.public_key),
+            }
+            .into());
+        }
+        if !signature.verify_signature_bytes(&self.header.hash()?)? {
+            return Err(BlockValidationError::BadValidatorSignature.into());
         }
 
         Ok(())
     }
 
-    pub fn validate(&self) -> Result<()> {
-        self.header.validate_hash()?;
-        self.validate_merkle_root()?;
-        self.validate_transactions()?;
+    pub fn validate_transaction_inputs(
+        &self,
+        utxo_set: &UTXOSet,
+        params: &NetworkParams,
+    ) -> Result<()> {
+        self.connect(&mut utxo_set.clone(), params)?;
         Ok(())
     }
 
-    pub fn validate_transaction_inputs(&self, utxo_set: &UTXOSet) -> Result<()> {
-        let mut pending_utxo_set = utxo_set.clone();
-        for tx in &self.transactions {
-            pending_utxo_set.validate_transaction(tx)?;
-            pending_utxo_set.update(tx)?;
+    /// Validates each transaction's spend against `utxo_set` and applies it, returning the
+    /// undo data for the block. Signatures are left to `validate`. On error `utxo_set` is
+    /// left partially updated.
+    pub fn connect(&self, utxo_set: &mut UTXOSet, params: &NetworkParams) -> Result<BlockUndo> {
+        let _span = tracing::debug_span!(
+            "block.connect",
+            block_hash = %format_args!("{:#x}", self.header.hash().unwrap_or_default().display()),
+            height = self.height,
+        )
+        .entered();
+        let mut undo = BlockUndo::default();
+        let mut fees = 0u64;
+        let mut spent = HashSet::new();
+
+        for (index, tx) in self.transactions.iter().enumerate() {
+            if let Some(outpoint) = tx.body.input.outpoint()
+                && !spent.insert(outpoint)
+            {
+                return Err(Self::invalid_transaction(
+                    index,
+                    tx,
+                    UtxoError::DoubleSpend {
+                        outpoint: outpoint.clone(),
+                    },
+                ));
+            }
+            let fee = utxo_set
+                .validate_spend(tx, self.height)
+                .map_err(|error| Self::invalid_transaction(index, tx, error))?;
+            fees = fees
+                .checked_add(fee)
+                .ok_or(BlockValidationError::ValueOverflow)?;
+            undo.spent
+                .push(utxo_set.apply_transaction(tx, self.height)?);
+        }
+
+        let expected_block_reward = params
+            .coinbase_subsidy(self.height)
+            .and_then(|subsidy| subsidy.checked_add(fees))
+            .ok_or(BlockValidationError::ValueOverflow)?;
+        let block_reward = self
+            .transactions
+            .first()
+            .map(|coinbase| coinbase.output_value())
+            .unwrap_or_default();
+
+        if block_reward != expected_block_reward {
+            return Err(BlockValidationError::BadCoinbaseValue {
+                value: block_reward,
+                expected: expected_block_reward,
+            }
+            .into());
         }
-        Ok(())
+
+        tracing::debug!(fees, "Connected block");
+        Ok(undo)
     }
 }
 
+/// The clock, in the seconds since the epoch that headers are stamped with.
+#[cfg(feature = "clock")]
+fn now() -> u32 {
+    chrono::Utc::now().timestamp() as u32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::crypto::*;
+    use crate::params::Network;
     use crate::transaction::*;
 
     #[test]
@@ -226,14 +781,38 @@ mod tests {
         let hash = header.hash().unwrap();
 
         println!("Block Header Bytes: 0x{}", hex::encode(bytes));
-        println!("Block Hash: 0x{}", hex::encode(hash));
+        println!("Block Hash: {:#x}", hash.display());
+    }
+
+    #[test]
+    fn test_hash_with_buf() {
+        let header = BlockHeader {
+            previous_block_hash: [2; 32],
+            merkle_root: [3; 32],
+            timestamp: 1760850297,
+            difficulty: 1,
+            nonce: 0,
+        };
+
+        // nonces on both sides of each varint width
+        let mut buf = Vec::new();
+        let mut hasher = NonceHasher::new(&header).unwrap();
+        for nonce in [0, 1, 250, 251, 65_535, 65_536, 1 << 32, u64::MAX] {
+            let header = BlockHeader {
+                nonce,
+                ..header.clone()
+            };
+            let hash = header.hash().unwrap();
+            assert_eq!(header.hash_with_buf(&mut buf).unwrap(), hash);
+            assert_eq!(hasher.hash(nonce).unwrap(), hash);
+        }
     }
 
     #[test]
     fn test_difficulty_target() {
         let header = BlockHeader {
-            previous_block_hash: [0; 32],
-            merkle_root: [0; 32],
+            previous_block_hash: Hash::zero(),
+            merkle_root: Hash::zero(),
             timestamp: 0,
             difficulty: 2,
             nonce: 0,
@@ -248,14 +827,26 @@ mod tests {
         ];
 
         assert_eq!(target, expected);
-        println!("Difficulty target: 0x{}", hex::encode(target));
+        assert_eq!(target.leading_zero_bits(), 16);
+        println!("Difficulty target: {:#x}", target.display());
+
+        let at = |difficulty| {
+            BlockHeader {
+                difficulty,
+                ..header.clone()
+            }
+            .difficulty_target()
+        };
+        assert_eq!(at(0).unwrap()[0], 0xff);
+        assert_eq!(at(31).unwrap().to_biguint(), BigUint::from(0xffu32));
+        assert!(at(32).is_err());
     }
 
     #[test]
     fn test_compute_nonce() {
         let mut header = BlockHeader {
-            previous_block_hash: [0; 32],
-            merkle_root: [0; 32],
+            previous_block_hash: Hash::zero(),
+            merkle_root: Hash::zero(),
             timestamp: 1760850297,
             difficulty: 1,
             nonce: 0,
@@ -266,22 +857,82 @@ mod tests {
         header.nonce = nonce;
 
         let hash = header.hash().unwrap();
-        println!("Block Hash: 0x{}", hex::encode(hash));
+        println!("Block Hash: {:#x}", hash.display());
 
         let is_valid = header.validate_hash().unwrap();
         assert!(is_valid);
     }
 
+    #[test]
+    fn test_compute_nonce_interruptible() {
+        let header = BlockHeader {
+            previous_block_hash: Hash::zero(),
+            merkle_root: Hash::zero(),
+            timestamp: 1760850297,
+            difficulty: 1,
+            nonce: 0,
+        };
+
+        let nonce = header
+            .compute_nonce_interruptible(0, |_| false)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(nonce, header.compute_nonce_naive().unwrap());
+
+        // an unreachable target only returns once interrupted
+        let hard_header = BlockHeader {
+            difficulty: 31,
+            ..header
+        };
+
+        let result = hard_header
+            .compute_nonce_interruptible(0, |_| true)
+            .unwrap();
+
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_compute_nonce_parallel() {
+        let mut header = BlockHeader {
+            previous_block_hash: Hash::zero(),
+            merkle_root: Hash::zero(),
+            timestamp: 1760850297,
+            difficulty: 1,
+            nonce: 0,
+        };
+
+        header.nonce = header
+            .compute_nonce_parallel(4, |_| false)
+            .unwrap()
+            .unwrap();
+        assert!(header.validate_hash().unwrap());
+
+        let hard_header = BlockHeader {
+            difficulty: 31,
+            ..header
+        };
+        assert!(
+            hard_header
+                .compute_nonce_parallel(4, |_| true)
+                .unwrap()
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_build_block() {
-        let keypair_bob = KeyPair::generate();
+        let keypair_bob = KeyPair::from_seed(&[1; 32]).unwrap();
 
-        let genesis_tx = Transaction::new_coinbase(&keypair_bob, 0).unwrap();
+        let genesis_tx =
+            Transaction::new_coinbase(&keypair_bob, 0, &NetworkParams::default()).unwrap();
 
         let mut genesis_block = Block {
             height: 0,
-            transactions: vec![genesis_tx.clone()],
+            transactions: vec![Arc::new(genesis_tx.clone())],
             header: BlockHeader::default(),
+            signature: None,
         };
 
         genesis_block.header.difficulty = 1;
@@ -289,14 +940,14 @@ mod tests {
 
         println!(
             "Genesis block hash: 0x{}",
-            hex::encode(genesis_block.header.hash().unwrap())
+            genesis_block.header.hash().unwrap().display()
         );
 
-        let keypair_alice = KeyPair::generate();
+        let keypair_alice = KeyPair::from_seed(&[2; 32]).unwrap();
         let address_alice = Address::from_public_key(&keypair_alice.public_key);
 
         let tx_a_body = TransactionBody {
-            input: TransactionInput::Reference(genesis_tx.output_reference(0).unwrap()),
+            input: TransactionInput::spending(genesis_tx.output_reference(0).unwrap()),
             outputs: vec![TransactionOutput {
                 value: 50,
                 address: address_alice.clone(),
@@ -305,12 +956,516 @@ mod tests {
 
         let tx_a = tx_a_body.into_tx(&keypair_bob).unwrap();
 
-        let mut block = Block::new(&keypair_bob, &genesis_block, vec![tx_a.clone()]).unwrap();
+        let mut block = Block::new(
+            &keypair_bob,
+            &genesis_block,
+            vec![Arc::new(tx_a.clone())],
+            0,
+            &NetworkParams::default(),
+        )
+        .unwrap();
 
         block.mine().unwrap();
-        println!(
-            "Block hash: 0x{}",
-            hex::encode(block.header.hash().unwrap())
+        println!("Block hash: 0x{}", block.header.hash().unwrap().display());
+
+        let id = tx_a.id().unwrap();
+        let proof = block.transaction_proof(&id).unwrap();
+        assert_eq!(proof.index, 1);
+        assert!(proof.verify(&block.header.merkle_root, &id, 2));
+        assert!(!proof.verify(&block.header.merkle_root, &genesis_tx.id().unwrap(), 2));
+        assert_eq!(
+            TransactionProof::from_hex(&proof.to_hex().unwrap()).unwrap(),
+            proof
+        );
+        assert!(block.transaction_proof(&genesis_tx.id().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_tail_rejected() {
+        let params = NetworkParams::mainnet();
+        let keypair = KeyPair::from_seed(&[1; 32]).unwrap();
+
+        let mut previous = Block {
+            height: 0,
+            transactions: vec![Arc::new(
+                Transaction::new_coinbase(&keypair, 0, &NetworkParams::default()).unwrap(),
+            )],
+            header: BlockHeader::default(),
+            signature: None,
+        };
+        previous.header.difficulty = 1;
+
+        let spend = |value| {
+            TransactionBody {
+                input: TransactionInput::spending(
+                    previous.transactions[0].output_reference(0).unwrap(),
+                ),
+                outputs: vec![TransactionOutput {
+                    value,
+                    address: Address::from_public_key(&keypair.public_key),
+                }],
+            }
+            .into_tx(&keypair)
+            .map(Arc::new)
+            .unwrap()
+        };
+
+        // an odd number of transactions, so the last leaf is unpaired
+        let mut block = Block::new(
+            &keypair,
+            &previous,
+            vec![spend(1), spend(2)],
+            0,
+            &NetworkParams::default(),
+        )
+        .unwrap();
+        block.mine().unwrap();
+        block.validate(&params).unwrap();
+
+        // repeating the tail under the same header
+        let mut duplicated = block.clone();
+        duplicated
+            .transactions
+            .push(block.transactions.last().unwrap().clone());
+        let root = Transaction::build_merkle_tree(&duplicated.transactions)
+            .unwrap()
+            .root()
+            .unwrap();
+        assert_ne!(root, block.header.merkle_root);
+        assert!(duplicated.validate(&params).is_err());
+
+        // and with a header committing to the repeated transactions
+        duplicated.header.merkle_root = root;
+        duplicated.mine().unwrap();
+        let error = duplicated.validate(&params).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<BlockValidationError>(),
+            Some(BlockValidationError::DuplicateTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let params = NetworkParams::mainnet();
+        let keypair = KeyPair::from_seed(&[1; 32]).unwrap();
+        let address = Address::from_public_key(&keypair.public_key);
+
+        let previous = Block {
+            height: 0,
+            transactions: vec![Arc::new(
+                Transaction::new_coinbase(&keypair, 0, &params).unwrap(),
+            )],
+            header: BlockHeader::default(),
+            signature: None,
+        };
+        let mut utxo_set = UTXOSet::default();
+        utxo_set.apply_block(&previous).unwrap();
+
+        let outpoint = previous.transactions[0].output_reference(0).unwrap();
+        let value = previous.transactions[0].output_value();
+        let spend = |outpoint: &TransactionOutputReference, address: &Address, signer| {
+            TransactionBody {
+                input: TransactionInput::spending(outpoint.clone()),
+                outputs: vec![TransactionOutput {
+                    value: value - 1,
+                    address: address.clone(),
+                }],
+            }
+            .into_tx(signer)
+            .unwrap()
+        };
+        // at the previous block's difficulty of 0 mining takes a few tries at most
+        let mined = |mut block: Block| {
+            block.mine().unwrap();
+            block
+        };
+        let block_with = |transactions: Vec<Transaction>, fees| {
+            let transactions = transactions.into_iter().map(Arc::new).collect();
+            mined(Block::new(&keypair, &previous, transactions, fees, &params).unwrap())
+        };
+        let resealed = |mut block: Block| {
+            block.header.merkle_root = Transaction::build_merkle_tree(&block.transactions)
+                .unwrap()
+                .root()
+                .unwrap_or_default();
+            mined(block)
+        };
+        let validate = |block: &Block| {
+            block
+                .validate(&params)
+                .and_then(|_| block.connect(&mut utxo_set.clone(), &params).map(|_| ()))
+        };
+
+        let payment = spend(&outpoint, &address, &keypair);
+        let block = block_with(vec![payment.clone()], 1);
+        validate(&block).unwrap();
+
+        let mut cases = Vec::new();
+
+        let mut unmined = block.clone();
+        unmined.header.difficulty = 4;
+        cases.push(("unmined", unmined, BlockValidationError::InvalidProofOfWork));
+
+        let mut oversized = block.clone();
+        let repeated = Arc::new(payment.clone());
+        oversized
+            .transactions
+            .extend(std::iter::repeat_n(repeated, BLOCK_SIZE_LIMIT - 1));
+        cases.push((
+            "oversized",
+            resealed(oversized),
+            BlockValidationError::Oversized {
+                transactions: BLOCK_SIZE_LIMIT + 1,
+                limit: BLOCK_SIZE_LIMIT,
+            },
+        ));
+
+        let mut wrong_root = block.clone();
+        wrong_root.header.merkle_root = Hash::default();
+        cases.push((
+            "wrong merkle root",
+            mined(wrong_root),
+            BlockValidationError::MerkleRootMismatch,
+        ));
+
+        let mut repeated = block.clone();
+        repeated.transactions.push(Arc::new(payment.clone()));
+        cases.push((
+            "repeated transaction",
+            resealed(repeated),
+            BlockValidationError::DuplicateTransaction {
+                txid: payment.id().unwrap(),
+            },
+        ));
+
+        let mut no_coinbase = block.clone();
+        no_coinbase.transactions.remove(0);
+        cases.push((
+            "no coinbase",
+            resealed(no_coinbase),
+            BlockValidationError::MissingCoinbase,
+        ));
+
+        let mut second_coinbase = block.clone();
+        second_coinbase.transactions.push(Arc::new(
+            Transaction::new_coinbase(&KeyPair::generate(), 1, &params).unwrap(),
+        ));
+        cases.push((
+            "second coinbase",
+            resealed(second_coinbase),
+            BlockValidationError::DuplicateCoinbase { index: 2 },
+        ));
+
+        let subsidy = params.coinbase_subsidy(1).unwrap();
+        let mut short_coinbase = block.clone();
+        short_coinbase.transactions[0] = TransactionBody {
+            input: TransactionInput::Coinbase { block_height: 1 },
+            outputs: vec![TransactionOutput {
+                value: 1,
+                address: address.clone(),
+            }],
+        }
+        .into_tx(&keypair)
+        .map(Arc::new)
+        .unwrap();
+        cases.push((
+            "coinbase short of the subsidy",
+            resealed(short_coinbase),
+            BlockValidationError::BadCoinbaseValue {
+                value: 1,
+                expected: subsidy,
+            },
+        ));
+        let mut overflowing_coinbase = block.clone();
+        overflowing_coinbase.transactions[0] = TransactionBody {
+            input: TransactionInput::Coinbase { block_height: 1 },
+            outputs: vec![
+                TransactionOutput {
+                    value: u64::MAX,
+                    address: address.clone(),
+                };
+                2
+            ],
+        }
+        .into_tx(&keypair)
+        .map(Arc::new)
+        .unwrap();
+        let overflowing_coinbase = resealed(overflowing_coinbase);
+        cases.push((
+            "coinbase outputs overflowing",
+            overflowing_coinbase.clone(),
+            BlockValidationError::Transaction {
+                index: 0,
+                txid: overflowing_coinbase.transactions[0].id().unwrap(),
+                error: TxValidationError::ValueOverflow.into(),
+            },
+        ));
+        let respend = spend(
+            &outpoint,
+            &Address::from_public_key(&KeyPair::generate().public_key),
+            &keypair,
+        );
+        cases.push((
+            "double spend",
+            block_with(vec![payment.clone(), respend.clone()], 2),
+            BlockValidationError::Transaction {
+                index: 2,
+                txid: respend.id().unwrap(),
+                error: UtxoError::DoubleSpend {
+                    outpoint: outpoint.clone(),
+                },
+            },
+        ));
+        cases.push((
+            "coinbase missing the fees",
+            block_with(vec![payment.clone()], 0),
+            BlockValidationError::BadCoinbaseValue {
+                value: subsidy,
+                expected: subsidy + 1,
+            },
+        ));
+
+        let invalid_transaction = |transaction: Transaction, error: UtxoError| {
+            let txid = transaction.id().unwrap();
+            (
+                block_with(vec![transaction], 1),
+                BlockValidationError::Transaction {
+                    index: 1,
+                    txid,
+                    error,
+                },
+            )
+        };
+        let mut tampered = payment.clone();
+        tampered.body.outputs[0].value -= 1;
+        let missing = TransactionOutputReference {
+            index: 1,
+            ..outpoint.clone()
+        };
+        let testnet = Address::from_public_key_for(&keypair.public_key, Network::Testnet);
+        for (name, (block, error)) in [
+            (
+                "bad signature",
+                invalid_transaction(tampered, TxValidationError::BadSignature.into()),
+            ),
+            (
+                "missing outpoint",
+                invalid_transaction(
+                    spend(&missing, &address, &keypair),
+                    UtxoError::MissingOutpoint { outpoint: missing },
+                ),
+            ),
+            (
+                "spend of another's output",
+                invalid_transaction(
+                    spend(&outpoint, &address, &KeyPair::generate()),
+                    UtxoError::OwnershipMismatch {
+                        address: address.to_string(),
+                    },
+                ),
+            ),
+            (
+                "output on another network",
+                invalid_transaction(
+                    spend(&outpoint, &testnet, &keypair),
+                    TxValidationError::WrongNetwork {
+                        address: testnet.to_string(),
+                        network: Network::Mainnet,
+                    }
+                    .into(),
+                ),
+            ),
+        ] {
+            cases.push((name, block, error));
+        }
+
+        for (name, block, expected) in cases {
+            let error = validate(&block).expect_err(name);
+            assert_eq!(
+                error.downcast_ref::<BlockValidationError>(),
+                Some(&expected),
+                "{name}"
+            );
+        }
+
+        // fees too large to add up are refused rather than wrapping
+        let rich = Block {
+            transactions: vec![Arc::new(
+                TransactionBody {
+                    input: TransactionInput::Coinbase { block_height: 0 },
+                    outputs: vec![
+                        TransactionOutput {
+                            value: u64::MAX,
+                            address: address.clone(),
+                        };
+                        2
+                    ],
+                }
+                .into_tx(&keypair)
+                .unwrap(),
+            )],
+            ..previous.clone()
+        };
+        let mut rich_utxo_set = UTXOSet::default();
+        rich_utxo_set.apply_block(&rich).unwrap();
+        let spends = (0..2)
+            .map(|index| {
+                let outpoint = rich.transactions[0].output_reference(index).unwrap();
+                TransactionBody {
+                    input: TransactionInput::spending(outpoint),
+                    outputs: vec![TransactionOutput {
+                        value: 1,
+                        address: address.clone(),
+                    }],
+                }
+                .into_tx(&keypair)
+                .map(Arc::new)
+                .unwrap()
+            })
+            .collect();
+        let greedy = Block::new(&keypair, &rich, spends, 0, &params).unwrap();
+        assert_eq!(
+            greedy
+                .connect(&mut rich_utxo_set, &params)
+                .unwrap_err()
+                .downcast_ref::<BlockValidationError>(),
+            Some(&BlockValidationError::ValueOverflow)
+        );
+
+        // compared loosely, as the limit moves with the clock
+        let mut future = block.clone();
+        future.header.timestamp = u32::MAX;
+        assert!(matches!(
+            validate(&mined(future)).unwrap_err().downcast(),
+            Ok(BlockValidationError::TimestampTooFar {
+                timestamp: u32::MAX,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_validate_at() {
+        let params = NetworkParams::regtest();
+        let blocks = crate::fixtures::chain(2, &crate::fixtures::key_pair(1), &params).unwrap();
+        let block = &blocks[1];
+        let earliest = block.header.timestamp - MAX_FUTURE_BLOCK_TIME_SECS;
+
+        block.validate_at(&params, earliest).unwrap();
+        assert_eq!(
+            block
+                .validate_at(&params, earliest - 1)
+                .unwrap_err()
+                .downcast_ref::<BlockValidationError>(),
+            Some(&BlockValidationError::TimestampTooFar {
+                timestamp: block.header.timestamp,
+                max: block.header.timestamp - 1,
+            })
+        );
+    }
+
+    /// A block with both kinds of input, from fixtures, so its encodings are the same on
+    /// every run.
+    fn fixture_block() -> Block {
+        let params = NetworkParams::regtest();
+        let key_pair = crate::fixtures::key_pair(1);
+        let (funding, _) = crate::fixtures::funding(1, 100, &key_pair, &params).unwrap();
+        let spends = crate::fixtures::spends(&funding, &key_pair, 1).unwrap();
+        let genesis = params.genesis_block().unwrap();
+        crate::fixtures::block(&genesis, &key_pair, spends, 1, &params).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let block = fixture_block();
+
+        let json = serde_json::to_string(&block).unwrap();
+        assert_eq!(serde_json::from_str::<Block>(&json).unwrap(), block);
+        assert_eq!(
+            Block::from_bytes(&block.to_bytes().unwrap()).unwrap(),
+            block
+        );
+
+        let header = block.header.as_bytes().unwrap();
+        assert_eq!(BlockHeader::from_bytes(&header).unwrap(), block.header);
+        assert!(BlockHeader::from_bytes(&[header.as_slice(), &[0]].concat()).is_err());
+        for transaction in &block.transactions {
+            let (body, _): (TransactionBody, _) = bincode::decode_from_slice(
+                &transaction.body.as_bytes().unwrap(),
+                bincode::config::standard(),
+            )
+            .unwrap();
+            assert_eq!(body, transaction.body);
+        }
+    }
+
+    #[test]
+    fn test_signature_encoding() {
+        // the signature is a field like any other, in JSON and on the wire alike
+        let mut signed = fixture_block();
+        signed.sign(&crate::fixtures::key_pair(1)).unwrap();
+        assert_eq!(
+            Block::from_bytes(&signed.to_bytes().unwrap()).unwrap(),
+            signed
+        );
+        assert_eq!(Block::from_hex(&signed.to_hex().unwrap()).unwrap(), signed);
+        let json = serde_json::to_string(&signed).unwrap();
+        assert_eq!(serde_json::from_str::<Block>(&json).unwrap(), signed);
+
+        // block files from before signatures still load
+        let mut unsigned = serde_json::to_value(fixture_block()).unwrap();
+        unsigned.as_object_mut().unwrap().remove("signature");
+        assert_eq!(
+            serde_json::from_value::<Block>(unsigned).unwrap(),
+            fixture_block()
+        );
+    }
+
+    #[test]
+    fn test_json_fixture() {
+        const PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/block.json");
+        let block = fixture_block();
+        let json = serde_json::to_string_pretty(&block).unwrap() + "\n";
+        if std::env::var_os("UPDATE_FIXTURES").is_some() {
+            std::fs::write(PATH, json).unwrap();
+            return;
+        }
+
+        let fixture = include_str!("../tests/fixtures/block.json");
+        assert_eq!(
+            json, fixture,
+            "Block's JSON changed; rerun with UPDATE_FIXTURES=1 if that's intended"
+        );
+        assert_eq!(serde_json::from_str::<Block>(fixture).unwrap(), block);
+    }
+
+    #[test]
+    fn test_display() {
+        let params = NetworkParams::regtest();
+        let key_pair = crate::fixtures::key_pair(1);
+        let (funding, _) = crate::fixtures::funding(2, 100, &key_pair, &params).unwrap();
+        let mut spends = crate::fixtures::spends(&funding, &key_pair, 1).unwrap();
+        spends.truncate(1);
+        let blocks = crate::fixtures::chain(3, &key_pair, &params).unwrap();
+        let block = crate::fixtures::block(&blocks[2], &key_pair, spends, 1, &params).unwrap();
+
+        assert_eq!(
+            block.to_string(),
+            [
+                "Block 4 0xf484a9a136e6b5630c3e4bfc85d65191e29b64415ea0f5ee83dbfe7088716c4c",
+                "  Previous: 0x5019e09df8bcfe975c949070ff41ed6bb7fd823c5629b98f08fc77cd34dd7e31",
+                "  Merkle Root: 0x5ccb7910cd65cc60fc1d4fc7e14869d0be77f0a436046e7376715a9e8831a4f0",
+                "  Timestamp: 2025-10-19T05:34:57+00:00",
+                "  Difficulty: 0, Nonce: 0",
+                "  Transactions: 2",
+                "    Transaction 0x9e236b4b553cdc4065f3240c483ded4c78ddad1a74663563b2a87e5b054213cd",
+                "      Input: coinbase at height 4",
+                "      Output 0: 51 → mxFcx9eEn6GWhjyCtmn1KfZW5XsCLZ68Kt",
+                "    Transaction 0xb0844fc9f33c5e6a03f68b997a8abc9fe26479b66f77d8f6a7735a5551149670",
+                "      Input: 0x9ea570ac6381e85fb3ff490a48040ccddb908924f7401f01851f7ad26c3fb0d3:0",
+                "      Output 0: 99 → mxFcx9eEn6GWhjyCtmn1KfZW5XsCLZ68Kt",
+            ]
+            .join("\n")
         );
     }
 }
@@ -0,0 +1,292 @@
+use std::collections::{BTreeMap, HashMap, btree_map::Entry};
+
+use libp2p::PeerId;
+
+use crate::{
+    block::Block,
+    constants::{BLOCK_DOWNLOAD_WINDOW, MAX_BLOCKS_IN_FLIGHT_PER_PEER},
+    crypto::{Hash, HashExt},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("Block {:#x} wasn't requested from this peer", .0.display())]
+    Unrequested(Hash),
+    #[error("Block {:#x} doesn't match its announced header", .0.display())]
+    Mismatch(Hash),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Block bodies along the best header chain being fetched from peers. Only blocks within
+/// `window` heights of the lowest one not yet handed back are requested, at most
+/// `per_peer` from any one peer, and blocks are handed back in height order whatever
+/// order they arrive in.
+#[derive(Debug, Clone)]
+pub struct BlockDownload {
+    window: u32,
+    per_peer: usize,
+    queued: BTreeMap<u32, Hash>,
+    in_flight: HashMap<Hash, (u32, PeerId)>,
+    /// Every scheduled height not handed back yet, with its block once it has arrived.
+    pending: BTreeMap<u32, Option<(Block, PeerId)>>,
+}
+
+impl Default for BlockDownload {
+    fn default() -> Self {
+        Self::new(BLOCK_DOWNLOAD_WINDOW, MAX_BLOCKS_IN_FLIGHT_PER_PEER)
+    }
+}
+
+impl BlockDownload {
+    pub fn new(window: u32, per_peer: usize) -> Self {
+        Self {
+            window,
+            per_peer,
+            queued: BTreeMap::new(),
+            in_flight: HashMap::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Queues blocks to fetch. A height already scheduled keeps its original block.
+    pub fn schedule(&mut self, blocks: impl IntoIterator<Item = (u32, Hash)>) {
+        for (height, hash) in blocks {
+            if let Entry::Vacant(entry) = self.pending.entry(height) {
+                entry.insert(None);
+                self.queued.insert(height, hash);
+            }
+        }
+    }
+
+    /// Hands queued blocks to the least busy of `peers` (with their best heights) that
+    /// have them, returning the requests to send.
+    pub fn assign(&mut self, peers: &[(PeerId, u32)]) -> Vec<(PeerId, Hash)> {
+        let Some(&lowest) = self.pending.keys().next() else {
+            return vec![];
+        };
+
+        let mut load = peers
+            .iter()
+            .map(|(peer_id, _)| (*peer_id, 0))
+            .collect::<HashMap<_, _>>();
+        for (_, peer_id) in self.in_flight.values() {
+            if let Some(count) = load.get_mut(peer_id) {
+                *count += 1;
+            }
+        }
+
+        let mut requests = Vec::new();
+
+        while let Some((&height, &hash)) = self.queued.first_key_value() {
+            if height >= lowest.saturating_add(self.window) {
+                break;
+            }
+
+            // heights only go up, so a block no peer can take leaves the rest waiting too
+            let Some((peer_id, _)) = peers
+                .iter()
+                .filter(|(peer_id, best_height)| {
+                    *best_height >= height && load[peer_id] < self.per_peer
+                })
+                .min_by_key(|(peer_id, _)| load[peer_id])
+            else {
+                break;
+            };
+
+            *load.get_mut(peer_id).unwrap() += 1;
+            self.queued.remove(&height);
+            self.in_flight.insert(hash, (height, *peer_id));
+            requests.push((*peer_id, hash));
+        }
+
+        requests
+    }
+
+    /// Takes a block `peer_id` sent, returning the blocks now ready to connect, lowest
+    /// first, along with the peers that sent them.
+    pub fn receive(
+        &mut self,
+        peer_id: PeerId,
+        block: Block,
+    ) -> Result<Vec<(Block, PeerId)>, DownloadError> {
+        let hash = block.header.hash()?;
+
+        let height = match self.in_flight.get(&hash) {
+            Some((height, from)) if *from == peer_id => *height,
+            _ => return Err(DownloadError::Unrequested(hash)),
+        };
+        self.in_flight.remove(&hash);
+
+        if block.height != height || block.validate_merkle_root().is_err() {
+            self.queued.insert(height, hash);
+            return Err(DownloadError::Mismatch(hash));
+        }
+
+        self.pending.insert(height, Some((block, peer_id)));
+
+        let mut ready = Vec::new();
+        while let Some(entry) = self.pending.first_entry()
+            && entry.get().is_some()
+        {
+            ready.extend(entry.remove());
+        }
+
+        Ok(ready)
+    }
+
+    /// Puts a block back in the queue after its request failed.
+    pub fn requeue(&mut self, hash: &Hash) {
+        if let Some((height, _)) = self.in_flight.remove(hash) {
+            self.queued.insert(height, *hash);
+        }
+    }
+
+    /// Requeues everything in flight from a peer that went away.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        let hashes = self
+            .in_flight
+            .iter()
+            .filter(|(_, (_, from))| from == peer_id)
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+
+        for hash in hashes {
+            self.requeue(&hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+
+    fn mined_blocks(count: u32) -> (Vec<Block>, tempfile::TempDir) {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(KeyPair::generate())
+                .data_dir(data_dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap();
+
+        let mut blocks = Vec::new();
+        for _ in 0..count {
+            let block = node.create_block().unwrap();
+            node.handle_message(Message::NewBlock(block.clone()))
+                .unwrap();
+            blocks.push(block);
+        }
+
+        (blocks, data_dir)
+    }
+
+    fn schedule(download: &mut BlockDownload, blocks: &[Block]) {
+        download.schedule(
+            blocks
+                .iter()
+                .map(|block| (block.height, block.header.hash().unwrap())),
+        );
+    }
+
+    #[test]
+    fn test_fetch_out_of_order_connect_in_order() {
+        let (blocks, _data_dir) = mined_blocks(10);
+        let by_hash = blocks
+            .iter()
+            .map(|block| (block.header.hash().unwrap(), block.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+        let peers = [(peer_a, 11), (peer_b, 11)];
+
+        let mut download = BlockDownload::new(6, 2);
+        schedule(&mut download, &blocks);
+
+        // two per peer, then nothing until blocks come back
+        let requests = download.assign(&peers);
+        assert_eq!(requests.len(), 4);
+        assert!(download.assign(&peers).is_empty());
+
+        // everything but the lowest block arrives first, and is held back
+        let (first, rest) = requests.split_first().unwrap();
+        for (peer_id, hash) in rest.iter().rev() {
+            let ready = download.receive(*peer_id, by_hash[hash].clone()).unwrap();
+            assert!(ready.is_empty());
+        }
+
+        // the window stops requests more than six heights past the missing block
+        let requests = download.assign(&peers);
+        assert_eq!(requests.len(), 2);
+        assert!(download.assign(&peers).is_empty());
+
+        let ready = download
+            .receive(first.0, by_hash[&first.1].clone())
+            .unwrap();
+        let heights = ready
+            .iter()
+            .map(|(block, _)| block.height)
+            .collect::<Vec<_>>();
+        assert_eq!(heights, [2, 3, 4, 5]);
+
+        for (peer_id, hash) in requests {
+            download.receive(peer_id, by_hash[&hash].clone()).unwrap();
+        }
+        while !download.is_idle() {
+            for (peer_id, hash) in download.assign(&peers) {
+                download.receive(peer_id, by_hash[&hash].clone()).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_lying_and_departed_peers() {
+        let (blocks, _data_dir) = mined_blocks(3);
+        let (liar, honest) = (PeerId::random(), PeerId::random());
+
+        let mut download = BlockDownload::new(10, 10);
+        schedule(&mut download, &blocks);
+        let requests = download.assign(&[(liar, 4)]);
+        assert_eq!(requests.len(), 3);
+
+        // right header, wrong body
+        let mut tampered = blocks[0].clone();
+        tampered.transactions.clear();
+        assert!(matches!(
+            download.receive(liar, tampered),
+            Err(DownloadError::Mismatch(_))
+        ));
+        assert!(matches!(
+            download.receive(honest, blocks[1].clone()),
+            Err(DownloadError::Unrequested(_))
+        ));
+
+        download.remove_peer(&liar);
+        assert_eq!(download.in_flight(), 0);
+
+        // an honest peer that's too far behind is never asked
+        assert!(download.assign(&[(honest, 1)]).is_empty());
+        let requests = download.assign(&[(honest, 4)]);
+        assert_eq!(requests.len(), 3);
+
+        let mut ready = Vec::new();
+        for (block, (peer_id, _)) in blocks.iter().zip(requests) {
+            ready.extend(download.receive(peer_id, block.clone()).unwrap());
+        }
+        assert_eq!(ready.len(), 3);
+        assert!(download.is_idle());
+    }
+}
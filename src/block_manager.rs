@@ -1,13 +1,95 @@
 use anyhow::Result;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::{block::Block, chain::BlockchainNode, crypto::Hash};
+use crate::{
+    block::Block,
+    chain::BlockchainNode,
+    crypto::{Hash, HashExt},
+};
 
 #[derive(Debug, Clone, Default)]
 pub struct BlockManager {
     pub blocks: HashMap<Hash, Arc<Block>>,
     pub nodes: HashMap<Hash, Arc<BlockchainNode>>,
     pub orphan_blocks: HashMap<Hash, Arc<Block>>,
+    pub data_dir: Option<PathBuf>,
+}
+
+/// Why a block file was set aside rather than loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    InvalidName,
+    Unreadable(String),
+    HashMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+impl fmt::Display for SkippedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.display();
+        match &self.reason {
+            SkipReason::InvalidName => write!(f, "Block file with invalid name: {path}"),
+            SkipReason::Unreadable(error) => write!(f, "Unreadable block file {path}: {error}"),
+            SkipReason::HashMismatch => write!(f, "Block file with mismatched hash: {path}"),
+        }
+    }
+}
+
+/// A block file `BlockManager::fsck` found wrong, or a stored block off every chain.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StorageProblem {
+    #[error("{0}")]
+    Skipped(SkippedFile),
+    #[error("Orphan block 0x{}, whose parent isn't stored", .0.display())]
+    Orphan(Hash),
+}
+
+/// What `BlockManager::load_from_disk` made of the data dir.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Blocks loaded onto the block tree.
+    pub loaded: usize,
+    /// Blocks loaded whose parents aren't stored.
+    pub orphaned: usize,
+    /// Files that aren't blocks, can't be read, or don't match their names, by path.
+    pub skipped: Vec<SkippedFile>,
+}
+
+impl LoadReport {
+    pub fn log(&self) {
+        for file in &self.skipped {
+            tracing::warn!(path = %file.path.display(), "{file}, skipping it");
+        }
+        if self.orphaned > 0 {
+            tracing::warn!(orphaned = self.orphaned, "Loaded orphan blocks");
+        }
+    }
+}
+
+/// Why the data dir couldn't be loaded at all, as opposed to the block files skipped in
+/// a `LoadReport`.
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Can't read block dir {}: {error}", .path.display())]
+    UnreadableDir {
+        path: PathBuf,
+        #[source]
+        error: io::Error,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
 }
 
 #[derive(Debug, Clone)]
@@ -17,6 +99,169 @@ pub enum AddBlockResult {
 }
 
 impl BlockManager {
+    /// Opens a block manager persisting blocks to `data_dir`, loading any blocks already
+    /// there.
+    pub fn open(data_dir: impl Into<PathBuf>) -> Result<(Self, LoadReport), StorageError> {
+        let data_dir = data_dir.into();
+        fs::create_dir_all(&data_dir)?;
+
+        let mut block_manager = Self {
+            data_dir: Some(data_dir),
+            ..Self::default()
+        };
+        let report = block_manager.load_from_disk()?;
+
+        Ok((block_manager, report))
+    }
+
+    fn block_path(&self, hash: &Hash) -> Option<PathBuf> {
+        self.data_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", hash.display())))
+    }
+
+    fn read_block_file(path: &Path) -> Result<Block> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Reads every block file in `data_dir`, setting aside those that can't be read or
+    /// whose contents don't match the hash in their filename.
+    fn scan(data_dir: &Path) -> Result<(Vec<Block>, Vec<SkippedFile>), StorageError> {
+        let mut blocks = Vec::new();
+        let mut skipped = Vec::new();
+        let mut buf = Vec::new();
+
+        let entries = fs::read_dir(data_dir).map_err(|error| StorageError::UnreadableDir {
+            path: data_dir.to_path_buf(),
+            error,
+        })?;
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            let Some(expected_hash) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| Hash::from_hex(stem).ok())
+            else {
+                skipped.push(SkippedFile {
+                    path,
+                    reason: SkipReason::InvalidName,
+                });
+                continue;
+            };
+
+            let block = match Self::read_block_file(&path) {
+                Ok(block) => block,
+                Err(e) => {
+                    skipped.push(SkippedFile {
+                        path,
+                        reason: SkipReason::Unreadable(e.to_string()),
+                    });
+                    continue;
+                }
+            };
+
+            if block.header.hash_with_buf(&mut buf)? != expected_hash {
+                skipped.push(SkippedFile {
+                    path,
+                    reason: SkipReason::HashMismatch,
+                });
+                continue;
+            }
+
+            blocks.push(block);
+        }
+
+        blocks.sort_by_key(|block| block.height);
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((blocks, skipped))
+    }
+
+    /// Loads every block file in the data dir, skipping files that can't be read or whose
+    /// contents don't match the hash in their filename, and reporting what it did.
+    pub fn load_from_disk(&mut self) -> Result<LoadReport, StorageError> {
+        let Some(data_dir) = self.data_dir.clone() else {
+            return Ok(LoadReport::default());
+        };
+
+        let (blocks, skipped) = Self::scan(&data_dir)?;
+        let mut report = LoadReport {
+            skipped,
+            ..LoadReport::default()
+        };
+
+        for block in blocks {
+            match self.add_block(Arc::new(block))? {
+                AddBlockResult::Added(_) => report.loaded += 1,
+                AddBlockResult::Orphaned => report.orphaned += 1,
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn persist_block(&self, hash: &Hash, block: &Block) -> Result<()> {
+        let Some(path) = self.block_path(hash) else {
+            return Ok(());
+        };
+
+        if !path.exists() {
+            fs::write(path, serde_json::to_vec(block)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the block files in the data dir again, and lists the stored blocks no chain
+    /// reaches.
+    pub fn fsck(&self) -> Result<Vec<StorageProblem>> {
+        let mut problems = match &self.data_dir {
+            Some(data_dir) => Self::scan(data_dir)?
+                .1
+                .into_iter()
+                .map(StorageProblem::Skipped)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut orphans = self.orphan_blocks.keys().copied().collect::<Vec<_>>();
+        orphans.sort();
+        problems.extend(orphans.into_iter().map(StorageProblem::Orphan));
+
+        Ok(problems)
+    }
+
+    /// Bytes taken by the block files in the data dir.
+    pub fn disk_usage(&self) -> Result<u64> {
+        let Some(data_dir) = &self.data_dir else {
+            return Ok(0);
+        };
+
+        let mut total = 0;
+        for entry in fs::read_dir(data_dir)? {
+            total += entry?.metadata()?.len();
+        }
+        Ok(total)
+    }
+
+    /// Hashes of the stored blocks at `height`, on any branch, in hash order.
+    pub fn hashes_at_height(&self, height: u32) -> Vec<Hash> {
+        let mut hashes = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.height == height)
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+        hashes.sort_unstable();
+        hashes
+    }
+
     pub fn get_block(&self, hash: &Hash) -> Option<&Block> {
         self.blocks.get(hash).map(Arc::as_ref)
     }
@@ -25,6 +270,14 @@ impl BlockManager {
         self.blocks.contains_key(hash)
     }
 
+    /// The stored node with the most cumulative work.
+    pub fn best_node(&self) -> Option<Arc<BlockchainNode>> {
+        self.nodes
+            .values()
+            .max_by(|a, b| a.work.cmp(&b.work))
+            .cloned()
+    }
+
     pub fn add_block(&mut self, block: Arc<Block>) -> Result<AddBlockResult> {
         let hash = block.header.hash()?;
 
@@ -40,15 +293,146 @@ impl BlockManager {
 
         let node_ref = Arc::new(node);
 
+        self.persist_block(&hash, &block)?;
+
         self.nodes.insert(hash, node_ref.clone());
         self.blocks.insert(hash, block);
+        self.orphan_blocks.remove(&hash);
 
         Ok(AddBlockResult::Added(node_ref))
     }
 
-    pub fn remove_block(&mut self, hash: &Hash) {
+    pub fn remove_block(&mut self, hash: &Hash) -> Result<()> {
         self.blocks.remove(hash);
         self.nodes.remove(hash);
         self.orphan_blocks.remove(hash);
+
+        if let Some(path) = self.block_path(hash).filter(|path| path.exists()) {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::*;
+    use crate::crypto::*;
+    use crate::params::*;
+    use crate::transaction::*;
+
+    #[test]
+    fn test_block_persistence() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let keypair = KeyPair::generate();
+
+        let transactions = vec![Arc::new(
+            Transaction::new_coinbase(&keypair, 1, &NetworkParams::default()).unwrap(),
+        )];
+        let merkle_root = Transaction::build_merkle_tree(&transactions)
+            .unwrap()
+            .root()
+            .unwrap();
+
+        let genesis = Block {
+            height: 1,
+            header: BlockHeader {
+                merkle_root,
+                ..BlockHeader::default()
+            },
+            transactions,
+            signature: None,
+        };
+        let child = Block::new(&keypair, &genesis, vec![], 0, &NetworkParams::default()).unwrap();
+
+        let genesis_hash = genesis.header.hash().unwrap();
+        let child_hash = child.header.hash().unwrap();
+
+        let (mut block_manager, _) = BlockManager::open(data_dir.path()).unwrap();
+        block_manager.add_block(Arc::new(genesis)).unwrap();
+        block_manager.add_block(Arc::new(child)).unwrap();
+
+        // a stray file that isn't a block is skipped rather than failing the load
+        fs::write(data_dir.path().join("not-a-block.json"), b"{}").unwrap();
+
+        let (reopened, report) = BlockManager::open(data_dir.path()).unwrap();
+        assert_eq!(reopened.blocks.len(), 2);
+        assert_eq!(report.loaded, 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(reopened.contains_block(&genesis_hash));
+        assert_eq!(
+            reopened.best_node().unwrap().header.hash().unwrap(),
+            child_hash
+        );
+
+        let mut block_manager = reopened;
+        block_manager.remove_block(&child_hash).unwrap();
+
+        let (reopened, _) = BlockManager::open(data_dir.path()).unwrap();
+        assert_eq!(reopened.blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_load_report() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let keypair = KeyPair::generate();
+        let params = NetworkParams::regtest();
+        let genesis = params.genesis_block().unwrap();
+        let child = Block::new(&keypair, &genesis, vec![], 0, &params).unwrap();
+        let missing_parent = Block::new(&KeyPair::generate(), &child, vec![], 0, &params).unwrap();
+        let orphan = Block::new(&keypair, &missing_parent, vec![], 0, &params).unwrap();
+
+        let (mut block_manager, report) = BlockManager::open(data_dir.path()).unwrap();
+        assert_eq!(report, LoadReport::default());
+        block_manager.add_block(Arc::new(genesis)).unwrap();
+        block_manager.add_block(Arc::new(child.clone())).unwrap();
+
+        // the orphan's file is written by hand, as the manager only stores linked blocks
+        let orphan_hash = orphan.header.hash().unwrap();
+        let orphan_path = data_dir
+            .path()
+            .join(format!("{}.json", orphan_hash.display()));
+        fs::write(orphan_path, serde_json::to_vec(&orphan).unwrap()).unwrap();
+
+        let bad_json = data_dir
+            .path()
+            .join(format!("{}.json", Hash::default().display()));
+        fs::write(&bad_json, b"{not json").unwrap();
+        let wrong_hash = data_dir.path().join(format!("{}.json", [1; 32].display()));
+        fs::write(&wrong_hash, serde_json::to_vec(&child).unwrap()).unwrap();
+
+        let (reopened, report) = BlockManager::open(data_dir.path()).unwrap();
+        assert_eq!(report.loaded, 2);
+        assert_eq!(report.orphaned, 1);
+        assert!(reopened.orphan_blocks.contains_key(&orphan_hash));
+        assert!(matches!(
+            &report.skipped[..],
+            [
+                SkippedFile {
+                    path: first,
+                    reason: SkipReason::Unreadable(_),
+                },
+                SkippedFile {
+                    path: second,
+                    reason: SkipReason::HashMismatch,
+                },
+            ] if *first == bad_json && *second == wrong_hash
+        ));
+
+        let problems = reopened.fsck().unwrap();
+        assert_eq!(problems.len(), 3);
+        assert_eq!(problems[2], StorageProblem::Orphan(orphan_hash));
+
+        let missing = data_dir.path().join("missing");
+        let mut block_manager = BlockManager {
+            data_dir: Some(missing),
+            ..BlockManager::default()
+        };
+        assert!(matches!(
+            block_manager.load_from_disk(),
+            Err(StorageError::UnreadableDir { .. })
+        ));
     }
 }
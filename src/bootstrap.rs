@@ -0,0 +1,209 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use libp2p::{Multiaddr, PeerId, multiaddr::Protocol, swarm::ConnectionId};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BootstrapState {
+    Dialing,
+    Connected,
+    /// Waiting to redial after a failed dial or a lost connection.
+    Waiting,
+    /// The peer turned out to be unable to talk to us, so it's never redialed.
+    Banned,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootstrapPeer {
+    pub peer_id: Option<PeerId>,
+    pub state: BootstrapState,
+    /// Failed dials and lost connections since the last successful connection.
+    pub failures: u32,
+    #[serde(skip)]
+    pub retry_at: Option<Instant>,
+    #[serde(skip)]
+    connection: Option<ConnectionId>,
+}
+
+/// The configured bootstrap peers, which unlike discovered ones are redialed whenever
+/// they can't be reached, with exponential backoff from `initial` up to `max` between
+/// attempts.
+#[derive(Debug, Clone)]
+pub struct BootstrapPeers {
+    initial: Duration,
+    max: Duration,
+    peers: BTreeMap<Multiaddr, BootstrapPeer>,
+}
+
+impl BootstrapPeers {
+    /// Every address is due to be dialed straight away. A trailing `/p2p/<peer id>` is
+    /// kept as the expected peer id rather than as part of the address.
+    pub fn new(
+        addresses: impl IntoIterator<Item = Multiaddr>,
+        initial: Duration,
+        max: Duration,
+        now: Instant,
+    ) -> Self {
+        let peers = addresses
+            .into_iter()
+            .map(|mut address| {
+                let peer_id = match address.iter().last() {
+                    Some(Protocol::P2p(peer_id)) => {
+                        address.pop();
+                        Some(peer_id)
+                    }
+                    _ => None,
+                };
+
+                let peer = BootstrapPeer {
+                    peer_id,
+                    state: BootstrapState::Waiting,
+                    failures: 0,
+                    retry_at: Some(now),
+                    connection: None,
+                };
+                (address, peer)
+            })
+            .collect();
+
+        Self {
+            initial,
+            max,
+            peers,
+        }
+    }
+
+    pub fn get(&self) -> &BTreeMap<Multiaddr, BootstrapPeer> {
+        &self.peers
+    }
+
+    pub fn contains(&self, address: &Multiaddr) -> bool {
+        let mut address = address.clone();
+        if let Some(Protocol::P2p(_)) = address.iter().last() {
+            address.pop();
+        }
+
+        self.peers.contains_key(&address)
+    }
+
+    /// Addresses waiting to be redialed whose backoff has run out, with the peer id to
+    /// expect if we know it.
+    pub fn due(&self, now: Instant) -> Vec<(Multiaddr, Option<PeerId>)> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| {
+                peer.state == BootstrapState::Waiting && peer.retry_at.is_none_or(|at| at <= now)
+            })
+            .map(|(address, peer)| (address.clone(), peer.peer_id))
+            .collect()
+    }
+
+    pub fn dialing(&mut self, address: &Multiaddr, connection: ConnectionId) {
+        if let Some(peer) = self.peers.get_mut(address) {
+            peer.state = BootstrapState::Dialing;
+            peer.retry_at = None;
+            peer.connection = Some(connection);
+        }
+    }
+
+    /// Returns the address if `connection` was a bootstrap dial.
+    pub fn connected(&mut self, connection: ConnectionId, peer_id: PeerId) -> Option<Multiaddr> {
+        let (address, peer) = self
+            .peers
+            .iter_mut()
+            .find(|(_, peer)| peer.connection == Some(connection))?;
+
+        peer.peer_id = Some(peer_id);
+        peer.state = BootstrapState::Connected;
+        peer.failures = 0;
+        Some(address.clone())
+    }
+
+    /// Schedules a redial after a bootstrap dial failed or its connection closed,
+    /// returning the address and how long until the redial.
+    pub fn failed(
+        &mut self,
+        connection: ConnectionId,
+        now: Instant,
+    ) -> Option<(Multiaddr, Duration)> {
+        let (address, peer) = self.peers.iter_mut().find(|(_, peer)| {
+            peer.connection == Some(connection) && peer.state != BootstrapState::Banned
+        })?;
+
+        peer.failures += 1;
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(peer.failures - 1))
+            .min(self.max);
+
+        peer.state = BootstrapState::Waiting;
+        peer.retry_at = Some(now + delay);
+        peer.connection = None;
+        Some((address.clone(), delay))
+    }
+
+    /// Stops redialing `peer_id`, returning its addresses.
+    pub fn ban(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.peers
+            .iter_mut()
+            .filter(|(_, peer)| peer.peer_id.as_ref() == Some(peer_id))
+            .map(|(address, peer)| {
+                peer.state = BootstrapState::Banned;
+                peer.retry_at = None;
+                address.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let start = Instant::now();
+        let address = "/ip4/127.0.0.1/tcp/9".parse::<Multiaddr>().unwrap();
+        let mut peers = BootstrapPeers::new(
+            [address.clone()],
+            Duration::from_secs(1),
+            Duration::from_secs(5),
+            start,
+        );
+
+        let mut now = start;
+        let mut delays = Vec::new();
+        for _ in 0..5 {
+            assert_eq!(peers.due(now), [(address.clone(), None)]);
+            let connection = ConnectionId::new_unchecked(delays.len());
+            peers.dialing(&address, connection);
+            assert!(peers.due(now).is_empty());
+
+            let (_, delay) = peers.failed(connection, now).unwrap();
+            assert!(peers.due(now + delay - Duration::from_millis(1)).is_empty());
+            delays.push(delay.as_secs());
+            now += delay;
+        }
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+
+        // a connection resets the backoff, and losing it starts over
+        let peer_id = PeerId::random();
+        let connection = ConnectionId::new_unchecked(10);
+        peers.dialing(&address, connection);
+        assert_eq!(peers.connected(connection, peer_id), Some(address.clone()));
+        assert_eq!(peers.get()[&address].failures, 0);
+        assert_eq!(
+            peers.failed(connection, now),
+            Some((address.clone(), Duration::from_secs(1)))
+        );
+
+        // only a ban stops the retries
+        assert_eq!(peers.ban(&peer_id), vec![address.clone()]);
+        assert!(peers.due(now + Duration::from_secs(60)).is_empty());
+        assert_eq!(peers.get()[&address].state, BootstrapState::Banned);
+    }
+}
@@ -1,15 +1,33 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, fmt, sync::Arc};
 
 use anyhow::Result;
 use num_bigint::BigUint;
 use num_traits::{One, Zero};
+use serde::Serialize;
 
+#[cfg(feature = "std-node")]
+use crate::{block::BlockValidationError, block_manager::BlockManager, params::NetworkParams};
 use crate::{
     block::{Block, BlockHeader},
-    block_manager::BlockManager,
+    constants::CHAIN_DISPLAY_ROWS,
+    crypto::{Hash, HashExt, serde_hex},
     utxo_set::UTXOSet,
 };
 
+/// The expected number of hashes to find a block, the unit chains are compared in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Work(pub BigUint);
+
+impl Work {
+    /// `2^256 / (target + 1)`, computed as `(max - target) / (target + 1) + 1` so it stays
+    /// within 256 bits. A zero target, which only the zero hash meets, is `2^256`.
+    pub fn from_target(target: &Hash) -> Self {
+        let target = target.to_biguint();
+        let max_target = (BigUint::one() << 256) - BigUint::one();
+        Self(((&max_target - &target) / (&target + BigUint::one())) + BigUint::one())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BlockchainNode {
     pub height: u32,
@@ -29,11 +47,7 @@ impl BlockchainNode {
     }
 
     fn calculate_work(&self) -> Result<BigUint> {
-        let target_bytes = self.header.difficulty_target()?;
-        let target = BigUint::from_bytes_be(&target_bytes);
-
-        let max_target = (BigUint::from(2u32).pow(256u32)) - BigUint::one();
-        let block_work = ((&max_target - &target) / (&target + BigUint::one())) + BigUint::one();
+        let Work(block_work) = Work::from_target(&self.header.difficulty_target()?);
 
         let previous_work = self
             .previous
@@ -57,11 +71,88 @@ impl BlockchainNode {
     }
 }
 
+/// The height, the first 16 hex digits of the hash, and the chain work up to the node.
+impl fmt::Display for BlockchainNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hash = self.header.hash().map_err(|_| fmt::Error)?;
+        write!(
+            f,
+            "{:>6} 0x{}… work {}",
+            self.height,
+            &hash.display().to_string()[..16],
+            self.work
+        )
+    }
+}
+
+/// Nodes that left (tip first) and joined (ascending height) the active chain.
+#[derive(Debug, Clone, Default)]
+pub struct ChainUpdate {
+    pub disconnected: Vec<Arc<BlockchainNode>>,
+    pub connected: Vec<Arc<BlockchainNode>>,
+}
+
+/// A summary of the active chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainStats {
+    pub tip_hash: Hash,
+    pub height: u32,
+    pub total_work: BigUint,
+    pub block_count: usize,
+    /// Mean seconds between blocks, leaving out the genesis block, whose timestamp is
+    /// fixed by the network. `None` until there are two blocks after it.
+    pub average_block_time: Option<f64>,
+}
+
+/// The first block `Blockchain::validate_full` found invalid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationFailure {
+    pub height: u32,
+    #[serde(with = "serde_hex")]
+    pub hash: Hash,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChainValidation {
+    /// Blocks validated, leaving out those below the starting height.
+    pub validated: u32,
+    pub transactions: usize,
+    pub failure: Option<ValidationFailure>,
+    /// The UTXO set the blocks built, only complete when there's no failure.
+    pub utxo_set: UTXOSet,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Blockchain {
     pub nodes: BTreeMap<u32, Arc<BlockchainNode>>,
 }
 
+/// A node per line from the tip down, `CHAIN_DISPLAY_ROWS` of them unless the precision
+/// (`{:.3}`) says otherwise, with a last line counting any left out.
+impl fmt::Display for Blockchain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.nodes.is_empty() {
+            return f.write_str("Empty chain");
+        }
+
+        let rows = f.precision().unwrap_or(CHAIN_DISPLAY_ROWS);
+        let mut lines = self
+            .nodes
+            .values()
+            .rev()
+            .take(rows)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        let hidden = self.nodes.len().saturating_sub(rows);
+        if hidden > 0 {
+            lines.push(format!("     … {hidden} more"));
+        }
+        f.write_str(&lines.join("\n"))
+    }
+}
+
 impl Blockchain {
     pub fn is_empty(&self) -> bool {
         self.tail().is_none()
@@ -98,12 +189,18 @@ impl Blockchain {
             .is_some_and(|node| node.header.hash().ok() == block.header.hash().ok())
     }
 
-    pub fn set_tail(&mut self, node: Arc<BlockchainNode>) -> Result<()> {
-        self.nodes.retain(|_, n| n.height < node.height);
-
-        let mut current_node = node.previous.clone();
+    /// Makes `node` the tip of the chain, returning the nodes that left and joined the
+    /// active chain as a result.
+    pub fn set_tail(&mut self, node: Arc<BlockchainNode>) -> Result<ChainUpdate> {
+        let mut disconnected: Vec<_> = self
+            .nodes
+            .split_off(&(node.height + 1))
+            .into_values()
+            .rev()
+            .collect();
 
-        self.nodes.insert(node.height, node);
+        let mut connected = Vec::new();
+        let mut current_node = Some(node);
 
         while let Some(node) = current_node {
             if self.contains_node(&node) {
@@ -111,20 +208,151 @@ impl Blockchain {
             }
 
             current_node = node.previous.clone();
-            self.nodes.insert(node.height, node);
+
+            if let Some(replaced) = self.nodes.insert(node.height, node.clone()) {
+                disconnected.push(replaced);
+            }
+            connected.push(node);
         }
 
-        Ok(())
+        connected.reverse();
+
+        Ok(ChainUpdate {
+            disconnected,
+            connected,
+        })
+    }
+
+    /// Hashes identifying the active chain to a peer: the most recent blocks one by one,
+    /// then exponentially sparser back to the first block.
+    pub fn locator(&self) -> Result<Vec<Hash>> {
+        let mut locator = Vec::new();
+
+        let Some(first_height) = self.nodes.first_key_value().map(|(height, _)| *height) else {
+            return Ok(locator);
+        };
+
+        let mut height = self.height();
+        let mut step = 1;
+
+        loop {
+            if let Some(node) = self.nodes.get(&height) {
+                locator.push(node.header.hash()?);
+            }
+
+            if height == first_height {
+                break;
+            }
+
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+
+            height = height.saturating_sub(step).max(first_height);
+        }
+
+        Ok(locator)
     }
 
+    pub fn stats(&self) -> Result<Option<ChainStats>> {
+        let Some(tail) = self.tail() else {
+            return Ok(None);
+        };
+
+        let mut after_genesis = self.nodes.values().skip(1);
+        let average_block_time = match (after_genesis.next(), self.nodes.len()) {
+            (Some(first), count) if count >= 3 => Some(
+                (tail.header.timestamp as f64 - first.header.timestamp as f64) / (count - 2) as f64,
+            ),
+            _ => None,
+        };
+
+        Ok(Some(ChainStats {
+            tip_hash: tail.header.hash()?,
+            height: tail.height,
+            total_work: tail.work.clone(),
+            block_count: self.nodes.len(),
+            average_block_time,
+        }))
+    }
+
+    /// Validates every block from `from_height` as if it were new: its proof of work,
+    /// merkle root, coinbase, and signatures, that it links to the block before (or is the
+    /// network's genesis block), and its spends against the UTXO set built so far. Blocks
+    /// below `from_height` are applied unchecked. Stops at the first invalid block, calling
+    /// `progress` with each height validated.
+    #[cfg(feature = "std-node")]
+    pub fn validate_full(
+        &self,
+        block_manager: &BlockManager,
+        params: &NetworkParams,
+        from_height: u32,
+        mut progress: impl FnMut(u32),
+    ) -> Result<ChainValidation> {
+        let mut validation = ChainValidation::default();
+        let mut previous_hash = Hash::default();
+
+        for (&height, node) in &self.nodes {
+            let hash = node.header.hash()?;
+            let Some(block) = block_manager.get_block(&hash) else {
+                validation.failure = Some(ValidationFailure {
+                    height,
+                    hash,
+                    reason: "Block is not stored".to_string(),
+                });
+                break;
+            };
+
+            if height < from_height {
+                validation.utxo_set.apply_block(block)?;
+                previous_hash = hash;
+                continue;
+            }
+
+            let result = if block.header.previous_block_hash != previous_hash {
+                Err(anyhow::anyhow!(
+                    "Block builds on 0x{}, not the block before it",
+                    block.header.previous_block_hash.display()
+                ))
+            } else if previous_hash == Hash::default() && hash != params.genesis_hash()? {
+                Err(BlockValidationError::NotGenesis.into())
+            } else if block.height != height {
+                Err(BlockValidationError::WrongHeight {
+                    claimed: block.height,
+                    expected: height,
+                }
+                .into())
+            } else {
+                block
+                    .validate(params)
+                    .and_then(|_| block.connect(&mut validation.utxo_set, params).map(|_| ()))
+            };
+
+            if let Err(error) = result {
+                validation.failure = Some(ValidationFailure {
+                    height,
+                    hash,
+                    reason: error.to_string(),
+                });
+                break;
+            }
+
+            validation.validated += 1;
+            validation.transactions += block.transactions.len();
+            previous_hash = hash;
+            progress(height);
+        }
+
+        Ok(validation)
+    }
+
+    #[cfg(feature = "std-node")]
     pub fn build_utxo_set(&self, block_manager: &BlockManager) -> Result<UTXOSet> {
         let mut utxo_set = UTXOSet::default();
 
         for node in self.nodes.values() {
             if let Some(block) = block_manager.get_block(&node.header.hash()?) {
-                for tx in &block.transactions {
-                    utxo_set.update(tx)?;
-                }
+                utxo_set.apply_block(block)?;
             }
         }
 
@@ -135,7 +363,6 @@ impl Blockchain {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::*;
     use crate::transaction::*;
 
     fn test_block(height: u32, previous: Option<&Block>, transactions: Vec<Transaction>) -> Block {
@@ -152,7 +379,8 @@ mod tests {
         Block {
             header,
             height,
-            transactions,
+            transactions: transactions.into_iter().map(Arc::new).collect(),
+            signature: None,
         }
     }
 
@@ -176,6 +404,77 @@ mod tests {
         Ok(Blockchain { nodes })
     }
 
+    #[test]
+    fn test_locator() {
+        let mut blocks = vec![test_block(1, None, vec![])];
+        for height in 2..=30 {
+            let block = test_block(height, blocks.last(), vec![]);
+            blocks.push(block);
+        }
+
+        let chain = build_from_blocks(blocks.clone()).unwrap();
+        let locator = chain.locator().unwrap();
+
+        let heights = locator
+            .iter()
+            .map(|hash| {
+                blocks
+                    .iter()
+                    .find(|block| block.header.hash().unwrap() == *hash)
+                    .unwrap()
+                    .height
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            heights,
+            vec![30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 19, 15, 7, 1]
+        );
+        assert!(Blockchain::default().locator().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(Blockchain::default().stats().unwrap(), None);
+
+        let mut blocks = vec![test_block(1, None, vec![])];
+        for (height, timestamp) in [(2, 1000), (3, 1030), (4, 1090)] {
+            let mut block = test_block(height, blocks.last(), vec![]);
+            block.header.timestamp = timestamp;
+            blocks.push(block);
+        }
+        let chain = build_from_blocks(blocks).unwrap();
+
+        let stats = chain.stats().unwrap().unwrap();
+        assert_eq!(stats.height, 4);
+        assert_eq!(stats.block_count, 4);
+        assert_eq!(stats.average_block_time, Some(45.0));
+        assert_eq!(Some(stats.total_work), chain.chain_work());
+    }
+
+    #[test]
+    fn test_work() {
+        // the easiest possible target takes one hash, the impossible-but-for-zero one 2^256
+        assert_eq!(Work::from_target(&[u8::MAX; 32]), Work(BigUint::one()));
+        assert_eq!(
+            Work::from_target(&Hash::zero()),
+            Work(BigUint::one() << 256)
+        );
+
+        let mut target = Hash::zero();
+        target[1] = 0xff;
+        let expected = (BigUint::one() << 256u32) / (target.to_biguint() + 1u32);
+        assert_eq!(Work::from_target(&target), Work(expected));
+
+        // one more leading zero byte is 256 times the work
+        let mut harder = Hash::zero();
+        harder[2] = 0xff;
+        assert_eq!(
+            Work::from_target(&harder).0 / Work::from_target(&target).0,
+            BigUint::from(256u32)
+        );
+    }
+
     #[test]
     fn test_build_blockchain() {
         let block_a = test_block(1, None, vec![]);
@@ -206,7 +505,12 @@ mod tests {
         assert!(chain_b.chain_work().unwrap() > chain_a.chain_work().unwrap());
 
         let tail_node_a = chain_a.tail().unwrap();
-        chain_b.set_tail(tail_node_a).unwrap();
+        let update = chain_b.set_tail(tail_node_a).unwrap();
+
+        assert_eq!(update.disconnected.len(), 2);
+        assert_eq!(update.disconnected[0].height, 5);
+        assert_eq!(update.disconnected[1].height, 4);
+        assert!(update.connected.is_empty());
 
         assert_eq!(chain_b.height(), 3);
         assert_eq!(chain_b.nodes.len(), 3);
@@ -215,4 +519,89 @@ mod tests {
         assert!(chain_b.contains_block(&block_c));
         assert_eq!(chain_b.chain_work().unwrap(), chain_a.chain_work().unwrap());
     }
+
+    #[test]
+    fn test_validate_full() {
+        let params = NetworkParams::regtest();
+        let mut state = crate::node::NodeState {
+            params: params.clone(),
+            ..Default::default()
+        };
+        state.add_block(params.genesis_block().unwrap()).unwrap();
+        let key_pair = crate::crypto::KeyPair::generate();
+        for _ in 0..3 {
+            let tail = state.chain.tail().unwrap().header.hash().unwrap();
+            let previous = state.block_manager.get_block(&tail).unwrap();
+            let mut block = Block::new(&key_pair, previous, vec![], 0, &params).unwrap();
+            block.mine().unwrap();
+            state.add_block(block).unwrap();
+        }
+
+        let mut heights = Vec::new();
+        let validation = state
+            .chain
+            .validate_full(&state.block_manager, &params, 2, |height| {
+                heights.push(height)
+            })
+            .unwrap();
+        assert_eq!(validation.failure, None);
+        assert_eq!(heights, [2, 3, 4]);
+        assert_eq!(validation.transactions, 3);
+        assert_eq!(
+            Some(validation.utxo_set.total_value()),
+            params.expected_supply(4)
+        );
+
+        // a block whose transactions no longer match its header
+        let hash = state.chain.get_node(3).unwrap().header.hash().unwrap();
+        let mut tampered = (*state.block_manager.blocks[&hash]).clone();
+        Arc::make_mut(&mut tampered.transactions[0]).body.outputs[0].value += 1;
+        state.block_manager.blocks.insert(hash, Arc::new(tampered));
+
+        let validation = state
+            .chain
+            .validate_full(&state.block_manager, &params, 1, |_| {})
+            .unwrap();
+        assert_eq!(validation.validated, 2);
+        let failure = validation.failure.unwrap();
+        assert_eq!((failure.height, failure.hash), (3, hash));
+    }
+
+    #[test]
+    fn test_display() {
+        let blocks =
+            crate::fixtures::chain(3, &crate::fixtures::key_pair(1), &NetworkParams::regtest())
+                .unwrap();
+        let mut chain = Blockchain::default();
+        for block in &blocks {
+            let mut node = BlockchainNode::new(block);
+            node.set_previous(chain.tail()).unwrap();
+            chain.set_tail(Arc::new(node)).unwrap();
+        }
+
+        assert_eq!(
+            chain.tail().unwrap().to_string(),
+            "     3 0x5019e09df8bcfe97… work 3"
+        );
+        assert_eq!(
+            chain.to_string(),
+            [
+                "     3 0x5019e09df8bcfe97… work 3",
+                "     2 0x846d029b27575bfa… work 2",
+                "     1 0xeb785ba15ec4079f… work 1",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            format!("{chain:.2}"),
+            [
+                "     3 0x5019e09df8bcfe97… work 3",
+                "     2 0x846d029b27575bfa… work 2",
+                "     … 1 more",
+            ]
+            .join("\n")
+        );
+        assert_eq!(format!("{chain:.0}"), "     … 3 more");
+        assert_eq!(Blockchain::default().to_string(), "Empty chain");
+    }
 }
@@ -0,0 +1,627 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{
+        DEFAULT_BOOTSTRAP_RETRY_MAX_SECS, DEFAULT_BOOTSTRAP_RETRY_SECS, DEFAULT_DATA_DIR,
+        DEFAULT_DUST_LIMIT, DEFAULT_IBD_BATCH_SIZE, DEFAULT_IBD_LAG_THRESHOLD,
+        DEFAULT_INVENTORY_INTERVAL_SECS, DEFAULT_MAX_PEER_CONNECTIONS,
+        DEFAULT_MEMPOOL_MAX_TRANSACTIONS, DEFAULT_MIN_TRANSACTION_FEE, DEFAULT_P2P_LISTEN_ADDR,
+    },
+    crypto::{Hash, KeyPair, serde_hex},
+    params::{Network, NetworkParams},
+    rate_limit::{RateLimit, RateLimits},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemPoolConfig {
+    pub max_transactions: usize,
+    /// Smallest output a transaction may pay to be accepted into the pool, and so the
+    /// smallest change `Node::send` will create.
+    pub dust_limit: u64,
+    /// Smallest fee a transaction must pay to be accepted into the pool.
+    pub min_fee: u64,
+}
+
+impl Default for MemPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_transactions: DEFAULT_MEMPOOL_MAX_TRANSACTIONS,
+            dust_limit: DEFAULT_DUST_LIMIT,
+            min_fee: DEFAULT_MIN_TRANSACTION_FEE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MiningConfig {
+    pub enabled: bool,
+    pub threads: usize,
+}
+
+impl Default for MiningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threads: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct P2pConfig {
+    pub listen_addr: String,
+    pub bootstrap_peers: Vec<String>,
+    /// Discover and dial peers on the local network. Unset means on for regtest only.
+    pub enable_mdns: Option<bool>,
+    /// Discovered peers are only dialed while we have fewer connections than this.
+    pub max_connections: usize,
+    /// How often mempool transactions are announced to peers that don't know them yet.
+    pub inventory_interval_secs: u64,
+    pub rate_limits: RateLimits,
+    /// Delay before redialing an unreachable bootstrap peer, doubling after each failure
+    /// up to `bootstrap_retry_max_secs`.
+    pub bootstrap_retry_secs: u64,
+    pub bootstrap_retry_max_secs: u64,
+    /// Reach and be reached by peers through circuit relays, upgrading to a direct
+    /// connection by hole punching where possible. Unset means on when `relay_servers`
+    /// is set, with relay addresses only reserved once AutoNAT finds we're not publicly
+    /// reachable; `true` reserves them straight away.
+    pub enable_relay_client: Option<bool>,
+    /// Relays to reserve addresses on, as multiaddrs ending in `/p2p/<peer id>`.
+    pub relay_servers: Vec<String>,
+}
+
+impl P2pConfig {
+    pub fn mdns_enabled(&self, network: Network) -> bool {
+        self.enable_mdns.unwrap_or(network == Network::Regtest)
+    }
+
+    pub fn relay_enabled(&self) -> bool {
+        self.enable_relay_client
+            .unwrap_or(!self.relay_servers.is_empty())
+    }
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: DEFAULT_P2P_LISTEN_ADDR.to_string(),
+            bootstrap_peers: Vec::new(),
+            enable_mdns: None,
+            max_connections: DEFAULT_MAX_PEER_CONNECTIONS,
+            inventory_interval_secs: DEFAULT_INVENTORY_INTERVAL_SECS,
+            rate_limits: RateLimits::default(),
+            bootstrap_retry_secs: DEFAULT_BOOTSTRAP_RETRY_SECS,
+            bootstrap_retry_max_secs: DEFAULT_BOOTSTRAP_RETRY_MAX_SECS,
+            enable_relay_client: None,
+            relay_servers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    /// Address the JSON-RPC server listens on, which is off when unset.
+    pub listen_addr: Option<String>,
+    /// Bearer token every request must carry, when set.
+    pub auth_token: Option<String>,
+}
+
+/// Initial block download: how far behind the best known header counts as syncing, and
+/// how blocks are connected meanwhile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    pub lag_threshold: u32,
+    pub batch_size: usize,
+    /// Signatures in this block and its ancestors aren't verified during initial block
+    /// download.
+    #[serde(default, with = "serde_hex::option")]
+    pub assume_valid: Option<Hash>,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            lag_threshold: DEFAULT_IBD_LAG_THRESHOLD,
+            batch_size: DEFAULT_IBD_BATCH_SIZE,
+            assume_valid: None,
+        }
+    }
+}
+
+/// Serializes with only the keypair's public key, so a saved config never holds the
+/// secret. A deserialized config gets a fresh keypair, to be replaced with the one from
+/// the keystore.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    #[serde(
+        rename = "public_key",
+        serialize_with = "serialize_public_only",
+        deserialize_with = "deserialize_public_only",
+        default = "KeyPair::generate"
+    )]
+    pub keypair: KeyPair,
+    pub data_dir: PathBuf,
+    /// Where the encrypted keypair is kept, `keypair` in the data dir when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+    /// Refuse to start with more block files than this skipped as unreadable or corrupt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_skipped_block_files: Option<usize>,
+    /// Keep the history of every address paid or spent from on the active chain, served
+    /// at `/addresses/{address}/history`.
+    pub index_address_history: bool,
+    pub mempool: MemPoolConfig,
+    pub mining: MiningConfig,
+    /// A network's name, or its params in full.
+    #[serde(deserialize_with = "deserialize_network")]
+    pub network: NetworkParams,
+    pub p2p: P2pConfig,
+    pub rpc: RpcConfig,
+    pub sync: SyncConfig,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self::builder(KeyPair::generate()).build()
+    }
+}
+
+impl NodeConfig {
+    pub fn builder(keypair: KeyPair) -> NodeConfigBuilder {
+        NodeConfigBuilder {
+            config: NodeConfig {
+                keypair,
+                data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+                key_file: None,
+                max_skipped_block_files: None,
+                index_address_history: false,
+                mempool: MemPoolConfig::default(),
+                mining: MiningConfig::default(),
+                network: NetworkParams::default(),
+                p2p: P2pConfig::default(),
+                rpc: RpcConfig::default(),
+                sync: SyncConfig::default(),
+            },
+        }
+    }
+
+    /// Reads a TOML config. Anything it leaves out keeps its default, except that the
+    /// network params, when given as a table rather than a name, must be given in full.
+    /// Unknown keys are refused, so a misspelled one isn't silently ignored.
+    pub fn load(path: &Path) -> Result<Self> {
+        let toml = fs::read_to_string(path)
+            .map_err(|error| anyhow::anyhow!("Can't read config {}: {error}", path.display()))?;
+        toml::from_str(&toml)
+            .map_err(|error| anyhow::anyhow!("Invalid config {}: {error}", path.display()))
+    }
+
+    /// A config file setting every key to its default, with comments saying what each
+    /// one does.
+    pub fn template() -> String {
+        let defaults = Self::default();
+        let (mempool, mining, p2p, sync) = (
+            &defaults.mempool,
+            &defaults.mining,
+            &defaults.p2p,
+            &defaults.sync,
+        );
+        let limits = &p2p.rate_limits;
+        let limit = |limit: RateLimit| {
+            format!("{{ per_sec = {}, burst = {} }}", limit.per_sec, limit.burst)
+        };
+
+        format!(
+            r#"# tiny-crypto node config, read by `node run --config`. Every key is optional and
+# shown with its default; flags given to `node run` override what's set here.
+
+# Directory holding the chain, mempool, and peer data
+data_dir = "{data_dir}"
+
+# Where the keypair is kept encrypted under the node's passphrase, `keypair` in the
+# data dir by default
+# key_file = "node.key"
+
+# Refuse to start rather than skip more than this many unreadable or corrupt block
+# files, which are otherwise skipped whatever their number
+# max_skipped_block_files = 0
+
+# Index every address's payments and spends for /addresses/{{address}}/history, built
+# from the stored chain when first turned on and saved in the data dir at shutdown
+index_address_history = {index_address_history}
+
+# mainnet, testnet, or regtest. A private network instead has a [network] table of its
+# params in full, as `genesis init` writes
+network = "{network}"
+
+[mempool]
+max_transactions = {max_transactions}
+# Smallest output a transaction may pay to be accepted into the pool, and so the
+# smallest change a send will create
+dust_limit = {dust_limit}
+# Smallest fee a transaction must pay to be accepted into the pool
+min_fee = {min_fee}
+
+[mining]
+# Mine blocks on the tip, paying the node's keypair
+enabled = {mining_enabled}
+threads = {mining_threads}
+
+[p2p]
+listen_addr = "{listen_addr}"
+# Multiaddrs of peers to connect to and keep redialing
+bootstrap_peers = []
+# Discover and dial peers on the local network, on for regtest only by default
+# enable_mdns = false
+# Discovered peers are only dialed below this many connections
+max_connections = {max_connections}
+# Seconds between announcing mempool transactions to peers
+inventory_interval_secs = {inventory_interval_secs}
+# Delay before redialing an unreachable bootstrap peer, doubling after each failure
+bootstrap_retry_secs = {bootstrap_retry_secs}
+bootstrap_retry_max_secs = {bootstrap_retry_max_secs}
+# Reach and be reached through circuit relays, on by default when relays are given
+# enable_relay_client = true
+# Relays to reserve addresses on, as multiaddrs ending in /p2p/<peer id>
+relay_servers = []
+
+# Inbound messages each peer may send, all of which must be given if any are. A peer
+# dropping more than max_dropped messages a minute is penalized
+[p2p.rate_limits]
+tip_blocks = {tip_blocks}
+blocks = {blocks}
+transactions = {transactions}
+requests = {requests}
+max_dropped = {max_dropped}
+
+[rpc]
+# Address to serve JSON-RPC, REST, and metrics on, off by default
+# listen_addr = "127.0.0.1:8332"
+# Bearer token every request must carry
+# auth_token = "secret"
+
+[sync]
+# Blocks behind the best known header that count as initial block download
+lag_threshold = {lag_threshold}
+# Blocks connected at a time during initial block download
+batch_size = {batch_size}
+# Block hash whose ancestors' signatures aren't verified during initial block download
+# assume_valid = "0000..."
+"#,
+            data_dir = defaults.data_dir.display(),
+            index_address_history = defaults.index_address_history,
+            network = defaults.network.network,
+            max_transactions = mempool.max_transactions,
+            dust_limit = mempool.dust_limit,
+            min_fee = mempool.min_fee,
+            mining_enabled = mining.enabled,
+            mining_threads = mining.threads,
+            listen_addr = p2p.listen_addr,
+            max_connections = p2p.max_connections,
+            inventory_interval_secs = p2p.inventory_interval_secs,
+            bootstrap_retry_secs = p2p.bootstrap_retry_secs,
+            bootstrap_retry_max_secs = p2p.bootstrap_retry_max_secs,
+            tip_blocks = limit(limits.tip_blocks),
+            blocks = limit(limits.blocks),
+            transactions = limit(limits.transactions),
+            requests = limit(limits.requests),
+            max_dropped = limits.max_dropped,
+            lag_threshold = sync.lag_threshold,
+            batch_size = sync.batch_size,
+        )
+    }
+
+    pub fn blocks_dir(&self) -> PathBuf {
+        self.data_dir.join("blocks")
+    }
+
+    /// The node's keypair, encrypted under a passphrase with `KeyPair::save_encrypted`.
+    pub fn keypair_path(&self) -> PathBuf {
+        self.key_file
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("keypair"))
+    }
+
+    /// The libp2p identity key, generated on first run, that the node's PeerId derives from.
+    pub fn network_key_path(&self) -> PathBuf {
+        self.data_dir.join("network_key")
+    }
+
+    pub fn peer_book_path(&self) -> PathBuf {
+        self.data_dir.join("peers.json")
+    }
+
+    /// Where the mempool is saved at shutdown and reloaded from at startup.
+    pub fn mem_pool_path(&self) -> PathBuf {
+        self.data_dir.join("mempool.json")
+    }
+
+    /// Where the address history index is saved at shutdown and reloaded from at startup.
+    pub fn address_history_path(&self) -> PathBuf {
+        self.data_dir.join("address_history.json")
+    }
+
+    pub fn mdns_enabled(&self) -> bool {
+        self.p2p.mdns_enabled(self.network.network)
+    }
+}
+
+fn serialize_public_only<S: serde::Serializer>(keypair: &KeyPair, s: S) -> Result<S::Ok, S::Error> {
+    keypair.public_only().serialize(s)
+}
+
+/// Accepts a saved config's public key, but leaves the keypair to be loaded separately.
+fn deserialize_public_only<'de, D: serde::Deserializer<'de>>(d: D) -> Result<KeyPair, D::Error> {
+    serde::de::IgnoredAny::deserialize(d)?;
+    Ok(KeyPair::generate())
+}
+
+/// A network's name selects its preset params, and a table gives custom params.
+fn deserialize_network<'de, D: serde::Deserializer<'de>>(d: D) -> Result<NetworkParams, D::Error> {
+    struct NetworkVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for NetworkVisitor {
+        type Value = NetworkParams;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("mainnet, testnet, regtest, or a table of network params")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<NetworkParams, E> {
+            let network = name
+                .parse::<Network>()
+                .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(name), &self))?;
+            Ok(NetworkParams::for_network(network))
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(
+            self,
+            map: A,
+        ) -> Result<NetworkParams, A::Error> {
+            let params =
+                NetworkParams::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+            params.check().map_err(serde::de::Error::custom)?;
+            Ok(params)
+        }
+    }
+
+    d.deserialize_any(NetworkVisitor)
+}
+
+pub struct NodeConfigBuilder {
+    config: NodeConfig,
+}
+
+impl NodeConfigBuilder {
+    pub fn data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.config.data_dir = data_dir.into();
+        self
+    }
+
+    pub fn key_file(mut self, key_file: impl Into<PathBuf>) -> Self {
+        self.config.key_file = Some(key_file.into());
+        self
+    }
+
+    pub fn max_skipped_block_files(mut self, max: usize) -> Self {
+        self.config.max_skipped_block_files = Some(max);
+        self
+    }
+
+    pub fn index_address_history(mut self, enabled: bool) -> Self {
+        self.config.index_address_history = enabled;
+        self
+    }
+
+    pub fn mempool(mut self, mempool: MemPoolConfig) -> Self {
+        self.config.mempool = mempool;
+        self
+    }
+
+    pub fn mining(mut self, mining: MiningConfig) -> Self {
+        self.config.mining = mining;
+        self
+    }
+
+    pub fn network(mut self, network: NetworkParams) -> Self {
+        self.config.network = network;
+        self
+    }
+
+    pub fn p2p(mut self, p2p: P2pConfig) -> Self {
+        self.config.p2p = p2p;
+        self
+    }
+
+    pub fn rpc(mut self, rpc: RpcConfig) -> Self {
+        self.config.rpc = rpc;
+        self
+    }
+
+    pub fn sync(mut self, sync: SyncConfig) -> Self {
+        self.config.sync = sync;
+        self
+    }
+
+    pub fn build(self) -> NodeConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::*;
+
+    #[test]
+    fn test_config_serde_round_trip() {
+        let config = NodeConfig::builder(KeyPair::generate())
+            .data_dir("/tmp/tiny-crypto")
+            .mempool(MemPoolConfig {
+                max_transactions: 10,
+                dust_limit: 2,
+                min_fee: 3,
+            })
+            .mining(MiningConfig {
+                enabled: true,
+                threads: 4,
+            })
+            .p2p(P2pConfig {
+                listen_addr: "/ip4/127.0.0.1/tcp/9000".to_string(),
+                bootstrap_peers: vec!["/ip4/10.0.0.1/tcp/9000".to_string()],
+                enable_mdns: Some(false),
+                max_connections: 4,
+                inventory_interval_secs: 5,
+                rate_limits: RateLimits {
+                    max_dropped: 10,
+                    ..RateLimits::default()
+                },
+                bootstrap_retry_secs: 2,
+                bootstrap_retry_max_secs: 60,
+                enable_relay_client: None,
+                relay_servers: vec![
+                    "/ip4/10.0.0.2/tcp/9000/p2p/12D3KooWD3eckifWpRn9wQpMG9R9hX3sD158z7EqHWmweQAJU5SA"
+                        .to_string(),
+                ],
+            })
+            .sync(SyncConfig {
+                assume_valid: Some([7; 32]),
+                ..SyncConfig::default()
+            })
+            .build();
+
+        let json = serde_json::to_string(&config.mempool).unwrap();
+        assert_eq!(config.mempool, serde_json::from_str(&json).unwrap());
+
+        let json = serde_json::to_string(&config.mining).unwrap();
+        assert_eq!(config.mining, serde_json::from_str(&json).unwrap());
+
+        let json = serde_json::to_string(&config.network).unwrap();
+        assert_eq!(config.network, serde_json::from_str(&json).unwrap());
+
+        let json = serde_json::to_string(&config.p2p).unwrap();
+        assert_eq!(config.p2p, serde_json::from_str(&json).unwrap());
+
+        let json = serde_json::to_string(&config.sync).unwrap();
+        assert!(json.contains(&[7; 32].display().to_string()));
+        assert_eq!(config.sync, serde_json::from_str(&json).unwrap());
+
+        let json = serde_json::to_string(&SyncConfig::default()).unwrap();
+        assert_eq!(SyncConfig::default(), serde_json::from_str(&json).unwrap());
+
+        let json = serde_json::to_string(&config.data_dir).unwrap();
+        assert_eq!(
+            config.data_dir,
+            serde_json::from_str::<PathBuf>(&json).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_load_partial_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.toml");
+        fs::write(
+            &path,
+            "data_dir = \"/tmp/tiny-crypto\"\n\n[p2p]\nenable_mdns = false\n\n[rpc]\nlisten_addr = \"127.0.0.1:8332\"\n",
+        )
+        .unwrap();
+
+        let config = NodeConfig::load(&path).unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/tmp/tiny-crypto"));
+        assert_eq!(config.p2p.enable_mdns, Some(false));
+        assert_eq!(config.p2p.listen_addr, DEFAULT_P2P_LISTEN_ADDR);
+        assert_eq!(config.rpc.listen_addr.as_deref(), Some("127.0.0.1:8332"));
+        assert_eq!(config.mempool, MemPoolConfig::default());
+        assert_eq!(config.network, NetworkParams::mainnet());
+
+        fs::write(&path, "[p2p]\nmax_connections = \"many\"\n").unwrap();
+        assert!(NodeConfig::load(&path).is_err());
+
+        fs::write(&path, "network = \"regtest\"\n").unwrap();
+        assert_eq!(
+            NodeConfig::load(&path).unwrap().network,
+            NetworkParams::regtest()
+        );
+
+        let custom = NetworkParams {
+            genesis_block_reward: 7,
+            ..NetworkParams::regtest()
+        };
+        fs::write(
+            &path,
+            toml::to_string(
+                &NodeConfig::builder(KeyPair::generate())
+                    .network(custom.clone())
+                    .build(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(NodeConfig::load(&path).unwrap().network, custom);
+    }
+
+    #[test]
+    fn test_unknown_keys_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.toml");
+
+        for (toml, key) in [
+            ("datadir = \"/tmp\"\n", "datadir"),
+            ("[p2p]\nmax_conections = 3\n", "max_conections"),
+            ("[mining]\nenable = true\n", "enable"),
+            ("network = \"regnet\"\n", "regnet"),
+        ] {
+            fs::write(&path, toml).unwrap();
+            let error = NodeConfig::load(&path).err().unwrap().to_string();
+            assert!(error.contains(key), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_template_is_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.toml");
+        fs::write(&path, NodeConfig::template()).unwrap();
+
+        let config = NodeConfig::load(&path).unwrap();
+        let defaults = NodeConfig::default();
+        assert_eq!(config.data_dir, defaults.data_dir);
+        assert_eq!(config.key_file, None);
+        assert_eq!(config.index_address_history, defaults.index_address_history);
+        assert_eq!(config.mempool, defaults.mempool);
+        assert_eq!(config.mining, defaults.mining);
+        assert_eq!(config.network, defaults.network);
+        assert_eq!(config.p2p, defaults.p2p);
+        assert_eq!(config.rpc, defaults.rpc);
+        assert_eq!(config.sync, defaults.sync);
+    }
+
+    #[test]
+    fn test_config_never_contains_secret() {
+        let keypair = KeyPair::generate();
+        let config = NodeConfig::builder(keypair.clone())
+            .data_dir("/tmp/tiny-crypto")
+            .build();
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains(&keypair.secret_key.display_secret().to_string()));
+        assert!(json.contains(&hex::encode(keypair.public_key.serialize())));
+
+        let loaded: NodeConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.data_dir, config.data_dir);
+        assert_eq!(loaded.p2p, config.p2p);
+        assert_ne!(loaded.keypair.public_key, keypair.public_key);
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::Result;
+
+use crate::{
+    crypto::Hash,
+    node::NodeState,
+    transaction::{TransactionOutputReference, TxId},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InconsistencyFinding {
+    /// A node on the active chain whose block isn't held by the block manager.
+    MissingBlock { height: u32, hash: Hash },
+    /// The active chain skips from `previous` to `height`, or a node is stored under the
+    /// wrong height.
+    HeightGap { previous: u32, height: u32 },
+    /// The UTXO set doesn't match one rebuilt from the active chain.
+    UtxoSetMismatch { expected: Hash, actual: Hash },
+    /// A mempool transaction spends an output that is neither unspent on the active chain
+    /// nor created by an earlier pending transaction.
+    MempoolSpendsMissingOutput {
+        id: TxId,
+        outpoint: TransactionOutputReference,
+    },
+    /// A block on the active chain is also held as an orphan.
+    OrphanInChain { hash: Hash },
+    /// The check itself failed before it could finish.
+    CheckFailed(String),
+}
+
+impl NodeState {
+    /// Verifies the invariants tying the chain, block manager, UTXO set, and mempool
+    /// together, returning every violation found.
+    pub fn check_consistency(&self) -> Result<(), Vec<InconsistencyFinding>> {
+        let findings = self
+            .find_inconsistencies()
+            .unwrap_or_else(|e| vec![InconsistencyFinding::CheckFailed(e.to_string())]);
+
+        if findings.is_empty() {
+            Ok(())
+        } else {
+            Err(findings)
+        }
+    }
+
+    fn find_inconsistencies(&self) -> Result<Vec<InconsistencyFinding>> {
+        let mut findings = Vec::new();
+        let mut previous_height = None;
+
+        for (&height, node) in &self.chain.nodes {
+            let hash = node.header.hash()?;
+
+            if !self.block_manager.contains_block(&hash) {
+                findings.push(InconsistencyFinding::MissingBlock { height, hash });
+            }
+
+            if self.block_manager.orphan_blocks.contains_key(&hash) {
+                findings.push(InconsistencyFinding::OrphanInChain { hash });
+            }
+
+            if let Some(previous) = previous_height
+                && (height != previous + 1 || node.height != height)
+            {
+                findings.push(InconsistencyFinding::HeightGap { previous, height });
+            }
+
+            previous_height = Some(height);
+        }
+
+        let expected = self
+            .chain
+            .build_utxo_set(&self.block_manager)?
+            .commitment()?;
+        let actual = self.utxo_set.commitment()?;
+
+        if expected != actual {
+            findings.push(InconsistencyFinding::UtxoSetMismatch { expected, actual });
+        }
+
+        let mut pending_utxo_set = self.utxo_set.clone();
+
+        for tx in &self.mem_pool.pending_transactions {
+            if let Some(outpoint) = tx.body.input.outpoint()
+                && !pending_utxo_set.outputs.contains_key(outpoint)
+            {
+                findings.push(InconsistencyFinding::MempoolSpendsMissingOutput {
+                    id: tx.id()?,
+                    outpoint: outpoint.clone(),
+                });
+                continue;
+            }
+
+            pending_utxo_set.update(tx)?;
+        }
+
+        Ok(findings)
+    }
+
+    /// Panics on any inconsistency; run after every chain switch in debug builds.
+    #[cfg(debug_assertions)]
+    pub(crate) fn debug_check_consistency(&self) {
+        let Err(findings) = self.check_consistency() else {
+            return;
+        };
+
+        // the mempool is only reconciled once initial block download finishes
+        let findings = findings
+            .into_iter()
+            .filter(|finding| {
+                !(self.is_syncing()
+                    && matches!(
+                        finding,
+                        InconsistencyFinding::MempoolSpendsMissingOutput { .. }
+                    ))
+            })
+            .collect::<Vec<_>>();
+
+        if !findings.is_empty() {
+            panic!("Node state is inconsistent: {findings:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+
+    fn test_node() -> (Node, tempfile::TempDir) {
+        let keypair = KeyPair::generate();
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair)
+                .data_dir(data_dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap();
+
+        while node.state.chain.height() < 3 {
+            let block = node.create_block().unwrap();
+            node.handle_message(Message::NewBlock(block)).unwrap();
+        }
+
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        node.send(recipient, 10, 1).unwrap();
+
+        (node, data_dir)
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        let (node, _data_dir) = test_node();
+        assert_eq!(node.state.check_consistency(), Ok(()));
+
+        let tip = node.state.chain.tail().unwrap();
+        let tip_hash = tip.header.hash().unwrap();
+        let tip_block = node.state.block_manager.blocks[&tip_hash].clone();
+
+        let mut state = node.state.clone();
+        state.block_manager.blocks.remove(&tip_hash);
+        let findings = state.check_consistency().unwrap_err();
+        assert!(findings.contains(&InconsistencyFinding::MissingBlock {
+            height: 3,
+            hash: tip_hash
+        }));
+
+        let mut state = node.state.clone();
+        state.chain.nodes.remove(&2);
+        let findings = state.check_consistency().unwrap_err();
+        assert!(findings.contains(&InconsistencyFinding::HeightGap {
+            previous: 1,
+            height: 3
+        }));
+
+        let mut state = node.state.clone();
+        let coinbase = &tip_block.transactions[0];
+        state
+            .utxo_set
+            .outputs
+            .remove(&coinbase.output_reference(0).unwrap());
+        let findings = state.check_consistency().unwrap_err();
+        assert!(matches!(
+            findings.as_slice(),
+            [InconsistencyFinding::UtxoSetMismatch { .. }]
+        ));
+
+        // resubmitting a pending transaction double spends its input
+        let mut state = node.state.clone();
+        let pending = state.mem_pool.pending_transactions[0].clone();
+        state.mem_pool.pending_transactions.push(pending.clone());
+        let findings = state.check_consistency().unwrap_err();
+        assert_eq!(
+            findings,
+            vec![InconsistencyFinding::MempoolSpendsMissingOutput {
+                id: pending.id().unwrap(),
+                outpoint: pending.body.input.outpoint().unwrap().clone(),
+            }]
+        );
+
+        let mut state = node.state.clone();
+        state
+            .block_manager
+            .orphan_blocks
+            .insert(tip_hash, tip_block);
+        let findings = state.check_consistency().unwrap_err();
+        assert_eq!(
+            findings,
+            vec![InconsistencyFinding::OrphanInChain { hash: tip_hash }]
+        );
+    }
+}
@@ -1,4 +1,55 @@
-// TODO: Move to config file
+// Mainnet's reward schedule; other networks take theirs from `NetworkParams`.
 pub const GENESIS_BLOCK_REWARD: u32 = 50;
 pub const BLOCKS_PER_REWARD_HALVING: u32 = 210_000;
+/// The most transactions a block may hold, its coinbase included.
 pub const BLOCK_SIZE_LIMIT: usize = 1_000;
+/// How far ahead of the local clock a block's timestamp may be.
+pub const MAX_FUTURE_BLOCK_TIME_SECS: u32 = 2 * 60 * 60;
+pub const MINING_INTERRUPT_INTERVAL: u64 = 10_000;
+/// Fewest transactions worth handing a thread of their own when computing txids.
+pub const TXID_CHUNK_SIZE: usize = 256;
+pub const NODE_EVENT_CHANNEL_CAPACITY: usize = 1_024;
+pub const DEFAULT_DATA_DIR: &str = ".tiny-crypto";
+pub const DEFAULT_MEMPOOL_MAX_TRANSACTIONS: usize = 5_000;
+pub const DEFAULT_P2P_LISTEN_ADDR: &str = "/ip4/0.0.0.0/tcp/0";
+pub const DEFAULT_MAX_PEER_CONNECTIONS: usize = 8;
+pub const DHT_RANDOM_WALK_INTERVAL_SECS: u64 = 60;
+pub const PEER_BOOK_MAX_AGE_SECS: i64 = 14 * 24 * 60 * 60;
+pub const DEFAULT_DUST_LIMIT: u64 = 1;
+/// The largest transaction, in bytes, the mempool accepts.
+pub const MAX_TRANSACTION_SIZE: usize = 100_000;
+pub const MAX_HEADERS_PER_MESSAGE: usize = 2_000;
+pub const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+pub const DEFAULT_MIN_TRANSACTION_FEE: u64 = 0;
+pub const DEFAULT_IBD_BATCH_SIZE: usize = 100;
+pub const DEFAULT_IBD_LAG_THRESHOLD: u32 = 10;
+pub const REJECTED_TRANSACTION_CACHE_SIZE: usize = 1_000;
+pub const HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+pub const BLOCK_DOWNLOAD_WINDOW: u32 = 128;
+pub const MAX_BLOCKS_IN_FLIGHT_PER_PEER: usize = 16;
+pub const BLOCK_REQUEST_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_INVENTORY_INTERVAL_SECS: u64 = 30;
+pub const MAX_INVENTORY_PER_MESSAGE: usize = 1_000;
+pub const KNOWN_INVENTORY_PER_PEER: usize = 5_000;
+pub const RATE_LIMIT_WINDOW_SECS: u64 = 60;
+pub const DEFAULT_BOOTSTRAP_RETRY_SECS: u64 = 1;
+pub const DEFAULT_BOOTSTRAP_RETRY_MAX_SECS: u64 = 5 * 60;
+pub const SEEN_CACHE_CAPACITY: usize = 20_000;
+pub const SEEN_CACHE_TTL_SECS: u64 = 10 * 60;
+pub const DEFAULT_WALLET_GAP_LIMIT: u32 = 20;
+pub const RPC_CLIENT_TIMEOUT_SECS: u64 = 30;
+/// Short of the client timeout, so a long poll answers before the client gives up.
+pub const RPC_LONGPOLL_TIMEOUT_SECS: u64 = 20;
+pub const DEFAULT_REST_PAGE_SIZE: usize = 100;
+pub const MAX_REST_PAGE_SIZE: usize = 1_000;
+pub const CONFIRMATION_POLL_INTERVAL_MILLIS: u64 = 500;
+pub const BENCH_SAMPLES: usize = 5;
+pub const BENCH_MERKLE_TRANSACTIONS: usize = 5_000;
+pub const DEFAULT_BENCH_BLOCK_TIME_SECS: u64 = 600;
+/// Rows `Blockchain`'s `Display` lists unless a precision asks for another number.
+pub const CHAIN_DISPLAY_ROWS: usize = 10;
+/// Seconds between the timestamps of consecutive `fixtures::block`s.
+pub const FIXTURE_BLOCK_INTERVAL_SECS: u32 = 600;
+pub const SIM_DEFAULT_LATENCY_MS: u64 = 50;
+pub const SIM_DEFAULT_JITTER_MS: u64 = 20;
+pub const SIM_MAX_DELIVERIES: usize = 1_000_000;
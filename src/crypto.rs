@@ -1,37 +1,436 @@
-use bincode::Encode;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use bincode::{Decode, Encode};
+use num_bigint::BigUint;
 use ripemd::Ripemd160;
-use secp256k1::ecdsa::Signature;
-use secp256k1::{Message, Secp256k1};
-use secp256k1::{PublicKey, SecretKey, rand};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId, Signature};
+#[cfg(feature = "rand")]
+use secp256k1::rand;
+use secp256k1::{All, Message, Secp256k1};
+use secp256k1::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+use crate::{constants::MAX_MESSAGE_SIZE, params::Network};
+
 pub type Hash = [u8; 32];
 
+/// One secp256k1 context for the whole process. Building one precomputes tables, which
+/// costs far more than the signing or verifying it's used for.
+pub static SECP: LazyLock<Secp256k1<All>> = LazyLock::new(|| {
+    #[cfg(test)]
+    tests::CONTEXTS_CREATED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    Secp256k1::new()
+});
+
+/// The textual form of hashes and other byte strings: lowercase hex, parsed with or
+/// without a `0x` prefix.
+pub fn to_hex(bytes: impl AsRef<[u8]>) -> String {
+    hex::encode(bytes)
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    hex::decode(hex.strip_prefix("0x").unwrap_or(hex))
+}
+
+/// The bincode configuration for decoding bytes from outside: the standard encoding, but
+/// refusing lengths that add up past `MAX_MESSAGE_SIZE` before allocating for them, as a
+/// claimed length is otherwise allocated up front.
+pub fn decode_config() -> impl bincode::config::Config {
+    bincode::config::standard().with_limit::<MAX_MESSAGE_SIZE>()
+}
+
+/// Constants, formatting, and parsing for `Hash`, which as a plain byte array can't have
+/// `Display` or `FromStr` of its own.
+pub trait HashExt: Sized {
+    fn zero() -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    /// Parses 64 hex digits, with or without a `0x` prefix.
+    fn from_hex(hex: &str) -> Result<Self, hex::FromHexError>;
+
+    /// Lowercase hex through `Display` or `LowerHex`, where `{:#x}` adds the `0x`.
+    fn display(&self) -> HashDisplay<'_>;
+
+    /// The hash as a big-endian integer, as it's compared against targets.
+    fn to_biguint(&self) -> BigUint;
+
+    /// Errors if `value` doesn't fit in 256 bits.
+    fn from_biguint(value: &BigUint) -> anyhow::Result<Self>;
+
+    fn leading_zero_bits(&self) -> u32;
+}
+
+impl HashExt for Hash {
+    fn zero() -> Self {
+        [0; 32]
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    fn from_hex(hex: &str) -> Result<Self, hex::FromHexError> {
+        from_hex(hex)?
+            .try_into()
+            .map_err(|_| hex::FromHexError::InvalidStringLength)
+    }
+
+    fn display(&self) -> HashDisplay<'_> {
+        HashDisplay(self)
+    }
+
+    fn to_biguint(&self) -> BigUint {
+        BigUint::from_bytes_be(self)
+    }
+
+    fn from_biguint(value: &BigUint) -> anyhow::Result<Self> {
+        let bytes = value.to_bytes_be();
+        if value.bits() > 256 {
+            return Err(anyhow::anyhow!("{} bits don't fit in a hash", value.bits()));
+        }
+
+        let mut hash = Self::zero();
+        hash[32 - bytes.len()..].copy_from_slice(&bytes);
+        Ok(hash)
+    }
+
+    fn leading_zero_bits(&self) -> u32 {
+        match self.iter().position(|&byte| byte != 0) {
+            Some(i) => i as u32 * 8 + self[i].leading_zeros(),
+            None => 256,
+        }
+    }
+}
+
+pub struct HashDisplay<'a>(&'a Hash);
+
+impl std::fmt::Display for HashDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&to_hex(self.0))
+    }
+}
+
+impl std::fmt::LowerHex for HashDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        f.write_str(&to_hex(self.0))
+    }
+}
+
+/// Serde helpers encoding byte arrays as lowercase hex strings (`to_hex`/`from_hex`) in
+/// human-readable formats and as raw bytes in binary ones.
+pub mod serde_hex {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, T: AsRef<[u8]>>(bytes: T, s: S) -> Result<S::Ok, S::Error> {
+        if s.is_human_readable() {
+            s.serialize_str(&super::to_hex(bytes))
+        } else {
+            bytes.as_ref().serialize(s)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+        d: D,
+    ) -> Result<T, D::Error> {
+        let bytes = if d.is_human_readable() {
+            let encoded = String::deserialize(d)?;
+            super::from_hex(&encoded).map_err(serde::de::Error::custom)?
+        } else {
+            Vec::<u8>::deserialize(d)?
+        };
+        T::try_from(bytes).map_err(|_| serde::de::Error::custom("Invalid byte length"))
+    }
+
+    /// The same encoding for optional values, as `null` when absent.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer, T: AsRef<[u8]>>(
+            bytes: &Option<T>,
+            s: S,
+        ) -> Result<S::Ok, S::Error> {
+            match bytes {
+                Some(bytes) => s.serialize_some(&Wrapper(bytes.as_ref())),
+                None => s.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+            d: D,
+        ) -> Result<Option<T>, D::Error> {
+            #[derive(Deserialize)]
+            struct Inner(#[serde(with = "super")] Vec<u8>);
+
+            Option::<Inner>::deserialize(d)?
+                .map(|Inner(bytes)| {
+                    T::try_from(bytes).map_err(|_| serde::de::Error::custom("Invalid byte length"))
+                })
+                .transpose()
+        }
+
+        struct Wrapper<'a>(&'a [u8]);
+
+        impl serde::Serialize for Wrapper<'_> {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                super::serialize(self.0, s)
+            }
+        }
+    }
+}
+
+/// Serde helpers for a public key as hex of its compressed form, like other byte strings.
+pub mod serde_public_key {
+    use secp256k1::PublicKey;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(public_key: &PublicKey, s: S) -> Result<S::Ok, S::Error> {
+        super::serde_hex::serialize(public_key.serialize(), s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<PublicKey, D::Error> {
+        let bytes: Vec<u8> = super::serde_hex::deserialize(d)?;
+        PublicKey::from_slice(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
 pub fn sha256d(bytes: &[u8]) -> Hash {
     Sha256::digest(Sha256::digest(bytes)).into()
 }
 
+/// RIPEMD-160 of SHA-256, as addresses hash public keys.
+pub fn hash160(bytes: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(bytes)).into()
+}
+
+/// Deliberately not serializable: configs and peer metadata hold a `PublicKeyOnly`, and
+/// secrets only leave memory as a `keystore::SerializableSecretKey`.
 #[derive(Clone)]
 pub struct KeyPair {
     pub secret_key: SecretKey,
     pub public_key: PublicKey,
 }
 
+/// The serializable, public half of a keypair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeyOnly(#[serde(with = "serde_public_key")] pub PublicKey);
+
+impl From<&KeyPair> for PublicKeyOnly {
+    fn from(key_pair: &KeyPair) -> Self {
+        Self(key_pair.public_key)
+    }
+}
+
 impl KeyPair {
+    pub fn public_only(&self) -> PublicKeyOnly {
+        self.into()
+    }
+
+    #[cfg(feature = "rand")]
     pub fn generate() -> Self {
-        let secp = Secp256k1::new();
-        let (secret_key, public_key) = secp.generate_keypair(&mut rand::rng());
+        let (secret_key, public_key) = SECP.generate_keypair(&mut rand::rng());
         Self {
             secret_key,
             public_key,
         }
     }
 
+    /// The keypair whose secret key is `seed`. About one seed in 2^128 is zero or not
+    /// below the curve order and is rejected; by convention the caller then retries with
+    /// `sha256d(seed)` until one is accepted.
+    pub fn from_seed(seed: &[u8; 32]) -> anyhow::Result<Self> {
+        Self::from_secret_bytes(*seed).map_err(|_| {
+            anyhow::anyhow!("Seed isn't a valid secp256k1 secret key, retry with its sha256d")
+        })
+    }
+
+    /// Parses a 32-byte secret key in hex, with or without a `0x` prefix.
+    pub fn from_secret_hex(hex: &str) -> anyhow::Result<Self> {
+        let bytes = from_hex(hex)?.try_into().map_err(|bytes: Vec<u8>| {
+            anyhow::anyhow!("Secret key is {} bytes, not 32", bytes.len())
+        })?;
+        Self::from_secret_bytes(bytes)
+    }
+
+    pub fn from_secret_bytes(bytes: [u8; 32]) -> anyhow::Result<Self> {
+        let secret_key = SecretKey::from_byte_array(bytes)?;
+        Ok(Self {
+            public_key: PublicKey::from_secret_key(&SECP, &secret_key),
+            secret_key,
+        })
+    }
+
     pub fn sign(&self, bytes: &[u8]) -> Signature {
-        let secp = Secp256k1::new();
         let digest = sha256d(bytes);
         let message = Message::from_digest(digest);
-        secp.sign_ecdsa(message, &self.secret_key)
+        SECP.sign_ecdsa(message, &self.secret_key)
+    }
+}
+
+impl KeyPair {
+    /// Signs the sha256d of `bytes` so the public key can be recovered from the signature.
+    pub fn sign_recoverable(&self, bytes: &[u8]) -> RecoverableSig {
+        let message = Message::from_digest(sha256d(bytes));
+        RecoverableSig(SECP.sign_ecdsa_recoverable(message, &self.secret_key))
+    }
+}
+
+/// A signature the signer's public key can be recovered from, so it needn't be sent
+/// along. Encoded as 65 bytes, the recovery id then the compact signature, and as hex in
+/// human-readable formats like other signatures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoverableSig(pub RecoverableSignature);
+
+impl RecoverableSig {
+    pub fn to_bytes(&self) -> [u8; 65] {
+        let (recovery_id, compact) = self.0.serialize_compact();
+        let mut bytes = [0; 65];
+        bytes[0] = i32::from(recovery_id) as u8;
+        bytes[1..].copy_from_slice(&compact);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let [recovery_id, compact @ ..] = bytes else {
+            return Err(anyhow::anyhow!("Empty recoverable signature"));
+        };
+        if compact.len() != 64 {
+            return Err(anyhow::anyhow!(
+                "Recoverable signature is {} bytes, not 65",
+                bytes.len()
+            ));
+        }
+
+        let recovery_id = RecoveryId::try_from(*recovery_id as i32)?;
+        Ok(Self(RecoverableSignature::from_compact(
+            compact,
+            recovery_id,
+        )?))
+    }
+
+    /// The public key that signed `bytes`, if the signature is valid for any.
+    pub fn recover(&self, bytes: &[u8]) -> anyhow::Result<PublicKey> {
+        let message = Message::from_digest(sha256d(bytes));
+        Ok(SECP.recover_ecdsa(message, &self.0)?)
+    }
+
+    /// Whether `bytes` was signed by the key behind `address`.
+    pub fn verify_against_address(&self, bytes: &[u8], address: &Address) -> bool {
+        self.recover(bytes)
+            .is_ok_and(|public_key| PublicKeyHash::from(&public_key) == address.payload())
+    }
+
+    /// The same signature without the recovery id.
+    pub fn to_standard(&self) -> Signature {
+        self.0.to_standard()
+    }
+}
+
+impl Serialize for RecoverableSig {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serde_hex::serialize(self.to_bytes(), s)
+    }
+}
+
+impl<'de> Deserialize<'de> for RecoverableSig {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let bytes: Vec<u8> = serde_hex::deserialize(d)?;
+        Self::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Flags a WIF secret key as belonging to a compressed public key.
+const WIF_COMPRESSED: u8 = 0x01;
+
+/// The WIF version byte for `network`. Testnet and regtest share one, as in Bitcoin.
+fn wif_version(network: Network) -> u8 {
+    match network {
+        Network::Mainnet => 0x80,
+        Network::Testnet | Network::Regtest => 0xef,
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WifError {
+    #[error("Invalid base58: {0}")]
+    Base58(#[from] bs58::decode::Error),
+    #[error("Invalid WIF checksum")]
+    Checksum,
+    #[error("Invalid WIF length {0}")]
+    Length(usize),
+    #[error("Unknown WIF version byte 0x{0:02x}")]
+    UnknownVersion(u8),
+    #[error("WIF key is for another network than {0}")]
+    WrongNetwork(Network),
+    #[error("Invalid WIF compression flag 0x{0:02x}")]
+    CompressionFlag(u8),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl KeyPair {
+    /// Base58check of the network's version byte, the secret key, and the compressed
+    /// public key flag.
+    pub fn to_wif(&self, network: Network) -> String {
+        let mut bytes = vec![wif_version(network)];
+        bytes.extend_from_slice(&self.secret_key.secret_bytes());
+        bytes.push(WIF_COMPRESSED);
+
+        let checksum = sha256d(&bytes);
+        bytes.extend_from_slice(&checksum[..4]);
+        bs58::encode(bytes).into_string()
+    }
+
+    /// Decodes a WIF secret key for any network, with or without the compressed flag.
+    pub fn from_wif(wif: &str) -> Result<Self, WifError> {
+        let (_, key_pair) = Self::decode_wif(wif)?;
+        Ok(key_pair)
+    }
+
+    /// Like `from_wif`, but refuses keys for another network.
+    pub fn from_wif_for(wif: &str, network: Network) -> Result<Self, WifError> {
+        let (version, key_pair) = Self::decode_wif(wif)?;
+        if version != wif_version(network) {
+            return Err(WifError::WrongNetwork(network));
+        }
+        Ok(key_pair)
+    }
+
+    fn decode_wif(wif: &str) -> Result<(u8, Self), WifError> {
+        let bytes = bs58::decode(wif).into_vec()?;
+        if bytes.len() < 4 {
+            return Err(WifError::Length(bytes.len()));
+        }
+
+        let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+        if sha256d(payload)[..4] != *checksum {
+            return Err(WifError::Checksum);
+        }
+
+        let secret = match payload {
+            [_, secret @ ..] if secret.len() == 32 => secret,
+            [_, secret @ .., WIF_COMPRESSED] if secret.len() == 32 => secret,
+            [_, secret @ .., flag] if secret.len() == 32 => {
+                return Err(WifError::CompressionFlag(*flag));
+            }
+            _ => return Err(WifError::Length(payload.len())),
+        };
+
+        let version = payload[0];
+        if version != wif_version(Network::Mainnet) && version != wif_version(Network::Testnet) {
+            return Err(WifError::UnknownVersion(version));
+        }
+
+        Ok((
+            version,
+            Self::from_secret_bytes(secret.try_into().unwrap())?,
+        ))
     }
 }
 
@@ -41,31 +440,245 @@ pub trait SignatureExt {
 
 impl SignatureExt for Signature {
     fn verify(&self, bytes: &[u8], public_key: &PublicKey) -> bool {
-        let secp = Secp256k1::verification_only();
         let digest = sha256d(bytes);
         let message = Message::from_digest(digest);
-        secp.verify_ecdsa(message, self, public_key).is_ok()
+        SECP.verify_ecdsa(message, self, public_key).is_ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Signature {index} of the batch is invalid")]
+pub struct BatchVerifyError {
+    pub index: usize,
+}
+
+/// Items are handed to the threads this many at a time.
+const BATCH_VERIFY_CHUNK: usize = 16;
+
+/// Verifies each signature over its sha256d `digest`, spread across up to `threads`
+/// threads sharing `SECP`. Stops early on a failure, reporting the lowest failing index:
+/// chunks are taken in order, so every earlier item has been checked by the time it's
+/// returned.
+pub fn verify_batch(
+    items: &[(Hash, &Signature, &PublicKey)],
+    threads: usize,
+) -> Result<(), BatchVerifyError> {
+    let verify = |(digest, signature, public_key): &(Hash, &Signature, &PublicKey)| {
+        SECP.verify_ecdsa(Message::from_digest(*digest), signature, public_key)
+            .is_ok()
+    };
+
+    let threads = threads.min(items.len().div_ceil(BATCH_VERIFY_CHUNK));
+    if threads <= 1 {
+        return match items.iter().position(|item| !verify(item)) {
+            Some(index) => Err(BatchVerifyError { index }),
+            None => Ok(()),
+        };
+    }
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(usize::MAX);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let start = next.fetch_add(BATCH_VERIFY_CHUNK, Ordering::Relaxed);
+                    if start >= items.len() || start > failed.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let end = (start + BATCH_VERIFY_CHUNK).min(items.len());
+                    if let Some(offset) = items[start..end].iter().position(|item| !verify(item)) {
+                        failed.fetch_min(start + offset, Ordering::Relaxed);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    match failed.into_inner() {
+        usize::MAX => Ok(()),
+        index => Err(BatchVerifyError { index }),
+    }
+}
+
+/// The address version byte for `network`. Testnet and regtest share one, as in Bitcoin.
+fn address_version(network: Network) -> u8 {
+    match network {
+        Network::Mainnet => 0x00,
+        Network::Testnet | Network::Regtest => 0x6f,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AddressError {
+    #[error("Invalid base58: {0}")]
+    Base58(#[from] bs58::decode::Error),
+    #[error("Invalid address checksum")]
+    Checksum,
+    #[error("Invalid address length {0}")]
+    Length(usize),
+    #[error("Unknown address version byte 0x{0:02x}")]
+    UnknownVersion(u8),
+    #[error("Address is for another network than {0}")]
+    WrongNetwork(Network),
+}
+
+/// The RIPEMD-160 of the SHA-256 of a public key's uncompressed form, the payload of its
+/// address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKeyHash(pub [u8; 20]);
+
+impl From<&PublicKey> for PublicKeyHash {
+    fn from(public_key: &PublicKey) -> Self {
+        Self(hash160(&public_key.serialize_uncompressed()))
+    }
+}
+
+/// A base58check address. Parsing one (`FromStr`, `TryFrom<&str>`, or deserializing)
+/// verifies its checksum and version, so a mistyped address is refused rather than paid.
+/// The decoded version and payload are kept alongside the string.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Address {
+    encoded: String,
+    version: u8,
+    payload: PublicKeyHash,
+}
+
+/// Encodes as the string alone, so transaction hashes don't depend on the cached fields.
+impl Encode for Address {
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> Result<(), bincode::error::EncodeError> {
+        self.encoded.encode(encoder)
+    }
+}
+
+/// Parses the string `Encode` writes, checksum and all.
+impl<Context> Decode<Context> for Address {
+    fn decode<D: bincode::de::Decoder<Context = Context>>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        let encoded = String::decode(decoder)?;
+        Self::decode(&encoded)
+            .map_err(|error| bincode::error::DecodeError::OtherString(error.to_string()))
     }
 }
 
-#[derive(Debug, Clone, Encode, Eq, PartialEq)]
-pub struct Address(String);
+bincode::impl_borrow_decode!(Address);
 
 impl Address {
+    /// The mainnet address of `public_key`.
     pub fn from_public_key(public_key: &PublicKey) -> Self {
-        let hash_1 = Sha256::digest(public_key.serialize_uncompressed());
+        Self::from_public_key_for(public_key, Network::Mainnet)
+    }
+
+    pub fn from_public_key_for(public_key: &PublicKey, network: Network) -> Self {
+        Self::from_pubkey_hash(public_key.into(), network)
+    }
+
+    pub fn from_pubkey_hash(payload: PublicKeyHash, network: Network) -> Self {
+        let version = address_version(network);
+        let mut bytes = vec![version];
+        bytes.extend_from_slice(&payload.0);
+
+        let checksum = sha256d(&bytes);
+        bytes.extend_from_slice(&checksum[..4]);
+
+        Self {
+            encoded: bs58::encode(bytes).into_string(),
+            version,
+            payload,
+        }
+    }
+
+    pub fn payload(&self) -> PublicKeyHash {
+        self.payload
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Whether the address has `network`'s version byte. Testnet and regtest share one.
+    pub fn is_for(&self, network: Network) -> bool {
+        self.version == address_version(network)
+    }
+
+    /// Like `from_str`, but refuses addresses for another network.
+    pub fn parse_for(address: &str, network: Network) -> Result<Self, AddressError> {
+        let address = address.parse::<Self>()?;
+        if address.version != address_version(network) {
+            return Err(AddressError::WrongNetwork(network));
+        }
+        Ok(address)
+    }
+
+    fn decode(address: &str) -> Result<Self, AddressError> {
+        let bytes = bs58::decode(address).into_vec()?;
+        // a version byte, the 20-byte public key hash, and the checksum
+        if bytes.len() != 25 {
+            return Err(AddressError::Length(bytes.len()));
+        }
 
-        let mut ripemd_hasher = Ripemd160::new();
-        ripemd_hasher.update(hash_1);
-        let hash_2 = ripemd_hasher.finalize();
+        let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+        if sha256d(payload)[..4] != *checksum {
+            return Err(AddressError::Checksum);
+        }
+
+        let version = payload[0];
+        if version != address_version(Network::Mainnet)
+            && version != address_version(Network::Testnet)
+        {
+            return Err(AddressError::UnknownVersion(version));
+        }
+
+        Ok(Self {
+            encoded: address.to_string(),
+            version,
+            payload: PublicKeyHash(payload[1..].try_into().unwrap()),
+        })
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.encoded)
+    }
+}
+
+impl std::str::FromStr for Address {
+    type Err = AddressError;
 
-        let version_byte = 0u8.to_le_bytes();
-        let version_and_hash = [version_byte.to_vec(), hash_2.to_vec()].concat();
+    /// Parses an address for any network.
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Self::decode(address)
+    }
+}
+
+impl TryFrom<&str> for Address {
+    type Error = AddressError;
+
+    fn try_from(address: &str) -> Result<Self, Self::Error> {
+        address.parse()
+    }
+}
 
-        let checksum = &sha256d(&version_and_hash)[..4];
-        let address_bytes = [version_byte.to_vec(), hash_2.to_vec(), checksum.to_vec()].concat();
+impl TryFrom<String> for Address {
+    type Error = AddressError;
 
-        Address(bs58::encode(address_bytes).into_string())
+    fn try_from(address: String) -> Result<Self, Self::Error> {
+        address.parse()
+    }
+}
+
+impl From<Address> for String {
+    fn from(address: Address) -> Self {
+        address.encoded
     }
 }
 
@@ -85,8 +698,13 @@ pub struct MerkleTree {
 }
 
 impl MerkleTree {
+    /// A tree over raw data, whose leaves are the sha256d of each item.
     pub fn from_leaves(leaf_bytes: Vec<&[u8]>) -> Self {
-        let leaves: Vec<[u8; 32]> = leaf_bytes.iter().map(|x| sha256d(x)).collect();
+        Self::from_hashed_leaves(leaf_bytes.iter().map(|x| sha256d(x)).collect())
+    }
+
+    /// A tree whose leaves are the given digests as they are, like txids.
+    pub fn from_hashed_leaves(leaves: Vec<Hash>) -> Self {
         Self {
             tree: rs_merkle::MerkleTree::<Sha256dHasher>::from_leaves(&leaves),
         }
@@ -95,12 +713,75 @@ impl MerkleTree {
     pub fn root(&self) -> Option<Hash> {
         self.tree.root()
     }
+
+    pub fn leaf_count(&self) -> usize {
+        self.tree.leaves_len()
+    }
+
+    /// A proof that the leaves at `indices` are in the tree, checked with
+    /// `MerkleProof::verify` against the root and leaf count alone.
+    pub fn proof(&self, indices: &[usize]) -> anyhow::Result<MerkleProof> {
+        if indices.is_empty() {
+            return Err(anyhow::anyhow!("No leaves to prove"));
+        }
+        if let Some(index) = indices.iter().find(|&&index| index >= self.leaf_count()) {
+            return Err(anyhow::anyhow!(
+                "Leaf {index} is out of range for a tree of {} leaves",
+                self.leaf_count()
+            ));
+        }
+
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+
+        Ok(MerkleProof {
+            hashes: self.tree.proof(&indices).proof_hashes().to_vec(),
+        })
+    }
+}
+
+/// The sibling hashes needed to recompute a merkle root from some of its leaves.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub hashes: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Whether `leaf_hashes` (the leaves as the tree holds them, by index) and the proof hash up to
+    /// `root` in a tree of `total_leaves`. Unpaired nodes are promoted rather than
+    /// duplicated, so a wrong count is only caught when it changes the shape of the proof.
+    pub fn verify(&self, root: &Hash, leaf_hashes: &[(usize, Hash)], total_leaves: usize) -> bool {
+        if leaf_hashes.is_empty() || leaf_hashes.iter().any(|(index, _)| *index >= total_leaves) {
+            return false;
+        }
+
+        let mut leaf_hashes = leaf_hashes.to_vec();
+        leaf_hashes.sort_unstable_by_key(|(index, _)| *index);
+        leaf_hashes.dedup();
+
+        // a lone leaf is its own root, with nothing to prove it by
+        if total_leaves == 1 {
+            return self.hashes.is_empty() && leaf_hashes[0].1 == *root;
+        }
+
+        let (indices, hashes): (Vec<_>, Vec<_>) = leaf_hashes.into_iter().unzip();
+        rs_merkle::MerkleProof::<Sha256dHasher>::new(self.hashes.clone()).verify(
+            *root,
+            &indices,
+            &hashes,
+            total_leaves,
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub(super) static CONTEXTS_CREATED: AtomicUsize = AtomicUsize::new(0);
 
     #[test]
     fn test_sign_and_verify_message() {
@@ -118,13 +799,291 @@ mod tests {
         assert!(!expected_invalid);
     }
 
+    #[test]
+    fn test_public_key_only_serde() {
+        let key_pair = KeyPair::generate();
+        let public = key_pair.public_only();
+
+        let json = serde_json::to_string(&public).unwrap();
+        assert_eq!(
+            json,
+            format!("\"{}\"", hex::encode(key_pair.public_key.serialize()))
+        );
+        assert_eq!(
+            serde_json::from_str::<PublicKeyOnly>(&json).unwrap(),
+            public
+        );
+        assert!(serde_json::from_str::<PublicKeyOnly>("\"02ff\"").is_err());
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let key_pairs = (0..100).map(|_| KeyPair::generate()).collect::<Vec<_>>();
+        let signed = key_pairs
+            .iter()
+            .enumerate()
+            .map(|(i, key_pair)| {
+                let bytes = i.to_le_bytes();
+                (sha256d(&bytes), key_pair.sign(&bytes), key_pair.public_key)
+            })
+            .collect::<Vec<_>>();
+        fn items(signed: &[(Hash, Signature, PublicKey)]) -> Vec<(Hash, &Signature, &PublicKey)> {
+            signed
+                .iter()
+                .map(|(digest, signature, public_key)| (*digest, signature, public_key))
+                .collect()
+        }
+
+        assert_eq!(verify_batch(&items(&signed), 4), Ok(()));
+        assert_eq!(verify_batch(&items(&signed), 1), Ok(()));
+        assert_eq!(verify_batch(&[], 4), Ok(()));
+
+        let mut bad = signed.clone();
+        bad[37].2 = key_pairs[38].public_key;
+        bad[90].2 = key_pairs[91].public_key;
+        assert_eq!(
+            verify_batch(&items(&bad), 4),
+            Err(BatchVerifyError { index: 37 })
+        );
+        assert_eq!(
+            verify_batch(&items(&bad), 1),
+            Err(BatchVerifyError { index: 37 })
+        );
+    }
+
+    #[test]
+    fn test_shared_context() {
+        let key_pair = KeyPair::generate();
+        let signature = key_pair.sign(b"Hello, world!");
+
+        for _ in 0..1000 {
+            assert!(signature.verify(b"Hello, world!", &key_pair.public_key));
+        }
+
+        // verifying from many threads at once shares the same context
+        let threads = (0..8)
+            .map(|i| {
+                let key_pair = key_pair.clone();
+                std::thread::spawn(move || {
+                    for j in 0..50 {
+                        let bytes = [i, j];
+                        let signature = key_pair.sign(&bytes);
+                        assert!(signature.verify(&bytes, &key_pair.public_key));
+                        assert!(!signature.verify(b"other", &key_pair.public_key));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(CONTEXTS_CREATED.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_recoverable_signature() {
+        let key_pair = KeyPair::from_seed(&[1; 32]).unwrap();
+        let address = Address::from_public_key(&key_pair.public_key);
+        let bytes = b"Hello, world!";
+
+        let signature = key_pair.sign_recoverable(bytes);
+        assert_eq!(signature.recover(bytes).unwrap(), key_pair.public_key);
+        assert!(signature.verify_against_address(bytes, &address));
+        assert!(!signature.verify_against_address(b"Goodbye, world!", &address));
+        assert!(
+            signature
+                .to_standard()
+                .verify(bytes, &signature.recover(bytes).unwrap())
+        );
+
+        let encoded = signature.to_bytes();
+        assert_eq!(RecoverableSig::from_bytes(&encoded).unwrap(), signature);
+        let json = serde_json::to_string(&signature).unwrap();
+        assert_eq!(json, format!("\"{}\"", hex::encode(encoded)));
+        assert_eq!(
+            serde_json::from_str::<RecoverableSig>(&json).unwrap(),
+            signature
+        );
+
+        // another recovery id recovers some other key, or none
+        let mut corrupted = encoded;
+        corrupted[0] ^= 1;
+        let corrupted = RecoverableSig::from_bytes(&corrupted).unwrap();
+        assert!(!corrupted.verify_against_address(bytes, &address));
+
+        let mut out_of_range = encoded;
+        out_of_range[0] = 4;
+        assert!(RecoverableSig::from_bytes(&out_of_range).is_err());
+        assert!(RecoverableSig::from_bytes(&encoded[..64]).is_err());
+    }
+
     #[test]
     fn test_address() {
         let pk_str = "035fe61fefdd77e3f8065c57ce7750d4b4aa7bc881ebb8875d1a211c28d08ca111";
         let pk = PublicKey::from_str(pk_str).unwrap();
 
         let pk_address = Address::from_public_key(&pk);
-        assert_eq!(pk_address.0, "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwW");
+        assert_eq!(pk_address.to_string(), "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwW");
+
+        // built from the hash, or parsed back, it's the same address
+        let hash = PublicKeyHash::from(&pk);
+        assert_eq!(
+            Address::from_pubkey_hash(hash, Network::Mainnet),
+            pk_address
+        );
+        let parsed = Address::from_str("1KYYpnPHa2fpyfrGmug6pprexoJU74ihwW").unwrap();
+        assert_eq!(parsed, pk_address);
+        assert_eq!(parsed.payload(), hash);
+        assert_eq!(parsed.version(), 0x00);
+        assert_eq!(
+            bincode::encode_to_vec(&parsed, bincode::config::standard()).unwrap(),
+            bincode::encode_to_vec(
+                "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwW",
+                bincode::config::standard()
+            )
+            .unwrap()
+        );
+
+        let testnet = Address::from_pubkey_hash(hash, Network::Testnet);
+        assert_eq!(testnet.to_string(), "mz4W7qUGP475knKtVUeUek4ypnuAwUu8hH");
+        assert_eq!(testnet.payload(), hash);
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let address = "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwW";
+        let parsed = Address::from_str(address).unwrap();
+        assert_eq!(parsed.to_string(), address);
+        assert_eq!(Address::try_from(address), Ok(parsed.clone()));
+        assert_eq!(Address::parse_for(address, Network::Mainnet), Ok(parsed));
+
+        // one character off
+        assert_eq!(
+            Address::from_str("1KYYpnPHa2fpyfrGmug6pprexoJU74ihwX"),
+            Err(AddressError::Checksum)
+        );
+        assert!(matches!(
+            Address::from_str("1KYYpnPHa2fpyfrGmug6pprexoJU74ihw0"),
+            Err(AddressError::Base58(_))
+        ));
+        assert_eq!(
+            Address::from_str("1KYYpnPHa2fpyfrGmug6ppre"),
+            Err(AddressError::Length(18))
+        );
+
+        assert_eq!(
+            Address::parse_for(address, Network::Regtest),
+            Err(AddressError::WrongNetwork(Network::Regtest))
+        );
+        // the same hash with the testnet version byte parses, but only for testnet
+        let testnet = "mz4W7qUGP475knKtVUeUek4ypnuAwUu8hH";
+        assert!(Address::parse_for(testnet, Network::Testnet).is_ok());
+        assert_eq!(
+            Address::parse_for(testnet, Network::Mainnet),
+            Err(AddressError::WrongNetwork(Network::Mainnet))
+        );
+
+        // a typo in a deserialized address is refused too
+        assert!(serde_json::from_str::<Address>("\"1KYYpnPHa2fpyfrGmug6pprexoJU74ihwX\"").is_err());
+    }
+
+    #[test]
+    fn test_from_seed() {
+        // these must never change, or keys regenerated from backed up seeds would
+        let key_pair = KeyPair::from_seed(&[1; 32]).unwrap();
+        assert_eq!(
+            key_pair.public_key.to_string(),
+            "031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f"
+        );
+        assert_eq!(
+            Address::from_public_key(&key_pair.public_key).to_string(),
+            "1BCwRkTsYzK5aNK4sdF7Bpti3PhrkPtLc4"
+        );
+
+        let hex = format!("0x{}", key_pair.secret_key.display_secret());
+        let parsed = KeyPair::from_secret_hex(&hex).unwrap();
+        assert_eq!(parsed.public_key, key_pair.public_key);
+        assert!(KeyPair::from_secret_hex("0x0101").is_err());
+        assert!(KeyPair::from_secret_hex("not hex").is_err());
+
+        // out of range seeds are refused, and the retry convention lands on a valid key
+        assert!(KeyPair::from_seed(&[0; 32]).is_err());
+        assert!(KeyPair::from_seed(&[0xff; 32]).is_err());
+        assert!(KeyPair::from_seed(&sha256d(&[0xff; 32])).is_ok());
+    }
+
+    #[test]
+    fn test_wif() {
+        let vectors = [
+            (
+                "0c28fca386c7a227600b2fe50b7cae11ec86d3bf1fbe471be89827e19d72aa1d",
+                Network::Mainnet,
+                "KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                Network::Mainnet,
+                "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                Network::Testnet,
+                "cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcA",
+            ),
+        ];
+        for (secret, network, wif) in vectors {
+            let key_pair =
+                KeyPair::from_secret_bytes(hex::decode(secret).unwrap().try_into().unwrap())
+                    .unwrap();
+            assert_eq!(key_pair.to_wif(network), wif);
+
+            let imported = KeyPair::from_wif_for(wif, network).unwrap();
+            assert_eq!(imported.secret_key, key_pair.secret_key);
+            assert_eq!(imported.public_key, key_pair.public_key);
+        }
+
+        // keys for uncompressed public keys import too
+        let uncompressed =
+            KeyPair::from_wif("5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ").unwrap();
+        assert_eq!(
+            hex::encode(uncompressed.secret_key.secret_bytes()),
+            vectors[0].0
+        );
+
+        let wif = vectors[0].2;
+        let mut corrupted = wif.to_string();
+        corrupted.replace_range(10..11, "n");
+        assert!(matches!(
+            KeyPair::from_wif(&corrupted),
+            Err(WifError::Checksum)
+        ));
+        assert!(matches!(
+            KeyPair::from_wif("0OIl"),
+            Err(WifError::Base58(_))
+        ));
+        assert!(matches!(
+            KeyPair::from_wif(&bs58::encode([0x80, 1, 2, 3, 4]).into_string()),
+            Err(WifError::Checksum)
+        ));
+        assert!(matches!(
+            KeyPair::from_wif_for(wif, Network::Regtest),
+            Err(WifError::WrongNetwork(Network::Regtest))
+        ));
+
+        let checked = |payload: &[u8]| {
+            let mut bytes = payload.to_vec();
+            bytes.extend_from_slice(&sha256d(payload)[..4]);
+            bs58::encode(bytes).into_string()
+        };
+        assert!(matches!(
+            KeyPair::from_wif(&checked(&[0x80; 20])),
+            Err(WifError::Length(20))
+        ));
+        assert!(matches!(
+            KeyPair::from_wif(&checked(&[0x42; 33])),
+            Err(WifError::UnknownVersion(0x42))
+        ));
     }
 
     #[test]
@@ -133,12 +1092,97 @@ mod tests {
         let tree = MerkleTree::from_leaves(leaves);
 
         let root = tree.root().unwrap();
-        println!("Root: 0x{}", hex::encode(root));
+        println!("Root: {:#x}", root.display());
 
         assert_eq!(
-            root.to_vec(),
-            hex::decode("9d6bf165d3b3552fcf9c4bd1fee36db5aca38d992a6aff5178c7aac79c6d715d")
+            root,
+            Hash::from_hex("9d6bf165d3b3552fcf9c4bd1fee36db5aca38d992a6aff5178c7aac79c6d715d")
                 .unwrap()
         );
     }
+
+    #[test]
+    fn test_merkle_proof() {
+        let leaves = (0..5u8).map(|i| vec![i]).collect::<Vec<_>>();
+        let tree = MerkleTree::from_leaves(leaves.iter().map(Vec::as_slice).collect());
+        let root = tree.root().unwrap();
+        let leaf = |i: usize| (i, sha256d(&leaves[i]));
+
+        let proof = tree.proof(&[3, 1]).unwrap();
+        assert!(proof.verify(&root, &[leaf(1), leaf(3)], 5));
+        assert!(!proof.verify(&root, &[leaf(1), leaf(2)], 5));
+        assert!(!proof.verify(&root, &[leaf(1), leaf(3)], 4));
+        let last = tree.proof(&[4]).unwrap();
+        assert!(last.verify(&root, &[leaf(4)], 5));
+        assert!(!last.verify(&root, &[leaf(4)], 6));
+        assert!(!proof.verify(&root, &[], 5));
+
+        let mut tampered = proof.clone();
+        tampered.hashes[0][0] ^= 1;
+        assert!(!tampered.verify(&root, &[leaf(1), leaf(3)], 5));
+
+        let bytes = bincode::encode_to_vec(&proof, bincode::config::standard()).unwrap();
+        let (decoded, _): (MerkleProof, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert_eq!(decoded, proof);
+
+        assert!(tree.proof(&[5]).is_err());
+        assert!(tree.proof(&[]).is_err());
+
+        // digests are taken as leaves without hashing them again
+        let hashed =
+            MerkleTree::from_hashed_leaves(leaves.iter().map(|leaf| sha256d(leaf)).collect());
+        assert_eq!(hashed.root(), Some(root));
+        assert!(proof.verify(&root, &[leaf(1), leaf(3)], 5));
+
+        let single = MerkleTree::from_leaves(vec![b"only".as_slice()]);
+        let proof = single.proof(&[0]).unwrap();
+        assert!(proof.hashes.is_empty());
+        assert!(proof.verify(&single.root().unwrap(), &[(0, sha256d(b"only"))], 1));
+        assert!(!proof.verify(&single.root().unwrap(), &[(0, sha256d(b"other"))], 1));
+    }
+
+    #[test]
+    fn test_hash_hex() {
+        let hash = sha256d(b"Hello, world!");
+        let hex = hash.display().to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(format!("{:x}", hash.display()), hex);
+        assert_eq!(format!("{:#x}", hash.display()), format!("0x{hex}"));
+
+        assert_eq!(Hash::from_hex(&hex), Ok(hash));
+        assert_eq!(Hash::from_hex(&format!("0x{hex}")), Ok(hash));
+        assert!(Hash::from_hex(&hex[2..]).is_err());
+        assert!(Hash::from_hex("not hex").is_err());
+
+        assert!(Hash::zero().is_zero());
+        assert!(!hash.is_zero());
+
+        assert_eq!(Hash::from_biguint(&hash.to_biguint()).unwrap(), hash);
+        assert_eq!(Hash::from_biguint(&BigUint::ZERO).unwrap(), Hash::zero());
+        let max = (BigUint::from(1u32) << 256) - 1u32;
+        assert_eq!(Hash::from_biguint(&max).unwrap(), [u8::MAX; 32]);
+        assert!(Hash::from_biguint(&(max + 1u32)).is_err());
+
+        assert_eq!(Hash::zero().leading_zero_bits(), 256);
+        assert_eq!([u8::MAX; 32].leading_zero_bits(), 0);
+        let mut target = Hash::zero();
+        target[2] = 0x10;
+        assert_eq!(target.leading_zero_bits(), 19);
+
+        // serde uses the same textual form
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Wrapper(#[serde(with = "serde_hex")] Hash);
+
+        let json = serde_json::to_string(&Wrapper(hash)).unwrap();
+        assert_eq!(json, format!("\"{hex}\""));
+        assert_eq!(
+            serde_json::from_str::<Wrapper>(&json).unwrap(),
+            Wrapper(hash)
+        );
+        assert_eq!(
+            serde_json::from_str::<Wrapper>(&format!("\"0x{hex}\"")).unwrap(),
+            Wrapper(hash)
+        );
+    }
 }
@@ -0,0 +1,229 @@
+//! Deterministic keys, headers, transactions, and blocks for the benchmarks and tests.
+//! Everything derives from a seed, and signing is deterministic, so two runs build
+//! byte-identical inputs.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use secp256k1::{PublicKey, ecdsa::Signature};
+
+use crate::{
+    block::{Block, BlockHeader},
+    constants::FIXTURE_BLOCK_INTERVAL_SECS,
+    crypto::{Address, Hash, KeyPair, sha256d},
+    params::NetworkParams,
+    transaction::{Transaction, TransactionBody, TransactionInput, TransactionOutput},
+    utxo_set::UTXOSet,
+};
+#[cfg(feature = "std-node")]
+use crate::{
+    config::{NodeConfig, NodeConfigBuilder},
+    node::{Message, Node},
+};
+
+/// The keypair for `seed`, its secret the first valid sha256d in a chain starting from
+/// the seed, as `KeyPair::from_seed` suggests.
+pub fn key_pair(seed: u64) -> KeyPair {
+    let mut secret = sha256d(format!("tiny-crypto fixture {seed}").as_bytes());
+    loop {
+        match KeyPair::from_seed(&secret) {
+            Ok(key_pair) => return key_pair,
+            Err(_) => secret = sha256d(&secret),
+        }
+    }
+}
+
+/// An unmined header at `difficulty` whose hashes differ from seed to seed.
+pub fn header(seed: u64, difficulty: u8) -> BlockHeader {
+    BlockHeader {
+        previous_block_hash: sha256d(&seed.to_le_bytes()),
+        merkle_root: sha256d(&sha256d(&seed.to_le_bytes())),
+        timestamp: NetworkParams::regtest().genesis_timestamp,
+        difficulty,
+        nonce: 0,
+    }
+}
+
+/// `count` coinbases paying `key_pair(seed)`, one per height from 1, so their txids all
+/// differ.
+pub fn coinbases(count: usize, seed: u64, params: &NetworkParams) -> Result<Vec<Transaction>> {
+    let key_pair = key_pair(seed);
+    (1..=count as u32)
+        .map(|height| Transaction::new_coinbase(&key_pair, height, params))
+        .collect()
+}
+
+/// `count` signatures over distinct digests, each by a key of its own, in the form
+/// `verify_batch` takes them.
+pub fn signed_digests(count: usize, seed: u64) -> Vec<(Hash, Signature, PublicKey)> {
+    (0..count as u64)
+        .map(|index| {
+            let key_pair = key_pair(seed.wrapping_add(index));
+            let message = index.to_le_bytes();
+            (
+                sha256d(&message),
+                key_pair.sign(&message),
+                key_pair.public_key,
+            )
+        })
+        .collect()
+}
+
+/// A transaction with `outputs` outputs of `value` each to `key_pair`, and a set in
+/// which they're all unspent. It's a coinbase paying more than any reward, so it only
+/// makes sense outside of consensus, as funds for `spends`.
+pub fn funding(
+    outputs: usize,
+    value: u64,
+    key_pair: &KeyPair,
+    params: &NetworkParams,
+) -> Result<(Arc<Transaction>, UTXOSet)> {
+    let address = Address::from_public_key_for(&key_pair.public_key, params.network);
+    let transaction = Arc::new(
+        TransactionBody {
+            input: TransactionInput::Coinbase { block_height: 1 },
+            outputs: vec![TransactionOutput { value, address }; outputs],
+        }
+        .into_tx(key_pair)?,
+    );
+
+    let mut utxo_set = UTXOSet::default();
+    utxo_set.apply_transaction(&transaction, 1)?;
+    Ok((transaction, utxo_set))
+}
+
+/// One transaction per output of `funding`, each paying it back to `key_pair` less
+/// `fee`. None of them conflict, so any subset can be pending or confirmed together.
+pub fn spends(
+    funding: &Transaction,
+    key_pair: &KeyPair,
+    fee: u64,
+) -> Result<Vec<Arc<Transaction>>> {
+    funding
+        .body
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(index, output)| {
+            TransactionBody {
+                input: TransactionInput::spending(funding.output_reference(index)?),
+                outputs: vec![TransactionOutput {
+                    value: output.value.saturating_sub(fee),
+                    address: output.address.clone(),
+                }],
+            }
+            .into_tx(key_pair)
+            .map(Arc::new)
+        })
+        .collect()
+}
+
+/// A mined block on `previous` with `transactions` after a coinbase claiming `fees`,
+/// stamped `FIXTURE_BLOCK_INTERVAL_SECS` after it rather than with the clock.
+pub fn block(
+    previous: &Block,
+    key_pair: &KeyPair,
+    transactions: Vec<Arc<Transaction>>,
+    fees: u64,
+    params: &NetworkParams,
+) -> Result<Block> {
+    let timestamp = previous.header.timestamp + FIXTURE_BLOCK_INTERVAL_SECS;
+    let mut block = Block::new_at(key_pair, previous, transactions, fees, timestamp, params)?;
+    block.mine()?;
+    Ok(block)
+}
+
+/// The genesis block of `params` followed by coinbase-only blocks mined by `key_pair`,
+/// `length` blocks in all. Each is mined at the genesis difficulty, so it's meant for
+/// regtest.
+pub fn chain(length: u32, key_pair: &KeyPair, params: &NetworkParams) -> Result<Vec<Block>> {
+    let mut blocks = Vec::with_capacity(length as usize);
+    if length == 0 {
+        return Ok(blocks);
+    }
+
+    blocks.push(params.genesis_block()?);
+    for _ in 1..length {
+        let next = block(&blocks[blocks.len() - 1], key_pair, Vec::new(), 0, params)?;
+        blocks.push(next);
+    }
+    Ok(blocks)
+}
+
+/// The config of a regtest node paying `seed`'s keypair, for a test to adjust further.
+#[cfg(feature = "std-node")]
+pub fn regtest_config(seed: u64) -> NodeConfigBuilder {
+    NodeConfig::builder(key_pair(seed)).network(NetworkParams::regtest())
+}
+
+/// A regtest node paying `seed`'s keypair, holding its chain in memory.
+#[cfg(feature = "std-node")]
+pub fn regtest_node(seed: u64) -> Result<Node> {
+    Node::in_memory(regtest_config(seed).build())
+}
+
+/// Creates a block on the node's tip and hands it to the node as a peer's would be.
+#[cfg(feature = "std-node")]
+pub fn mine_block(node: &mut Node) -> Result<Block> {
+    let block = node.create_block()?;
+    node.handle_message(Message::NewBlock(block.clone()))?;
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::verify_batch;
+
+    #[test]
+    fn test_deterministic() {
+        let params = NetworkParams::regtest();
+        assert_eq!(key_pair(1).public_key, key_pair(1).public_key);
+        assert_ne!(key_pair(1).public_key, key_pair(2).public_key);
+        assert_ne!(header(1, 0).hash().unwrap(), header(2, 0).hash().unwrap());
+
+        let ids = |transactions: Vec<Transaction>| Transaction::ids(&transactions, 1).unwrap();
+        assert_eq!(
+            ids(coinbases(3, 7, &params).unwrap()),
+            ids(coinbases(3, 7, &params).unwrap())
+        );
+
+        let first = chain(3, &key_pair(1), &params).unwrap();
+        let second = chain(3, &key_pair(1), &params).unwrap();
+        assert_eq!(first.len(), 3);
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!(a.header.hash().unwrap(), b.header.hash().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_valid() {
+        let params = NetworkParams::regtest();
+        let key_pair = key_pair(1);
+
+        let blocks = chain(3, &key_pair, &params).unwrap();
+        let mut utxo_set = UTXOSet::default();
+        for (previous, block) in blocks.iter().zip(&blocks[1..]) {
+            assert_eq!(
+                block.header.previous_block_hash,
+                previous.header.hash().unwrap()
+            );
+            assert_eq!(block.height, previous.height + 1);
+            block.validate(&params).unwrap();
+            block.connect(&mut utxo_set, &params).unwrap();
+        }
+
+        let digests = signed_digests(20, 1);
+        let items = digests
+            .iter()
+            .map(|(digest, signature, public_key)| (*digest, signature, public_key))
+            .collect::<Vec<_>>();
+        assert_eq!(verify_batch(&items, 2), Ok(()));
+
+        let (funding, mut utxo_set) = funding(4, 100, &key_pair, &params).unwrap();
+        for spend in spends(&funding, &key_pair, 1).unwrap() {
+            assert_eq!(utxo_set.validate_transaction(&spend, 2), Ok(1));
+            utxo_set.update(&spend).unwrap();
+        }
+    }
+}
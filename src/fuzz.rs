@@ -0,0 +1,78 @@
+//! The bodies of the `fuzz/` targets, kept here so `tests/fuzz_regressions.rs` replays
+//! the committed inputs through the same code under `cargo test`. Each takes arbitrary
+//! bytes, as a peer or RPC client could send them, and may fail only by returning an
+//! error; a panic is a bug.
+
+use std::str::FromStr;
+
+use crate::{
+    block::{Block, TransactionProof},
+    crypto::{Address, Hash, MerkleProof},
+    params::NetworkParams,
+    transaction::{Transaction, TxId},
+    utxo_set::UTXOSet,
+};
+
+/// A raw transaction, as hex and as bytes, validated if it parses.
+pub fn transaction(data: &[u8]) {
+    if let Ok(raw) = std::str::from_utf8(data) {
+        let _ = Transaction::from_hex(raw);
+    }
+    if let Ok(transaction) = Transaction::from_bytes(data) {
+        let _ = transaction.id();
+        let _ = transaction.validate();
+    }
+}
+
+/// A block in the wire encoding, validated as of its own timestamp so parsed blocks
+/// get past the clock, then connected to an empty UTXO set.
+pub fn block(data: &[u8]) {
+    let Ok(block) = Block::from_bytes(data) else {
+        return;
+    };
+    let params = NetworkParams::regtest();
+    let _ = block.validate_at(&params, block.header.timestamp);
+    let _ = block.connect(&mut UTXOSet::default(), &params);
+}
+
+/// A framed peer message.
+#[cfg(feature = "std-node")]
+pub fn wire_message(data: &[u8]) {
+    let mut reader = data;
+    let _ = crate::wire::decode_message(&mut reader);
+}
+
+/// An address string, which must print back as it was parsed.
+pub fn address(data: &[u8]) {
+    let Ok(raw) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(address) = Address::from_str(raw) {
+        assert_eq!(address.to_string(), raw);
+    }
+}
+
+/// A merkle proof checked against a root and leaf: the root, the leaf, then the leaf's
+/// index and the leaf count as little-endian `u64`s, then the proof's hashes. The same
+/// bytes are also tried as a hex `TransactionProof`.
+pub fn merkle_proof(data: &[u8]) {
+    if let Ok(raw) = std::str::from_utf8(data)
+        && let Ok(proof) = TransactionProof::from_hex(raw)
+    {
+        let _ = proof.verify(&Hash::default(), &TxId::empty(), proof.index as usize + 1);
+    }
+
+    let Some((fixed, hashes)) = data.split_at_checked(80) else {
+        return;
+    };
+    let hash = |bytes: &[u8]| Hash::try_from(bytes).unwrap();
+    let number = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap()) as usize;
+    let proof = MerkleProof {
+        hashes: hashes.chunks_exact(32).map(hash).collect(),
+    };
+    let _ = proof.verify(
+        &hash(&fixed[..32]),
+        &[(number(&fixed[64..72]), hash(&fixed[32..64]))],
+        number(&fixed[72..80]),
+    );
+}
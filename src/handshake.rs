@@ -0,0 +1,47 @@
+use anyhow::Result;
+
+use crate::{node::Node, wire::Version};
+
+/// 2 gave inputs relative locks, changing how transactions encode and so every txid, and
+/// blocks a validator signature.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Oldest protocol version we still talk to; version 1 peers' transactions and blocks
+/// can't be decoded.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+pub const HANDSHAKE_PROTOCOL: &str = "/tiny-crypto/handshake/1";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HandshakeError {
+    #[error("Peer is on another network (magic {theirs:02x?}, ours {ours:02x?})")]
+    WrongNetwork { ours: [u8; 4], theirs: [u8; 4] },
+    #[error("Peer protocol version {0} is older than {MIN_PROTOCOL_VERSION}")]
+    IncompatibleVersion(u32),
+}
+
+impl Version {
+    pub fn for_node(node: &Node) -> Result<Self> {
+        Ok(Self {
+            protocol_version: PROTOCOL_VERSION,
+            network_magic: node.state.params.magic()?,
+            best_height: node.state.chain.height(),
+            best_hash: node.tip_hash()?.unwrap_or_default(),
+            user_agent: format!("tiny-crypto/{}", env!("CARGO_PKG_VERSION")),
+        })
+    }
+
+    /// Whether a peer announcing `self` can talk to us, announcing `ours`.
+    pub fn check_compatible(&self, ours: &Version) -> Result<(), HandshakeError> {
+        if self.network_magic != ours.network_magic {
+            return Err(HandshakeError::WrongNetwork {
+                ours: ours.network_magic,
+                theirs: self.network_magic,
+            });
+        }
+
+        if self.protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(HandshakeError::IncompatibleVersion(self.protocol_version));
+        }
+
+        Ok(())
+    }
+}
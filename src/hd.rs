@@ -0,0 +1,495 @@
+use std::{fmt, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use ripemd::Ripemd160;
+use secp256k1::{PublicKey, Scalar, SecretKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{
+    crypto::{KeyPair, SECP, sha256d},
+    params::Network,
+};
+
+const HARDENED: u32 = 1 << 31;
+const MASTER_KEY: &[u8] = b"Bitcoin seed";
+const SERIALIZED_LEN: usize = 78;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HdError {
+    #[error("Invalid derivation path {0:?}")]
+    InvalidPath(String),
+    #[error("Seed derives an invalid master key")]
+    InvalidSeed,
+    #[error("Hardened children can't be derived from a public key")]
+    HardenedFromPublic,
+    /// One in about 2^127 indices, which BIP32 says to skip.
+    #[error("Child {0} derives an invalid key, use the next index")]
+    InvalidChild(ChildNumber),
+    #[error("Invalid base58: {0}")]
+    Base58(#[from] bs58::decode::Error),
+    #[error("Invalid extended key checksum")]
+    Checksum,
+    #[error("Extended keys are {SERIALIZED_LEN} bytes, not {0}")]
+    Length(usize),
+    #[error("Unknown extended key version 0x{0:08x}")]
+    UnknownVersion(u32),
+    #[error("Invalid key data in extended key")]
+    InvalidKeyData,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// An index below `2^31`, or a hardened one at or above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChildNumber(pub u32);
+
+impl ChildNumber {
+    pub fn normal(index: u32) -> Self {
+        Self(index & !HARDENED)
+    }
+
+    pub fn hardened(index: u32) -> Self {
+        Self(index | HARDENED)
+    }
+
+    pub fn is_hardened(&self) -> bool {
+        self.0 & HARDENED != 0
+    }
+
+    pub fn index(&self) -> u32 {
+        self.0 & !HARDENED
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index())?;
+        if self.is_hardened() {
+            write!(f, "'")?;
+        }
+        Ok(())
+    }
+}
+
+/// A path like `m/44'/0'/0'/0/5` from the master key, with `'` or `h` marking hardened
+/// steps.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DerivationPath(pub Vec<ChildNumber>);
+
+impl DerivationPath {
+    pub fn child(&self, child: ChildNumber) -> Self {
+        let mut path = self.clone();
+        path.0.push(child);
+        path
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = HdError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = || HdError::InvalidPath(path.to_string());
+        let mut steps = path.split('/');
+
+        if steps.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        steps
+            .map(|step| {
+                let (index, hardened) = match step.strip_suffix(['\'', 'h']) {
+                    Some(index) => (index, true),
+                    None => (step, false),
+                };
+                let index = index.parse::<u32>().map_err(|_| invalid())?;
+                if index >= HARDENED {
+                    return Err(invalid());
+                }
+
+                Ok(if hardened {
+                    ChildNumber::hardened(index)
+                } else {
+                    ChildNumber::normal(index)
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for child in &self.0 {
+            write!(f, "/{child}")?;
+        }
+        Ok(())
+    }
+}
+
+/// As its `m/44'/0'` string form.
+impl Serialize for DerivationPath {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DerivationPath {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A BIP32 extended private key: a keypair plus the chain code its children derive from.
+#[derive(Clone)]
+pub struct ExtendedPrivKey {
+    pub network: Network,
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: ChildNumber,
+    pub chain_code: [u8; 32],
+    pub key_pair: KeyPair,
+}
+
+/// The public half of an `ExtendedPrivKey`, which can derive non-hardened children's
+/// public keys, and so their addresses, without any secrets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPubKey {
+    pub network: Network,
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: ChildNumber,
+    pub chain_code: [u8; 32],
+    pub public_key: PublicKey,
+}
+
+impl ExtendedPrivKey {
+    pub fn new_master(seed: &[u8], network: Network) -> Result<Self, HdError> {
+        let (key, chain_code) = hmac_sha512(MASTER_KEY, &[seed]);
+        let secret_key = SecretKey::from_byte_array(key).map_err(|_| HdError::InvalidSeed)?;
+
+        Ok(Self {
+            network,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: ChildNumber(0),
+            chain_code,
+            key_pair: KeyPair::from_secret_bytes(secret_key.secret_bytes())?,
+        })
+    }
+
+    pub fn derive_child(&self, child: ChildNumber) -> Result<Self, HdError> {
+        let secret = self.key_pair.secret_key.secret_bytes();
+        let public = self.key_pair.public_key.serialize();
+        let index = child.0.to_be_bytes();
+
+        let data: [&[u8]; 3] = if child.is_hardened() {
+            [&[0], &secret, &index]
+        } else {
+            [&public, &[], &index]
+        };
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| HdError::InvalidChild(child))?;
+        let secret_key = self
+            .key_pair
+            .secret_key
+            .add_tweak(&tweak)
+            .map_err(|_| HdError::InvalidChild(child))?;
+
+        Ok(Self {
+            network: self.network,
+            depth: self.depth.saturating_add(1),
+            parent_fingerprint: fingerprint(&self.key_pair.public_key),
+            child_number: child,
+            chain_code,
+            key_pair: KeyPair::from_secret_bytes(secret_key.secret_bytes())?,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, HdError> {
+        path.0
+            .iter()
+            .try_fold(self.clone(), |key, child| key.derive_child(*child))
+    }
+
+    pub fn to_public(&self) -> ExtendedPubKey {
+        ExtendedPubKey {
+            network: self.network,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            public_key: self.key_pair.public_key,
+        }
+    }
+}
+
+impl ExtendedPubKey {
+    pub fn derive_child(&self, child: ChildNumber) -> Result<Self, HdError> {
+        if child.is_hardened() {
+            return Err(HdError::HardenedFromPublic);
+        }
+
+        let (tweak, chain_code) = hmac_sha512(
+            &self.chain_code,
+            &[&self.public_key.serialize(), &child.0.to_be_bytes()],
+        );
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| HdError::InvalidChild(child))?;
+        let public_key = self
+            .public_key
+            .add_exp_tweak(&SECP, &tweak)
+            .map_err(|_| HdError::InvalidChild(child))?;
+
+        Ok(Self {
+            network: self.network,
+            depth: self.depth.saturating_add(1),
+            parent_fingerprint: fingerprint(&self.public_key),
+            child_number: child,
+            chain_code,
+            public_key,
+        })
+    }
+}
+
+impl fmt::Display for ExtendedPrivKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut key_data = [0; 33];
+        key_data[1..].copy_from_slice(&self.key_pair.secret_key.secret_bytes());
+        let header = Header {
+            version: private_version(self.network),
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+        };
+        write!(f, "{}", header.encode(&key_data))
+    }
+}
+
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = Header {
+            version: public_version(self.network),
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+        };
+        write!(f, "{}", header.encode(&self.public_key.serialize()))
+    }
+}
+
+impl FromStr for ExtendedPrivKey {
+    type Err = HdError;
+
+    fn from_str(encoded: &str) -> Result<Self, Self::Err> {
+        let (header, key_data) = Header::decode(encoded)?;
+        let network = [Network::Mainnet, Network::Testnet]
+            .into_iter()
+            .find(|network| private_version(*network) == header.version)
+            .ok_or(HdError::UnknownVersion(header.version))?;
+
+        let [0, secret @ ..] = key_data else {
+            return Err(HdError::InvalidKeyData);
+        };
+
+        Ok(Self {
+            network,
+            depth: header.depth,
+            parent_fingerprint: header.parent_fingerprint,
+            child_number: header.child_number,
+            chain_code: header.chain_code,
+            key_pair: KeyPair::from_secret_bytes(secret).map_err(|_| HdError::InvalidKeyData)?,
+        })
+    }
+}
+
+impl FromStr for ExtendedPubKey {
+    type Err = HdError;
+
+    fn from_str(encoded: &str) -> Result<Self, Self::Err> {
+        let (header, key_data) = Header::decode(encoded)?;
+        let network = [Network::Mainnet, Network::Testnet]
+            .into_iter()
+            .find(|network| public_version(*network) == header.version)
+            .ok_or(HdError::UnknownVersion(header.version))?;
+
+        Ok(Self {
+            network,
+            depth: header.depth,
+            parent_fingerprint: header.parent_fingerprint,
+            child_number: header.child_number,
+            chain_code: header.chain_code,
+            public_key: PublicKey::from_slice(&key_data).map_err(|_| HdError::InvalidKeyData)?,
+        })
+    }
+}
+
+/// `xprv`, or `tprv` for the test networks.
+fn private_version(network: Network) -> u32 {
+    match network {
+        Network::Mainnet => 0x0488ade4,
+        Network::Testnet | Network::Regtest => 0x04358394,
+    }
+}
+
+/// `xpub`, or `tpub` for the test networks.
+fn public_version(network: Network) -> u32 {
+    match network {
+        Network::Mainnet => 0x0488b21e,
+        Network::Testnet | Network::Regtest => 0x043587cf,
+    }
+}
+
+/// Everything in a serialized extended key but the key data.
+struct Header {
+    version: u32,
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: ChildNumber,
+    chain_code: [u8; 32],
+}
+
+impl Header {
+    fn encode(&self, key_data: &[u8; 33]) -> String {
+        let mut bytes = Vec::with_capacity(SERIALIZED_LEN + 4);
+        bytes.extend_from_slice(&self.version.to_be_bytes());
+        bytes.push(self.depth);
+        bytes.extend_from_slice(&self.parent_fingerprint);
+        bytes.extend_from_slice(&self.child_number.0.to_be_bytes());
+        bytes.extend_from_slice(&self.chain_code);
+        bytes.extend_from_slice(key_data);
+
+        let checksum = sha256d(&bytes);
+        bytes.extend_from_slice(&checksum[..4]);
+        bs58::encode(bytes).into_string()
+    }
+
+    fn decode(encoded: &str) -> Result<(Self, [u8; 33]), HdError> {
+        let bytes = bs58::decode(encoded).into_vec()?;
+        if bytes.len() != SERIALIZED_LEN + 4 {
+            return Err(HdError::Length(bytes.len().saturating_sub(4)));
+        }
+
+        let (payload, checksum) = bytes.split_at(SERIALIZED_LEN);
+        if sha256d(payload)[..4] != *checksum {
+            return Err(HdError::Checksum);
+        }
+
+        let header = Self {
+            version: u32::from_be_bytes(payload[..4].try_into().unwrap()),
+            depth: payload[4],
+            parent_fingerprint: payload[5..9].try_into().unwrap(),
+            child_number: ChildNumber(u32::from_be_bytes(payload[9..13].try_into().unwrap())),
+            chain_code: payload[13..45].try_into().unwrap(),
+        };
+        Ok((header, payload[45..].try_into().unwrap()))
+    }
+}
+
+/// The left and right halves of HMAC-SHA512 over `data`'s concatenation.
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+
+    let output = mac.finalize().into_bytes();
+    (
+        output[..32].try_into().unwrap(),
+        output[32..].try_into().unwrap(),
+    )
+}
+
+/// The first four bytes of the compressed public key's hash160.
+fn fingerprint(public_key: &PublicKey) -> [u8; 4] {
+    let hash = Ripemd160::digest(Sha256::digest(public_key.serialize()));
+    hash[..4].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_1() {
+        // BIP32 test vector 1
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivKey::new_master(&seed, Network::Mainnet).unwrap();
+
+        let vectors = [
+            (
+                "m",
+                "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+                "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+            ),
+            (
+                "m/0'",
+                "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7",
+                "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+            ),
+            (
+                "m/0'/1",
+                "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs",
+                "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+            ),
+            (
+                "m/0h/1/2h",
+                "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM",
+                "xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5",
+            ),
+            (
+                "m/0'/1/2'/2",
+                "xprvA2JDeKCSNNZky6uBCviVfJSKyQ1mDYahRjijr5idH2WwLsEd4Hsb2Tyh8RfQMuPh7f7RtyzTtdrbdqqsunu5Mm3wDvUAKRHSC34sJ7in334",
+                "xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV",
+            ),
+            (
+                "m/0'/1/2'/2/1000000000",
+                "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76",
+                "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+            ),
+        ];
+
+        for (path, xprv, xpub) in vectors {
+            let key = master.derive_path(&path.parse().unwrap()).unwrap();
+            assert_eq!(key.to_string(), xprv, "{path}");
+            assert_eq!(key.to_public().to_string(), xpub, "{path}");
+
+            let parsed = xprv.parse::<ExtendedPrivKey>().unwrap();
+            assert_eq!(parsed.to_public(), key.to_public());
+            assert_eq!(xpub.parse::<ExtendedPubKey>().unwrap(), key.to_public());
+        }
+
+        // the public side derives the same non-hardened children
+        let account = master.derive_path(&"m/0'/1/2'".parse().unwrap()).unwrap();
+        let child = ChildNumber::normal(2);
+        assert_eq!(
+            account.to_public().derive_child(child).unwrap(),
+            account.derive_child(child).unwrap().to_public()
+        );
+        assert!(matches!(
+            account.to_public().derive_child(ChildNumber::hardened(2)),
+            Err(HdError::HardenedFromPublic)
+        ));
+    }
+
+    #[test]
+    fn test_derivation_path() {
+        let path = "m/44'/0'/0h/0/5".parse::<DerivationPath>().unwrap();
+        assert_eq!(path.to_string(), "m/44'/0'/0'/0/5");
+        assert_eq!(path.0[4], ChildNumber::normal(5));
+        assert!(path.0[0].is_hardened());
+
+        assert_eq!(
+            "m".parse::<DerivationPath>().unwrap(),
+            DerivationPath::default()
+        );
+        for invalid in ["", "44'/0", "m/", "m/x", "m/2147483648", "m/1''"] {
+            assert!(invalid.parse::<DerivationPath>().is_err(), "{invalid}");
+        }
+    }
+}
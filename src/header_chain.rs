@@ -0,0 +1,278 @@
+use std::{collections::HashMap, sync::Arc};
+
+use num_bigint::BigUint;
+
+#[cfg(feature = "std-node")]
+use crate::node::NodeState;
+use crate::{
+    block::BlockHeader,
+    chain::BlockchainNode,
+    crypto::{Hash, HashExt},
+};
+
+/// Headers checked for proof of work and linkage only. A node keeps those received ahead
+/// of their blocks, which together with the block manager's nodes form the header tree
+/// that headers-first sync downloads blocks along; `spv::LightClient` keeps every header.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderChain {
+    nodes: HashMap<Hash, Arc<BlockchainNode>>,
+    best: Option<Arc<BlockchainNode>>,
+}
+
+impl HeaderChain {
+    /// A chain rooted at `root`, whose header is trusted rather than checked, as the
+    /// genesis block's is.
+    pub fn with_root(root: Arc<BlockchainNode>) -> anyhow::Result<Self> {
+        let hash = root.header.hash()?;
+        Ok(Self {
+            nodes: HashMap::from([(hash, root.clone())]),
+            best: Some(root),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&Arc<BlockchainNode>> {
+        self.nodes.get(hash)
+    }
+
+    /// The header with the most cumulative work.
+    pub fn best(&self) -> Option<&Arc<BlockchainNode>> {
+        self.best.as_ref()
+    }
+
+    /// Checks `header`, whose hash is `hash`, against `parent` and adds it. Its hash must
+    /// meet its own target, which counts towards the chain's work, and its height follow
+    /// its parent's. There's no retargeting schedule: each block sets its difficulty, as
+    /// full nodes allow.
+    pub fn insert(
+        &mut self,
+        hash: Hash,
+        height: u32,
+        header: &BlockHeader,
+        parent: Arc<BlockchainNode>,
+    ) -> Result<Arc<BlockchainNode>, HeaderError> {
+        // an out of range difficulty has no valid hash either
+        if !header.hash_meets_target(&hash).unwrap_or(false) {
+            return Err(HeaderError::InvalidProofOfWork);
+        }
+
+        if height != parent.height + 1 {
+            return Err(HeaderError::WrongHeight {
+                claimed: height,
+                parent: parent.height,
+            });
+        }
+
+        let mut node = BlockchainNode {
+            height,
+            header: header.clone(),
+            work: BigUint::default(),
+            previous: None,
+        };
+        node.set_previous(Some(parent))?;
+        let node = Arc::new(node);
+
+        if self.best.as_ref().is_none_or(|best| node.work > best.work) {
+            self.best = Some(node.clone());
+        }
+
+        self.nodes.insert(hash, node.clone());
+        Ok(node)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HeaderError {
+    #[error("Header hash does not meet difficulty target")]
+    InvalidProofOfWork,
+    #[error("Header follows unknown block {:#x}", .0.display())]
+    UnknownParent(Hash),
+    #[error("Header claims height {claimed} but follows a header at {parent}")]
+    WrongHeight { claimed: u32, parent: u32 },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[cfg(feature = "std-node")]
+impl NodeState {
+    fn header_node(&self, hash: &Hash) -> Option<&Arc<BlockchainNode>> {
+        self.block_manager
+            .nodes
+            .get(hash)
+            .or_else(|| self.headers.get(hash))
+    }
+
+    /// Adds `headers`, each following the one before it or a header we already know, to
+    /// the header chain. Headers ahead of the first invalid one are kept.
+    pub fn add_headers(&mut self, headers: &[(u32, BlockHeader)]) -> Result<(), HeaderError> {
+        // one encoding buffer for the whole batch, which in initial block download can be
+        // thousands of headers
+        let mut buf = Vec::new();
+        let result = headers
+            .iter()
+            .try_for_each(|(height, header)| self.add_header(*height, header, &mut buf));
+
+        if let Some(best) = self.best_header() {
+            self.note_header_height(best.height);
+        }
+
+        result
+    }
+
+    fn add_header(
+        &mut self,
+        height: u32,
+        header: &BlockHeader,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), HeaderError> {
+        let hash = header.hash_with_buf(buf)?;
+
+        // a header we already know was checked when it arrived
+        if self.header_node(&hash).is_some() {
+            return Ok(());
+        }
+
+        let parent = self
+            .header_node(&header.previous_block_hash)
+            .cloned()
+            .ok_or(HeaderError::UnknownParent(header.previous_block_hash))?;
+
+        self.headers.insert(hash, height, header, parent)?;
+        Ok(())
+    }
+
+    /// The header with the most cumulative work, whether or not we have its block.
+    pub fn best_header(&self) -> Option<Arc<BlockchainNode>> {
+        let best_block = self.block_manager.best_node();
+
+        match (best_block, &self.headers.best) {
+            (Some(block), Some(header)) if header.work > block.work => Some(header.clone()),
+            (None, header) => header.clone(),
+            (block, _) => block,
+        }
+    }
+
+    /// Locator for the best header, so peers send the headers that follow it.
+    pub fn header_locator(&self) -> anyhow::Result<Vec<Hash>> {
+        let mut locator = Vec::new();
+        let Some(mut node) = self.best_header() else {
+            return Ok(locator);
+        };
+        let mut step = 1;
+
+        loop {
+            locator.push(node.header.hash()?);
+
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+
+            let Some(mut next) = node.previous.clone() else {
+                break;
+            };
+            for _ in 1..step {
+                match next.previous.clone() {
+                    Some(previous) => next = previous,
+                    None => break,
+                }
+            }
+            node = next;
+        }
+
+        Ok(locator)
+    }
+
+    /// Blocks on the best header chain that we don't have yet, lowest first.
+    pub fn missing_blocks(&self) -> anyhow::Result<Vec<(u32, Hash)>> {
+        let mut missing = Vec::new();
+        let mut node = self.best_header();
+
+        while let Some(current) = node {
+            let hash = current.header.hash()?;
+
+            if self.block_manager.contains_block(&hash) {
+                break;
+            }
+
+            missing.push((current.height, hash));
+            node = current.previous.clone();
+        }
+
+        missing.reverse();
+        Ok(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+
+    #[test]
+    fn test_add_headers() {
+        let data_dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let [mut ahead, mut behind] = data_dirs.each_ref().map(|data_dir| {
+            Node::new(
+                NodeConfig::builder(KeyPair::generate())
+                    .data_dir(data_dir.path())
+                    .network(NetworkParams::regtest())
+                    .build(),
+            )
+            .unwrap()
+        });
+
+        while ahead.state.chain.height() < 30 {
+            let block = ahead.create_block().unwrap();
+            ahead.handle_message(Message::NewBlock(block)).unwrap();
+        }
+
+        let headers = ahead
+            .state
+            .headers_after(&behind.state.header_locator().unwrap(), None, 100)
+            .unwrap();
+        assert_eq!(headers.len(), 29);
+
+        // headers must link up with what we know
+        assert!(matches!(
+            behind.state.add_headers(&headers[1..]),
+            Err(HeaderError::UnknownParent(_))
+        ));
+        let mut wrong_height = headers[..1].to_vec();
+        wrong_height[0].0 += 1;
+        assert!(matches!(
+            behind.state.add_headers(&wrong_height),
+            Err(HeaderError::WrongHeight { .. })
+        ));
+        assert!(behind.state.headers.is_empty());
+
+        behind.state.add_headers(&headers).unwrap();
+        assert_eq!(behind.state.headers.len(), 29);
+        assert_eq!(behind.state.best_header_height, 30);
+        assert_eq!(
+            behind.state.best_header().unwrap().header.hash().unwrap(),
+            ahead.tip_hash().unwrap().unwrap()
+        );
+        assert_eq!(
+            behind.state.header_locator().unwrap(),
+            ahead.state.chain.locator().unwrap()
+        );
+
+        let missing = behind.state.missing_blocks().unwrap();
+        assert_eq!(missing.len(), 29);
+        assert_eq!(missing[0].0, 2);
+        assert_eq!(missing[28].1, ahead.tip_hash().unwrap().unwrap());
+
+        // the blocks themselves still need to arrive
+        assert_eq!(behind.state.chain.height(), 1);
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::{constants::KNOWN_INVENTORY_PER_PEER, transaction::TxId};
+
+/// Transactions a peer is known to have, because it announced or sent them to us or we
+/// announced them to it. The oldest are forgotten first once `capacity` is reached.
+#[derive(Debug, Clone)]
+pub struct KnownInventory {
+    capacity: usize,
+    entries: HashSet<TxId>,
+    order: VecDeque<TxId>,
+}
+
+impl Default for KnownInventory {
+    fn default() -> Self {
+        Self::new(KNOWN_INVENTORY_PER_PEER)
+    }
+}
+
+impl KnownInventory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Returns whether `id` wasn't known yet.
+    pub fn insert(&mut self, id: TxId) -> bool {
+        if !self.entries.insert(id.clone()) {
+            return false;
+        }
+
+        self.order.push_back(id);
+
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    pub fn contains(&self, id: &TxId) -> bool {
+        self.entries.contains(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forgets_oldest() {
+        let ids = (0..4).map(|i| TxId([i; 32])).collect::<Vec<_>>();
+        let mut known = KnownInventory::new(3);
+
+        for id in &ids[..3] {
+            assert!(known.insert(id.clone()));
+        }
+        assert!(!known.insert(ids[0].clone()));
+
+        assert!(known.insert(ids[3].clone()));
+        assert!(!known.contains(&ids[0]));
+        assert!(ids[1..].iter().all(|id| known.contains(id)));
+    }
+}
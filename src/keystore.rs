@@ -0,0 +1,335 @@
+use std::{fs, io::Write, path::Path};
+
+use argon2::{Algorithm, Argon2, Params};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, KeyInit, Payload},
+};
+use secp256k1::rand;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{KeyPair, serde_hex};
+
+const MAGIC: &[u8; 4] = b"TCKY";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// Magic, version, the three argon2 parameters, salt, and nonce.
+const HEADER_LEN: usize = MAGIC.len() + 1 + 12 + SALT_LEN + NONCE_LEN;
+/// The encrypted secret key and its tag.
+const CIPHERTEXT_LEN: usize = 32 + 16;
+/// The most memory (in KiB), passes, and lanes a key file may ask argon2 for, four
+/// times the defaults files are written with, so a corrupted or hostile file is refused
+/// rather than exhausting memory.
+const MAX_COSTS: [u32; 3] = [
+    4 * Params::DEFAULT_M_COST,
+    4 * Params::DEFAULT_T_COST,
+    4 * Params::DEFAULT_P_COST,
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeystoreError {
+    #[error("Not a key file")]
+    NotAKeyFile,
+    #[error("Unsupported key file version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Key file is truncated or corrupted")]
+    Corrupted,
+    /// The tag covers the header too, so a tampered file looks the same.
+    #[error("Wrong passphrase, or the key file has been tampered with")]
+    WrongPassphrase,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// A secret key in a serializable form, as hex in human-readable formats. The only way
+/// a `KeyPair`'s secret can be serialized, so writing one anywhere is a deliberate act.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableSecretKey(#[serde(with = "serde_hex")] [u8; 32]);
+
+impl SerializableSecretKey {
+    pub fn from_key_pair(key_pair: &KeyPair) -> Self {
+        Self(key_pair.secret_key.secret_bytes())
+    }
+
+    pub fn to_key_pair(&self) -> anyhow::Result<KeyPair> {
+        KeyPair::from_secret_bytes(self.0)
+    }
+}
+
+impl std::fmt::Debug for SerializableSecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SerializableSecretKey(..)")
+    }
+}
+
+impl KeyPair {
+    /// Encrypts the secret key under `passphrase` and writes it to `path`, readable only
+    /// by the owner. The key is stretched with argon2id (its parameters are kept in the
+    /// file) and the secret sealed with ChaCha20-Poly1305.
+    pub fn save_encrypted(&self, path: &Path, passphrase: &str) -> Result<(), KeystoreError> {
+        self.save_encrypted_with(path, passphrase, Params::default())
+    }
+
+    fn save_encrypted_with(
+        &self,
+        path: &Path,
+        passphrase: &str,
+        params: Params,
+    ) -> Result<(), KeystoreError> {
+        let secret = SerializableSecretKey::from_key_pair(self);
+        write_owner_only(path, &seal(MAGIC, &secret.0, passphrase, params)?)
+    }
+
+    /// Writes the secret key unencrypted, as JSON hex, for development setups only.
+    pub fn save_plaintext(&self, path: &Path) -> Result<(), KeystoreError> {
+        let json = serde_json::to_vec(&SerializableSecretKey::from_key_pair(self))
+            .map_err(anyhow::Error::from)?;
+        write_owner_only(path, &json)
+    }
+
+    pub fn load_plaintext(path: &Path) -> Result<Self, KeystoreError> {
+        let secret: SerializableSecretKey =
+            serde_json::from_slice(&fs::read(path)?).map_err(|_| KeystoreError::NotAKeyFile)?;
+        Ok(secret.to_key_pair()?)
+    }
+
+    pub fn load_encrypted(path: &Path, passphrase: &str) -> Result<Self, KeystoreError> {
+        let file = fs::read(path)?;
+        check_header(MAGIC, &file)?;
+        if file.len() != HEADER_LEN + CIPHERTEXT_LEN {
+            return Err(KeystoreError::Corrupted);
+        }
+
+        let secret = unseal(MAGIC, &file, passphrase)?;
+        let bytes = secret.try_into().map_err(|_| KeystoreError::Corrupted)?;
+        Ok(SerializableSecretKey(bytes).to_key_pair()?)
+    }
+
+    /// Loads the key at `path`, generating and saving one if it doesn't exist yet.
+    pub fn load_or_create_encrypted(path: &Path, passphrase: &str) -> Result<Self, KeystoreError> {
+        if path.exists() {
+            return Self::load_encrypted(path, passphrase);
+        }
+
+        let key_pair = KeyPair::generate();
+        key_pair.save_encrypted(path, passphrase)?;
+        Ok(key_pair)
+    }
+}
+
+/// Writes `bytes` to `path`, creating its directory, readable only by the owner.
+pub(crate) fn write_owner_only(path: &Path, bytes: &[u8]) -> Result<(), KeystoreError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut out = options.open(path)?;
+    // the mode only applies to new files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        out.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    out.write_all(bytes)?;
+
+    Ok(())
+}
+
+/// Encrypts `secret` under `passphrase` behind a header of `magic`, the version, the
+/// argon2 parameters, salt, and nonce, which the tag covers too. Key files seal a secret
+/// key, wallet files their seed and metadata.
+pub(crate) fn seal(
+    magic: &[u8; 4],
+    secret: &[u8],
+    passphrase: &str,
+    params: Params,
+) -> Result<Vec<u8>, KeystoreError> {
+    let salt = rand::random::<[u8; SALT_LEN]>();
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+
+    let mut file = Vec::with_capacity(HEADER_LEN + secret.len() + 16);
+    file.extend_from_slice(magic);
+    file.push(VERSION);
+    file.extend_from_slice(&params.m_cost().to_le_bytes());
+    file.extend_from_slice(&params.t_cost().to_le_bytes());
+    file.extend_from_slice(&params.p_cost().to_le_bytes());
+    file.extend_from_slice(&salt);
+    file.extend_from_slice(&nonce);
+
+    let ciphertext = cipher(passphrase, &salt, params)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: secret,
+                aad: &file,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt secret"))?;
+    file.extend_from_slice(&ciphertext);
+
+    Ok(file)
+}
+
+fn check_header(magic: &[u8; 4], file: &[u8]) -> Result<(), KeystoreError> {
+    if file.get(..magic.len()) != Some(magic) {
+        return Err(KeystoreError::NotAKeyFile);
+    }
+    match file.get(magic.len()) {
+        Some(&VERSION) => Ok(()),
+        Some(&version) => Err(KeystoreError::UnsupportedVersion(version)),
+        None => Err(KeystoreError::Corrupted),
+    }
+}
+
+/// Decrypts a file written by `seal` with the same `magic`.
+pub(crate) fn unseal(
+    magic: &[u8; 4],
+    file: &[u8],
+    passphrase: &str,
+) -> Result<Vec<u8>, KeystoreError> {
+    check_header(magic, file)?;
+    if file.len() < HEADER_LEN + 16 {
+        return Err(KeystoreError::Corrupted);
+    }
+
+    let (header, ciphertext) = file.split_at(HEADER_LEN);
+    let cost = |i: usize| {
+        let start = magic.len() + 1 + 4 * i;
+        u32::from_le_bytes(header[start..start + 4].try_into().unwrap())
+    };
+    if (0..3).any(|i| cost(i) > MAX_COSTS[i]) {
+        return Err(KeystoreError::Corrupted);
+    }
+    let params =
+        Params::new(cost(0), cost(1), cost(2), Some(32)).map_err(|_| KeystoreError::Corrupted)?;
+    let salt = &header[HEADER_LEN - NONCE_LEN - SALT_LEN..HEADER_LEN - NONCE_LEN];
+    let nonce = &header[HEADER_LEN - NONCE_LEN..];
+
+    cipher(passphrase, salt, params)?
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| KeystoreError::WrongPassphrase)
+}
+
+fn cipher(passphrase: &str, salt: &[u8], params: Params) -> anyhow::Result<ChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::new(Algorithm::Argon2id, argon2::Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|error| anyhow::anyhow!("Failed to derive key: {error}"))?;
+
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters, so the tests don't spend their time stretching passphrases.
+    fn test_params() -> Params {
+        Params::new(256, 1, 1, Some(32)).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys").join("node.key");
+        let key_pair = KeyPair::generate();
+
+        key_pair
+            .save_encrypted_with(&path, "hunter2", test_params())
+            .unwrap();
+        let loaded = KeyPair::load_encrypted(&path, "hunter2").unwrap();
+        assert_eq!(loaded.secret_key, key_pair.secret_key);
+        assert_eq!(loaded.public_key, key_pair.public_key);
+
+        assert!(matches!(
+            KeyPair::load_encrypted(&path, "hunter3"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(&path).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_serializable_secret_key() {
+        let key_pair = KeyPair::generate();
+        let secret = SerializableSecretKey::from_key_pair(&key_pair);
+
+        let json = serde_json::to_string(&secret).unwrap();
+        assert_eq!(
+            json,
+            format!("\"{}\"", key_pair.secret_key.display_secret())
+        );
+        assert!(!format!("{secret:?}").contains(&key_pair.secret_key.display_secret().to_string()));
+
+        let loaded = serde_json::from_str::<SerializableSecretKey>(&json)
+            .unwrap()
+            .to_key_pair()
+            .unwrap();
+        assert_eq!(loaded.public_key, key_pair.public_key);
+    }
+
+    #[test]
+    fn test_corrupted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.key");
+        KeyPair::generate()
+            .save_encrypted_with(&path, "hunter2", test_params())
+            .unwrap();
+        let file = fs::read(&path).unwrap();
+
+        let load = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            KeyPair::load_encrypted(&path, "hunter2")
+        };
+
+        let mut flipped = file.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            load(&flipped),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+
+        // the header is authenticated along with the secret
+        let mut salted = file.clone();
+        salted[HEADER_LEN - NONCE_LEN - 1] ^= 1;
+        assert!(matches!(load(&salted), Err(KeystoreError::WrongPassphrase)));
+
+        assert!(matches!(
+            load(&file[..file.len() - 1]),
+            Err(KeystoreError::Corrupted)
+        ));
+        assert!(matches!(load(b"TC"), Err(KeystoreError::NotAKeyFile)));
+
+        // about 256 GiB of memory, refused before argon2 tries to allocate it
+        let mut costly = file.clone();
+        costly[MAGIC.len() + 1..MAGIC.len() + 5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(load(&costly), Err(KeystoreError::Corrupted)));
+
+        let mut versioned = file.clone();
+        versioned[MAGIC.len()] = 2;
+        assert!(matches!(
+            load(&versioned),
+            Err(KeystoreError::UnsupportedVersion(2))
+        ));
+    }
+}
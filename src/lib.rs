@@ -1,9 +1,77 @@
+#[cfg(feature = "std-node")]
+pub mod address_history;
+#[cfg(feature = "std-node")]
+pub mod archive;
+#[cfg(feature = "std-node")]
+pub mod bandwidth;
+#[cfg(feature = "std-node")]
+pub mod bench;
 pub mod block;
+#[cfg(feature = "std-node")]
+pub mod block_download;
+#[cfg(feature = "std-node")]
 pub mod block_manager;
+#[cfg(feature = "std-node")]
+pub mod bootstrap;
 pub mod chain;
+#[cfg(feature = "std-node")]
+pub mod config;
+#[cfg(feature = "std-node")]
+pub mod consistency;
 pub mod constants;
 pub mod crypto;
+#[doc(hidden)]
+pub mod fixtures;
+#[doc(hidden)]
+pub mod fuzz;
+#[cfg(feature = "std-node")]
+pub mod handshake;
+pub mod hd;
+pub mod header_chain;
+#[cfg(feature = "std-node")]
+pub mod inventory;
+#[cfg(feature = "std-node")]
+pub mod keystore;
+#[cfg(feature = "std-node")]
 pub mod mem_pool;
+#[cfg(feature = "std-node")]
+pub mod metrics;
+#[cfg(feature = "std-node")]
+pub mod miner;
+pub mod mnemonic;
+#[cfg(feature = "std-node")]
 pub mod node;
+pub mod outcome;
+#[cfg(feature = "std-node")]
+pub mod p2p;
+pub mod params;
+#[cfg(feature = "std-node")]
+pub mod peer_book;
+#[cfg(feature = "std-node")]
+pub mod rate_limit;
+#[cfg(feature = "std-node")]
+pub mod rest;
+#[cfg(feature = "std-node")]
+pub mod rpc;
+#[cfg(feature = "std-node")]
+pub mod seen_cache;
+#[cfg(feature = "std-node")]
+pub mod shared_node;
+pub mod signed_message;
+#[cfg(any(feature = "test-support", all(test, feature = "std-node")))]
+pub mod sim;
+#[cfg(feature = "std-node")]
+pub mod snapshot;
+pub mod spv;
 pub mod transaction;
+#[cfg(feature = "std-node")]
+pub mod tx_index;
 pub mod utxo_set;
+#[cfg(feature = "std-node")]
+pub mod vanity;
+#[cfg(feature = "std-node")]
+pub mod wallet;
+#[cfg(feature = "std-node")]
+pub mod wire;
+#[cfg(feature = "std-node")]
+pub mod ws;
@@ -1,8 +1,56 @@
+mod output;
+
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
 use base64::Engine;
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ripemd::Ripemd160;
+use secp256k1::PublicKey;
+use serde::Serialize;
 use sha2::{Digest, Sha256};
 use strum_macros::Display;
-use tiny_crypto::crypto::KeyPair;
+use tiny_crypto::{
+    archive::ImportSummary,
+    bench,
+    block::{Block, BlockHeader, TransactionProof},
+    block_manager::BlockManager,
+    chain::{Blockchain, BlockchainNode, ValidationFailure},
+    config::NodeConfig,
+    constants::{
+        BENCH_MERKLE_TRANSACTIONS, BLOCKS_PER_REWARD_HALVING, CONFIRMATION_POLL_INTERVAL_MILLIS,
+        DEFAULT_BENCH_BLOCK_TIME_SECS, GENESIS_BLOCK_REWARD,
+    },
+    crypto::{Address, Hash, HashExt, KeyPair, PublicKeyOnly, from_hex, serde_hex, to_hex},
+    hd::{DerivationPath, ExtendedPrivKey, HdError},
+    keystore::{KeystoreError, SerializableSecretKey},
+    mem_pool::MemPoolStats,
+    mnemonic::Mnemonic,
+    node::{Node, NodeState, build_payment},
+    outcome::HandleOutcome,
+    p2p::P2pService,
+    params::{ConsensusMode, GenesisAllocation, Network, NetworkParams},
+    rpc::{REJECTED, RpcClient, RpcClientError, RpcServer},
+    shared_node::SharedNode,
+    signed_message::{MessageSignature, message_digest},
+    transaction::{
+        Transaction, TransactionInput, TransactionOutput, TransactionOutputReference, TxId,
+    },
+    tx_index::TxIndex,
+    vanity::VanityOptions,
+    wallet::{Wallet, WalletOutput},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::output::{OutputFormat, Render, Verdict, render};
 
 #[derive(Parser)]
 #[command(name = "tiny-crypto")]
@@ -10,6 +58,10 @@ use tiny_crypto::crypto::KeyPair;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// How results are printed on stdout, text unless a command says otherwise
+    #[arg(long, global = true, alias = "format")]
+    output: Option<OutputFormat>,
 }
 
 #[derive(Default, Debug, Clone, Copy, ValueEnum, Display)]
@@ -21,45 +73,3235 @@ enum ByteDisplay {
     Base64,
 }
 
+#[derive(Default, Debug, Clone, Copy, ValueEnum, Display)]
+enum HashAlgo {
+    #[default]
+    #[strum(to_string = "sha256")]
+    Sha256,
+    /// SHA-256 twice, as block and transaction ids are
+    #[strum(to_string = "sha256d")]
+    Sha256d,
+    /// RIPEMD-160 of SHA-256, as addresses are
+    #[strum(to_string = "hash160")]
+    Hash160,
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    /// Hash a string using SHA-256
+    /// Hash a string, a file, or stdin
     Hash {
         /// The string to hash
-        #[arg(short, long)]
-        input: String,
+        #[arg(short, long, conflicts_with = "file")]
+        input: Option<String>,
+
+        /// File whose contents are hashed
+        #[arg(long)]
+        file: Option<PathBuf>,
 
-        /// Output format (hex, base64)
+        #[arg(long, default_value_t = HashAlgo::Sha256)]
+        algo: HashAlgo,
+
+        /// How the hash is written (hex, base64)
         #[arg(short, long, default_value_t = ByteDisplay::Hex)]
-        format: ByteDisplay,
+        encoding: ByteDisplay,
+    },
+    /// Generate or derive a keypair, printing it or saving its secret to a key file
+    #[command(visible_alias = "keygen")]
+    GenerateKeyPair {
+        /// Network the WIF-encoded secret key is for
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        /// Derive the keypair from a new mnemonic phrase, printed for backing up
+        #[arg(long, conflicts_with = "recover")]
+        mnemonic: bool,
+
+        /// Words in the new mnemonic phrase (12, 15, 18, 21, or 24)
+        #[arg(long, default_value_t = 12, requires = "mnemonic")]
+        words: usize,
+
+        /// Recover the keypair from an existing mnemonic phrase
+        #[arg(long, conflicts_with = "seed_hex")]
+        recover: Option<String>,
+
+        /// Derive the keypair from a 32-byte seed, in hex
+        #[arg(long, conflicts_with = "mnemonic")]
+        seed_hex: Option<String>,
+
+        /// Passphrase the mnemonic is extended with
+        #[arg(long, default_value = "")]
+        mnemonic_passphrase: String,
+
+        /// Generate keypairs until one's address starts with this prefix
+        #[arg(long, conflicts_with_all = ["mnemonic", "recover", "seed_hex"])]
+        vanity: Option<String>,
+
+        /// Match the vanity prefix regardless of case
+        #[arg(long, requires = "vanity")]
+        ignore_case: bool,
+
+        /// Threads searching for the vanity address, all cores by default
+        #[arg(long, requires = "vanity")]
+        threads: Option<usize>,
+
+        /// Save the secret key to this file, encrypted under a passphrase, instead of
+        /// printing it
+        #[arg(long)]
+        out: Option<PathBuf>,
+
+        /// Passphrase for the key file, prompted for when not given
+        #[arg(
+            long,
+            env = "TINY_CRYPTO_PASSPHRASE",
+            hide_env_values = true,
+            requires = "out"
+        )]
+        passphrase: Option<String>,
+
+        /// Save the key file unencrypted, for development only
+        #[arg(long, requires = "out", conflicts_with = "passphrase")]
+        insecure_plaintext: bool,
+
+        /// Overwrite an existing key file
+        #[arg(long, requires = "out")]
+        force: bool,
+    },
+    /// Derive an address from a public key, or check one and show what it encodes
+    Address {
+        /// Compressed or uncompressed public key, in hex
+        #[arg(
+            long,
+            required_unless_present = "validate",
+            conflicts_with = "validate"
+        )]
+        from_pubkey: Option<String>,
+
+        /// Address to decode, failing if it's invalid
+        #[arg(long)]
+        validate: Option<String>,
+
+        /// Network the derived address is for, mainnet by default. When validating,
+        /// addresses for any other network are rejected
+        #[arg(long)]
+        network: Option<Network>,
+    },
+    /// Inspect a secret key, or re-encode it in another form
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+    /// Show the keypair a WIF-encoded secret key decodes to
+    ImportWif {
+        wif: String,
+
+        /// Refuse keys for any other network
+        #[arg(long)]
+        network: Option<Network>,
+    },
+    /// Sign a message, proving control of the key's address. The message is read from
+    /// stdin unless given with --message or --file
+    #[command(visible_alias = "sign")]
+    SignMessage {
+        #[arg(long, conflicts_with = "file")]
+        message: Option<String>,
+
+        /// File whose contents, as bytes, are the message
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// WIF-encoded secret key to sign with
+        #[arg(
+            long,
+            env = "TINY_CRYPTO_WIF",
+            hide_env_values = true,
+            required_unless_present = "key",
+            conflicts_with = "key"
+        )]
+        wif: Option<String>,
+
+        /// Key file to sign with, as written by keygen --out
+        #[arg(long)]
+        key: Option<PathBuf>,
+
+        /// Passphrase for an encrypted key file, prompted for when not given
+        #[arg(
+            long,
+            env = "TINY_CRYPTO_PASSPHRASE",
+            hide_env_values = true,
+            requires = "key"
+        )]
+        passphrase: Option<String>,
+
+        /// Network the key and address are for
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        /// How the signature is written (hex, base64)
+        #[arg(long, default_value_t = ByteDisplay::Base64)]
+        encoding: ByteDisplay,
+    },
+    /// Check a message signature, printing "valid" or "invalid" and exiting 0 or 1. The
+    /// message is read from stdin unless given with --message or --file
+    #[command(visible_alias = "verify")]
+    VerifyMessage {
+        /// The signature, in base64 or hex
+        #[arg(long)]
+        signature: String,
+
+        #[arg(long, conflicts_with = "file")]
+        message: Option<String>,
+
+        /// File whose contents, as bytes, are the message
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// Public key that should have signed, in hex
+        #[arg(long, required_unless_present = "address", conflicts_with = "address")]
+        pubkey: Option<String>,
+
+        /// Address whose key should have signed, checked by recovering the key
+        #[arg(long)]
+        address: Option<String>,
+
+        /// Reject addresses for any other network
+        #[arg(long, requires = "address")]
+        network: Option<Network>,
+    },
+    /// Mine blocks on the chain in a data directory, without running a node
+    Mine {
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        /// Key file the block rewards are paid to, as written by keygen --out
+        #[arg(long)]
+        key: PathBuf,
+
+        /// Passphrase for an encrypted key file, prompted for when not given
+        #[arg(long, env = "TINY_CRYPTO_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+
+        /// Blocks to mine
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+
+        /// Threads searching for each nonce, all cores by default
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Have a running regtest node mine blocks paying an address, instantly
+    Generate {
+        #[command(flatten)]
+        rpc: RpcArgs,
+
+        /// Address the block rewards are paid to
+        #[arg(long)]
+        address: String,
+
+        /// Blocks to mine
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+    },
+    /// Show an address's balance on the chain stored in a data directory
+    Balance {
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        #[arg(long, required_unless_present = "key", conflicts_with = "key")]
+        address: Option<String>,
+
+        /// Key file whose address is looked up
+        #[arg(long)]
+        key: Option<PathBuf>,
+
+        /// Passphrase for an encrypted key file, prompted for when not given
+        #[arg(
+            long,
+            env = "TINY_CRYPTO_PASSPHRASE",
+            hide_env_values = true,
+            requires = "key"
+        )]
+        passphrase: Option<String>,
+
+        /// List each unspent output too
+        #[arg(long)]
+        utxos: bool,
+    },
+    /// Pay an address from a key's outputs on a running node, reached over JSON-RPC. The
+    /// payment is built and signed here, so the key never leaves this machine
+    Send {
+        #[command(flatten)]
+        rpc: RpcArgs,
+
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        /// Key file whose outputs are spent and which the change is paid to
+        #[arg(long)]
+        key: PathBuf,
+
+        /// Passphrase for an encrypted key file, prompted for when not given
+        #[arg(long, env = "TINY_CRYPTO_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+
+        #[arg(long)]
+        to: String,
+
+        #[arg(long)]
+        amount: u64,
+
+        /// Fee paid to the miner, the node's minimum fee by default
+        #[arg(long)]
+        fee: Option<u64>,
+
+        /// Wait until the transaction has this many confirmations
+        #[arg(long)]
+        wait_confirm: Option<u32>,
+
+        /// Seconds to wait for the confirmations before giving up
+        #[arg(long, default_value_t = 600, requires = "wait_confirm")]
+        timeout: u64,
+    },
+    /// Inspect the chain stored in a data directory
+    Chain {
+        #[command(subcommand)]
+        command: ChainCommands,
+    },
+    /// Validate every block stored in a data directory as if it were new, reporting the
+    /// first invalid one. Only reads the data directory
+    ValidateChain {
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        /// First block to validate, those below it are trusted
+        #[arg(long, default_value_t = 1)]
+        from_height: u32,
+
+        /// Check the coins in the UTXO set add up to the subsidies of the blocks
+        #[arg(long)]
+        check_utxo: bool,
+    },
+    /// Inspect blocks stored in a data directory
+    Block {
+        #[command(subcommand)]
+        command: BlockCommands,
+    },
+    /// Move the chain stored in a data directory to another through an archive file
+    Blocks {
+        #[command(subcommand)]
+        command: BlocksCommands,
+    },
+    /// Build and inspect transactions
+    Tx {
+        #[command(subcommand)]
+        command: TxCommands,
+    },
+    /// Run and inspect a node
+    Node {
+        #[command(subcommand)]
+        command: NodeCommands,
+    },
+    /// Write and check the TOML config `node run --config` reads
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Inspect a running node's pending transactions
+    Mempool {
+        #[command(flatten)]
+        rpc: RpcArgs,
+
+        #[command(subcommand)]
+        command: MempoolCommands,
+    },
+    /// Prove a transaction is in a block on the chain stored in a data directory, or check
+    /// such a proof with `merkle-proof verify`
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    MerkleProof {
+        #[command(subcommand)]
+        command: Option<MerkleProofCommands>,
+
+        #[arg(long, required = true)]
+        data_dir: Option<PathBuf>,
+
+        /// Txid of a transaction confirmed on the active chain
+        #[arg(long, required = true)]
+        tx: Option<String>,
+    },
+    /// Create and use an HD wallet kept in an encrypted wallet file
+    Wallet {
+        #[command(subcommand)]
+        command: WalletCommands,
+    },
+    /// Create and inspect the params and genesis block of a private network
+    Genesis {
+        #[command(subcommand)]
+        command: GenesisCommands,
+    },
+    /// Measure this machine's hash rate and validation throughput, and suggest a
+    /// difficulty for a block time
+    Bench {
+        /// Seconds each measurement runs for, after a warmup
+        #[arg(long, default_value_t = 2.0)]
+        secs: f64,
+
+        /// Threads hashing headers in the parallel measurement, all cores by default
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Seconds between blocks the suggested difficulty is for
+        #[arg(long, default_value_t = DEFAULT_BENCH_BLOCK_TIME_SECS)]
+        block_time: u64,
     },
-    GenerateKeyPair,
 }
 
-fn hash_string(input: &str, format: ByteDisplay) {
-    let hash = Sha256::digest(input.as_bytes());
+/// How to reach a running node's JSON-RPC server.
+#[derive(Args)]
+struct RpcArgs {
+    /// URL of the node's JSON-RPC server
+    #[arg(long)]
+    rpc: String,
+
+    /// Token the server requires, if its config sets one
+    #[arg(long, env = "TINY_CRYPTO_RPC_TOKEN", hide_env_values = true)]
+    rpc_token: Option<String>,
+}
+
+impl RpcArgs {
+    fn client(self) -> Result<RpcClient> {
+        let client = RpcClient::new(self.rpc)?;
+        Ok(match self.rpc_token {
+            Some(token) => client.with_auth_token(token),
+            None => client,
+        })
+    }
+}
+
+/// A node's config: a config file's, or the defaults, with these options overriding it.
+#[derive(Args)]
+struct NodeArgs {
+    /// Directory holding the chain, mempool, and peer data
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+
+    /// TOML node config, which the other options override
+    #[arg(long)]
+    config: Option<PathBuf>,
 
-    match format {
-        ByteDisplay::Hex => println!("Hash (hex): {hash:#32x}"),
-        ByteDisplay::Base64 => {
-            let bytes = hash.to_vec();
-            let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
-            println!("Hash (base64): {encoded}");
+    /// Network whose consensus params are used, mainnet unless the config says otherwise
+    #[arg(long)]
+    network: Option<Network>,
+
+    /// Where the node's keypair is kept encrypted, `keypair` in the data dir by default
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+
+    /// Multiaddr to listen for peers on
+    #[arg(long)]
+    listen: Option<String>,
+
+    /// Multiaddr of a peer to connect to and keep redialing, may be repeated
+    #[arg(long, visible_alias = "peer")]
+    bootstrap: Vec<String>,
+
+    /// Most peers to dial discovered peers up to
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// Mine blocks on the node's tip, paying its keypair
+    #[arg(long)]
+    mine: bool,
+
+    /// Address to serve JSON-RPC on, such as 127.0.0.1:8332
+    #[arg(long)]
+    rpc_listen: Option<String>,
+
+    /// Bearer token RPC requests must carry
+    #[arg(long)]
+    rpc_auth_token: Option<String>,
+}
+
+impl NodeArgs {
+    fn config(self) -> Result<NodeConfig> {
+        let mut config = match self.config {
+            Some(path) => NodeConfig::load(&path)?,
+            None => NodeConfig::default(),
+        };
+        if let Some(data_dir) = self.data_dir {
+            config.data_dir = data_dir;
+        }
+        if let Some(network) = self.network {
+            config.network = NetworkParams::for_network(network);
+        }
+        if self.key_file.is_some() {
+            config.key_file = self.key_file;
+        }
+        if let Some(listen) = self.listen {
+            config.p2p.listen_addr = listen;
         }
+        config.p2p.bootstrap_peers.extend(self.bootstrap);
+        if let Some(max_connections) = self.max_connections {
+            config.p2p.max_connections = max_connections;
+        }
+        config.mining.enabled |= self.mine;
+        if self.rpc_listen.is_some() {
+            config.rpc.listen_addr = self.rpc_listen;
+        }
+        if self.rpc_auth_token.is_some() {
+            config.rpc.auth_token = self.rpc_auth_token;
+        }
+        Ok(config)
     }
 }
 
-fn main() {
-    let cli = Cli::parse();
+/// A secret key given in one of its forms.
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct KeySource {
+    #[arg(long)]
+    wif: Option<String>,
 
-    match cli.command {
-        Commands::Hash { input, format } => {
-            hash_string(&input, format);
+    /// 32-byte secret key, in hex
+    #[arg(long)]
+    secret_hex: Option<String>,
+
+    /// Key file as written by keygen --out
+    #[arg(long)]
+    keyfile: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct KeySourceArgs {
+    #[command(flatten)]
+    source: KeySource,
+
+    /// Passphrase for an encrypted key file, prompted for when not given
+    #[arg(long, env = "TINY_CRYPTO_PASSPHRASE", hide_env_values = true)]
+    passphrase: Option<String>,
+
+    /// Network the WIF is for, the WIF's own or mainnet by default
+    #[arg(long)]
+    network: Option<Network>,
+
+    /// Write the secret key to stdout, where it may end up in logs or shell history
+    #[arg(long)]
+    yes_print_secrets: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Display, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum KeyFormat {
+    #[strum(to_string = "wif")]
+    Wif,
+    #[strum(to_string = "hex")]
+    Hex,
+    #[strum(to_string = "keyfile")]
+    Keyfile,
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// Show a secret key's public key and addresses, and with --yes-print-secrets its WIF
+    Inspect {
+        #[command(flatten)]
+        key: KeySourceArgs,
+    },
+    /// Re-encode a secret key as WIF, hex, or a key file
+    Convert {
+        #[command(flatten)]
+        key: KeySourceArgs,
+
+        #[arg(long)]
+        to: KeyFormat,
+
+        /// Key file to write, for --to keyfile
+        #[arg(long, required_if_eq("to", "keyfile"))]
+        out: Option<PathBuf>,
+
+        /// Passphrase the new key file is encrypted under, prompted for when not given
+        #[arg(long, requires = "out")]
+        new_passphrase: Option<String>,
+
+        /// Save the key file unencrypted, for development only
+        #[arg(long, requires = "out", conflicts_with = "new_passphrase")]
+        insecure_plaintext: bool,
+
+        /// Overwrite an existing key file
+        #[arg(long, requires = "out")]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ChainCommands {
+    /// Show the active chain's tip, work, and block times, and what's stored. Only reads
+    /// the data directory, so it can be pointed at one a node is using
+    Info {
+        #[arg(long)]
+        data_dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum BlockCommands {
+    /// Show a block by hash, or by height on the active chain
+    Show {
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        #[arg(long, required_unless_present = "height", conflicts_with = "height")]
+        hash: Option<String>,
+
+        #[arg(long)]
+        height: Option<u32>,
+
+        /// Show only the header
+        #[arg(long)]
+        header_only: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum BlocksCommands {
+    /// Write the active chain's blocks to an archive, in height order. Only reads the data
+    /// directory, so it can be pointed at one a node is using
+    Export {
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Last block to export, the tip by default
+        #[arg(long)]
+        to_height: Option<u32>,
+
+        /// Overwrite an existing archive
+        #[arg(long)]
+        force: bool,
+    },
+    /// Validate and store an archive's blocks as if a peer had sent them
+    Import {
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        #[arg(long = "in")]
+        input: PathBuf,
+
+        /// Validate the archive against the network's genesis block without touching the
+        /// data directory
+        #[arg(long)]
+        verify_only: bool,
+
+        /// Keep going past blocks that fail validation, and exit successfully
+        #[arg(long)]
+        continue_on_error: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TxCommands {
+    /// Build and sign a payment from a key's outputs, printing it without broadcasting it,
+    /// as JSON unless --output text is given
+    Create {
+        /// Data directory holding the chain the outputs are spent from
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        /// Key file whose outputs are spent and which the change is paid to
+        #[arg(long)]
+        key: PathBuf,
+
+        /// Passphrase for an encrypted key file, prompted for when not given
+        #[arg(long, env = "TINY_CRYPTO_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+
+        #[arg(long)]
+        to: String,
+
+        #[arg(long)]
+        amount: u64,
+
+        /// Fee paid to the miner, the mempool's minimum fee by default
+        #[arg(long)]
+        fee: Option<u64>,
+    },
+    /// Show what a raw transaction spends and pays, and whether its signature verifies.
+    /// It's read from stdin unless given as an argument or with --file
+    Decode {
+        #[arg(conflicts_with = "file")]
+        hex: Option<String>,
+
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// Data directory whose UTXO set the spent output's value and the fee are
+        /// looked up in
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+
+        #[arg(long, default_value_t = Network::Mainnet, requires = "data_dir")]
+        network: Network,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeCommands {
+    /// Run a node until interrupted with ctrl-c
+    Run {
+        #[command(flatten)]
+        node: NodeArgs,
+
+        /// Passphrase the node's keypair is kept encrypted under in the data dir. Without
+        /// one a throwaway keypair is generated
+        #[arg(long, env = "TINY_CRYPTO_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Write a config file with every setting at its default, commented
+    Init {
+        /// Where to write the config
+        #[arg(long, default_value = "node.toml")]
+        out: PathBuf,
+
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the config `node run` would use with the same options
+    Show {
+        #[command(flatten)]
+        node: NodeArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum MempoolCommands {
+    /// List the pending transactions, highest fee rate first
+    List,
+    /// Show a pending transaction and those it spends from or that spend from it
+    Get { txid: String },
+    /// Show the pool's size and how its fee rates are spread
+    Stats,
+}
+
+#[derive(Subcommand)]
+enum MerkleProofCommands {
+    /// Check a proof against a block's merkle root and transaction count alone, printing
+    /// "valid" or "invalid" and exiting 0 or 1
+    Verify {
+        /// The block's merkle root
+        #[arg(long)]
+        root: String,
+
+        #[arg(long)]
+        txid: String,
+
+        /// The proof's hex, as `merkle-proof` prints it
+        #[arg(long)]
+        proof: String,
+
+        /// Transactions in the block
+        #[arg(long)]
+        count: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum GenesisCommands {
+    /// Mine a new genesis block, writing the network's params to a file `node run
+    /// --config` reads and the block itself next to it
+    Init {
+        /// The params file, the genesis block is written beside it as .genesis.json
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Network whose address version the chain uses
+        #[arg(long, default_value_t = Network::Testnet)]
+        network: Network,
+
+        /// Block reward until the first halving
+        #[arg(long, default_value_t = GENESIS_BLOCK_REWARD as u64)]
+        reward: u64,
+
+        /// Blocks between reward halvings
+        #[arg(
+            long,
+            default_value_t = BLOCKS_PER_REWARD_HALVING,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        halving: u32,
+
+        /// Reward every block keeps once halvings would take it lower
+        #[arg(long)]
+        tail_emission: Option<u64>,
+
+        /// Zero bytes the genesis block's hash, and the blocks after it, start with
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(..32))]
+        difficulty: u8,
+
+        /// Text the genesis block commits to, telling the network apart from others
+        #[arg(long)]
+        message: String,
+
+        /// ADDRESS:AMOUNT the genesis block pays on top of its reward, may be repeated
+        #[arg(long)]
+        premine: Vec<String>,
+
+        /// Public key, as hex, of a validator that signs blocks rather than mining them,
+        /// may be repeated. Blocks then have difficulty 0, whatever --difficulty says
+        #[arg(long)]
+        authority: Vec<String>,
+
+        /// Threads mining the genesis block, all cores by default
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Overwrite existing files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the genesis block a params file describes
+    Inspect {
+        /// A params file from `genesis init`, or any node config
+        params: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct WalletFileArgs {
+    /// The wallet file
+    #[arg(long)]
+    wallet: PathBuf,
+
+    /// Passphrase the wallet file is encrypted under, prompted for when not given
+    #[arg(long, env = "TINY_CRYPTO_PASSPHRASE", hide_env_values = true)]
+    passphrase: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum WalletCommands {
+    /// Create a wallet file with a new master key, or one from a mnemonic phrase
+    Create {
+        #[command(flatten)]
+        file: WalletFileArgs,
+
+        #[arg(long, default_value_t = Network::Mainnet)]
+        network: Network,
+
+        /// Derive the master key from a new mnemonic phrase, printed for backing up
+        #[arg(long, conflicts_with = "recover")]
+        mnemonic: bool,
+
+        /// Words in the new mnemonic phrase (12, 15, 18, 21, or 24)
+        #[arg(long, default_value_t = 12, requires = "mnemonic")]
+        words: usize,
+
+        /// Recover the wallet from an existing mnemonic phrase
+        #[arg(long)]
+        recover: Option<String>,
+
+        /// Passphrase the mnemonic is extended with
+        #[arg(long, default_value = "")]
+        mnemonic_passphrase: String,
+
+        /// Overwrite an existing wallet file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the wallet's addresses with their derivation paths, and its balance as of the
+    /// last rescan
+    #[command(visible_alias = "unlock")]
+    Info {
+        #[command(flatten)]
+        file: WalletFileArgs,
+    },
+    /// Derive the next receive address
+    NewAddress {
+        #[command(flatten)]
+        file: WalletFileArgs,
+
+        /// Label kept for the address in the wallet file
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Find the wallet's outputs in the UTXO set of the chain in a data directory,
+    /// replacing the ones the wallet file has cached
+    Rescan {
+        #[command(flatten)]
+        file: WalletFileArgs,
+
+        #[arg(long)]
+        data_dir: PathBuf,
+    },
+}
+
+/// Hashes everything `reader` yields without holding it in memory. Only the first
+/// SHA-256 sees the input, so sha256d and hash160 finish from its digest, as
+/// `crypto::sha256d` and `crypto::hash160` do.
+fn hash_reader(mut reader: impl Read, algo: HashAlgo) -> Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+    let digest = hasher.finalize();
+
+    Ok(match algo {
+        HashAlgo::Sha256 => digest.to_vec(),
+        HashAlgo::Sha256d => Sha256::digest(digest).to_vec(),
+        HashAlgo::Hash160 => Ripemd160::digest(digest).to_vec(),
+    })
+}
+
+#[derive(Serialize)]
+struct HashOutput {
+    algo: String,
+    encoding: String,
+    hash: String,
+}
+
+impl Render for HashOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Hash ({}): {}", self.encoding, self.hash)
+    }
+}
+
+fn hash_command(
+    input: Option<String>,
+    file: Option<PathBuf>,
+    algo: HashAlgo,
+    encoding: ByteDisplay,
+) -> Result<HashOutput> {
+    let hash = match (input, file) {
+        (Some(input), _) => hash_reader(input.as_bytes(), algo)?,
+        (None, Some(file)) => hash_reader(std::fs::File::open(file)?, algo)?,
+        (None, None) => hash_reader(std::io::stdin().lock(), algo)?,
+    };
+
+    Ok(HashOutput {
+        algo: algo.to_string(),
+        encoding: encoding.to_string(),
+        hash: match encoding {
+            ByteDisplay::Hex => hex::encode(hash),
+            ByteDisplay::Base64 => base64::engine::general_purpose::STANDARD.encode(hash),
+        },
+    })
+}
+
+fn vanity_key_pair(
+    prefix: &str,
+    network: Network,
+    case_insensitive: bool,
+    threads: Option<usize>,
+) -> Result<KeyPair> {
+    let threads = match threads {
+        Some(threads) => threads,
+        None => std::thread::available_parallelism()?.get(),
+    };
+    let progress = |attempts: u64, rate: f64| {
+        eprintln!("{attempts} keys tried, {rate:.0}/s");
+    };
+
+    Address::vanity_with(
+        prefix,
+        threads,
+        &AtomicBool::new(false),
+        VanityOptions {
+            network,
+            case_insensitive,
+            progress: Some(&progress),
+            ..VanityOptions::default()
+        },
+    )
+}
+
+/// What `keygen` prints. The secret only appears when it isn't saved to a key file.
+#[derive(Serialize)]
+struct KeygenOutput {
+    address: Address,
+    public_key: PublicKeyOnly,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mnemonic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_key: Option<SerializableSecretKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wif: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_file: Option<PathBuf>,
+    #[serde(skip)]
+    key_pair: KeyPair,
+}
+
+impl Render for KeygenOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        if let Some(mnemonic) = &self.mnemonic {
+            writeln!(out, "Mnemonic: {mnemonic}")?;
+        }
+        writeln!(out, "Public Key: 0x{}", self.key_pair.public_key)?;
+        if let Some(wif) = &self.wif {
+            let secret_key = self.key_pair.secret_key.display_secret();
+            writeln!(out, "Secret Key: 0x{secret_key}")?;
+            writeln!(out, "WIF: {wif}")?;
+        }
+        if let Some(key_file) = &self.key_file {
+            writeln!(out, "Key File: {}", key_file.display())?;
+        }
+        writeln!(out, "Address: {}", self.address)
+    }
+}
+
+#[derive(Serialize)]
+struct AddressOutput {
+    address: Address,
+    version: u8,
+    payload: String,
+    networks: Vec<Network>,
+}
+
+impl Render for AddressOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let networks = self.networks.iter().map(Network::to_string);
+        writeln!(out, "Address: {}", self.address)?;
+        writeln!(out, "Version: 0x{:02x}", self.version)?;
+        writeln!(out, "Payload: {}", self.payload)?;
+        writeln!(out, "Networks: {}", networks.collect::<Vec<_>>().join(", "))
+    }
+}
+
+fn address_command(
+    from_pubkey: Option<String>,
+    validate: Option<String>,
+    network: Option<Network>,
+) -> Result<AddressOutput> {
+    let address = match (from_pubkey, validate) {
+        (Some(public_key), _) => {
+            let public_key = PublicKey::from_slice(&from_hex(&public_key)?)?;
+            Address::from_public_key_for(&public_key, network.unwrap_or(Network::Mainnet))
         }
-        Commands::GenerateKeyPair => {
-            let key_pair = KeyPair::generate();
-            println!("Public Key: 0x{}", key_pair.public_key);
-            println!("Secret Key: 0x{}", key_pair.secret_key.display_secret());
+        (None, Some(address)) => match network {
+            Some(network) => Address::parse_for(&address, network)?,
+            None => address.parse()?,
+        },
+        (None, None) => anyhow::bail!("Pass --from-pubkey or --validate"),
+    };
+
+    Ok(AddressOutput {
+        version: address.version(),
+        payload: hex::encode(address.payload().0),
+        networks: [Network::Mainnet, Network::Testnet, Network::Regtest]
+            .into_iter()
+            .filter(|network| address.is_for(*network))
+            .collect(),
+        address,
+    })
+}
+
+/// The input given inline, or the contents of `file`, or else all of stdin.
+fn read_input(inline: Option<String>, file: Option<PathBuf>) -> Result<Vec<u8>> {
+    match (inline, file) {
+        (Some(inline), _) => Ok(inline.into_bytes()),
+        (None, Some(file)) => Ok(std::fs::read(file)?),
+        (None, None) => {
+            let mut input = Vec::new();
+            std::io::stdin().read_to_end(&mut input)?;
+            Ok(input)
         }
     }
 }
+
+/// Loads a key file saved by keygen, prompting for the passphrase if it's encrypted and
+/// none was given.
+fn load_key_file(path: &Path, passphrase: Option<String>) -> Result<KeyPair> {
+    match KeyPair::load_plaintext(path) {
+        Err(KeystoreError::NotAKeyFile) => {}
+        loaded => return Ok(loaded?),
+    }
+
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))?,
+    };
+    Ok(KeyPair::load_encrypted(path, &passphrase)?)
+}
+
+#[derive(Serialize)]
+struct SignOutput {
+    address: Address,
+    #[serde(with = "serde_hex")]
+    digest: Hash,
+    signature: String,
+}
+
+impl Render for SignOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Address: {}", self.address)?;
+        writeln!(out, "Signature: {}", self.signature)
+    }
+}
+
+/// Loads the key `args` give, and the network its WIF is for.
+fn load_key_source(args: &KeySourceArgs) -> Result<(KeyPair, Network)> {
+    let source = &args.source;
+    let (key_pair, wif_network) = match (&source.wif, &source.secret_hex, &source.keyfile) {
+        (Some(wif), _, _) => {
+            let key_pair = KeyPair::from_wif(wif)
+                .map_err(|error| anyhow::anyhow!("Invalid --wif: {error}"))?;
+            // testnet and regtest share a version byte, so it's reported as testnet
+            let network = match KeyPair::from_wif_for(wif, Network::Mainnet) {
+                Ok(_) => Network::Mainnet,
+                Err(_) => Network::Testnet,
+            };
+            (key_pair, Some(network))
+        }
+        (None, Some(hex), _) => (
+            KeyPair::from_secret_hex(hex)
+                .map_err(|error| anyhow::anyhow!("Invalid --secret-hex: {error}"))?,
+            None,
+        ),
+        (None, None, Some(path)) => (
+            load_key_file(path, args.passphrase.clone()).map_err(|error| {
+                anyhow::anyhow!("Invalid --keyfile {}: {error}", path.display())
+            })?,
+            None,
+        ),
+        (None, None, None) => anyhow::bail!("Pass --wif, --secret-hex, or --keyfile"),
+    };
+
+    let network = args.network.or(wif_network).unwrap_or(Network::Mainnet);
+    Ok((key_pair, network))
+}
+
+fn check_print_secrets(args: &KeySourceArgs) -> Result<()> {
+    if !args.yes_print_secrets {
+        anyhow::bail!("Pass --yes-print-secrets to print the secret key");
+    }
+    eprintln!("Warning: printing the secret key");
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct NetworkAddress {
+    network: Network,
+    address: Address,
+}
+
+/// What `key inspect` prints.
+#[derive(Serialize)]
+struct KeyInfo {
+    public_key: PublicKeyOnly,
+    uncompressed_public_key: String,
+    addresses: Vec<NetworkAddress>,
+    network: Network,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wif: Option<String>,
+}
+
+impl KeyInfo {
+    fn new(key_pair: &KeyPair, network: Network, print_secrets: bool) -> Self {
+        Self {
+            public_key: key_pair.public_only(),
+            uncompressed_public_key: to_hex(key_pair.public_key.serialize_uncompressed()),
+            addresses: Network::ALL
+                .into_iter()
+                .map(|network| NetworkAddress {
+                    network,
+                    address: Address::from_public_key_for(&key_pair.public_key, network),
+                })
+                .collect(),
+            network,
+            wif: print_secrets.then(|| key_pair.to_wif(network)),
+        }
+    }
+}
+
+impl Render for KeyInfo {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Public Key: 0x{}", self.public_key.0)?;
+        writeln!(
+            out,
+            "Uncompressed Public Key: 0x{}",
+            self.uncompressed_public_key
+        )?;
+        for address in &self.addresses {
+            writeln!(out, "Address ({}): {}", address.network, address.address)?;
+        }
+        if let Some(wif) = &self.wif {
+            writeln!(out, "WIF ({}): {wif}", self.network)?;
+        }
+        Ok(())
+    }
+}
+
+/// What `key convert` prints: the key in its new form, or where it was saved.
+#[derive(Serialize)]
+struct ConvertedKey {
+    format: KeyFormat,
+    address: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_file: Option<PathBuf>,
+}
+
+impl Render for ConvertedKey {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        if let Some(secret) = &self.secret {
+            writeln!(out, "{secret}")?;
+        }
+        if let Some(key_file) = &self.key_file {
+            writeln!(out, "Key File: {}", key_file.display())?;
+            writeln!(out, "Address: {}", self.address)?;
+        }
+        Ok(())
+    }
+}
+
+/// What `import-wif` prints.
+#[derive(Serialize)]
+struct ImportedKey {
+    public_key: PublicKeyOnly,
+}
+
+impl Render for ImportedKey {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Public Key: 0x{}", self.public_key.0)
+    }
+}
+
+#[derive(Serialize)]
+struct InputSummary {
+    outpoint: TransactionOutputReference,
+    value: u64,
+}
+
+/// What `tx create` prints: the raw transaction and what it spends and pays.
+#[derive(Serialize)]
+struct CreatedTransaction {
+    txid: TxId,
+    inputs: Vec<InputSummary>,
+    outputs: Vec<TransactionOutput>,
+    fee: u64,
+    hex: String,
+}
+
+impl Render for CreatedTransaction {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Txid: {}", self.txid)?;
+        for input in &self.inputs {
+            writeln!(out, "Input: {} ({})", input.outpoint, input.value)?;
+        }
+        for (index, output) in self.outputs.iter().enumerate() {
+            writeln!(out, "Output {index}: {output}")?;
+        }
+        writeln!(out, "Fee: {}", self.fee)?;
+        writeln!(out, "Hex: {}", self.hex)
+    }
+}
+
+fn create_transaction(
+    node: &Node,
+    to: &str,
+    amount: u64,
+    fee: Option<u64>,
+) -> Result<CreatedTransaction> {
+    let to = Address::parse_for(to, node.state.params.network)
+        .map_err(|error| anyhow::anyhow!("Invalid destination address {to}: {error}"))?;
+    let fee = fee.unwrap_or(node.state.mem_pool.config.min_fee);
+
+    let transaction = node.create_send(to, amount, fee)?;
+    let inputs = match transaction.body.input.outpoint() {
+        Some(outpoint) => vec![InputSummary {
+            outpoint: outpoint.clone(),
+            value: transaction.output_value() + fee,
+        }],
+        None => vec![],
+    };
+
+    Ok(CreatedTransaction {
+        txid: transaction.id()?,
+        inputs,
+        fee,
+        hex: transaction.to_hex()?,
+        outputs: transaction.body.outputs,
+    })
+}
+
+/// Builds and signs a payment from the outputs a node reports for the key's address, and
+/// submits it to that node.
+async fn send_payment(
+    client: &RpcClient,
+    key_pair: &KeyPair,
+    network: Network,
+    to: &str,
+    amount: u64,
+    fee: Option<u64>,
+) -> Result<TxId> {
+    let to = Address::parse_for(to, network)
+        .map_err(|error| anyhow::anyhow!("Invalid destination address {to}: {error}"))?;
+    let mem_pool = client.get_mem_pool_info().await?;
+    let unspent = client
+        .list_unspent(&Address::from_public_key_for(&key_pair.public_key, network))
+        .await?;
+
+    let transaction = build_payment(
+        key_pair,
+        network,
+        unspent,
+        to,
+        amount,
+        fee.unwrap_or(mem_pool.min_fee),
+        mem_pool.dust_limit,
+    )?;
+    match client.send_raw_transaction(&transaction).await {
+        Err(RpcClientError::Rpc(error)) if error.code == REJECTED => {
+            anyhow::bail!("Node rejected the transaction: {}", error.message)
+        }
+        sent => Ok(sent?),
+    }
+}
+
+/// What `send` prints. The confirmations are only known when it waited for them.
+#[derive(Serialize)]
+struct SentTransaction {
+    txid: TxId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confirmations: Option<u32>,
+}
+
+impl Render for SentTransaction {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}", self.txid)
+    }
+}
+
+/// Polls the node until the transaction has `confirmations`, reporting each new one on
+/// stderr.
+async fn wait_for_confirmations(
+    client: &RpcClient,
+    id: &TxId,
+    confirmations: u32,
+    timeout: Duration,
+) -> Result<u32> {
+    let deadline = Instant::now() + timeout;
+    let mut reported = 0;
+
+    loop {
+        let raw = client.get_raw_transaction(id).await?;
+        if let Some(reason) = raw.rejected {
+            anyhow::bail!("Node rejected the transaction: {reason}");
+        }
+        if raw.confirmations > reported {
+            reported = raw.confirmations;
+            eprintln!("{reported}/{confirmations} confirmations");
+        }
+        if raw.confirmations >= confirmations {
+            return Ok(raw.confirmations);
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!(
+                "Timed out after {}s waiting for {confirmations} confirmations, the transaction has {}",
+                timeout.as_secs(),
+                raw.confirmations
+            );
+        }
+
+        tokio::time::sleep(Duration::from_millis(CONFIRMATION_POLL_INTERVAL_MILLIS)).await;
+    }
+}
+
+#[derive(Serialize)]
+struct UtxoSummary {
+    outpoint: TransactionOutputReference,
+    value: u64,
+    confirmations: u32,
+}
+
+/// What `balance` prints. The pending delta is what the saved mempool's transactions
+/// add to or take from the confirmed balance.
+#[derive(Serialize)]
+struct Balance {
+    address: Address,
+    confirmed: u64,
+    utxo_count: usize,
+    pending_delta: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    utxos: Option<Vec<UtxoSummary>>,
+}
+
+fn balance(node: &Node, address: Address, list_utxos: bool) -> Result<Balance> {
+    let confirmed = node.get_confirmed_balance(&address);
+    let unspent = node.list_unspent(&address)?;
+    let pending = unspent.iter().map(|output| output.value).sum::<u64>();
+
+    Ok(Balance {
+        confirmed,
+        utxo_count: node
+            .state
+            .utxo_set
+            .outputs
+            .iter()
+            .filter(|(outpoint, tx)| tx.body.outputs[outpoint.index].address == address)
+            .count(),
+        pending_delta: pending as i64 - confirmed as i64,
+        utxos: list_utxos.then(|| {
+            unspent
+                .into_iter()
+                .map(|output| UtxoSummary {
+                    outpoint: output.outpoint,
+                    value: output.value,
+                    confirmations: output.confirmations,
+                })
+                .collect()
+        }),
+        address,
+    })
+}
+
+impl Render for Balance {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Address: {}", self.address)?;
+        writeln!(out, "Confirmed: {}", self.confirmed)?;
+        writeln!(out, "UTXOs: {}", self.utxo_count)?;
+        if self.pending_delta != 0 {
+            writeln!(out, "Pending: {:+}", self.pending_delta)?;
+        }
+        for utxo in self.utxos.iter().flatten() {
+            writeln!(
+                out,
+                "  {} {} ({} confirmations)",
+                utxo.outpoint, utxo.value, utxo.confirmations
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct ChainInfo {
+    #[serde(with = "serde_hex")]
+    tip_hash: Hash,
+    height: u32,
+    /// Decimal, as it may not fit in any JSON number type.
+    total_work: String,
+    average_block_time: Option<f64>,
+    block_count: usize,
+    stored_blocks: usize,
+    orphan_count: usize,
+    utxo_count: usize,
+    total_supply: u64,
+    disk_usage: u64,
+    /// Listed from the tip down in text.
+    #[serde(skip)]
+    chain: Blockchain,
+}
+
+/// The blocks stored in a data directory and the chain with the most work among them.
+fn open_chain(data_dir: &Path) -> Result<(BlockManager, Blockchain)> {
+    let blocks_dir = NodeConfig::builder(KeyPair::generate())
+        .data_dir(data_dir)
+        .build()
+        .blocks_dir();
+    if !blocks_dir.is_dir() {
+        anyhow::bail!("No blocks in {}", data_dir.display());
+    }
+
+    let (block_manager, report) = BlockManager::open(blocks_dir)?;
+    report.log();
+    let chain = match block_manager.best_node() {
+        Some(tip) => BlockchainNode::into_chain(tip)?,
+        None => anyhow::bail!("No blocks in {}", data_dir.display()),
+    };
+    Ok((block_manager, chain))
+}
+
+fn chain_info(data_dir: &Path) -> Result<ChainInfo> {
+    let (block_manager, chain) = open_chain(data_dir)?;
+    let stats = chain
+        .stats()?
+        .ok_or(anyhow::anyhow!("No blocks in {}", data_dir.display()))?;
+    let utxo_set = chain.build_utxo_set(&block_manager)?;
+
+    Ok(ChainInfo {
+        tip_hash: stats.tip_hash,
+        height: stats.height,
+        total_work: stats.total_work.to_string(),
+        average_block_time: stats.average_block_time,
+        block_count: stats.block_count,
+        stored_blocks: block_manager.blocks.len(),
+        orphan_count: block_manager.orphan_blocks.len(),
+        utxo_count: utxo_set.outputs.len(),
+        total_supply: utxo_set.total_value(),
+        disk_usage: block_manager.disk_usage()?,
+        chain,
+    })
+}
+
+impl Render for ChainInfo {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "Tip: 0x{} at height {}",
+            self.tip_hash.display(),
+            self.height
+        )?;
+        writeln!(out, "Total Work: {}", self.total_work)?;
+        match self.average_block_time {
+            Some(seconds) => writeln!(out, "Average Block Time: {seconds:.1}s")?,
+            None => writeln!(out, "Average Block Time: unknown")?,
+        }
+        writeln!(
+            out,
+            "Blocks: {} in the active chain, {} stored, {} orphaned",
+            self.block_count, self.stored_blocks, self.orphan_count
+        )?;
+        writeln!(
+            out,
+            "UTXOs: {} holding {} in total",
+            self.utxo_count, self.total_supply
+        )?;
+        writeln!(out, "Disk Usage: {} bytes", self.disk_usage)?;
+        writeln!(out, "Recent Blocks:")?;
+        writeln!(out, "{}", self.chain)
+    }
+}
+
+/// Finds a block by hash, or the active chain's block at a height, listing the others
+/// stored at that height on stderr. Returns it with its confirmations, which are zero
+/// off the active chain.
+fn find_block(data_dir: &Path, hash: Option<String>, height: Option<u32>) -> Result<(Block, u32)> {
+    let (block_manager, chain) = open_chain(data_dir)?;
+
+    let hash = match (hash, height) {
+        (Some(hash), _) => {
+            Hash::from_hex(&hash).map_err(|error| anyhow::anyhow!("Invalid block hash: {error}"))?
+        }
+        (None, Some(height)) => {
+            let hashes = block_manager.hashes_at_height(height);
+            let active = chain
+                .get_node(height)
+                .map(|node| node.header.hash())
+                .transpose()?;
+
+            if hashes.len() > 1 {
+                eprintln!("{} blocks at height {height}:", hashes.len());
+                for hash in &hashes {
+                    let marker = if Some(*hash) == active {
+                        " (active)"
+                    } else {
+                        ""
+                    };
+                    eprintln!("  0x{}{marker}", hash.display());
+                }
+            }
+
+            match active {
+                Some(hash) => hash,
+                None if hashes.is_empty() => anyhow::bail!("No block at height {height}"),
+                None => anyhow::bail!(
+                    "No block at height {height} on the active chain, pass one of the above with --hash"
+                ),
+            }
+        }
+        (None, None) => anyhow::bail!("Pass --hash or --height"),
+    };
+
+    let block = block_manager
+        .get_block(&hash)
+        .ok_or(anyhow::anyhow!("No block 0x{}", hash.display()))?
+        .clone();
+    let confirmations = match chain.contains_block(&block) {
+        true => chain.height() - block.height + 1,
+        false => 0,
+    };
+    Ok((block, confirmations))
+}
+
+impl Render for BlockHeader {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let timestamp = chrono::DateTime::from_timestamp(self.timestamp as i64, 0)
+            .map(|timestamp| timestamp.to_rfc3339())
+            .unwrap_or_else(|| self.timestamp.to_string());
+        let hash = self.hash().map_err(io::Error::other)?;
+
+        writeln!(out, "Hash: 0x{}", hash.display())?;
+        writeln!(out, "Previous: 0x{}", self.previous_block_hash.display())?;
+        writeln!(out, "Merkle Root: 0x{}", self.merkle_root.display())?;
+        writeln!(out, "Timestamp: {timestamp}")?;
+        writeln!(out, "Difficulty: {}", self.difficulty)?;
+        writeln!(out, "Nonce: {}", self.nonce)
+    }
+}
+
+/// What `block show` prints: the block's JSON form, or in text its confirmations too.
+#[derive(Serialize)]
+struct ShownBlock {
+    #[serde(flatten)]
+    block: Block,
+    #[serde(skip)]
+    confirmations: u32,
+}
+
+impl Render for ShownBlock {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let block = &self.block;
+        writeln!(out, "Height: {}", block.height)?;
+        block.header.render_text(out)?;
+        writeln!(out, "Confirmations: {}", self.confirmations)?;
+        writeln!(out, "Transactions: {}", block.transactions.len())?;
+        if let Some(coinbase) = block.transactions.first() {
+            writeln!(out, "Coinbase Value: {}", coinbase.output_value())?;
+        }
+        for transaction in &block.transactions {
+            for line in transaction.to_string().lines() {
+                writeln!(out, "  {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What `tx decode` prints. The input's value and the fee are only known with a UTXO
+/// set to look the spent output up in.
+#[derive(Serialize)]
+struct DecodedTransaction {
+    txid: TxId,
+    input: TransactionInput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_value: Option<u64>,
+    outputs: Vec<TransactionOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee: Option<u64>,
+    size: usize,
+    signature_valid: bool,
+}
+
+fn decode_transaction(raw: &str, node: Option<&Node>) -> Result<DecodedTransaction> {
+    let transaction = Transaction::from_hex(raw)?;
+
+    let input_value = match (transaction.body.input.outpoint(), node) {
+        (Some(outpoint), Some(node)) => node
+            .state
+            .utxo_set
+            .outputs
+            .get(outpoint)
+            .map(|tx| tx.body.outputs[outpoint.index].value),
+        _ => None,
+    };
+
+    Ok(DecodedTransaction {
+        txid: transaction.id()?,
+        input_value,
+        fee: input_value.map(|value| value.saturating_sub(transaction.output_value())),
+        size: transaction.size()?,
+        signature_valid: transaction.verify_signature()?,
+        input: transaction.body.input,
+        outputs: transaction.body.outputs,
+    })
+}
+
+impl Render for DecodedTransaction {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Txid: {}", self.txid)?;
+        match self.input_value {
+            Some(value) => writeln!(out, "Input: {} ({value})", self.input)?,
+            None => writeln!(out, "Input: {}", self.input)?,
+        }
+        for (index, output) in self.outputs.iter().enumerate() {
+            writeln!(out, "Output {index}: {output}")?;
+        }
+        if let Some(fee) = self.fee {
+            writeln!(out, "Fee: {fee}")?;
+        }
+        writeln!(out, "Size: {} bytes", self.size)?;
+        writeln!(
+            out,
+            "Signature: {}",
+            if self.signature_valid {
+                "valid"
+            } else {
+                "invalid"
+            }
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct ListedTransaction {
+    txid: TxId,
+    size: usize,
+    fee: u64,
+    fee_rate: f64,
+    /// Seconds since the node accepted it.
+    age: u64,
+}
+
+/// What `mempool list` prints.
+#[derive(Serialize)]
+struct MemPoolList {
+    transactions: Vec<ListedTransaction>,
+}
+
+impl Render for MemPoolList {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        for transaction in &self.transactions {
+            writeln!(
+                out,
+                "{} {} bytes, fee {} ({:.2}/kB), {}s old",
+                transaction.txid,
+                transaction.size,
+                transaction.fee,
+                transaction.fee_rate,
+                transaction.age
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What `mempool get` prints.
+#[derive(Serialize)]
+struct PendingTransaction {
+    #[serde(flatten)]
+    transaction: DecodedTransaction,
+    fee_rate: f64,
+    age: u64,
+    ancestors: Vec<TxId>,
+    descendants: Vec<TxId>,
+}
+
+impl Render for PendingTransaction {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.transaction.render_text(out)?;
+        writeln!(out, "Fee Rate: {:.2}/kB", self.fee_rate)?;
+        writeln!(out, "Age: {}s", self.age)?;
+        for ancestor in &self.ancestors {
+            writeln!(out, "Ancestor: {ancestor}")?;
+        }
+        for descendant in &self.descendants {
+            writeln!(out, "Descendant: {descendant}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Render for MemPoolStats {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Transactions: {}", self.count)?;
+        writeln!(out, "Bytes: {}", self.bytes)?;
+        writeln!(out, "Total Fees: {}", self.total_fees)?;
+        if let Some(min_fee_rate) = self.min_fee_rate {
+            writeln!(out, "Min Fee Rate: {min_fee_rate:.2}/kB")?;
+        }
+        for bucket in &self.histogram {
+            writeln!(
+                out,
+                "From {}/kB: {} transactions, {} bytes",
+                bucket.min_fee_rate, bucket.count, bucket.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn age(time: u32) -> u64 {
+    (chrono::Utc::now().timestamp() - time as i64).max(0) as u64
+}
+
+async fn list_mem_pool(client: &RpcClient) -> Result<MemPoolList> {
+    let mut entries = client.get_raw_mem_pool().await?;
+    entries.sort_by(|a, b| b.fee_rate.total_cmp(&a.fee_rate));
+
+    Ok(MemPoolList {
+        transactions: entries
+            .into_iter()
+            .map(|entry| ListedTransaction {
+                age: age(entry.time),
+                txid: entry.txid,
+                size: entry.size,
+                fee: entry.fee,
+                fee_rate: entry.fee_rate,
+            })
+            .collect(),
+    })
+}
+
+async fn get_pending_transaction(client: &RpcClient, txid: &str) -> Result<PendingTransaction> {
+    let info = client.get_mem_pool_entry(&parse_txid(txid)?).await?;
+    let mut transaction = decode_transaction(&info.hex, None)?;
+    transaction.fee = Some(info.entry.fee);
+    let output_value = transaction
+        .outputs
+        .iter()
+        .map(|output| output.value)
+        .sum::<u64>();
+    transaction.input_value = Some(info.entry.fee + output_value);
+
+    Ok(PendingTransaction {
+        transaction,
+        fee_rate: info.entry.fee_rate,
+        age: age(info.entry.time),
+        ancestors: info.ancestors,
+        descendants: info.descendants,
+    })
+}
+
+fn seed_key_pair(seed_hex: &str) -> Result<KeyPair> {
+    let seed = from_hex(seed_hex)?
+        .try_into()
+        .map_err(|seed: Vec<u8>| anyhow::anyhow!("Seed is {} bytes, not 32", seed.len()))?;
+    KeyPair::from_seed(&seed)
+}
+
+fn save_key_file(
+    key_pair: &KeyPair,
+    path: &Path,
+    passphrase: Option<String>,
+    insecure_plaintext: bool,
+    force: bool,
+) -> Result<()> {
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        );
+    }
+
+    if insecure_plaintext {
+        eprintln!("Warning: saving the secret key unencrypted");
+        key_pair.save_plaintext(path)?;
+        return Ok(());
+    }
+
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => prompt_new_passphrase()?,
+    };
+    key_pair.save_encrypted(path, &passphrase)?;
+    Ok(())
+}
+
+fn parse_txid(txid: &str) -> Result<TxId> {
+    Ok(TxId(Hash::from_hex(txid).map_err(|error| {
+        anyhow::anyhow!("Invalid txid: {error}")
+    })?))
+}
+
+/// What `merkle-proof` prints: the proof, and the block a light client checks it against.
+#[derive(Serialize)]
+struct ProofOutput {
+    txid: TxId,
+    #[serde(with = "serde_hex")]
+    block_hash: Hash,
+    height: u32,
+    index: u32,
+    transaction_count: usize,
+    #[serde(with = "serde_hex")]
+    merkle_root: Hash,
+    hashes: Vec<String>,
+    proof: String,
+}
+
+/// Finds the block confirming `txid` through the txid→block index, and proves it's there.
+fn prove_transaction(data_dir: &Path, txid: &str) -> Result<ProofOutput> {
+    let id = parse_txid(txid)?;
+    let (block_manager, chain) = open_chain(data_dir)?;
+    let index = TxIndex::build(&chain, &block_manager)?;
+
+    let block_hash = *index.get(&id).ok_or(anyhow::anyhow!(
+        "Transaction {id} isn't confirmed on the active chain"
+    ))?;
+    let block = block_manager
+        .get_block(&block_hash)
+        .ok_or(anyhow::anyhow!("No block 0x{}", block_hash.display()))?;
+    let proof = block.transaction_proof(&id)?;
+
+    Ok(ProofOutput {
+        txid: id,
+        block_hash,
+        height: block.height,
+        index: proof.index,
+        transaction_count: block.transactions.len(),
+        merkle_root: block.header.merkle_root,
+        hashes: proof.proof.hashes.iter().map(to_hex).collect(),
+        proof: proof.to_hex()?,
+    })
+}
+
+impl Render for ProofOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Txid: {}", self.txid)?;
+        writeln!(
+            out,
+            "Block: 0x{} (height {})",
+            self.block_hash.display(),
+            self.height
+        )?;
+        writeln!(
+            out,
+            "Position: {} of {} transactions",
+            self.index, self.transaction_count
+        )?;
+        writeln!(out, "Merkle Root: 0x{}", self.merkle_root.display())?;
+        writeln!(out, "Proof: {}", self.proof)
+    }
+}
+
+/// Prompts twice for a new passphrase, without echoing it.
+fn prompt_new_passphrase() -> Result<String> {
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        anyhow::bail!("Passphrases don't match");
+    }
+    Ok(passphrase)
+}
+
+fn load_wallet(file: &WalletFileArgs) -> Result<(Wallet, String)> {
+    let passphrase = match &file.passphrase {
+        Some(passphrase) => passphrase.clone(),
+        None => rpassword::prompt_password(format!("Passphrase for {}: ", file.wallet.display()))?,
+    };
+    let wallet = Wallet::load_encrypted(&file.wallet, &passphrase)?;
+    Ok((wallet, passphrase))
+}
+
+#[derive(Serialize)]
+struct WalletAddress {
+    index: u32,
+    path: DerivationPath,
+    address: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+impl WalletAddress {
+    fn new(wallet: &Wallet, index: u32, address: Address) -> Self {
+        Self {
+            index,
+            path: wallet.path(index),
+            address,
+            label: wallet.labels.get(&index).cloned(),
+        }
+    }
+}
+
+impl Render for WalletAddress {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "{} {} {}", self.index, self.path, self.address)?;
+        match &self.label {
+            Some(label) => writeln!(out, " {label}"),
+            None => writeln!(out),
+        }
+    }
+}
+
+/// What the wallet commands print about the wallet as a whole.
+#[derive(Serialize)]
+struct WalletInfo {
+    wallet: PathBuf,
+    network: Network,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mnemonic: Option<String>,
+    next_index: u32,
+    addresses: Vec<WalletAddress>,
+    outputs: Vec<WalletOutput>,
+    balance: u64,
+    scanned_height: Option<u32>,
+}
+
+fn wallet_info(path: &Path, wallet: &Wallet, mnemonic: Option<String>) -> Result<WalletInfo> {
+    let mut addresses = Vec::new();
+    for index in 0..wallet.next_index {
+        match wallet.address(index) {
+            Ok(address) => addresses.push(WalletAddress::new(wallet, index, address)),
+            // new_address skips these
+            Err(HdError::InvalidChild(_)) => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(WalletInfo {
+        wallet: path.to_path_buf(),
+        network: wallet.network(),
+        mnemonic,
+        next_index: wallet.next_index,
+        addresses,
+        outputs: wallet.outputs.clone(),
+        balance: wallet.balance(),
+        scanned_height: wallet.scanned_height,
+    })
+}
+
+impl Render for WalletInfo {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        if let Some(mnemonic) = &self.mnemonic {
+            writeln!(out, "Mnemonic: {mnemonic}")?;
+        }
+        writeln!(out, "Wallet: {}", self.wallet.display())?;
+        writeln!(out, "Network: {}", self.network)?;
+        writeln!(out, "Addresses: {}", self.addresses.len())?;
+        for address in &self.addresses {
+            write!(out, "  ")?;
+            address.render_text(out)?;
+        }
+        match self.scanned_height {
+            Some(height) => writeln!(out, "Balance: {} at height {height}", self.balance)?,
+            None => writeln!(out, "Balance: unknown, not rescanned yet")?,
+        }
+        for output in &self.outputs {
+            writeln!(
+                out,
+                "  {} {} {}",
+                output.outpoint, output.path, output.value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct MinedBlock {
+    height: u32,
+    #[serde(with = "serde_hex")]
+    hash: Hash,
+    seconds: f64,
+    hash_rate: f64,
+}
+
+/// What `mine` prints.
+#[derive(Serialize)]
+struct MinedBlocks {
+    blocks: Vec<MinedBlock>,
+}
+
+impl Render for MinedBlocks {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        for block in &self.blocks {
+            writeln!(
+                out,
+                "Mined block {} 0x{} in {:.2?}, {:.0} hashes/s",
+                block.height,
+                block.hash.display(),
+                Duration::from_secs_f64(block.seconds),
+                block.hash_rate
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What `generate` prints.
+#[derive(Serialize)]
+struct GeneratedBlocks {
+    hashes: Vec<String>,
+}
+
+impl Render for GeneratedBlocks {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        for hash in &self.hashes {
+            writeln!(out, "Generated block 0x{hash}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds a nonce for `header` on `threads` threads, reporting the hash rate on stderr
+/// every second. Returns the hashes computed.
+fn mine_header(header: &mut BlockHeader, threads: usize) -> Result<u64> {
+    let started = Instant::now();
+    let hashes = AtomicU64::new(0);
+    let last_report = Mutex::new(started);
+    let nonce = header.compute_nonce_parallel(threads, |new_hashes| {
+        let hashes = hashes.fetch_add(new_hashes, Ordering::Relaxed) + new_hashes;
+        let mut last_report = last_report.lock().unwrap();
+        if last_report.elapsed() >= Duration::from_secs(1) {
+            *last_report = Instant::now();
+            let elapsed = started.elapsed().as_secs_f64();
+            eprintln!(
+                "{hashes} hashes in {elapsed:.0}s, {:.0}/s",
+                hashes as f64 / elapsed
+            );
+        }
+        false
+    })?;
+    header.nonce = nonce.ok_or(anyhow::anyhow!("Mining was interrupted"))?;
+
+    Ok(hashes.into_inner())
+}
+
+/// Mines `count` blocks on the tip of the node's chain, including its mempool's
+/// transactions, reporting progress on stderr.
+fn mine_blocks(mut node: Node, count: u32, threads: usize) -> Result<MinedBlocks> {
+    let mut blocks = Vec::new();
+    for _ in 0..count {
+        let mut block = node.block_template()?;
+        let started = Instant::now();
+        let hashes = mine_header(&mut block.header, threads)?;
+
+        let hash = block.header.hash()?;
+        let height = block.height;
+        match node.state.add_block(block)? {
+            HandleOutcome::Accepted => {}
+            outcome => anyhow::bail!("Mined block was not accepted: {outcome:?}"),
+        }
+
+        let seconds = started.elapsed().as_secs_f64();
+        blocks.push(MinedBlock {
+            height,
+            hash,
+            seconds,
+            hash_rate: hashes as f64 / seconds,
+        });
+    }
+
+    node.flush()?;
+    Ok(MinedBlocks { blocks })
+}
+
+#[derive(Serialize)]
+struct SupplyCheck {
+    expected: u64,
+    actual: u64,
+}
+
+/// What `validate-chain` prints.
+#[derive(Serialize)]
+struct ValidationReport {
+    height: u32,
+    validated: u32,
+    transactions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure: Option<ValidationFailure>,
+    storage_problems: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supply: Option<SupplyCheck>,
+    valid: bool,
+}
+
+impl Render for ValidationReport {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "Validated {} blocks with {} transactions, of {}",
+            self.validated, self.transactions, self.height
+        )?;
+        if let Some(failure) = &self.failure {
+            writeln!(
+                out,
+                "Invalid block {} 0x{}: {}",
+                failure.height,
+                failure.hash.display(),
+                failure.reason
+            )?;
+        }
+        for problem in &self.storage_problems {
+            writeln!(out, "{problem}")?;
+        }
+        if let Some(supply) = &self.supply {
+            writeln!(
+                out,
+                "Supply: {} in the UTXO set, {} expected",
+                supply.actual, supply.expected
+            )?;
+        }
+        writeln!(out, "{}", if self.valid { "valid" } else { "invalid" })
+    }
+}
+
+fn validate_chain(
+    data_dir: &Path,
+    network: Network,
+    from_height: u32,
+    check_utxo: bool,
+) -> Result<ValidationReport> {
+    let params = NetworkParams::for_network(network);
+    let (block_manager, chain) = open_chain(data_dir)?;
+    let height = chain.height();
+
+    let mut report = throttled(|validated: &u32| {
+        eprintln!(
+            "Validated block {validated} of {height} ({:.0}%)",
+            *validated as f64 * 100.0 / height as f64
+        )
+    });
+    let validation = chain.validate_full(&block_manager, &params, from_height, |height| {
+        report(&height)
+    })?;
+
+    // the supply only adds up over a chain that connected all the way
+    let supply = (check_utxo && validation.failure.is_none())
+        .then(|| {
+            Ok::<_, anyhow::Error>(SupplyCheck {
+                expected: params.expected_supply(height).ok_or(anyhow::anyhow!(
+                    "The supply at height {height} is more than {}",
+                    u64::MAX
+                ))?,
+                actual: validation.utxo_set.total_value(),
+            })
+        })
+        .transpose()?;
+
+    let storage_problems = block_manager
+        .fsck()?
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    Ok(ValidationReport {
+        height,
+        validated: validation.validated,
+        transactions: validation.transactions,
+        valid: validation.failure.is_none()
+            && storage_problems.is_empty()
+            && supply
+                .as_ref()
+                .is_none_or(|supply| supply.expected == supply.actual),
+        failure: validation.failure,
+        storage_problems,
+        supply,
+    })
+}
+
+/// What `blocks export` prints.
+#[derive(Serialize)]
+struct ExportOutput {
+    blocks: u32,
+    height: u32,
+    #[serde(with = "serde_hex")]
+    tip_hash: Hash,
+    out: PathBuf,
+}
+
+impl Render for ExportOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "Exported {} blocks to {}, up to block {} 0x{}",
+            self.blocks,
+            self.out.display(),
+            self.height,
+            self.tip_hash.display()
+        )
+    }
+}
+
+/// What `blocks import` prints.
+#[derive(Serialize)]
+struct ImportOutput {
+    #[serde(flatten)]
+    summary: ImportSummary,
+    height: u32,
+    #[serde(with = "serde_hex")]
+    tip_hash: Hash,
+    verify_only: bool,
+}
+
+impl Render for ImportOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let action = if self.verify_only {
+            "Verified"
+        } else {
+            "Imported"
+        };
+        writeln!(
+            out,
+            "{action} {}, skipped {}, rejected {}",
+            self.summary.imported, self.summary.skipped, self.summary.rejected
+        )?;
+        if let Some(rejection) = &self.summary.first_rejection {
+            writeln!(out, "First Rejection: {rejection}")?;
+        }
+        writeln!(out, "Tip: {} 0x{}", self.height, self.tip_hash.display())
+    }
+}
+
+/// Calls `report` at most once a second.
+fn throttled<T: ?Sized>(mut report: impl FnMut(&T)) -> impl FnMut(&T) {
+    let mut last_report = Instant::now();
+    move |value| {
+        if last_report.elapsed() >= Duration::from_secs(1) {
+            last_report = Instant::now();
+            report(value);
+        }
+    }
+}
+
+fn export_blocks(
+    data_dir: &Path,
+    out: &Path,
+    to_height: Option<u32>,
+    force: bool,
+) -> Result<ExportOutput> {
+    if out.exists() && !force {
+        anyhow::bail!(
+            "{} already exists, pass --force to overwrite it",
+            out.display()
+        );
+    }
+    let (block_manager, chain) = open_chain(data_dir)?;
+    let tip = match to_height {
+        Some(height) => chain.nodes.get(&height).cloned().ok_or(anyhow::anyhow!(
+            "No block at height {height}, the tip is at {}",
+            chain.height()
+        ))?,
+        None => chain.tail().ok_or(anyhow::anyhow!("No blocks to export"))?,
+    };
+
+    let mut report = throttled(|count: &u32| eprintln!("Exported {count} blocks"));
+    let blocks =
+        block_manager.export_archive(&chain, std::fs::File::create(out)?, to_height, |count| {
+            report(&count)
+        })?;
+
+    Ok(ExportOutput {
+        blocks,
+        height: tip.height,
+        tip_hash: tip.header.hash()?,
+        out: out.to_path_buf(),
+    })
+}
+
+/// Imports an archive into the data dir, or with `verify_only` into a node that's only
+/// in memory.
+fn import_blocks(
+    data_dir: &Path,
+    network: Network,
+    input: &Path,
+    verify_only: bool,
+    continue_on_error: bool,
+) -> Result<ImportOutput> {
+    let params = NetworkParams::for_network(network);
+    let file = std::fs::File::open(input)
+        .map_err(|error| anyhow::anyhow!("Failed to open {}: {error}", input.display()))?;
+    let mut report = throttled(|summary: &ImportSummary| {
+        eprintln!(
+            "Imported {}, skipped {}, rejected {}",
+            summary.imported, summary.skipped, summary.rejected
+        )
+    });
+
+    let (summary, tip) = if verify_only {
+        let mut state = NodeState {
+            params,
+            ..NodeState::default()
+        };
+        let summary = state.import_archive(file, continue_on_error, &mut report)?;
+        (summary, state.chain.tail())
+    } else {
+        let mut node = Node::new(
+            NodeConfig::builder(KeyPair::generate())
+                .data_dir(data_dir)
+                .network(params)
+                .build(),
+        )?;
+        let summary = node
+            .state
+            .import_archive(file, continue_on_error, &mut report)?;
+        node.flush()?;
+        (summary, node.state.chain.tail())
+    };
+
+    let tip = tip.ok_or(anyhow::anyhow!("The archive holds no valid blocks"))?;
+    Ok(ImportOutput {
+        summary,
+        height: tip.height,
+        tip_hash: tip.header.hash()?,
+        verify_only,
+    })
+}
+
+/// A merged node config, shown as the TOML that would configure it.
+#[derive(Serialize)]
+#[serde(transparent)]
+struct ConfigInfo(NodeConfig);
+
+impl Render for ConfigInfo {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let toml = toml::to_string(&self.0).map_err(io::Error::other)?;
+        write!(out, "{toml}")
+    }
+}
+
+/// What a params file holds: the `[network]` table of a node config.
+#[derive(Serialize)]
+struct ParamsFile<'a> {
+    network: &'a NetworkParams,
+}
+
+/// What the genesis commands print.
+#[derive(Serialize)]
+struct GenesisInfo {
+    network: Network,
+    #[serde(with = "serde_hex")]
+    hash: Hash,
+    timestamp: u32,
+    difficulty: u8,
+    nonce: u64,
+    block_reward: u64,
+    blocks_per_reward_halving: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tail_emission: Option<u64>,
+    premine: Vec<GenesisAllocation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_file: Option<PathBuf>,
+}
+
+impl GenesisInfo {
+    fn new(params: &NetworkParams, block: &Block) -> Result<Self> {
+        Ok(Self {
+            network: params.network,
+            hash: block.header.hash()?,
+            timestamp: block.header.timestamp,
+            difficulty: block.header.difficulty,
+            nonce: block.header.nonce,
+            block_reward: params.genesis_block_reward,
+            blocks_per_reward_halving: params.blocks_per_reward_halving,
+            tail_emission: params.tail_emission,
+            premine: params.premine.clone(),
+            params_file: None,
+            block_file: None,
+        })
+    }
+}
+
+impl Render for GenesisInfo {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Genesis: 0x{}", self.hash.display())?;
+        writeln!(out, "Network: {}", self.network)?;
+        writeln!(out, "Difficulty: {}", self.difficulty)?;
+        writeln!(out, "Nonce: {}", self.nonce)?;
+        writeln!(
+            out,
+            "Block Reward: {}, halving every {} blocks",
+            self.block_reward, self.blocks_per_reward_halving
+        )?;
+        if let Some(tail_emission) = self.tail_emission {
+            writeln!(out, "Tail Emission: {tail_emission}")?;
+        }
+        for allocation in &self.premine {
+            writeln!(
+                out,
+                "Premine: {} to {}",
+                allocation.value, allocation.address
+            )?;
+        }
+        if let Some(params_file) = &self.params_file {
+            writeln!(out, "Params File: {}", params_file.display())?;
+        }
+        if let Some(block_file) = &self.block_file {
+            writeln!(out, "Block File: {}", block_file.display())?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_allocation(allocation: &str, network: Network) -> Result<GenesisAllocation> {
+    let (address, value) = allocation.rsplit_once(':').ok_or(anyhow::anyhow!(
+        "Invalid premine {allocation}, expected ADDRESS:AMOUNT"
+    ))?;
+    Ok(GenesisAllocation {
+        address: Address::parse_for(address, network)
+            .map_err(|error| anyhow::anyhow!("Invalid premine address {address}: {error}"))?,
+        value: value
+            .parse()
+            .map_err(|error| anyhow::anyhow!("Invalid premine amount {value}: {error}"))?,
+    })
+}
+
+/// Mines the genesis block `params` describe, writing the params with its nonce to `out`
+/// and the block beside them.
+fn init_genesis(
+    mut params: NetworkParams,
+    out: &Path,
+    threads: usize,
+    force: bool,
+) -> Result<GenesisInfo> {
+    let block_file = out.with_extension("genesis.json");
+    for path in [out, &block_file] {
+        if path.exists() && !force {
+            anyhow::bail!(
+                "{} already exists, pass --force to overwrite it",
+                path.display()
+            );
+        }
+    }
+
+    let mut block = params.genesis_template()?;
+    mine_header(&mut block.header, threads)?;
+    params.genesis_nonce = Some(block.header.nonce);
+    block.validate(&params)?;
+
+    std::fs::write(out, toml::to_string(&ParamsFile { network: &params })?)?;
+    std::fs::write(&block_file, serde_json::to_string_pretty(&block)?)?;
+
+    Ok(GenesisInfo {
+        params_file: Some(out.to_path_buf()),
+        block_file: Some(block_file),
+        ..GenesisInfo::new(&params, &block)?
+    })
+}
+
+/// What `bench` prints. Rates are the median of their samples, per second.
+#[derive(Serialize)]
+struct BenchOutput {
+    threads: usize,
+    header_hashes: f64,
+    header_hashes_parallel: f64,
+    signature_verifications: f64,
+    merkle_transactions: usize,
+    merkle_roots: f64,
+    block_time: u64,
+    suggested_difficulty: u8,
+    /// At the suggested difficulty and the parallel hash rate.
+    expected_block_time: f64,
+}
+
+impl Render for BenchOutput {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        let rows = [
+            ("Header hashes (1 thread)".to_string(), self.header_hashes),
+            (
+                format!("Header hashes ({} threads)", self.threads),
+                self.header_hashes_parallel,
+            ),
+            (
+                "Signature verifications".to_string(),
+                self.signature_verifications,
+            ),
+            (
+                format!("Merkle roots ({} txs)", self.merkle_transactions),
+                self.merkle_roots,
+            ),
+        ];
+        for (name, rate) in rows {
+            writeln!(out, "{name:<28} {rate:>14.0}/s")?;
+        }
+        writeln!(
+            out,
+            "Suggested difficulty for {}s blocks: {} (about {:.0}s per block)",
+            self.block_time, self.suggested_difficulty, self.expected_block_time
+        )
+    }
+}
+
+fn run_bench(duration: Duration, threads: usize, block_time: u64) -> Result<BenchOutput> {
+    eprintln!("Measuring header hashes");
+    let header_hashes = bench::header_hash_rate(duration, 1)?;
+    let header_hashes_parallel = bench::header_hash_rate(duration, threads)?;
+    eprintln!("Measuring signature verifications");
+    let signature_verifications = bench::signature_verification_rate(duration)?;
+    eprintln!("Measuring merkle roots");
+    let merkle_roots = bench::merkle_root_rate(duration, BENCH_MERKLE_TRANSACTIONS)?;
+
+    let suggested_difficulty =
+        bench::suggested_difficulty(header_hashes_parallel, Duration::from_secs(block_time));
+    Ok(BenchOutput {
+        threads,
+        header_hashes,
+        header_hashes_parallel,
+        signature_verifications,
+        merkle_transactions: BENCH_MERKLE_TRANSACTIONS,
+        merkle_roots,
+        block_time,
+        suggested_difficulty,
+        expected_block_time: bench::expected_hashes(suggested_difficulty) / header_hashes_parallel,
+    })
+}
+
+/// Runs the p2p service, and the RPC server and miner when configured, until ctrl-c,
+/// then stops them in turn.
+async fn run_node(config: NodeConfig) -> Result<()> {
+    let node = Node::new(config.clone())?;
+    // refused before anything starts rather than when the first block is sealed
+    if config.mining.enabled {
+        node.check_can_seal()?;
+    }
+    let node = SharedNode::new(node);
+    let mut p2p = P2pService::new(node.clone(), config.p2p.clone()).await?;
+    let listen_addr = p2p.listen_addr().await?;
+
+    eprintln!("Network: {}", config.network.network);
+    eprintln!("Peer ID: {}", p2p.local_peer_id());
+    eprintln!("Listening on {listen_addr}/p2p/{}", p2p.local_peer_id());
+    {
+        let node = node.read().await;
+        let tip_hash = node.tip_hash()?.unwrap_or_default();
+        eprintln!(
+            "Tip: height {} 0x{}",
+            node.state.chain.height(),
+            tip_hash.display()
+        );
+    }
+    let peers = p2p.peer_list();
+    let p2p = p2p.spawn();
+
+    let rpc = match &config.rpc.listen_addr {
+        Some(addr) => {
+            let mut server = RpcServer::bind(node.clone(), addr).await?.with_peers(peers);
+            if let Some(token) = &config.rpc.auth_token {
+                server = server.with_auth_token(token);
+            }
+            eprintln!("RPC listening on http://{}", server.local_addr()?);
+            Some(server.spawn())
+        }
+        None => None,
+    };
+    let miner = if config.mining.enabled {
+        eprintln!("Mining to {}", node.read().await.address());
+        let miner = node.start_miner(CancellationToken::new());
+        node.write().await.state.metrics.miner = Some(miner.stats().clone());
+        Some(miner)
+    } else {
+        None
+    };
+
+    tokio::signal::ctrl_c().await?;
+    eprintln!("Shutting down");
+
+    if let Some(miner) = miner {
+        tokio::task::spawn_blocking(|| miner.stop()).await??;
+    }
+    if let Some(rpc) = rpc {
+        rpc.shutdown().await?;
+    }
+    p2p.shutdown().await
+}
+
+/// Logs to stderr, filtered by `RUST_LOG` when it's set. A running node logs what it does
+/// at `info`; other commands only warn, so their own output isn't buried.
+fn init_tracing(command: &Commands) {
+    let default = match command {
+        Commands::Node {
+            command: NodeCommands::Run { .. },
+            ..
+        } => "info",
+        _ => "warn",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let format = cli.output.unwrap_or_default();
+    init_tracing(&cli.command);
+
+    match cli.command {
+        Commands::Hash {
+            input,
+            file,
+            algo,
+            encoding,
+        } => render(&hash_command(input, file, algo, encoding)?, format)?,
+        Commands::GenerateKeyPair {
+            network,
+            mnemonic,
+            words,
+            recover,
+            mnemonic_passphrase,
+            vanity,
+            ignore_case,
+            threads,
+            seed_hex,
+            out,
+            passphrase,
+            insecure_plaintext,
+            force,
+        } => {
+            let mnemonic = match recover {
+                Some(phrase) => Some(Mnemonic::parse(&phrase)?),
+                None if mnemonic => Some(Mnemonic::generate(words)?),
+                None => None,
+            };
+            let key_pair = match (&mnemonic, seed_hex, vanity) {
+                (Some(mnemonic), _, _) => mnemonic.keypair(&mnemonic_passphrase),
+                (None, Some(seed_hex), _) => seed_key_pair(&seed_hex)?,
+                (None, None, Some(prefix)) => {
+                    vanity_key_pair(&prefix, network, ignore_case, threads)?
+                }
+                (None, None, None) => KeyPair::generate(),
+            };
+
+            if let Some(out) = &out {
+                save_key_file(&key_pair, out, passphrase, insecure_plaintext, force)?;
+            }
+
+            let saved = out.is_some();
+            let output = KeygenOutput {
+                address: Address::from_public_key_for(&key_pair.public_key, network),
+                public_key: key_pair.public_only(),
+                mnemonic: mnemonic.map(|mnemonic| mnemonic.to_string()),
+                secret_key: (!saved).then(|| SerializableSecretKey::from_key_pair(&key_pair)),
+                wif: (!saved).then(|| key_pair.to_wif(network)),
+                key_file: out,
+                key_pair,
+            };
+            render(&output, format)?;
+        }
+        Commands::Address {
+            from_pubkey,
+            validate,
+            network,
+        } => render(&address_command(from_pubkey, validate, network)?, format)?,
+        Commands::Key {
+            command: KeyCommands::Inspect { key },
+        } => {
+            let (key_pair, network) = load_key_source(&key)?;
+            if key.yes_print_secrets {
+                check_print_secrets(&key)?;
+            } else {
+                eprintln!("Pass --yes-print-secrets to show the WIF too");
+            }
+            render(
+                &KeyInfo::new(&key_pair, network, key.yes_print_secrets),
+                format,
+            )?;
+        }
+        Commands::Key {
+            command:
+                KeyCommands::Convert {
+                    key,
+                    to,
+                    out,
+                    new_passphrase,
+                    insecure_plaintext,
+                    force,
+                },
+        } => {
+            let (key_pair, network) = load_key_source(&key)?;
+            let mut converted = ConvertedKey {
+                format: to,
+                address: Address::from_public_key_for(&key_pair.public_key, network),
+                secret: None,
+                key_file: None,
+            };
+            match (to, out) {
+                (KeyFormat::Keyfile, Some(out)) => {
+                    save_key_file(&key_pair, &out, new_passphrase, insecure_plaintext, force)?;
+                    converted.key_file = Some(out);
+                }
+                (KeyFormat::Keyfile, None) => anyhow::bail!("Pass --out for --to keyfile"),
+                (KeyFormat::Wif, _) => {
+                    check_print_secrets(&key)?;
+                    converted.secret = Some(key_pair.to_wif(network));
+                }
+                (KeyFormat::Hex, _) => {
+                    check_print_secrets(&key)?;
+                    converted.secret = Some(key_pair.secret_key.display_secret().to_string());
+                }
+            }
+            render(&converted, format)?;
+        }
+        Commands::ImportWif { wif, network } => {
+            let key_pair = match network {
+                Some(network) => KeyPair::from_wif_for(&wif, network)?,
+                None => KeyPair::from_wif(&wif)?,
+            };
+            let imported = ImportedKey {
+                public_key: key_pair.public_only(),
+            };
+            render(&imported, format)?;
+        }
+        Commands::SignMessage {
+            message,
+            file,
+            wif,
+            key,
+            passphrase,
+            network,
+            encoding,
+        } => {
+            let key_pair = match (wif, key) {
+                (Some(wif), _) => KeyPair::from_wif_for(&wif, network)?,
+                (None, Some(key)) => load_key_file(&key, passphrase)?,
+                (None, None) => anyhow::bail!("Pass --wif or --key"),
+            };
+            let message = read_input(message, file)?;
+
+            let signature = key_pair.sign_message(&message);
+            let output = SignOutput {
+                address: Address::from_public_key_for(&key_pair.public_key, network),
+                digest: message_digest(&message),
+                signature: match encoding {
+                    ByteDisplay::Hex => signature.to_hex(),
+                    ByteDisplay::Base64 => signature.to_string(),
+                },
+            };
+            render(&output, format)?;
+        }
+        Commands::VerifyMessage {
+            signature,
+            message,
+            file,
+            pubkey,
+            address,
+            network,
+        } => {
+            let signature = signature.parse::<MessageSignature>()?;
+            let valid = match (pubkey, address) {
+                (Some(public_key), _) => {
+                    let public_key = PublicKey::from_slice(&from_hex(&public_key)?)
+                        .map_err(|error| anyhow::anyhow!("Invalid public key: {error}"))?;
+                    let message = read_input(message, file)?;
+                    signature.verify_public_key(&message, &public_key)
+                }
+                (None, Some(address)) => {
+                    let address = match network {
+                        Some(network) => Address::parse_for(&address, network)?,
+                        None => address.parse()?,
+                    };
+                    let message = read_input(message, file)?;
+                    signature.verify(&message, &address)
+                }
+                (None, None) => anyhow::bail!("Pass --pubkey or --address"),
+            };
+
+            render(&Verdict { valid }, format)?;
+            if !valid {
+                std::process::exit(1);
+            }
+        }
+        Commands::Mine {
+            data_dir,
+            network,
+            key,
+            passphrase,
+            count,
+            threads,
+        } => {
+            let config = NodeConfig::builder(load_key_file(&key, passphrase)?)
+                .data_dir(data_dir)
+                .network(NetworkParams::for_network(network))
+                .build();
+            let threads = match threads {
+                Some(threads) => threads,
+                None => std::thread::available_parallelism()?.get(),
+            };
+
+            render(&mine_blocks(Node::new(config)?, count, threads)?, format)?;
+        }
+        Commands::Balance {
+            data_dir,
+            network,
+            address,
+            key,
+            passphrase,
+            utxos,
+        } => {
+            // the address is checked before the data dir is opened
+            let (address, key_pair) = match (address, key) {
+                (Some(address), _) => (Address::parse_for(&address, network)?, KeyPair::generate()),
+                (None, Some(key)) => {
+                    let key_pair = load_key_file(&key, passphrase)?;
+                    let address = Address::from_public_key_for(&key_pair.public_key, network);
+                    (address, key_pair)
+                }
+                (None, None) => anyhow::bail!("Pass --address or --key"),
+            };
+            let node = Node::new(
+                NodeConfig::builder(key_pair)
+                    .data_dir(data_dir)
+                    .network(NetworkParams::for_network(network))
+                    .build(),
+            )?;
+
+            render(&balance(&node, address, utxos)?, format)?;
+        }
+        Commands::Send {
+            rpc,
+            network,
+            key,
+            passphrase,
+            to,
+            amount,
+            fee,
+            wait_confirm,
+            timeout,
+        } => {
+            let key_pair = load_key_file(&key, passphrase)?;
+            let client = rpc.client()?;
+
+            let sent = tokio::runtime::Runtime::new()?.block_on(async {
+                let txid = send_payment(&client, &key_pair, network, &to, amount, fee).await?;
+                let confirmations = match wait_confirm {
+                    Some(confirmations) => {
+                        eprintln!("Sent {txid}");
+                        let timeout = Duration::from_secs(timeout);
+                        Some(wait_for_confirmations(&client, &txid, confirmations, timeout).await?)
+                    }
+                    None => None,
+                };
+                anyhow::Ok(SentTransaction {
+                    txid,
+                    confirmations,
+                })
+            })?;
+            render(&sent, format)?;
+        }
+        Commands::Chain {
+            command: ChainCommands::Info { data_dir },
+        } => render(&chain_info(&data_dir)?, format)?,
+        Commands::Block {
+            command:
+                BlockCommands::Show {
+                    data_dir,
+                    hash,
+                    height,
+                    header_only,
+                },
+        } => {
+            let (block, confirmations) = find_block(&data_dir, hash, height)?;
+            match header_only {
+                true => render(&block.header, format)?,
+                false => render(
+                    &ShownBlock {
+                        block,
+                        confirmations,
+                    },
+                    format,
+                )?,
+            }
+        }
+        Commands::ValidateChain {
+            data_dir,
+            network,
+            from_height,
+            check_utxo,
+        } => {
+            let report = validate_chain(&data_dir, network, from_height, check_utxo)?;
+            render(&report, format)?;
+            if !report.valid {
+                anyhow::bail!("The chain in {} is invalid", data_dir.display());
+            }
+        }
+        Commands::Blocks {
+            command:
+                BlocksCommands::Export {
+                    data_dir,
+                    out,
+                    to_height,
+                    force,
+                },
+        } => {
+            render(&export_blocks(&data_dir, &out, to_height, force)?, format)?;
+        }
+        Commands::Blocks {
+            command:
+                BlocksCommands::Import {
+                    data_dir,
+                    network,
+                    input,
+                    verify_only,
+                    continue_on_error,
+                },
+        } => {
+            let imported =
+                import_blocks(&data_dir, network, &input, verify_only, continue_on_error)?;
+            render(&imported, format)?;
+            if imported.summary.rejected > 0 && !continue_on_error {
+                anyhow::bail!("{}", imported.summary.first_rejection.unwrap_or_default());
+            }
+        }
+        Commands::Tx {
+            command:
+                TxCommands::Create {
+                    data_dir,
+                    network,
+                    key,
+                    passphrase,
+                    to,
+                    amount,
+                    fee,
+                },
+        } => {
+            let config = NodeConfig::builder(load_key_file(&key, passphrase)?)
+                .data_dir(data_dir)
+                .network(NetworkParams::for_network(network))
+                .build();
+            let created = create_transaction(&Node::new(config)?, &to, amount, fee)?;
+            render(&created, cli.output.unwrap_or(OutputFormat::Json))?;
+        }
+        Commands::Tx {
+            command:
+                TxCommands::Decode {
+                    hex,
+                    file,
+                    data_dir,
+                    network,
+                },
+        } => {
+            let raw = String::from_utf8(read_input(hex, file)?)?;
+            let node = match data_dir {
+                Some(data_dir) => Some(Node::new(
+                    NodeConfig::builder(KeyPair::generate())
+                        .data_dir(data_dir)
+                        .network(NetworkParams::for_network(network))
+                        .build(),
+                )?),
+                None => None,
+            };
+
+            render(&decode_transaction(&raw, node.as_ref())?, format)?;
+        }
+        Commands::Node {
+            command: NodeCommands::Run { node, passphrase },
+        } => {
+            let mut config = node.config()?;
+            if let Some(passphrase) = passphrase {
+                config.keypair =
+                    KeyPair::load_or_create_encrypted(&config.keypair_path(), &passphrase)?;
+            }
+
+            tokio::runtime::Runtime::new()?.block_on(run_node(config))?;
+        }
+        Commands::Config {
+            command: ConfigCommands::Init { out, force },
+        } => {
+            if out.exists() && !force {
+                anyhow::bail!(
+                    "{} already exists, pass --force to overwrite it",
+                    out.display()
+                );
+            }
+            std::fs::write(&out, NodeConfig::template())?;
+            eprintln!("Wrote {}", out.display());
+        }
+        Commands::Config {
+            command: ConfigCommands::Show { node },
+        } => render(&ConfigInfo(node.config()?), format)?,
+        Commands::Generate {
+            rpc,
+            address,
+            count,
+        } => {
+            let address = address.parse::<Address>()?;
+            let hashes = tokio::runtime::Runtime::new()?
+                .block_on(rpc.client()?.generate(count, &address))?;
+            let generated = GeneratedBlocks {
+                hashes: hashes
+                    .iter()
+                    .map(|hash| hash.display().to_string())
+                    .collect(),
+            };
+            render(&generated, format)?;
+        }
+        Commands::Mempool { rpc, command } => {
+            let client = rpc.client()?;
+            let runtime = tokio::runtime::Runtime::new()?;
+            match command {
+                MempoolCommands::List => {
+                    render(&runtime.block_on(list_mem_pool(&client))?, format)?
+                }
+                MempoolCommands::Get { txid } => render(
+                    &runtime.block_on(get_pending_transaction(&client, &txid))?,
+                    format,
+                )?,
+                MempoolCommands::Stats => {
+                    render(&runtime.block_on(client.get_mem_pool_stats())?, format)?
+                }
+            }
+        }
+        Commands::MerkleProof {
+            command:
+                Some(MerkleProofCommands::Verify {
+                    root,
+                    txid,
+                    proof,
+                    count,
+                }),
+            ..
+        } => {
+            let root = Hash::from_hex(&root)
+                .map_err(|error| anyhow::anyhow!("Invalid merkle root: {error}"))?;
+            let id = parse_txid(&txid)?;
+            let proof = TransactionProof::from_hex(&proof)?;
+
+            let valid = proof.verify(&root, &id, count);
+            render(&Verdict { valid }, format)?;
+            if !valid {
+                std::process::exit(1);
+            }
+        }
+        Commands::MerkleProof {
+            command: None,
+            data_dir,
+            tx,
+        } => {
+            let (Some(data_dir), Some(tx)) = (data_dir, tx) else {
+                anyhow::bail!("Pass --data-dir and --tx");
+            };
+            render(&prove_transaction(&data_dir, &tx)?, format)?;
+        }
+        Commands::Wallet { command } => match command {
+            WalletCommands::Create {
+                file,
+                network,
+                mnemonic,
+                words,
+                recover,
+                mnemonic_passphrase,
+                force,
+            } => {
+                if file.wallet.exists() && !force {
+                    anyhow::bail!(
+                        "{} already exists, pass --force to overwrite it",
+                        file.wallet.display()
+                    );
+                }
+                let mnemonic = match recover {
+                    Some(phrase) => Some(Mnemonic::parse(&phrase)?),
+                    None if mnemonic => Some(Mnemonic::generate(words)?),
+                    None => None,
+                };
+                let wallet = match &mnemonic {
+                    Some(mnemonic) => {
+                        Wallet::from_mnemonic(mnemonic, &mnemonic_passphrase, network)?
+                    }
+                    None => Wallet::new(&ExtendedPrivKey::new_master(
+                        &secp256k1::rand::random::<[u8; 32]>(),
+                        network,
+                    )?)?,
+                };
+
+                let passphrase = match file.passphrase {
+                    Some(passphrase) => passphrase,
+                    None => prompt_new_passphrase()?,
+                };
+                wallet.save_encrypted(&file.wallet, &passphrase)?;
+
+                let info = wallet_info(
+                    &file.wallet,
+                    &wallet,
+                    mnemonic.map(|mnemonic| mnemonic.to_string()),
+                )?;
+                render(&info, format)?;
+            }
+            WalletCommands::Info { file } => {
+                let (wallet, _) = load_wallet(&file)?;
+                let info = wallet_info(&file.wallet, &wallet, None)?;
+                render(&info, format)?;
+            }
+            WalletCommands::NewAddress { file, label } => {
+                let (mut wallet, passphrase) = load_wallet(&file)?;
+                let (index, address) = wallet.new_address()?;
+                if let Some(label) = label {
+                    wallet.labels.insert(index, label);
+                }
+                wallet.save_encrypted(&file.wallet, &passphrase)?;
+
+                let address = WalletAddress::new(&wallet, index, address);
+                render(&address, format)?;
+            }
+            WalletCommands::Rescan { file, data_dir } => {
+                let (mut wallet, passphrase) = load_wallet(&file)?;
+                let node = Node::new(
+                    NodeConfig::builder(KeyPair::generate())
+                        .data_dir(data_dir)
+                        .network(NetworkParams::for_network(wallet.network()))
+                        .build(),
+                )?;
+                wallet.rescan(&node.state.utxo_set, node.state.chain.height())?;
+                wallet.save_encrypted(&file.wallet, &passphrase)?;
+
+                let info = wallet_info(&file.wallet, &wallet, None)?;
+                render(&info, format)?;
+            }
+        },
+        Commands::Genesis {
+            command:
+                GenesisCommands::Init {
+                    out,
+                    network,
+                    reward,
+                    halving,
+                    tail_emission,
+                    difficulty,
+                    message,
+                    premine,
+                    authority,
+                    threads,
+                    force,
+                },
+        } => {
+            let authorities = authority
+                .iter()
+                .map(|public_key| {
+                    PublicKey::from_slice(&from_hex(public_key)?)
+                        .map(PublicKeyOnly)
+                        .map_err(|error| anyhow::anyhow!("Invalid authority {public_key}: {error}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let (difficulty, consensus) = if authorities.is_empty() {
+                (difficulty, ConsensusMode::ProofOfWork)
+            } else {
+                (0, ConsensusMode::ProofOfAuthority { authorities })
+            };
+            let params = NetworkParams {
+                network,
+                genesis_block_reward: reward,
+                blocks_per_reward_halving: halving,
+                tail_emission,
+                genesis_timestamp: chrono::Utc::now().timestamp() as u32,
+                genesis_difficulty: difficulty,
+                genesis_nonce: None,
+                genesis_message: Some(message),
+                premine: premine
+                    .iter()
+                    .map(|allocation| parse_allocation(allocation, network))
+                    .collect::<Result<_>>()?,
+                consensus,
+            };
+            params.check()?;
+            let threads = match threads {
+                Some(threads) => threads,
+                None => std::thread::available_parallelism()?.get(),
+            };
+            render(&init_genesis(params, &out, threads, force)?, format)?;
+        }
+        Commands::Genesis {
+            command: GenesisCommands::Inspect { params },
+        } => {
+            let params = NodeConfig::load(&params)?.network;
+            render(
+                &GenesisInfo::new(&params, &params.genesis_block()?)?,
+                format,
+            )?;
+        }
+        Commands::Bench {
+            secs,
+            threads,
+            block_time,
+        } => {
+            let threads = match threads {
+                Some(threads) => threads,
+                None => std::thread::available_parallelism()?.get(),
+            };
+            let duration = Duration::try_from_secs_f64(secs)
+                .map_err(|error| anyhow::anyhow!("Invalid --secs {secs}: {error}"))?;
+            render(&run_bench(duration, threads, block_time)?, format)?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,26 +1,612 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::Block,
+    config::MemPoolConfig,
+    constants::BLOCK_SIZE_LIMIT,
+    outcome::RejectReason,
+    transaction::{Transaction, TxId, TxValidationError},
+    utxo_set::{UTXOSet, UtxoError},
+};
+
+/// Why a transaction can't join the pool.
+#[derive(Debug, thiserror::Error)]
+pub enum MempoolError {
+    #[error("Mempool is full")]
+    Full,
+    /// A pending transaction already spends the same output.
+    #[error("Input is already spent by pending transaction {txid}")]
+    Conflict { txid: TxId },
+    #[error("Transaction fee of {fee} is below the minimum of {min_fee}")]
+    LowFee { fee: u64, min_fee: u64 },
+    #[error(transparent)]
+    Utxo(#[from] UtxoError),
+    /// The pool itself failed, through no fault of the transaction.
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<TxValidationError> for MempoolError {
+    fn from(error: TxValidationError) -> Self {
+        Self::Utxo(error.into())
+    }
+}
 
-use crate::{transaction::Transaction, utxo_set::UTXOSet};
+impl MempoolError {
+    /// The reason to give the transaction's sender, or the internal error if there's none.
+    pub fn into_reject_reason(self) -> Result<RejectReason> {
+        match self {
+            Self::Full => Ok(RejectReason::PoolFull),
+            Self::Conflict { txid } => Ok(RejectReason::Conflict { txid }),
+            Self::LowFee { fee, min_fee } => Ok(RejectReason::LowFee { fee, min_fee }),
+            Self::Utxo(error) => Ok(error.into()),
+            Self::Internal(error) => Err(error),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct MemPool {
-    pub pending_transactions: Vec<Transaction>,
+    pub config: MemPoolConfig,
+    pub pending_transactions: Vec<Arc<Transaction>>,
+    /// Unix time each pending transaction entered the pool, or the pool was loaded.
+    pub arrivals: HashMap<TxId, u32>,
+}
+
+/// A pending transaction as `entries` describes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemPoolEntry {
+    pub txid: TxId,
+    pub size: usize,
+    pub fee: u64,
+    /// Fee per 1000 bytes.
+    pub fee_rate: f64,
+    pub time: u32,
+    /// The pending transaction whose output this one spends, if any.
+    #[serde(default)]
+    pub depends: Option<TxId>,
+}
+
+/// Pending transactions whose fee rates fall in `[min_fee_rate, 2 * min_fee_rate)`, or
+/// below 1 for the first bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeRateBucket {
+    pub min_fee_rate: f64,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemPoolStats {
+    pub count: usize,
+    pub bytes: usize,
+    pub total_fees: u64,
+    pub min_fee_rate: Option<f64>,
+    /// The non-empty buckets, by fee rate.
+    pub histogram: Vec<FeeRateBucket>,
+}
+
+impl MemPoolStats {
+    pub fn new(entries: &[MemPoolEntry]) -> Self {
+        let mut buckets = BTreeMap::<u32, FeeRateBucket>::new();
+        for entry in entries {
+            let exponent = match entry.fee_rate {
+                rate if rate < 1.0 => 0,
+                rate => rate.log2().floor() as u32 + 1,
+            };
+            let bucket = buckets.entry(exponent).or_insert(FeeRateBucket {
+                min_fee_rate: match exponent {
+                    0 => 0.0,
+                    exponent => 2f64.powi(exponent as i32 - 1),
+                },
+                count: 0,
+                bytes: 0,
+            });
+            bucket.count += 1;
+            bucket.bytes += entry.size;
+        }
+
+        Self {
+            count: entries.len(),
+            bytes: entries.iter().map(|entry| entry.size).sum(),
+            total_fees: entries.iter().map(|entry| entry.fee).sum(),
+            min_fee_rate: entries
+                .iter()
+                .map(|entry| entry.fee_rate)
+                .min_by(f64::total_cmp),
+            histogram: buckets.into_values().collect(),
+        }
+    }
+}
+
+/// The pending transactions `txid` spends from, nearest first.
+pub fn ancestors(entries: &[MemPoolEntry], txid: &TxId) -> Vec<TxId> {
+    let parents = entries
+        .iter()
+        .filter_map(|entry| Some((&entry.txid, entry.depends.as_ref()?)))
+        .collect::<HashMap<_, _>>();
+
+    let mut ancestors = Vec::new();
+    let mut current = txid;
+    while let Some(&parent) = parents.get(current) {
+        ancestors.push(parent.clone());
+        current = parent;
+    }
+    ancestors
+}
+
+/// The pending transactions spending from `txid`, directly or through others.
+pub fn descendants(entries: &[MemPoolEntry], txid: &TxId) -> Vec<TxId> {
+    let mut descendants = Vec::new();
+    let mut frontier = vec![txid];
+    while let Some(parent) = frontier.pop() {
+        for entry in entries {
+            if entry.depends.as_ref() == Some(parent) {
+                descendants.push(entry.txid.clone());
+                frontier.push(&entry.txid);
+            }
+        }
+    }
+    descendants
 }
 
 impl MemPool {
-    pub fn add(&mut self, utxo_set: &UTXOSet, transaction: Transaction) -> Result<()> {
+    pub fn new(config: MemPoolConfig) -> Self {
+        Self {
+            config,
+            pending_transactions: Vec::new(),
+            arrivals: HashMap::new(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_vec(&self.pending_transactions)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path, config: MemPoolConfig) -> Result<Self> {
+        let pending_transactions: Vec<Arc<Transaction>> = serde_json::from_slice(&fs::read(path)?)?;
+        let now = now();
+        let arrivals = pending_transactions
+            .iter()
+            .map(|tx| Ok((tx.id()?, now)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            config,
+            pending_transactions,
+            arrivals,
+        })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.pending_transactions.len() >= self.config.max_transactions
+    }
+
+    /// As many pending transactions as a block at `height` holds beside its coinbase,
+    /// taken in pool order so each follows any it spends from, and the fees they pay.
+    pub fn block_transactions(
+        &self,
+        utxo_set: &UTXOSet,
+        height: u32,
+    ) -> Result<(Vec<Arc<Transaction>>, u64)> {
+        let transactions = self
+            .pending_transactions
+            .iter()
+            .take(BLOCK_SIZE_LIMIT - 1)
+            .cloned()
+            .collect::<Vec<_>>();
         let mut pending_utxo_set = utxo_set.clone();
-        for tx in self.pending_transactions.iter() {
+        let mut fees = 0;
+
+        for tx in &transactions {
+            fees += pending_utxo_set.validate_transaction(tx, height)?;
             pending_utxo_set.update(tx)?;
         }
 
-        pending_utxo_set.validate_transaction(&transaction)?;
+        Ok((transactions, fees))
+    }
+
+    pub fn add(
+        &mut self,
+        utxo_set: &UTXOSet,
+        transaction: Arc<Transaction>,
+        height: u32,
+    ) -> Result<(), MempoolError> {
+        let id = transaction.id()?;
+        let _span = tracing::debug_span!("mempool.add", txid = %id).entered();
+        self.check(utxo_set, &transaction, height)?;
+
+        self.arrivals.insert(id, now());
         self.pending_transactions.push(transaction);
 
         Ok(())
     }
 
-    pub fn drain(&mut self) -> Vec<Transaction> {
+    /// Whether `transaction` could be added on top of `utxo_set`, to be confirmed at
+    /// `height` (the tip's height plus one), returning its fee.
+    pub fn check(
+        &self,
+        utxo_set: &UTXOSet,
+        transaction: &Transaction,
+        height: u32,
+    ) -> Result<u64, MempoolError> {
+        if self.is_full() {
+            return Err(MempoolError::Full);
+        }
+
+        transaction.validate_standard(self.config.dust_limit)?;
+
+        if let Some(outpoint) = transaction.body.input.outpoint() {
+            let conflict = self
+                .pending_transactions
+                .iter()
+                .find(|tx| tx.body.input.outpoint() == Some(outpoint));
+            if let Some(conflict) = conflict {
+                return Err(MempoolError::Conflict {
+                    txid: conflict.id()?,
+                });
+            }
+        }
+
+        let fee =
+            utxo_set.validate_transaction_after(transaction, &self.pending_transactions, height)?;
+
+        if fee < self.config.min_fee {
+            return Err(MempoolError::LowFee {
+                fee,
+                min_fee: self.config.min_fee,
+            });
+        }
+
+        Ok(fee)
+    }
+
+    /// Describes the pending transactions, in pool order, as confirmed at `height`.
+    pub fn entries(&self, utxo_set: &UTXOSet, height: u32) -> Result<Vec<MemPoolEntry>> {
+        let mut pending_utxo_set = utxo_set.clone();
+        let mut pending_ids = HashSet::new();
+        let mut entries = Vec::with_capacity(self.pending_transactions.len());
+
+        for tx in &self.pending_transactions {
+            let fee = pending_utxo_set.validate_transaction(tx, height)?;
+            pending_utxo_set.update(tx)?;
+
+            let txid = tx.id()?;
+            let size = tx.size()?;
+            let depends = tx
+                .body
+                .input
+                .outpoint()
+                .filter(|outpoint| pending_ids.contains(&outpoint.id))
+                .map(|outpoint| outpoint.id.clone());
+            pending_ids.insert(txid.clone());
+
+            entries.push(MemPoolEntry {
+                time: self.arrivals.get(&txid).copied().unwrap_or_else(now),
+                txid,
+                size,
+                fee,
+                fee_rate: fee as f64 * 1000.0 / size as f64,
+                depends,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    pub fn drain(&mut self) -> Vec<Arc<Transaction>> {
+        self.arrivals.clear();
         self.pending_transactions.drain(..).collect()
     }
+
+    /// Drops transactions confirmed by `block`, along with any remaining transactions
+    /// that are no longer valid against the updated `utxo_set`.
+    pub fn remove_confirmed(&mut self, utxo_set: &UTXOSet, block: &Block) -> Result<()> {
+        self.reorganize(utxo_set, block.height + 1, &[], &[block])
+    }
+
+    /// Updates the pool for a chain switch: transactions from `disconnected` blocks (in
+    /// ascending height order) go back ahead of those already pending, then anything
+    /// confirmed by `connected` blocks or no longer valid against `utxo_set`, for
+    /// confirmation at `height`, is dropped. A reorg to a shorter chain can lock inputs
+    /// again.
+    pub fn reorganize(
+        &mut self,
+        utxo_set: &UTXOSet,
+        height: u32,
+        disconnected: &[&Block],
+        connected: &[&Block],
+    ) -> Result<()> {
+        let confirmed_ids = connected
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .map(|tx| tx.id())
+            .collect::<Result<HashSet<_>>>()?;
+
+        let returned = disconnected
+            .iter()
+            .flat_map(|block| block.transactions.iter().skip(1))
+            .cloned();
+
+        let candidates = returned
+            .chain(self.pending_transactions.drain(..))
+            .collect::<Vec<_>>();
+
+        let mut pending_utxo_set = utxo_set.clone();
+        let mut remaining = Vec::new();
+
+        for tx in candidates {
+            if remaining.len() >= self.config.max_transactions || confirmed_ids.contains(&tx.id()?)
+            {
+                continue;
+            }
+
+            if pending_utxo_set.validate_transaction(&tx, height).is_ok() {
+                pending_utxo_set.update(&tx)?;
+                remaining.push(tx);
+            }
+        }
+
+        let now = now();
+        let arrivals = remaining
+            .iter()
+            .map(|tx| {
+                let id = tx.id()?;
+                let time = self.arrivals.get(&id).copied().unwrap_or(now);
+                Ok((id, time))
+            })
+            .collect::<Result<_>>()?;
+        self.arrivals = arrivals;
+        self.pending_transactions = remaining;
+
+        Ok(())
+    }
+}
+
+fn now() -> u32 {
+    chrono::Utc::now().timestamp() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAX_TRANSACTION_SIZE;
+    use crate::crypto::{Address, Hash, KeyPair};
+    use crate::fixtures;
+    use crate::params::NetworkParams;
+    use crate::transaction::{
+        TransactionBody, TransactionInput, TransactionOutput, TransactionOutputReference,
+    };
+
+    fn entry(id: u8, depends: Option<u8>, fee: u64, size: usize) -> MemPoolEntry {
+        let txid = |id| TxId(Hash::from([id; 32]));
+        MemPoolEntry {
+            txid: txid(id),
+            size,
+            fee,
+            fee_rate: fee as f64 * 1000.0 / size as f64,
+            time: 0,
+            depends: depends.map(txid),
+        }
+    }
+
+    #[test]
+    fn test_entry_relations_and_stats() {
+        // 1 <- 2 <- 3, and 1 <- 4
+        let entries = [
+            entry(1, None, 0, 200),
+            entry(2, Some(1), 1, 200),
+            entry(3, Some(2), 2, 200),
+            entry(4, Some(1), 1, 400),
+        ];
+        let ids = |ids: &[u8]| {
+            ids.iter()
+                .map(|&id| entry(id, None, 0, 1).txid)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ancestors(&entries, &entries[2].txid), ids(&[2, 1]));
+        assert!(ancestors(&entries, &entries[0].txid).is_empty());
+        let mut below = descendants(&entries, &entries[0].txid);
+        below.sort_by_key(|txid| txid.0);
+        assert_eq!(below, ids(&[2, 3, 4]));
+
+        let stats = MemPoolStats::new(&entries);
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.bytes, 1000);
+        assert_eq!(stats.total_fees, 4);
+        assert_eq!(stats.min_fee_rate, Some(0.0));
+        // fee rates of 0, 5, 10, and 2.5 per 1000 bytes
+        let buckets = stats
+            .histogram
+            .iter()
+            .map(|bucket| (bucket.min_fee_rate, bucket.count))
+            .collect::<Vec<_>>();
+        assert_eq!(buckets, [(0.0, 1), (2.0, 1), (4.0, 1), (8.0, 1)]);
+
+        assert_eq!(MemPoolStats::new(&[]), MemPoolStats::default());
+    }
+
+    #[test]
+    fn test_check_reasons() {
+        let key_pair = KeyPair::generate();
+        let address = Address::from_public_key(&key_pair.public_key);
+        let coinbase =
+            Arc::new(Transaction::new_coinbase(&key_pair, 1, &NetworkParams::default()).unwrap());
+        let mut utxo_set = UTXOSet::default();
+        utxo_set.update(&coinbase).unwrap();
+
+        let spend = |outpoint: TransactionOutputReference, value| {
+            TransactionBody {
+                input: TransactionInput::spending(outpoint),
+                outputs: vec![TransactionOutput {
+                    value,
+                    address: address.clone(),
+                }],
+            }
+            .into_tx(&key_pair)
+            .unwrap()
+        };
+
+        let mut mem_pool = MemPool::new(MemPoolConfig::default());
+        let outpoint = coinbase.output_reference(0).unwrap();
+        let payment = spend(outpoint.clone(), 1);
+        mem_pool
+            .add(&utxo_set, Arc::new(payment.clone()), 2)
+            .unwrap();
+
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &spend(outpoint.clone(), 2), 2),
+            Err(MempoolError::Conflict { txid }) if txid == payment.id().unwrap()
+        ));
+        let missing = TransactionOutputReference {
+            index: 1,
+            ..outpoint.clone()
+        };
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &spend(missing.clone(), 1), 2),
+            Err(MempoolError::Utxo(UtxoError::MissingOutpoint { outpoint })) if outpoint == missing
+        ));
+
+        let mut mem_pool = MemPool::new(MemPoolConfig {
+            min_fee: 1,
+            ..MemPoolConfig::default()
+        });
+        let coinbase_value = coinbase.output_value();
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &spend(outpoint.clone(), coinbase_value), 2),
+            Err(MempoolError::LowFee { fee: 0, .. })
+        ));
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &spend(outpoint.clone(), coinbase_value + 1), 2),
+            Err(MempoolError::Utxo(UtxoError::ValueMismatch { .. }))
+        ));
+        let rejected = mem_pool
+            .check(&utxo_set, &spend(outpoint.clone(), coinbase_value), 2)
+            .unwrap_err()
+            .into_reject_reason()
+            .unwrap();
+        assert_eq!(rejected, RejectReason::LowFee { fee: 0, min_fee: 1 });
+
+        // outputs below the dust limit, zero-value ones included, and oversized
+        // transactions are refused, though a block may hold them
+        let dust = mem_pool
+            .check(&utxo_set, &spend(outpoint.clone(), 0), 2)
+            .unwrap_err()
+            .into_reject_reason()
+            .unwrap();
+        assert_eq!(
+            dust,
+            RejectReason::InvalidTransaction(
+                TxValidationError::Dust {
+                    index: 0,
+                    value: 0,
+                    dust_limit: 1,
+                }
+                .into()
+            )
+        );
+        assert_eq!(dust.misbehavior(), 0);
+        let oversized = TransactionBody {
+            input: TransactionInput::spending(outpoint.clone()),
+            outputs: vec![
+                TransactionOutput {
+                    value: 1,
+                    address: address.clone(),
+                };
+                MAX_TRANSACTION_SIZE / 20
+            ],
+        }
+        .into_tx(&key_pair)
+        .unwrap();
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &oversized, 2),
+            Err(MempoolError::Utxo(UtxoError::Transaction(
+                TxValidationError::Oversized { size, max_size: MAX_TRANSACTION_SIZE }
+            ))) if size > MAX_TRANSACTION_SIZE
+        ));
+
+        mem_pool.config.max_transactions = 0;
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &spend(outpoint, 1), 2),
+            Err(MempoolError::Full)
+        ));
+    }
+
+    #[test]
+    fn test_block_transactions() {
+        let params = NetworkParams::regtest();
+        let key_pair = fixtures::key_pair(1);
+        let (funding, utxo_set) =
+            fixtures::funding(BLOCK_SIZE_LIMIT, 10, &key_pair, &params).unwrap();
+        let spends = fixtures::spends(&funding, &key_pair, 1).unwrap();
+
+        let mut mem_pool = MemPool::new(MemPoolConfig::default());
+        for spend in &spends {
+            mem_pool.add(&utxo_set, spend.clone(), 2).unwrap();
+        }
+
+        // a block has room for all but one beside its coinbase, the first in pool order
+        let (transactions, fees) = mem_pool.block_transactions(&utxo_set, 2).unwrap();
+        assert_eq!(transactions, spends[..BLOCK_SIZE_LIMIT - 1]);
+        assert_eq!(fees, BLOCK_SIZE_LIMIT as u64 - 1);
+    }
+
+    #[test]
+    fn test_relative_lock() {
+        let params = NetworkParams::regtest();
+        let key_pair = fixtures::key_pair(1);
+        let (funding, utxo_set) = fixtures::funding(1, 100, &key_pair, &params).unwrap();
+        let locked = |outpoint: TransactionOutputReference, relative_lock| {
+            let address = funding.body.outputs[0].address.clone();
+            TransactionBody {
+                input: TransactionInput::Reference {
+                    outpoint,
+                    relative_lock,
+                },
+                outputs: vec![TransactionOutput { value: 99, address }],
+            }
+            .into_tx(&key_pair)
+            .map(Arc::new)
+            .unwrap()
+        };
+
+        // funded at height 1 and locked for 2 blocks, so it can first go in block 3,
+        // once the tip is at 2
+        let spend = locked(funding.output_reference(0).unwrap(), Some(2));
+        let mut mem_pool = MemPool::new(MemPoolConfig::default());
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &spend, 2),
+            Err(MempoolError::Utxo(UtxoError::Locked {
+                spendable_at: 3,
+                ..
+            }))
+        ));
+        mem_pool.add(&utxo_set, spend.clone(), 3).unwrap();
+
+        // spending its output, still pending, can't be locked at all
+        let child_outpoint = spend.output_reference(0).unwrap();
+        assert!(matches!(
+            mem_pool.check(&utxo_set, &locked(child_outpoint.clone(), Some(0)), 3),
+            Err(MempoolError::Utxo(UtxoError::LockedUnconfirmed { .. }))
+        ));
+        let child = locked(child_outpoint, None);
+        mem_pool.add(&utxo_set, child.clone(), 3).unwrap();
+
+        mem_pool.reorganize(&utxo_set, 3, &[], &[]).unwrap();
+        assert_eq!(mem_pool.pending_transactions, vec![spend, child]);
+
+        // a reorg back to a tip at height 1 locks the spend again, orphaning its child
+        mem_pool.reorganize(&utxo_set, 2, &[], &[]).unwrap();
+        assert!(mem_pool.pending_transactions.is_empty());
+    }
 }
@@ -0,0 +1,389 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::{FromRef, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use prometheus::{Encoder, Gauge, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crypto::HashExt,
+    mem_pool::MemPool,
+    miner::MinerStats,
+    node::Node,
+    outcome::RejectReason,
+    p2p::{PeerInfo, PeerList},
+    shared_node::SharedNode,
+    utxo_set::UTXOSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeMetrics {
+    pub chain_height: u32,
+    pub tip_hash: Option<String>,
+    pub total_work_hex: String,
+    pub utxo_count: usize,
+    pub utxo_total_value: u64,
+    pub mempool_count: usize,
+    pub mempool_bytes: usize,
+    pub orphan_blocks: usize,
+    pub syncing: bool,
+    pub best_header_height: u32,
+    pub utxo_set_rebuilds: u64,
+    pub blocks_validated: u64,
+    pub seen_duplicates: u64,
+    pub peers: usize,
+    pub routing_table_size: usize,
+    pub peer_info: Vec<PeerInfo>,
+    pub uptime_secs: u64,
+    pub blocks_connected: u64,
+    pub blocks_disconnected: u64,
+    pub reorgs: u64,
+    pub max_reorg_depth: u64,
+    pub transactions_accepted: u64,
+    /// By `RejectReason::kind`.
+    pub transactions_rejected: BTreeMap<String, u64>,
+    /// Over every p2p connection since the service started.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Hashes per second, while the miner is running.
+    pub miner_hashrate: Option<f64>,
+}
+
+/// Aggregates that would otherwise need a full scan, kept up to date by `NodeState`
+/// wherever it emits the corresponding `NodeEvent`s.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsCache {
+    pub utxo_total_value: u64,
+    pub mempool_bytes: usize,
+    /// UTXO sets built for chain switches, one per switch however many blocks it connects.
+    pub utxo_set_rebuilds: u64,
+    /// Blocks that got past the duplicate check to be validated.
+    pub blocks_validated: u64,
+    /// Gossip and announcements the p2p service dropped as already seen.
+    pub seen_duplicates: u64,
+    /// Peers subscribed to our gossip topics, kept up to date by the p2p service.
+    pub peers: usize,
+    /// Peers in the Kademlia routing table, also kept up to date by the p2p service.
+    pub routing_table_size: usize,
+    /// The p2p service's peer list, read whenever metrics are.
+    pub peer_list: PeerList,
+    pub blocks_connected: u64,
+    pub blocks_disconnected: u64,
+    /// Chain switches that disconnected blocks, and the most any one disconnected.
+    pub reorgs: u64,
+    pub max_reorg_depth: u64,
+    pub transactions_accepted: u64,
+    pub transactions_rejected: BTreeMap<String, u64>,
+    /// The running miner's stats, set by whoever starts it.
+    pub miner: Option<Arc<MinerStats>>,
+}
+
+impl MetricsCache {
+    pub fn refresh_utxo_set(&mut self, utxo_set: &UTXOSet) {
+        self.utxo_total_value = utxo_set.total_value();
+    }
+
+    pub fn refresh_mem_pool(&mut self, mem_pool: &MemPool) -> Result<()> {
+        self.mempool_bytes = mem_pool
+            .pending_transactions
+            .iter()
+            .map(|tx| tx.size())
+            .sum::<Result<usize>>()?;
+        Ok(())
+    }
+
+    pub fn record_transaction(&mut self, size: usize) {
+        self.mempool_bytes += size;
+        self.transactions_accepted += 1;
+    }
+
+    pub fn record_rejection(&mut self, reason: &RejectReason) {
+        *self
+            .transactions_rejected
+            .entry(reason.kind().to_string())
+            .or_default() += 1;
+    }
+
+    pub fn record_chain_update(&mut self, disconnected: usize, connected: usize) {
+        self.blocks_connected += connected as u64;
+        self.blocks_disconnected += disconnected as u64;
+        if disconnected > 0 {
+            self.reorgs += 1;
+            self.max_reorg_depth = self.max_reorg_depth.max(disconnected as u64);
+        }
+    }
+}
+
+impl Node {
+    pub fn metrics(&self) -> Result<NodeMetrics> {
+        let state = &self.state;
+        let (bytes_in, bytes_out) = state.metrics.peer_list.total_bytes();
+
+        Ok(NodeMetrics {
+            chain_height: state.chain.height(),
+            tip_hash: self.tip_hash()?.map(|hash| hash.display().to_string()),
+            total_work_hex: format!("{:x}", state.chain.chain_work().unwrap_or_default()),
+            utxo_count: state.utxo_set.outputs.len(),
+            utxo_total_value: state.metrics.utxo_total_value,
+            mempool_count: state.mem_pool.pending_transactions.len(),
+            mempool_bytes: state.metrics.mempool_bytes,
+            orphan_blocks: state.block_manager.orphan_blocks.len(),
+            syncing: state.is_syncing(),
+            best_header_height: state.best_header_height,
+            utxo_set_rebuilds: state.metrics.utxo_set_rebuilds,
+            blocks_validated: state.metrics.blocks_validated,
+            seen_duplicates: state.metrics.seen_duplicates,
+            peers: state.metrics.peers,
+            routing_table_size: state.metrics.routing_table_size,
+            peer_info: state.metrics.peer_list.connected(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            blocks_connected: state.metrics.blocks_connected,
+            blocks_disconnected: state.metrics.blocks_disconnected,
+            reorgs: state.metrics.reorgs,
+            max_reorg_depth: state.metrics.max_reorg_depth,
+            transactions_accepted: state.metrics.transactions_accepted,
+            transactions_rejected: state.metrics.transactions_rejected.clone(),
+            bytes_in,
+            bytes_out,
+            miner_hashrate: state.metrics.miner.as_ref().map(|miner| miner.hashrate()),
+        })
+    }
+}
+
+impl NodeMetrics {
+    /// The metrics in the Prometheus text exposition format, named `tiny_crypto_*`.
+    pub fn to_prometheus(&self) -> Result<String> {
+        let registry = Registry::new_custom(Some("tiny_crypto".to_string()), None)?;
+        let gauge = |name: &str, help: &str, value: f64| -> Result<()> {
+            let gauge = Gauge::new(name, help)?;
+            gauge.set(value);
+            Ok(registry.register(Box::new(gauge))?)
+        };
+        let counter = |name: &str, help: &str, value: u64| -> Result<()> {
+            let counter = IntCounter::new(name, help)?;
+            counter.inc_by(value);
+            Ok(registry.register(Box::new(counter))?)
+        };
+
+        gauge(
+            "chain_height",
+            "Height of the tip",
+            self.chain_height as f64,
+        )?;
+        gauge(
+            "best_header_height",
+            "Best height peers have announced",
+            self.best_header_height as f64,
+        )?;
+        gauge(
+            "syncing",
+            "1 during initial block download",
+            self.syncing as u8 as f64,
+        )?;
+        gauge("utxo_count", "Unspent outputs", self.utxo_count as f64)?;
+        gauge(
+            "utxo_total_value",
+            "Coins in unspent outputs",
+            self.utxo_total_value as f64,
+        )?;
+        gauge(
+            "mempool_transactions",
+            "Pending transactions",
+            self.mempool_count as f64,
+        )?;
+        gauge(
+            "mempool_bytes",
+            "Size of pending transactions",
+            self.mempool_bytes as f64,
+        )?;
+        gauge(
+            "orphan_blocks",
+            "Blocks waiting on their parent",
+            self.orphan_blocks as f64,
+        )?;
+        gauge("peers", "Peers subscribed to our topics", self.peers as f64)?;
+        gauge(
+            "routing_table_size",
+            "Peers in the Kademlia routing table",
+            self.routing_table_size as f64,
+        )?;
+        gauge(
+            "uptime_seconds",
+            "Seconds since the node started",
+            self.uptime_secs as f64,
+        )?;
+        gauge(
+            "max_reorg_depth",
+            "Most blocks a single reorg disconnected",
+            self.max_reorg_depth as f64,
+        )?;
+        if let Some(hashrate) = self.miner_hashrate {
+            gauge(
+                "miner_hashrate",
+                "Hashes per second since the miner started",
+                hashrate,
+            )?;
+        }
+
+        counter(
+            "blocks_validated_total",
+            "Blocks validated",
+            self.blocks_validated,
+        )?;
+        counter(
+            "blocks_connected_total",
+            "Blocks connected to the active chain",
+            self.blocks_connected,
+        )?;
+        counter(
+            "blocks_disconnected_total",
+            "Blocks disconnected from the active chain",
+            self.blocks_disconnected,
+        )?;
+        counter(
+            "reorgs_total",
+            "Chain switches that disconnected blocks",
+            self.reorgs,
+        )?;
+        counter(
+            "utxo_set_rebuilds_total",
+            "UTXO sets built for chain switches",
+            self.utxo_set_rebuilds,
+        )?;
+        counter(
+            "transactions_accepted_total",
+            "Transactions accepted into the mempool",
+            self.transactions_accepted,
+        )?;
+        counter(
+            "seen_duplicates_total",
+            "Gossip dropped as already seen",
+            self.seen_duplicates,
+        )?;
+        counter(
+            "p2p_bytes_received_total",
+            "Bytes received from peers",
+            self.bytes_in,
+        )?;
+        counter(
+            "p2p_bytes_sent_total",
+            "Bytes sent to peers",
+            self.bytes_out,
+        )?;
+
+        let rejected = IntCounterVec::new(
+            Opts::new(
+                "transactions_rejected_total",
+                "Transactions refused, by reason",
+            ),
+            &["reason"],
+        )?;
+        for (reason, count) in &self.transactions_rejected {
+            rejected.with_label_values(&[reason]).inc_by(*count);
+        }
+        registry.register(Box::new(rejected))?;
+
+        let mut text = Vec::new();
+        TextEncoder::new().encode(&registry.gather(), &mut text)?;
+        Ok(String::from_utf8(text)?)
+    }
+}
+
+/// `GET /metrics`, for any router state the node can be taken from.
+pub fn routes<S>() -> Router<S>
+where
+    SharedNode: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route("/metrics", get(scrape))
+}
+
+async fn scrape(State(node): State<SharedNode>) -> Response {
+    match node
+        .metrics()
+        .await
+        .and_then(|metrics| metrics.to_prometheus())
+    {
+        Ok(text) => (
+            [(
+                header::CONTENT_TYPE,
+                TextEncoder::new().format_type().to_string(),
+            )],
+            text,
+        )
+            .into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::*;
+    use crate::constants::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+
+    #[test]
+    fn test_metrics() {
+        let keypair = KeyPair::generate();
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair)
+                .data_dir(data_dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap();
+
+        let reward = GENESIS_BLOCK_REWARD as u64;
+        let genesis_hash = NetworkParams::regtest().genesis_hash().unwrap();
+
+        let fresh = node.metrics().unwrap();
+        assert_eq!(fresh.chain_height, 1);
+        assert_eq!(fresh.tip_hash, Some(genesis_hash.display().to_string()));
+        assert_eq!(fresh.utxo_count, 1);
+        assert_eq!(fresh.utxo_total_value, reward);
+        assert_ne!(fresh.total_work_hex, "0");
+
+        let block = node.create_block().unwrap();
+        let block_hash = block.header.hash().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+
+        let after_block = node.metrics().unwrap();
+        assert_eq!(after_block.chain_height, 2);
+        assert_eq!(after_block.tip_hash, Some(block_hash.display().to_string()));
+        assert_ne!(after_block.total_work_hex, fresh.total_work_hex);
+        assert_eq!(after_block.utxo_count, 2);
+        assert_eq!(after_block.utxo_total_value, 2 * reward);
+        assert_eq!(after_block.mempool_count, 0);
+        assert_eq!(after_block.mempool_bytes, 0);
+
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        node.send(recipient, 20, 1).unwrap();
+
+        let tx_size = node.state.mem_pool.pending_transactions[0].size().unwrap();
+        let after_tx = node.metrics().unwrap();
+        assert_eq!(after_tx.mempool_count, 1);
+        assert_eq!(after_tx.mempool_bytes, tx_size);
+        assert_eq!(after_tx.utxo_total_value, 2 * reward);
+
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+
+        // the fee returns to the miner, so only the new block reward is added
+        let after_confirm = node.metrics().unwrap();
+        assert_eq!(after_confirm.chain_height, 3);
+        assert_eq!(after_confirm.mempool_count, 0);
+        assert_eq!(after_confirm.mempool_bytes, 0);
+        assert_eq!(after_confirm.utxo_count, 4);
+        assert_eq!(after_confirm.utxo_total_value, 3 * reward);
+    }
+}
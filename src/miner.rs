@@ -0,0 +1,260 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+use anyhow::Result;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    crypto::HashExt,
+    node::{Message, NodeEvent},
+    outcome::HandleOutcome,
+    params::ConsensusMode,
+    shared_node::SharedNode,
+};
+
+#[derive(Debug)]
+pub struct MinerStats {
+    started_at: Instant,
+    hashes: AtomicU64,
+    blocks_mined: AtomicU64,
+}
+
+impl Default for MinerStats {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            hashes: AtomicU64::new(0),
+            blocks_mined: AtomicU64::new(0),
+        }
+    }
+}
+
+impl MinerStats {
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+
+    pub fn blocks_mined(&self) -> u64 {
+        self.blocks_mined.load(Ordering::Relaxed)
+    }
+
+    /// Average hashes per second since the miner started.
+    pub fn hashrate(&self) -> f64 {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        self.hashes() as f64 / elapsed
+    }
+}
+
+pub struct MinerHandle {
+    cancel: CancellationToken,
+    stats: Arc<MinerStats>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl MinerHandle {
+    pub fn stats(&self) -> &Arc<MinerStats> {
+        &self.stats
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Cancels the mining loop and waits for the worker thread to exit.
+    pub fn stop(self) -> Result<()> {
+        self.cancel.cancel();
+        self.thread
+            .join()
+            .map_err(|_| anyhow::anyhow!("Miner thread panicked"))?
+    }
+}
+
+fn tip_changed(events: &mut broadcast::Receiver<NodeEvent>) -> bool {
+    loop {
+        match events.try_recv() {
+            Ok(NodeEvent::TipChanged { .. }) | Err(TryRecvError::Lagged(_)) => return true,
+            Ok(_) => continue,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Closed) => return true,
+        }
+    }
+}
+
+fn mine_loop(node: SharedNode, cancel: CancellationToken, stats: Arc<MinerStats>) -> Result<()> {
+    node.blocking_read().check_can_seal()?;
+
+    while !cancel.is_cancelled() {
+        let (mut block, tip_hash, mut events, signer) = {
+            let node = node.blocking_read();
+            let signer = match node.state.params.consensus {
+                ConsensusMode::ProofOfWork => None,
+                ConsensusMode::ProofOfAuthority { .. } => Some(node.config.keypair.clone()),
+            };
+            (
+                node.block_template()?,
+                node.tip_hash()?,
+                node.subscribe(),
+                signer,
+            )
+        };
+
+        match &signer {
+            // authorities sign rather than search for a nonce
+            Some(keypair) => block.sign(keypair)?,
+            None => {
+                // abandon the template as soon as the tip moves, so we never mine on a stale
+                // parent
+                let nonce = block.header.compute_nonce_interruptible(0, |hashes| {
+                    stats.hashes.fetch_add(hashes, Ordering::Relaxed);
+                    cancel.is_cancelled() || tip_changed(&mut events)
+                })?;
+                let Some(nonce) = nonce else {
+                    continue;
+                };
+                block.header.nonce = nonce;
+            }
+        }
+        let hash = block.header.hash()?;
+        let height = block.height;
+
+        let mut node = node.blocking_write();
+        if node.tip_hash()? == tip_hash {
+            match node.handle_message(Message::NewBlock(block))? {
+                (HandleOutcome::Accepted, _) => {
+                    tracing::info!(
+                        block_hash = %format_args!("{:#x}", hash.display()),
+                        height,
+                        "Mined block"
+                    );
+                    stats.blocks_mined.fetch_add(1, Ordering::Relaxed);
+                }
+                (HandleOutcome::Rejected(reason), _) => return Err(reason.into()),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl SharedNode {
+    /// Spawns a background thread that repeatedly mines blocks on the node's tip and
+    /// submits them through `handle_message`, until `cancel` is triggered.
+    pub fn start_miner(&self, cancel: CancellationToken) -> MinerHandle {
+        let node = self.clone();
+        let stats = Arc::new(MinerStats::default());
+
+        let thread = {
+            let cancel = cancel.clone();
+            let stats = stats.clone();
+            std::thread::spawn(move || mine_loop(node, cancel, stats))
+        };
+
+        MinerHandle {
+            cancel,
+            stats,
+            thread,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_miner_mines_blocks() {
+        let keypair = KeyPair::generate();
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let node = Node::new(
+            NodeConfig::builder(keypair)
+                .data_dir(data_dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap();
+
+        let node = SharedNode::new(node);
+        let miner = node.start_miner(CancellationToken::new());
+
+        let started = Instant::now();
+        while node.blocking_read().state.chain.height() < 4 {
+            assert!(!miner.is_finished(), "miner exited early");
+            assert!(
+                started.elapsed() < Duration::from_secs(60),
+                "miner timed out"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let blocks_mined = miner.stats().blocks_mined();
+        miner.stop().unwrap();
+
+        assert!(blocks_mined >= 3);
+        assert!(node.blocking_read().state.chain.height() >= 4);
+    }
+
+    #[test]
+    fn test_miner_signs_as_authority() {
+        let authority = KeyPair::generate();
+        let params = NetworkParams {
+            genesis_message: Some("authority".to_string()),
+            consensus: ConsensusMode::ProofOfAuthority {
+                authorities: vec![PublicKeyOnly::from(&authority)],
+            },
+            ..NetworkParams::regtest()
+        };
+        let node_with = |keypair: KeyPair| {
+            SharedNode::new(
+                Node::in_memory(NodeConfig::builder(keypair).network(params.clone()).build())
+                    .unwrap(),
+            )
+        };
+
+        // a key that isn't an authority is refused before anything is signed
+        let outsider = node_with(KeyPair::generate());
+        let error = outsider
+            .start_miner(CancellationToken::new())
+            .stop()
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("not one of the network's authorities"),
+            "{error}"
+        );
+        assert_eq!(outsider.blocking_read().state.chain.height(), 1);
+
+        let node = node_with(authority);
+        let miner = node.start_miner(CancellationToken::new());
+        let started = Instant::now();
+        while node.blocking_read().state.chain.height() < 4 {
+            assert!(!miner.is_finished(), "miner exited early");
+            assert!(
+                started.elapsed() < Duration::from_secs(60),
+                "miner timed out"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // signed without searching for a nonce
+        assert_eq!(miner.stats().hashes.load(Ordering::Relaxed), 0);
+        miner.stop().unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+use std::{fmt, str::FromStr, sync::LazyLock};
+
+#[cfg(feature = "rand")]
+use secp256k1::rand;
+use sha2::{Digest, Sha256, Sha512};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::crypto::{KeyPair, sha256d};
+
+/// The BIP39 English wordlist, one word per line in index order.
+static WORDLIST: LazyLock<Vec<&'static str>> =
+    LazyLock::new(|| include_str!("mnemonic/english.txt").lines().collect());
+
+const PBKDF2_ROUNDS: u32 = 2048;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MnemonicError {
+    #[error("Mnemonics have 12, 15, 18, 21, or 24 words, not {0}")]
+    WordCount(usize),
+    #[error("Entropy is 16, 20, 24, 28, or 32 bytes, not {0}")]
+    EntropyLength(usize),
+    #[error("Word {index} ({word:?}) isn't in the wordlist")]
+    UnknownWord { index: usize, word: String },
+    /// The checksum bits are the tail of the last word, so that's the one named.
+    #[error("Bad checksum, word {index} is wrong")]
+    Checksum { index: usize },
+}
+
+/// A BIP39 mnemonic: entropy plus a checksum of its sha256, as 11-bit indices into the
+/// English wordlist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    words: Vec<u16>,
+}
+
+impl Mnemonic {
+    /// A new phrase of `word_count` words from system entropy.
+    #[cfg(feature = "rand")]
+    pub fn generate(word_count: usize) -> Result<Self, MnemonicError> {
+        if !matches!(word_count, 12 | 15 | 18 | 21 | 24) {
+            return Err(MnemonicError::WordCount(word_count));
+        }
+
+        let entropy = rand::random::<[u8; 32]>();
+        Self::from_entropy(&entropy[..word_count / 3 * 4])
+    }
+
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, MnemonicError> {
+        if !matches!(entropy.len(), 16 | 20 | 24 | 28 | 32) {
+            return Err(MnemonicError::EntropyLength(entropy.len()));
+        }
+
+        // one checksum bit per 4 bytes of entropy
+        let checksum_bits = entropy.len() / 4;
+        let checksum = Sha256::digest(entropy)[0];
+        let bit = |i: usize| {
+            let byte = match entropy.get(i / 8) {
+                Some(byte) => *byte,
+                None => checksum,
+            };
+            (byte >> (7 - i % 8)) & 1
+        };
+
+        let total_bits = entropy.len() * 8 + checksum_bits;
+        let words = (0..total_bits / 11)
+            .map(|word| (0..11).fold(0, |index, i| (index << 1) | bit(word * 11 + i) as u16))
+            .collect();
+
+        Ok(Self { words })
+    }
+
+    /// Parses a phrase, checking every word is in the wordlist and the checksum matches.
+    pub fn parse(phrase: &str) -> Result<Self, MnemonicError> {
+        let phrase = phrase.nfkd().collect::<String>();
+        let words = phrase
+            .split_whitespace()
+            .enumerate()
+            .map(|(index, word)| {
+                WORDLIST
+                    .binary_search(&word)
+                    .map(|index| index as u16)
+                    .map_err(|_| MnemonicError::UnknownWord {
+                        index,
+                        word: word.to_string(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !matches!(words.len(), 12 | 15 | 18 | 21 | 24) {
+            return Err(MnemonicError::WordCount(words.len()));
+        }
+
+        let mnemonic = Self { words };
+        if Self::from_entropy(&mnemonic.entropy())? != mnemonic {
+            return Err(MnemonicError::Checksum {
+                index: mnemonic.words.len() - 1,
+            });
+        }
+
+        Ok(mnemonic)
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn entropy(&self) -> Vec<u8> {
+        let entropy_bits = self.words.len() * 11 * 32 / 33;
+        let mut entropy = vec![0; entropy_bits / 8];
+        for i in 0..entropy_bits {
+            let bit = (self.words[i / 11] >> (10 - i % 11)) & 1;
+            entropy[i / 8] |= (bit as u8) << (7 - i % 8);
+        }
+        entropy
+    }
+
+    /// The 64-byte BIP39 seed: PBKDF2-HMAC-SHA512 of the phrase, salted with "mnemonic"
+    /// and the passphrase.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let salt = format!("mnemonic{}", passphrase.nfkd());
+        let mut seed = [0; 64];
+        pbkdf2::pbkdf2_hmac::<Sha512>(
+            self.to_string().as_bytes(),
+            salt.as_bytes(),
+            PBKDF2_ROUNDS,
+            &mut seed,
+        );
+        seed
+    }
+
+    /// `KeyPair::from_seed` of the first half of the seed, following its retry convention.
+    pub fn keypair(&self, passphrase: &str) -> KeyPair {
+        let mut seed = self.to_seed(passphrase)[..32].try_into().unwrap();
+        loop {
+            match KeyPair::from_seed(&seed) {
+                Ok(key_pair) => return key_pair,
+                Err(_) => seed = sha256d(&seed),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words = self
+            .words
+            .iter()
+            .map(|&index| WORDLIST[index as usize])
+            .collect::<Vec<_>>();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = MnemonicError;
+
+    fn from_str(phrase: &str) -> Result<Self, Self::Err> {
+        Self::parse(phrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        // from the BIP39 reference implementation, all with the passphrase "TREZOR"
+        let vectors = [
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+            (
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+                "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+            ),
+            (
+                "f585c11aec520db57dd353c69554b21a89b20fb0650966fa0a9d6f74fd989d8f",
+                "void come effort suffer camp survey warrior heavy shoot primary clutch crush open amazing screen patrol group space point ten exist slush involve unfold",
+                "01f5bced59dec48e362f2c45b5de68b9fd6c92c6634f44d6d40aab69056506f0e35524a518034ddc1192e1dacd32c1ed3eaa3c3b131c88ed8e7e54c49a5d0998",
+            ),
+        ];
+
+        for (entropy, phrase, seed) in vectors {
+            let mnemonic = Mnemonic::from_entropy(&hex::decode(entropy).unwrap()).unwrap();
+            assert_eq!(mnemonic.to_string(), phrase);
+            assert_eq!(Mnemonic::parse(phrase).unwrap(), mnemonic);
+            assert_eq!(hex::encode(mnemonic.entropy()), entropy);
+            assert_eq!(hex::encode(mnemonic.to_seed("TREZOR")), seed);
+        }
+
+        let generated = Mnemonic::generate(24).unwrap();
+        assert_eq!(generated.word_count(), 24);
+        assert_eq!(
+            Mnemonic::parse(&generated.to_string())
+                .unwrap()
+                .keypair("")
+                .public_key,
+            generated.keypair("").public_key
+        );
+    }
+
+    #[test]
+    fn test_invalid_phrases() {
+        assert_eq!(
+            Mnemonic::parse(
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+            ),
+            Err(MnemonicError::Checksum { index: 11 })
+        );
+        assert_eq!(
+            Mnemonic::parse(
+                "abandon abandon abandon abandon abandon abandonn abandon abandon abandon abandon abandon about"
+            ),
+            Err(MnemonicError::UnknownWord {
+                index: 5,
+                word: "abandonn".to_string()
+            })
+        );
+        assert_eq!(
+            Mnemonic::parse("abandon about"),
+            Err(MnemonicError::WordCount(2))
+        );
+        assert_eq!(Mnemonic::generate(13), Err(MnemonicError::WordCount(13)));
+    }
+}
@@ -1,15 +1,64 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
 
 use crate::{
-    block::Block,
+    address_history::AddressHistory,
+    block::{Block, BlockHeader, BlockValidationError},
     block_manager::{AddBlockResult, BlockManager},
-    chain::{Blockchain, BlockchainNode},
-    crypto::KeyPair,
-    mem_pool::MemPool,
-    transaction::Transaction,
-    utxo_set::UTXOSet,
+    chain::{Blockchain, BlockchainNode, ChainUpdate},
+    config::{NodeConfig, SyncConfig},
+    constants::{MAX_HEADERS_PER_MESSAGE, NODE_EVENT_CHANNEL_CAPACITY},
+    crypto::{Address, Hash, HashExt, KeyPair, to_hex},
+    header_chain::{HeaderChain, HeaderError},
+    mem_pool::{MemPool, MempoolError},
+    metrics::MetricsCache,
+    outcome::{HandleOutcome, RejectReason},
+    params::{Network, NetworkParams},
+    transaction::{
+        Transaction, TransactionBody, TransactionInput, TransactionOutput,
+        TransactionOutputReference, TxId,
+    },
+    tx_index::{RejectionCache, TransactionRecord, TxIndex},
+    utxo_set::{BlockUndo, UTXOSet},
 };
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeEvent {
+    BlockConnected { hash: Hash, height: u32 },
+    BlockDisconnected { hash: Hash },
+    TipChanged { old: Option<Hash>, new: Hash },
+    TransactionAccepted(TxId),
+    TransactionRejected { id: TxId, reason: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<NodeEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(NODE_EVENT_CHANNEL_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: NodeEvent) {
+        // an error only means there are currently no subscribers
+        let _ = self.sender.send(event);
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct NodeState {
@@ -17,77 +66,702 @@ pub struct NodeState {
     pub chain: Blockchain,
     pub utxo_set: UTXOSet,
     pub mem_pool: MemPool,
+    pub params: NetworkParams,
+    pub events: EventBus,
+    pub metrics: MetricsCache,
+    /// Undo data for the blocks on the active chain, keyed by block hash.
+    pub undo: HashMap<Hash, BlockUndo>,
+    pub sync: SyncConfig,
+    /// Height of the best header announced by a peer.
+    pub best_header_height: u32,
+    /// Blocks stored during initial block download whose signatures are still unchecked.
+    pub unverified_blocks: HashSet<Hash>,
+    /// Blocks stored while syncing since the chain last switched tips.
+    pub pending_batch: usize,
+    pub tx_index: TxIndex,
+    /// Kept only when `index_address_history` is set.
+    pub address_history: Option<AddressHistory>,
+    pub rejected_transactions: RejectionCache,
+    pub headers: HeaderChain,
 }
 
 impl NodeState {
-    pub fn add_block(&mut self, block: Block) -> Result<()> {
+    /// Builds node state from the blocks already held by `block_manager`, taking the block
+    /// with the most cumulative work as the tip.
+    pub fn new(
+        block_manager: BlockManager,
+        mem_pool: MemPool,
+        params: NetworkParams,
+    ) -> Result<Self> {
+        let mut state = Self {
+            block_manager,
+            mem_pool,
+            params,
+            ..Self::default()
+        };
+
+        if let Some(best_node) = state.block_manager.best_node() {
+            state.chain.set_tail(best_node)?;
+
+            for node in state.chain.nodes.values() {
+                let hash = node.header.hash()?;
+                if let Some(block) = state.block_manager.get_block(&hash) {
+                    let undo = state.utxo_set.apply_block(block)?;
+                    state.undo.insert(hash, undo);
+                }
+            }
+        }
+
+        state.tx_index = TxIndex::build(&state.chain, &state.block_manager)?;
+        state.metrics.refresh_utxo_set(&state.utxo_set);
+        state.metrics.refresh_mem_pool(&state.mem_pool)?;
+
+        Ok(state)
+    }
+
+    /// Whether the node is in initial block download, far enough behind the best announced
+    /// header that blocks are connected in batches rather than one at a time.
+    pub fn is_syncing(&self) -> bool {
+        self.best_header_height > self.chain.height() + self.sync.lag_threshold
+    }
+
+    /// The height the next block, and so any transaction accepted now, would be confirmed
+    /// at.
+    pub fn next_height(&self) -> u32 {
+        self.chain.height() + 1
+    }
+
+    pub fn note_header_height(&mut self, height: u32) {
+        self.best_header_height = self.best_header_height.max(height);
+    }
+
+    pub fn add_block(&mut self, block: Block) -> Result<HandleOutcome> {
         let hash = block.header.hash()?;
+        let height = block.height;
+        let outcome = self.try_add_block(block, hash)?;
+
+        let block_hash = format_args!("{:#x}", hash.display());
+        match &outcome {
+            HandleOutcome::Accepted => tracing::debug!(%block_hash, height, "Accepted block"),
+            HandleOutcome::Duplicate => tracing::trace!(%block_hash, height, "Duplicate block"),
+            HandleOutcome::Orphaned { missing_parent } => tracing::debug!(
+                %block_hash,
+                height,
+                missing_parent = %format_args!("{:#x}", missing_parent.display()),
+                "Orphaned block"
+            ),
+            HandleOutcome::Rejected(reason) => {
+                tracing::warn!(%block_hash, height, %reason, "Rejected block")
+            }
+        }
 
-        if self.block_manager.contains_block(&hash) {
-            return Ok(());
+        Ok(outcome)
+    }
+
+    fn try_add_block(&mut self, block: Block, hash: Hash) -> Result<HandleOutcome> {
+        // an orphan is only worth another look once its parent has arrived
+        let parent_known = self
+            .block_manager
+            .nodes
+            .contains_key(&block.header.previous_block_hash);
+
+        if self.block_manager.contains_block(&hash)
+            || (self.block_manager.orphan_blocks.contains_key(&hash) && !parent_known)
+        {
+            return Ok(HandleOutcome::Duplicate);
+        }
+
+        let syncing = self.is_syncing();
+        self.metrics.blocks_validated += 1;
+
+        let validation = if syncing {
+            block.validate_without_signatures(&self.params)
+        } else {
+            block.validate(&self.params)
+        };
+
+        if let Err(e) = validation {
+            return HandleOutcome::from_error(e);
         }
 
-        block.validate()?;
+        // the genesis block is the only block without a parent
+        if block.header.previous_block_hash == Hash::default()
+            && hash != self.params.genesis_hash()?
+        {
+            return Ok(HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::NotGenesis,
+            )));
+        }
 
+        let missing_parent = block.header.previous_block_hash;
         let block = Arc::new(block);
         let block_node = match self.block_manager.add_block(block.clone())? {
             AddBlockResult::Added(node) => node,
             AddBlockResult::Orphaned => {
-                return Ok(());
+                return Ok(HandleOutcome::Orphaned { missing_parent });
+            }
+        };
+
+        if syncing {
+            self.unverified_blocks.insert(hash);
+        }
+
+        // blocks off the active chain are only checked against the UTXO set once they
+        // would become part of it
+        if block_node.work < self.chain.chain_work().unwrap_or_default() {
+            return Ok(HandleOutcome::Accepted);
+        }
+
+        let target = if syncing {
+            self.pending_batch += 1;
+
+            let batch_full = self.pending_batch >= self.sync.batch_size;
+            let caught_up = block_node.height + self.sync.lag_threshold >= self.best_header_height;
+
+            if !batch_full && !caught_up {
+                return Ok(HandleOutcome::Accepted);
             }
+
+            self.block_manager.best_node().unwrap_or(block_node)
+        } else {
+            block_node
         };
 
-        if let Some(previous_node) = block_node.previous.as_ref() {
-            let utxo_set = BlockchainNode::into_chain(previous_node.clone())?
-                .build_utxo_set(&self.block_manager)?;
+        self.pending_batch = 0;
+
+        if let Err(e) = self.switch_tip(target) {
+            return HandleOutcome::from_error(e);
+        }
+
+        #[cfg(debug_assertions)]
+        self.debug_check_consistency();
+
+        Ok(HandleOutcome::Accepted)
+    }
+
+    fn get_stored_block(&self, hash: &Hash) -> Result<Arc<Block>> {
+        self.block_manager
+            .blocks
+            .get(hash)
+            .cloned()
+            .ok_or(anyhow::anyhow!("Block {:#x} not found", hash.display()))
+    }
+
+    /// Makes `node` the tip: reverts the blocks it replaces, then connects its branch with
+    /// full validation. If any block on the branch is invalid, it and its descendants are
+    /// dropped and the node stays on its original tip.
+    fn switch_tip(&mut self, node: Arc<BlockchainNode>) -> Result<()> {
+        let old_tip = self
+            .chain
+            .tail()
+            .map(|node| node.header.hash())
+            .transpose()?;
+        let new_tip = node.header.hash()?;
+
+        let mut chain = self.chain.clone();
+        let update = chain.set_tail(node)?;
+        let mut utxo_set = self.fork_utxo_set(&update)?;
+        self.metrics.utxo_set_rebuilds += 1;
+
+        // signatures deferred during initial block download are checked now, unless the
+        // block is covered by the assumed valid block on the new chain
+        let assume_valid_height = self
+            .sync
+            .assume_valid
+            .and_then(|hash| self.block_manager.nodes.get(&hash))
+            .filter(|node| chain.contains_node(node))
+            .map(|node| node.height);
 
-            if let Err(e) = block.validate_transaction_inputs(&utxo_set) {
-                self.block_manager.remove_block(&hash);
-                return Err(e);
+        let mut connected_blocks = Vec::new();
+        let mut connected_undo = Vec::new();
+
+        for (index, node) in update.connected.iter().enumerate() {
+            let hash = node.header.hash()?;
+            let block = self.get_stored_block(&hash)?;
+
+            let verify_signatures = self.unverified_blocks.contains(&hash)
+                && assume_valid_height.is_none_or(|height| node.height > height);
+
+            let result = if verify_signatures {
+                block.verify_signatures()
+            } else {
+                Ok(())
+            }
+            .and_then(|_| block.connect(&mut utxo_set, &self.params));
+
+            match result {
+                Ok(undo) => {
+                    connected_blocks.push(block);
+                    connected_undo.push((hash, undo));
+                }
+                Err(e) => {
+                    for invalid in &update.connected[index..] {
+                        let invalid_hash = invalid.header.hash()?;
+                        self.block_manager.remove_block(&invalid_hash)?;
+                        self.unverified_blocks.remove(&invalid_hash);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        // ascending height order, so returned transactions keep their original order
+        let disconnected_blocks = update
+            .disconnected
+            .iter()
+            .rev()
+            .map(|node| self.get_stored_block(&node.header.hash()?))
+            .collect::<Result<Vec<_>>>()?;
+
+        for node in &update.disconnected {
+            self.undo.remove(&node.header.hash()?);
+        }
+        for (hash, _) in &connected_undo {
+            self.unverified_blocks.remove(hash);
+        }
+        self.undo.extend(connected_undo);
+        self.chain = chain;
+        self.utxo_set = utxo_set;
+
+        for block in &disconnected_blocks {
+            self.tx_index.disconnect_block(block)?;
+        }
+        for block in &connected_blocks {
+            self.tx_index.connect_block(block)?;
+        }
+        if let Some(history) = &mut self.address_history {
+            for node in &update.disconnected {
+                history.disconnect_block(&node.header.hash()?);
+            }
+            for block in &connected_blocks {
+                let hash = block.header.hash()?;
+                let undo = self.undo.get(&hash).ok_or(anyhow::anyhow!(
+                    "No undo data for connected block 0x{}",
+                    hash.display()
+                ))?;
+                history.connect_block(block, undo)?;
             }
         }
 
-        if block_node.work >= self.chain.chain_work().unwrap_or_default() {
-            self.chain.set_tail(block_node)?;
-            self.utxo_set = self.chain.build_utxo_set(&self.block_manager)?;
+        // the mempool is reconciled once, when initial block download finishes
+        if !self.is_syncing() {
+            let height = self.next_height();
+            self.mem_pool.reorganize(
+                &self.utxo_set,
+                height,
+                &disconnected_blocks
+                    .iter()
+                    .map(Arc::as_ref)
+                    .collect::<Vec<_>>(),
+                &connected_blocks.iter().map(Arc::as_ref).collect::<Vec<_>>(),
+            )?;
         }
 
+        self.rejected_transactions
+            .forget_unlocked(self.next_height());
+
+        self.metrics.refresh_utxo_set(&self.utxo_set);
+        self.metrics.refresh_mem_pool(&self.mem_pool)?;
+        self.emit_chain_update(old_tip, new_tip, &update)?;
+
         Ok(())
     }
 
-    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<()> {
-        transaction.validate()?;
-        self.mem_pool.add(&self.utxo_set, transaction)?;
+    /// The UTXO set as of the last block `update` leaves in place, reverting disconnected
+    /// blocks with their undo data where we have it and rebuilding otherwise.
+    fn fork_utxo_set(&self, update: &ChainUpdate) -> Result<UTXOSet> {
+        let mut utxo_set = self.utxo_set.clone();
+
+        for node in &update.disconnected {
+            let hash = node.header.hash()?;
+
+            match (self.block_manager.get_block(&hash), self.undo.get(&hash)) {
+                (Some(block), Some(undo)) => utxo_set.revert_block(block, undo)?,
+                // e.g. blocks connected before the state was restored from a snapshot
+                _ => {
+                    return match update.connected.first().and_then(|n| n.previous.clone()) {
+                        Some(fork_node) => BlockchainNode::into_chain(fork_node)?
+                            .build_utxo_set(&self.block_manager),
+                        None => Ok(UTXOSet::default()),
+                    };
+                }
+            }
+        }
+
+        Ok(utxo_set)
+    }
+
+    fn emit_chain_update(
+        &mut self,
+        old_tip: Option<Hash>,
+        new_tip: Hash,
+        update: &ChainUpdate,
+    ) -> Result<()> {
+        for node in &update.disconnected {
+            self.events.emit(NodeEvent::BlockDisconnected {
+                hash: node.header.hash()?,
+            });
+        }
+
+        for node in &update.connected {
+            self.events.emit(NodeEvent::BlockConnected {
+                hash: node.header.hash()?,
+                height: node.height,
+            });
+        }
+
+        self.events.emit(NodeEvent::TipChanged {
+            old: old_tip,
+            new: new_tip,
+        });
+        tracing::info!(
+            block_hash = %format_args!("{:#x}", new_tip.display()),
+            height = self.chain.height(),
+            disconnected = update.disconnected.len(),
+            connected = update.connected.len(),
+            "Switched tip"
+        );
+        self.metrics
+            .record_chain_update(update.disconnected.len(), update.connected.len());
+
         Ok(())
     }
+
+    /// Headers on the active chain after the most recent `locator` hash we know of (or from
+    /// the start of the chain if none match), up to and including `stop`.
+    pub fn headers_after(
+        &self,
+        locator: &[Hash],
+        stop: Option<&Hash>,
+        max: usize,
+    ) -> Result<Vec<(u32, BlockHeader)>> {
+        let fork_height = locator
+            .iter()
+            .filter_map(|hash| self.block_manager.nodes.get(hash))
+            .find(|node| self.chain.contains_node(node))
+            .map(|node| node.height);
+
+        let start = fork_height.map_or(0, |height| height + 1);
+        let mut headers = Vec::new();
+
+        for node in self.chain.nodes.range(start..).map(|(_, node)| node) {
+            if headers.len() >= max {
+                break;
+            }
+
+            headers.push((node.height, node.header.clone()));
+
+            if stop.is_some_and(|stop| node.header.hash().ok().as_ref() == Some(stop)) {
+                break;
+            }
+        }
+
+        Ok(headers)
+    }
+
+    fn is_pending(&self, id: &TxId) -> bool {
+        self.mem_pool
+            .pending_transactions
+            .iter()
+            .any(|tx| tx.id().is_ok_and(|pending_id| pending_id == *id))
+    }
+
+    /// Makes `add_transaction`'s checks without adding the transaction, recording a
+    /// rejection, or emitting events. Returns the fee it would pay if it's accepted.
+    pub fn test_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<(HandleOutcome, Option<u64>)> {
+        if self.is_pending(&transaction.id()?) {
+            return Ok((HandleOutcome::Duplicate, None));
+        }
+
+        let result = transaction
+            .validate()
+            .and_then(|_| transaction.validate_network(self.params.network))
+            .map_err(MempoolError::from)
+            .and_then(|_| {
+                self.mem_pool
+                    .check(&self.utxo_set, transaction, self.next_height())
+            });
+        Ok(match result {
+            Ok(fee) => (HandleOutcome::Accepted, Some(fee)),
+            Err(e) => (HandleOutcome::Rejected(e.into_reject_reason()?), None),
+        })
+    }
+
+    pub fn add_transaction(&mut self, transaction: Arc<Transaction>) -> Result<HandleOutcome> {
+        let id = transaction.id()?;
+
+        let size = transaction.size()?;
+
+        if self.is_pending(&id) {
+            return Ok(HandleOutcome::Duplicate);
+        }
+
+        let result = transaction
+            .validate()
+            .and_then(|_| transaction.validate_network(self.params.network))
+            .map_err(MempoolError::from)
+            .and_then(|_| {
+                let height = self.next_height();
+                self.mem_pool
+                    .add(&self.utxo_set, transaction.clone(), height)
+            });
+
+        let outcome = match result {
+            Ok(()) => HandleOutcome::Accepted,
+            Err(e) => HandleOutcome::Rejected(e.into_reject_reason()?),
+        };
+
+        match &outcome {
+            HandleOutcome::Accepted => {
+                tracing::debug!(txid = %id, "Accepted transaction");
+                self.metrics.record_transaction(size);
+                self.rejected_transactions.remove(&id);
+                self.events.emit(NodeEvent::TransactionAccepted(id));
+            }
+            HandleOutcome::Rejected(reason) => {
+                tracing::debug!(txid = %id, %reason, "Rejected transaction");
+                self.metrics.record_rejection(reason);
+                self.rejected_transactions
+                    .insert(id.clone(), transaction, reason.clone());
+                self.events.emit(NodeEvent::TransactionRejected {
+                    id,
+                    reason: reason.to_string(),
+                });
+            }
+            _ => {}
+        }
+
+        Ok(outcome)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    #[error("Insufficient funds: {available} available, {required} required")]
+    InsufficientFunds { available: u64, required: u64 },
+    #[error("Change of {change} is below the dust limit of {dust_limit}")]
+    DustChange { change: u64, dust_limit: u64 },
+    /// The amount plus the fee, or the outputs to spend from, total more than a `u64`.
+    #[error(
+        "Value overflow: the amount and fee or the outputs total more than {}",
+        u64::MAX
+    )]
+    ValueOverflow,
+    #[error("Mempool is full")]
+    PoolFull,
+    #[error("Transaction rejected: {0}")]
+    Rejected(RejectReason),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnspentOutput {
+    pub outpoint: TransactionOutputReference,
+    pub value: u64,
+    pub confirmations: u32,
+}
+
+/// Builds and signs a payment of `amount` to `to` from one of `unspent`, returning change
+/// to the key's address on `network`. The outputs needn't come from a local node, so a
+/// client can spend outputs it looked up remotely without its key leaving the machine.
+pub fn build_payment(
+    keypair: &KeyPair,
+    network: Network,
+    unspent: Vec<UnspentOutput>,
+    to: Address,
+    amount: u64,
+    fee: u64,
+    dust_limit: u64,
+) -> Result<Transaction, SendError> {
+    if !to.is_for(network) {
+        return Err(SendError::Rejected(RejectReason::WrongNetwork {
+            address: to.to_string(),
+            network,
+        }));
+    }
+
+    let required = amount.checked_add(fee).ok_or(SendError::ValueOverflow)?;
+    let available = unspent
+        .iter()
+        .try_fold(0u64, |total, output| total.checked_add(output.value))
+        .ok_or(SendError::ValueOverflow)?;
+
+    // transactions have a single input, so pick the smallest output covering the payment
+    let selected = unspent
+        .into_iter()
+        .filter(|output| output.value >= required)
+        .min_by_key(|output| output.value)
+        .ok_or(SendError::InsufficientFunds {
+            available,
+            required,
+        })?;
+
+    let change = selected.value - required;
+    if change > 0 && change < dust_limit {
+        return Err(SendError::DustChange { change, dust_limit });
+    }
+
+    let mut outputs = vec![TransactionOutput {
+        value: amount,
+        address: to,
+    }];
+    if change > 0 {
+        outputs.push(TransactionOutput {
+            value: change,
+            address: Address::from_public_key_for(&keypair.public_key, network),
+        });
+    }
+
+    Ok(TransactionBody {
+        input: TransactionInput::spending(selected.outpoint),
+        outputs,
+    }
+    .into_tx(keypair)?)
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     NewBlock(Block),
     NewTransaction(Transaction),
-}
-
-#[derive(Clone)]
-pub struct NodeConfig {
-    pub keypair: KeyPair,
+    /// Requests headers following the first locator hash found on the peer's active chain.
+    GetHeaders {
+        locator: Vec<Hash>,
+        stop: Option<Hash>,
+    },
+    Headers(Vec<(u32, BlockHeader)>),
+    GetData(Vec<Hash>),
+    BlockData(Block),
+    Inv(Vec<Hash>),
+    /// Answers a `GetData` for blocks we don't have.
+    NotFound(Vec<Hash>),
+    /// Announces mempool transactions.
+    TxInv(Vec<TxId>),
+    GetTransactions(Vec<TxId>),
+    Transactions(Vec<Transaction>),
 }
 
 #[derive(Clone)]
 pub struct Node {
     pub config: NodeConfig,
     pub state: NodeState,
+    pub started_at: Instant,
 }
 
 impl Node {
-    pub fn new(config: NodeConfig) -> Self {
-        Self {
-            state: NodeState::default(),
+    pub fn new(config: NodeConfig) -> Result<Self> {
+        let (block_manager, report) = BlockManager::open(config.blocks_dir())?;
+        report.log();
+        if let Some(max) = config
+            .max_skipped_block_files
+            .filter(|&max| report.skipped.len() > max)
+        {
+            return Err(anyhow::anyhow!(
+                "{} block files in {} couldn't be loaded, more than the {max} allowed",
+                report.skipped.len(),
+                config.blocks_dir().display()
+            ));
+        }
+        let mem_pool = MemPool::new(config.mempool.clone());
+        let mut state = NodeState::new(block_manager, mem_pool, config.network.clone())?;
+        state.sync = config.sync.clone();
+
+        let genesis = config.network.genesis_block()?;
+        let genesis_hash = genesis.header.hash()?;
+
+        match state.chain.nodes.first_key_value() {
+            Some((_, first)) => {
+                let stored_hash = first.header.hash()?;
+
+                if stored_hash != genesis_hash {
+                    return Err(anyhow::anyhow!(
+                        "Data dir {} holds a chain starting at 0x{}, not the {} genesis block 0x{}",
+                        config.data_dir.display(),
+                        stored_hash.display(),
+                        config.network.network,
+                        genesis_hash.display()
+                    ));
+                }
+            }
+            None => match state.add_block(genesis)? {
+                HandleOutcome::Accepted => {}
+                outcome => {
+                    return Err(anyhow::anyhow!(
+                        "Genesis block was not accepted: {outcome:?}"
+                    ));
+                }
+            },
+        }
+
+        if config.index_address_history {
+            state.address_history = Some(AddressHistory::load_or_build(
+                &config.address_history_path(),
+                &state,
+            )?);
+        }
+
+        // saved transactions are validated again against the chain they come back to
+        let mem_pool_path = config.mem_pool_path();
+        if mem_pool_path.exists() {
+            for transaction in MemPool::load(&mem_pool_path, config.mempool.clone())?.drain() {
+                state.add_transaction(transaction)?;
+            }
+        }
+
+        Ok(Self {
+            state,
+            config,
+            started_at: Instant::now(),
+        })
+    }
+
+    /// A node holding its chain in memory alone, starting from the genesis block, for
+    /// simulations and tests. `config.data_dir` is never touched.
+    pub fn in_memory(config: NodeConfig) -> Result<Self> {
+        let mem_pool = MemPool::new(config.mempool.clone());
+        let mut state = NodeState::new(BlockManager::default(), mem_pool, config.network.clone())?;
+        state.sync = config.sync.clone();
+        if config.index_address_history {
+            state.address_history = Some(AddressHistory::default());
+        }
+
+        match state.add_block(config.network.genesis_block()?)? {
+            HandleOutcome::Accepted => {}
+            outcome => anyhow::bail!("Genesis block was not accepted: {outcome:?}"),
+        }
+
+        Ok(Self {
+            state,
             config,
+            started_at: Instant::now(),
+        })
+    }
+
+    /// Writes out the state that isn't persisted as it changes, which is the mempool and
+    /// any address history; blocks are written as they're stored.
+    pub fn flush(&self) -> Result<()> {
+        self.state.mem_pool.save(&self.config.mem_pool_path())?;
+        if let Some(history) = &self.state.address_history
+            && let Some(tip) = self.tip_hash()?
+        {
+            history.save(&self.config.address_history_path(), &tip)?;
         }
+        Ok(())
     }
 
-    pub fn create_block(&mut self) -> Result<Block> {
+    /// Builds an unmined block on the current tip from the pending mempool transactions,
+    /// as many as `BLOCK_SIZE_LIMIT` leaves room for.
+    ///
+    /// The mempool is left untouched; transactions are removed once the block connects.
+    pub fn block_template(&self) -> Result<Block> {
+        self.block_template_at(chrono::Utc::now().timestamp() as u32)
+    }
+
+    /// `block_template`, stamped with `timestamp` rather than the clock.
+    pub fn block_template_at(&self, timestamp: u32) -> Result<Block> {
         let tail_node = self
             .state
             .chain
@@ -100,18 +774,383 @@ impl Node {
             .get_block(&tail_node.header.hash()?)
             .ok_or(anyhow::anyhow!("Unable to mine block: no previous block"))?;
 
-        let transactions = self.state.mem_pool.drain();
+        let (transactions, fees) = self
+            .state
+            .mem_pool
+            .block_transactions(&self.state.utxo_set, self.state.next_height())?;
 
-        let mut block = Block::new(&self.config.keypair, previous_block, transactions)?;
-        block.mine()?;
+        Block::new_at(
+            &self.config.keypair,
+            previous_block,
+            transactions,
+            fees,
+            timestamp,
+            &self.state.params,
+        )
+    }
+
+    /// Fails unless the node's key can seal blocks, which on a proof of authority network
+    /// means it's one of the authorities.
+    pub fn check_can_seal(&self) -> Result<()> {
+        let public_key = &self.config.keypair.public_key;
+        if !self.state.params.consensus.can_seal(public_key) {
+            anyhow::bail!(
+                "Key 0x{} is not one of the network's authorities, so it can't seal blocks",
+                to_hex(public_key.serialize())
+            );
+        }
+        Ok(())
+    }
+
+    /// A block on the tip holding the pending transactions, mined or, on a proof of
+    /// authority network, signed with the node's key.
+    pub fn create_block(&mut self) -> Result<Block> {
+        let mut block = self.block_template()?;
+        block.seal(&self.config.keypair, &self.state.params)?;
 
         Ok(block)
     }
 
-    pub fn handle_message(&mut self, message: Message) -> Result<()> {
+    /// Mines and connects `count` blocks on the tip whose coinbases pay `address`,
+    /// returning their hashes. Only regtest, where any nonce meets the target, allows it;
+    /// the blocks are otherwise validated like any other.
+    pub fn generate(&mut self, count: u32, address: &Address) -> Result<Vec<Hash>> {
+        let network = self.state.params.network;
+        if network != Network::Regtest {
+            anyhow::bail!("Blocks can only be generated on regtest, not {network}");
+        }
+
+        let mut hashes = Vec::new();
+        for _ in 0..count {
+            let mut block = self.block_template()?;
+            let coinbase = &block.transactions[0];
+            block.transactions[0] = Arc::new(
+                TransactionBody {
+                    input: coinbase.body.input.clone(),
+                    outputs: vec![TransactionOutput {
+                        value: coinbase.output_value(),
+                        address: address.clone(),
+                    }],
+                }
+                .into_tx(&self.config.keypair)?,
+            );
+            block.header.merkle_root = Transaction::build_merkle_tree(&block.transactions)?
+                .root()
+                .ok_or(anyhow::anyhow!("Failed to compute merkle root"))?;
+            block.seal(&self.config.keypair, &self.state.params)?;
+
+            let hash = block.header.hash()?;
+            match self.handle_message(Message::NewBlock(block))?.0 {
+                HandleOutcome::Accepted => hashes.push(hash),
+                HandleOutcome::Rejected(reason) => return Err(reason.into()),
+                outcome => anyhow::bail!("Generated block was not accepted: {outcome:?}"),
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.state.events.subscribe()
+    }
+
+    pub fn tip_hash(&self) -> Result<Option<Hash>> {
+        self.state
+            .chain
+            .tail()
+            .map(|node| node.header.hash())
+            .transpose()
+    }
+
+    pub fn get_transaction(&self, id: &TxId) -> Option<TransactionRecord> {
+        self.state.get_transaction(id)
+    }
+
+    /// Unspent outputs paying `address`, as seen after applying pending mempool
+    /// transactions. Outputs created by pending transactions have 0 confirmations and
+    /// confirmed outputs spent by pending transactions are excluded.
+    pub fn list_unspent(&self, address: &Address) -> Result<Vec<UnspentOutput>> {
+        let utxo_set = &self.state.utxo_set;
+        let tip_height = self.state.chain.height();
+
+        let mut pending_spent = HashSet::new();
+        let mut unspent = Vec::new();
+
+        for tx in &self.state.mem_pool.pending_transactions {
+            if let Some(reference) = tx.body.input.outpoint() {
+                pending_spent.insert(reference.clone());
+            }
+
+            for (index, output) in tx.body.outputs.iter().enumerate() {
+                if output.address == *address {
+                    unspent.push(UnspentOutput {
+                        outpoint: tx.output_reference(index)?,
+                        value: output.value,
+                        confirmations: 0,
+                    });
+                }
+            }
+        }
+
+        // pending transactions may spend outputs created by other pending transactions
+        unspent.retain(|output| !pending_spent.contains(&output.outpoint));
+
+        for (outpoint, tx) in &utxo_set.outputs {
+            let output = &tx.body.outputs[outpoint.index];
+
+            if output.address != *address || pending_spent.contains(outpoint) {
+                continue;
+            }
+
+            let confirmations = utxo_set
+                .creation_height(outpoint)
+                .map(|height| tip_height - height + 1)
+                .unwrap_or_default();
+
+            unspent.push(UnspentOutput {
+                outpoint: outpoint.clone(),
+                value: output.value,
+                confirmations,
+            });
+        }
+
+        unspent.sort_by(|a, b| {
+            b.confirmations
+                .cmp(&a.confirmations)
+                .then(b.value.cmp(&a.value))
+        });
+
+        Ok(unspent)
+    }
+
+    /// Spendable balance of `address`, including the effects of pending transactions.
+    pub fn get_balance(&self, address: &Address) -> Result<u64> {
+        Ok(self
+            .list_unspent(address)?
+            .iter()
+            .map(|output| output.value)
+            .sum())
+    }
+
+    /// Balance of `address` according to the confirmed UTXO set only.
+    pub fn get_confirmed_balance(&self, address: &Address) -> u64 {
+        self.state
+            .utxo_set
+            .outputs
+            .iter()
+            .map(|(outpoint, tx)| &tx.body.outputs[outpoint.index])
+            .filter(|output| output.address == *address)
+            .map(|output| output.value)
+            .sum()
+    }
+
+    /// The node's own address on its network, which its blocks and change pay.
+    pub fn address(&self) -> Address {
+        Address::from_public_key_for(&self.config.keypair.public_key, self.state.params.network)
+    }
+
+    /// Pays `amount` to `to` from the node's own outputs, returning change to the node's
+    /// address, and submits the transaction to the mempool.
+    pub fn send(&mut self, to: Address, amount: u64, fee: u64) -> Result<TxId, SendError> {
+        if self.state.mem_pool.is_full() {
+            return Err(SendError::PoolFull);
+        }
+
+        let transaction = self.create_send(to, amount, fee)?;
+        let id = transaction.id()?;
+
+        match self.state.add_transaction(Arc::new(transaction))? {
+            HandleOutcome::Accepted => Ok(id),
+            HandleOutcome::Rejected(RejectReason::PoolFull) => Err(SendError::PoolFull),
+            HandleOutcome::Rejected(reason) => Err(SendError::Rejected(reason)),
+            outcome => {
+                Err(anyhow::anyhow!("Unexpected outcome for new transaction: {outcome:?}").into())
+            }
+        }
+    }
+
+    /// Builds and signs the transaction `send` would submit, without submitting it.
+    pub fn create_send(
+        &self,
+        to: Address,
+        amount: u64,
+        fee: u64,
+    ) -> Result<Transaction, SendError> {
+        build_payment(
+            &self.config.keypair,
+            self.state.params.network,
+            self.list_unspent(&self.address())?,
+            to,
+            amount,
+            fee,
+            self.state.mem_pool.config.dust_limit,
+        )
+    }
+
+    /// Message asking a peer for the headers we are missing.
+    pub fn request_headers(&self) -> Result<Message> {
+        Ok(Message::GetHeaders {
+            locator: self.state.chain.locator()?,
+            stop: None,
+        })
+    }
+
+    /// Handles a message from a peer, returning what became of it along with any replies
+    /// to send back. `Err` is reserved for internal failures.
+    pub fn handle_message(&mut self, message: Message) -> Result<(HandleOutcome, Vec<Message>)> {
         match message {
-            Message::NewBlock(block) => self.state.add_block(block),
-            Message::NewTransaction(transaction) => self.state.add_transaction(transaction),
+            Message::NewBlock(block) | Message::BlockData(block) => {
+                Ok((self.state.add_block(block)?, vec![]))
+            }
+            Message::NewTransaction(transaction) => {
+                Ok((self.state.add_transaction(Arc::new(transaction))?, vec![]))
+            }
+            Message::GetHeaders { locator, stop } => {
+                let headers =
+                    self.state
+                        .headers_after(&locator, stop.as_ref(), MAX_HEADERS_PER_MESSAGE)?;
+                Ok((HandleOutcome::Accepted, vec![Message::Headers(headers)]))
+            }
+            Message::Headers(headers) => {
+                let error = match self.state.add_headers(&headers) {
+                    Ok(()) => None,
+                    Err(HeaderError::InvalidProofOfWork) => {
+                        Some(BlockValidationError::InvalidProofOfWork)
+                    }
+                    Err(HeaderError::UnknownParent(parent)) => {
+                        Some(BlockValidationError::UnknownParent { parent })
+                    }
+                    Err(HeaderError::WrongHeight { claimed, parent }) => {
+                        Some(BlockValidationError::WrongHeight {
+                            claimed,
+                            expected: parent + 1,
+                        })
+                    }
+                    Err(HeaderError::Other(e)) => return Err(e),
+                };
+
+                if let Some(error) = error {
+                    let reason = RejectReason::InvalidBlock(error);
+                    return Ok((HandleOutcome::Rejected(reason), vec![]));
+                }
+
+                let mut missing = Vec::new();
+
+                for (_, header) in &headers {
+                    let hash = header.hash()?;
+                    if !self.state.block_manager.contains_block(&hash) {
+                        missing.push(hash);
+                    }
+                }
+
+                let mut responses = Vec::new();
+
+                if !missing.is_empty() {
+                    responses.push(Message::GetData(missing));
+                }
+
+                // a full batch means the peer has more headers to send
+                if let Some((_, last)) = headers
+                    .last()
+                    .filter(|_| headers.len() == MAX_HEADERS_PER_MESSAGE)
+                {
+                    responses.push(Message::GetHeaders {
+                        locator: vec![last.hash()?],
+                        stop: None,
+                    });
+                }
+
+                Ok((HandleOutcome::Accepted, responses))
+            }
+            Message::GetData(hashes) => {
+                let mut responses = Vec::new();
+                let mut missing = Vec::new();
+
+                for hash in hashes {
+                    match self.state.block_manager.get_block(&hash) {
+                        Some(block) => responses.push(Message::BlockData(block.clone())),
+                        None => missing.push(hash),
+                    }
+                }
+
+                if !missing.is_empty() {
+                    responses.push(Message::NotFound(missing));
+                }
+
+                Ok((HandleOutcome::Accepted, responses))
+            }
+            Message::Inv(hashes) => {
+                let missing = hashes
+                    .into_iter()
+                    .filter(|hash| !self.state.block_manager.contains_block(hash))
+                    .collect::<Vec<_>>();
+
+                if missing.is_empty() {
+                    return Ok((HandleOutcome::Duplicate, vec![]));
+                }
+
+                Ok((HandleOutcome::Accepted, vec![Message::GetData(missing)]))
+            }
+            Message::NotFound(_) => Ok((HandleOutcome::Accepted, vec![])),
+            Message::TxInv(ids) => {
+                let pending = self
+                    .state
+                    .mem_pool
+                    .pending_transactions
+                    .iter()
+                    .map(|tx| tx.id())
+                    .collect::<Result<HashSet<_>>>()?;
+
+                // rejected transactions would only be rejected again
+                let missing = ids
+                    .into_iter()
+                    .filter(|id| {
+                        !pending.contains(id)
+                            && self.state.tx_index.get(id).is_none()
+                            && self.state.rejected_transactions.get(id).is_none()
+                    })
+                    .collect::<Vec<_>>();
+
+                if missing.is_empty() {
+                    return Ok((HandleOutcome::Duplicate, vec![]));
+                }
+
+                Ok((
+                    HandleOutcome::Accepted,
+                    vec![Message::GetTransactions(missing)],
+                ))
+            }
+            Message::GetTransactions(ids) => {
+                let ids = ids.into_iter().collect::<HashSet<_>>();
+                let mut transactions = Vec::new();
+
+                for tx in &self.state.mem_pool.pending_transactions {
+                    if ids.contains(&tx.id()?) {
+                        transactions.push(tx.as_ref().clone());
+                    }
+                }
+
+                Ok((
+                    HandleOutcome::Accepted,
+                    vec![Message::Transactions(transactions)],
+                ))
+            }
+            Message::Transactions(transactions) => {
+                let outcomes = transactions
+                    .into_iter()
+                    .map(|transaction| self.state.add_transaction(Arc::new(transaction)))
+                    .collect::<Result<Vec<_>>>()?;
+
+                // a rejection isn't hidden by the good transactions around it
+                let outcome = outcomes
+                    .iter()
+                    .find(|outcome| matches!(outcome, HandleOutcome::Rejected(_)))
+                    .or_else(|| outcomes.iter().find(|o| **o == HandleOutcome::Accepted))
+                    .cloned()
+                    .unwrap_or(HandleOutcome::Duplicate);
+
+                Ok((outcome, vec![]))
+            }
         }
     }
 }
@@ -120,10 +1159,13 @@ impl Node {
 mod tests {
     use super::*;
     use crate::block::*;
+    use crate::config::*;
     use crate::constants::*;
     use crate::crypto::*;
+    use crate::params::*;
     use crate::transaction::*;
-    use std::collections::HashSet;
+    use crate::utxo_set::UtxoError;
+    use std::{collections::HashSet, fs};
 
     fn create_test_block(
         keypair: &KeyPair,
@@ -131,19 +1173,20 @@ mod tests {
         previous: Option<&Block>,
         transactions: Vec<Transaction>,
     ) -> Result<Block> {
-        let height = previous.map(|p| p.height + 1).unwrap_or(1);
+        // blocks without a given parent build on the genesis block
+        let genesis = NetworkParams::default().genesis_block()?;
+        let previous = previous.unwrap_or(&genesis);
+        let height = previous.height + 1;
 
-        let coinbase_tx = Transaction::new_coinbase(keypair, height)?;
-        let mut block_transactions = vec![coinbase_tx];
-        block_transactions.extend(transactions);
+        let coinbase_tx = Transaction::new_coinbase(keypair, height, &NetworkParams::default())?;
+        let mut block_transactions = vec![Arc::new(coinbase_tx)];
+        block_transactions.extend(transactions.into_iter().map(Arc::new));
 
         let merkle_tree = Transaction::build_merkle_tree(&block_transactions)?;
         let merkle_root = merkle_tree.root().unwrap_or_default();
 
         let header = BlockHeader {
-            previous_block_hash: previous
-                .and_then(|p| p.header.hash().ok())
-                .unwrap_or_default(),
+            previous_block_hash: previous.header.hash()?,
             merkle_root,
             timestamp: chrono::Utc::now().timestamp() as u32,
             difficulty,
@@ -154,6 +1197,7 @@ mod tests {
             header,
             height,
             transactions: block_transactions,
+            signature: None,
         };
 
         block.mine()?;
@@ -165,24 +1209,28 @@ mod tests {
         let keypair_bob = KeyPair::generate();
         let address_bob = Address::from_public_key(&keypair_bob.public_key);
 
-        let mut node = Node::new(NodeConfig {
-            keypair: keypair_bob.clone(),
-        });
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair_bob.clone())
+                .data_dir(data_dir.path())
+                .build(),
+        )
+        .unwrap();
 
-        let genesis_block = create_test_block(&keypair_bob, 2, None, vec![]).unwrap();
-        node.handle_message(Message::NewBlock(genesis_block.clone()))
+        let first_block = create_test_block(&keypair_bob, 2, None, vec![]).unwrap();
+        node.handle_message(Message::NewBlock(first_block.clone()))
             .unwrap();
 
-        assert_eq!(node.state.chain.height(), 1);
+        assert_eq!(node.state.chain.height(), 2);
 
-        // first transaction from genesis block to alice
+        // first transaction from the first block to alice
         let keypair_alice = KeyPair::generate();
         let address_alice = Address::from_public_key(&keypair_alice.public_key);
 
-        let coinbase_tx = genesis_block.transactions.first().unwrap();
+        let coinbase_tx = first_block.transactions.first().unwrap();
 
         let tx_a_body = TransactionBody {
-            input: TransactionInput::Reference(coinbase_tx.output_reference(0).unwrap()),
+            input: TransactionInput::spending(coinbase_tx.output_reference(0).unwrap()),
             outputs: vec![
                 TransactionOutput {
                     value: (GENESIS_BLOCK_REWARD / 2) as u64,
@@ -207,7 +1255,7 @@ mod tests {
         let address_charlie = Address::from_public_key(&keypair_charlie.public_key);
 
         let tx_b_body = TransactionBody {
-            input: TransactionInput::Reference(tx_a.output_reference(0).unwrap()),
+            input: TransactionInput::spending(tx_a.output_reference(0).unwrap()),
             outputs: vec![TransactionOutput {
                 value: (GENESIS_BLOCK_REWARD / 2) as u64,
                 address: address_charlie.clone(),
@@ -227,7 +1275,7 @@ mod tests {
         assert_eq!(node.state.mem_pool.pending_transactions.len(), 0);
 
         let tail_node = node.state.chain.tail().unwrap();
-        assert_eq!(tail_node.height, 2);
+        assert_eq!(tail_node.height, 3);
 
         let latest_block_transaction_ids = node
             .state
@@ -245,25 +1293,884 @@ mod tests {
         assert!(latest_block_transaction_ids.is_superset(&expected_transaction_ids));
     }
 
+    fn drain_events(events: &mut broadcast::Receiver<NodeEvent>) -> Vec<NodeEvent> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
     #[test]
-    fn test_append_block() {
+    fn test_block_events() {
         let keypair = KeyPair::generate();
 
-        let mut node = Node::new(NodeConfig {
-            keypair: keypair.clone(),
-        });
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair.clone())
+                .data_dir(data_dir.path())
+                .build(),
+        )
+        .unwrap();
+        let mut events = node.subscribe();
+
+        let block_a = create_test_block(&keypair, 1, None, vec![]).unwrap();
+        let block_b = create_test_block(&keypair, 1, Some(&block_a), vec![]).unwrap();
+        let hash_a = block_a.header.hash().unwrap();
+        let genesis_hash = NetworkParams::default().genesis_hash().unwrap();
+        let hash_b = block_b.header.hash().unwrap();
 
-        let block_a = create_test_block(&keypair, 2, None, vec![]).unwrap();
         node.handle_message(Message::NewBlock(block_a.clone()))
             .unwrap();
+        node.handle_message(Message::NewBlock(block_b.clone()))
+            .unwrap();
+
+        assert_eq!(
+            drain_events(&mut events),
+            vec![
+                NodeEvent::BlockConnected {
+                    hash: hash_a,
+                    height: 2
+                },
+                NodeEvent::TipChanged {
+                    old: Some(genesis_hash),
+                    new: hash_a
+                },
+                NodeEvent::BlockConnected {
+                    hash: hash_b,
+                    height: 3
+                },
+                NodeEvent::TipChanged {
+                    old: Some(hash_a),
+                    new: hash_b
+                },
+            ]
+        );
+
+        // a rejected block produces no events
+        let mut invalid_block = create_test_block(&keypair, 1, Some(&block_b), vec![]).unwrap();
+        invalid_block.header.merkle_root = Hash::default();
+        invalid_block.mine().unwrap();
+
+        assert_eq!(
+            node.handle_message(Message::NewBlock(invalid_block))
+                .unwrap()
+                .0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::MerkleRootMismatch
+            ))
+        );
+        assert!(drain_events(&mut events).is_empty());
+
+        // a heavier fork from block a reorganizes the chain
+        let keypair_fork = KeyPair::generate();
+        let block_c = create_test_block(&keypair_fork, 1, Some(&block_a), vec![]).unwrap();
+        let block_d = create_test_block(&keypair_fork, 1, Some(&block_c), vec![]).unwrap();
+        let block_e = create_test_block(&keypair_fork, 1, Some(&block_d), vec![]).unwrap();
+        let hash_c = block_c.header.hash().unwrap();
+        let hash_d = block_d.header.hash().unwrap();
+        let hash_e = block_e.header.hash().unwrap();
+
+        // stage the fork in the block manager so only its final block triggers the switch
+        node.state
+            .block_manager
+            .add_block(Arc::new(block_c))
+            .unwrap();
+        node.state
+            .block_manager
+            .add_block(Arc::new(block_d))
+            .unwrap();
+        node.handle_message(Message::NewBlock(block_e)).unwrap();
+
+        assert_eq!(
+            drain_events(&mut events),
+            vec![
+                NodeEvent::BlockDisconnected { hash: hash_b },
+                NodeEvent::BlockConnected {
+                    hash: hash_c,
+                    height: 3
+                },
+                NodeEvent::BlockConnected {
+                    hash: hash_d,
+                    height: 4
+                },
+                NodeEvent::BlockConnected {
+                    hash: hash_e,
+                    height: 5
+                },
+                NodeEvent::TipChanged {
+                    old: Some(hash_b),
+                    new: hash_e
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_block_logs() {
+        let keypair = KeyPair::generate();
+        let mut node = Node::in_memory(NodeConfig::builder(keypair.clone()).build()).unwrap();
+
+        let block = create_test_block(&keypair, 1, None, vec![]).unwrap();
+        let block_hash = format!("{:#x}", block.header.hash().unwrap().display());
+        node.handle_message(Message::NewBlock(block.clone()))
+            .unwrap();
+
+        assert!(logs_contain(&format!(
+            "block.connect{{block_hash={block_hash} height=2}}: tiny_crypto::block: Connected block fees=0"
+        )));
+        assert!(logs_contain(&format!(
+            "Switched tip block_hash={block_hash} height=2 disconnected=0 connected=1"
+        )));
+        assert!(logs_contain(&format!(
+            "Accepted block block_hash={block_hash} height=2"
+        )));
+
+        let mut invalid_block = create_test_block(&keypair, 1, Some(&block), vec![]).unwrap();
+        invalid_block.header.merkle_root = Hash::default();
+        invalid_block.mine().unwrap();
+        node.handle_message(Message::NewBlock(invalid_block.clone()))
+            .unwrap();
+
+        assert!(logs_contain(&format!(
+            "Rejected block block_hash={:#x} height=3 reason=",
+            invalid_block.header.hash().unwrap().display()
+        )));
+    }
+
+    #[test]
+    fn test_reorg() {
+        let keypair_bob = KeyPair::generate();
+        let keypair_alice = KeyPair::generate();
+        let address_alice = Address::from_public_key(&keypair_alice.public_key);
+        let address_charlie = Address::from_public_key(&KeyPair::generate().public_key);
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair_bob.clone())
+                .data_dir(data_dir.path())
+                .build(),
+        )
+        .unwrap();
+
+        let block_a = create_test_block(&keypair_bob, 1, None, vec![]).unwrap();
+        let coinbase_a = block_a.transactions[0].output_reference(0).unwrap();
+
+        let tx_1 = TransactionBody {
+            input: TransactionInput::spending(coinbase_a.clone()),
+            outputs: vec![TransactionOutput {
+                value: GENESIS_BLOCK_REWARD as u64,
+                address: address_alice,
+            }],
+        }
+        .into_tx(&keypair_bob)
+        .unwrap();
+
+        let tx_2 = TransactionBody {
+            input: TransactionInput::spending(tx_1.output_reference(0).unwrap()),
+            outputs: vec![TransactionOutput {
+                value: GENESIS_BLOCK_REWARD as u64,
+                address: address_charlie.clone(),
+            }],
+        }
+        .into_tx(&keypair_alice)
+        .unwrap();
+
+        let block_b =
+            create_test_block(&keypair_bob, 1, Some(&block_a), vec![tx_1.clone()]).unwrap();
+        let block_c = create_test_block(&keypair_bob, 1, Some(&block_b), vec![]).unwrap();
+        let hash_b = block_b.header.hash().unwrap();
+        let hash_c = block_c.header.hash().unwrap();
+
+        for block in [block_a.clone(), block_b, block_c.clone()] {
+            node.handle_message(Message::NewBlock(block)).unwrap();
+        }
+        node.handle_message(Message::NewTransaction(tx_2.clone()))
+            .unwrap();
+
+        let keypair_fork = KeyPair::generate();
+        let block_d = create_test_block(&keypair_fork, 1, Some(&block_a), vec![]).unwrap();
+        let block_e = create_test_block(&keypair_fork, 1, Some(&block_d), vec![]).unwrap();
+        let block_f = create_test_block(&keypair_fork, 1, Some(&block_e), vec![]).unwrap();
+        let hash_d = block_d.header.hash().unwrap();
+        let hash_e = block_e.header.hash().unwrap();
+        let hash_f = block_f.header.hash().unwrap();
+
+        let mut events = node.subscribe();
+
+        // a lighter side branch is stored without switching tips
+        node.handle_message(Message::NewBlock(block_d)).unwrap();
+        assert!(drain_events(&mut events).is_empty());
+
+        node.state
+            .block_manager
+            .add_block(Arc::new(block_e))
+            .unwrap();
+        node.handle_message(Message::NewBlock(block_f)).unwrap();
+
+        assert_eq!(node.tip_hash().unwrap(), Some(hash_f));
+        assert_eq!(
+            drain_events(&mut events),
+            vec![
+                NodeEvent::BlockDisconnected { hash: hash_c },
+                NodeEvent::BlockDisconnected { hash: hash_b },
+                NodeEvent::BlockConnected {
+                    hash: hash_d,
+                    height: 3
+                },
+                NodeEvent::BlockConnected {
+                    hash: hash_e,
+                    height: 4
+                },
+                NodeEvent::BlockConnected {
+                    hash: hash_f,
+                    height: 5
+                },
+                NodeEvent::TipChanged {
+                    old: Some(hash_c),
+                    new: hash_f
+                },
+            ]
+        );
+
+        // reverting via undo data matches rebuilding the new chain from scratch
+        let commitment = node.state.utxo_set.commitment().unwrap();
+        assert_eq!(
+            commitment,
+            node.state
+                .chain
+                .build_utxo_set(&node.state.block_manager)
+                .unwrap()
+                .commitment()
+                .unwrap()
+        );
+
+        // the transaction from the disconnected block is pending again, ahead of its child
+        let pending_ids = node
+            .state
+            .mem_pool
+            .pending_transactions
+            .iter()
+            .map(|tx| tx.id().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(pending_ids, vec![tx_1.id().unwrap(), tx_2.id().unwrap()]);
+
+        // a heavier branch that double spends fails partway and leaves the node untouched
+        let double_spend = TransactionBody {
+            input: TransactionInput::spending(coinbase_a),
+            outputs: vec![TransactionOutput {
+                value: GENESIS_BLOCK_REWARD as u64,
+                address: address_charlie,
+            }],
+        }
+        .into_tx(&keypair_bob)
+        .unwrap();
+
+        let block_g = create_test_block(&keypair_bob, 1, Some(&block_c), vec![]).unwrap();
+        let block_h =
+            create_test_block(&keypair_bob, 1, Some(&block_g), vec![double_spend]).unwrap();
+        let hash_h = block_h.header.hash().unwrap();
+
+        node.state
+            .block_manager
+            .add_block(Arc::new(block_g))
+            .unwrap();
+        assert!(matches!(
+            node.handle_message(Message::NewBlock(block_h)).unwrap().0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::Transaction {
+                    index: 1,
+                    error: UtxoError::MissingOutpoint { .. },
+                    ..
+                }
+            ))
+        ));
+
+        assert_eq!(node.tip_hash().unwrap(), Some(hash_f));
+        assert_eq!(node.state.utxo_set.commitment().unwrap(), commitment);
+        assert_eq!(node.state.mem_pool.pending_transactions.len(), 2);
+        assert!(!node.state.block_manager.contains_block(&hash_h));
+        assert!(drain_events(&mut events).is_empty());
+    }
+
+    #[test]
+    fn test_balances() {
+        let keypair_bob = KeyPair::generate();
+        let address_bob = Address::from_public_key(&keypair_bob.public_key);
+        let keypair_alice = KeyPair::generate();
+        let address_alice = Address::from_public_key(&keypair_alice.public_key);
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair_bob.clone())
+                .data_dir(data_dir.path())
+                .build(),
+        )
+        .unwrap();
+
+        let first_block = create_test_block(&keypair_bob, 1, None, vec![]).unwrap();
+        node.handle_message(Message::NewBlock(first_block.clone()))
+            .unwrap();
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+
+        let reward = GENESIS_BLOCK_REWARD as u64;
+        let coinbase_outpoint = first_block.transactions[0].output_reference(0).unwrap();
+
+        assert_eq!(node.get_confirmed_balance(&address_bob), 2 * reward);
+        assert_eq!(node.get_balance(&address_bob).unwrap(), 2 * reward);
+
+        let unspent = node.list_unspent(&address_bob).unwrap();
+        assert_eq!(unspent.len(), 2);
+        assert_eq!(unspent[0].outpoint, coinbase_outpoint);
+        assert_eq!(unspent[0].confirmations, 2);
+        assert_eq!(unspent[1].confirmations, 1);
+
+        let tx_body = TransactionBody {
+            input: TransactionInput::spending(coinbase_outpoint.clone()),
+            outputs: vec![
+                TransactionOutput {
+                    value: reward / 2,
+                    address: address_alice.clone(),
+                },
+                TransactionOutput {
+                    value: reward / 2,
+                    address: address_bob.clone(),
+                },
+            ],
+        };
+        let tx = tx_body.into_tx(&keypair_bob).unwrap();
+        node.handle_message(Message::NewTransaction(tx.clone()))
+            .unwrap();
+
+        // the spent coinbase no longer counts towards bob's spendable balance
+        assert_eq!(node.get_confirmed_balance(&address_bob), 2 * reward);
+        assert_eq!(node.get_balance(&address_bob).unwrap(), reward + reward / 2);
+        assert!(
+            node.list_unspent(&address_bob)
+                .unwrap()
+                .iter()
+                .all(|output| output.outpoint != coinbase_outpoint)
+        );
+
+        assert_eq!(node.get_confirmed_balance(&address_alice), 0);
+        assert_eq!(node.get_balance(&address_alice).unwrap(), reward / 2);
+        assert_eq!(
+            node.list_unspent(&address_alice).unwrap(),
+            vec![UnspentOutput {
+                outpoint: tx.output_reference(0).unwrap(),
+                value: reward / 2,
+                confirmations: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let keypair_bob = KeyPair::generate();
+        let address_bob = Address::from_public_key(&keypair_bob.public_key);
+        let keypair_alice = KeyPair::generate();
+        let address_alice = Address::from_public_key(&keypair_alice.public_key);
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair_bob.clone())
+                .data_dir(data_dir.path())
+                .mempool(MemPoolConfig {
+                    dust_limit: 5,
+                    ..MemPoolConfig::default()
+                })
+                .build(),
+        )
+        .unwrap();
+
+        let first_block = create_test_block(&keypair_bob, 1, None, vec![]).unwrap();
+        node.handle_message(Message::NewBlock(first_block)).unwrap();
+
+        let reward = GENESIS_BLOCK_REWARD as u64;
+
+        assert!(matches!(
+            node.send(address_alice.clone(), reward, 1),
+            Err(SendError::InsufficientFunds {
+                available: 50,
+                required: 51
+            })
+        ));
+        assert!(matches!(
+            node.send(address_alice.clone(), reward - 3, 1),
+            Err(SendError::DustChange { change: 2, .. })
+        ));
+        assert!(matches!(
+            node.send(address_alice.clone(), u64::MAX, 1),
+            Err(SendError::ValueOverflow)
+        ));
+        let huge = |value| UnspentOutput {
+            outpoint: TransactionOutputReference {
+                id: TxId::empty(),
+                index: 0,
+            },
+            value,
+            confirmations: 1,
+        };
+        assert!(matches!(
+            build_payment(
+                &keypair_bob,
+                Network::Mainnet,
+                vec![huge(u64::MAX), huge(1)],
+                address_alice.clone(),
+                1,
+                1,
+                0,
+            ),
+            Err(SendError::ValueOverflow)
+        ));
+
+        // a testnet address is refused by a mainnet node
+        let testnet_alice =
+            Address::from_public_key_for(&keypair_alice.public_key, Network::Testnet);
+        let error = node.send(testnet_alice.clone(), reward / 2, 1).unwrap_err();
+        assert!(matches!(
+            &error,
+            SendError::Rejected(RejectReason::WrongNetwork {
+                network: Network::Mainnet,
+                ..
+            })
+        ));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Transaction rejected: Output pays {testnet_alice}, which isn't a mainnet address"
+            )
+        );
+        assert!(node.state.mem_pool.pending_transactions.is_empty());
+
+        let id = node.send(address_alice.clone(), reward / 2, 1).unwrap();
+        assert_eq!(node.state.mem_pool.pending_transactions.len(), 1);
+        assert_eq!(
+            node.state.mem_pool.pending_transactions[0].id().unwrap(),
+            id
+        );
+
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+
+        // bob keeps the change, the new block reward, and the fee he paid himself as miner
+        assert_eq!(node.state.mem_pool.pending_transactions.len(), 0);
+        assert_eq!(node.get_confirmed_balance(&address_alice), reward / 2);
+        assert_eq!(
+            node.get_confirmed_balance(&address_bob),
+            2 * reward - reward / 2
+        );
+    }
+
+    /// Delivers messages between two nodes until neither has anything left to send.
+    fn exchange(node_a: &mut Node, node_b: &mut Node, initial: Message) {
+        let mut to_a = vec![initial];
+        let mut to_b = vec![];
+
+        while !to_a.is_empty() || !to_b.is_empty() {
+            for message in std::mem::take(&mut to_a) {
+                let (outcome, replies) = node_a.handle_message(message).unwrap();
+                assert!(!matches!(outcome, HandleOutcome::Rejected(_)));
+                to_b.extend(replies);
+            }
+            for message in std::mem::take(&mut to_b) {
+                let (outcome, replies) = node_b.handle_message(message).unwrap();
+                assert!(!matches!(outcome, HandleOutcome::Rejected(_)));
+                to_a.extend(replies);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sync_messages() {
+        let keypair = KeyPair::generate();
+
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let mut node_a = Node::new(
+            NodeConfig::builder(keypair.clone())
+                .data_dir(data_dir_a.path())
+                .build(),
+        )
+        .unwrap();
+
+        let first_block = create_test_block(&keypair, 1, None, vec![]).unwrap();
+        node_a
+            .handle_message(Message::NewBlock(first_block))
+            .unwrap();
+
+        while node_a.state.chain.height() < 20 {
+            let block = node_a.create_block().unwrap();
+            node_a.handle_message(Message::NewBlock(block)).unwrap();
+        }
+
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let mut node_b = Node::new(
+            NodeConfig::builder(KeyPair::generate())
+                .data_dir(data_dir_b.path())
+                .build(),
+        )
+        .unwrap();
+
+        let request = node_b.request_headers().unwrap();
+        exchange(&mut node_a, &mut node_b, request);
+
+        assert_eq!(node_b.state.chain.height(), 20);
+        assert_eq!(node_b.tip_hash().unwrap(), node_a.tip_hash().unwrap());
+
+        // once in sync, another round only returns no headers
+        let request = node_b.request_headers().unwrap();
+        assert!(matches!(
+            node_a.handle_message(request).unwrap().1.as_slice(),
+            [Message::Headers(headers)] if headers.is_empty()
+        ));
+
+        // announcing a known block doesn't trigger a download
+        let tip = node_a.tip_hash().unwrap().unwrap();
+        assert!(matches!(
+            node_b.handle_message(Message::Inv(vec![tip])).unwrap(),
+            (HandleOutcome::Duplicate, replies) if replies.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_initial_block_download() {
+        let regtest_node = |sync: SyncConfig| {
+            let data_dir = tempfile::tempdir().unwrap();
+            let node = Node::new(
+                NodeConfig::builder(KeyPair::generate())
+                    .data_dir(data_dir.path())
+                    .network(NetworkParams::regtest())
+                    .sync(sync)
+                    .build(),
+            )
+            .unwrap();
+            (node, data_dir)
+        };
+
+        let (mut source, _source_dir) = regtest_node(SyncConfig::default());
+        let mut blocks = Vec::new();
+
+        while blocks.len() < 500 {
+            let block = source.create_block().unwrap();
+            source
+                .handle_message(Message::NewBlock(block.clone()))
+                .unwrap();
+            blocks.push(block);
+        }
+
+        // without announced headers, every block switches the tip on its own
+        let (mut naive, _naive_dir) = regtest_node(SyncConfig::default());
+        let bootstrap_rebuilds = naive.metrics().unwrap().utxo_set_rebuilds;
+        for block in &blocks {
+            naive
+                .handle_message(Message::NewBlock(block.clone()))
+                .unwrap();
+        }
+        let naive_rebuilds = naive.metrics().unwrap().utxo_set_rebuilds - bootstrap_rebuilds;
+        assert_eq!(naive_rebuilds, 500);
+
+        let sync = SyncConfig {
+            assume_valid: Some(blocks[249].header.hash().unwrap()),
+            ..SyncConfig::default()
+        };
+        let (mut node, _data_dir) = regtest_node(sync.clone());
+        let mut events = node.subscribe();
+
+        let request = node.request_headers().unwrap();
+        exchange(&mut source, &mut node, request);
+
+        let metrics = node.metrics().unwrap();
+        assert!(!node.state.is_syncing());
+        assert_eq!(metrics.best_header_height, 501);
+        assert_eq!(node.tip_hash().unwrap(), source.tip_hash().unwrap());
+        assert_eq!(
+            node.state.utxo_set.commitment().unwrap(),
+            source.state.utxo_set.commitment().unwrap()
+        );
+        assert!(node.state.unverified_blocks.is_empty());
+
+        // one switch per full batch, then one per block within the lag threshold
+        let rebuilds = metrics.utxo_set_rebuilds - bootstrap_rebuilds;
+        assert!(rebuilds <= 500 / sync.batch_size as u64 + sync.lag_threshold as u64 + 1);
+        assert!(rebuilds * 10 < naive_rebuilds);
+
+        let tip_changes = std::iter::from_fn(|| events.try_recv().ok())
+            .filter(|event| matches!(event, NodeEvent::TipChanged { .. }))
+            .count();
+        assert_eq!(tip_changes as u64, rebuilds);
+    }
+
+    #[test]
+    fn test_reload_from_data_dir() {
+        let keypair = KeyPair::generate();
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = NodeConfig::builder(keypair.clone())
+            .data_dir(data_dir.path())
+            .build();
+
+        let mut node = Node::new(config.clone()).unwrap();
+
+        let block_a = create_test_block(&keypair, 1, None, vec![]).unwrap();
+        node.handle_message(Message::NewBlock(block_a)).unwrap();
+        let block_b = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block_b)).unwrap();
+
+        let reloaded = Node::new(config).unwrap();
+
+        assert_eq!(reloaded.state.chain.height(), 3);
+        assert_eq!(reloaded.tip_hash().unwrap(), node.tip_hash().unwrap());
+        assert_eq!(
+            reloaded.state.utxo_set.outputs.len(),
+            node.state.utxo_set.outputs.len()
+        );
+    }
+
+    #[test]
+    fn test_genesis_bootstrap() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = NodeConfig::builder(KeyPair::generate())
+            .data_dir(data_dir.path())
+            .network(NetworkParams::regtest())
+            .build();
+
+        let node = Node::new(config.clone()).unwrap();
+        let genesis_hash = NetworkParams::regtest().genesis_hash().unwrap();
 
         assert_eq!(node.state.chain.height(), 1);
+        assert_eq!(node.tip_hash().unwrap(), Some(genesis_hash));
+        assert!(
+            config
+                .blocks_dir()
+                .join(format!("{}.json", genesis_hash.display()))
+                .exists()
+        );
+        drop(node);
 
-        let block_b = create_test_block(&keypair, 2, Some(&block_a), vec![]).unwrap();
-        node.handle_message(Message::NewBlock(block_b.clone()))
+        let mut reopened = Node::new(config).unwrap();
+        assert_eq!(reopened.tip_hash().unwrap(), Some(genesis_hash));
+
+        // some other block claiming to start the chain
+        let mut root = reopened.create_block().unwrap();
+        root.header.previous_block_hash = Hash::default();
+        root.mine().unwrap();
+
+        assert!(matches!(
+            reopened.handle_message(Message::NewBlock(root)).unwrap().0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(BlockValidationError::NotGenesis))
+        ));
+        drop(reopened);
+
+        let err = Node::new(
+            NodeConfig::builder(KeyPair::generate())
+                .data_dir(data_dir.path())
+                .network(NetworkParams::testnet())
+                .build(),
+        )
+        .err()
+        .unwrap();
+
+        assert!(err.to_string().contains("not the testnet genesis block"));
+    }
+
+    #[test]
+    fn test_max_skipped_block_files() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = |max| {
+            let builder = NodeConfig::builder(KeyPair::generate())
+                .data_dir(data_dir.path())
+                .network(NetworkParams::regtest());
+            match max {
+                Some(max) => builder.max_skipped_block_files(max),
+                None => builder,
+            }
+            .build()
+        };
+
+        let blocks_dir = config(None).blocks_dir();
+        Node::new(config(None)).unwrap();
+        fs::write(
+            blocks_dir.join(format!("{}.json", Hash::default().display())),
+            b"{not json",
+        )
+        .unwrap();
+
+        // skipped whatever their number, unless there's a limit
+        Node::new(config(None)).unwrap();
+        Node::new(config(Some(1))).unwrap();
+        let error = Node::new(config(Some(0))).err().unwrap();
+        assert!(
+            error.to_string().contains("more than the 0 allowed"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_custom_params() {
+        let params = NetworkParams {
+            genesis_block_reward: 8,
+            blocks_per_reward_halving: 2,
+            ..NetworkParams::regtest()
+        };
+        let keypair = KeyPair::generate();
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair.clone())
+                .data_dir(data_dir.path())
+                .network(params.clone())
+                .build(),
+        )
+        .unwrap();
+
+        for height in 2..=5 {
+            let block = node.create_block().unwrap();
+            assert_eq!(
+                block.transactions[0].output_value(),
+                Transaction::block_reward(height, &params)
+            );
+            node.handle_message(Message::NewBlock(block)).unwrap();
+        }
+        assert_eq!(
+            node.metrics().unwrap().utxo_total_value,
+            params.expected_supply(5).unwrap()
+        );
+
+        // a coinbase claiming the default regtest reward is more than these params allow
+        let mut block = node.create_block().unwrap();
+        block.transactions[0] = Arc::new(
+            Transaction::new_coinbase_with_fees(&keypair, 6, 0, &NetworkParams::regtest()).unwrap(),
+        );
+        block.header.merkle_root = Transaction::build_merkle_tree(&block.transactions)
+            .unwrap()
+            .root()
             .unwrap();
+        block.mine().unwrap();
+
+        assert!(matches!(
+            node.handle_message(Message::NewBlock(block)).unwrap().0,
+            HandleOutcome::Rejected(_)
+        ));
+    }
 
+    #[test]
+    fn test_proof_of_authority() {
+        let validator = KeyPair::generate();
+        let params = NetworkParams {
+            genesis_message: Some("authority".to_string()),
+            consensus: ConsensusMode::ProofOfAuthority {
+                authorities: vec![PublicKeyOnly::from(&validator)],
+            },
+            ..NetworkParams::regtest()
+        };
+        let node_with = |keypair: &KeyPair, params: NetworkParams| {
+            Node::in_memory(NodeConfig::builder(keypair.clone()).network(params).build()).unwrap()
+        };
+        let mut node = node_with(&validator, params.clone());
+
+        // signed by an authority rather than mined, and only at difficulty 0
+        let block = node.create_block().unwrap();
+        assert!(block.signature.is_some());
+        assert_eq!(
+            node.handle_message(Message::NewBlock(block.clone()))
+                .unwrap()
+                .0,
+            HandleOutcome::Accepted
+        );
         assert_eq!(node.state.chain.height(), 2);
+        assert_eq!(
+            Block::from_bytes(&block.to_bytes().unwrap()).unwrap(),
+            block
+        );
+        let frame = crate::wire::encode_message(&Message::NewBlock(block.clone()).into());
+        assert!(matches!(
+            crate::wire::decode_message(&mut frame.as_slice()).unwrap(),
+            crate::wire::NetMessage::Node(Message::NewBlock(decoded)) if decoded == block
+        ));
+        let json = serde_json::to_string(&block).unwrap();
+        assert_eq!(serde_json::from_str::<Block>(&json).unwrap(), block);
+
+        let outsider = KeyPair::generate();
+        let mut unauthorized = node.block_template().unwrap();
+        unauthorized.sign(&outsider).unwrap();
+        assert_eq!(
+            node.handle_message(Message::NewBlock(unauthorized))
+                .unwrap()
+                .0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::UnauthorizedValidator {
+                    public_key: PublicKeyOnly::from(&outsider)
+                }
+            ))
+        );
+
+        let mut unsigned = node.block_template().unwrap();
+        unsigned.mine().unwrap();
+        assert_eq!(
+            node.handle_message(Message::NewBlock(unsigned)).unwrap().0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::MissingSignature
+            ))
+        );
+
+        // the signature covers the header
+        let mut tampered = node.create_block().unwrap();
+        tampered.header.timestamp += 1;
+        assert_eq!(
+            node.handle_message(Message::NewBlock(tampered)).unwrap().0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::BadValidatorSignature
+            ))
+        );
+
+        // a node mining the same chain refuses signed blocks
+        let genesis_hash = params.genesis_hash().unwrap();
+        let mut pow_node = node_with(
+            &outsider,
+            NetworkParams {
+                consensus: ConsensusMode::ProofOfWork,
+                ..params
+            },
+        );
+        assert_eq!(pow_node.tip_hash().unwrap(), Some(genesis_hash));
+        assert_eq!(
+            pow_node.handle_message(Message::NewBlock(block)).unwrap().0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::UnexpectedSignature
+            ))
+        );
+    }
+
+    #[test]
+    fn test_append_block() {
+        let keypair = KeyPair::generate();
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair.clone())
+                .data_dir(data_dir.path())
+                .build(),
+        )
+        .unwrap();
+
+        let block_a = create_test_block(&keypair, 2, None, vec![]).unwrap();
+        node.handle_message(Message::NewBlock(block_a.clone()))
+            .unwrap();
+
+        assert_eq!(node.state.chain.height(), 2);
+
+        let block_b = create_test_block(&keypair, 2, Some(&block_a), vec![]).unwrap();
+        assert_eq!(
+            node.handle_message(Message::NewBlock(block_b.clone()))
+                .unwrap()
+                .0,
+            HandleOutcome::Accepted
+        );
+
+        assert_eq!(node.state.chain.height(), 3);
+
+        assert_eq!(
+            node.handle_message(Message::NewBlock(block_b.clone()))
+                .unwrap()
+                .0,
+            HandleOutcome::Duplicate
+        );
 
         // add a block that is not the next in the chain, should be orphaned
         let block_c = create_test_block(&keypair, 2, Some(&block_b), vec![]).unwrap();
@@ -271,10 +2178,96 @@ mod tests {
 
         assert_eq!(node.state.block_manager.orphan_blocks.len(), 0);
 
-        node.handle_message(Message::NewBlock(block_d.clone()))
-            .unwrap();
+        assert_eq!(
+            node.handle_message(Message::NewBlock(block_d.clone()))
+                .unwrap()
+                .0,
+            HandleOutcome::Orphaned {
+                missing_parent: block_c.header.hash().unwrap()
+            }
+        );
 
         assert_eq!(node.state.block_manager.orphan_blocks.len(), 1);
-        assert_eq!(node.state.chain.height(), 2);
+        assert_eq!(node.state.chain.height(), 3);
+
+        // a nonce that doesn't meet the target
+        let mut unmined = create_test_block(&keypair, 2, Some(&block_b), vec![]).unwrap();
+        while unmined.header.validate_hash().unwrap() {
+            unmined.header.nonce += 1;
+        }
+
+        assert_eq!(
+            node.handle_message(Message::NewBlock(unmined)).unwrap().0,
+            HandleOutcome::Rejected(RejectReason::InvalidBlock(
+                BlockValidationError::InvalidProofOfWork
+            ))
+        );
+    }
+
+    #[test]
+    fn test_transaction_outcomes() {
+        let keypair = KeyPair::generate();
+        let recipient = Address::from_public_key(&KeyPair::generate().public_key);
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair.clone())
+                .data_dir(data_dir.path())
+                .mempool(MemPoolConfig {
+                    max_transactions: 1,
+                    min_fee: 2,
+                    ..MemPoolConfig::default()
+                })
+                .build(),
+        )
+        .unwrap();
+
+        let block_a = create_test_block(&keypair, 1, None, vec![]).unwrap();
+        let block_b = create_test_block(&keypair, 1, Some(&block_a), vec![]).unwrap();
+        node.handle_message(Message::NewBlock(block_a.clone()))
+            .unwrap();
+        node.handle_message(Message::NewBlock(block_b.clone()))
+            .unwrap();
+
+        let reward = GENESIS_BLOCK_REWARD as u64;
+        let spend = |block: &Block, fee: u64, signer: &KeyPair| {
+            TransactionBody {
+                input: TransactionInput::spending(
+                    block.transactions[0].output_reference(0).unwrap(),
+                ),
+                outputs: vec![TransactionOutput {
+                    value: reward - fee,
+                    address: recipient.clone(),
+                }],
+            }
+            .into_tx(signer)
+            .unwrap()
+        };
+
+        let mut outcome =
+            |tx: Transaction| node.handle_message(Message::NewTransaction(tx)).unwrap().0;
+
+        assert!(matches!(
+            outcome(spend(&block_a, 2, &KeyPair::generate())),
+            HandleOutcome::Rejected(RejectReason::InvalidTransaction(
+                UtxoError::OwnershipMismatch { .. }
+            ))
+        ));
+        assert_eq!(
+            outcome(spend(&block_a, 1, &keypair)),
+            HandleOutcome::Rejected(RejectReason::LowFee { fee: 1, min_fee: 2 })
+        );
+        assert_eq!(
+            outcome(spend(&block_a, 2, &keypair)),
+            HandleOutcome::Accepted
+        );
+        assert_eq!(
+            outcome(spend(&block_a, 2, &keypair)),
+            HandleOutcome::Duplicate
+        );
+        assert_eq!(
+            outcome(spend(&block_b, 2, &keypair)),
+            HandleOutcome::Rejected(RejectReason::PoolFull)
+        );
     }
 }
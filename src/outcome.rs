@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::BlockValidationError,
+    crypto::Hash,
+    params::Network,
+    transaction::{TransactionOutputReference, TxId, TxValidationError},
+    utxo_set::UtxoError,
+};
+
+/// Result of a message the node handled without an internal error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandleOutcome {
+    Accepted,
+    /// The block or transaction is already known.
+    Duplicate,
+    /// The block was stored but can't be connected until its parent arrives.
+    Orphaned {
+        missing_parent: Hash,
+    },
+    Rejected(RejectReason),
+}
+
+impl HandleOutcome {
+    /// Maps an error carrying a `RejectReason` or `BlockValidationError` to `Rejected`,
+    /// passing any other error through.
+    pub fn from_error(error: anyhow::Error) -> anyhow::Result<Self> {
+        let reason = match error.downcast::<BlockValidationError>() {
+            Ok(error) => RejectReason::InvalidBlock(error),
+            Err(error) => error.downcast::<RejectReason>()?,
+        };
+        Ok(Self::Rejected(reason))
+    }
+}
+
+/// Why a peer's block or transaction was refused. Validation code returns these wrapped
+/// in `anyhow::Error`; anything else is an internal failure rather than the peer's fault.
+/// Serialized as `{"reason": "low_fee", "details": {...}}` for RPC clients to match on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[serde(tag = "reason", content = "details", rename_all = "snake_case")]
+pub enum RejectReason {
+    #[error("Invalid block: {0}")]
+    InvalidBlock(BlockValidationError),
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(UtxoError),
+    #[error("Transaction fee of {fee} is below the minimum of {min_fee}")]
+    LowFee { fee: u64, min_fee: u64 },
+    #[error("Mempool is full")]
+    PoolFull,
+    /// The output spent is neither unspent on the chain nor created by a pending
+    /// transaction.
+    #[error("Input {outpoint} is missing or already spent")]
+    MissingInput {
+        outpoint: TransactionOutputReference,
+    },
+    /// A pending transaction already spends the same output.
+    #[error("Input is already spent by pending transaction {txid}")]
+    Conflict { txid: TxId },
+    #[error("Output pays {address}, which isn't a {network} address")]
+    WrongNetwork { address: String, network: Network },
+}
+
+impl RejectReason {
+    /// The variant's name as it's serialized, to count rejections by.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidBlock(_) => "invalid_block",
+            Self::InvalidTransaction(_) => "invalid_transaction",
+            Self::LowFee { .. } => "low_fee",
+            Self::PoolFull => "pool_full",
+            Self::MissingInput { .. } => "missing_input",
+            Self::Conflict { .. } => "conflict",
+            Self::WrongNetwork { .. } => "wrong_network",
+        }
+    }
+
+    /// How much sending something refused for this reason counts against a peer. An
+    /// honest peer with a different view of the chain or pool can send what we refuse
+    /// for a missing input, a conflict, a low fee, a full pool, an input still locked at
+    /// our height, or an unknown parent, and our dust and size limits are only ours, and
+    /// clocks drift, so those count for little or nothing; anything else breaks rules
+    /// that every node enforces alike.
+    pub fn misbehavior(&self) -> u32 {
+        match self {
+            Self::LowFee { .. }
+            | Self::PoolFull
+            | Self::MissingInput { .. }
+            | Self::Conflict { .. }
+            | Self::InvalidTransaction(
+                UtxoError::Locked { .. }
+                | UtxoError::LockedUnconfirmed { .. }
+                | UtxoError::Transaction(
+                    TxValidationError::Dust { .. } | TxValidationError::Oversized { .. },
+                ),
+            ) => 0,
+            Self::InvalidBlock(
+                BlockValidationError::UnknownParent { .. }
+                | BlockValidationError::TimestampTooFar { .. },
+            ) => 1,
+            _ => 10,
+        }
+    }
+}
+
+impl From<UtxoError> for RejectReason {
+    /// Keeps the reasons peers already match on for missing inputs and foreign addresses.
+    fn from(error: UtxoError) -> Self {
+        match error {
+            UtxoError::MissingOutpoint { outpoint } => Self::MissingInput { outpoint },
+            UtxoError::Transaction(TxValidationError::WrongNetwork { address, network }) => {
+                Self::WrongNetwork { address, network }
+            }
+            error => Self::InvalidTransaction(error),
+        }
+    }
+}
+
+impl From<TxValidationError> for RejectReason {
+    fn from(error: TxValidationError) -> Self {
+        UtxoError::from(error).into()
+    }
+}
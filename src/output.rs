@@ -0,0 +1,50 @@
+//! How the CLI writes a command's result: as JSON for scripts, or as text for people.
+//! Only results go to stdout, progress and warnings go to stderr, so with `--output json`
+//! stdout is always exactly one JSON document.
+
+use std::io::{self, Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use strum_macros::Display;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Display)]
+pub enum OutputFormat {
+    #[default]
+    #[strum(to_string = "text")]
+    Text,
+    #[strum(to_string = "json")]
+    Json,
+}
+
+/// A command's result. Its JSON form is its serde form.
+pub trait Render: Serialize {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Writes `value` to stdout in `format`.
+pub fn render(value: &impl Render, format: OutputFormat) -> Result<()> {
+    let mut out = io::stdout().lock();
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, value)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Text => value.render_text(&mut out)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The result of the commands that check something, printed as "valid" or "invalid".
+#[derive(Serialize)]
+pub struct Verdict {
+    pub valid: bool,
+}
+
+impl Render for Verdict {
+    fn render_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}", if self.valid { "valid" } else { "invalid" })
+    }
+}
@@ -0,0 +1,2252 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::Write,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use futures::StreamExt;
+use libp2p::{
+    Multiaddr, PeerId, StreamProtocol, Swarm, Transport, autonat,
+    core::{muxing::StreamMuxerBox, transport::ListenerId, upgrade},
+    dcutr,
+    gossipsub::{
+        self, IdentTopic, MessageAcceptance, MessageAuthenticity, MessageId, PublishError,
+        ValidationMode,
+    },
+    identify, identity,
+    kad::{self, store::MemoryStore},
+    mdns,
+    multiaddr::Protocol,
+    noise, ping, relay,
+    request_response::{self, OutboundFailure, OutboundRequestId, ProtocolSupport},
+    swarm::{
+        ConnectionId, DialError, NetworkBehaviour, SwarmEvent, behaviour::toggle::Toggle,
+        dial_opts::DialOpts,
+    },
+    tcp, yamux,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+use crate::{
+    bandwidth::{ByteCounters, CountingMuxer},
+    block::{Block, BlockHeader},
+    block_download::{BlockDownload, DownloadError},
+    bootstrap::{BootstrapPeer, BootstrapPeers},
+    config::P2pConfig,
+    constants::{
+        BLOCK_REQUEST_TIMEOUT_SECS, DHT_RANDOM_WALK_INTERVAL_SECS, HANDSHAKE_TIMEOUT_SECS,
+        MAX_HEADERS_PER_MESSAGE, MAX_INVENTORY_PER_MESSAGE, PEER_BOOK_MAX_AGE_SECS,
+    },
+    crypto::{Hash, sha256d},
+    handshake::HANDSHAKE_PROTOCOL,
+    header_chain::HeaderError,
+    inventory::KnownInventory,
+    node::{Message, NodeEvent},
+    outcome::{HandleOutcome, RejectReason},
+    peer_book::PeerBook,
+    rate_limit::{MessageKind, PeerRateLimiter, RateLimits, Verdict},
+    seen_cache::SeenCache,
+    shared_node::SharedNode,
+    transaction::{Transaction, TxId},
+    wire::{NetMessage, Version, WireCodec},
+};
+
+pub const BLOCKS_TOPIC: &str = "tiny-crypto/blocks/1";
+pub const TRANSACTIONS_TOPIC: &str = "tiny-crypto/txs/1";
+pub const KADEMLIA_PROTOCOL: &str = "/tiny-crypto/kad/1";
+pub const IDENTIFY_PROTOCOL: &str = "/tiny-crypto/id/1";
+pub const SYNC_PROTOCOL: &str = "/tiny-crypto/sync/1";
+
+#[derive(NetworkBehaviour)]
+pub struct Behaviour {
+    pub gossipsub: gossipsub::Behaviour,
+    pub mdns: Toggle<mdns::tokio::Behaviour>,
+    pub kademlia: kad::Behaviour<MemoryStore>,
+    /// Tells Kademlia the addresses peers listen on, which inbound connections don't.
+    pub identify: identify::Behaviour,
+    pub ping: ping::Behaviour,
+    pub handshake: request_response::Behaviour<WireCodec>,
+    /// Headers and blocks requested directly from a peer while catching up.
+    pub sync: request_response::Behaviour<WireCodec>,
+    /// Dials and listens through circuit relays.
+    pub relay_client: Toggle<relay::client::Behaviour>,
+    /// Upgrades relayed connections to direct ones by hole punching.
+    pub dcutr: Toggle<dcutr::Behaviour>,
+    /// Probes whether we're publicly reachable, to decide whether we need a relay.
+    pub autonat: Toggle<autonat::Behaviour>,
+}
+
+impl Behaviour {
+    /// Relaying, hole punching, and AutoNAT are only composed in along with a relay
+    /// client, which the swarm builder hands us when it set up the relay transport.
+    fn new(
+        key: &identity::Keypair,
+        mdns_enabled: bool,
+        relay_client: Option<relay::client::Behaviour>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mdns = if mdns_enabled {
+            Some(mdns::tokio::Behaviour::new(
+                mdns::Config::default(),
+                key.public().to_peer_id(),
+            )?)
+        } else {
+            None
+        };
+
+        let gossipsub_config = gossipsub::ConfigBuilder::default()
+            .validation_mode(ValidationMode::Strict)
+            // messages are only forwarded once the node has accepted them
+            .validate_messages()
+            .message_id_fn(|message| MessageId::from(sha256d(&message.data).to_vec()))
+            .build()?;
+
+        let peer_id = key.public().to_peer_id();
+        let mut kademlia = kad::Behaviour::with_config(
+            peer_id,
+            MemoryStore::new(peer_id),
+            kad::Config::new(StreamProtocol::new(KADEMLIA_PROTOCOL)),
+        );
+        // answer queries even before we know our external address
+        kademlia.set_mode(Some(kad::Mode::Server));
+
+        let relayed = relay_client.is_some();
+
+        Ok(Behaviour {
+            gossipsub: gossipsub::Behaviour::new(
+                MessageAuthenticity::Signed(key.clone()),
+                gossipsub_config,
+            )?,
+            mdns: mdns.into(),
+            kademlia,
+            identify: identify::Behaviour::new(identify::Config::new(
+                IDENTIFY_PROTOCOL.to_string(),
+                key.public(),
+            )),
+            ping: ping::Behaviour::new(ping::Config::new()),
+            handshake: request_response::Behaviour::new(
+                [(
+                    StreamProtocol::new(HANDSHAKE_PROTOCOL),
+                    ProtocolSupport::Full,
+                )],
+                request_response::Config::default()
+                    .with_request_timeout(Duration::from_secs(HANDSHAKE_TIMEOUT_SECS)),
+            ),
+            sync: request_response::Behaviour::new(
+                [(StreamProtocol::new(SYNC_PROTOCOL), ProtocolSupport::Full)],
+                request_response::Config::default()
+                    .with_request_timeout(Duration::from_secs(BLOCK_REQUEST_TIMEOUT_SECS)),
+            ),
+            relay_client: relay_client.into(),
+            dcutr: relayed.then(|| dcutr::Behaviour::new(peer_id)).into(),
+            autonat: relayed
+                .then(|| autonat::Behaviour::new(peer_id, autonat::Config::default()))
+                .into(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionDirection {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub peer_id: PeerId,
+    /// Remote address of our connection to the peer, if we have one.
+    pub connected_address: Option<Multiaddr>,
+    /// Whether the peer dialed us or we dialed it, for our first connection to it.
+    pub direction: Option<ConnectionDirection>,
+    /// Unix time we connected to the peer.
+    pub connected_since: Option<i64>,
+    /// Addresses the peer currently advertises on the local network.
+    pub mdns_addresses: Vec<Multiaddr>,
+    /// Best height the peer reported in its handshake.
+    pub best_height: Option<u32>,
+    /// Round trip time of the last ping.
+    pub latency: Option<Duration>,
+    /// Bytes received from and sent to the peer since it connected.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Misbehavior score, starting from what the peer book has against it.
+    pub score: u32,
+}
+
+impl PeerInfo {
+    fn new(peer_id: PeerId) -> Self {
+        Self {
+            peer_id,
+            connected_address: None,
+            direction: None,
+            connected_since: None,
+            mdns_addresses: vec![],
+            best_height: None,
+            latency: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            score: 0,
+        }
+    }
+}
+
+/// The peers we're connected to or have discovered, shared with whoever reports on them.
+#[derive(Debug, Clone, Default)]
+pub struct PeerList {
+    peers: Arc<RwLock<BTreeMap<PeerId, PeerInfo>>>,
+    /// Updated by the transport as bytes move, so read whenever the list is.
+    bytes: Arc<RwLock<HashMap<PeerId, Arc<ByteCounters>>>>,
+    /// What peers moved before they disconnected.
+    disconnected_bytes: Arc<ByteCounters>,
+    bootstrap: Arc<RwLock<BTreeMap<Multiaddr, BootstrapPeer>>>,
+}
+
+impl PeerList {
+    pub fn get(&self) -> BTreeMap<PeerId, PeerInfo> {
+        let mut peers = self.peers.read().unwrap().clone();
+
+        for (peer_id, counters) in self.bytes.read().unwrap().iter() {
+            if let Some(peer) = peers.get_mut(peer_id) {
+                peer.bytes_in = counters.inbound();
+                peer.bytes_out = counters.outbound();
+            }
+        }
+
+        peers
+    }
+
+    /// Bytes received and sent since the service started, over every connection.
+    pub fn total_bytes(&self) -> (u64, u64) {
+        self.bytes.read().unwrap().values().fold(
+            (
+                self.disconnected_bytes.inbound(),
+                self.disconnected_bytes.outbound(),
+            ),
+            |(inbound, outbound), counters| {
+                (inbound + counters.inbound(), outbound + counters.outbound())
+            },
+        )
+    }
+
+    fn remove_byte_counters(&self, peer_id: &PeerId) {
+        if let Some(counters) = self.bytes.write().unwrap().remove(peer_id) {
+            self.disconnected_bytes.absorb(&counters);
+        }
+    }
+
+    /// The configured bootstrap peers and whether we're connected to them.
+    pub fn bootstrap_peers(&self) -> BTreeMap<Multiaddr, BootstrapPeer> {
+        self.bootstrap.read().unwrap().clone()
+    }
+
+    /// The peers we have a connection to.
+    pub fn connected(&self) -> Vec<PeerInfo> {
+        self.get()
+            .into_values()
+            .filter(|peer| peer.connected_address.is_some())
+            .collect()
+    }
+
+    fn update(&self, peer_id: PeerId, update: impl FnOnce(&mut PeerInfo)) {
+        let mut peers = self.peers.write().unwrap();
+        let peer = peers
+            .entry(peer_id)
+            .or_insert_with(|| PeerInfo::new(peer_id));
+        update(peer);
+
+        if peer.connected_address.is_none() && peer.mdns_addresses.is_empty() {
+            peers.remove(&peer_id);
+        }
+    }
+
+    /// The counters a new connection to `peer_id` adds its traffic to.
+    fn byte_counters(&self, peer_id: PeerId) -> Arc<ByteCounters> {
+        self.bytes
+            .write()
+            .unwrap()
+            .entry(peer_id)
+            .or_default()
+            .clone()
+    }
+
+    /// The handshaken peer with the most blocks, to download the chain from.
+    pub fn best_peer(&self) -> Option<(PeerId, u32)> {
+        self.peers
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(peer_id, peer)| Some((*peer_id, peer.best_height?)))
+            .max_by_key(|(_, height)| *height)
+    }
+}
+
+/// Loads the protobuf-encoded identity key at `path`, generating one readable only by the
+/// owner if it doesn't exist yet.
+pub fn load_or_create_network_key(path: &Path) -> Result<identity::Keypair> {
+    if path.exists() {
+        return Ok(identity::Keypair::from_protobuf_encoding(&fs::read(path)?)?);
+    }
+
+    let key = identity::Keypair::generate_ed25519();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)?
+        .write_all(&key.to_protobuf_encoding()?)?;
+
+    Ok(key)
+}
+
+/// The peer on the other end of a request-response event.
+fn request_peer<Request, Response>(event: &request_response::Event<Request, Response>) -> PeerId {
+    match event {
+        request_response::Event::Message { peer, .. }
+        | request_response::Event::OutboundFailure { peer, .. }
+        | request_response::Event::InboundFailure { peer, .. }
+        | request_response::Event::ResponseSent { peer, .. } => *peer,
+    }
+}
+
+/// Whether `address` goes through a circuit relay.
+fn is_relayed(address: &Multiaddr) -> bool {
+    address
+        .iter()
+        .any(|protocol| protocol == Protocol::P2pCircuit)
+}
+
+/// Connects a node to its peers. The service owns the swarm: inbound gossip and
+/// request-response messages are handed to the node, new tips and accepted transactions
+/// from the node's events are published, and what we learn about peers is kept in the
+/// peer list and peer book.
+///
+/// The service takes a `SharedNode` the caller builds, so it can be embedded alongside a
+/// miner or RPC server sharing the same node:
+///
+/// ```
+/// use tiny_crypto::{
+///     config::{NodeConfig, P2pConfig},
+///     crypto::KeyPair,
+///     node::Node,
+///     p2p::P2pService,
+///     params::NetworkParams,
+///     shared_node::SharedNode,
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let data_dir = tempfile::tempdir()?;
+/// let config = NodeConfig::builder(KeyPair::generate())
+///     .data_dir(data_dir.path())
+///     .network(NetworkParams::regtest())
+///     .build();
+/// let node = SharedNode::new(Node::new(config)?);
+///
+/// let p2p = P2pConfig {
+///     listen_addr: "/ip4/127.0.0.1/tcp/0".to_string(),
+///     ..P2pConfig::default()
+/// };
+/// let handle = P2pService::new(node.clone(), p2p).await?.spawn();
+///
+/// // ... use `node` while the service runs ...
+///
+/// handle.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct P2pService {
+    swarm: Swarm<Behaviour>,
+    listener: ListenerId,
+    node: SharedNode,
+    events: broadcast::Receiver<NodeEvent>,
+    blocks_topic: IdentTopic,
+    transactions_topic: IdentTopic,
+    peers: PeerList,
+    peer_book: PeerBook,
+    max_connections: usize,
+    /// Connected peers we haven't received a compatible `Version` from yet, and since when.
+    handshakes: HashMap<PeerId, Instant>,
+    /// Peers whose handshake showed they can't talk to us, which we won't redial.
+    incompatible: HashSet<PeerId>,
+    download: BlockDownload,
+    block_requests: HashMap<OutboundRequestId, Hash>,
+    inventory_interval: Duration,
+    known_transactions: HashMap<PeerId, KnownInventory>,
+    rate_limits: RateLimits,
+    limiters: HashMap<PeerId, PeerRateLimiter>,
+    bootstrap: BootstrapPeers,
+    seen: SeenCache,
+    relay_servers: Vec<Multiaddr>,
+    /// Whether AutoNAT decides when to reserve relay addresses.
+    relay_auto: bool,
+    /// Our listeners on relay servers, by server.
+    relay_listeners: HashMap<ListenerId, Multiaddr>,
+    /// Connections to a peer that go through a relay, closed once we hole punch a direct one.
+    relayed: HashMap<PeerId, HashSet<ConnectionId>>,
+}
+
+impl P2pService {
+    /// Listens on `config.listen_addr` and dials the bootstrap peers along with the best
+    /// entries of the peer book. The peer identity and peer book are kept in the node's
+    /// data dir.
+    pub async fn new(node: SharedNode, config: P2pConfig) -> Result<Self> {
+        let node_config = node.read().await.config.clone();
+        let key = load_or_create_network_key(&node_config.network_key_path())?;
+        let mdns_enabled = config.mdns_enabled(node_config.network.network);
+        let relay_enabled = config.relay_enabled();
+
+        let peers = PeerList::default();
+        let transport_peers = peers.clone();
+
+        let builder = libp2p::SwarmBuilder::with_existing_identity(key)
+            .with_tokio()
+            .with_other_transport(|key| {
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+                    tcp::tokio::Transport::new(tcp::Config::default())
+                        .upgrade(upgrade::Version::V1)
+                        .authenticate(noise::Config::new(key)?)
+                        .multiplex(yamux::Config::default())
+                        .map(move |(peer_id, muxer), _| {
+                            let counters = transport_peers.byte_counters(peer_id);
+                            (
+                                peer_id,
+                                CountingMuxer::new(StreamMuxerBox::new(muxer), counters),
+                            )
+                        }),
+                )
+            })?;
+        let swarm_config = |swarm_config: libp2p::swarm::Config| {
+            swarm_config.with_idle_connection_timeout(Duration::from_secs(60))
+        };
+        let mut swarm = if relay_enabled {
+            builder
+                .with_relay_client(noise::Config::new, yamux::Config::default)?
+                .with_behaviour(|key, relay_client| {
+                    Behaviour::new(key, mdns_enabled, Some(relay_client))
+                })?
+                .with_swarm_config(swarm_config)
+                .build()
+        } else {
+            builder
+                .with_behaviour(|key| Behaviour::new(key, mdns_enabled, None))?
+                .with_swarm_config(swarm_config)
+                .build()
+        };
+
+        let blocks_topic = IdentTopic::new(BLOCKS_TOPIC);
+        let transactions_topic = IdentTopic::new(TRANSACTIONS_TOPIC);
+
+        swarm.behaviour_mut().gossipsub.subscribe(&blocks_topic)?;
+        swarm
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(&transactions_topic)?;
+
+        tracing::info!(peer_id = %swarm.local_peer_id(), "Local peer id");
+
+        let listener = swarm.listen_on(config.listen_addr.parse()?)?;
+
+        let now = chrono::Utc::now().timestamp();
+        let peer_book = PeerBook::load(node_config.peer_book_path())?;
+        peer_book.prune(now, PEER_BOOK_MAX_AGE_SECS);
+
+        let mut bootstrap_addresses = Vec::new();
+        for peer in &config.bootstrap_peers {
+            let address = peer.parse::<Multiaddr>()?;
+            bootstrap_addresses.push(address.clone());
+
+            // addresses ending in /p2p/<peer id> seed the routing table directly
+            if let Some(Protocol::P2p(peer_id)) = address.iter().last() {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer_id, address.clone());
+            }
+
+            peer_book.insert(address, now);
+        }
+
+        let mut relay_servers = Vec::new();
+        for server in &config.relay_servers {
+            let address = server.parse::<Multiaddr>()?;
+            let Some(Protocol::P2p(peer_id)) = address.iter().last() else {
+                return Err(anyhow::anyhow!(
+                    "Relay server {address} doesn't end in /p2p/<peer id>"
+                ));
+            };
+
+            // the relays double as AutoNAT servers to probe our reachability
+            if let Some(autonat) = swarm.behaviour_mut().autonat.as_mut() {
+                autonat.add_server(peer_id, Some(address.clone()));
+            }
+            relay_servers.push(address);
+        }
+
+        let bootstrap = BootstrapPeers::new(
+            bootstrap_addresses,
+            Duration::from_secs(config.bootstrap_retry_secs),
+            Duration::from_secs(config.bootstrap_retry_max_secs),
+            Instant::now(),
+        );
+
+        node.write().await.state.metrics.peer_list = peers.clone();
+
+        let mut p2p = Self {
+            events: node.subscribe(),
+            swarm,
+            listener,
+            node,
+            blocks_topic,
+            transactions_topic,
+            peers,
+            peer_book,
+            max_connections: config.max_connections,
+            handshakes: HashMap::new(),
+            incompatible: HashSet::new(),
+            download: BlockDownload::default(),
+            block_requests: HashMap::new(),
+            inventory_interval: Duration::from_secs(config.inventory_interval_secs),
+            known_transactions: HashMap::new(),
+            rate_limits: config.rate_limits.clone(),
+            limiters: HashMap::new(),
+            bootstrap,
+            seen: SeenCache::default(),
+            relay_servers,
+            relay_auto: config.enable_relay_client.is_none(),
+            relay_listeners: HashMap::new(),
+            relayed: HashMap::new(),
+        };
+        p2p.dial_bootstrap();
+        p2p.dial_peer_book();
+        if config.enable_relay_client == Some(true) {
+            p2p.reserve_relays();
+        }
+
+        Ok(p2p)
+    }
+
+    pub fn local_peer_id(&self) -> &PeerId {
+        self.swarm.local_peer_id()
+    }
+
+    /// The peers we're connected to.
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        self.peers.connected()
+    }
+
+    /// The configured bootstrap peers and whether we're connected to them.
+    pub fn bootstrap_peers(&self) -> BTreeMap<Multiaddr, BootstrapPeer> {
+        self.bootstrap.get().clone()
+    }
+
+    /// A handle on the peer list that stays up to date while the service runs.
+    pub fn peer_list(&self) -> PeerList {
+        self.peers.clone()
+    }
+
+    pub fn peer_book(&self) -> PeerBook {
+        self.peer_book.clone()
+    }
+
+    /// Waits for the listener to be bound, returning its address.
+    pub async fn listen_addr(&mut self) -> Result<Multiaddr> {
+        loop {
+            match self.swarm.select_next_some().await {
+                SwarmEvent::NewListenAddr {
+                    listener_id,
+                    address,
+                } if listener_id == self.listener => return Ok(address),
+                event => self.handle_swarm_event(event).await?,
+            }
+        }
+    }
+
+    /// Runs the service until `cancel` fires or the node goes away, then shuts it down.
+    pub async fn run(mut self, cancel: CancellationToken) -> Result<()> {
+        let mut random_walk =
+            tokio::time::interval(Duration::from_secs(DHT_RANDOM_WALK_INTERVAL_SECS));
+        let mut maintenance = tokio::time::interval(Duration::from_secs(1));
+        let mut inventory = tokio::time::interval(self.inventory_interval);
+
+        let peer_book = self.peer_book.clone();
+
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => return self.shutdown().await,
+                _ = peer_book.added() => self.dial_peer_book(),
+                _ = random_walk.tick() => {
+                    self.swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .get_closest_peers(PeerId::random());
+                }
+                _ = maintenance.tick() => {
+                    self.drop_stalled_handshakes();
+                    self.dial_bootstrap();
+                    // picks up blocks requeued after a failed request
+                    self.request_blocks();
+                }
+                _ = inventory.tick() => self.announce_transactions().await?,
+                event = self.swarm.select_next_some() => self.handle_swarm_event(event).await?,
+                event = self.events.recv() => match event {
+                    Ok(event) => self.publish_node_event(event).await?,
+                    // a publish is only missed when the tip moves past it anyway
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return self.shutdown().await,
+                },
+            }
+        }
+    }
+
+    /// Runs the service on its own task.
+    pub fn spawn(self) -> P2pHandle {
+        let cancel = CancellationToken::new();
+
+        P2pHandle {
+            task: tokio::spawn(self.run(cancel.clone())),
+            cancel,
+        }
+    }
+
+    /// Stops accepting connections and saves the peer book and node state.
+    async fn shutdown(mut self) -> Result<()> {
+        self.swarm.remove_listener(self.listener);
+        self.peer_book.save()?;
+        self.node.flush().await
+    }
+
+    async fn handle_swarm_event(&mut self, event: SwarmEvent<BehaviourEvent>) -> Result<()> {
+        match event {
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => {
+                let acceptance = self
+                    .handle_gossip(propagation_source, &message.topic, &message.data)
+                    .instrument(tracing::debug_span!(
+                        "p2p.gossip",
+                        peer_id = %propagation_source,
+                        topic = %message.topic,
+                    ))
+                    .await?;
+
+                self.swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .report_message_validation_result(&message_id, &propagation_source, acceptance);
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(
+                gossipsub::Event::Subscribed { .. } | gossipsub::Event::Unsubscribed { .. },
+            )) => self.refresh_peers().await,
+            SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Discovered(discovered))) => {
+                for (peer_id, address) in discovered {
+                    tracing::debug!(%peer_id, %address, "Discovered peer over mDNS");
+
+                    self.peers.update(peer_id, |peer| {
+                        if !peer.mdns_addresses.contains(&address) {
+                            peer.mdns_addresses.push(address.clone());
+                        }
+                    });
+
+                    self.dial_discovered(peer_id, address);
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Expired(expired))) => {
+                for (peer_id, address) in expired {
+                    tracing::debug!(%peer_id, %address, "Discovered peer expired");
+
+                    self.peers.update(peer_id, |peer| {
+                        peer.mdns_addresses.retain(|known| *known != address);
+                    });
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(identify::Event::Received {
+                peer_id,
+                info,
+                ..
+            })) if !self.incompatible.contains(&peer_id) => {
+                for address in info.listen_addrs {
+                    self.swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .add_address(&peer_id, address);
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Kademlia(kad::Event::RoutingUpdated {
+                peer,
+                addresses,
+                is_new_peer,
+                ..
+            })) => {
+                if is_new_peer {
+                    tracing::debug!(peer_id = %peer, "Found peer in the DHT");
+                    self.dial_discovered(peer, addresses.first().clone());
+                }
+
+                let routing_table_size = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .kbuckets()
+                    .map(|bucket| bucket.num_entries())
+                    .sum();
+                self.node.write().await.state.metrics.routing_table_size = routing_table_size;
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event {
+                peer,
+                result: Ok(rtt),
+                ..
+            })) => self.peers.update(peer, |info| info.latency = Some(rtt)),
+            SwarmEvent::Behaviour(BehaviourEvent::Autonat(autonat::Event::StatusChanged {
+                new,
+                ..
+            })) => {
+                tracing::info!(status = ?new, "Reachability changed");
+
+                if self.relay_auto {
+                    match new {
+                        autonat::NatStatus::Private => self.reserve_relays(),
+                        autonat::NatStatus::Public(_) => self.release_relays(),
+                        autonat::NatStatus::Unknown => {}
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::RelayClient(
+                relay::client::Event::ReservationReqAccepted {
+                    relay_peer_id,
+                    renewal: false,
+                    ..
+                },
+            )) => tracing::info!(peer_id = %relay_peer_id, "Reserved an address on relay"),
+            SwarmEvent::Behaviour(BehaviourEvent::Dcutr(dcutr::Event {
+                remote_peer_id,
+                result,
+            })) => match result {
+                Ok(_) => {
+                    tracing::info!(peer_id = %remote_peer_id, "Hole punched a direct connection");
+
+                    // the direct connection is all we need
+                    for connection_id in self.relayed.remove(&remote_peer_id).unwrap_or_default() {
+                        self.swarm.close_connection(connection_id);
+                    }
+                }
+                Err(error) => {
+                    tracing::debug!(peer_id = %remote_peer_id, %error, "Failed to hole punch")
+                }
+            },
+            SwarmEvent::NewListenAddr {
+                listener_id,
+                address,
+            } if self.relay_listeners.contains_key(&listener_id) => {
+                // advertised to peers through identify and the DHT
+                tracing::info!(%address, "Listening through relay");
+                self.swarm.add_external_address(address);
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } if is_relayed(&address) => {
+                self.swarm.remove_external_address(&address);
+            }
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                reason,
+                ..
+            } => {
+                if let Some(server) = self.relay_listeners.remove(&listener_id) {
+                    tracing::info!(%server, ?reason, "Stopped listening through relay");
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Handshake(event)) => {
+                let span = tracing::debug_span!("p2p.handshake", peer_id = %request_peer(&event));
+                self.handle_handshake_event(event).instrument(span).await?
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Sync(event)) => {
+                let span = tracing::debug_span!("p2p.sync", peer_id = %request_peer(&event));
+                self.handle_sync_event(event).instrument(span).await?
+            }
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                connection_id,
+                endpoint,
+                num_established,
+                ..
+            } => {
+                if let Some(address) = self.bootstrap.connected(connection_id, peer_id) {
+                    tracing::info!(%peer_id, %address, "Connected to bootstrap peer");
+                    self.publish_bootstrap();
+                }
+
+                let address = endpoint.get_remote_address().clone();
+                if endpoint.is_relayed() {
+                    self.relayed
+                        .entry(peer_id)
+                        .or_default()
+                        .insert(connection_id);
+                }
+
+                // only addresses we dialed are ones the peer listens on
+                if endpoint.is_dialer() {
+                    self.peer_book.record_connected(
+                        address.clone().with_p2p(peer_id).unwrap_or(address.clone()),
+                        peer_id,
+                        chrono::Utc::now().timestamp(),
+                    );
+                    self.save_peer_book();
+                }
+
+                let first = num_established.get() == 1;
+                let score = self.peer_book.misbehavior(&peer_id);
+                self.peers.update(peer_id, |peer| {
+                    peer.connected_address = Some(address);
+                    peer.score = score;
+
+                    if first {
+                        peer.direction = Some(if endpoint.is_dialer() {
+                            ConnectionDirection::Outbound
+                        } else {
+                            ConnectionDirection::Inbound
+                        });
+                        peer.connected_since = Some(chrono::Utc::now().timestamp());
+                    }
+                });
+
+                if first {
+                    let version = Version::for_node(&*self.node.read().await)?;
+                    self.swarm
+                        .behaviour_mut()
+                        .handshake
+                        .send_request(&peer_id, NetMessage::Version(version));
+                    self.handshakes.insert(peer_id, Instant::now());
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                connection_id,
+                num_established,
+                ..
+            } => {
+                if let Some((address, delay)) = self.bootstrap.failed(connection_id, Instant::now())
+                {
+                    tracing::warn!(%peer_id, %address, ?delay, "Lost bootstrap peer, redialing");
+                    self.publish_bootstrap();
+                }
+
+                if let Some(relayed) = self.relayed.get_mut(&peer_id) {
+                    relayed.remove(&connection_id);
+                    if relayed.is_empty() {
+                        self.relayed.remove(&peer_id);
+                    }
+                }
+
+                if num_established == 0 {
+                    self.peers.update(peer_id, |peer| {
+                        peer.connected_address = None;
+                        peer.direction = None;
+                        peer.connected_since = None;
+                        peer.best_height = None;
+                        peer.latency = None;
+                    });
+                    self.peers.remove_byte_counters(&peer_id);
+                    self.handshakes.remove(&peer_id);
+                    self.download.remove_peer(&peer_id);
+                    self.known_transactions.remove(&peer_id);
+                    self.limiters.remove(&peer_id);
+                }
+                self.refresh_peers().await;
+            }
+            SwarmEvent::OutgoingConnectionError {
+                peer_id,
+                connection_id,
+                error,
+            } => {
+                tracing::debug!(peer_id = ?peer_id, %error, "Failed to connect to peer");
+
+                if let Some((address, delay)) = self.bootstrap.failed(connection_id, Instant::now())
+                {
+                    tracing::warn!(%address, ?delay, "Bootstrap peer unreachable, redialing");
+                    self.publish_bootstrap();
+                }
+
+                if let DialError::Transport(errors) = &error {
+                    for (address, _) in errors {
+                        self.peer_book.record_failure(address);
+                    }
+                    self.save_peer_book();
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Both sides send their `Version` as a request once connected and answer the other's
+    /// with a `VerAck`, disconnecting when they can't talk to each other.
+    async fn handle_handshake_event(
+        &mut self,
+        event: request_response::Event<NetMessage, NetMessage>,
+    ) -> Result<()> {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        request: NetMessage::Version(theirs),
+                        channel,
+                        ..
+                    },
+                ..
+            } => {
+                let ours = Version::for_node(&*self.node.read().await)?;
+
+                if let Err(e) = theirs.check_compatible(&ours) {
+                    tracing::info!(peer_id = %peer, reason = %e, "Disconnecting peer");
+                    self.incompatible.insert(peer);
+                    for address in self.bootstrap.ban(&peer) {
+                        tracing::warn!(peer_id = %peer, %address, "Banned bootstrap peer");
+                        self.publish_bootstrap();
+                    }
+                    self.swarm.behaviour_mut().kademlia.remove_peer(&peer);
+                    let _ = self.swarm.disconnect_peer_id(peer);
+                    return Ok(());
+                }
+
+                self.handshakes.remove(&peer);
+                self.peers
+                    .update(peer, |info| info.best_height = Some(theirs.best_height));
+                self.peer_book.record_height(&peer, theirs.best_height);
+
+                // the peer may have hung up in the meantime, which ends the connection anyway
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .handshake
+                    .send_response(channel, NetMessage::VerAck);
+
+                let best_header_height = self
+                    .node
+                    .read()
+                    .await
+                    .state
+                    .best_header()
+                    .map_or(0, |header| header.height);
+
+                if theirs.best_height > best_header_height {
+                    let locator = self.node.read().await.state.header_locator()?;
+                    self.request_headers(peer, locator);
+                }
+            }
+            request_response::Event::Message {
+                message:
+                    request_response::Message::Response {
+                        response: NetMessage::VerAck,
+                        ..
+                    },
+                ..
+            } => {}
+            request_response::Event::Message { peer, message, .. } => {
+                let expected = match message {
+                    request_response::Message::Request { .. } => "Version",
+                    request_response::Message::Response { .. } => "VerAck",
+                };
+                tracing::info!(peer_id = %peer, reason = %format_args!("expected a {expected}"), "Disconnecting peer");
+                let _ = self.swarm.disconnect_peer_id(peer);
+            }
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                tracing::debug!(peer_id = %peer, %error, "Handshake failed");
+                let _ = self.swarm.disconnect_peer_id(peer);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Headers-first sync: headers from a peer ahead of us are checked into the header
+    /// chain, then the blocks along the best one are fetched from every peer that has
+    /// them and connected in order.
+    async fn handle_sync_event(
+        &mut self,
+        event: request_response::Event<NetMessage, NetMessage>,
+    ) -> Result<()> {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        request: NetMessage::Node(Message::TxInv(ids)),
+                        channel,
+                        ..
+                    },
+                ..
+            } => {
+                if !self.within_rate_limit(peer, MessageKind::Request) {
+                    return Ok(());
+                }
+
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .sync
+                    .send_response(channel, NetMessage::Ack);
+
+                self.mark_known(peer, &ids);
+
+                // another peer may already be sending us the rest
+                let now = Instant::now();
+                let (unseen, seen) = ids
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|id| !self.seen.contains(&id.0, now));
+                self.record_duplicates(seen.len()).await;
+                if unseen.is_empty() {
+                    return Ok(());
+                }
+
+                // only the transactions we don't have come back as a request for them
+                let (_, replies) = self.node.handle_message(Message::TxInv(unseen)).await?;
+                for reply in replies {
+                    if let Message::GetTransactions(requested) = &reply {
+                        for id in requested {
+                            self.seen.insert(id.0, now);
+                        }
+                    }
+
+                    self.swarm
+                        .behaviour_mut()
+                        .sync
+                        .send_request(&peer, reply.into());
+                }
+            }
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        request: NetMessage::Node(message),
+                        channel,
+                        ..
+                    },
+                ..
+            } if matches!(
+                message,
+                Message::GetHeaders { .. } | Message::GetData(_) | Message::GetTransactions(_)
+            ) =>
+            {
+                // dropping the channel leaves the peer's request to time out
+                if !self.within_rate_limit(peer, MessageKind::Request) {
+                    return Ok(());
+                }
+
+                let requested = match &message {
+                    Message::GetData(hashes) => hashes.clone(),
+                    _ => vec![],
+                };
+
+                let (_, replies) = self.node.handle_message(message).await?;
+                let reply = replies
+                    .into_iter()
+                    .next()
+                    .unwrap_or(Message::NotFound(requested));
+
+                if self
+                    .swarm
+                    .behaviour_mut()
+                    .sync
+                    .send_response(channel, reply.into())
+                    .is_err()
+                {
+                    tracing::debug!(peer_id = %peer, "Peer hung up before its sync request was answered");
+                }
+            }
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Request { .. },
+                ..
+            } => self.penalize(peer, "unexpected sync request"),
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Response {
+                        request_id,
+                        response,
+                    },
+                ..
+            } => match response {
+                NetMessage::Node(Message::Headers(headers)) => {
+                    self.handle_headers(peer, headers).await?
+                }
+                NetMessage::Node(Message::BlockData(block)) => {
+                    self.block_requests.remove(&request_id);
+                    self.handle_block(peer, block).await?
+                }
+                NetMessage::Node(Message::NotFound(_)) => {
+                    if let Some(hash) = self.block_requests.remove(&request_id) {
+                        self.download.requeue(&hash);
+                    }
+                }
+                NetMessage::Node(Message::Transactions(transactions)) => {
+                    self.handle_transactions(peer, transactions).await?
+                }
+                NetMessage::Ack => {}
+                _ => self.penalize(peer, "unexpected sync response"),
+            },
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+                ..
+            } => {
+                if let Some(hash) = self.block_requests.remove(&request_id) {
+                    self.download.requeue(&hash);
+                }
+
+                if matches!(error, OutboundFailure::Timeout) {
+                    tracing::info!(peer_id = %peer, "Peer stalled a sync request");
+                    self.record_misbehavior(&peer, 1);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn request_headers(&mut self, peer_id: PeerId, locator: Vec<Hash>) {
+        self.swarm.behaviour_mut().sync.send_request(
+            &peer_id,
+            Message::GetHeaders {
+                locator,
+                stop: None,
+            }
+            .into(),
+        );
+    }
+
+    async fn handle_headers(
+        &mut self,
+        peer_id: PeerId,
+        headers: Vec<(u32, BlockHeader)>,
+    ) -> Result<()> {
+        let Some((last_height, last)) = headers.last().cloned() else {
+            return Ok(());
+        };
+
+        let missing = {
+            let mut node = self.node.write().await;
+
+            match node.state.add_headers(&headers) {
+                Ok(()) => {}
+                Err(HeaderError::Other(e)) => return Err(e),
+                Err(e) => {
+                    drop(node);
+                    self.penalize(peer_id, &e.to_string());
+                    return Ok(());
+                }
+            }
+
+            node.state.missing_blocks()?
+        };
+
+        // the peer has at least the headers it sent, whatever its handshake said
+        self.peers.update(peer_id, |peer| {
+            peer.best_height = peer.best_height.max(Some(last_height))
+        });
+
+        // a full batch means the peer has more headers to send
+        if headers.len() == MAX_HEADERS_PER_MESSAGE {
+            self.request_headers(peer_id, vec![last.hash()?]);
+        }
+
+        self.download.schedule(missing);
+        self.request_blocks();
+        Ok(())
+    }
+
+    async fn handle_block(&mut self, peer_id: PeerId, block: Block) -> Result<()> {
+        match self.download.receive(peer_id, block) {
+            Ok(ready) => {
+                for (block, source) in ready {
+                    let (outcome, _) = self.node.handle_message(Message::BlockData(block)).await?;
+
+                    if let HandleOutcome::Rejected(reason) = outcome {
+                        self.penalize_rejection(source, &reason);
+                    }
+                }
+            }
+            Err(DownloadError::Other(e)) => return Err(e),
+            Err(e) => self.penalize(peer_id, &e.to_string()),
+        }
+
+        self.request_blocks();
+        Ok(())
+    }
+
+    async fn handle_transactions(
+        &mut self,
+        peer_id: PeerId,
+        transactions: Vec<Transaction>,
+    ) -> Result<()> {
+        for transaction in transactions {
+            self.mark_known(peer_id, &[transaction.id()?]);
+
+            let (outcome, _) = self
+                .node
+                .handle_message(Message::NewTransaction(transaction))
+                .await?;
+
+            if let HandleOutcome::Rejected(reason) = outcome {
+                self.record_misbehavior(&peer_id, reason.misbehavior());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Offers each handshaken peer the mempool transactions it isn't known to have, so
+    /// ones accepted while it was away still reach it.
+    async fn announce_transactions(&mut self) -> Result<()> {
+        let ids = self
+            .node
+            .read()
+            .await
+            .state
+            .mem_pool
+            .pending_transactions
+            .iter()
+            .map(|tx| tx.id())
+            .collect::<Result<Vec<_>>>()?;
+
+        let peers = self
+            .peers
+            .get()
+            .into_iter()
+            .filter(|(_, peer)| peer.best_height.is_some())
+            .map(|(peer_id, _)| peer_id)
+            .collect::<Vec<_>>();
+
+        // transactions that just arrived by gossip are already being relayed by it
+        let now = Instant::now();
+        let ids = ids
+            .into_iter()
+            .filter(|id| {
+                self.seen
+                    .age(&id.0, now)
+                    .is_none_or(|age| age >= self.inventory_interval)
+            })
+            .collect::<Vec<_>>();
+
+        for peer_id in peers {
+            let known = self.known_transactions.entry(peer_id).or_default();
+            let unknown = ids
+                .iter()
+                .filter(|id| !known.contains(id))
+                .take(MAX_INVENTORY_PER_MESSAGE)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if unknown.is_empty() {
+                continue;
+            }
+
+            self.mark_known(peer_id, &unknown);
+            self.swarm
+                .behaviour_mut()
+                .sync
+                .send_request(&peer_id, Message::TxInv(unknown).into());
+        }
+
+        Ok(())
+    }
+
+    fn mark_known(&mut self, peer_id: PeerId, ids: &[TxId]) {
+        let known = self.known_transactions.entry(peer_id).or_default();
+
+        for id in ids {
+            known.insert(id.clone());
+        }
+    }
+
+    /// Fills the download window from the handshaken peers.
+    fn request_blocks(&mut self) {
+        let peers = self
+            .peers
+            .get()
+            .into_iter()
+            .filter_map(|(peer_id, peer)| Some((peer_id, peer.best_height?)))
+            .collect::<Vec<_>>();
+
+        for (peer_id, hash) in self.download.assign(&peers) {
+            let request_id = self
+                .swarm
+                .behaviour_mut()
+                .sync
+                .send_request(&peer_id, Message::GetData(vec![hash]).into());
+            self.block_requests.insert(request_id, hash);
+        }
+    }
+
+    fn record_misbehavior(&mut self, peer_id: &PeerId, score: u32) {
+        self.peer_book.record_misbehavior(peer_id, score);
+        self.peers.update(*peer_id, |peer| peer.score += score);
+    }
+
+    /// Whether to handle a message from `peer_id`. Messages over its limits are dropped,
+    /// and a peer that keeps sending them is penalized.
+    fn within_rate_limit(&mut self, peer_id: PeerId, kind: MessageKind) -> bool {
+        let now = Instant::now();
+        let verdict = self
+            .limiters
+            .entry(peer_id)
+            .or_insert_with(|| PeerRateLimiter::new(self.rate_limits.clone(), now))
+            .check(kind, now);
+
+        match verdict {
+            Verdict::Allow => true,
+            Verdict::Drop => false,
+            Verdict::Penalize => {
+                self.penalize(peer_id, "kept exceeding its rate limits");
+                false
+            }
+        }
+    }
+
+    /// Scores and drops a peer that sent us something invalid.
+    fn penalize(&mut self, peer_id: PeerId, reason: &str) {
+        self.penalize_by(peer_id, reason, 1);
+    }
+
+    /// `penalize` by the weight of the reason a block or transaction was refused, leaving
+    /// peers alone for what an honest peer might have sent.
+    fn penalize_rejection(&mut self, peer_id: PeerId, reason: &RejectReason) {
+        match reason.misbehavior() {
+            0 => {}
+            score => self.penalize_by(peer_id, &reason.to_string(), score),
+        }
+    }
+
+    fn penalize_by(&mut self, peer_id: PeerId, reason: &str, score: u32) {
+        tracing::info!(%peer_id, reason, score, "Disconnecting peer");
+        self.record_misbehavior(&peer_id, score);
+        let _ = self.swarm.disconnect_peer_id(peer_id);
+    }
+
+    fn drop_stalled_handshakes(&mut self) {
+        let timeout = Duration::from_secs(HANDSHAKE_TIMEOUT_SECS);
+        let stalled = self
+            .handshakes
+            .iter()
+            .filter(|(_, started)| started.elapsed() >= timeout)
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+
+        for peer_id in stalled {
+            tracing::info!(%peer_id, reason = "handshake timed out", "Disconnecting peer");
+            self.handshakes.remove(&peer_id);
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+    }
+
+    /// Dials the bootstrap peers due a retry, whatever the connection limit.
+    fn dial_bootstrap(&mut self) {
+        for (address, peer_id) in self.bootstrap.due(Instant::now()) {
+            let opts = match peer_id {
+                Some(peer_id) => DialOpts::peer_id(peer_id)
+                    .addresses(vec![address.clone()])
+                    .build(),
+                None => DialOpts::unknown_peer_id().address(address.clone()).build(),
+            };
+            let connection_id = opts.connection_id();
+
+            tracing::debug!(%address, "Dialing bootstrap peer");
+            self.bootstrap.dialing(&address, connection_id);
+
+            if let Err(e) = self.swarm.dial(opts) {
+                tracing::warn!(%address, error = %e, "Failed to dial bootstrap peer");
+                self.bootstrap.failed(connection_id, Instant::now());
+            }
+        }
+
+        self.publish_bootstrap();
+    }
+
+    fn publish_bootstrap(&self) {
+        *self.peers.bootstrap.write().unwrap() = self.bootstrap.get().clone();
+    }
+
+    /// Listens through every relay server we aren't already listening through, which
+    /// reserves us an address on it.
+    fn reserve_relays(&mut self) {
+        for server in self.relay_servers.clone() {
+            if self.relay_listeners.values().any(|known| *known == server) {
+                continue;
+            }
+
+            match self
+                .swarm
+                .listen_on(server.clone().with(Protocol::P2pCircuit))
+            {
+                Ok(listener) => {
+                    self.relay_listeners.insert(listener, server);
+                }
+                Err(error) => {
+                    tracing::warn!(%server, %error, "Failed to listen through relay")
+                }
+            }
+        }
+    }
+
+    /// Gives up our relay addresses once we're reachable directly.
+    fn release_relays(&mut self) {
+        for (listener, server) in self.relay_listeners.drain() {
+            tracing::info!(%server, "Reachable directly, no longer listening through relay");
+            self.swarm.remove_listener(listener);
+        }
+
+        let relayed = self
+            .swarm
+            .external_addresses()
+            .filter(|address| is_relayed(address))
+            .cloned()
+            .collect::<Vec<_>>();
+        for address in relayed {
+            self.swarm.remove_external_address(&address);
+        }
+    }
+
+    /// Dials the best peer book entries we aren't connected to, up to the connection limit.
+    /// Bootstrap peers are left to `dial_bootstrap`.
+    fn dial_peer_book(&mut self) {
+        let connected = self
+            .peers
+            .get()
+            .into_values()
+            .filter_map(|peer| peer.connected_address)
+            .collect::<Vec<_>>();
+
+        for address in self.peer_book.best(self.max_connections) {
+            if self.swarm.connected_peers().count() >= self.max_connections {
+                break;
+            }
+
+            if !connected.contains(&address)
+                && !self.bootstrap.contains(&address)
+                && let Err(e) = self.swarm.dial(address.clone())
+            {
+                tracing::debug!(%address, error = %e, "Failed to dial peer book entry");
+            }
+        }
+    }
+
+    fn save_peer_book(&self) {
+        if let Err(e) = self.peer_book.save() {
+            tracing::warn!(error = %e, "Failed to save the peer book");
+        }
+    }
+
+    /// Dials a peer found by mDNS or the DHT unless we're already connected to it, it's
+    /// incompatible, or we're at the connection limit.
+    fn dial_discovered(&mut self, peer_id: PeerId, address: Multiaddr) {
+        if self.swarm.is_connected(&peer_id)
+            || self.incompatible.contains(&peer_id)
+            || self.swarm.connected_peers().count() >= self.max_connections
+        {
+            return;
+        }
+
+        let opts = DialOpts::peer_id(peer_id).addresses(vec![address]).build();
+
+        if let Err(e) = self.swarm.dial(opts) {
+            tracing::debug!(%peer_id, error = %e, "Failed to dial discovered peer");
+        }
+    }
+
+    async fn handle_gossip(
+        &mut self,
+        source: PeerId,
+        topic: &gossipsub::TopicHash,
+        data: &[u8],
+    ) -> Result<MessageAcceptance> {
+        let now = Instant::now();
+        if !self.seen.insert(sha256d(data), now) {
+            self.record_duplicates(1).await;
+            return Ok(MessageAcceptance::Ignore);
+        }
+
+        let message = if *topic == self.blocks_topic.hash() {
+            Block::from_bytes(data).map(Message::NewBlock)
+        } else if *topic == self.transactions_topic.hash() {
+            Transaction::from_bytes(data).map(Message::NewTransaction)
+        } else {
+            return Ok(MessageAcceptance::Ignore);
+        };
+
+        let Ok(message) = message else {
+            self.record_misbehavior(&source, 1);
+            return Ok(MessageAcceptance::Reject);
+        };
+
+        let kind = match &message {
+            Message::NewBlock(block)
+                if Some(block.header.previous_block_hash) == self.node.tip_hash().await? =>
+            {
+                MessageKind::TipBlock
+            }
+            Message::NewBlock(_) => MessageKind::Block,
+            _ => MessageKind::Transaction,
+        };
+
+        if !self.within_rate_limit(source, kind) {
+            return Ok(MessageAcceptance::Ignore);
+        }
+
+        let id = match &message {
+            Message::NewBlock(block) => block.header.hash()?,
+            Message::NewTransaction(transaction) => {
+                let id = transaction.id()?;
+                self.mark_known(source, std::slice::from_ref(&id));
+                id.0
+            }
+            _ => return Ok(MessageAcceptance::Ignore),
+        };
+
+        // the same block or transaction in a different encoding
+        if !self.seen.insert(id, now) {
+            self.record_duplicates(1).await;
+            return Ok(MessageAcceptance::Ignore);
+        }
+
+        Ok(match self.node.handle_message(message).await?.0 {
+            HandleOutcome::Accepted => MessageAcceptance::Accept,
+            HandleOutcome::Duplicate | HandleOutcome::Orphaned { .. } => MessageAcceptance::Ignore,
+            HandleOutcome::Rejected(reason) => match reason.misbehavior() {
+                0 => MessageAcceptance::Ignore,
+                score => {
+                    self.record_misbehavior(&source, score);
+                    MessageAcceptance::Reject
+                }
+            },
+        })
+    }
+
+    async fn publish_node_event(&mut self, event: NodeEvent) -> Result<()> {
+        let (topic, data) = match event {
+            NodeEvent::TipChanged { new, .. } => {
+                let node = self.node.read().await;
+                let Some(block) = node.state.block_manager.get_block(&new) else {
+                    return Ok(());
+                };
+                (self.blocks_topic.clone(), block.to_bytes()?)
+            }
+            NodeEvent::TransactionAccepted(id) => {
+                let Some(record) = self.node.get_transaction(&id).await else {
+                    return Ok(());
+                };
+                (
+                    self.transactions_topic.clone(),
+                    record.transaction.to_bytes()?,
+                )
+            }
+            _ => return Ok(()),
+        };
+
+        match self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
+            // duplicates are blocks and transactions that arrived over gossip in the first place
+            Ok(_) | Err(PublishError::Duplicate | PublishError::NoPeersSubscribedToTopic) => {}
+            Err(e) => tracing::warn!(error = %e, "Failed to publish"),
+        }
+
+        Ok(())
+    }
+
+    async fn record_duplicates(&mut self, count: usize) {
+        if count > 0 {
+            self.node.write().await.state.metrics.seen_duplicates += count as u64;
+        }
+    }
+
+    async fn refresh_peers(&mut self) {
+        let transactions = self.transactions_topic.hash();
+        let peers = self
+            .swarm
+            .behaviour()
+            .gossipsub
+            .all_peers()
+            .filter(|(_, topics)| topics.contains(&&transactions))
+            .count();
+
+        self.node.write().await.state.metrics.peers = peers;
+    }
+}
+
+/// A p2p service running on its own task.
+pub struct P2pHandle {
+    cancel: CancellationToken,
+    task: JoinHandle<Result<()>>,
+}
+
+impl P2pHandle {
+    /// Stops the service, waiting for it to save its state.
+    pub async fn shutdown(self) -> Result<()> {
+        self.cancel.cancel();
+        self.task.await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::*;
+    use crate::config::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+    use crate::rate_limit::*;
+    use crate::tx_index::*;
+
+    fn regtest_config(data_dir: &Path) -> NodeConfig {
+        NodeConfig::builder(KeyPair::generate())
+            .data_dir(data_dir)
+            .network(NetworkParams::regtest())
+            .p2p(P2pConfig {
+                listen_addr: "/ip4/127.0.0.1/tcp/0".to_string(),
+                enable_mdns: Some(false),
+                ..P2pConfig::default()
+            })
+            .build()
+    }
+
+    async fn wait_for(mut condition: impl AsyncFnMut() -> bool) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !condition().await {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_gossip_block() {
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let config_a = regtest_config(data_dir_a.path());
+        let node_a = SharedNode::new(Node::new(config_a.clone()).unwrap());
+
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let mut config_b = regtest_config(data_dir_b.path());
+        let node_b = SharedNode::new(Node::new(config_b.clone()).unwrap());
+
+        let mut p2p_a = P2pService::new(node_a.clone(), config_a.p2p.clone())
+            .await
+            .unwrap();
+        let peer_id_a = *p2p_a.local_peer_id();
+        config_b.p2p.bootstrap_peers = vec![p2p_a.listen_addr().await.unwrap().to_string()];
+        let p2p_b = P2pService::new(node_b.clone(), config_b.p2p.clone())
+            .await
+            .unwrap();
+        let peer_id_b = *p2p_b.local_peer_id();
+
+        let cancel = CancellationToken::new();
+        let tasks = [
+            tokio::spawn(p2p_a.run(cancel.clone())),
+            tokio::spawn(p2p_b.run(cancel.clone())),
+        ];
+
+        wait_for(async || node_a.metrics().await.unwrap().peers == 1).await;
+
+        let block = node_a.write().await.create_block().unwrap();
+        let hash = block.header.hash().unwrap();
+        node_a
+            .handle_message(Message::NewBlock(block))
+            .await
+            .unwrap();
+
+        wait_for(async || node_b.read().await.tip_hash().unwrap() == Some(hash)).await;
+
+        // B dialed A, and both counted the block going across
+        for (node, peer_id, direction) in [
+            (&node_a, peer_id_b, ConnectionDirection::Inbound),
+            (&node_b, peer_id_a, ConnectionDirection::Outbound),
+        ] {
+            let peers = node.metrics().await.unwrap().peer_info;
+            assert_eq!(peers.len(), 1);
+            assert_eq!(peers[0].peer_id, peer_id);
+            assert_eq!(peers[0].direction, Some(direction));
+            assert!(peers[0].connected_since.is_some());
+            assert!(peers[0].bytes_in > 0 && peers[0].bytes_out > 0);
+        }
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_block_from_three_peers_validated_once() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = regtest_config(data_dir.path());
+        let node = SharedNode::new(Node::new(config.clone()).unwrap());
+        let mut p2p = P2pService::new(node.clone(), config.p2p.clone())
+            .await
+            .unwrap();
+        let address = p2p.listen_addr().await.unwrap().to_string();
+
+        let cancel = CancellationToken::new();
+        let mut tasks = vec![tokio::spawn(p2p.run(cancel.clone()))];
+        let mut data_dirs = Vec::new();
+        let mut peers = Vec::new();
+
+        for _ in 0..3 {
+            let data_dir = tempfile::tempdir().unwrap();
+            let mut config = regtest_config(data_dir.path());
+            config.p2p.bootstrap_peers = vec![address.clone()];
+            let peer = SharedNode::new(Node::new(config.clone()).unwrap());
+            let p2p = P2pService::new(peer.clone(), config.p2p.clone())
+                .await
+                .unwrap();
+
+            tasks.push(tokio::spawn(p2p.run(cancel.clone())));
+            data_dirs.push(data_dir);
+            peers.push(peer);
+        }
+
+        wait_for(async || node.metrics().await.unwrap().peers == 3).await;
+        let validated = node.metrics().await.unwrap().blocks_validated;
+
+        // every peer accepts the block and publishes it
+        let block = peers[0].write().await.create_block().unwrap();
+        let hash = block.header.hash().unwrap();
+        for peer in &peers {
+            peer.handle_message(Message::NewBlock(block.clone()))
+                .await
+                .unwrap();
+        }
+
+        wait_for(async || node.tip_hash().await.unwrap() == Some(hash)).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(
+            node.metrics().await.unwrap().blocks_validated,
+            validated + 1
+        );
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_gossip_transaction() {
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let config_a = regtest_config(data_dir_a.path());
+        let node_a = SharedNode::new(Node::new(config_a.clone()).unwrap());
+
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let mut config_b = regtest_config(data_dir_b.path());
+        let node_b = SharedNode::new(Node::new(config_b.clone()).unwrap());
+
+        let block = node_a.write().await.create_block().unwrap();
+        let message = Message::NewBlock(block);
+        node_a.handle_message(message.clone()).await.unwrap();
+        node_b.handle_message(message).await.unwrap();
+
+        let mut p2p_a = P2pService::new(node_a.clone(), config_a.p2p.clone())
+            .await
+            .unwrap();
+        let peer_id_a = *p2p_a.local_peer_id();
+        let addr = p2p_a.listen_addr().await.unwrap();
+        config_b.p2p.bootstrap_peers = vec![addr.to_string()];
+        let p2p_b = P2pService::new(node_b.clone(), config_b.p2p.clone())
+            .await
+            .unwrap();
+        let peers_b = p2p_b.peer_list();
+        let peer_book_b = p2p_b.peer_book();
+
+        let cancel = CancellationToken::new();
+        let tasks = [
+            tokio::spawn(p2p_a.run(cancel.clone())),
+            tokio::spawn(p2p_b.run(cancel.clone())),
+        ];
+
+        wait_for(async || node_a.metrics().await.unwrap().peers == 1).await;
+
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        let id = node_a.send(recipient, 10, 1).await.unwrap();
+
+        wait_for(async || node_b.get_transaction(&id).await.is_some()).await;
+
+        // the handshake recorded A's height, ready to sync from
+        wait_for(async || peers_b.best_peer() == Some((peer_id_a, 2))).await;
+        assert!(
+            peer_book_b
+                .get()
+                .values()
+                .any(|record| record.last_height == Some(2))
+        );
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_mempool_inventory() {
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let mut config_a = regtest_config(data_dir_a.path());
+        config_a.p2p.inventory_interval_secs = 1;
+        let node_a = SharedNode::new(Node::new(config_a.clone()).unwrap());
+
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let mut config_b = regtest_config(data_dir_b.path());
+        let node_b = SharedNode::new(Node::new(config_b.clone()).unwrap());
+
+        let block = node_a.write().await.create_block().unwrap();
+        let message = Message::NewBlock(block);
+        node_a.handle_message(message.clone()).await.unwrap();
+        node_b.handle_message(message).await.unwrap();
+
+        // accepted before B is around to hear it gossiped
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        let id = node_a.send(recipient, 10, 1).await.unwrap();
+
+        let mut p2p_a = P2pService::new(node_a, config_a.p2p.clone()).await.unwrap();
+        config_b.p2p.bootstrap_peers = vec![p2p_a.listen_addr().await.unwrap().to_string()];
+        let p2p_b = P2pService::new(node_b.clone(), config_b.p2p.clone())
+            .await
+            .unwrap();
+
+        let cancel = CancellationToken::new();
+        let tasks = [
+            tokio::spawn(p2p_a.run(cancel.clone())),
+            tokio::spawn(p2p_b.run(cancel.clone())),
+        ];
+
+        wait_for(async || node_b.get_transaction(&id).await.is_some()).await;
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[ignore = "needs multicast on the local network"]
+    async fn test_mdns_discovery() {
+        let data_dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let cancel = CancellationToken::new();
+        let mut peer_ids = Vec::new();
+        let mut peer_lists = Vec::new();
+        let mut tasks = Vec::new();
+
+        for data_dir in &data_dirs {
+            let mut config = regtest_config(data_dir.path());
+            config.p2p.enable_mdns = None;
+            config.p2p.listen_addr = "/ip4/0.0.0.0/tcp/0".to_string();
+            assert!(config.mdns_enabled());
+
+            let node = SharedNode::new(Node::new(config.clone()).unwrap());
+            let p2p = P2pService::new(node, config.p2p.clone()).await.unwrap();
+            peer_ids.push(*p2p.local_peer_id());
+            peer_lists.push(p2p.peer_list());
+            tasks.push(tokio::spawn(p2p.run(cancel.clone())));
+        }
+
+        wait_for(async || {
+            peer_lists[0]
+                .get()
+                .get(&peer_ids[1])
+                .is_some_and(|peer| peer.connected_address.is_some())
+        })
+        .await;
+        assert!(!peer_lists[1].get()[&peer_ids[0]].mdns_addresses.is_empty());
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_dht_discovery() {
+        let data_dirs = [(); 3].map(|_| tempfile::tempdir().unwrap());
+        let cancel = CancellationToken::new();
+        let mut bootstrap_peers = vec![];
+        let mut peer_ids = Vec::new();
+        let mut peer_lists = Vec::new();
+        let mut nodes = Vec::new();
+        let mut tasks = Vec::new();
+
+        // A knows nobody, B bootstraps from A, and C bootstraps from B
+        for data_dir in &data_dirs {
+            let mut config = regtest_config(data_dir.path());
+            config.p2p.bootstrap_peers = bootstrap_peers;
+
+            let node = SharedNode::new(Node::new(config.clone()).unwrap());
+            let mut p2p = P2pService::new(node.clone(), config.p2p.clone())
+                .await
+                .unwrap();
+            let peer_id = *p2p.local_peer_id();
+            let address = p2p
+                .listen_addr()
+                .await
+                .unwrap()
+                .with(Protocol::P2p(peer_id));
+            bootstrap_peers = vec![address.to_string()];
+
+            peer_ids.push(peer_id);
+            peer_lists.push(p2p.peer_list());
+            nodes.push(node);
+            tasks.push(tokio::spawn(p2p.run(cancel.clone())));
+        }
+
+        wait_for(async || {
+            peer_lists[2]
+                .get()
+                .get(&peer_ids[0])
+                .is_some_and(|peer| peer.connected_address.is_some())
+        })
+        .await;
+        wait_for(async || nodes[2].metrics().await.unwrap().routing_table_size == 2).await;
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_headers_first_sync() {
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let config_a = regtest_config(data_dir_a.path());
+        let mut node = Node::new(config_a.clone()).unwrap();
+        while node.state.chain.height() < 101 {
+            let block = node.create_block().unwrap();
+            node.handle_message(Message::NewBlock(block)).unwrap();
+        }
+        let tip = node.tip_hash().unwrap();
+        let node_a = SharedNode::new(node);
+
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let mut config_b = regtest_config(data_dir_b.path());
+        let node_b = SharedNode::new(Node::new(config_b.clone()).unwrap());
+        let mut events = node_b.subscribe();
+
+        let mut p2p_a = P2pService::new(node_a, config_a.p2p.clone()).await.unwrap();
+        config_b.p2p.bootstrap_peers = vec![p2p_a.listen_addr().await.unwrap().to_string()];
+        let p2p_b = P2pService::new(node_b.clone(), config_b.p2p.clone())
+            .await
+            .unwrap();
+
+        let cancel = CancellationToken::new();
+        let tasks = [
+            tokio::spawn(p2p_a.run(cancel.clone())),
+            tokio::spawn(p2p_b.run(cancel.clone())),
+        ];
+
+        wait_for(async || node_b.read().await.tip_hash().unwrap() == tip).await;
+
+        // blocks arrive in whatever order, but each is connected on top of the last
+        let mut heights = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let NodeEvent::BlockConnected { height, .. } = event {
+                heights.push(height);
+            }
+        }
+        assert_eq!(heights, (2..=101).collect::<Vec<_>>());
+        assert_eq!(node_b.metrics().await.unwrap().orphan_blocks, 0);
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_block_flood() {
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let config_b = regtest_config(data_dir_b.path());
+        let node_b = SharedNode::new(Node::new(config_b.clone()).unwrap());
+        let mut p2p_b = P2pService::new(node_b.clone(), config_b.p2p.clone())
+            .await
+            .unwrap();
+        let peer_id_b = *p2p_b.local_peer_id();
+
+        // A dials B, so B is in A's peer book to be scored
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let mut config_a = regtest_config(data_dir_a.path());
+        config_a.p2p.bootstrap_peers = vec![p2p_b.listen_addr().await.unwrap().to_string()];
+        let limit = RateLimit {
+            per_sec: 1,
+            burst: 1,
+        };
+        config_a.p2p.rate_limits = RateLimits {
+            tip_blocks: limit,
+            blocks: limit,
+            max_dropped: 5,
+            ..RateLimits::default()
+        };
+        let node_a = SharedNode::new(Node::new(config_a.clone()).unwrap());
+        let p2p_a = P2pService::new(node_a.clone(), config_a.p2p.clone())
+            .await
+            .unwrap();
+        let peers_a = p2p_a.peer_list();
+        let peer_book_a = p2p_a.peer_book();
+
+        let cancel = CancellationToken::new();
+        let tasks = [
+            tokio::spawn(p2p_a.run(cancel.clone())),
+            tokio::spawn(p2p_b.run(cancel.clone())),
+        ];
+
+        wait_for(async || node_b.metrics().await.unwrap().peers == 1).await;
+
+        for _ in 0..30 {
+            let block = node_b.write().await.create_block().unwrap();
+            node_b
+                .handle_message(Message::NewBlock(block))
+                .await
+                .unwrap();
+        }
+
+        wait_for(async || peer_book_a.misbehavior(&peer_id_b) > 0).await;
+        wait_for(async || peers_a.connected().is_empty()).await;
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_bootstrap_retry() {
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let config_a = regtest_config(data_dir_a.path());
+        let node_a = SharedNode::new(Node::new(config_a.clone()).unwrap());
+        let block = node_a.write().await.create_block().unwrap();
+        let hash = block.header.hash().unwrap();
+        node_a
+            .handle_message(Message::NewBlock(block))
+            .await
+            .unwrap();
+
+        let mut p2p_a = P2pService::new(node_a, config_a.p2p.clone()).await.unwrap();
+        let reachable = p2p_a.listen_addr().await.unwrap();
+        let unreachable = "/ip4/127.0.0.1/tcp/9".parse::<Multiaddr>().unwrap();
+
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let mut config_b = regtest_config(data_dir_b.path());
+        config_b.p2p.bootstrap_peers = vec![unreachable.to_string(), reachable.to_string()];
+        config_b.p2p.bootstrap_retry_secs = 1;
+        config_b.p2p.bootstrap_retry_max_secs = 2;
+        let node_b = SharedNode::new(Node::new(config_b.clone()).unwrap());
+        let p2p_b = P2pService::new(node_b.clone(), config_b.p2p.clone())
+            .await
+            .unwrap();
+        let peers_b = p2p_b.peer_list();
+
+        let cancel = CancellationToken::new();
+        let tasks = [
+            tokio::spawn(p2p_a.run(cancel.clone())),
+            tokio::spawn(p2p_b.run(cancel.clone())),
+        ];
+
+        wait_for(async || node_b.tip_hash().await.unwrap() == Some(hash)).await;
+        assert_eq!(
+            peers_b.bootstrap_peers()[&reachable].state,
+            BootstrapState::Connected
+        );
+
+        // retried after one second, then after two; without the doubling both retries
+        // would be done within about two seconds, so 2.5s or more shows the backoff
+        wait_for(async || peers_b.bootstrap_peers()[&unreachable].failures == 1).await;
+        let first_failure = Instant::now();
+        wait_for(async || peers_b.bootstrap_peers()[&unreachable].failures == 3).await;
+        assert!(first_failure.elapsed() >= Duration::from_millis(2500));
+        assert_eq!(
+            peers_b.bootstrap_peers()[&unreachable].state,
+            BootstrapState::Waiting
+        );
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_handshake_network_mismatch() {
+        let data_dir_a = tempfile::tempdir().unwrap();
+        let config_a = regtest_config(data_dir_a.path());
+        let node_a = SharedNode::new(Node::new(config_a.clone()).unwrap());
+        let mut p2p_a = P2pService::new(node_a, config_a.p2p.clone()).await.unwrap();
+        let addr = p2p_a.listen_addr().await.unwrap();
+        let peers_a = p2p_a.peer_list();
+
+        let data_dir_b = tempfile::tempdir().unwrap();
+        let mut config_b = regtest_config(data_dir_b.path());
+        config_b.network = NetworkParams::testnet();
+        config_b.p2p.bootstrap_peers = vec![addr.to_string()];
+        let node_b = SharedNode::new(Node::new(config_b.clone()).unwrap());
+        let p2p_b = P2pService::new(node_b, config_b.p2p.clone()).await.unwrap();
+        let peers_b = p2p_b.peer_list();
+        let peer_book_b = p2p_b.peer_book();
+
+        let cancel = CancellationToken::new();
+        let tasks = [
+            tokio::spawn(p2p_a.run(cancel.clone())),
+            tokio::spawn(p2p_b.run(cancel.clone())),
+        ];
+
+        // B connects, then both sides hang up on learning the other's network
+        wait_for(async || {
+            peer_book_b
+                .get()
+                .values()
+                .any(|record| record.last_seen.is_some())
+        })
+        .await;
+        wait_for(async || peers_a.get().is_empty() && peers_b.get().is_empty()).await;
+
+        assert!(
+            peer_book_b
+                .get()
+                .values()
+                .all(|record| record.last_height.is_none())
+        );
+
+        cancel.cancel();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_graceful_shutdown() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut config = regtest_config(data_dir.path());
+        config.p2p.bootstrap_peers = vec!["/ip4/127.0.0.1/tcp/9".to_string()];
+        let node = SharedNode::new(Node::new(config.clone()).unwrap());
+
+        let block = node.write().await.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).await.unwrap();
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        let id = node.send(recipient, 10, 1).await.unwrap();
+
+        let handle = P2pService::new(node, config.p2p.clone())
+            .await
+            .unwrap()
+            .spawn();
+        tokio::time::timeout(Duration::from_secs(5), handle.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(config.peer_book_path().exists());
+        let restarted = Node::new(config).unwrap();
+        assert_eq!(
+            restarted.get_transaction(&id).unwrap().status,
+            TransactionStatus::Mempool
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stable_peer_id() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = regtest_config(data_dir.path());
+        let node = SharedNode::new(Node::new(config.clone()).unwrap());
+
+        let first = *P2pService::new(node.clone(), config.p2p.clone())
+            .await
+            .unwrap()
+            .local_peer_id();
+        let second = *P2pService::new(node, config.p2p.clone())
+            .await
+            .unwrap()
+            .local_peer_id();
+        assert_eq!(first, second);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(config.network_key_path()).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay_toggle() {
+        let enabled = |p2p: &P2pService| {
+            let behaviour = p2p.swarm.behaviour();
+            [
+                behaviour.relay_client.is_enabled(),
+                behaviour.dcutr.is_enabled(),
+                behaviour.autonat.is_enabled(),
+            ]
+        };
+        let relay = format!("/ip4/127.0.0.1/tcp/9/p2p/{}", PeerId::random());
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = regtest_config(data_dir.path());
+        let node = SharedNode::new(Node::new(config.clone()).unwrap());
+
+        let p2p = P2pService::new(node.clone(), config.p2p.clone())
+            .await
+            .unwrap();
+        assert_eq!(enabled(&p2p), [false; 3]);
+
+        // configuring a relay is enough to let AutoNAT decide
+        let auto = P2pConfig {
+            relay_servers: vec![relay.clone()],
+            ..config.p2p.clone()
+        };
+        let p2p = P2pService::new(node.clone(), auto.clone()).await.unwrap();
+        assert_eq!(enabled(&p2p), [true; 3]);
+        assert!(p2p.relay_listeners.is_empty());
+
+        let forced = P2pConfig {
+            enable_relay_client: Some(true),
+            ..auto.clone()
+        };
+        let p2p = P2pService::new(node.clone(), forced).await.unwrap();
+        assert_eq!(enabled(&p2p), [true; 3]);
+        assert_eq!(
+            p2p.relay_listeners.values().collect::<Vec<_>>(),
+            [&relay.parse::<Multiaddr>().unwrap()]
+        );
+
+        let disabled = P2pConfig {
+            enable_relay_client: Some(false),
+            ..auto.clone()
+        };
+        let p2p = P2pService::new(node.clone(), disabled).await.unwrap();
+        assert_eq!(enabled(&p2p), [false; 3]);
+
+        // relays are only reachable by peer id
+        let unnamed = P2pConfig {
+            relay_servers: vec!["/ip4/127.0.0.1/tcp/9".to_string()],
+            ..auto
+        };
+        assert!(P2pService::new(node, unnamed).await.is_err());
+    }
+}
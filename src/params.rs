@@ -0,0 +1,403 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::{
+    block::{Block, BlockHeader},
+    constants::{BLOCKS_PER_REWARD_HALVING, GENESIS_BLOCK_REWARD},
+    crypto::{Address, Hash, KeyPair, PublicKeyOnly, sha256d},
+    transaction::{Transaction, TransactionBody, TransactionInput, TransactionOutput},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl Network {
+    pub const ALL: [Self; 3] = [Self::Mainnet, Self::Testnet, Self::Regtest];
+}
+
+/// An output the genesis coinbase pays on top of its block reward.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisAllocation {
+    pub address: Address,
+    pub value: u64,
+}
+
+/// How blocks are sealed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "details", rename_all = "snake_case")]
+pub enum ConsensusMode {
+    /// Mined to the difficulty target each header sets, the chain with the most work
+    /// winning.
+    #[default]
+    ProofOfWork,
+    /// Signed by one of `authorities` instead of mined, for private networks and tests.
+    /// Every block has difficulty 0, so each adds the same work and the longest chain
+    /// wins. Headers alone can't be checked without the blocks' signatures.
+    ProofOfAuthority { authorities: Vec<PublicKeyOnly> },
+}
+
+impl ConsensusMode {
+    pub fn is_proof_of_work(&self) -> bool {
+        matches!(self, Self::ProofOfWork)
+    }
+
+    /// Whether blocks sealed by `public_key`'s keypair are valid: anyone may mine, but
+    /// only the authorities may sign.
+    pub fn can_seal(&self, public_key: &PublicKey) -> bool {
+        match self {
+            Self::ProofOfWork => true,
+            Self::ProofOfAuthority { authorities } => authorities
+                .iter()
+                .any(|authority| authority.0 == *public_key),
+        }
+    }
+}
+
+/// Consensus parameters a node validates blocks against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkParams {
+    pub network: Network,
+    pub genesis_block_reward: u64,
+    pub blocks_per_reward_halving: u32,
+    /// The least a block ever rewards, once halvings would take the reward below it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail_emission: Option<u64>,
+    pub genesis_timestamp: u32,
+    pub genesis_difficulty: u8,
+    /// `None` mines the genesis block when it's built, which is only practical for a
+    /// trivial difficulty.
+    pub genesis_nonce: Option<u64>,
+    /// Seeds the genesis coinbase's key in place of the network name, so a private
+    /// network gets a genesis block of its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genesis_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub premine: Vec<GenesisAllocation>,
+    #[serde(default, skip_serializing_if = "ConsensusMode::is_proof_of_work")]
+    pub consensus: ConsensusMode,
+}
+
+impl Default for NetworkParams {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+impl NetworkParams {
+    pub fn mainnet() -> Self {
+        Self {
+            network: Network::Mainnet,
+            genesis_block_reward: GENESIS_BLOCK_REWARD as u64,
+            blocks_per_reward_halving: BLOCKS_PER_REWARD_HALVING,
+            tail_emission: None,
+            genesis_timestamp: 1760850297,
+            genesis_difficulty: 2,
+            genesis_nonce: Some(11247),
+            genesis_message: None,
+            premine: Vec::new(),
+            consensus: ConsensusMode::ProofOfWork,
+        }
+    }
+
+    pub fn testnet() -> Self {
+        Self {
+            network: Network::Testnet,
+            genesis_difficulty: 1,
+            genesis_nonce: Some(223),
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn regtest() -> Self {
+        Self {
+            network: Network::Regtest,
+            genesis_difficulty: 0,
+            genesis_nonce: None,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn for_network(network: Network) -> Self {
+        match network {
+            Network::Mainnet => Self::mainnet(),
+            Network::Testnet => Self::testnet(),
+            Network::Regtest => Self::regtest(),
+        }
+    }
+
+    /// The genesis reward halved once every `blocks_per_reward_halving` blocks, down to
+    /// the tail emission or zero. An interval of zero never halves.
+    pub fn block_reward(&self, height: u32) -> u64 {
+        let halvings = height
+            .checked_div(self.blocks_per_reward_halving)
+            .unwrap_or(0);
+        let reward = self.genesis_block_reward.checked_shr(halvings).unwrap_or(0);
+
+        reward.max(self.tail_emission.unwrap_or(0))
+    }
+
+    /// The new coins the coinbase at `height` claims before fees: the block reward, and
+    /// for the genesis block the premine too. `None` when they total more than a `u64`
+    /// holds, which `check` refuses.
+    pub fn coinbase_subsidy(&self, height: u32) -> Option<u64> {
+        match height {
+            1 => self
+                .premine
+                .iter()
+                .try_fold(self.block_reward(1), |total, output| {
+                    total.checked_add(output.value)
+                }),
+            _ => Some(self.block_reward(height)),
+        }
+    }
+
+    /// The coins in existence once the chain reaches `height`: the subsidies of every
+    /// block up to it, as fees only move coins from one output to another. `None` when
+    /// that's more than a `u64` holds.
+    pub fn expected_supply(&self, height: u32) -> Option<u64> {
+        (1..=height).try_fold(0u64, |total, height| {
+            total.checked_add(self.coinbase_subsidy(height)?)
+        })
+    }
+
+    /// Refuses params whose genesis coinbase couldn't be paid, because the block reward
+    /// and premine total more than a `u64` holds.
+    pub fn check(&self) -> Result<()> {
+        if self.coinbase_subsidy(1).is_none() {
+            anyhow::bail!(
+                "The genesis block reward and premine total more than {}",
+                u64::MAX
+            );
+        }
+        Ok(())
+    }
+
+    /// The first block of the network. Its coinbase pays a key derived from the network
+    /// name (or the genesis message), and signing is deterministic, so every node builds
+    /// the same block.
+    pub fn genesis_block(&self) -> Result<Block> {
+        let mut block = self.genesis_template()?;
+        if self.genesis_nonce.is_none() {
+            block.mine()?;
+        }
+
+        Ok(block)
+    }
+
+    /// The genesis block with the configured nonce, which is zero when it's mined on the
+    /// fly.
+    pub fn genesis_template(&self) -> Result<Block> {
+        let seed = match &self.genesis_message {
+            Some(message) => sha256d(message.as_bytes()),
+            None => sha256d(format!("tiny-crypto {} genesis", self.network).as_bytes()),
+        };
+        let keypair = KeyPair::from_seed(&seed)?;
+
+        let mut outputs = vec![TransactionOutput {
+            value: self.block_reward(1),
+            address: Address::from_public_key_for(&keypair.public_key, self.network),
+        }];
+        outputs.extend(self.premine.iter().map(|output| TransactionOutput {
+            value: output.value,
+            address: output.address.clone(),
+        }));
+        let coinbase_tx = TransactionBody {
+            input: TransactionInput::Coinbase { block_height: 1 },
+            outputs,
+        }
+        .into_tx(&keypair)?;
+
+        let transactions = vec![Arc::new(coinbase_tx)];
+        let merkle_root = Transaction::build_merkle_tree(&transactions)?
+            .root()
+            .ok_or(anyhow::anyhow!("Failed to compute merkle root"))?;
+
+        Ok(Block {
+            height: 1,
+            header: BlockHeader {
+                previous_block_hash: Hash::default(),
+                merkle_root,
+                timestamp: self.genesis_timestamp,
+                difficulty: self.genesis_difficulty,
+                nonce: self.genesis_nonce.unwrap_or_default(),
+            },
+            transactions,
+            signature: None,
+        })
+    }
+
+    pub fn genesis_hash(&self) -> Result<Hash> {
+        self.genesis_block()?.header.hash()
+    }
+
+    /// Identifies the network in peer handshakes: the leading bytes of its genesis hash.
+    pub fn magic(&self) -> Result<[u8; 4]> {
+        let hash = self.genesis_hash()?;
+        Ok([hash[0], hash[1], hash[2], hash[3]])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::BlockValidationError, utxo_set::UTXOSet};
+
+    #[test]
+    fn test_genesis_blocks() {
+        let networks = [Network::Mainnet, Network::Testnet, Network::Regtest];
+
+        let hashes = networks
+            .iter()
+            .map(|&network| {
+                let params = NetworkParams::for_network(network);
+                let genesis = params.genesis_block().unwrap();
+
+                genesis.validate(&params).unwrap();
+                assert_eq!(
+                    genesis.header.hash().unwrap(),
+                    params.genesis_hash().unwrap()
+                );
+
+                genesis.header.hash().unwrap()
+            })
+            .collect::<std::collections::HashSet<_>>();
+
+        assert_eq!(hashes.len(), networks.len());
+        assert_eq!("testnet".parse::<Network>().unwrap(), Network::Testnet);
+    }
+
+    #[test]
+    fn test_custom_genesis() {
+        let premined =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Testnet);
+        let params = NetworkParams {
+            network: Network::Testnet,
+            genesis_block_reward: 10,
+            genesis_difficulty: 0,
+            genesis_nonce: None,
+            genesis_message: Some("private".to_string()),
+            premine: vec![GenesisAllocation {
+                address: premined.clone(),
+                value: 1_000,
+            }],
+            ..NetworkParams::mainnet()
+        };
+
+        let genesis = params.genesis_block().unwrap();
+        genesis.validate(&params).unwrap();
+        let mut utxo_set = UTXOSet::default();
+        genesis.connect(&mut utxo_set, &params).unwrap();
+        assert_eq!(genesis.transactions[0].body.outputs[1].address, premined);
+        assert_eq!(params.coinbase_subsidy(1), Some(1_010));
+        assert_eq!(params.coinbase_subsidy(2), Some(10));
+
+        // the message, not the network name, determines the genesis block
+        let named = NetworkParams {
+            genesis_message: None,
+            premine: Vec::new(),
+            ..params.clone()
+        };
+        assert_ne!(
+            named.genesis_hash().unwrap(),
+            params.genesis_hash().unwrap()
+        );
+
+        // without its premine the genesis coinbase claims too little
+        let mut unpremined = genesis.clone();
+        unpremined.transactions = vec![named.genesis_block().unwrap().transactions[0].clone()];
+        assert!(
+            unpremined
+                .connect(&mut UTXOSet::default(), &params)
+                .is_err()
+        );
+        params.check().unwrap();
+
+        // premines too large for the coinbase to pay are refused rather than wrapping
+        let allocation = GenesisAllocation {
+            address: premined,
+            value: i64::MAX as u64,
+        };
+        let overflowing = NetworkParams {
+            premine: vec![allocation.clone(), allocation],
+            ..params
+        };
+        assert_eq!(overflowing.coinbase_subsidy(1), None);
+        assert_eq!(overflowing.expected_supply(2), None);
+        assert!(overflowing.check().is_err());
+        let genesis = overflowing.genesis_template().unwrap();
+        assert!(matches!(
+            genesis.validate(&overflowing).unwrap_err().downcast_ref(),
+            Some(BlockValidationError::ValueOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_reward_schedule() {
+        let params = NetworkParams::mainnet();
+        let interval = params.blocks_per_reward_halving;
+
+        assert_eq!(params.block_reward(1), 50);
+        assert_eq!(params.block_reward(interval - 1), 50);
+        assert_eq!(params.block_reward(interval), 25);
+        assert_eq!(params.block_reward(2 * interval - 1), 25);
+        assert_eq!(params.block_reward(2 * interval), 12);
+        assert_eq!(params.block_reward(6 * interval - 1), 1);
+        assert_eq!(params.block_reward(6 * interval), 0);
+
+        // past the width of the reward, where shifting or raising two would overflow
+        assert_eq!(params.block_reward(32 * interval), 0);
+        assert_eq!(params.block_reward(interval * 64), 0);
+        assert_eq!(params.block_reward(u32::MAX), 0);
+        assert_eq!(
+            params.expected_supply(7 * interval),
+            params.expected_supply(6 * interval)
+        );
+    }
+
+    #[test]
+    fn test_tail_emission() {
+        let params = NetworkParams {
+            genesis_block_reward: 1_000,
+            blocks_per_reward_halving: 10,
+            tail_emission: Some(100),
+            ..NetworkParams::regtest()
+        };
+
+        assert_eq!(params.block_reward(9), 1_000);
+        assert_eq!(params.block_reward(10), 500);
+        assert_eq!(params.block_reward(20), 250);
+        assert_eq!(params.block_reward(29), 250);
+        assert_eq!(params.block_reward(30), 125);
+        assert_eq!(params.block_reward(40), 100);
+        assert_eq!(params.block_reward(10 * 64), 100);
+        assert_eq!(params.block_reward(u32::MAX), 100);
+
+        let coinbase = Transaction::new_coinbase(&KeyPair::generate(), 10 * 64, &params).unwrap();
+        assert_eq!(coinbase.output_value(), 100);
+
+        // the tail emission round-trips, and params files without one still parse
+        let json = serde_json::to_string(&params).unwrap();
+        assert_eq!(
+            serde_json::from_str::<NetworkParams>(&json).unwrap(),
+            params
+        );
+        let json = serde_json::to_string(&NetworkParams::mainnet()).unwrap();
+        assert!(!json.contains("tail_emission"));
+        assert_eq!(
+            serde_json::from_str::<NetworkParams>(&json).unwrap(),
+            NetworkParams::mainnet()
+        );
+    }
+}
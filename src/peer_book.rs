@@ -0,0 +1,253 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub peer_id: Option<PeerId>,
+    /// Unix time the address was first recorded.
+    pub added_at: i64,
+    /// Unix time of the last successful connection.
+    pub last_seen: Option<i64>,
+    /// Best height the peer reported in its handshake.
+    pub last_height: Option<u32>,
+    /// Failed dials since the last successful connection.
+    pub failures: u32,
+    pub misbehavior: u32,
+}
+
+impl PeerRecord {
+    fn new(now: i64) -> Self {
+        Self {
+            peer_id: None,
+            added_at: now,
+            last_seen: None,
+            last_height: None,
+            failures: 0,
+            misbehavior: 0,
+        }
+    }
+
+    /// Sorts best first: least misbehavior, then fewest failed dials, then most recently
+    /// seen.
+    fn dial_order(&self) -> (u32, u32, Reverse<Option<i64>>) {
+        (self.misbehavior, self.failures, Reverse(self.last_seen))
+    }
+}
+
+/// Addresses of peers we've known, persisted as JSON in the data dir so a restarted node
+/// has somewhere to dial. Clones share the same book.
+#[derive(Debug, Clone)]
+pub struct PeerBook {
+    path: PathBuf,
+    entries: Arc<RwLock<BTreeMap<Multiaddr, PeerRecord>>>,
+    added: Arc<Notify>,
+}
+
+impl PeerBook {
+    /// Loads the book at `path`, starting empty if it doesn't exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let entries = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path,
+            entries: Arc::new(RwLock::new(entries)),
+            added: Arc::new(Notify::new()),
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_vec_pretty(&*self.entries.read().unwrap())?;
+        fs::write(&self.path, json)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> BTreeMap<Multiaddr, PeerRecord> {
+        self.entries.read().unwrap().clone()
+    }
+
+    /// Records an address to dial, waking the p2p service if it's new.
+    pub fn add_peer(&self, address: Multiaddr, now: i64) -> bool {
+        let added = self.insert(address, now);
+
+        if added {
+            self.added.notify_one();
+        }
+
+        added
+    }
+
+    /// Like `add_peer`, without waking the p2p service.
+    pub(crate) fn insert(&self, address: Multiaddr, now: i64) -> bool {
+        let mut entries = self.entries.write().unwrap();
+
+        if entries.contains_key(&address) {
+            return false;
+        }
+
+        entries.insert(address, PeerRecord::new(now));
+        true
+    }
+
+    /// Resolves once an address has been added with `add_peer`.
+    pub async fn added(&self) {
+        self.added.notified().await
+    }
+
+    pub fn record_connected(&self, address: Multiaddr, peer_id: PeerId, now: i64) {
+        let mut entries = self.entries.write().unwrap();
+        let record = entries
+            .entry(address)
+            .or_insert_with(|| PeerRecord::new(now));
+
+        record.peer_id = Some(peer_id);
+        record.last_seen = Some(now);
+        record.failures = 0;
+    }
+
+    pub fn record_failure(&self, address: &Multiaddr) {
+        if let Some(record) = self.entries.write().unwrap().get_mut(address) {
+            record.failures += 1;
+        }
+    }
+
+    pub fn record_misbehavior(&self, peer_id: &PeerId, score: u32) {
+        self.update_peer(peer_id, |record| record.misbehavior += score);
+    }
+
+    pub fn record_height(&self, peer_id: &PeerId, height: u32) {
+        self.update_peer(peer_id, |record| record.last_height = Some(height));
+    }
+
+    /// Misbehavior recorded against a peer under any of its addresses.
+    pub fn misbehavior(&self, peer_id: &PeerId) -> u32 {
+        self.entries
+            .read()
+            .unwrap()
+            .values()
+            .filter(|record| record.peer_id.as_ref() == Some(peer_id))
+            .map(|record| record.misbehavior)
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn update_peer(&self, peer_id: &PeerId, mut update: impl FnMut(&mut PeerRecord)) {
+        self.entries
+            .write()
+            .unwrap()
+            .values_mut()
+            .filter(|record| record.peer_id.as_ref() == Some(peer_id))
+            .for_each(&mut update);
+    }
+
+    /// Drops entries not seen, or added if never seen, within `max_age_secs` of `now`.
+    pub fn prune(&self, now: i64, max_age_secs: i64) {
+        self.entries
+            .write()
+            .unwrap()
+            .retain(|_, record| now - record.last_seen.unwrap_or(record.added_at) <= max_age_secs);
+    }
+
+    /// Up to `count` addresses, best first.
+    pub fn best(&self, count: usize) -> Vec<Multiaddr> {
+        let entries = self.entries.read().unwrap();
+
+        let mut addresses = entries.iter().collect::<Vec<_>>();
+        addresses.sort_by_key(|(_, record)| record.dial_order());
+
+        addresses
+            .into_iter()
+            .take(count)
+            .map(|(address, _)| address.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(port: u16) -> Multiaddr {
+        format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap()
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path = data_dir.path().join("peers.json");
+        let peer_id = PeerId::random();
+
+        let book = PeerBook::load(&path).unwrap();
+        assert!(book.get().is_empty());
+
+        assert!(book.add_peer(address(1), 100));
+        assert!(!book.add_peer(address(1), 200));
+        book.record_connected(address(2), peer_id, 300);
+        book.record_height(&peer_id, 42);
+        book.record_misbehavior(&peer_id, 5);
+        book.save().unwrap();
+
+        let loaded = PeerBook::load(&path).unwrap();
+        assert_eq!(loaded.get(), book.get());
+        assert_eq!(
+            loaded.get()[&address(2)],
+            PeerRecord {
+                peer_id: Some(peer_id),
+                added_at: 300,
+                last_seen: Some(300),
+                last_height: Some(42),
+                failures: 0,
+                misbehavior: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_dial_order_and_prune() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let book = PeerBook::load(data_dir.path().join("peers.json")).unwrap();
+
+        let misbehaving = PeerId::random();
+        book.record_connected(address(1), misbehaving, 500);
+        book.record_misbehavior(&misbehaving, 1);
+
+        book.add_peer(address(2), 100);
+        book.record_failure(&address(2));
+
+        book.record_connected(address(3), PeerId::random(), 200);
+        book.record_connected(address(4), PeerId::random(), 400);
+        book.add_peer(address(5), 600);
+
+        assert_eq!(
+            book.best(10),
+            vec![address(4), address(3), address(5), address(2), address(1)]
+        );
+        assert_eq!(book.best(2), vec![address(4), address(3)]);
+
+        book.prune(700, 300);
+        assert_eq!(book.best(10), vec![address(4), address(5), address(1)]);
+    }
+}
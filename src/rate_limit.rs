@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::RATE_LIMIT_WINDOW_SECS;
+
+/// A sustained rate of messages per second, with bursts of up to `burst` allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub per_sec: u32,
+    pub burst: u32,
+}
+
+/// Per-peer limits on inbound messages, one per `MessageKind`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimits {
+    /// Gossiped blocks that extend our tip, which we never want to miss.
+    pub tip_blocks: RateLimit,
+    /// Other gossiped blocks.
+    pub blocks: RateLimit,
+    pub transactions: RateLimit,
+    /// Sync requests: headers, blocks, transactions, and inventory.
+    pub requests: RateLimit,
+    /// Messages a peer may have dropped within `RATE_LIMIT_WINDOW_SECS` before it's
+    /// penalized.
+    pub max_dropped: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            tip_blocks: RateLimit {
+                per_sec: 10,
+                burst: 50,
+            },
+            blocks: RateLimit {
+                per_sec: 2,
+                burst: 10,
+            },
+            transactions: RateLimit {
+                per_sec: 50,
+                burst: 200,
+            },
+            requests: RateLimit {
+                per_sec: 20,
+                burst: 100,
+            },
+            max_dropped: 100,
+        }
+    }
+}
+
+impl RateLimits {
+    fn get(&self, kind: MessageKind) -> RateLimit {
+        match kind {
+            MessageKind::TipBlock => self.tip_blocks,
+            MessageKind::Block => self.blocks,
+            MessageKind::Transaction => self.transactions,
+            MessageKind::Request => self.requests,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    TipBlock,
+    Block,
+    Transaction,
+    Request,
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    per_sec: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full.
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            per_sec: limit.per_sec as f64,
+            capacity: limit.burst as f64,
+            tokens: limit.burst as f64,
+            updated: now,
+        }
+    }
+
+    /// Takes a token if there's one, after refilling for the time since the last call.
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.capacity);
+        self.updated = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Drop,
+    /// Dropped, and the peer has gone over `max_dropped`.
+    Penalize,
+}
+
+/// The buckets for one peer, and how many of its messages were dropped recently.
+#[derive(Debug, Clone)]
+pub struct PeerRateLimiter {
+    limits: RateLimits,
+    buckets: HashMap<MessageKind, TokenBucket>,
+    dropped: u32,
+    window_start: Instant,
+}
+
+impl PeerRateLimiter {
+    pub fn new(limits: RateLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            buckets: HashMap::new(),
+            dropped: 0,
+            window_start: now,
+        }
+    }
+
+    pub fn check(&mut self, kind: MessageKind, now: Instant) -> Verdict {
+        let limit = self.limits.get(kind);
+        let bucket = self
+            .buckets
+            .entry(kind)
+            .or_insert_with(|| TokenBucket::new(limit, now));
+
+        if bucket.try_take(now) {
+            return Verdict::Allow;
+        }
+
+        if now.saturating_duration_since(self.window_start)
+            >= Duration::from_secs(RATE_LIMIT_WINDOW_SECS)
+        {
+            self.window_start = now;
+            self.dropped = 0;
+        }
+        self.dropped += 1;
+
+        if self.dropped > self.limits.max_dropped {
+            self.dropped = 0;
+            Verdict::Penalize
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(
+            RateLimit {
+                per_sec: 2,
+                burst: 3,
+            },
+            start,
+        );
+
+        // the burst, then nothing until it refills at two a second
+        assert!((0..3).all(|_| bucket.try_take(start)));
+        assert!(!bucket.try_take(start));
+        assert!(!bucket.try_take(start + Duration::from_millis(499)));
+        assert!(bucket.try_take(start + Duration::from_millis(500)));
+        assert!(!bucket.try_take(start + Duration::from_millis(500)));
+
+        // a long quiet spell refills no more than the burst
+        let later = start + Duration::from_secs(60);
+        assert_eq!((0..10).filter(|_| bucket.try_take(later)).count(), 3);
+    }
+
+    #[test]
+    fn test_peer_rate_limiter() {
+        let start = Instant::now();
+        let limits = RateLimits {
+            transactions: RateLimit {
+                per_sec: 1,
+                burst: 1,
+            },
+            max_dropped: 2,
+            ..RateLimits::default()
+        };
+        let mut limiter = PeerRateLimiter::new(limits, start);
+
+        let verdicts = (0..5)
+            .map(|_| limiter.check(MessageKind::Transaction, start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Allow,
+                Verdict::Drop,
+                Verdict::Drop,
+                Verdict::Penalize,
+                Verdict::Drop
+            ]
+        );
+
+        // kinds have their own buckets, so a transaction flood doesn't hold up blocks
+        assert_eq!(limiter.check(MessageKind::TipBlock, start), Verdict::Allow);
+
+        // drops are only counted against the peer for a window
+        let later = start + Duration::from_secs(RATE_LIMIT_WINDOW_SECS);
+        assert_eq!(
+            limiter.check(MessageKind::Transaction, later),
+            Verdict::Allow
+        );
+        assert_eq!(
+            limiter.check(MessageKind::Transaction, later),
+            Verdict::Drop
+        );
+        assert_eq!(
+            limiter.check(MessageKind::Transaction, later),
+            Verdict::Drop
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Read-only REST routes for explorers and web frontends, served beside JSON-RPC by
+//! `RpcServer`. Responses are the same serde forms the RPC methods return, and failures
+//! are a JSON `{"error": ...}` body with a 400 for malformed input or a 404 for unknown
+//! objects.
+
+use axum::{
+    Json, Router,
+    extract::{FromRef, Path, Query, State, rejection::QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    address_history::HistoryEntry,
+    constants::{DEFAULT_REST_PAGE_SIZE, MAX_REST_PAGE_SIZE},
+    crypto::{Address, Hash, HashExt},
+    node::{Node, UnspentOutput},
+    rpc::{AddressBalance, BlockInfo, RawTransaction, TransactionInfo, hash_at},
+    shared_node::SharedNode,
+    transaction::TxId,
+    tx_index::TransactionStatus,
+};
+
+#[derive(Debug)]
+pub struct RestError {
+    status: StatusCode,
+    message: String,
+}
+
+impl RestError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+
+    fn internal(error: anyhow::Error) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for RestError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type RestResult<T> = Result<Json<T>, RestError>;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PageQuery {
+    #[serde(default)]
+    pub offset: usize,
+    /// At most `MAX_REST_PAGE_SIZE`.
+    pub limit: Option<usize>,
+}
+
+/// One page of a list, with the full list's length to page through it by.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub items: Vec<T>,
+}
+
+impl PageQuery {
+    fn limit(&self) -> Result<usize, RestError> {
+        let limit = self.limit.unwrap_or(DEFAULT_REST_PAGE_SIZE);
+        if limit == 0 || limit > MAX_REST_PAGE_SIZE {
+            return Err(RestError::bad_request(format!(
+                "Limit must be between 1 and {MAX_REST_PAGE_SIZE}"
+            )));
+        }
+        Ok(limit)
+    }
+}
+
+impl<T> Page<T> {
+    fn new(items: Vec<T>, query: PageQuery) -> Result<Self, RestError> {
+        let limit = query.limit()?;
+        let total = items.len();
+        Ok(Self {
+            total,
+            offset: query.offset,
+            limit,
+            items: items.into_iter().skip(query.offset).take(limit).collect(),
+        })
+    }
+}
+
+/// The routes, for any router state the node can be taken from.
+pub fn routes<S>() -> Router<S>
+where
+    SharedNode: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/blocks/tip", get(tip))
+        .route("/blocks/{hash}", get(block))
+        .route("/blocks/height/{height}", get(block_at))
+        .route("/txs/{txid}", get(transaction))
+        .route("/addresses/{address}/balance", get(balance))
+        .route("/addresses/{address}/utxos", get(utxos))
+        .route("/addresses/{address}/history", get(history))
+}
+
+fn find_block(node: &Node, hash: &Hash) -> RestResult<BlockInfo> {
+    BlockInfo::find(node, hash)
+        .map(Json)
+        .ok_or_else(|| RestError::not_found(format!("No block 0x{}", hash.display())))
+}
+
+fn parse_hash(hash: &str) -> Result<Hash, RestError> {
+    Hash::from_hex(hash).map_err(|error| RestError::bad_request(format!("Invalid hash: {error}")))
+}
+
+fn parse_address(node: &Node, address: &str) -> Result<Address, RestError> {
+    Address::parse_for(address, node.state.params.network)
+        .map_err(|error| RestError::bad_request(format!("Invalid address: {error}")))
+}
+
+async fn tip(State(node): State<SharedNode>) -> RestResult<BlockInfo> {
+    let node = node.read().await;
+    let hash = node
+        .tip_hash()
+        .map_err(RestError::internal)?
+        .ok_or_else(|| RestError::not_found("The chain is empty"))?;
+    find_block(&node, &hash)
+}
+
+async fn block(State(node): State<SharedNode>, Path(hash): Path<String>) -> RestResult<BlockInfo> {
+    let hash = parse_hash(&hash)?;
+    find_block(&*node.read().await, &hash)
+}
+
+async fn block_at(
+    State(node): State<SharedNode>,
+    Path(height): Path<String>,
+) -> RestResult<BlockInfo> {
+    let height = height
+        .parse::<u32>()
+        .map_err(|error| RestError::bad_request(format!("Invalid height: {error}")))?;
+    let node = node.read().await;
+    let hash = hash_at(&node, height)
+        .map_err(RestError::internal)?
+        .ok_or_else(|| RestError::not_found(format!("No block at height {height}")))?;
+    find_block(&node, &hash)
+}
+
+async fn transaction(
+    State(node): State<SharedNode>,
+    Path(txid): Path<String>,
+) -> RestResult<TransactionInfo> {
+    let id = TxId(parse_hash(&txid)?);
+    let node = node.read().await;
+    let record = node
+        .get_transaction(&id)
+        .ok_or_else(|| RestError::not_found(format!("No transaction {id}")))?;
+
+    let proof = match &record.status {
+        TransactionStatus::Confirmed { block_hash, .. } => Some(
+            node.state
+                .block_manager
+                .get_block(block_hash)
+                .ok_or_else(|| {
+                    RestError::internal(anyhow::anyhow!(
+                        "No block 0x{} for confirmed transaction {id}",
+                        block_hash.display()
+                    ))
+                })?
+                .transaction_proof(&id)
+                .map_err(RestError::internal)?,
+        ),
+        _ => None,
+    };
+    Ok(Json(TransactionInfo {
+        raw: RawTransaction::new(id, &record).map_err(RestError::internal)?,
+        transaction: record.transaction,
+        proof,
+    }))
+}
+
+async fn balance(
+    State(node): State<SharedNode>,
+    Path(address): Path<String>,
+) -> RestResult<AddressBalance> {
+    let node = node.read().await;
+    let address = parse_address(&node, &address)?;
+    Ok(Json(AddressBalance {
+        confirmed: node.get_confirmed_balance(&address),
+        spendable: node.get_balance(&address).map_err(RestError::internal)?,
+    }))
+}
+
+/// The address's unspent outputs as `listunspent` orders them, pending ones included.
+async fn utxos(
+    State(node): State<SharedNode>,
+    Path(address): Path<String>,
+    query: Result<Query<PageQuery>, QueryRejection>,
+) -> RestResult<Page<UnspentOutput>> {
+    let Query(query) = query.map_err(|error| RestError::bad_request(error.body_text()))?;
+    let node = node.read().await;
+    let address = parse_address(&node, &address)?;
+    let unspent = node.list_unspent(&address).map_err(RestError::internal)?;
+    Ok(Json(Page::new(unspent, query)?))
+}
+
+/// The address's confirmed history, oldest first, on nodes indexing it.
+async fn history(
+    State(node): State<SharedNode>,
+    Path(address): Path<String>,
+    query: Result<Query<PageQuery>, QueryRejection>,
+) -> RestResult<Page<HistoryEntry>> {
+    let Query(query) = query.map_err(|error| RestError::bad_request(error.body_text()))?;
+    let limit = query.limit()?;
+    let node = node.read().await;
+    let address = parse_address(&node, &address)?;
+    let page = node
+        .state
+        .address_history(&address, query.offset, limit)
+        .ok_or_else(|| RestError::not_found("Address history isn't indexed"))?;
+    Ok(Json(Page {
+        total: page.total,
+        offset: query.offset,
+        limit,
+        items: page.entries,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page() {
+        let query = |offset, limit| PageQuery { offset, limit };
+        let page = Page::new((0..5).collect(), query(3, Some(10))).unwrap();
+        assert_eq!((page.total, page.items), (5, vec![3, 4]));
+        assert_eq!(
+            Page::new((0..5).collect::<Vec<_>>(), query(0, None))
+                .unwrap()
+                .limit,
+            DEFAULT_REST_PAGE_SIZE
+        );
+        assert!(Page::new(vec![1], query(0, Some(0))).is_err());
+        assert!(Page::new(vec![1], query(0, Some(MAX_REST_PAGE_SIZE + 1))).is_err());
+    }
+}
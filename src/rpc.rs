@@ -0,0 +1,1035 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::{FromRef, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    block::{Block, BlockHeader, BlockValidationError, TransactionProof},
+    constants::{RPC_CLIENT_TIMEOUT_SECS, RPC_LONGPOLL_TIMEOUT_SECS},
+    crypto::{Address, Hash, HashExt, KeyPair, serde_hex, sha256d},
+    mem_pool::{MemPoolEntry, MemPoolStats, ancestors, descendants},
+    metrics,
+    node::{Message, Node, NodeEvent, UnspentOutput},
+    outcome::{HandleOutcome, RejectReason},
+    p2p::{PeerInfo, PeerList},
+    params::Network,
+    rest,
+    shared_node::SharedNode,
+    transaction::{Transaction, TransactionBody, TransactionInput, TransactionOutput, TxId},
+    tx_index::{TransactionRecord, TransactionStatus},
+    ws,
+};
+
+/// JSON-RPC 2.0 error codes, followed by the node's own.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The node refused a transaction, with the reason as the message.
+pub const REJECTED: i64 = -26;
+/// The node refused a block, with the reason as the message.
+pub const BLOCK_REJECTED: i64 = -25;
+pub const NOT_FOUND: i64 = -5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} (code {code})")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    /// For `REJECTED` and `BLOCK_REJECTED`, the serialized `RejectReason`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn rejected(reason: &RejectReason) -> Self {
+        let code = match reason {
+            RejectReason::InvalidBlock(_) => BLOCK_REJECTED,
+            _ => REJECTED,
+        };
+        Self {
+            data: serde_json::to_value(reason).ok(),
+            ..Self::new(code, reason.to_string())
+        }
+    }
+
+    /// The typed reason of a `REJECTED` or `BLOCK_REJECTED` error.
+    pub fn reject_reason(&self) -> Option<RejectReason> {
+        match self.code {
+            REJECTED | BLOCK_REJECTED => serde_json::from_value(self.data.clone()?).ok(),
+            _ => None,
+        }
+    }
+
+    fn internal(error: anyhow::Error) -> Self {
+        Self::new(INTERNAL_ERROR, error.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcResponse {
+    jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+impl RpcResponse {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+/// What `getrawtransaction` returns: the transaction and where it stands.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawTransaction {
+    pub txid: TxId,
+    pub hex: String,
+    /// 0 while the transaction is pending or after it was rejected.
+    pub confirmations: u32,
+    #[serde(default, with = "serde_hex::option")]
+    pub block_hash: Option<Hash>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Why the node refused it, if it did.
+    #[serde(default)]
+    pub rejected: Option<String>,
+}
+
+impl RawTransaction {
+    pub(crate) fn new(id: TxId, record: &TransactionRecord) -> Result<Self> {
+        let mut raw = Self {
+            txid: id,
+            hex: record.transaction.to_hex()?,
+            confirmations: 0,
+            block_hash: None,
+            height: None,
+            rejected: None,
+        };
+        match &record.status {
+            TransactionStatus::Mempool => {}
+            TransactionStatus::Confirmed {
+                block_hash,
+                height,
+                confirmations,
+            } => {
+                raw.confirmations = *confirmations;
+                raw.block_hash = Some(*block_hash);
+                raw.height = Some(*height);
+            }
+            TransactionStatus::Rejected(reason) => raw.rejected = Some(reason.to_string()),
+        }
+        Ok(raw)
+    }
+}
+
+/// What `GET /txs/{txid}` returns: a transaction decoded, where it stands, and for a
+/// confirmed one the proof it's in its block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionInfo {
+    #[serde(flatten)]
+    pub raw: RawTransaction,
+    pub transaction: Transaction,
+    #[serde(default)]
+    pub proof: Option<TransactionProof>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemPoolInfo {
+    pub size: usize,
+    pub max_transactions: usize,
+    pub min_fee: u64,
+    pub dust_limit: u64,
+}
+
+/// What `testmempoolaccept` returns: whether the node would take the transaction now.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemPoolAcceptance {
+    pub txid: TxId,
+    pub allowed: bool,
+    /// Already pending, so it wouldn't be added again.
+    pub duplicate: bool,
+    #[serde(default)]
+    pub fee: Option<u64>,
+    #[serde(default)]
+    pub reject_reason: Option<RejectReason>,
+}
+
+/// What `getblock` returns: the block's JSON form with its hash and confirmations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockInfo {
+    #[serde(with = "serde_hex")]
+    pub hash: Hash,
+    /// 0 for a block off the active chain.
+    pub confirmations: u32,
+    #[serde(flatten)]
+    pub block: Block,
+}
+
+impl BlockInfo {
+    /// The stored block with `hash`, if the node has it.
+    pub(crate) fn find(node: &Node, hash: &Hash) -> Option<Self> {
+        let chain = &node.state.chain;
+        let block = node.state.block_manager.get_block(hash)?;
+        let confirmations = match chain.get_node(block.height) {
+            Some(on_chain) if on_chain.header == block.header => chain.height() - block.height + 1,
+            _ => 0,
+        };
+        Some(Self {
+            hash: *hash,
+            confirmations,
+            block: block.clone(),
+        })
+    }
+}
+
+/// The hash of the active chain's block at `height`.
+pub(crate) fn hash_at(node: &Node, height: u32) -> Result<Option<Hash>> {
+    node.state
+        .chain
+        .get_node(height)
+        .map(|on_chain| on_chain.header.hash())
+        .transpose()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressBalance {
+    pub confirmed: u64,
+    /// What can be spent once pending transactions are taken into account.
+    pub spendable: u64,
+}
+
+/// What `getblocktemplate` returns: what an external miner needs to build a block on the
+/// node's tip, paying itself with a coinbase of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockTemplate {
+    pub height: u32,
+    #[serde(with = "serde_hex")]
+    pub previous_block_hash: Hash,
+    pub difficulty: u8,
+    /// The target the header hash must not exceed, from `difficulty`.
+    #[serde(with = "serde_hex")]
+    pub target: Hash,
+    pub time: u32,
+    /// The tip's timestamp, which the block's shouldn't go below.
+    pub min_time: u32,
+    /// In the order they must appear after the coinbase.
+    pub transactions: Vec<TemplateTransaction>,
+    /// The block reward plus the transactions' fees.
+    pub coinbase_value: u64,
+    /// Passed back to wait for the next template; changes with the tip.
+    pub longpollid: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateTransaction {
+    pub txid: TxId,
+    pub hex: String,
+    pub fee: u64,
+}
+
+impl BlockTemplate {
+    fn new(node: &Node) -> Result<Self> {
+        let block = node.block_template()?;
+        let tip = node
+            .state
+            .chain
+            .tail()
+            .ok_or(anyhow::anyhow!("No tip to build on"))?;
+        let fees = node
+            .state
+            .mem_pool
+            .entries(&node.state.utxo_set, node.state.next_height())?
+            .into_iter()
+            .map(|entry| (entry.txid, entry.fee))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        let transactions = block.transactions[1..]
+            .iter()
+            .map(|transaction| {
+                let txid = transaction.id()?;
+                Ok(TemplateTransaction {
+                    fee: fees.get(&txid).copied().unwrap_or_default(),
+                    txid,
+                    hex: transaction.to_hex()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            height: block.height,
+            previous_block_hash: block.header.previous_block_hash,
+            difficulty: block.header.difficulty,
+            target: block.header.difficulty_target()?,
+            time: block.header.timestamp,
+            min_time: tip.header.timestamp,
+            transactions,
+            coinbase_value: block.transactions[0].output_value(),
+            longpollid: block.header.previous_block_hash.display().to_string(),
+        })
+    }
+
+    /// The unmined block, with a coinbase claiming `coinbase_value` for `keypair`'s address
+    /// on `network`.
+    pub fn to_block(&self, keypair: &KeyPair, network: Network) -> Result<Block> {
+        let coinbase = TransactionBody {
+            input: TransactionInput::Coinbase {
+                block_height: self.height,
+            },
+            outputs: vec![TransactionOutput {
+                value: self.coinbase_value,
+                address: Address::from_public_key_for(&keypair.public_key, network),
+            }],
+        }
+        .into_tx(keypair)?;
+
+        let mut transactions = vec![Arc::new(coinbase)];
+        for transaction in &self.transactions {
+            transactions.push(Arc::new(Transaction::from_hex(&transaction.hex)?));
+        }
+        let merkle_root = Transaction::build_merkle_tree(&transactions)?
+            .root()
+            .ok_or(anyhow::anyhow!("Failed to compute merkle root"))?;
+
+        Ok(Block {
+            height: self.height,
+            header: BlockHeader {
+                previous_block_hash: self.previous_block_hash,
+                merkle_root,
+                timestamp: self.time,
+                difficulty: self.difficulty,
+                nonce: 0,
+            },
+            transactions,
+            signature: None,
+        })
+    }
+}
+
+/// What `getmempoolentry` returns: a pending transaction and the others it's tied to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemPoolEntryInfo {
+    #[serde(flatten)]
+    pub entry: MemPoolEntry,
+    pub hex: String,
+    pub ancestors: Vec<TxId>,
+    pub descendants: Vec<TxId>,
+}
+
+/// Serves JSON-RPC 2.0 over HTTP POSTs to `/`, and beside it the read-only REST routes of
+/// `rest`, the WebSocket of `ws`, and Prometheus metrics at `/metrics`, answering with the
+/// node's state.
+pub struct RpcServer {
+    state: RpcState,
+    listener: TcpListener,
+}
+
+#[derive(Clone)]
+struct RpcState {
+    node: SharedNode,
+    peers: Option<PeerList>,
+    /// The sha256d of the token, compared by hash so the time a comparison takes says
+    /// nothing about the token.
+    auth_token: Option<Hash>,
+    /// Cancelled on shutdown, to close open WebSockets.
+    shutdown: CancellationToken,
+}
+
+impl FromRef<RpcState> for SharedNode {
+    fn from_ref(state: &RpcState) -> Self {
+        state.node.clone()
+    }
+}
+
+impl FromRef<RpcState> for CancellationToken {
+    fn from_ref(state: &RpcState) -> Self {
+        state.shutdown.clone()
+    }
+}
+
+impl RpcServer {
+    pub async fn bind(node: SharedNode, addr: &str) -> Result<Self> {
+        Ok(Self {
+            state: RpcState {
+                node,
+                peers: None,
+                auth_token: None,
+                shutdown: CancellationToken::new(),
+            },
+            listener: TcpListener::bind(addr).await?,
+        })
+    }
+
+    /// Answers `getpeerinfo` from `peers`; without them it lists none.
+    pub fn with_peers(mut self, peers: PeerList) -> Self {
+        self.state.peers = Some(peers);
+        self
+    }
+
+    /// Refuses requests, REST ones included, without an `Authorization: Bearer` header
+    /// carrying `token`.
+    pub fn with_auth_token(mut self, token: &str) -> Self {
+        self.state.auth_token = Some(sha256d(token.as_bytes()));
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn spawn(self) -> RpcHandle {
+        let cancel = self.state.shutdown.clone();
+        let router = Router::new()
+            .route("/", post(handle_request))
+            .merge(rest::routes())
+            .merge(ws::routes())
+            .merge(metrics::routes())
+            .layer(middleware::from_fn_with_state(
+                self.state.clone(),
+                require_auth,
+            ))
+            .with_state(self.state);
+        let server = axum::serve(self.listener, router)
+            .with_graceful_shutdown(cancel.clone().cancelled_owned());
+
+        RpcHandle {
+            task: tokio::spawn(async move { Ok(server.await?) }),
+            cancel,
+        }
+    }
+}
+
+pub struct RpcHandle {
+    cancel: CancellationToken,
+    task: JoinHandle<Result<()>>,
+}
+
+impl RpcHandle {
+    /// Stops accepting requests, waiting for those in flight.
+    pub async fn shutdown(self) -> Result<()> {
+        self.cancel.cancel();
+        self.task.await?
+    }
+}
+
+fn is_authorized(state: &RpcState, headers: &HeaderMap) -> bool {
+    let Some(expected) = state.auth_token else {
+        return true;
+    };
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| sha256d(token.as_bytes()) == expected)
+}
+
+/// Refuses unauthorized requests with a 401 before their body is looked at.
+async fn require_auth(State(state): State<RpcState>, request: Request, next: Next) -> Response {
+    if !is_authorized(&state, request.headers()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+/// Parses the body itself rather than through axum's `Json`, so malformed requests get
+/// JSON-RPC errors instead of HTTP ones.
+async fn handle_request(State(state): State<RpcState>, body: String) -> Response {
+    let request = match serde_json::from_str::<Value>(&body) {
+        Ok(request) => request,
+        Err(error) => {
+            return axum::Json(RpcResponse::new(
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, error.to_string())),
+            ))
+            .into_response();
+        }
+    };
+    let request = match serde_json::from_value::<RpcRequest>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        _ => {
+            return axum::Json(RpcResponse::new(
+                Value::Null,
+                Err(RpcError::new(INVALID_REQUEST, "Not a JSON-RPC 2.0 request")),
+            ))
+            .into_response();
+        }
+    };
+
+    let outcome = dispatch(&state, &request.method, &request.params).await;
+    axum::Json(RpcResponse::new(request.id, outcome)).into_response()
+}
+
+async fn dispatch(state: &RpcState, method: &str, params: &Value) -> Result<Value, RpcError> {
+    let node = &state.node;
+    match method {
+        "getblockcount" => to_value(node.read().await.state.chain.height()),
+        "getbestblockhash" => {
+            let tip_hash = node.read().await.tip_hash().map_err(RpcError::internal)?;
+            to_value(tip_hash.map(|hash| hash.display().to_string()))
+        }
+        "getblock" => {
+            let node = node.read().await;
+            let hash = match param::<Value>(params, 0, "hash or height")? {
+                Value::Number(height) => {
+                    let height = height
+                        .as_u64()
+                        .and_then(|height| u32::try_from(height).ok())
+                        .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Invalid height"))?;
+                    hash_at(&node, height)
+                        .map_err(RpcError::internal)?
+                        .ok_or_else(|| {
+                            RpcError::new(NOT_FOUND, format!("No block at height {height}"))
+                        })?
+                }
+                Value::String(hash) => Hash::from_hex(&hash).map_err(|error| {
+                    RpcError::new(INVALID_PARAMS, format!("Invalid hash: {error}"))
+                })?,
+                _ => {
+                    return Err(RpcError::new(
+                        INVALID_PARAMS,
+                        "Expected a block hash or height",
+                    ));
+                }
+            };
+
+            to_value(BlockInfo::find(&node, &hash).ok_or_else(|| {
+                RpcError::new(NOT_FOUND, format!("No block 0x{}", hash.display()))
+            })?)
+        }
+        "getbalance" => {
+            let address = address_param(node, params, 0).await?;
+            let node = node.read().await;
+            to_value(AddressBalance {
+                confirmed: node.get_confirmed_balance(&address),
+                spendable: node.get_balance(&address).map_err(RpcError::internal)?,
+            })
+        }
+        "getpeerinfo" => to_value(
+            state
+                .peers
+                .as_ref()
+                .map(PeerList::connected)
+                .unwrap_or_default(),
+        ),
+        "listunspent" => {
+            let address = address_param(node, params, 0).await?;
+            let unspent = node
+                .read()
+                .await
+                .list_unspent(&address)
+                .map_err(RpcError::internal)?;
+            to_value(unspent)
+        }
+        "sendrawtransaction" => {
+            let raw = param::<String>(params, 0, "hex")?;
+            let transaction = Transaction::from_hex(&raw)
+                .map_err(|error| RpcError::new(INVALID_PARAMS, format!("{error:#}")))?;
+            let id = transaction.id().map_err(RpcError::internal)?;
+
+            let (outcome, _) = node
+                .handle_message(Message::NewTransaction(transaction))
+                .await
+                .map_err(RpcError::internal)?;
+            match outcome {
+                // resubmitting is harmless, so a known transaction isn't an error
+                HandleOutcome::Accepted | HandleOutcome::Duplicate => to_value(id),
+                HandleOutcome::Rejected(reason) => Err(RpcError::rejected(&reason)),
+                outcome => Err(RpcError::new(
+                    INTERNAL_ERROR,
+                    format!("Unexpected outcome for new transaction: {outcome:?}"),
+                )),
+            }
+        }
+        "testmempoolaccept" => {
+            let raw = param::<String>(params, 0, "hex")?;
+            let transaction = Transaction::from_hex(&raw)
+                .map_err(|error| RpcError::new(INVALID_PARAMS, format!("{error:#}")))?;
+            let (outcome, fee) = node
+                .read()
+                .await
+                .state
+                .test_transaction(&transaction)
+                .map_err(RpcError::internal)?;
+
+            to_value(MemPoolAcceptance {
+                txid: transaction.id().map_err(RpcError::internal)?,
+                allowed: outcome == HandleOutcome::Accepted,
+                duplicate: outcome == HandleOutcome::Duplicate,
+                fee,
+                reject_reason: match outcome {
+                    HandleOutcome::Rejected(reason) => Some(reason),
+                    _ => None,
+                },
+            })
+        }
+        "getrawtransaction" => {
+            let id = param::<TxId>(params, 0, "txid")?;
+            let record = node
+                .get_transaction(&id)
+                .await
+                .ok_or_else(|| RpcError::new(NOT_FOUND, format!("No transaction {id}")))?;
+
+            to_value(RawTransaction::new(id, &record).map_err(RpcError::internal)?)
+        }
+        "getmempoolinfo" => {
+            let node = node.read().await;
+            let mem_pool = &node.state.mem_pool;
+            to_value(MemPoolInfo {
+                size: mem_pool.pending_transactions.len(),
+                max_transactions: mem_pool.config.max_transactions,
+                min_fee: mem_pool.config.min_fee,
+                dust_limit: mem_pool.config.dust_limit,
+            })
+        }
+        "getrawmempool" => to_value(mem_pool_entries(node).await?),
+        "getmempoolentry" => {
+            let id = param::<TxId>(params, 0, "txid")?;
+            let entries = mem_pool_entries(node).await?;
+            let entry = entries
+                .iter()
+                .find(|entry| entry.txid == id)
+                .ok_or_else(|| RpcError::new(NOT_FOUND, format!("No pending transaction {id}")))?;
+            let transaction = node
+                .get_transaction(&id)
+                .await
+                .ok_or_else(|| RpcError::new(NOT_FOUND, format!("No pending transaction {id}")))?
+                .transaction;
+
+            to_value(MemPoolEntryInfo {
+                entry: entry.clone(),
+                hex: transaction.to_hex().map_err(RpcError::internal)?,
+                ancestors: ancestors(&entries, &id),
+                descendants: descendants(&entries, &id),
+            })
+        }
+        "getmempoolstats" => to_value(MemPoolStats::new(&mem_pool_entries(node).await?)),
+        "getblocktemplate" => {
+            let longpollid = params
+                .get(0)
+                .and_then(|options| options.get("longpollid"))
+                .and_then(Value::as_str);
+            if let Some(longpollid) = longpollid {
+                wait_for_new_tip(node, longpollid).await;
+            }
+            to_value(BlockTemplate::new(&*node.read().await).map_err(RpcError::internal)?)
+        }
+        "submitblock" => {
+            let raw = param::<String>(params, 0, "hex")?;
+            let block = Block::from_hex(&raw)
+                .map_err(|error| RpcError::new(INVALID_PARAMS, format!("{error:#}")))?;
+            let hash = block.header.hash().map_err(RpcError::internal)?;
+
+            let (outcome, _) = node
+                .handle_message(Message::NewBlock(block))
+                .await
+                .map_err(RpcError::internal)?;
+            match outcome {
+                HandleOutcome::Accepted | HandleOutcome::Duplicate => {
+                    to_value(hash.display().to_string())
+                }
+                HandleOutcome::Orphaned { missing_parent } => Err(RpcError::rejected(
+                    &RejectReason::InvalidBlock(BlockValidationError::UnknownParent {
+                        parent: missing_parent,
+                    }),
+                )),
+                HandleOutcome::Rejected(reason) => Err(RpcError::rejected(&reason)),
+            }
+        }
+        "generate" => {
+            let count = param::<u32>(params, 0, "count")?;
+            let address = address_param(node, params, 1).await?;
+            let hashes = node
+                .write()
+                .await
+                .generate(count, &address)
+                .map_err(|error| RpcError::new(REJECTED, format!("{error:#}")))?;
+            to_value(
+                hashes
+                    .iter()
+                    .map(|hash| hash.display().to_string())
+                    .collect::<Vec<_>>(),
+            )
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {method}"),
+        )),
+    }
+}
+
+/// Returns once the tip is no longer `longpollid`, or after `RPC_LONGPOLL_TIMEOUT_SECS`.
+async fn wait_for_new_tip(node: &SharedNode, longpollid: &str) {
+    let mut events = node.subscribe();
+    let tip_is = |tip: Option<Hash>| tip.is_some_and(|tip| tip.display().to_string() == longpollid);
+    if !tip_is(node.tip_hash().await.ok().flatten()) {
+        return;
+    }
+
+    let _ = tokio::time::timeout(Duration::from_secs(RPC_LONGPOLL_TIMEOUT_SECS), async {
+        loop {
+            match events.recv().await {
+                Ok(NodeEvent::TipChanged { .. }) | Err(_) => return,
+                Ok(_) => {}
+            }
+        }
+    })
+    .await;
+}
+
+async fn mem_pool_entries(node: &SharedNode) -> Result<Vec<MemPoolEntry>, RpcError> {
+    let node = node.read().await;
+    node.state
+        .mem_pool
+        .entries(&node.state.utxo_set, node.state.next_height())
+        .map_err(RpcError::internal)
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|error| RpcError::internal(error.into()))
+}
+
+/// The positional parameter at `index`.
+fn param<T: DeserializeOwned>(params: &Value, index: usize, name: &str) -> Result<T, RpcError> {
+    let value = params
+        .get(index)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing parameter {name}")))?;
+    serde_json::from_value(value.clone())
+        .map_err(|error| RpcError::new(INVALID_PARAMS, format!("Invalid {name}: {error}")))
+}
+
+async fn address_param(
+    node: &SharedNode,
+    params: &Value,
+    index: usize,
+) -> Result<Address, RpcError> {
+    let address = param::<String>(params, index, "address")?;
+    let network = node.read().await.state.params.network;
+    Address::parse_for(&address, network)
+        .map_err(|error| RpcError::new(INVALID_PARAMS, format!("Invalid address: {error}")))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RpcClientError {
+    #[error("Can't reach the node: {0}")]
+    Network(reqwest::Error),
+    #[error("Timed out waiting for the node to respond")]
+    Timeout,
+    #[error("The node refused the RPC token")]
+    Unauthorized,
+    /// The node answered, but refused the call.
+    #[error("Node returned an error: {0}")]
+    Rpc(RpcError),
+    #[error("Invalid response from the node: {0}")]
+    InvalidResponse(String),
+}
+
+impl From<reqwest::Error> for RpcClientError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout
+        } else if error.is_decode() {
+            Self::InvalidResponse(error.to_string())
+        } else {
+            Self::Network(error)
+        }
+    }
+}
+
+/// Calls a node's JSON-RPC server over HTTP.
+pub struct RpcClient {
+    url: String,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+    auth_token: Option<String>,
+}
+
+impl RpcClient {
+    pub fn new(url: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            url: url.into(),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(RPC_CLIENT_TIMEOUT_SECS))
+                .build()?,
+            next_id: AtomicU64::new(0),
+            auth_token: None,
+        })
+    }
+
+    /// Sends `token` as a bearer token with every call.
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, RpcClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: id.into(),
+        };
+
+        let mut http_request = self.http.post(&self.url).json(&request);
+        if let Some(token) = &self.auth_token {
+            http_request = http_request.bearer_auth(token);
+        }
+        let response = http_request.send().await?;
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(RpcClientError::Unauthorized);
+        }
+        let response = response.json::<RpcResponse>().await?;
+
+        match response {
+            RpcResponse {
+                error: Some(error), ..
+            } => Err(RpcClientError::Rpc(error)),
+            RpcResponse {
+                result: Some(result),
+                ..
+            } => serde_json::from_value(result)
+                .map_err(|error| RpcClientError::InvalidResponse(error.to_string())),
+            _ => Err(RpcClientError::InvalidResponse(
+                "Neither a result nor an error".to_string(),
+            )),
+        }
+    }
+
+    pub async fn get_block_count(&self) -> Result<u32, RpcClientError> {
+        self.call("getblockcount", json!([])).await
+    }
+
+    pub async fn get_best_block_hash(&self) -> Result<Hash, RpcClientError> {
+        let hash = self.call::<String>("getbestblockhash", json!([])).await?;
+        Hash::from_hex(&hash).map_err(|error| RpcClientError::InvalidResponse(error.to_string()))
+    }
+
+    pub async fn get_block(&self, hash: &Hash) -> Result<BlockInfo, RpcClientError> {
+        self.call("getblock", json!([hash.display().to_string()]))
+            .await
+    }
+
+    pub async fn get_block_at(&self, height: u32) -> Result<BlockInfo, RpcClientError> {
+        self.call("getblock", json!([height])).await
+    }
+
+    pub async fn get_balance(&self, address: &Address) -> Result<AddressBalance, RpcClientError> {
+        self.call("getbalance", json!([address.to_string()])).await
+    }
+
+    pub async fn get_peer_info(&self) -> Result<Vec<PeerInfo>, RpcClientError> {
+        self.call("getpeerinfo", json!([])).await
+    }
+
+    pub async fn list_unspent(
+        &self,
+        address: &Address,
+    ) -> Result<Vec<UnspentOutput>, RpcClientError> {
+        self.call("listunspent", json!([address.to_string()])).await
+    }
+
+    pub async fn send_raw_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TxId, RpcClientError> {
+        let raw = transaction
+            .to_hex()
+            .map_err(|error| RpcClientError::InvalidResponse(error.to_string()))?;
+        self.call("sendrawtransaction", json!([raw])).await
+    }
+
+    /// Whether the node would accept `transaction`, without submitting it.
+    pub async fn test_mem_pool_accept(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<MemPoolAcceptance, RpcClientError> {
+        let raw = transaction
+            .to_hex()
+            .map_err(|error| RpcClientError::InvalidResponse(error.to_string()))?;
+        self.call("testmempoolaccept", json!([raw])).await
+    }
+
+    pub async fn get_raw_transaction(&self, id: &TxId) -> Result<RawTransaction, RpcClientError> {
+        self.call("getrawtransaction", json!([id])).await
+    }
+
+    pub async fn get_mem_pool_info(&self) -> Result<MemPoolInfo, RpcClientError> {
+        self.call("getmempoolinfo", json!([])).await
+    }
+
+    pub async fn get_raw_mem_pool(&self) -> Result<Vec<MemPoolEntry>, RpcClientError> {
+        self.call("getrawmempool", json!([])).await
+    }
+
+    pub async fn get_mem_pool_entry(&self, id: &TxId) -> Result<MemPoolEntryInfo, RpcClientError> {
+        self.call("getmempoolentry", json!([id])).await
+    }
+
+    pub async fn get_mem_pool_stats(&self) -> Result<MemPoolStats, RpcClientError> {
+        self.call("getmempoolstats", json!([])).await
+    }
+
+    /// With a `longpollid` from an earlier template, waits for the tip to move on first.
+    pub async fn get_block_template(
+        &self,
+        longpollid: Option<&str>,
+    ) -> Result<BlockTemplate, RpcClientError> {
+        let params = match longpollid {
+            Some(longpollid) => json!([{ "longpollid": longpollid }]),
+            None => json!([]),
+        };
+        self.call("getblocktemplate", params).await
+    }
+
+    pub async fn submit_block(&self, block: &Block) -> Result<Hash, RpcClientError> {
+        let raw = block
+            .to_hex()
+            .map_err(|error| RpcClientError::InvalidResponse(error.to_string()))?;
+        let hash = self.call::<String>("submitblock", json!([raw])).await?;
+        Hash::from_hex(&hash).map_err(|error| RpcClientError::InvalidResponse(error.to_string()))
+    }
+
+    /// Has a regtest node mine `count` blocks paying `address`, returning their hashes.
+    pub async fn generate(
+        &self,
+        count: u32,
+        address: &Address,
+    ) -> Result<Vec<Hash>, RpcClientError> {
+        let hashes = self
+            .call::<Vec<String>>("generate", json!([count, address.to_string()]))
+            .await?;
+        hashes
+            .iter()
+            .map(|hash| {
+                Hash::from_hex(hash)
+                    .map_err(|error| RpcClientError::InvalidResponse(error.to_string()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+
+    #[tokio::test]
+    async fn test_rpc_round_trip() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let keypair = KeyPair::generate();
+        let mut node = Node::new(
+            NodeConfig::builder(keypair.clone())
+                .data_dir(data_dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap();
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+        let address = node.address();
+
+        let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+            .await
+            .unwrap();
+        let client = RpcClient::new(format!("http://{}", server.local_addr().unwrap())).unwrap();
+        let handle = server.spawn();
+
+        let unspent = client.list_unspent(&address).await.unwrap();
+        assert_eq!(unspent.len(), 1);
+
+        let transaction = build_payment(
+            &keypair,
+            Network::Regtest,
+            unspent,
+            address.clone(),
+            10,
+            0,
+            1,
+        )
+        .unwrap();
+        let id = client.send_raw_transaction(&transaction).await.unwrap();
+        assert_eq!(id, transaction.id().unwrap());
+        assert_eq!(
+            client.get_raw_transaction(&id).await.unwrap().confirmations,
+            0
+        );
+        assert_eq!(client.get_mem_pool_info().await.unwrap().size, 1);
+        assert_eq!(client.get_raw_mem_pool().await.unwrap()[0].txid, id);
+        assert_eq!(client.get_mem_pool_stats().await.unwrap().count, 1);
+        let entry = client.get_mem_pool_entry(&id).await.unwrap();
+        assert_eq!(entry.hex, transaction.to_hex().unwrap());
+        assert!(entry.ancestors.is_empty());
+
+        assert!(matches!(
+            client.call::<Value>("getnothing", json!([])).await,
+            Err(RpcClientError::Rpc(RpcError {
+                code: METHOD_NOT_FOUND,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            client.call::<Value>("listunspent", json!(["nope"])).await,
+            Err(RpcClientError::Rpc(RpcError {
+                code: INVALID_PARAMS,
+                ..
+            }))
+        ));
+
+        handle.shutdown().await.unwrap();
+    }
+}
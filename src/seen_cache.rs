@@ -0,0 +1,120 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    constants::{SEEN_CACHE_CAPACITY, SEEN_CACHE_TTL_SECS},
+    crypto::Hash,
+};
+
+/// Hashes of the gossip payloads, blocks, and transactions we've handled or requested
+/// lately, so copies from other peers are dropped before they're decoded or requested
+/// again. Entries expire after `ttl`, letting a transaction evicted from the mempool be
+/// accepted when it's broadcast again, and the oldest are forgotten past `capacity`.
+#[derive(Debug, Clone)]
+pub struct SeenCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<Hash, Instant>,
+    order: VecDeque<(Hash, Instant)>,
+}
+
+impl Default for SeenCache {
+    fn default() -> Self {
+        Self::new(
+            SEEN_CACHE_CAPACITY,
+            Duration::from_secs(SEEN_CACHE_TTL_SECS),
+        )
+    }
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns whether `hash` wasn't seen within the ttl.
+    pub fn insert(&mut self, hash: Hash, now: Instant) -> bool {
+        self.expire(now);
+
+        if self.entries.contains_key(&hash) {
+            return false;
+        }
+
+        self.entries.insert(hash, now);
+        self.order.push_back((hash, now));
+
+        while self.order.len() > self.capacity {
+            if let Some((oldest, _)) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    /// How long ago `hash` was first seen, if it hasn't expired.
+    pub fn age(&self, hash: &Hash, now: Instant) -> Option<Duration> {
+        let seen = self.entries.get(hash)?;
+        let age = now.saturating_duration_since(*seen);
+        (age < self.ttl).then_some(age)
+    }
+
+    pub fn contains(&self, hash: &Hash, now: Instant) -> bool {
+        self.age(hash, now).is_some()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((hash, seen)) = self.order.front()
+            && now.saturating_duration_since(*seen) >= self.ttl
+        {
+            self.entries.remove(hash);
+            self.order.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry_and_capacity() {
+        let start = Instant::now();
+        let mut seen = SeenCache::new(3, Duration::from_secs(10));
+
+        assert!(seen.insert([1; 32], start));
+        assert!(!seen.insert([1; 32], start + Duration::from_secs(9)));
+        assert_eq!(
+            seen.age(&[1; 32], start + Duration::from_secs(9)),
+            Some(Duration::from_secs(9))
+        );
+
+        // a copy arriving after the ttl is new again
+        let later = start + Duration::from_secs(10);
+        assert!(!seen.contains(&[1; 32], later));
+        assert!(seen.insert([1; 32], later));
+
+        // past capacity the oldest goes first
+        for byte in 2..=4 {
+            assert!(seen.insert([byte; 32], later));
+        }
+        assert_eq!(seen.len(), 3);
+        assert!(!seen.contains(&[1; 32], later));
+        assert!(seen.contains(&[2; 32], later));
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, broadcast};
+
+use crate::{
+    crypto::{Address, Hash},
+    metrics::NodeMetrics,
+    node::{EventBus, Message, Node, NodeEvent, SendError},
+    outcome::HandleOutcome,
+    transaction::TxId,
+    tx_index::TransactionRecord,
+};
+
+/// A cloneable handle to a `Node` for the tasks and threads that drive it concurrently.
+///
+/// Every method takes the lock for a single synchronous `Node` call and releases it before
+/// returning, so no guard is ever held across an await point or a mining search.
+#[derive(Clone)]
+pub struct SharedNode {
+    node: Arc<RwLock<Node>>,
+    events: EventBus,
+}
+
+impl SharedNode {
+    pub fn new(node: Node) -> Self {
+        Self {
+            events: node.state.events.clone(),
+            node: Arc::new(RwLock::new(node)),
+        }
+    }
+
+    pub async fn read(&self) -> RwLockReadGuard<'_, Node> {
+        self.node.read().await
+    }
+
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Node> {
+        self.node.write().await
+    }
+
+    /// For threads outside the async runtime, such as the miner. Panics if called from
+    /// within an async task.
+    pub fn blocking_read(&self) -> RwLockReadGuard<'_, Node> {
+        self.node.blocking_read()
+    }
+
+    /// See `blocking_read`.
+    pub fn blocking_write(&self) -> RwLockWriteGuard<'_, Node> {
+        self.node.blocking_write()
+    }
+
+    /// Subscribing doesn't need the node lock.
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
+    pub async fn handle_message(&self, message: Message) -> Result<(HandleOutcome, Vec<Message>)> {
+        self.write().await.handle_message(message)
+    }
+
+    pub async fn send(&self, to: Address, amount: u64, fee: u64) -> Result<TxId, SendError> {
+        self.write().await.send(to, amount, fee)
+    }
+
+    pub async fn flush(&self) -> Result<()> {
+        self.read().await.flush()
+    }
+
+    pub async fn metrics(&self) -> Result<NodeMetrics> {
+        self.read().await.metrics()
+    }
+
+    pub async fn get_balance(&self, address: &Address) -> Result<u64> {
+        self.read().await.get_balance(address)
+    }
+
+    pub async fn get_confirmed_balance(&self, address: &Address) -> u64 {
+        self.read().await.get_confirmed_balance(address)
+    }
+
+    pub async fn tip_hash(&self) -> Result<Option<Hash>> {
+        self.read().await.tip_hash()
+    }
+
+    pub async fn get_transaction(&self, id: &TxId) -> Option<TransactionRecord> {
+        self.read().await.get_transaction(id)
+    }
+
+    pub async fn request_headers(&self) -> Result<Message> {
+        self.read().await.request_headers()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::constants::*;
+    use crate::crypto::*;
+    use crate::params::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_access() {
+        let keypair = KeyPair::generate();
+        let address = Address::from_public_key_for(&keypair.public_key, Network::Regtest);
+
+        // build the chain up front on a separate node, so the tasks below only submit
+        let source_dir = tempfile::tempdir().unwrap();
+        let mut source = Node::new(
+            NodeConfig::builder(keypair.clone())
+                .data_dir(source_dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap();
+
+        let mut blocks = Vec::new();
+
+        while blocks.len() < 20 {
+            let block = source.create_block().unwrap();
+            source
+                .handle_message(Message::NewBlock(block.clone()))
+                .unwrap();
+            blocks.push(block);
+        }
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let node = SharedNode::new(
+            Node::new(
+                NodeConfig::builder(keypair)
+                    .data_dir(data_dir.path())
+                    .network(NetworkParams::regtest())
+                    .build(),
+            )
+            .unwrap(),
+        );
+        let mut events = node.subscribe();
+
+        // each submitter delivers every block, so most arrive as duplicates or orphans
+        let submitters = (0..4).map(|offset| {
+            let node = node.clone();
+            let mut blocks = blocks.clone();
+            blocks.rotate_left(offset * 5);
+
+            tokio::spawn(async move {
+                for _ in 0..blocks.len() {
+                    for block in &blocks {
+                        node.handle_message(Message::NewBlock(block.clone()))
+                            .await
+                            .unwrap();
+                    }
+                }
+            })
+        });
+
+        let readers = (0..4).map(|_| {
+            let node = node.clone();
+            let address = address.clone();
+
+            tokio::spawn(async move {
+                for _ in 0..50 {
+                    let balance = node.get_balance(&address).await.unwrap();
+                    let metrics = node.metrics().await.unwrap();
+                    assert!(balance <= metrics.utxo_total_value);
+                    tokio::task::yield_now().await;
+                }
+            })
+        });
+
+        for task in submitters.chain(readers).collect::<Vec<_>>() {
+            task.await.unwrap();
+        }
+
+        let reward = GENESIS_BLOCK_REWARD as u64;
+        assert_eq!(node.metrics().await.unwrap().chain_height, 21);
+        assert_eq!(node.get_balance(&address).await.unwrap(), 20 * reward);
+        assert_eq!(
+            node.tip_hash().await.unwrap(),
+            Some(blocks.last().unwrap().header.hash().unwrap())
+        );
+        assert!(node.read().await.state.check_consistency().is_ok());
+
+        let connected = std::iter::from_fn(|| events.try_recv().ok())
+            .filter(|event| matches!(event, NodeEvent::BlockConnected { .. }))
+            .count();
+        assert_eq!(connected, 20);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fmt, str::FromStr};
+
+use base64::Engine;
+
+use secp256k1::PublicKey;
+
+use crate::crypto::{
+    Address, Hash, KeyPair, RecoverableSig, SignatureExt, from_hex, sha256d, to_hex,
+};
+
+/// Prefixed to every signed message, so a message signature can't be passed off as a
+/// signature over a transaction or anything else signed with the same key.
+const MESSAGE_MAGIC: &str = "Tiny Crypto Signed Message:\n";
+
+/// The bytes a message signature covers: the magic and the message, each preceded by its
+/// length, as in Bitcoin's signed messages.
+fn message_bytes(message: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(MESSAGE_MAGIC.len() + message.len() + 16);
+    for part in [MESSAGE_MAGIC.as_bytes(), message] {
+        bytes.extend_from_slice(&(part.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(part);
+    }
+    bytes
+}
+
+/// The digest a signature over `message` is made over.
+pub fn message_digest(message: impl AsRef<[u8]>) -> Hash {
+    sha256d(&message_bytes(message.as_ref()))
+}
+
+/// A recoverable signature over a message, text or binary, proving control of an address without
+/// revealing its public key up front. Written as base64, and parsed from base64 or hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageSignature(pub RecoverableSig);
+
+impl KeyPair {
+    pub fn sign_message(&self, message: impl AsRef<[u8]>) -> MessageSignature {
+        MessageSignature(self.sign_recoverable(&message_bytes(message.as_ref())))
+    }
+}
+
+impl MessageSignature {
+    /// Whether `message` was signed by the key behind `address`.
+    pub fn verify(&self, message: impl AsRef<[u8]>, address: &Address) -> bool {
+        self.0
+            .verify_against_address(&message_bytes(message.as_ref()), address)
+    }
+
+    /// Whether `message` was signed by `public_key`. The signature is checked directly,
+    /// not through recovery.
+    pub fn verify_public_key(&self, message: impl AsRef<[u8]>, public_key: &PublicKey) -> bool {
+        self.0
+            .to_standard()
+            .verify(&message_bytes(message.as_ref()), public_key)
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(self.0.to_bytes())
+    }
+}
+
+impl fmt::Display for MessageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(self.0.to_bytes());
+        f.write_str(&encoded)
+    }
+}
+
+impl FromStr for MessageSignature {
+    type Err = anyhow::Error;
+
+    fn from_str(encoded: &str) -> Result<Self, Self::Err> {
+        let encoded = encoded.trim();
+        // 65 bytes are 130 hex digits but only 88 base64 characters
+        let bytes = match from_hex(encoded) {
+            Ok(bytes) if encoded.len() >= 130 => bytes,
+            _ => base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|_| anyhow::anyhow!("Signature is neither base64 nor hex"))?,
+        };
+        let signature = RecoverableSig::from_bytes(&bytes)
+            .map_err(|error| anyhow::anyhow!("Malformed signature: {error}"))?;
+        Ok(Self(signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::*;
+    use crate::transaction::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key_pair = KeyPair::from_seed(&[1; 32]).unwrap();
+        let address = Address::from_public_key(&key_pair.public_key);
+        let other = Address::from_public_key(&KeyPair::generate().public_key);
+
+        let signature = key_pair.sign_message("I control this address");
+        assert!(signature.verify("I control this address", &address));
+        assert!(!signature.verify("I control this address.", &address));
+        assert!(!signature.verify("I control this address", &other));
+
+        let encoded = signature.to_string();
+        assert_eq!(encoded.parse::<MessageSignature>().unwrap(), signature);
+        assert_eq!(
+            signature.to_hex().parse::<MessageSignature>().unwrap(),
+            signature
+        );
+        assert!(signature.verify_public_key("I control this address", &key_pair.public_key));
+        assert!(!signature.verify_public_key("I control this address.", &key_pair.public_key));
+        assert!("not base64!".parse::<MessageSignature>().is_err());
+        assert!("AAAA".parse::<MessageSignature>().is_err());
+
+        // binary messages are signed as they are
+        let binary = [0xff, 0x00, 0xfe];
+        assert!(key_pair.sign_message(binary).verify(binary, &address));
+        assert_ne!(message_digest(binary), message_digest([0xff, 0x00]));
+    }
+
+    #[test]
+    fn test_replay_as_transaction_signature() {
+        let key_pair = KeyPair::from_seed(&[1; 32]).unwrap();
+        let body = TransactionBody {
+            input: TransactionInput::Coinbase { block_height: 2 },
+            outputs: vec![TransactionOutput {
+                value: 50,
+                address: Address::from_public_key(&KeyPair::generate().public_key),
+            }],
+        };
+
+        // even a "message" that is exactly what a transaction signs is hashed differently
+        let body_bytes = body.as_bytes().unwrap();
+        let message = String::from_utf8_lossy(&body_bytes).into_owned();
+        for message in [message, "challenge".to_string()] {
+            let signature = key_pair.sign_message(&message);
+            let replayed = Transaction {
+                body: body.clone(),
+                signing_info: SigningInfo {
+                    signature: signature.0.to_standard(),
+                    public_key: key_pair.public_key,
+                },
+            };
+            assert!(!replayed.verify_signature().unwrap());
+            assert!(
+                !signature
+                    .0
+                    .to_standard()
+                    .verify(message.as_bytes(), &key_pair.public_key)
+            );
+        }
+
+        assert!(body.into_tx(&key_pair).unwrap().verify_signature().unwrap());
+    }
+}
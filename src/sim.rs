@@ -0,0 +1,495 @@
+//! A deterministic network of in-memory nodes, for testing propagation, reorgs, and sync
+//! without sockets. Messages cross a virtual bus where each link has its own latency,
+//! jitter, and drop probability, and the network can be partitioned and healed. Every
+//! random choice comes from one seeded generator and time only moves as messages are
+//! delivered, so a seed reproduces a run exactly.
+//!
+//! Nodes relay as the p2p service does: a new tip or transaction is announced to every
+//! peer, replies go back to the sender, and a block whose parent is missing has its
+//! sender asked for headers. Healing a partition has the nodes that were cut off ask
+//! each other for headers, as reconnecting peers do.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use anyhow::Result;
+use tokio::sync::broadcast;
+
+use crate::{
+    config::NodeConfig,
+    constants::{SIM_DEFAULT_JITTER_MS, SIM_DEFAULT_LATENCY_MS, SIM_MAX_DELIVERIES},
+    crypto::{Hash, HashExt},
+    fixtures,
+    node::{Message, Node, NodeEvent},
+    outcome::HandleOutcome,
+    params::NetworkParams,
+    transaction::Transaction,
+};
+
+/// A node's index in `Network::nodes`.
+pub type NodeId = usize;
+
+/// How messages travel over a link. Each takes `latency_ms` plus up to `jitter_ms` more,
+/// so with any jitter a message can overtake one sent before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConfig {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    /// The chance, from 0 to 1, that a message is lost.
+    pub drop_probability: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency_ms: SIM_DEFAULT_LATENCY_MS,
+            jitter_ms: SIM_DEFAULT_JITTER_MS,
+            drop_probability: 0.0,
+        }
+    }
+}
+
+/// What became of the messages sent so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimStats {
+    pub delivered: usize,
+    /// Lost to a link's drop probability or to a partition.
+    pub dropped: usize,
+    /// Blocks that arrived before their parent.
+    pub orphaned: usize,
+    pub rejected: usize,
+}
+
+/// splitmix64: small, and the same sequence on every platform and toolchain.
+#[derive(Debug, Clone)]
+struct SimRng(u64);
+
+impl SimRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..=max`.
+    fn up_to(&mut self, max: u64) -> u64 {
+        match max.checked_add(1) {
+            Some(bound) => self.next_u64() % bound,
+            None => self.next_u64(),
+        }
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        // the top 53 bits, as a float in [0, 1)
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// A message in flight, ordered so the heap pops the earliest delivery first, ties going
+/// to the message sent first.
+#[derive(Debug)]
+struct Envelope {
+    deliver_at: u64,
+    sequence: u64,
+    from: NodeId,
+    to: NodeId,
+    message: Message,
+}
+
+impl Envelope {
+    fn key(&self) -> (u64, u64) {
+        (self.deliver_at, self.sequence)
+    }
+}
+
+impl PartialEq for Envelope {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Envelope {}
+
+impl PartialOrd for Envelope {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Envelope {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+/// Regtest nodes, each linked to every other, exchanging messages in virtual time.
+pub struct Network {
+    pub nodes: Vec<Node>,
+    pub stats: SimStats,
+    events: Vec<broadcast::Receiver<NodeEvent>>,
+    default_link: LinkConfig,
+    links: HashMap<(NodeId, NodeId), LinkConfig>,
+    /// The side of the partition each node is on. Only nodes on the same side reach each
+    /// other.
+    sides: Vec<usize>,
+    queue: BinaryHeap<Envelope>,
+    now_ms: u64,
+    sequence: u64,
+    rng: SimRng,
+}
+
+impl Network {
+    /// `size` nodes with keys and link randomness derived from `seed`, all at genesis.
+    pub fn new(size: usize, seed: u64) -> Result<Self> {
+        let nodes = (0..size as u64)
+            .map(|id| {
+                let key_pair = fixtures::key_pair(seed.wrapping_add(id));
+                Node::in_memory(
+                    NodeConfig::builder(key_pair)
+                        .network(NetworkParams::regtest())
+                        .build(),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            events: nodes.iter().map(Node::subscribe).collect(),
+            stats: SimStats::default(),
+            default_link: LinkConfig::default(),
+            links: HashMap::new(),
+            sides: vec![0; size],
+            queue: BinaryHeap::new(),
+            now_ms: 0,
+            sequence: 0,
+            rng: SimRng(seed),
+            nodes,
+        })
+    }
+
+    /// Virtual milliseconds since the network started.
+    pub fn now_ms(&self) -> u64 {
+        self.now_ms
+    }
+
+    /// Sets the link between `a` and `b`, both ways, for messages sent from now on.
+    pub fn set_link(&mut self, a: NodeId, b: NodeId, link: LinkConfig) {
+        self.links.insert((a, b), link);
+        self.links.insert((b, a), link);
+    }
+
+    /// Sets every link, replacing any set with `set_link`.
+    pub fn set_all_links(&mut self, link: LinkConfig) {
+        self.default_link = link;
+        self.links.clear();
+    }
+
+    /// Splits the network so only nodes in the same group reach each other. Nodes left
+    /// out of every group form one more. Messages already in flight across the split
+    /// are lost.
+    pub fn partition(&mut self, groups: &[&[NodeId]]) {
+        self.sides = vec![0; self.nodes.len()];
+        for (side, group) in groups.iter().enumerate() {
+            for &id in *group {
+                self.sides[id] = side + 1;
+            }
+        }
+    }
+
+    /// Rejoins the network, each node asking every one it was cut off from for headers.
+    pub fn heal(&mut self) -> Result<()> {
+        let sides = std::mem::replace(&mut self.sides, vec![0; self.nodes.len()]);
+        for from in 0..self.nodes.len() {
+            for to in 0..self.nodes.len() {
+                if sides[from] != sides[to] {
+                    let request = self.nodes[from].request_headers()?;
+                    self.send(from, to, request);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reachable(&self, a: NodeId, b: NodeId) -> bool {
+        self.sides[a] == self.sides[b]
+    }
+
+    /// Mines (or signs) a block on `id`'s tip, stamped with the virtual clock, and announces it.
+    pub fn mine(&mut self, id: NodeId) -> Result<Hash> {
+        let node = &self.nodes[id];
+        let timestamp = node.state.params.genesis_timestamp + (self.now_ms / 1000) as u32;
+        let mut block = node.block_template_at(timestamp)?;
+        block.seal(&node.config.keypair, &node.state.params)?;
+
+        let hash = block.header.hash()?;
+        match self.handle(id, None, Message::NewBlock(block))? {
+            HandleOutcome::Accepted => Ok(hash),
+            outcome => anyhow::bail!("Node {id} didn't accept its own block: {outcome:?}"),
+        }
+    }
+
+    /// Submits `transaction` to `id`, which announces it if accepted.
+    pub fn submit(&mut self, id: NodeId, transaction: Transaction) -> Result<HandleOutcome> {
+        self.handle(id, None, Message::NewTransaction(transaction))
+    }
+
+    /// Delivers the next message, moving the clock to its arrival. `false` once nothing
+    /// is in flight.
+    pub fn step(&mut self) -> Result<bool> {
+        let Some(envelope) = self.queue.pop() else {
+            return Ok(false);
+        };
+        self.now_ms = self.now_ms.max(envelope.deliver_at);
+
+        if !self.reachable(envelope.from, envelope.to) {
+            self.stats.dropped += 1;
+            return Ok(true);
+        }
+        self.stats.delivered += 1;
+        self.handle(envelope.to, Some(envelope.from), envelope.message)?;
+        Ok(true)
+    }
+
+    /// Delivers messages until none are left in flight, failing if they're still flowing
+    /// after `SIM_MAX_DELIVERIES`.
+    pub fn run_until_idle(&mut self) -> Result<()> {
+        for _ in 0..SIM_MAX_DELIVERIES {
+            if !self.step()? {
+                return Ok(());
+            }
+        }
+        anyhow::bail!("Messages still in flight after {SIM_MAX_DELIVERIES} deliveries")
+    }
+
+    /// Delivers the messages due in the next `ms` and moves the clock past them.
+    pub fn advance(&mut self, ms: u64) -> Result<()> {
+        let until = self.now_ms + ms;
+        while self
+            .queue
+            .peek()
+            .is_some_and(|envelope| envelope.deliver_at <= until)
+        {
+            self.step()?;
+        }
+        self.now_ms = until;
+        Ok(())
+    }
+
+    pub fn tips(&self) -> Result<Vec<Option<Hash>>> {
+        self.nodes.iter().map(Node::tip_hash).collect()
+    }
+
+    pub fn utxo_commitments(&self) -> Result<Vec<Hash>> {
+        self.nodes
+            .iter()
+            .map(|node| node.state.utxo_set.commitment())
+            .collect()
+    }
+
+    /// Whether every node has the same tip and UTXO set.
+    pub fn converged(&self) -> Result<bool> {
+        let tips = self.tips()?;
+        let commitments = self.utxo_commitments()?;
+        Ok(tips.windows(2).all(|pair| pair[0] == pair[1])
+            && commitments.windows(2).all(|pair| pair[0] == pair[1]))
+    }
+
+    /// Panics, listing each node's tip and UTXO commitment, unless they've converged.
+    pub fn assert_converged(&self) {
+        if self.converged().unwrap() {
+            return;
+        }
+        let tips = self.tips().unwrap();
+        let commitments = self.utxo_commitments().unwrap();
+        let nodes = tips
+            .iter()
+            .zip(&commitments)
+            .enumerate()
+            .map(|(id, (tip, commitment))| {
+                let tip = tip.map_or("none".to_string(), |tip| format!("{:#x}", tip.display()));
+                format!(
+                    "  node {id}: tip {tip}, UTXO set {:#x}",
+                    commitment.display()
+                )
+            })
+            .collect::<Vec<_>>();
+        panic!("Nodes haven't converged:\n{}", nodes.join("\n"));
+    }
+
+    fn send(&mut self, from: NodeId, to: NodeId, message: Message) {
+        let link = self
+            .links
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(self.default_link);
+        if !self.reachable(from, to) || self.rng.chance(link.drop_probability) {
+            self.stats.dropped += 1;
+            return;
+        }
+
+        let deliver_at = self.now_ms + link.latency_ms + self.rng.up_to(link.jitter_ms);
+        self.sequence += 1;
+        self.queue.push(Envelope {
+            deliver_at,
+            sequence: self.sequence,
+            from,
+            to,
+            message,
+        });
+    }
+
+    fn broadcast(&mut self, from: NodeId, message: &Message) {
+        for to in (0..self.nodes.len()).filter(|&to| to != from) {
+            self.send(from, to, message.clone());
+        }
+    }
+
+    /// Hands `message` to `id`, sending its replies back to `from`, then relays whatever
+    /// the node's events announce.
+    fn handle(
+        &mut self,
+        id: NodeId,
+        from: Option<NodeId>,
+        message: Message,
+    ) -> Result<HandleOutcome> {
+        let (outcome, replies) = self.nodes[id].handle_message(message)?;
+        match &outcome {
+            HandleOutcome::Orphaned { .. } => self.stats.orphaned += 1,
+            HandleOutcome::Rejected(_) => self.stats.rejected += 1,
+            _ => {}
+        }
+
+        if let Some(from) = from {
+            if matches!(outcome, HandleOutcome::Orphaned { .. }) {
+                let request = self.nodes[id].request_headers()?;
+                self.send(id, from, request);
+            }
+            for reply in replies {
+                self.send(id, from, reply);
+            }
+        }
+
+        while let Ok(event) = self.events[id].try_recv() {
+            let announcement = match event {
+                NodeEvent::TipChanged { new, .. } => self.nodes[id]
+                    .state
+                    .block_manager
+                    .get_block(&new)
+                    .map(|block| Message::NewBlock(block.clone())),
+                NodeEvent::TransactionAccepted(txid) => self.nodes[id]
+                    .get_transaction(&txid)
+                    .map(|record| Message::NewTransaction(record.transaction.clone())),
+                _ => None,
+            };
+            if let Some(announcement) = announcement {
+                self.broadcast(id, &announcement);
+            }
+        }
+
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_propagation() {
+        let mut network = Network::new(3, 1).unwrap();
+        let mut tip = None;
+        for _ in 0..3 {
+            tip = Some(network.mine(0).unwrap());
+            network.advance(1_000).unwrap();
+        }
+        network.run_until_idle().unwrap();
+
+        network.assert_converged();
+        assert_eq!(network.tips().unwrap(), vec![tip; 3]);
+        assert_eq!(network.nodes[2].state.chain.height(), 4);
+        assert_eq!(network.stats.orphaned, 0);
+    }
+
+    #[test]
+    fn test_partition_reorg() {
+        let mut network = Network::new(3, 2).unwrap();
+        network.mine(0).unwrap();
+        network.run_until_idle().unwrap();
+
+        network.partition(&[&[0], &[1, 2]]);
+        let stale = network.mine(0).unwrap();
+        network.advance(1_000).unwrap();
+        network.mine(1).unwrap();
+        network.advance(1_000).unwrap();
+        let winner = network.mine(2).unwrap();
+        network.run_until_idle().unwrap();
+        assert_eq!(
+            network.tips().unwrap(),
+            vec![Some(stale), Some(winner), Some(winner)]
+        );
+
+        network.heal().unwrap();
+        network.run_until_idle().unwrap();
+
+        network.assert_converged();
+        assert_eq!(network.tips().unwrap()[0], Some(winner));
+        let node = &network.nodes[0];
+        let stale = node.state.block_manager.get_block(&stale).unwrap();
+        assert!(!node.state.chain.contains_block(stale));
+    }
+
+    #[test]
+    fn test_orphan_parent_arrives_late() {
+        let mut network = Network::new(2, 3).unwrap();
+        let slow = LinkConfig {
+            latency_ms: 5_000,
+            jitter_ms: 0,
+            ..LinkConfig::default()
+        };
+        let fast = LinkConfig {
+            latency_ms: 10,
+            ..slow
+        };
+
+        network.set_link(0, 1, slow);
+        let parent = network.mine(0).unwrap();
+        network.set_link(0, 1, fast);
+        let child = network.mine(0).unwrap();
+
+        // the child gets there first, and the parent comes with the headers it prompts
+        network.advance(100).unwrap();
+        assert_eq!(network.stats.orphaned, 1);
+        assert_eq!(network.tips().unwrap(), vec![Some(child); 2]);
+        assert!(network.nodes[1].state.block_manager.contains_block(&parent));
+
+        network.run_until_idle().unwrap();
+        network.assert_converged();
+    }
+
+    #[test]
+    fn test_reproducible() {
+        let run = |seed| {
+            let mut network = Network::new(4, seed).unwrap();
+            network.set_all_links(LinkConfig {
+                latency_ms: 20,
+                jitter_ms: 200,
+                drop_probability: 0.1,
+            });
+            for round in 0..6 {
+                network.mine(round % 4).unwrap();
+                network.advance(100).unwrap();
+            }
+            network.run_until_idle().unwrap();
+            (
+                network.tips().unwrap(),
+                network.stats.clone(),
+                network.now_ms(),
+            )
+        };
+
+        assert_eq!(run(7), run(7));
+    }
+}
@@ -0,0 +1,195 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_manager::BlockManager,
+    config::NodeConfig,
+    crypto::{Hash, HashExt, serde_hex, sha256d},
+    mem_pool::MemPool,
+    node::NodeState,
+    tx_index::TxIndex,
+    utxo_set::UTXOSet,
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const UTXO_SET_FILE: &str = "utxo_set.json";
+const MEM_POOL_FILE: &str = "mem_pool.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    #[serde(with = "serde_hex")]
+    pub tip_hash: Hash,
+    pub height: u32,
+    #[serde(with = "serde_hex")]
+    pub utxo_commitment: Hash,
+    #[serde(with = "serde_hex")]
+    pub utxo_set_file_hash: Hash,
+    #[serde(with = "serde_hex")]
+    pub mem_pool_file_hash: Hash,
+}
+
+fn file_hash(path: &Path) -> Result<Hash> {
+    Ok(sha256d(&fs::read(path)?))
+}
+
+impl NodeState {
+    /// Writes the chain tip, UTXO set, and mempool to `path`, along with a manifest of
+    /// their hashes. Blocks themselves stay in the node's data dir.
+    pub fn snapshot(&self, path: &Path) -> Result<SnapshotManifest> {
+        let tail = self
+            .chain
+            .tail()
+            .ok_or(anyhow::anyhow!("Unable to snapshot: chain is empty"))?;
+
+        fs::create_dir_all(path)?;
+
+        let utxo_set_path = path.join(UTXO_SET_FILE);
+        let mem_pool_path = path.join(MEM_POOL_FILE);
+
+        self.utxo_set.save(&utxo_set_path)?;
+        self.mem_pool.save(&mem_pool_path)?;
+
+        let manifest = SnapshotManifest {
+            tip_hash: tail.header.hash()?,
+            height: tail.height,
+            utxo_commitment: self.utxo_set.commitment()?,
+            utxo_set_file_hash: file_hash(&utxo_set_path)?,
+            mem_pool_file_hash: file_hash(&mem_pool_path)?,
+        };
+
+        fs::write(
+            path.join(MANIFEST_FILE),
+            serde_json::to_vec_pretty(&manifest)?,
+        )?;
+
+        Ok(manifest)
+    }
+
+    /// Rebuilds node state from a snapshot at `path` on top of the blocks in the configured
+    /// data dir, verifying every file against the manifest.
+    pub fn restore(path: &Path, config: &NodeConfig) -> Result<Self> {
+        let manifest: SnapshotManifest =
+            serde_json::from_slice(&fs::read(path.join(MANIFEST_FILE))?)?;
+
+        let utxo_set_path = path.join(UTXO_SET_FILE);
+        let mem_pool_path = path.join(MEM_POOL_FILE);
+
+        if file_hash(&utxo_set_path)? != manifest.utxo_set_file_hash {
+            return Err(anyhow::anyhow!("Snapshot UTXO set does not match manifest"));
+        }
+
+        if file_hash(&mem_pool_path)? != manifest.mem_pool_file_hash {
+            return Err(anyhow::anyhow!("Snapshot mempool does not match manifest"));
+        }
+
+        let (block_manager, report) = BlockManager::open(config.blocks_dir())?;
+        report.log();
+
+        let tip_node =
+            block_manager
+                .nodes
+                .get(&manifest.tip_hash)
+                .cloned()
+                .ok_or(anyhow::anyhow!(
+                    "Snapshot tip 0x{} is not in the data dir",
+                    manifest.tip_hash.display()
+                ))?;
+
+        let utxo_set = UTXOSet::load(&utxo_set_path)?;
+
+        if utxo_set.commitment()? != manifest.utxo_commitment {
+            return Err(anyhow::anyhow!(
+                "Snapshot UTXO set does not match manifest commitment"
+            ));
+        }
+
+        let mut state = NodeState {
+            block_manager,
+            utxo_set,
+            mem_pool: MemPool::load(&mem_pool_path, config.mempool.clone())?,
+            params: config.network.clone(),
+            sync: config.sync.clone(),
+            ..NodeState::default()
+        };
+        state.chain.set_tail(tip_node)?;
+        state.tx_index = TxIndex::build(&state.chain, &state.block_manager)?;
+        state.metrics.refresh_utxo_set(&state.utxo_set);
+        state.metrics.refresh_mem_pool(&state.mem_pool)?;
+
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::*;
+    use crate::node::*;
+    use crate::params::*;
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let keypair = KeyPair::generate();
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = NodeConfig::builder(keypair.clone())
+            .data_dir(data_dir.path())
+            .network(NetworkParams::regtest())
+            .build();
+
+        let mut node = Node::new(config.clone()).unwrap();
+
+        while node.state.chain.height() < 10 {
+            let block = node.create_block().unwrap();
+            node.handle_message(Message::NewBlock(block)).unwrap();
+        }
+
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        node.send(recipient, 10, 1).unwrap();
+
+        let snapshot_dir = tempfile::tempdir().unwrap();
+        let manifest = node.state.snapshot(snapshot_dir.path()).unwrap();
+        assert_eq!(manifest.height, 10);
+
+        let restored = NodeState::restore(snapshot_dir.path(), &config).unwrap();
+
+        assert_eq!(restored.chain.height(), 10);
+        assert_eq!(
+            restored.chain.tail().unwrap().header.hash().unwrap(),
+            manifest.tip_hash
+        );
+        assert_eq!(
+            restored.utxo_set.commitment().unwrap(),
+            node.state.utxo_set.commitment().unwrap()
+        );
+        assert_eq!(
+            restored
+                .mem_pool
+                .pending_transactions
+                .iter()
+                .map(|tx| tx.id().unwrap())
+                .collect::<Vec<_>>(),
+            node.state
+                .mem_pool
+                .pending_transactions
+                .iter()
+                .map(|tx| tx.id().unwrap())
+                .collect::<Vec<_>>()
+        );
+
+        // a data dir without the snapshot's blocks can't be restored onto
+        let empty_dir = tempfile::tempdir().unwrap();
+        let empty_config = NodeConfig::builder(keypair)
+            .data_dir(empty_dir.path())
+            .build();
+
+        let err = NodeState::restore(snapshot_dir.path(), &empty_config).unwrap_err();
+        assert!(err.to_string().contains("is not in the data dir"));
+
+        // tampering with the snapshot is caught by the manifest
+        fs::write(snapshot_dir.path().join(MEM_POOL_FILE), b"[]").unwrap();
+        assert!(NodeState::restore(snapshot_dir.path(), &config).is_err());
+    }
+}
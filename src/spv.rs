@@ -0,0 +1,311 @@
+//! A light client: it follows the chain by headers alone and checks payments with the
+//! merkle proofs a full node gives out (`Block::transaction_proof`), trusting a payment
+//! as far as the proof of work piled on top of it. It needs nothing from `std-node`, so
+//! it builds for wasm.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    block::{BlockHeader, TransactionProof},
+    chain::{Blockchain, BlockchainNode},
+    crypto::{Hash, HashExt},
+    header_chain::{HeaderChain, HeaderError},
+    params::NetworkParams,
+    transaction::Transaction,
+};
+
+/// Why a payment didn't verify.
+#[derive(Debug, thiserror::Error)]
+pub enum SpvError {
+    #[error("Unknown header {:#x}", .0.display())]
+    UnknownHeader(Hash),
+    /// The header is known but on a branch with less work, or was reorged out.
+    #[error("Header {:#x} is not on the best chain", .0.display())]
+    NotOnBestChain(Hash),
+    #[error("Merkle proof does not match the header's merkle root")]
+    InvalidProof,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// A verified payment's place in the best chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PaymentProofStatus {
+    pub height: u32,
+    /// 1 for a payment in the tip.
+    pub confirmations: u32,
+}
+
+/// Every header from the genesis block on, and the chain of them with the most work.
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    headers: HeaderChain,
+    chain: Blockchain,
+}
+
+impl LightClient {
+    /// A client knowing only the genesis block of `params`.
+    pub fn new(params: &NetworkParams) -> Result<Self> {
+        let mut genesis = BlockchainNode::new(&params.genesis_block()?);
+        genesis.set_previous(None)?;
+        let genesis = Arc::new(genesis);
+        let mut chain = Blockchain::default();
+        chain.set_tail(genesis.clone())?;
+
+        Ok(Self {
+            headers: HeaderChain::with_root(genesis)?,
+            chain,
+        })
+    }
+
+    /// Adds `headers`, from any source, each following the one before it or a header
+    /// already known, switching to the chain with the most work. Headers ahead of the
+    /// first invalid one are kept.
+    pub fn add_headers(&mut self, headers: &[(u32, BlockHeader)]) -> Result<(), HeaderError> {
+        let mut buf = Vec::new();
+        let result = headers.iter().try_for_each(|(height, header)| {
+            let hash = header.hash_with_buf(&mut buf)?;
+            if self.headers.get(&hash).is_some() {
+                return Ok(());
+            }
+
+            let parent = self
+                .headers
+                .get(&header.previous_block_hash)
+                .cloned()
+                .ok_or(HeaderError::UnknownParent(header.previous_block_hash))?;
+            self.headers.insert(hash, *height, header, parent)?;
+            Ok(())
+        });
+
+        if let Some(best) = self.headers.best()
+            && !self.chain.contains_node(best)
+        {
+            self.chain.set_tail(best.clone())?;
+        }
+
+        result
+    }
+
+    pub fn tip(&self) -> Option<Arc<BlockchainNode>> {
+        self.chain.tail()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.chain.height()
+    }
+
+    /// Locator for the best chain, for asking a node for the headers that follow it.
+    pub fn locator(&self) -> Result<Vec<Hash>> {
+        self.chain.locator()
+    }
+
+    /// Whether the header hashing to `hash` is on the best chain.
+    pub fn is_on_best_chain(&self, hash: &Hash) -> bool {
+        self.headers
+            .get(hash)
+            .is_some_and(|node| self.chain.contains_node(node))
+    }
+
+    /// Checks `proof` puts `transaction` under the merkle root of the header hashing to
+    /// `header_hash`, in a block of `transaction_count` transactions, and that the header
+    /// is on the best chain. A payment verified before a reorg fails once its header is
+    /// reorged out.
+    pub fn verify_payment(
+        &self,
+        header_hash: &Hash,
+        transaction: &Transaction,
+        proof: &TransactionProof,
+        transaction_count: usize,
+    ) -> Result<PaymentProofStatus, SpvError> {
+        let node = self
+            .headers
+            .get(header_hash)
+            .ok_or(SpvError::UnknownHeader(*header_hash))?;
+        if !self.chain.contains_node(node) {
+            return Err(SpvError::NotOnBestChain(*header_hash));
+        }
+
+        if !proof.verify(
+            &node.header.merkle_root,
+            &transaction.id()?,
+            transaction_count,
+        ) {
+            return Err(SpvError::InvalidProof);
+        }
+
+        Ok(PaymentProofStatus {
+            height: node.height,
+            confirmations: self.chain.height() - node.height + 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::Block,
+        crypto::{Address, KeyPair},
+        fixtures,
+        node::Node,
+        params::Network,
+    };
+
+    fn sync(client: &mut LightClient, node: &Node) {
+        let headers = node
+            .state
+            .headers_after(&client.locator().unwrap(), None, 100)
+            .unwrap();
+        client.add_headers(&headers).unwrap();
+    }
+
+    /// A node that's paid someone in its third block, and that block.
+    fn paying_node(seed: u64) -> (Node, Block, Transaction) {
+        let mut node = fixtures::regtest_node(seed).unwrap();
+        fixtures::mine_block(&mut node).unwrap();
+        let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        let id = node.send(to, 10, 1).unwrap();
+        let block = fixtures::mine_block(&mut node).unwrap();
+        let payment = node.get_transaction(&id).unwrap().transaction;
+        (node, block, payment)
+    }
+
+    #[test]
+    fn test_verify_payment() {
+        let (mut node, block, payment) = paying_node(1);
+        fixtures::mine_block(&mut node).unwrap();
+
+        let mut client = LightClient::new(&NetworkParams::regtest()).unwrap();
+        sync(&mut client, &node);
+        assert_eq!(client.height(), 4);
+        assert_eq!(
+            client.tip().unwrap().header.hash().unwrap(),
+            node.tip_hash().unwrap().unwrap()
+        );
+
+        let hash = block.header.hash().unwrap();
+        let proof = block.transaction_proof(&payment.id().unwrap()).unwrap();
+        let count = block.transactions.len();
+        assert_eq!(
+            client
+                .verify_payment(&hash, &payment, &proof, count)
+                .unwrap(),
+            PaymentProofStatus {
+                height: 3,
+                confirmations: 2
+            }
+        );
+
+        fixtures::mine_block(&mut node).unwrap();
+        sync(&mut client, &node);
+        assert_eq!(
+            client
+                .verify_payment(&hash, &payment, &proof, count)
+                .unwrap()
+                .confirmations,
+            3
+        );
+        assert!(matches!(
+            client.verify_payment(&[7; 32], &payment, &proof, count),
+            Err(SpvError::UnknownHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_forged_proof() {
+        let (node, block, payment) = paying_node(1);
+        let mut client = LightClient::new(&NetworkParams::regtest()).unwrap();
+        sync(&mut client, &node);
+
+        let hash = block.header.hash().unwrap();
+        let count = block.transactions.len();
+        let proof = block.transaction_proof(&payment.id().unwrap()).unwrap();
+
+        // a payment the block doesn't hold, claimed with the real payment's proof
+        let mut forged = payment.clone();
+        forged.body.outputs[0].value += 1;
+        assert!(matches!(
+            client.verify_payment(&hash, &forged, &proof, count),
+            Err(SpvError::InvalidProof)
+        ));
+
+        let mut tampered = proof.clone();
+        tampered.proof.hashes[0][0] ^= 1;
+        assert!(matches!(
+            client.verify_payment(&hash, &payment, &tampered, count),
+            Err(SpvError::InvalidProof)
+        ));
+
+        // the right proof against the header before it
+        let previous = block.header.previous_block_hash;
+        assert!(matches!(
+            client.verify_payment(&previous, &payment, &proof, count),
+            Err(SpvError::InvalidProof)
+        ));
+    }
+
+    #[test]
+    fn test_reorg_demotes_payment() {
+        let (node, block, payment) = paying_node(1);
+        let mut fork = fixtures::regtest_node(2).unwrap();
+        while fork.state.chain.height() < 5 {
+            fixtures::mine_block(&mut fork).unwrap();
+        }
+
+        let mut client = LightClient::new(&NetworkParams::regtest()).unwrap();
+        sync(&mut client, &node);
+        let hash = block.header.hash().unwrap();
+        let proof = block.transaction_proof(&payment.id().unwrap()).unwrap();
+        let count = block.transactions.len();
+        assert!(
+            client
+                .verify_payment(&hash, &payment, &proof, count)
+                .is_ok()
+        );
+
+        // the fork has more work, so its headers take over
+        let fork_headers = fork.state.headers_after(&[], None, 100).unwrap();
+        client.add_headers(&fork_headers).unwrap();
+        assert_eq!(client.height(), 5);
+        assert!(!client.is_on_best_chain(&hash));
+        assert!(matches!(
+            client.verify_payment(&hash, &payment, &proof, count),
+            Err(SpvError::NotOnBestChain(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_headers_errors() {
+        let mut node = fixtures::regtest_node(1).unwrap();
+        for _ in 0..3 {
+            fixtures::mine_block(&mut node).unwrap();
+        }
+        let headers = node.state.headers_after(&[], None, 100).unwrap();
+        let mut client = LightClient::new(&NetworkParams::regtest()).unwrap();
+
+        assert!(matches!(
+            client.add_headers(&headers[2..]),
+            Err(HeaderError::UnknownParent(_))
+        ));
+
+        let mut bad_work = headers[1].clone();
+        bad_work.1.difficulty = 32;
+        assert!(matches!(
+            client.add_headers(&[bad_work]),
+            Err(HeaderError::InvalidProofOfWork)
+        ));
+
+        // the valid prefix is kept
+        let mut wrong_height = headers[2].clone();
+        wrong_height.0 += 1;
+        assert!(matches!(
+            client.add_headers(&[headers[1].clone(), wrong_height]),
+            Err(HeaderError::WrongHeight { .. })
+        ));
+        assert_eq!(client.height(), 2);
+    }
+}
@@ -1,24 +1,53 @@
+use std::{borrow::Borrow, thread};
+
 use anyhow::Result;
-use bincode::Encode;
+use bincode::{Decode, Encode};
 use secp256k1::{PublicKey, ecdsa::Signature};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    constants::{BLOCKS_PER_REWARD_HALVING, GENESIS_BLOCK_REWARD},
-    crypto::{Address, Hash, KeyPair, MerkleTree, SignatureExt, sha256d},
+    constants::{MAX_TRANSACTION_SIZE, TXID_CHUNK_SIZE},
+    crypto::{
+        Address, Hash, HashExt, KeyPair, MerkleTree, PublicKeyHash, SignatureExt, decode_config,
+        serde_hex, serde_public_key, sha256d, to_hex,
+    },
+    params::{Network, NetworkParams},
 };
 
-#[derive(Clone, Hash, Eq, PartialEq, Encode)]
-pub struct TxId(pub Hash);
+/// Why a transaction is invalid in itself, whatever outputs it spends.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[serde(tag = "kind", content = "details", rename_all = "snake_case")]
+pub enum TxValidationError {
+    #[error("Transaction signature is invalid")]
+    BadSignature,
+    #[error("Transaction output values overflow")]
+    ValueOverflow,
+    #[error("Output pays {address}, which isn't a {network} address")]
+    WrongNetwork { address: String, network: Network },
+    /// An output too small to be worth spending, which the mempool won't accept.
+    #[error("Output {index} pays {value}, below the dust limit of {dust_limit}")]
+    Dust {
+        index: usize,
+        value: u64,
+        dust_limit: u64,
+    },
+    /// A transaction larger than the mempool accepts.
+    #[error("Transaction is {size} bytes, over the limit of {max_size}")]
+    Oversized { size: usize, max_size: usize },
+}
+
+#[derive(Clone, Hash, Eq, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct TxId(#[serde(with = "serde_hex")] pub Hash);
 
 impl TxId {
     pub fn empty() -> Self {
-        Self([0; 32])
+        Self(Hash::zero())
     }
 }
 
 impl std::fmt::Display for TxId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "0x{}", hex::encode(self.0))
+        write!(f, "{:#x}", self.0.display())
     }
 }
 
@@ -28,31 +57,86 @@ impl std::fmt::Debug for TxId {
     }
 }
 
-#[derive(Debug, Clone, Encode)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub struct TransactionOutput {
     pub value: u64,
     pub address: Address,
 }
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq, Encode)]
+impl std::fmt::Display for TransactionOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} → {}", self.value, self.address)
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Encode, Decode, Serialize, Deserialize)]
 pub struct TransactionOutputReference {
     pub id: TxId,
     pub index: usize,
 }
 
-#[derive(Debug, Clone, Encode)]
+/// `txid:index`, as explorers write outpoints.
+impl std::fmt::Display for TransactionOutputReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.id, self.index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub enum TransactionInput {
-    Coinbase { block_height: u32 },
-    Reference(TransactionOutputReference),
+    Coinbase {
+        block_height: u32,
+    },
+    Reference {
+        outpoint: TransactionOutputReference,
+        /// How many blocks after the one creating `outpoint` it can first be spent in, so
+        /// with `Some(n)` it needs `n` confirmations and must be confirmed at all. Part of
+        /// the signed body.
+        relative_lock: Option<u32>,
+    },
 }
 
 impl TransactionInput {
+    /// Spends `outpoint` with no relative lock.
+    pub fn spending(outpoint: TransactionOutputReference) -> Self {
+        Self::Reference {
+            outpoint,
+            relative_lock: None,
+        }
+    }
+
     pub fn is_coinbase(&self) -> bool {
         matches!(self, TransactionInput::Coinbase { .. })
     }
+
+    /// The output spent, unless this is a coinbase.
+    pub fn outpoint(&self) -> Option<&TransactionOutputReference> {
+        match self {
+            Self::Coinbase { .. } => None,
+            Self::Reference { outpoint, .. } => Some(outpoint),
+        }
+    }
+}
+
+impl std::fmt::Display for TransactionInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Coinbase { block_height } => write!(f, "coinbase at height {block_height}"),
+            Self::Reference {
+                outpoint,
+                relative_lock: None,
+            } => write!(f, "{outpoint}"),
+            Self::Reference {
+                outpoint,
+                relative_lock: Some(blocks),
+            } => write!(f, "{outpoint}, locked for {blocks} blocks"),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Encode)]
+/// What's signed: its bincode `Encode` is hashed into the txid, so, as with `BlockHeader`,
+/// the field order is part of consensus.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
 pub struct TransactionBody {
     pub input: TransactionInput,
     pub outputs: Vec<TransactionOutput>,
@@ -75,9 +159,23 @@ impl TransactionBody {
     }
 }
 
-#[derive(Debug, Clone)]
+fn serialize_signature<S: Serializer>(signature: &Signature, s: S) -> Result<S::Ok, S::Error> {
+    serde_hex::serialize(signature.serialize_compact(), s)
+}
+
+fn deserialize_signature<'de, D: Deserializer<'de>>(d: D) -> Result<Signature, D::Error> {
+    let bytes: Vec<u8> = serde_hex::deserialize(d)?;
+    Signature::from_compact(&bytes).map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SigningInfo {
+    #[serde(
+        serialize_with = "serialize_signature",
+        deserialize_with = "deserialize_signature"
+    )]
     pub signature: Signature,
+    #[serde(with = "serde_public_key")]
     pub public_key: PublicKey,
 }
 
@@ -96,14 +194,32 @@ impl SigningInfo {
     pub fn address(&self) -> Address {
         Address::from_public_key(&self.public_key)
     }
+
+    pub fn public_key_hash(&self) -> PublicKeyHash {
+        (&self.public_key).into()
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
     pub body: TransactionBody,
     pub signing_info: SigningInfo,
 }
 
+/// The txid on the first line, then the input and each output on lines of their own,
+/// indented. There's no trailing newline.
+impl std::fmt::Display for Transaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self.id().map_err(|_| std::fmt::Error)?;
+        write!(f, "Transaction {id}")?;
+        write!(f, "\n  Input: {}", self.body.input)?;
+        for (index, output) in self.body.outputs.iter().enumerate() {
+            write!(f, "\n  Output {index}: {output}")?;
+        }
+        Ok(())
+    }
+}
+
 impl Transaction {
     pub fn id(&self) -> Result<TxId> {
         self.body.id()
@@ -125,37 +241,214 @@ impl Transaction {
         })
     }
 
-    pub fn block_reward(height: u32) -> u64 {
-        GENESIS_BLOCK_REWARD as u64 / 2u32.pow(height / BLOCKS_PER_REWARD_HALVING) as u64
+    pub fn block_reward(height: u32, params: &NetworkParams) -> u64 {
+        params.block_reward(height)
+    }
+
+    /// Binary serialization used for sizing and transport.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serde::encode_to_vec(
+            self,
+            bincode::config::standard(),
+        )?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (transaction, _) = bincode::serde::decode_from_slice(bytes, decode_config())?;
+        Ok(transaction)
+    }
+
+    /// The raw transaction, `to_bytes` as hex.
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(to_hex(self.to_bytes()?))
+    }
+
+    /// Parses a raw transaction, which must be exactly one transaction. Errors give the
+    /// offset, in hex digits, where parsing stopped.
+    pub fn from_hex(raw: &str) -> Result<Self> {
+        let raw = raw.trim();
+        let raw = raw.strip_prefix("0x").unwrap_or(raw);
+        let bytes = hex::decode(raw).map_err(|error| match error {
+            hex::FromHexError::InvalidHexCharacter { c, index } => {
+                anyhow::anyhow!("Invalid hex character {c:?} at offset {index}")
+            }
+            error => anyhow::anyhow!("Invalid hex: {error}"),
+        })?;
+
+        let mut reader = std::io::Cursor::new(&bytes);
+        let result: Result<Self, _> =
+            bincode::serde::decode_from_std_read(&mut reader, decode_config());
+        let offset = 2 * reader.position();
+
+        match result {
+            Ok(_) if offset as usize != raw.len() => Err(anyhow::anyhow!(
+                "Unexpected data after the transaction at offset {offset}"
+            )),
+            Ok(transaction) => Ok(transaction),
+            Err(bincode::error::DecodeError::Io { .. }) => Err(anyhow::anyhow!(
+                "Transaction is truncated at offset {offset}"
+            )),
+            Err(error) => Err(anyhow::anyhow!(
+                "Invalid transaction at offset {offset}: {error}"
+            )),
+        }
+    }
+
+    pub fn size(&self) -> Result<usize> {
+        Ok(self.to_bytes()?.len())
+    }
+
+    /// The outputs' total, saturating at `u64::MAX`, for display; validation uses
+    /// `checked_output_value`.
+    pub fn output_value(&self) -> u64 {
+        self.body
+            .outputs
+            .iter()
+            .fold(0, |total, output| total.saturating_add(output.value))
     }
 
-    pub fn new_coinbase(keypair: &KeyPair, block_height: u32) -> Result<Self> {
-        let value = Self::block_reward(block_height);
+    /// The outputs' total, unless it doesn't fit in a `u64`.
+    pub fn checked_output_value(&self) -> Result<u64, TxValidationError> {
+        self.body
+            .outputs
+            .iter()
+            .try_fold(0u64, |total, output| total.checked_add(output.value))
+            .ok_or(TxValidationError::ValueOverflow)
+    }
+
+    /// A coinbase claiming just the block reward of `params`.
+    pub fn new_coinbase(
+        keypair: &KeyPair,
+        block_height: u32,
+        params: &NetworkParams,
+    ) -> Result<Self> {
+        Self::new_coinbase_with_fees(keypair, block_height, 0, params)
+    }
+
+    /// A coinbase claiming the block reward of `params` plus `fees` for the address of
+    /// `keypair` on its network.
+    pub fn new_coinbase_with_fees(
+        keypair: &KeyPair,
+        block_height: u32,
+        fees: u64,
+        params: &NetworkParams,
+    ) -> Result<Self> {
+        let value = params.block_reward(block_height) + fees;
+        let network = params.network;
 
         let body = TransactionBody {
             input: TransactionInput::Coinbase { block_height },
             outputs: vec![TransactionOutput {
                 value,
-                address: Address::from_public_key(&keypair.public_key),
+                address: Address::from_public_key_for(&keypair.public_key, network),
             }],
         };
 
         body.into_tx(keypair)
     }
 
-    pub fn build_merkle_tree(transactions: &[Self]) -> Result<MerkleTree> {
-        let tx_ids = transactions
-            .iter()
-            .map(|tx| tx.id())
-            .collect::<Result<Vec<_>>>()?;
+    /// The ids of `transactions` in order, computed in chunks of at least
+    /// `TXID_CHUNK_SIZE` across up to `threads` threads. Takes the transactions owned or
+    /// in `Arc`s, as blocks hold them.
+    pub fn ids<T: Borrow<Self> + Sync>(transactions: &[T], threads: usize) -> Result<Vec<TxId>> {
+        let chunk_size = transactions
+            .len()
+            .div_ceil(threads.max(1))
+            .max(TXID_CHUNK_SIZE);
+        if transactions.len() <= chunk_size {
+            return transactions.iter().map(|tx| tx.borrow().id()).collect();
+        }
 
-        let leaves = tx_ids.iter().map(|id| id.0.as_slice()).collect();
+        thread::scope(|scope| {
+            let workers = transactions
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|tx| tx.borrow().id())
+                            .collect::<Result<Vec<_>>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut ids = Vec::with_capacity(transactions.len());
+            for worker in workers {
+                let chunk_ids = worker
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("Txid thread panicked")))?;
+                ids.extend(chunk_ids);
+            }
+            Ok(ids)
+        })
+    }
+
+    /// The ids of `transactions` across the available cores.
+    pub fn ids_parallel<T: Borrow<Self> + Sync>(transactions: &[T]) -> Result<Vec<TxId>> {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Self::ids(transactions, threads)
+    }
+
+    pub fn build_merkle_tree<T: Borrow<Self> + Sync>(transactions: &[T]) -> Result<MerkleTree> {
+        let tx_ids = Self::ids_parallel(transactions)?;
+        Ok(Self::merkle_tree_from_ids(&tx_ids))
+    }
+
+    /// The merkle tree over already computed `ids`, as `build_merkle_tree` builds it.
+    pub fn merkle_tree_from_ids(ids: &[TxId]) -> MerkleTree {
+        MerkleTree::from_hashed_leaves(ids.iter().map(|id| id.0).collect())
+    }
+
+    pub fn validate(&self) -> Result<(), TxValidationError> {
+        // a body that can't be encoded can't have been signed either
+        if !self.verify_signature().unwrap_or(false) {
+            return Err(TxValidationError::BadSignature);
+        }
+        self.checked_output_value()?;
+        Ok(())
+    }
 
-        Ok(MerkleTree::from_leaves(leaves))
+    /// Refuses outputs paying another network's addresses.
+    pub fn validate_network(&self, network: Network) -> Result<(), TxValidationError> {
+        match self
+            .body
+            .outputs
+            .iter()
+            .find(|o| !o.address.is_for(network))
+        {
+            Some(output) => Err(TxValidationError::WrongNetwork {
+                address: output.address.to_string(),
+                network,
+            }),
+            None => Ok(()),
+        }
     }
 
-    pub fn validate(&self) -> Result<()> {
-        self.verify_signature()?;
+    /// Refuses what a block may hold but the mempool won't accept: outputs below
+    /// `dust_limit`, and transactions over `MAX_TRANSACTION_SIZE`.
+    pub fn validate_standard(&self, dust_limit: u64) -> Result<(), TxValidationError> {
+        if let Some((index, output)) = self
+            .body
+            .outputs
+            .iter()
+            .enumerate()
+            .find(|(_, output)| output.value < dust_limit)
+        {
+            return Err(TxValidationError::Dust {
+                index,
+                value: output.value,
+                dust_limit,
+            });
+        }
+
+        // a transaction that can't be encoded can't be sent either
+        let size = self.size().unwrap_or(usize::MAX);
+        if size > MAX_TRANSACTION_SIZE {
+            return Err(TxValidationError::Oversized {
+                size,
+                max_size: MAX_TRANSACTION_SIZE,
+            });
+        }
         Ok(())
     }
 }
@@ -167,7 +460,7 @@ mod tests {
 
     #[test]
     fn test_transaction() {
-        let keypair_bob = KeyPair::generate();
+        let keypair_bob = KeyPair::from_seed(&[1; 32]).unwrap();
         let address_bob = Address::from_public_key(&keypair_bob.public_key);
 
         let tx_a_body = TransactionBody {
@@ -181,5 +474,130 @@ mod tests {
         let tx_a = tx_a_body.into_tx(&keypair_bob).unwrap();
 
         assert!(tx_a.verify_signature().unwrap());
+
+        let decoded = Transaction::from_bytes(&tx_a.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.id().unwrap(), tx_a.id().unwrap());
+        assert!(decoded.verify_signature().unwrap());
+        // txids are the merkle leaves as they are, so a lone transaction is its own root
+        let id = tx_a.id().unwrap();
+        let tree = Transaction::build_merkle_tree(&[tx_a]).unwrap();
+        assert_eq!(tree.root(), Some(id.0));
+        assert!(tree.proof(&[0]).unwrap().verify(&id.0, &[(0, id.0)], 1));
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let keypair = KeyPair::from_seed(&[1; 32]).unwrap();
+        let output = |value, network| TransactionOutput {
+            value,
+            address: Address::from_public_key_for(&keypair.public_key, network),
+        };
+        let tx = |outputs| {
+            TransactionBody {
+                input: TransactionInput::Coinbase { block_height: 0 },
+                outputs,
+            }
+            .into_tx(&keypair)
+            .unwrap()
+        };
+
+        let valid = tx(vec![output(100, Network::Mainnet)]);
+        assert_eq!(valid.validate(), Ok(()));
+        assert_eq!(valid.validate_network(Network::Mainnet), Ok(()));
+
+        let mut tampered = valid.clone();
+        tampered.body.outputs[0].value += 1;
+        assert_eq!(tampered.validate(), Err(TxValidationError::BadSignature));
+
+        let overflowing = tx(vec![output(u64::MAX, Network::Mainnet); 2]);
+        assert_eq!(
+            overflowing.validate(),
+            Err(TxValidationError::ValueOverflow)
+        );
+
+        let testnet = tx(vec![output(100, Network::Testnet)]);
+        assert!(matches!(
+            testnet.validate_network(Network::Mainnet),
+            Err(TxValidationError::WrongNetwork {
+                network: Network::Mainnet,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parallel_ids() {
+        let params = NetworkParams::default();
+        let key_pairs = (0..4).map(|_| KeyPair::generate()).collect::<Vec<_>>();
+        // sizes on both sides of a chunk, with keys random on every run
+        for count in [0, 1, 2, 255, 256, 257, 1_000, 2_049] {
+            let transactions = (0..count)
+                .map(|height| {
+                    Transaction::new_coinbase(
+                        &key_pairs[height % key_pairs.len()],
+                        height as u32,
+                        &params,
+                    )
+                    .unwrap()
+                })
+                .collect::<Vec<_>>();
+            let serial = transactions
+                .iter()
+                .map(Transaction::id)
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            let serial_root =
+                MerkleTree::from_hashed_leaves(serial.iter().map(|id| id.0).collect()).root();
+
+            for threads in [0, 1, 3, 8] {
+                assert_eq!(Transaction::ids(&transactions, threads).unwrap(), serial);
+            }
+            assert_eq!(
+                Transaction::build_merkle_tree(&transactions)
+                    .unwrap()
+                    .root(),
+                serial_root
+            );
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let params = NetworkParams::regtest();
+        let key_pair = crate::fixtures::key_pair(1);
+        let (funding, _) = crate::fixtures::funding(2, 100, &key_pair, &params).unwrap();
+        let spend = &crate::fixtures::spends(&funding, &key_pair, 1).unwrap()[0];
+
+        assert_eq!(
+            funding.to_string(),
+            [
+                "Transaction 0x9ea570ac6381e85fb3ff490a48040ccddb908924f7401f01851f7ad26c3fb0d3",
+                "  Input: coinbase at height 1",
+                "  Output 0: 100 → mxFcx9eEn6GWhjyCtmn1KfZW5XsCLZ68Kt",
+                "  Output 1: 100 → mxFcx9eEn6GWhjyCtmn1KfZW5XsCLZ68Kt",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            spend.to_string(),
+            [
+                "Transaction 0xb0844fc9f33c5e6a03f68b997a8abc9fe26479b66f77d8f6a7735a5551149670",
+                "  Input: 0x9ea570ac6381e85fb3ff490a48040ccddb908924f7401f01851f7ad26c3fb0d3:0",
+                "  Output 0: 99 → mxFcx9eEn6GWhjyCtmn1KfZW5XsCLZ68Kt",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            funding.output_reference(1).unwrap().to_string(),
+            "0x9ea570ac6381e85fb3ff490a48040ccddb908924f7401f01851f7ad26c3fb0d3:1"
+        );
+        let locked = TransactionInput::Reference {
+            outpoint: funding.output_reference(1).unwrap(),
+            relative_lock: Some(10),
+        };
+        assert_eq!(
+            locked.to_string(),
+            "0x9ea570ac6381e85fb3ff490a48040ccddb908924f7401f01851f7ad26c3fb0d3:1, locked for 10 blocks"
+        );
     }
 }
@@ -0,0 +1,303 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use anyhow::Result;
+
+use crate::{
+    block::Block,
+    block_manager::BlockManager,
+    chain::Blockchain,
+    constants::REJECTED_TRANSACTION_CACHE_SIZE,
+    crypto::Hash,
+    node::NodeState,
+    outcome::RejectReason,
+    transaction::{Transaction, TxId},
+    utxo_set::UtxoError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionStatus {
+    Mempool,
+    /// Included in a block on the active chain.
+    Confirmed {
+        block_hash: Hash,
+        height: u32,
+        confirmations: u32,
+    },
+    /// Refused recently, and not pending or confirmed since.
+    Rejected(RejectReason),
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionRecord {
+    pub transaction: Transaction,
+    pub status: TransactionStatus,
+}
+
+/// The block confirming each transaction on the active chain. Blocks on side branches
+/// aren't indexed, so their transactions never look confirmed.
+#[derive(Debug, Clone, Default)]
+pub struct TxIndex {
+    blocks: HashMap<TxId, Hash>,
+}
+
+impl TxIndex {
+    pub fn build(chain: &Blockchain, block_manager: &BlockManager) -> Result<Self> {
+        let mut index = Self::default();
+
+        for node in chain.nodes.values() {
+            if let Some(block) = block_manager.get_block(&node.header.hash()?) {
+                index.connect_block(block)?;
+            }
+        }
+
+        Ok(index)
+    }
+
+    pub fn connect_block(&mut self, block: &Block) -> Result<()> {
+        let hash = block.header.hash()?;
+
+        for tx in &block.transactions {
+            self.blocks.insert(tx.id()?, hash);
+        }
+
+        Ok(())
+    }
+
+    pub fn disconnect_block(&mut self, block: &Block) -> Result<()> {
+        for tx in &block.transactions {
+            self.blocks.remove(&tx.id()?);
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, id: &TxId) -> Option<&Hash> {
+        self.blocks.get(id)
+    }
+}
+
+/// The most recently rejected transactions and why, oldest evicted first.
+#[derive(Debug, Clone)]
+pub struct RejectionCache {
+    capacity: usize,
+    entries: HashMap<TxId, (Arc<Transaction>, RejectReason)>,
+    order: VecDeque<TxId>,
+}
+
+impl Default for RejectionCache {
+    fn default() -> Self {
+        Self::new(REJECTED_TRANSACTION_CACHE_SIZE)
+    }
+}
+
+impl RejectionCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn insert(&mut self, id: TxId, transaction: Arc<Transaction>, reason: RejectReason) {
+        if self
+            .entries
+            .insert(id.clone(), (transaction, reason))
+            .is_none()
+        {
+            self.order.push_back(id);
+        }
+
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: &TxId) {
+        if self.entries.remove(id).is_some() {
+            self.order.retain(|cached| cached != id);
+        }
+    }
+
+    pub fn get(&self, id: &TxId) -> Option<&(Arc<Transaction>, RejectReason)> {
+        self.entries.get(id)
+    }
+
+    /// Forgets transactions refused for a relative lock that a block at `height` might
+    /// satisfy, so they're fetched and checked again when next announced.
+    pub fn forget_unlocked(&mut self, height: u32) {
+        let unlocked = |reason: &RejectReason| match reason {
+            RejectReason::InvalidTransaction(UtxoError::Locked { spendable_at, .. }) => {
+                *spendable_at <= height
+            }
+            RejectReason::InvalidTransaction(UtxoError::LockedUnconfirmed { .. }) => true,
+            _ => false,
+        };
+        self.entries.retain(|_, (_, reason)| !unlocked(reason));
+        self.order.retain(|id| self.entries.contains_key(id));
+    }
+}
+
+impl NodeState {
+    /// Looks a transaction up on the active chain, then in the mempool, then among recent
+    /// rejections.
+    pub fn get_transaction(&self, id: &TxId) -> Option<TransactionRecord> {
+        let has_id = |tx: &&Arc<Transaction>| tx.id().is_ok_and(|tx_id| tx_id == *id);
+
+        if let Some(block_hash) = self.tx_index.get(id)
+            && let Some(block) = self.block_manager.get_block(block_hash)
+            && let Some(transaction) = block.transactions.iter().find(has_id)
+        {
+            return Some(TransactionRecord {
+                transaction: transaction.as_ref().clone(),
+                status: TransactionStatus::Confirmed {
+                    block_hash: *block_hash,
+                    height: block.height,
+                    confirmations: self.chain.height() + 1 - block.height,
+                },
+            });
+        }
+
+        if let Some(transaction) = self.mem_pool.pending_transactions.iter().find(has_id) {
+            return Some(TransactionRecord {
+                transaction: transaction.as_ref().clone(),
+                status: TransactionStatus::Mempool,
+            });
+        }
+
+        self.rejected_transactions
+            .get(id)
+            .map(|(transaction, reason)| TransactionRecord {
+                transaction: transaction.as_ref().clone(),
+                status: TransactionStatus::Rejected(reason.clone()),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::*;
+    use crate::fixtures;
+    use crate::node::*;
+    use crate::outcome::HandleOutcome;
+    use crate::params::*;
+    use crate::transaction::{
+        TransactionBody, TransactionInput, TransactionOutput, TxValidationError,
+    };
+
+    #[test]
+    fn test_transaction_status() {
+        let mut node = fixtures::regtest_node(1).unwrap();
+        let mut fork = fixtures::regtest_node(2).unwrap();
+
+        let funding = fixtures::mine_block(&mut node).unwrap();
+        fork.handle_message(Message::NewBlock(funding)).unwrap();
+
+        let recipient =
+            Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        let id = node.send(recipient, 10, 1).unwrap();
+
+        let record = node.get_transaction(&id).unwrap();
+        assert_eq!(record.transaction.id().unwrap(), id);
+        assert_eq!(record.status, TransactionStatus::Mempool);
+
+        let block_hash = fixtures::mine_block(&mut node)
+            .unwrap()
+            .header
+            .hash()
+            .unwrap();
+        fixtures::mine_block(&mut node).unwrap();
+
+        assert_eq!(
+            node.get_transaction(&id).unwrap().status,
+            TransactionStatus::Confirmed {
+                block_hash,
+                height: 3,
+                confirmations: 2
+            }
+        );
+
+        // a heavier fork without the transaction returns it to the mempool
+        let mut fork_blocks = Vec::new();
+        while fork.state.chain.height() < 5 {
+            fork_blocks.push(fixtures::mine_block(&mut fork).unwrap());
+        }
+        for block in fork_blocks {
+            node.handle_message(Message::NewBlock(block)).unwrap();
+        }
+
+        assert_eq!(node.tip_hash().unwrap(), fork.tip_hash().unwrap());
+        assert!(node.state.block_manager.contains_block(&block_hash));
+        assert_eq!(
+            node.get_transaction(&id).unwrap().status,
+            TransactionStatus::Mempool
+        );
+
+        let mut tampered = record.transaction;
+        tampered.body.outputs[0].value += 1;
+        let tampered_id = tampered.id().unwrap();
+        node.handle_message(Message::NewTransaction(tampered))
+            .unwrap();
+
+        assert!(matches!(
+            node.get_transaction(&tampered_id).unwrap().status,
+            TransactionStatus::Rejected(RejectReason::InvalidTransaction(UtxoError::Transaction(
+                TxValidationError::BadSignature
+            )))
+        ));
+        assert!(node.get_transaction(&TxId::empty()).is_none());
+    }
+
+    #[test]
+    fn test_locked_rejection_forgotten() {
+        let mut node = fixtures::regtest_node(1).unwrap();
+        let funding = fixtures::mine_block(&mut node).unwrap();
+
+        // funded at height 2 and locked for 2 blocks, so it can first go in block 4
+        let coinbase = &funding.transactions[0];
+        let locked = TransactionBody {
+            input: TransactionInput::Reference {
+                outpoint: coinbase.output_reference(0).unwrap(),
+                relative_lock: Some(2),
+            },
+            outputs: vec![TransactionOutput {
+                value: coinbase.output_value() - 1,
+                address: coinbase.body.outputs[0].address.clone(),
+            }],
+        }
+        .into_tx(&node.config.keypair)
+        .unwrap();
+        let id = locked.id().unwrap();
+
+        let (outcome, _) = node
+            .handle_message(Message::NewTransaction(locked.clone()))
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            HandleOutcome::Rejected(RejectReason::InvalidTransaction(UtxoError::Locked {
+                spendable_at: 4,
+                ..
+            }))
+        ));
+        assert!(node.get_transaction(&id).is_some());
+
+        fixtures::mine_block(&mut node).unwrap();
+        assert!(node.get_transaction(&id).is_none());
+
+        let (outcome, _) = node
+            .handle_message(Message::NewTransaction(locked))
+            .unwrap();
+        assert_eq!(outcome, HandleOutcome::Accepted);
+        fixtures::mine_block(&mut node).unwrap();
+        assert!(matches!(
+            node.get_transaction(&id).unwrap().status,
+            TransactionStatus::Confirmed { height: 4, .. }
+        ));
+    }
+}
@@ -1,25 +1,250 @@
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
+#[cfg(feature = "std-node")]
+use std::{collections::hash_map::Entry, fs, path::Path};
 
-use crate::transaction::{
-    Transaction, TransactionBody, TransactionInput, TransactionOutputReference,
+use crate::{
+    block::Block,
+    crypto::{Hash, HashExt, sha256d},
+    transaction::{
+        Transaction, TransactionBody, TransactionInput, TransactionOutput,
+        TransactionOutputReference, TxValidationError,
+    },
 };
 
+/// Why a transaction can't spend what it claims to from the set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[serde(tag = "kind", content = "details", rename_all = "snake_case")]
+pub enum UtxoError {
+    #[error("Input {outpoint} is missing or already spent")]
+    MissingOutpoint {
+        outpoint: TransactionOutputReference,
+    },
+    #[error("Transaction is not signed by the owner of {address}")]
+    OwnershipMismatch { address: String },
+    /// An earlier transaction in the same block spends the same output.
+    #[error("Input {outpoint} is spent twice in the block")]
+    DoubleSpend {
+        outpoint: TransactionOutputReference,
+    },
+    #[error("Transaction outputs of {outputs} exceed its input of {input}")]
+    ValueMismatch { input: u64, outputs: u64 },
+    /// The input's relative lock hasn't passed at the height it would be confirmed at.
+    #[error("Input {outpoint} is locked until height {spendable_at}")]
+    Locked {
+        outpoint: TransactionOutputReference,
+        spendable_at: u32,
+    },
+    /// The input has a relative lock, but the output it spends isn't confirmed to count
+    /// from.
+    #[error("Input {outpoint} has a relative lock but is unconfirmed")]
+    LockedUnconfirmed {
+        outpoint: TransactionOutputReference,
+    },
+    #[error(transparent)]
+    Transaction(#[from] TxValidationError),
+}
+
+#[cfg(feature = "std-node")]
+#[derive(Serialize, Deserialize)]
+struct UTXOEntry {
+    outpoint: TransactionOutputReference,
+    transaction: Transaction,
+    height: Option<u32>,
+}
+
+/// An output spent by a transaction, along with the height it was created at.
+#[derive(Debug, Clone)]
+pub struct SpentOutput {
+    pub outpoint: TransactionOutputReference,
+    pub transaction: Arc<Transaction>,
+    pub height: Option<u32>,
+}
+
+/// What a block removed from the UTXO set, one entry per transaction (`None` for the
+/// coinbase), so its effects can be reverted on a reorg.
+#[derive(Debug, Clone, Default)]
+pub struct BlockUndo {
+    pub spent: Vec<Option<SpentOutput>>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct UTXOSet {
     pub outputs: HashMap<TransactionOutputReference, Arc<Transaction>>,
+    /// Height of the block that created each confirmed output.
+    pub heights: HashMap<TransactionOutputReference, u32>,
 }
 
 impl UTXOSet {
-    pub fn update(&mut self, transaction: &Transaction) -> Result<()> {
-        let transaction = Arc::new(transaction.clone());
+    pub fn apply_block(&mut self, block: &Block) -> Result<BlockUndo> {
+        let _span = tracing::debug_span!(
+            "utxo.apply",
+            block_hash = %format_args!("{:#x}", block.header.hash()?.display()),
+            height = block.height,
+        )
+        .entered();
+        let mut undo = BlockUndo::default();
+
+        for tx in &block.transactions {
+            undo.spent.push(self.apply_transaction(tx, block.height)?);
+        }
+
+        Ok(undo)
+    }
+
+    /// Updates the set with a transaction confirmed at `height`, returning the output it spent.
+    pub fn apply_transaction(
+        &mut self,
+        transaction: &Arc<Transaction>,
+        height: u32,
+    ) -> Result<Option<SpentOutput>> {
+        let spent = transaction.body.input.outpoint().and_then(|reference| {
+            self.outputs.get(reference).map(|spent_tx| SpentOutput {
+                outpoint: reference.clone(),
+                transaction: spent_tx.clone(),
+                height: self.creation_height(reference),
+            })
+        });
+
+        self.update(transaction)?;
+
+        for index in 0..transaction.body.outputs.len() {
+            self.heights
+                .insert(transaction.output_reference(index)?, height);
+        }
+
+        Ok(spent)
+    }
+
+    /// Undoes `apply_block`, removing the block's outputs and restoring those it spent.
+    pub fn revert_block(&mut self, block: &Block, undo: &BlockUndo) -> Result<()> {
+        let _span = tracing::debug_span!(
+            "utxo.revert",
+            block_hash = %format_args!("{:#x}", block.header.hash()?.display()),
+            height = block.height,
+        )
+        .entered();
+        if undo.spent.len() != block.transactions.len() {
+            return Err(anyhow::anyhow!("Undo data does not match block"));
+        }
+
+        for (tx, spent) in block.transactions.iter().zip(&undo.spent).rev() {
+            for index in 0..tx.body.outputs.len() {
+                let reference = tx.output_reference(index)?;
+                self.outputs.remove(&reference);
+                self.heights.remove(&reference);
+            }
+
+            if let Some(spent) = spent {
+                if let Some(height) = spent.height {
+                    self.heights.insert(spent.outpoint.clone(), height);
+                }
+                self.outputs
+                    .insert(spent.outpoint.clone(), spent.transaction.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn creation_height(&self, reference: &TransactionOutputReference) -> Option<u32> {
+        self.heights.get(reference).copied()
+    }
+
+    /// The value of every unspent output.
+    pub fn total_value(&self) -> u64 {
+        self.outputs
+            .iter()
+            .map(|(outpoint, tx)| tx.body.outputs[outpoint.index].value)
+            .sum()
+    }
+
+    /// A hash committing to every unspent output, independent of map ordering.
+    pub fn commitment(&self) -> Result<Hash> {
+        let mut entries = self
+            .outputs
+            .iter()
+            .map(|(outpoint, tx)| {
+                let config = bincode::config::standard();
+                Ok([
+                    bincode::encode_to_vec(outpoint, config)?,
+                    bincode::encode_to_vec(&tx.body.outputs[outpoint.index], config)?,
+                ]
+                .concat())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        entries.sort();
+
+        Ok(sha256d(&entries.concat()))
+    }
+
+    #[cfg(feature = "std-node")]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let entries = self
+            .outputs
+            .iter()
+            .map(|(outpoint, tx)| UTXOEntry {
+                outpoint: outpoint.clone(),
+                transaction: tx.as_ref().clone(),
+                height: self.creation_height(outpoint),
+            })
+            .collect::<Vec<_>>();
+
+        fs::write(path, serde_json::to_vec(&entries)?)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "std-node")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let entries: Vec<UTXOEntry> = serde_json::from_slice(&fs::read(path)?)?;
+
+        let mut utxo_set = Self::default();
+        let mut transactions: HashMap<_, Arc<Transaction>> = HashMap::new();
+
+        // a tampered file mustn't leave outpoints that index past their transaction
+        for entry in entries {
+            let transaction = match transactions.entry(entry.outpoint.id.clone()) {
+                Entry::Occupied(occupied) => occupied.get().clone(),
+                Entry::Vacant(vacant) => {
+                    let id = entry.transaction.id()?;
+                    if id != entry.outpoint.id {
+                        anyhow::bail!("UTXO set entry {} holds transaction {id}", entry.outpoint);
+                    }
+                    vacant.insert(Arc::new(entry.transaction)).clone()
+                }
+            };
+            if entry.outpoint.index >= transaction.body.outputs.len() {
+                anyhow::bail!(
+                    "UTXO set entry {} is past the transaction's {} outputs",
+                    entry.outpoint,
+                    transaction.body.outputs.len()
+                );
+            }
+
+            if let Some(height) = entry.height {
+                utxo_set.heights.insert(entry.outpoint.clone(), height);
+            }
+            utxo_set.outputs.insert(entry.outpoint, transaction);
+        }
 
+        Ok(utxo_set)
+    }
+
+    /// Spends the output `transaction` references and adds its outputs, each holding a
+    /// clone of the `Arc` rather than of the transaction.
+    pub fn update(&mut self, transaction: &Arc<Transaction>) -> Result<()> {
         let TransactionBody { input, outputs } = &transaction.body;
 
-        if let TransactionInput::Reference(reference) = input {
+        if let Some(reference) = input.outpoint() {
             let removed = self.outputs.remove(reference);
+            self.heights.remove(reference);
             if removed.is_none() {
-                return Err(anyhow::anyhow!("Transaction output reference not found"));
+                return Err(UtxoError::MissingOutpoint {
+                    outpoint: reference.clone(),
+                }
+                .into());
             }
         }
 
@@ -36,33 +261,127 @@ impl UTXOSet {
         Ok(())
     }
 
-    pub fn validate_transaction(&self, transaction: &Transaction) -> Result<bool> {
-        transaction.verify_signature()?;
+    /// Validates `transaction` against the set as if confirmed at `height`, returning the
+    /// fee it pays (the amount by which the spent output exceeds the new outputs).
+    pub fn validate_transaction(
+        &self,
+        transaction: &Transaction,
+        height: u32,
+    ) -> Result<u64, UtxoError> {
+        transaction.validate()?;
+        self.validate_spend(transaction, height)
+    }
 
-        let TransactionBody { input, outputs } = &transaction.body;
+    /// `validate_transaction` as if `pending` had been applied to the set in order, without
+    /// copying the set to apply them. Outputs created by `pending` are unconfirmed.
+    pub fn validate_transaction_after(
+        &self,
+        transaction: &Transaction,
+        pending: &[Arc<Transaction>],
+        height: u32,
+    ) -> Result<u64, UtxoError> {
+        transaction.validate()?;
 
-        if let TransactionInput::Reference(reference) = input {
-            let Some(spent_tx) = self.outputs.get(reference) else {
-                return Err(anyhow::anyhow!("Transaction output already spent"));
-            };
+        let TransactionInput::Reference {
+            outpoint: reference,
+            relative_lock,
+        } = &transaction.body.input
+        else {
+            return self.validate_spend(transaction, height);
+        };
+        let spent_by_pending = pending
+            .iter()
+            .any(|tx| tx.body.input.outpoint() == Some(reference));
+        let output = match self.outputs.get(reference) {
+            _ if spent_by_pending => None,
+            Some(spent_tx) => spent_tx.body.outputs.get(reference.index),
+            None => pending
+                .iter()
+                .find(|tx| tx.id().is_ok_and(|id| id == reference.id))
+                .and_then(|spent_tx| spent_tx.body.outputs.get(reference.index)),
+        };
 
-            let Some(output) = spent_tx.body.outputs.get(reference.index) else {
-                return Err(anyhow::anyhow!("Transaction output index not found"));
-            };
+        let fee = Self::spend_fee(transaction, reference, output)?;
+        self.check_relative_lock(reference, *relative_lock, height)?;
+        Ok(fee)
+    }
 
-            if output.address != transaction.signing_info.address() {
-                return Err(anyhow::anyhow!(
-                    "Transaction not signed by owner of output address"
-                ));
+    /// `validate_transaction` without the signature check, for transactions whose
+    /// signatures were verified (or deliberately skipped) elsewhere.
+    pub fn validate_spend(&self, transaction: &Transaction, height: u32) -> Result<u64, UtxoError> {
+        match &transaction.body.input {
+            TransactionInput::Reference {
+                outpoint: reference,
+                relative_lock,
+            } => {
+                let output = self
+                    .outputs
+                    .get(reference)
+                    .and_then(|spent_tx| spent_tx.body.outputs.get(reference.index));
+                let fee = Self::spend_fee(transaction, reference, output)?;
+                self.check_relative_lock(reference, *relative_lock, height)?;
+                Ok(fee)
             }
-
-            let tx_output_value = outputs.iter().map(|o| o.value).sum::<u64>();
-            if tx_output_value != output.value {
-                return Err(anyhow::anyhow!("Transaction output value does not match"));
+            TransactionInput::Coinbase { .. } => {
+                transaction.checked_output_value()?;
+                Ok(0)
             }
         }
+    }
+
+    /// Whether `reference`, locked for `relative_lock` blocks, can be spent in a block at
+    /// `height`: it must have been confirmed at least that many blocks earlier. Outputs
+    /// without a creation height are unconfirmed.
+    fn check_relative_lock(
+        &self,
+        reference: &TransactionOutputReference,
+        relative_lock: Option<u32>,
+        height: u32,
+    ) -> Result<(), UtxoError> {
+        let Some(relative_lock) = relative_lock else {
+            return Ok(());
+        };
+        let created =
+            self.creation_height(reference)
+                .ok_or_else(|| UtxoError::LockedUnconfirmed {
+                    outpoint: reference.clone(),
+                })?;
+
+        let spendable_at = created.saturating_add(relative_lock);
+        if height < spendable_at {
+            return Err(UtxoError::Locked {
+                outpoint: reference.clone(),
+                spendable_at,
+            });
+        }
+        Ok(())
+    }
+
+    /// The fee `transaction` pays spending `output`, the one `reference` names if it's
+    /// unspent.
+    fn spend_fee(
+        transaction: &Transaction,
+        reference: &TransactionOutputReference,
+        output: Option<&TransactionOutput>,
+    ) -> Result<u64, UtxoError> {
+        let outputs_value = transaction.checked_output_value()?;
+        let output = output.ok_or_else(|| UtxoError::MissingOutpoint {
+            outpoint: reference.clone(),
+        })?;
+
+        if output.address.payload() != transaction.signing_info.public_key_hash() {
+            return Err(UtxoError::OwnershipMismatch {
+                address: output.address.to_string(),
+            });
+        }
 
-        Ok(true)
+        output
+            .value
+            .checked_sub(outputs_value)
+            .ok_or(UtxoError::ValueMismatch {
+                input: output.value,
+                outputs: outputs_value,
+            })
     }
 }
 
@@ -70,13 +389,14 @@ impl UTXOSet {
 mod tests {
     use super::*;
     use crate::crypto::*;
+    use crate::fixtures;
     use crate::transaction::*;
 
     #[test]
     fn test_utxo_set() {
         let mut utxo_set = UTXOSet::default();
 
-        let keypair_bob = KeyPair::generate();
+        let keypair_bob = KeyPair::from_seed(&[1; 32]).unwrap();
         let address_bob = Address::from_public_key(&keypair_bob.public_key);
 
         let tx_a_body = TransactionBody {
@@ -87,7 +407,7 @@ mod tests {
             }],
         };
 
-        let tx_a = tx_a_body.into_tx(&keypair_bob).unwrap();
+        let tx_a = Arc::new(tx_a_body.into_tx(&keypair_bob).unwrap());
 
         utxo_set.update(&tx_a).unwrap();
 
@@ -97,11 +417,11 @@ mod tests {
                 .contains_key(&tx_a.output_reference(0).unwrap())
         );
 
-        let keypair_alice = KeyPair::generate();
+        let keypair_alice = KeyPair::from_seed(&[2; 32]).unwrap();
         let address_alice = Address::from_public_key(&keypair_alice.public_key);
 
         let tx_b_body = TransactionBody {
-            input: TransactionInput::Reference(tx_a.output_reference(0).unwrap()),
+            input: TransactionInput::spending(tx_a.output_reference(0).unwrap()),
             outputs: vec![
                 TransactionOutput {
                     value: 50,
@@ -114,7 +434,7 @@ mod tests {
             ],
         };
 
-        let tx_b = tx_b_body.into_tx(&keypair_bob).unwrap();
+        let tx_b = Arc::new(tx_b_body.into_tx(&keypair_bob).unwrap());
 
         utxo_set.update(&tx_b).unwrap();
 
@@ -136,4 +456,219 @@ mod tests {
                 .contains_key(&tx_b.output_reference(1).unwrap())
         );
     }
+
+    #[cfg(feature = "std-node")]
+    #[test]
+    fn test_load_rejects_tampered_entries() {
+        let keypair = KeyPair::from_seed(&[1; 32]).unwrap();
+        let coinbase = TransactionBody {
+            input: TransactionInput::Coinbase { block_height: 0 },
+            outputs: vec![TransactionOutput {
+                value: 100,
+                address: Address::from_public_key(&keypair.public_key),
+            }],
+        }
+        .into_tx(&keypair)
+        .unwrap();
+        let mut utxo_set = UTXOSet::default();
+        utxo_set.update(&Arc::new(coinbase)).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("utxo_set.json");
+        utxo_set.save(&path).unwrap();
+        assert_eq!(
+            UTXOSet::load(&path).unwrap().commitment().unwrap(),
+            utxo_set.commitment().unwrap()
+        );
+        let saved: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+
+        let tamper = |edit: fn(&mut serde_json::Value)| {
+            let mut entries = saved.clone();
+            edit(&mut entries[0]);
+            fs::write(&path, serde_json::to_vec(&entries).unwrap()).unwrap();
+            UTXOSet::load(&path).unwrap_err().to_string()
+        };
+        assert!(tamper(|entry| entry["outpoint"]["index"] = 1.into()).contains("past"));
+        assert!(
+            tamper(|entry| entry["transaction"]["body"]["outputs"][0]["value"] = 200.into())
+                .contains("holds transaction")
+        );
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let keypair_bob = KeyPair::from_seed(&[1; 32]).unwrap();
+        let keypair_alice = KeyPair::from_seed(&[2; 32]).unwrap();
+        let address_bob = Address::from_public_key(&keypair_bob.public_key);
+
+        let coinbase = TransactionBody {
+            input: TransactionInput::Coinbase { block_height: 0 },
+            outputs: vec![TransactionOutput {
+                value: 100,
+                address: address_bob.clone(),
+            }],
+        }
+        .into_tx(&keypair_bob)
+        .map(Arc::new)
+        .unwrap();
+        let mut utxo_set = UTXOSet::default();
+        utxo_set.update(&coinbase).unwrap();
+
+        let outpoint = coinbase.output_reference(0).unwrap();
+        let spend = |outpoint: &TransactionOutputReference, values: &[u64], signer| {
+            TransactionBody {
+                input: TransactionInput::spending(outpoint.clone()),
+                outputs: values
+                    .iter()
+                    .map(|&value| TransactionOutput {
+                        value,
+                        address: address_bob.clone(),
+                    })
+                    .collect(),
+            }
+            .into_tx(signer)
+            .unwrap()
+        };
+
+        assert_eq!(
+            utxo_set.validate_transaction(&spend(&outpoint, &[90], &keypair_bob), 1),
+            Ok(10)
+        );
+
+        let missing = TransactionOutputReference {
+            index: 1,
+            ..outpoint.clone()
+        };
+        assert_eq!(
+            utxo_set.validate_transaction(&spend(&missing, &[90], &keypair_bob), 1),
+            Err(UtxoError::MissingOutpoint { outpoint: missing })
+        );
+        assert_eq!(
+            utxo_set.validate_transaction(&spend(&outpoint, &[90], &keypair_alice), 1),
+            Err(UtxoError::OwnershipMismatch {
+                address: address_bob.to_string()
+            })
+        );
+        assert_eq!(
+            utxo_set.validate_transaction(&spend(&outpoint, &[60, 50], &keypair_bob), 1),
+            Err(UtxoError::ValueMismatch {
+                input: 100,
+                outputs: 110
+            })
+        );
+        assert_eq!(
+            utxo_set.validate_spend(&spend(&outpoint, &[u64::MAX, 1], &keypair_bob), 1),
+            Err(UtxoError::Transaction(TxValidationError::ValueOverflow))
+        );
+
+        let mut tampered = spend(&outpoint, &[90], &keypair_bob);
+        tampered.body.outputs[0].value = 80;
+        assert_eq!(
+            utxo_set.validate_transaction(&tampered, 1),
+            Err(UtxoError::Transaction(TxValidationError::BadSignature))
+        );
+        assert_eq!(utxo_set.validate_spend(&tampered, 1), Ok(20));
+    }
+
+    #[test]
+    fn test_validate_transaction_after() {
+        let params = crate::params::NetworkParams::regtest();
+        let key_pair = fixtures::key_pair(1);
+        let (funding, utxo_set) = fixtures::funding(3, 100, &key_pair, &params).unwrap();
+        let spends = fixtures::spends(&funding, &key_pair, 1).unwrap();
+        // the outputs are shared with the funding transaction, not copied
+        assert!(
+            utxo_set
+                .outputs
+                .values()
+                .all(|tx| Arc::ptr_eq(tx, &funding))
+        );
+
+        let pending = vec![spends[0].clone()];
+        let candidates = [
+            spends[1].clone(),
+            // spent by the pending transaction
+            spends[0].clone(),
+            // created by it
+            fixtures::spends(&spends[0], &key_pair, 2)
+                .unwrap()
+                .remove(0),
+            Arc::new(Transaction::new_coinbase(&key_pair, 2, &params).unwrap()),
+        ];
+
+        let mut applied = utxo_set.clone();
+        for tx in &pending {
+            applied.update(tx).unwrap();
+        }
+        for candidate in &candidates {
+            assert_eq!(
+                utxo_set.validate_transaction_after(candidate, &pending, 1),
+                applied.validate_transaction(candidate, 1)
+            );
+        }
+        assert_eq!(
+            utxo_set.validate_transaction_after(&candidates[2], &pending, 1),
+            Ok(2)
+        );
+        assert!(matches!(
+            utxo_set.validate_transaction_after(&candidates[1], &pending, 1),
+            Err(UtxoError::MissingOutpoint { .. })
+        ));
+    }
+
+    #[test]
+    fn test_relative_lock() {
+        let params = crate::params::NetworkParams::regtest();
+        let key_pair = fixtures::key_pair(1);
+        let (funding, utxo_set) = fixtures::funding(2, 100, &key_pair, &params).unwrap();
+        let locked = |outpoint: TransactionOutputReference, relative_lock| {
+            let address = funding.body.outputs[0].address.clone();
+            TransactionBody {
+                input: TransactionInput::Reference {
+                    outpoint,
+                    relative_lock,
+                },
+                outputs: vec![TransactionOutput { value: 99, address }],
+            }
+            .into_tx(&key_pair)
+            .map(Arc::new)
+            .unwrap()
+        };
+
+        // funded at height 1, so locked for 3 blocks it's first spendable at height 4
+        let outpoint = funding.output_reference(0).unwrap();
+        let spend = locked(outpoint.clone(), Some(3));
+        assert_eq!(
+            utxo_set.validate_transaction(&spend, 3),
+            Err(UtxoError::Locked {
+                outpoint: outpoint.clone(),
+                spendable_at: 4
+            })
+        );
+        assert_eq!(utxo_set.validate_transaction(&spend, 4), Ok(1));
+        assert_eq!(
+            utxo_set.validate_transaction(&locked(outpoint.clone(), Some(0)), 2),
+            Ok(1)
+        );
+
+        // a pending parent's outputs have no height to count from, however short the lock
+        let parent = locked(funding.output_reference(1).unwrap(), None);
+        let pending = vec![parent.clone()];
+        let child_outpoint = parent.output_reference(0).unwrap();
+        assert_eq!(
+            utxo_set.validate_transaction_after(
+                &locked(child_outpoint.clone(), Some(0)),
+                &pending,
+                2
+            ),
+            Err(UtxoError::LockedUnconfirmed {
+                outpoint: child_outpoint.clone()
+            })
+        );
+        assert!(
+            utxo_set
+                .validate_transaction_after(&locked(child_outpoint, None), &pending, 2)
+                .is_ok()
+        );
+    }
 }
@@ -0,0 +1,213 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    crypto::{Address, KeyPair},
+    params::Network,
+};
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// How often the searching threads are polled for a result, a cancellation, or progress.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Options for `Address::vanity_with`.
+pub struct VanityOptions<'a> {
+    pub network: Network,
+    pub case_insensitive: bool,
+    /// Called every `progress_interval` with the attempts so far and attempts per second.
+    pub progress: Option<&'a (dyn Fn(u64, f64) + Sync)>,
+    pub progress_interval: Duration,
+}
+
+impl Default for VanityOptions<'_> {
+    fn default() -> Self {
+        Self {
+            network: Network::Mainnet,
+            case_insensitive: false,
+            progress: None,
+            progress_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Address {
+    /// Generates keypairs on `threads` threads until one's mainnet address starts with
+    /// `prefix`, or `cancel` is set.
+    pub fn vanity(prefix: &str, threads: usize, cancel: &AtomicBool) -> anyhow::Result<KeyPair> {
+        Self::vanity_with(prefix, threads, cancel, VanityOptions::default())
+    }
+
+    pub fn vanity_with(
+        prefix: &str,
+        threads: usize,
+        cancel: &AtomicBool,
+        options: VanityOptions,
+    ) -> anyhow::Result<KeyPair> {
+        check_prefix(prefix, options.network, options.case_insensitive)?;
+        if threads == 0 {
+            return Err(anyhow::anyhow!("Vanity search needs at least one thread"));
+        }
+
+        let matches = |address: &str| match options.case_insensitive {
+            true => address
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+            false => address.starts_with(prefix),
+        };
+
+        let attempts = AtomicU64::new(0);
+        let done = AtomicBool::new(false);
+        let found = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    while !done.load(Ordering::Relaxed) && !cancel.load(Ordering::Relaxed) {
+                        let key_pair = KeyPair::generate();
+                        let address =
+                            Address::from_public_key_for(&key_pair.public_key, options.network);
+                        attempts.fetch_add(1, Ordering::Relaxed);
+
+                        if matches(&address.to_string()) {
+                            found.lock().unwrap().get_or_insert(key_pair);
+                            done.store(true, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+
+            let start = Instant::now();
+            let mut last_report = start;
+            while !done.load(Ordering::Relaxed) && !cancel.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+
+                if let Some(progress) = options.progress
+                    && last_report.elapsed() >= options.progress_interval
+                {
+                    last_report = Instant::now();
+                    let attempts = attempts.load(Ordering::Relaxed);
+                    progress(attempts, attempts as f64 / start.elapsed().as_secs_f64());
+                }
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        found
+            .into_inner()
+            .unwrap()
+            .ok_or(anyhow::anyhow!("Vanity search cancelled"))
+    }
+}
+
+/// Refuses prefixes no address on `network` can start with: characters outside base58,
+/// or a first character other than the version byte's.
+fn check_prefix(prefix: &str, network: Network, case_insensitive: bool) -> anyhow::Result<()> {
+    let in_alphabet = |c: char| match case_insensitive {
+        true => {
+            BASE58_ALPHABET.contains(c.to_ascii_lowercase())
+                || BASE58_ALPHABET.contains(c.to_ascii_uppercase())
+        }
+        false => BASE58_ALPHABET.contains(c),
+    };
+    if let Some(c) = prefix.chars().find(|&c| !in_alphabet(c)) {
+        return Err(anyhow::anyhow!("'{c}' never appears in a base58 address"));
+    }
+
+    let leading = match network {
+        Network::Mainnet => "1",
+        Network::Testnet | Network::Regtest => "mn",
+    };
+    match prefix.chars().next() {
+        None => Err(anyhow::anyhow!("Vanity prefix is empty")),
+        Some(first)
+            if !(leading.contains(first)
+                || case_insensitive && leading.contains(first.to_ascii_lowercase())) =>
+        {
+            Err(anyhow::anyhow!(
+                "{network} addresses start with one of \"{leading}\", not '{first}'"
+            ))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_vanity() {
+        let cancel = AtomicBool::new(false);
+
+        // one character past the '1' every mainnet address starts with
+        let key_pair = Address::vanity("1A", 2, &cancel).unwrap();
+        assert!(
+            Address::from_public_key(&key_pair.public_key)
+                .to_string()
+                .starts_with("1A")
+        );
+
+        let key_pair = Address::vanity_with(
+            "M",
+            2,
+            &cancel,
+            VanityOptions {
+                network: Network::Testnet,
+                case_insensitive: true,
+                ..VanityOptions::default()
+            },
+        )
+        .unwrap();
+        let address = Address::from_public_key_for(&key_pair.public_key, Network::Testnet);
+        assert!(address.to_string().starts_with('m'));
+
+        assert!(Address::vanity("10", 1, &cancel).is_err());
+        assert!(Address::vanity("2", 1, &cancel).is_err());
+        assert!(Address::vanity("", 1, &cancel).is_err());
+        assert!(Address::vanity("1A", 0, &cancel).is_err());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let reports = AtomicU64::new(0);
+        let progress = |attempts: u64, _rate: f64| {
+            assert!(attempts > 0);
+            reports.fetch_add(1, Ordering::Relaxed);
+        };
+
+        let canceller = {
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(300));
+                cancel.store(true, Ordering::Relaxed);
+            })
+        };
+
+        // practically never found, so it runs until cancelled
+        let start = Instant::now();
+        let result = Address::vanity_with(
+            "1zzzzzzzzz",
+            2,
+            &cancel,
+            VanityOptions {
+                progress: Some(&progress),
+                progress_interval: Duration::from_millis(50),
+                ..VanityOptions::default()
+            },
+        );
+        canceller.join().unwrap();
+
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(reports.load(Ordering::Relaxed) > 0);
+    }
+}
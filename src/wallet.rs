@@ -0,0 +1,334 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use argon2::Params;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::DEFAULT_WALLET_GAP_LIMIT,
+    crypto::{Address, KeyPair},
+    hd::{ChildNumber, DerivationPath, ExtendedPrivKey, HdError},
+    keystore::{KeystoreError, seal, unseal, write_owner_only},
+    mnemonic::Mnemonic,
+    params::Network,
+    transaction::TransactionOutputReference,
+    utxo_set::UTXOSet,
+};
+
+const WALLET_MAGIC: &[u8; 4] = b"TCWL";
+
+/// The BIP44-style chain receive addresses are derived along.
+pub const RECEIVE_PATH: &str = "m/44'/0'/0'/0";
+
+/// An unspent output paying one of the wallet's addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletOutput {
+    pub path: DerivationPath,
+    pub outpoint: TransactionOutputReference,
+    pub value: u64,
+}
+
+/// Receive addresses derived one per payment from a single master key, so backing up the
+/// seed backs up every address.
+#[derive(Clone)]
+pub struct Wallet {
+    master: ExtendedPrivKey,
+    receive: ExtendedPrivKey,
+    receive_path: DerivationPath,
+    /// Index of the next receive address to hand out.
+    pub next_index: u32,
+    /// How many unused addresses in a row a scan looks past before it stops.
+    pub gap_limit: u32,
+    /// Labels given to receive addresses, by index.
+    pub labels: BTreeMap<u32, String>,
+    /// The outputs found by the last `rescan`.
+    pub outputs: Vec<WalletOutput>,
+    /// Height of the tip the last `rescan` saw.
+    pub scanned_height: Option<u32>,
+}
+
+/// The metadata section of a wallet file, sealed along with the master key.
+#[derive(Serialize, Deserialize)]
+struct WalletMetadata {
+    /// Regtest keys are serialized as testnet ones, so the network is kept separately.
+    network: Network,
+    next_index: u32,
+    gap_limit: u32,
+    labels: BTreeMap<u32, String>,
+    outputs: Vec<WalletOutput>,
+    scanned_height: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WalletFile {
+    /// The master key as an `xprv` string.
+    master: String,
+    metadata: WalletMetadata,
+}
+
+impl Wallet {
+    pub fn new(master: &ExtendedPrivKey) -> Result<Self, HdError> {
+        let receive_path = RECEIVE_PATH.parse::<DerivationPath>()?;
+
+        Ok(Self {
+            master: master.clone(),
+            receive: master.derive_path(&receive_path)?,
+            receive_path,
+            next_index: 0,
+            gap_limit: DEFAULT_WALLET_GAP_LIMIT,
+            labels: BTreeMap::new(),
+            outputs: Vec::new(),
+            scanned_height: None,
+        })
+    }
+
+    pub fn from_mnemonic(
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        network: Network,
+    ) -> Result<Self, HdError> {
+        Self::new(&ExtendedPrivKey::new_master(
+            &mnemonic.to_seed(passphrase),
+            network,
+        )?)
+    }
+
+    pub fn network(&self) -> Network {
+        self.master.network
+    }
+
+    /// Seals the master key and metadata in the keystore's encrypted format, under its own
+    /// magic so a wallet file is never mistaken for a key file.
+    pub fn save_encrypted(&self, path: &Path, passphrase: &str) -> Result<(), KeystoreError> {
+        self.save_encrypted_with(path, passphrase, Params::default())
+    }
+
+    fn save_encrypted_with(
+        &self,
+        path: &Path,
+        passphrase: &str,
+        params: Params,
+    ) -> Result<(), KeystoreError> {
+        let file = WalletFile {
+            master: self.master.to_string(),
+            metadata: WalletMetadata {
+                network: self.network(),
+                next_index: self.next_index,
+                gap_limit: self.gap_limit,
+                labels: self.labels.clone(),
+                outputs: self.outputs.clone(),
+                scanned_height: self.scanned_height,
+            },
+        };
+        let json = serde_json::to_vec(&file).map_err(anyhow::Error::from)?;
+        write_owner_only(path, &seal(WALLET_MAGIC, &json, passphrase, params)?)
+    }
+
+    pub fn load_encrypted(path: &Path, passphrase: &str) -> Result<Self, KeystoreError> {
+        let json = unseal(WALLET_MAGIC, &fs::read(path)?, passphrase)?;
+        let file: WalletFile =
+            serde_json::from_slice(&json).map_err(|_| KeystoreError::Corrupted)?;
+        let metadata = file.metadata;
+        let mut master = file
+            .master
+            .parse::<ExtendedPrivKey>()
+            .map_err(|_| KeystoreError::Corrupted)?;
+        master.network = metadata.network;
+
+        Ok(Self {
+            next_index: metadata.next_index,
+            gap_limit: metadata.gap_limit,
+            labels: metadata.labels,
+            outputs: metadata.outputs,
+            scanned_height: metadata.scanned_height,
+            ..Self::new(&master).map_err(anyhow::Error::from)?
+        })
+    }
+
+    pub fn path(&self, index: u32) -> DerivationPath {
+        self.receive_path.child(ChildNumber::normal(index))
+    }
+
+    pub fn keypair(&self, index: u32) -> Result<KeyPair, HdError> {
+        Ok(self
+            .receive
+            .derive_child(ChildNumber::normal(index))?
+            .key_pair)
+    }
+
+    pub fn address(&self, index: u32) -> Result<Address, HdError> {
+        Ok(Address::from_public_key_for(
+            &self.keypair(index)?.public_key,
+            self.receive.network,
+        ))
+    }
+
+    /// Derives the next receive address, skipping the rare index with no valid key.
+    pub fn new_address(&mut self) -> Result<(u32, Address), HdError> {
+        loop {
+            let index = self.next_index;
+            self.next_index += 1;
+
+            match self.address(index) {
+                Ok(address) => return Ok((index, address)),
+                Err(HdError::InvalidChild(_)) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Finds the outputs in `utxo_set` paying the wallet, deriving addresses until
+    /// `gap_limit` in a row have none, and moves `next_index` past the last used one.
+    pub fn scan(&mut self, utxo_set: &UTXOSet) -> Result<Vec<WalletOutput>, HdError> {
+        let mut by_address = HashMap::<&Address, Vec<_>>::new();
+        for (outpoint, tx) in &utxo_set.outputs {
+            let output = &tx.body.outputs[outpoint.index];
+            by_address
+                .entry(&output.address)
+                .or_default()
+                .push((outpoint, output.value));
+        }
+
+        let mut found = Vec::new();
+        let mut gap = 0;
+        let mut index = 0;
+
+        while gap < self.gap_limit {
+            let outputs = match self.address(index) {
+                Ok(address) => by_address.get(&address),
+                Err(HdError::InvalidChild(_)) => None,
+                Err(error) => return Err(error),
+            };
+
+            match outputs {
+                Some(outputs) => {
+                    found.extend(outputs.iter().map(|(outpoint, value)| WalletOutput {
+                        path: self.path(index),
+                        outpoint: (*outpoint).clone(),
+                        value: *value,
+                    }));
+                    self.next_index = self.next_index.max(index + 1);
+                    gap = 0;
+                }
+                None => gap += 1,
+            }
+            index += 1;
+        }
+
+        Ok(found)
+    }
+
+    /// Replaces the cached outputs with what `scan` finds in the UTXO set of a chain at
+    /// `height`.
+    pub fn rescan(&mut self, utxo_set: &UTXOSet, height: u32) -> Result<(), HdError> {
+        self.outputs = self.scan(utxo_set)?;
+        self.scanned_height = Some(height);
+        Ok(())
+    }
+
+    pub fn balance(&self) -> u64 {
+        self.outputs.iter().map(|output| output.value).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::node::*;
+    use crate::params::*;
+
+    #[test]
+    fn test_wallet_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet");
+        let params = Params::new(256, 1, 1, Some(32)).unwrap();
+
+        let mut wallet =
+            Wallet::new(&ExtendedPrivKey::new_master(&[7; 32], Network::Regtest).unwrap()).unwrap();
+        let (index, address) = wallet.new_address().unwrap();
+        wallet.labels.insert(index, "rent".to_string());
+        wallet
+            .save_encrypted_with(&path, "hunter2", params)
+            .unwrap();
+
+        let loaded = Wallet::load_encrypted(&path, "hunter2").unwrap();
+        assert_eq!(loaded.network(), Network::Regtest);
+        assert_eq!(loaded.next_index, 1);
+        assert_eq!(loaded.address(index).unwrap(), address);
+        assert_eq!(loaded.labels[&index], "rent");
+
+        assert!(matches!(
+            Wallet::load_encrypted(&path, "hunter3"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+        // key files and wallet files aren't interchangeable
+        KeyPair::generate()
+            .save_encrypted(&path, "hunter2")
+            .unwrap();
+        assert!(matches!(
+            Wallet::load_encrypted(&path, "hunter2"),
+            Err(KeystoreError::NotAKeyFile)
+        ));
+    }
+
+    #[test]
+    fn test_receive_on_derived_addresses() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut node = Node::new(
+            NodeConfig::builder(KeyPair::from_seed(&[1; 32]).unwrap())
+                .data_dir(data_dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap();
+
+        let mnemonic = Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        let mut wallet = Wallet::from_mnemonic(&mnemonic, "", Network::Regtest).unwrap();
+
+        // two handed out in order, and one further along that's still within the gap limit
+        let (_, first) = wallet.new_address().unwrap();
+        let (_, second) = wallet.new_address().unwrap();
+        let later = wallet.address(5).unwrap();
+        assert_ne!(first, second);
+
+        for (address, value) in [(first, 10), (second, 20), (later, 30)] {
+            let block = node.create_block().unwrap();
+            node.handle_message(Message::NewBlock(block)).unwrap();
+            node.send(address, value, 1).unwrap();
+        }
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+
+        // a wallet restored from the same mnemonic finds all of it
+        let mut restored = Wallet::from_mnemonic(&mnemonic, "", Network::Regtest).unwrap();
+        let mut found = restored.scan(&node.state.utxo_set).unwrap();
+        found.sort_by_key(|output| output.value);
+
+        let paths = found
+            .iter()
+            .map(|output| (output.path.to_string(), output.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                ("m/44'/0'/0'/0/0".to_string(), 10),
+                ("m/44'/0'/0'/0/1".to_string(), 20),
+                ("m/44'/0'/0'/0/5".to_string(), 30),
+            ]
+        );
+        assert_eq!(restored.next_index, 6);
+
+        // nothing is found past the gap limit
+        restored.gap_limit = 3;
+        restored.next_index = 0;
+        assert_eq!(restored.scan(&node.state.utxo_set).unwrap().len(), 2);
+        assert_eq!(restored.next_index, 2);
+    }
+}
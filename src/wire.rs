@@ -0,0 +1,352 @@
+use std::io::{self, Read};
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::{StreamProtocol, request_response};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    constants::MAX_MESSAGE_SIZE,
+    crypto::{Hash, decode_config, serde_hex, sha256d},
+    node::Message,
+};
+
+/// Frame layout: a 4-byte big-endian payload length, a message type byte, a 4-byte checksum
+/// (the first bytes of the SHA-256d of the type byte and payload), then the bincode payload.
+const HEADER_SIZE: usize = 9;
+
+/// Sent by each side when a connection opens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    pub protocol_version: u32,
+    pub network_magic: [u8; 4],
+    pub best_height: u32,
+    #[serde(with = "serde_hex")]
+    pub best_hash: Hash,
+    pub user_agent: String,
+}
+
+/// Everything that goes over the wire between peers.
+#[derive(Debug, Clone)]
+pub enum NetMessage {
+    Version(Version),
+    VerAck,
+    /// Answers a request that needs no reply of its own.
+    Ack,
+    Node(Message),
+}
+
+impl From<Message> for NetMessage {
+    fn from(message: Message) -> Self {
+        Self::Node(message)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WireError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Message of {size} bytes exceeds the maximum of {MAX_MESSAGE_SIZE}")]
+    TooLarge { size: usize },
+    #[error("Unknown message type {0}")]
+    UnknownType(u8),
+    #[error("Message checksum mismatch")]
+    ChecksumMismatch,
+    #[error("Malformed message payload: {0}")]
+    Malformed(String),
+}
+
+fn checksum(message_type: u8, payload: &[u8]) -> [u8; 4] {
+    let mut bytes = Vec::with_capacity(payload.len() + 1);
+    bytes.push(message_type);
+    bytes.extend_from_slice(payload);
+
+    let hash = sha256d(&bytes);
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn encode_payload(value: &impl Serialize) -> Vec<u8> {
+    bincode::serde::encode_to_vec(value, bincode::config::standard())
+        .expect("Node messages always serialize")
+}
+
+fn decode_payload<T: DeserializeOwned>(payload: &[u8]) -> Result<T, WireError> {
+    let (value, read) = bincode::serde::decode_from_slice(payload, decode_config())
+        .map_err(|e| WireError::Malformed(e.to_string()))?;
+
+    if read != payload.len() {
+        return Err(WireError::Malformed(format!(
+            "{} trailing bytes",
+            payload.len() - read
+        )));
+    }
+
+    Ok(value)
+}
+
+pub fn encode_message(message: &NetMessage) -> Vec<u8> {
+    let (message_type, payload) = match message {
+        NetMessage::Version(version) => (0, encode_payload(version)),
+        NetMessage::VerAck => (1, vec![]),
+        NetMessage::Node(Message::NewBlock(block)) => (2, encode_payload(block)),
+        NetMessage::Node(Message::NewTransaction(tx)) => (3, encode_payload(tx)),
+        NetMessage::Node(Message::GetHeaders { locator, stop }) => {
+            (4, encode_payload(&(locator, stop)))
+        }
+        NetMessage::Node(Message::Headers(headers)) => (5, encode_payload(headers)),
+        NetMessage::Node(Message::GetData(hashes)) => (6, encode_payload(hashes)),
+        NetMessage::Node(Message::BlockData(block)) => (7, encode_payload(block)),
+        NetMessage::Node(Message::Inv(hashes)) => (8, encode_payload(hashes)),
+        NetMessage::Node(Message::NotFound(hashes)) => (9, encode_payload(hashes)),
+        NetMessage::Ack => (10, vec![]),
+        NetMessage::Node(Message::TxInv(ids)) => (11, encode_payload(ids)),
+        NetMessage::Node(Message::GetTransactions(ids)) => (12, encode_payload(ids)),
+        NetMessage::Node(Message::Transactions(txs)) => (13, encode_payload(txs)),
+    };
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    bytes.push(message_type);
+    bytes.extend_from_slice(&checksum(message_type, &payload));
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/// Reads one frame from `reader`. A frame claiming more than `MAX_MESSAGE_SIZE` bytes is
+/// refused before its payload is read.
+pub fn decode_message(reader: &mut impl Read) -> Result<NetMessage, WireError> {
+    let mut header = [0; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let message_type = header[4];
+
+    if size > MAX_MESSAGE_SIZE {
+        return Err(WireError::TooLarge { size });
+    }
+
+    if message_type > 13 {
+        return Err(WireError::UnknownType(message_type));
+    }
+
+    let mut payload = vec![0; size];
+    reader.read_exact(&mut payload)?;
+
+    if checksum(message_type, &payload) != header[5..] {
+        return Err(WireError::ChecksumMismatch);
+    }
+
+    Ok(match message_type {
+        0 => NetMessage::Version(decode_payload(&payload)?),
+        1 if payload.is_empty() => NetMessage::VerAck,
+        1 => return Err(WireError::Malformed("VerAck carries a payload".to_string())),
+        2 => Message::NewBlock(decode_payload(&payload)?).into(),
+        3 => Message::NewTransaction(decode_payload(&payload)?).into(),
+        4 => {
+            let (locator, stop) = decode_payload(&payload)?;
+            Message::GetHeaders { locator, stop }.into()
+        }
+        5 => Message::Headers(decode_payload(&payload)?).into(),
+        6 => Message::GetData(decode_payload(&payload)?).into(),
+        7 => Message::BlockData(decode_payload(&payload)?).into(),
+        8 => Message::Inv(decode_payload(&payload)?).into(),
+        9 => Message::NotFound(decode_payload(&payload)?).into(),
+        10 if payload.is_empty() => NetMessage::Ack,
+        10 => return Err(WireError::Malformed("Ack carries a payload".to_string())),
+        11 => Message::TxInv(decode_payload(&payload)?).into(),
+        12 => Message::GetTransactions(decode_payload(&payload)?).into(),
+        13 => Message::Transactions(decode_payload(&payload)?).into(),
+        _ => return Err(WireError::UnknownType(message_type)),
+    })
+}
+
+/// `decode_message` for async streams.
+pub async fn read_message(reader: &mut (impl AsyncRead + Unpin)) -> Result<NetMessage, WireError> {
+    let mut frame = vec![0; HEADER_SIZE];
+    reader.read_exact(&mut frame).await?;
+
+    let size = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+
+    if size > MAX_MESSAGE_SIZE {
+        return Err(WireError::TooLarge { size });
+    }
+
+    frame.resize(HEADER_SIZE + size, 0);
+    reader.read_exact(&mut frame[HEADER_SIZE..]).await?;
+
+    decode_message(&mut frame.as_slice())
+}
+
+/// Carries `NetMessage`s in both directions of a libp2p request-response protocol.
+#[derive(Debug, Clone, Default)]
+pub struct WireCodec;
+
+impl WireCodec {
+    async fn read(io: &mut (impl AsyncRead + Unpin + Send)) -> io::Result<NetMessage> {
+        read_message(io).await.map_err(|e| match e {
+            WireError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        })
+    }
+
+    async fn write(
+        io: &mut (impl AsyncWrite + Unpin + Send),
+        message: NetMessage,
+    ) -> io::Result<()> {
+        io.write_all(&encode_message(&message)).await?;
+        io.close().await
+    }
+}
+
+impl request_response::Codec for WireCodec {
+    type Protocol = StreamProtocol;
+    type Request = NetMessage;
+    type Response = NetMessage;
+
+    async fn read_request<T: AsyncRead + Unpin + Send>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<NetMessage> {
+        Self::read(io).await
+    }
+
+    async fn read_response<T: AsyncRead + Unpin + Send>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<NetMessage> {
+        Self::read(io).await
+    }
+
+    async fn write_request<T: AsyncWrite + Unpin + Send>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        request: NetMessage,
+    ) -> io::Result<()> {
+        Self::write(io, request).await
+    }
+
+    async fn write_response<T: AsyncWrite + Unpin + Send>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        response: NetMessage,
+    ) -> io::Result<()> {
+        Self::write(io, response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::*;
+    use crate::params::*;
+    use crate::transaction::*;
+
+    fn all_messages() -> Vec<NetMessage> {
+        let block = NetworkParams::regtest().genesis_block().unwrap();
+        let tx = Transaction::new_coinbase(
+            &KeyPair::from_seed(&[1; 32]).unwrap(),
+            2,
+            &NetworkParams::default(),
+        )
+        .unwrap();
+        let hash = block.header.hash().unwrap();
+
+        vec![
+            NetMessage::Version(Version {
+                protocol_version: 1,
+                network_magic: *b"tiny",
+                best_height: 7,
+                best_hash: hash,
+                user_agent: "tiny-crypto/0.1.0".to_string(),
+            }),
+            NetMessage::VerAck,
+            Message::NewBlock(block.clone()).into(),
+            Message::NewTransaction(tx.clone()).into(),
+            Message::GetHeaders {
+                locator: vec![hash, [1; 32]],
+                stop: Some(hash),
+            }
+            .into(),
+            Message::Headers(vec![(1, block.header.clone())]).into(),
+            Message::GetData(vec![hash]).into(),
+            Message::BlockData(block).into(),
+            Message::Inv(vec![]).into(),
+            Message::NotFound(vec![hash]).into(),
+            NetMessage::Ack,
+            Message::TxInv(vec![tx.id().unwrap()]).into(),
+            Message::GetTransactions(vec![]).into(),
+            Message::Transactions(vec![tx]).into(),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let messages = all_messages();
+        let mut stream = messages.iter().flat_map(encode_message).collect::<Vec<_>>();
+        let mut reader = stream.as_slice();
+
+        for message in &messages {
+            let decoded = decode_message(&mut reader).unwrap();
+            assert_eq!(
+                std::mem::discriminant(&decoded),
+                std::mem::discriminant(message)
+            );
+            assert_eq!(encode_message(&decoded), encode_message(message));
+        }
+        assert!(reader.is_empty());
+
+        // the stream ends mid-frame
+        stream.truncate(stream.len() - 1);
+        let mut reader = stream.as_slice();
+        for _ in 1..messages.len() {
+            decode_message(&mut reader).unwrap();
+        }
+        assert!(matches!(decode_message(&mut reader), Err(WireError::Io(_))));
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        for message in all_messages() {
+            let bytes = encode_message(&message);
+
+            for length in 0..bytes.len() {
+                assert!(decode_message(&mut &bytes[..length]).is_err());
+            }
+
+            for index in 0..bytes.len() {
+                for bit in 0..8 {
+                    let mut flipped = bytes.clone();
+                    flipped[index] ^= 1 << bit;
+                    assert!(decode_message(&mut flipped.as_slice()).is_err());
+                }
+            }
+        }
+
+        let mut oversized = ((MAX_MESSAGE_SIZE + 1) as u32).to_be_bytes().to_vec();
+        oversized.extend_from_slice(&[2, 0, 0, 0, 0]);
+        assert!(matches!(
+            decode_message(&mut oversized.as_slice()),
+            Err(WireError::TooLarge { .. })
+        ));
+
+        let unknown = [0, 0, 0, 0, 14, 0, 0, 0, 0];
+        assert!(matches!(
+            decode_message(&mut unknown.as_slice()),
+            Err(WireError::UnknownType(14))
+        ));
+
+        // a well-framed payload claiming a vector far larger than the frame
+        let payload = encode_payload(&(u32::MAX as u64));
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.push(6);
+        bytes.extend_from_slice(&checksum(6, &payload));
+        bytes.extend_from_slice(&payload);
+        assert!(matches!(
+            decode_message(&mut bytes.as_slice()),
+            Err(WireError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,316 @@
+//! Push notifications over a WebSocket at `/ws`, served beside JSON-RPC by `RpcServer`.
+//! Clients send `{"subscribe": [...]}` or `{"unsubscribe": [...]}` with channels
+//! `blocks`, `transactions`, and `address:<addr>`, and get `WsEvent`s as JSON text frames,
+//! sourced from the node's `NodeEvent`s. A client too slow to keep up misses events
+//! rather than holding the node up, and is told how many with a `lagged` event.
+
+use std::collections::HashSet;
+
+use axum::{
+    Router,
+    extract::{
+        FromRef, State,
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    crypto::{Address, Hash, serde_hex},
+    node::{Node, NodeEvent},
+    shared_node::SharedNode,
+    transaction::{Transaction, TransactionOutputReference, TxId},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Blocks,
+    Transactions,
+    Address(Address),
+}
+
+impl Channel {
+    fn parse(channel: &str, node: &Node) -> Result<Self, String> {
+        match channel {
+            "blocks" => Ok(Self::Blocks),
+            "transactions" => Ok(Self::Transactions),
+            _ => match channel.strip_prefix("address:") {
+                Some(address) => Address::parse_for(address, node.state.params.network)
+                    .map(Self::Address)
+                    .map_err(|error| format!("Invalid address in {channel}: {error}")),
+                None => Err(format!("Unknown channel {channel}")),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WsRequest {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsEvent {
+    /// The channels now subscribed to, after a request.
+    Subscribed {
+        channels: Vec<String>,
+    },
+    Error {
+        message: String,
+    },
+    /// This many node events were dropped because the client fell behind.
+    Lagged {
+        missed: u64,
+    },
+    Tip {
+        #[serde(with = "serde_hex")]
+        hash: Hash,
+        height: u32,
+    },
+    /// A block left the active chain in a reorg.
+    BlockDisconnected {
+        #[serde(with = "serde_hex")]
+        hash: Hash,
+    },
+    Transaction {
+        txid: TxId,
+    },
+    /// An output paying a subscribed address, in a block or while `pending`.
+    Received {
+        address: Address,
+        outpoint: TransactionOutputReference,
+        value: u64,
+        pending: bool,
+    },
+    /// An output paying a subscribed address was spent.
+    Spent {
+        address: Address,
+        outpoint: TransactionOutputReference,
+        value: u64,
+        pending: bool,
+    },
+}
+
+/// The route, for any router state the node and server shutdown can be taken from.
+pub fn routes<S>() -> Router<S>
+where
+    SharedNode: FromRef<S>,
+    CancellationToken: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route("/ws", get(upgrade))
+}
+
+async fn upgrade(
+    State(node): State<SharedNode>,
+    State(shutdown): State<CancellationToken>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| async move {
+        // subscribed before anything else so no event is missed
+        let events = node.subscribe();
+        Session {
+            node,
+            channels: HashSet::new(),
+        }
+        .run(socket, events, shutdown)
+        .await
+    })
+}
+
+struct Session {
+    node: SharedNode,
+    channels: HashSet<Channel>,
+}
+
+impl Session {
+    /// Ends when the client goes away or the server shuts down. Sending waits on the
+    /// client, while the node's events queue up in `events` until they're dropped.
+    async fn run(
+        mut self,
+        mut socket: WebSocket,
+        mut events: broadcast::Receiver<NodeEvent>,
+        shutdown: CancellationToken,
+    ) {
+        loop {
+            let replies = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                message = socket.recv() => match message {
+                    Some(Ok(WsMessage::Text(text))) => vec![self.handle_request(&text).await],
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                },
+                event = events.recv() => match event {
+                    Ok(event) => self.notifications(event).await,
+                    Err(RecvError::Lagged(missed)) => vec![WsEvent::Lagged { missed }],
+                    Err(RecvError::Closed) => break,
+                },
+            };
+
+            for reply in replies {
+                let Ok(text) = serde_json::to_string(&reply) else {
+                    continue;
+                };
+                if socket.send(WsMessage::Text(text.into())).await.is_err() {
+                    return;
+                }
+            }
+        }
+        let _ = socket.send(WsMessage::Close(None)).await;
+    }
+
+    async fn handle_request(&mut self, text: &str) -> WsEvent {
+        let request = match serde_json::from_str::<WsRequest>(text) {
+            Ok(request) => request,
+            Err(error) => {
+                return WsEvent::Error {
+                    message: format!("Invalid request: {error}"),
+                };
+            }
+        };
+
+        let node = self.node.read().await;
+        let (subscribe, names) = match request {
+            WsRequest::Subscribe(names) => (true, names),
+            WsRequest::Unsubscribe(names) => (false, names),
+        };
+        let channels = match names
+            .iter()
+            .map(|name| Channel::parse(name, &node))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(channels) => channels,
+            Err(message) => return WsEvent::Error { message },
+        };
+        for channel in channels {
+            if subscribe {
+                self.channels.insert(channel);
+            } else {
+                self.channels.remove(&channel);
+            }
+        }
+
+        let mut channels = self
+            .channels
+            .iter()
+            .map(|channel| match channel {
+                Channel::Blocks => "blocks".to_string(),
+                Channel::Transactions => "transactions".to_string(),
+                Channel::Address(address) => format!("address:{address}"),
+            })
+            .collect::<Vec<_>>();
+        channels.sort();
+        WsEvent::Subscribed { channels }
+    }
+
+    /// What the subscribed channels make of a node event.
+    async fn notifications(&self, event: NodeEvent) -> Vec<WsEvent> {
+        let node = self.node.read().await;
+        match event {
+            NodeEvent::TipChanged { new, .. } if self.channels.contains(&Channel::Blocks) => {
+                match node.state.block_manager.get_block(&new) {
+                    Some(block) => vec![WsEvent::Tip {
+                        hash: new,
+                        height: block.height,
+                    }],
+                    None => vec![],
+                }
+            }
+            NodeEvent::BlockDisconnected { hash } if self.channels.contains(&Channel::Blocks) => {
+                vec![WsEvent::BlockDisconnected { hash }]
+            }
+            NodeEvent::BlockConnected { hash, .. } => {
+                match node.state.block_manager.get_block(&hash) {
+                    Some(block) => block
+                        .transactions
+                        .iter()
+                        .flat_map(|transaction| self.address_changes(&node, transaction, false))
+                        .collect(),
+                    None => vec![],
+                }
+            }
+            NodeEvent::TransactionAccepted(txid) => {
+                let mut notifications = Vec::new();
+                if self.channels.contains(&Channel::Transactions) {
+                    notifications.push(WsEvent::Transaction { txid: txid.clone() });
+                }
+                if let Some(record) = node.get_transaction(&txid) {
+                    notifications.extend(self.address_changes(&node, &record.transaction, true));
+                }
+                notifications
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The outputs `transaction` creates and spends for subscribed addresses.
+    fn address_changes(
+        &self,
+        node: &Node,
+        transaction: &Transaction,
+        pending: bool,
+    ) -> Vec<WsEvent> {
+        let subscribed =
+            |address: &Address| self.channels.contains(&Channel::Address(address.clone()));
+        let mut changes = Vec::new();
+
+        if let Some(outpoint) = transaction.body.input.outpoint()
+            && let Some(spent) = node.get_transaction(&outpoint.id)
+            && let Some(output) = spent.transaction.body.outputs.get(outpoint.index)
+            && subscribed(&output.address)
+        {
+            changes.push(WsEvent::Spent {
+                address: output.address.clone(),
+                outpoint: outpoint.clone(),
+                value: output.value,
+                pending,
+            });
+        }
+
+        for (index, output) in transaction.body.outputs.iter().enumerate() {
+            if subscribed(&output.address)
+                && let Ok(outpoint) = transaction.output_reference(index)
+            {
+                changes.push(WsEvent::Received {
+                    address: output.address.clone(),
+                    outpoint,
+                    value: output.value,
+                    pending,
+                });
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::HashExt;
+
+    #[test]
+    fn test_wire_format() {
+        assert_eq!(
+            serde_json::from_str::<WsRequest>(r#"{"subscribe": ["blocks"]}"#).unwrap(),
+            WsRequest::Subscribe(vec!["blocks".to_string()])
+        );
+        assert!(serde_json::from_str::<WsRequest>(r#"{"publish": []}"#).is_err());
+
+        let tip = serde_json::to_value(WsEvent::Tip {
+            hash: Hash::zero(),
+            height: 7,
+        })
+        .unwrap();
+        assert_eq!(
+            tip,
+            serde_json::json!({ "type": "tip", "hash": "00".repeat(32), "height": 7 })
+        );
+    }
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{json_output, tiny_crypto};
+
+const PUBLIC_KEY: &str = "035fe61fefdd77e3f8065c57ce7750d4b4aa7bc881ebb8875d1a211c28d08ca111";
+const ADDRESS: &str = "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwW";
+
+fn address() -> Command {
+    let mut command = tiny_crypto();
+    command.arg("address");
+    command
+}
+
+#[test]
+fn test_derive_and_validate() {
+    let derived = json_output(address().args(["--from-pubkey", PUBLIC_KEY]));
+    assert_eq!(derived["address"], ADDRESS);
+
+    let validated = json_output(address().args(["--validate", ADDRESS]));
+    assert_eq!(validated, derived);
+    assert_eq!(validated["version"], 0);
+    assert_eq!(validated["networks"], serde_json::json!(["mainnet"]));
+
+    let testnet =
+        json_output(address().args(["--from-pubkey", PUBLIC_KEY, "--network", "testnet"]));
+    assert_eq!(testnet["payload"], derived["payload"]);
+    assert_eq!(
+        testnet["networks"],
+        serde_json::json!(["testnet", "regtest"])
+    );
+}
+
+#[test]
+fn test_invalid_address() {
+    // one character off
+    address()
+        .args(["--validate", "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwX"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("checksum"));
+
+    address()
+        .args(["--validate", ADDRESS, "--network", "testnet"])
+        .assert()
+        .failure();
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{json_output, tiny_crypto};
+use predicates::str::contains;
+use tiny_crypto::{
+    crypto::{Address, KeyPair},
+    params::{Network, NetworkParams},
+};
+
+fn balance(args: &[&str]) -> serde_json::Value {
+    json_output(
+        tiny_crypto()
+            .args(["balance", "--network", "regtest"])
+            .args(args),
+    )
+}
+
+#[test]
+fn test_balance() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let key = dir.path().join("miner.key");
+    let key_pair = KeyPair::generate();
+    key_pair.save_plaintext(&key).unwrap();
+
+    common::mine(&data_dir, &key, Network::Regtest, 2);
+
+    let params = NetworkParams::regtest();
+    let subsidy = params.block_reward(2) + params.block_reward(3);
+    let address = Address::from_public_key_for(&key_pair.public_key, Network::Regtest);
+    let data_dir = data_dir.to_str().unwrap();
+
+    let by_address = balance(&["--data-dir", data_dir, "--address", &address.to_string()]);
+    assert_eq!(by_address["confirmed"], subsidy);
+    assert_eq!(by_address["utxo_count"], 2);
+    assert_eq!(by_address["pending_delta"], 0);
+
+    let by_key = balance(&[
+        "--data-dir",
+        data_dir,
+        "--key",
+        key.to_str().unwrap(),
+        "--utxos",
+    ]);
+    assert_eq!(by_key["confirmed"], by_address["confirmed"]);
+    let utxos = by_key["utxos"].as_array().unwrap();
+    assert_eq!(utxos.len(), 2);
+    assert_eq!(utxos[0]["confirmations"], 2);
+
+    // checked before the data dir is touched
+    let missing = dir.path().join("missing");
+    tiny_crypto()
+        .args(["balance", "--network", "regtest"])
+        .args([
+            "--address",
+            "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwX",
+            "--data-dir",
+        ])
+        .arg(&missing)
+        .assert()
+        .failure()
+        .stderr(contains("checksum"));
+    assert!(!missing.exists());
+}
@@ -0,0 +1,43 @@
+use assert_cmd::Command;
+
+#[test]
+fn test_bench() {
+    let output = Command::cargo_bin("tiny-crypto")
+        .unwrap()
+        .args([
+            "bench",
+            "--secs",
+            "0.1",
+            "--threads",
+            "2",
+            "--block-time",
+            "60",
+        ])
+        .args(["--output", "json"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let bench: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(bench["threads"], 2);
+    assert_eq!(bench["merkle_transactions"], 5_000);
+    for rate in [
+        "header_hashes",
+        "header_hashes_parallel",
+        "signature_verifications",
+        "merkle_roots",
+    ] {
+        assert!(bench[rate].as_f64().unwrap() > 0.0, "{rate}");
+    }
+    assert!(bench["suggested_difficulty"].as_u64().unwrap() <= 31);
+
+    Command::cargo_bin("tiny-crypto")
+        .unwrap()
+        .args(["bench", "--secs", "-1"])
+        .assert()
+        .failure();
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use std::path::Path;
+
+use common::{json_output, tiny_crypto};
+use predicates::str::contains;
+use tiny_crypto::{crypto::KeyPair, params::Network};
+
+fn mine(data_dir: &Path, count: u32) {
+    let key = data_dir.with_extension("key");
+    KeyPair::generate().save_plaintext(&key).unwrap();
+    common::mine(data_dir, &key, Network::Regtest, count);
+}
+
+fn show(data_dir: &Path, args: &[&str]) -> serde_json::Value {
+    json_output(
+        tiny_crypto()
+            .args(["block", "show", "--data-dir"])
+            .arg(data_dir)
+            .args(args),
+    )
+}
+
+#[test]
+fn test_block_show() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    mine(&data_dir, 3);
+
+    let block = show(&data_dir, &["--height", "3"]);
+    assert_eq!(block["height"], 3);
+    let previous = show(&data_dir, &["--height", "2"]);
+    let hash = block["header"]["previous_block_hash"].as_str().unwrap();
+    assert_eq!(show(&data_dir, &["--hash", hash]), previous);
+    assert_eq!(
+        show(&data_dir, &["--hash", hash, "--header-only"]),
+        previous["header"]
+    );
+
+    tiny_crypto()
+        .args(["block", "show", "--height", "3", "--data-dir"])
+        .arg(&data_dir)
+        .assert()
+        .success()
+        .stdout(contains("Confirmations: 2"))
+        .stdout(contains("Transactions: 1"));
+
+    tiny_crypto()
+        .args(["block", "show", "--height", "9", "--data-dir"])
+        .arg(&data_dir)
+        .assert()
+        .failure()
+        .stderr(contains("No block at height 9"));
+    tiny_crypto()
+        .args(["block", "show", "--hash", &"00".repeat(32), "--data-dir"])
+        .arg(&data_dir)
+        .assert()
+        .failure()
+        .stderr(contains("No block 0x"));
+}
+
+#[test]
+fn test_block_show_fork() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let fork_dir = dir.path().join("fork");
+    mine(&data_dir, 2);
+    mine(&fork_dir, 1);
+
+    // the fork's block at height 2 joins the longer chain's blocks
+    let fork = show(&fork_dir, &["--height", "2"]);
+    for entry in std::fs::read_dir(fork_dir.join("blocks")).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(
+            &path,
+            data_dir.join("blocks").join(path.file_name().unwrap()),
+        )
+        .unwrap();
+    }
+
+    let active = show(&data_dir, &["--height", "2"]);
+    assert_ne!(active, fork);
+
+    tiny_crypto()
+        .args(["block", "show", "--height", "2", "--data-dir"])
+        .arg(&data_dir)
+        .assert()
+        .success()
+        .stderr(contains("2 blocks at height 2"))
+        .stderr(contains("(active)"));
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use std::path::Path;
+
+use assert_cmd::Command;
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::{Hash, KeyPair},
+    node::Node,
+    params::{Network, NetworkParams},
+};
+
+fn mine(data_dir: &Path, network: Network, count: u32) {
+    let key = data_dir.with_extension("key");
+    KeyPair::generate().save_plaintext(&key).unwrap();
+    common::mine(data_dir, &key, network, count);
+}
+
+fn blocks(args: &[&str]) -> Command {
+    let mut command = tiny_crypto();
+    command.arg("blocks").args(args);
+    command
+}
+
+/// The tip hash and UTXO set commitment of a regtest data dir.
+fn tip(data_dir: &Path) -> (Hash, Hash) {
+    let node = Node::new(
+        NodeConfig::builder(KeyPair::generate())
+            .data_dir(data_dir)
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    (
+        node.tip_hash().unwrap().unwrap(),
+        node.state.utxo_set.commitment().unwrap(),
+    )
+}
+
+#[test]
+fn test_export_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let target = dir.path().join("target");
+    let archive = dir.path().join("chain.tcb");
+    mine(&source, Network::Regtest, 3);
+
+    let exported = json_output(
+        blocks(&["export", "--data-dir"])
+            .arg(&source)
+            .arg("--out")
+            .arg(&archive),
+    );
+    assert_eq!(exported["blocks"], 4);
+
+    // verifying leaves the data dir alone
+    let verified = json_output(
+        blocks(&[
+            "import",
+            "--network",
+            "regtest",
+            "--verify-only",
+            "--data-dir",
+        ])
+        .arg(&target)
+        .arg("--in")
+        .arg(&archive),
+    );
+    assert_eq!(verified["imported"], 4);
+    assert_eq!(verified["tip_hash"], exported["tip_hash"]);
+    assert!(!target.exists());
+
+    // the target already has the genesis block
+    let imported = json_output(
+        blocks(&["import", "--network", "regtest", "--data-dir"])
+            .arg(&target)
+            .arg("--in")
+            .arg(&archive),
+    );
+    assert_eq!(imported["imported"], 3);
+    assert_eq!(imported["skipped"], 1);
+    assert_eq!(imported["rejected"], 0);
+    assert_eq!(tip(&target), tip(&source));
+
+    let partial = json_output(
+        blocks(&["export", "--to-height", "2", "--data-dir"])
+            .arg(&source)
+            .arg("--out")
+            .arg(&archive)
+            .arg("--force"),
+    );
+    assert_eq!(partial["blocks"], 2);
+    assert_eq!(partial["height"], 2);
+
+    let bytes = std::fs::read(&archive).unwrap();
+    std::fs::write(&archive, &bytes[..bytes.len() - 1]).unwrap();
+    blocks(&[
+        "import",
+        "--network",
+        "regtest",
+        "--verify-only",
+        "--data-dir",
+    ])
+    .arg(&target)
+    .arg("--in")
+    .arg(&archive)
+    .assert()
+    .failure()
+    .stderr(predicates::str::contains("truncated"));
+}
+
+#[test]
+fn test_import_rejected_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    let mainnet = dir.path().join("mainnet");
+    let target = dir.path().join("target");
+    let archive = dir.path().join("chain.tcb");
+    mine(&mainnet, Network::Mainnet, 1);
+    blocks(&["export", "--data-dir"])
+        .arg(&mainnet)
+        .arg("--out")
+        .arg(&archive)
+        .assert()
+        .success();
+
+    let import = || {
+        let mut command = blocks(&["import", "--network", "regtest", "--data-dir"]);
+        command.arg(&target).arg("--in").arg(&archive);
+        command
+    };
+    import().assert().failure();
+
+    let imported = json_output(import().arg("--continue-on-error"));
+    assert_eq!(imported["imported"], 0);
+    assert_eq!(imported["rejected"], 2);
+    assert!(imported["first_rejection"].is_string());
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::{HashExt, KeyPair},
+    node::Node,
+    params::{Network, NetworkParams},
+};
+
+#[test]
+fn test_chain_info() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let key = dir.path().join("miner.key");
+    KeyPair::generate().save_plaintext(&key).unwrap();
+
+    common::mine(&data_dir, &key, Network::Regtest, 3);
+
+    let info = json_output(
+        tiny_crypto()
+            .args(["chain", "info", "--data-dir"])
+            .arg(&data_dir),
+    );
+
+    let node = Node::new(
+        NodeConfig::builder(KeyPair::generate())
+            .data_dir(&data_dir)
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    let params = &node.state.params;
+
+    assert_eq!(
+        info["tip_hash"],
+        node.tip_hash().unwrap().unwrap().display().to_string()
+    );
+    assert_eq!(info["height"], 4);
+    assert_eq!(info["block_count"], 4);
+    assert_eq!(info["stored_blocks"], 4);
+    assert_eq!(info["orphan_count"], 0);
+    assert_eq!(info["utxo_count"], 4);
+    assert_eq!(
+        info["total_supply"],
+        (1..=4)
+            .map(|height| params.block_reward(height))
+            .sum::<u64>()
+    );
+    assert_eq!(
+        info["total_work"],
+        node.state.chain.chain_work().unwrap().to_string()
+    );
+    assert!(info["disk_usage"].as_u64().unwrap() > 0);
+
+    let text = tiny_crypto()
+        .args(["chain", "info", "--data-dir"])
+        .arg(&data_dir)
+        .output()
+        .unwrap();
+    let text = String::from_utf8(text.stdout).unwrap();
+    let tip = node.state.chain.tail().unwrap();
+    assert!(text.ends_with(&format!("Recent Blocks:\n{}\n", node.state.chain)));
+    assert!(text.contains(&format!("\n{tip}\n")));
+
+    tiny_crypto()
+        .args(["chain", "info", "--data-dir"])
+        .arg(dir.path().join("missing"))
+        .assert()
+        .failure();
+}
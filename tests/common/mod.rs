@@ -0,0 +1,44 @@
+//! What the CLI tests share: running the binary, reading its JSON output, and mining
+//! blocks into a data dir. Each test crate uses only some of it.
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use assert_cmd::Command;
+use tiny_crypto::params::Network;
+
+/// The binary, without the passphrase or WIF a developer's environment might set.
+pub fn tiny_crypto() -> Command {
+    let mut command = Command::cargo_bin("tiny-crypto").unwrap();
+    command
+        .env_remove("TINY_CRYPTO_PASSPHRASE")
+        .env_remove("TINY_CRYPTO_WIF");
+    command
+}
+
+/// Runs `command` with `--output json`, asserting it succeeds, and parses all of stdout
+/// as one JSON document, so any stray line on stdout fails it.
+pub fn json_output(command: &mut Command) -> serde_json::Value {
+    let output = command.args(["--output", "json"]).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// Mines `count` blocks on `network` into `data_dir`, paying the key at `key`.
+pub fn mine(data_dir: &Path, key: &Path, network: Network, count: u32) {
+    tiny_crypto()
+        .args(["mine", "--threads", "1", "--network"])
+        .arg(network.to_string())
+        .arg("--count")
+        .arg(count.to_string())
+        .arg("--data-dir")
+        .arg(data_dir)
+        .arg("--key")
+        .arg(key)
+        .assert()
+        .success();
+}
@@ -0,0 +1,66 @@
+use assert_cmd::Command;
+
+fn config(args: &[&str]) -> std::process::Output {
+    Command::cargo_bin("tiny-crypto")
+        .unwrap()
+        .arg("config")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_config_file_and_flags() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("node.toml");
+    let path_arg = path.to_str().unwrap();
+
+    assert!(config(&["init", "--out", path_arg]).status.success());
+    assert!(std::fs::read_to_string(&path).unwrap().starts_with('#'));
+    // an existing config is only replaced with --force
+    assert!(!config(&["init", "--out", path_arg]).status.success());
+    assert!(
+        config(&["init", "--out", path_arg, "--force"])
+            .status
+            .success()
+    );
+
+    std::fs::write(
+        &path,
+        "data_dir = \"/tmp/from-file\"\nnetwork = \"regtest\"\n\n[p2p]\nmax_connections = 3\nlisten_addr = \"/ip4/127.0.0.1/tcp/9000\"\n\n[rpc]\nlisten_addr = \"127.0.0.1:8332\"\n",
+    )
+    .unwrap();
+    let output = config(&[
+        "show",
+        "--config",
+        path_arg,
+        "--max-connections",
+        "5",
+        "--rpc-auth-token",
+        "secret",
+        "--output",
+        "json",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let merged: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    // flags override the file, which overrides the defaults
+    assert_eq!(merged["p2p"]["max_connections"], 5);
+    assert_eq!(merged["rpc"]["auth_token"], "secret");
+    assert_eq!(merged["data_dir"], "/tmp/from-file");
+    assert_eq!(merged["network"]["network"], "regtest");
+    assert_eq!(merged["p2p"]["listen_addr"], "/ip4/127.0.0.1/tcp/9000");
+    assert_eq!(merged["rpc"]["listen_addr"], "127.0.0.1:8332");
+    assert_eq!(merged["mempool"]["max_transactions"], 5_000);
+
+    std::fs::write(&path, "[mempool]\nmax_txs = 10\n").unwrap();
+    let output = config(&["show", "--config", path_arg]);
+    assert!(!output.status.success());
+    let error = String::from_utf8_lossy(&output.stderr);
+    assert!(error.contains("unknown field `max_txs`"), "{error}");
+    assert!(error.contains("max_transactions"), "{error}");
+}
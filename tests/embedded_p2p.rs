@@ -0,0 +1,64 @@
+use std::{path::Path, time::Duration};
+
+use tiny_crypto::{
+    config::{NodeConfig, P2pConfig},
+    crypto::KeyPair,
+    node::{Message, Node},
+    p2p::P2pService,
+    params::NetworkParams,
+    shared_node::SharedNode,
+};
+
+fn regtest_node(data_dir: &Path) -> SharedNode {
+    let config = NodeConfig::builder(KeyPair::generate())
+        .data_dir(data_dir)
+        .network(NetworkParams::regtest())
+        .build();
+
+    SharedNode::new(Node::new(config).unwrap())
+}
+
+fn local_p2p_config(bootstrap_peers: Vec<String>) -> P2pConfig {
+    P2pConfig {
+        listen_addr: "/ip4/127.0.0.1/tcp/0".to_string(),
+        enable_mdns: Some(false),
+        bootstrap_peers,
+        ..P2pConfig::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_two_services_in_one_runtime() {
+    let data_dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+    let node_a = regtest_node(data_dirs[0].path());
+    let node_b = regtest_node(data_dirs[1].path());
+
+    let mut p2p_a = P2pService::new(node_a.clone(), local_p2p_config(vec![]))
+        .await
+        .unwrap();
+    let address = p2p_a.listen_addr().await.unwrap().to_string();
+    let handle_a = p2p_a.spawn();
+    let handle_b = P2pService::new(node_b.clone(), local_p2p_config(vec![address]))
+        .await
+        .unwrap()
+        .spawn();
+
+    let block = node_a.write().await.create_block().unwrap();
+    let hash = block.header.hash().unwrap();
+    node_a
+        .handle_message(Message::NewBlock(block))
+        .await
+        .unwrap();
+
+    // reaches B by gossip, or by sync once the handshake shows A is ahead
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while node_b.tip_hash().await.unwrap() != Some(hash) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    handle_a.shutdown().await.unwrap();
+    handle_b.shutdown().await.unwrap();
+}
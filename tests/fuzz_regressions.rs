@@ -0,0 +1,49 @@
+//! Replays the inputs under `fuzz/regressions/<target>` through the fuzz targets' bodies:
+//! the crashes and out-of-memory inputs fuzzing found, which now have to fail cleanly,
+//! alongside edge cases and valid inputs for the targets that found nothing.
+
+use std::{fs, path::Path};
+
+use tiny_crypto::fuzz;
+
+fn replay(target: &str, run: fn(&[u8])) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fuzz/regressions")
+        .join(target);
+    let mut inputs = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no inputs in {}", dir.display());
+
+    for input in inputs {
+        eprintln!("{}", input.display());
+        run(&fs::read(&input).unwrap());
+    }
+}
+
+#[test]
+fn test_transaction() {
+    replay("transaction", fuzz::transaction);
+}
+
+#[test]
+fn test_block() {
+    replay("block", fuzz::block);
+}
+
+#[test]
+fn test_wire_message() {
+    replay("wire_message", fuzz::wire_message);
+}
+
+#[test]
+fn test_address() {
+    replay("address", fuzz::address);
+}
+
+#[test]
+fn test_merkle_proof() {
+    replay("merkle_proof", fuzz::merkle_proof);
+}
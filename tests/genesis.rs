@@ -0,0 +1,160 @@
+mod common;
+
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    block::Block,
+    config::NodeConfig,
+    crypto::{Address, HashExt, KeyPair, to_hex},
+    node::{Message, Node},
+    outcome::HandleOutcome,
+    params::{ConsensusMode, Network},
+};
+
+fn genesis(args: &[&str]) -> serde_json::Value {
+    json_output(tiny_crypto().arg("genesis").args(args))
+}
+
+#[test]
+fn test_private_network() {
+    let dir = tempfile::tempdir().unwrap();
+    let params = dir.path().join("private.toml");
+    let params_arg = params.to_str().unwrap();
+    let premined = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Testnet);
+
+    let created = genesis(&[
+        "init",
+        "--out",
+        params_arg,
+        "--network",
+        "testnet",
+        "--reward",
+        "25",
+        "--halving",
+        "100",
+        "--difficulty",
+        "1",
+        "--message",
+        "a private network",
+        "--premine",
+        &format!("{premined}:500"),
+        "--threads",
+        "1",
+    ]);
+    let inspected = genesis(&["inspect", params_arg]);
+    assert_eq!(inspected["hash"], created["hash"]);
+    assert_eq!(inspected["premine"][0]["value"], 500);
+
+    let block: Block =
+        serde_json::from_slice(&std::fs::read(dir.path().join("private.genesis.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        block.header.hash().unwrap().display().to_string(),
+        created["hash"]
+    );
+
+    // a node started from the params file mines on the private genesis block
+    let key_pair = KeyPair::generate();
+    let mut config = NodeConfig::load(&params).unwrap();
+    config.keypair = key_pair.clone();
+    config.data_dir = dir.path().join("data");
+    let mut node = Node::new(config).unwrap();
+    let child = node.create_block().unwrap();
+    assert_eq!(
+        child.header.previous_block_hash,
+        block.header.hash().unwrap()
+    );
+    node.handle_message(Message::NewBlock(child)).unwrap();
+
+    assert_eq!(node.state.chain.height(), 2);
+    assert_eq!(node.get_confirmed_balance(&premined), 500);
+    let miner = Address::from_public_key_for(&key_pair.public_key, Network::Testnet);
+    assert_eq!(node.get_confirmed_balance(&miner), 25);
+
+    // the premine is checked against the network
+    tiny_crypto()
+        .args(["genesis", "init", "--force", "--message", "m", "--out"])
+        .arg(&params)
+        .args([
+            "--network",
+            "mainnet",
+            "--premine",
+            &format!("{premined}:1"),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid premine address"));
+
+    // as is a premine the genesis coinbase couldn't pay, whether given or loaded
+    tiny_crypto()
+        .args(["genesis", "init", "--force", "--message", "m", "--out"])
+        .arg(&params)
+        .args([
+            "--network",
+            "testnet",
+            "--difficulty",
+            "0",
+            "--premine",
+            &format!("{premined}:{}", u64::MAX),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("total more than"));
+    let toml = std::fs::read_to_string(&params)
+        .unwrap()
+        .replace("value = 500", &format!("value = {}", i64::MAX));
+    let allocation = toml.find("[[network.premine]]").unwrap();
+    std::fs::write(&params, format!("{toml}\n{}", &toml[allocation..])).unwrap();
+    let error = NodeConfig::load(&params).err().unwrap().to_string();
+    assert!(error.contains("total more than"), "{error}");
+}
+
+#[test]
+fn test_authority_network() {
+    let dir = tempfile::tempdir().unwrap();
+    let params = dir.path().join("authority.toml");
+    let validator = KeyPair::generate();
+
+    genesis(&[
+        "init",
+        "--out",
+        params.to_str().unwrap(),
+        "--message",
+        "an authority network",
+        "--authority",
+        &to_hex(validator.public_key.serialize()),
+    ]);
+
+    // the validator's node signs its blocks, which other nodes on the network accept
+    let node = |key_pair: &KeyPair, data_dir: &str| {
+        let mut config = NodeConfig::load(&params).unwrap();
+        config.keypair = key_pair.clone();
+        config.data_dir = dir.path().join(data_dir);
+        Node::new(config).unwrap()
+    };
+    let mut validating = node(&validator, "validator");
+    let mut following = node(&KeyPair::generate(), "follower");
+    assert!(matches!(
+        validating.state.params.consensus,
+        ConsensusMode::ProofOfAuthority { .. }
+    ));
+
+    let block = validating.create_block().unwrap();
+    assert_eq!(block.header.difficulty, 0);
+    assert_eq!(
+        following
+            .handle_message(Message::NewBlock(block))
+            .unwrap()
+            .0,
+        HandleOutcome::Accepted
+    );
+
+    // as long as it's the validator signing them
+    let block = following.create_block().unwrap();
+    assert!(matches!(
+        validating
+            .handle_message(Message::NewBlock(block))
+            .unwrap()
+            .0,
+        HandleOutcome::Rejected(_)
+    ));
+}
@@ -0,0 +1,55 @@
+use assert_cmd::Command;
+use tiny_crypto::crypto::{hash160, sha256d, to_hex};
+
+fn hash(algo: &str, stdin: &[u8]) -> String {
+    let output = Command::cargo_bin("tiny-crypto")
+        .unwrap()
+        .args(["hash", "--algo", algo])
+        .write_stdin(stdin)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_hash_stdin() {
+    assert_eq!(
+        hash("sha256", b"abc"),
+        "Hash (hex): ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n"
+    );
+    assert_eq!(
+        hash("sha256d", b"abc"),
+        "Hash (hex): 4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358\n"
+    );
+    assert_eq!(
+        hash("hash160", b"abc"),
+        "Hash (hex): bb1be98c142444d7a56aa3981c3942a978e4dc33\n"
+    );
+
+    // binary input, larger than one read
+    let bytes = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    assert_eq!(
+        hash("sha256d", &bytes),
+        format!("Hash (hex): {}\n", to_hex(sha256d(&bytes)))
+    );
+    assert_eq!(
+        hash("hash160", &bytes),
+        format!("Hash (hex): {}\n", to_hex(hash160(&bytes)))
+    );
+}
+
+#[test]
+fn test_hash_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("input.bin");
+    std::fs::write(&path, b"abc").unwrap();
+
+    Command::cargo_bin("tiny-crypto")
+        .unwrap()
+        .args(["hash", "--encoding", "base64", "--file"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout("Hash (base64): ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=\n");
+}
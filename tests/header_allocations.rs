@@ -0,0 +1,66 @@
+//! Counts the allocations header hashing makes, with an allocator of its own, which is
+//! why this is a test binary by itself.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use tiny_crypto::{block::BlockHeader, crypto::Hash};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Allocations `op` makes on this thread.
+fn allocations(op: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    op();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn test_hash_with_buf_allocations() {
+    let headers = (0..1_000u64)
+        .map(|nonce| BlockHeader {
+            previous_block_hash: [1; 32],
+            merkle_root: [2; 32],
+            timestamp: 1760850297,
+            difficulty: 1,
+            nonce,
+        })
+        .collect::<Vec<_>>();
+    let mut hashes = Vec::<Hash>::with_capacity(headers.len());
+
+    // an encoding buffer per header
+    let plain = allocations(|| {
+        hashes.extend(headers.iter().map(|header| header.hash().unwrap()));
+    });
+    assert!(plain >= headers.len(), "{plain}");
+
+    // and one buffer for them all, once it has grown to fit a header
+    let mut buf = Vec::new();
+    headers[0].hash_with_buf(&mut buf).unwrap();
+    let buffered = allocations(|| {
+        for (header, hash) in headers.iter().zip(&hashes) {
+            assert_eq!(header.hash_with_buf(&mut buf).unwrap(), *hash);
+        }
+    });
+    assert_eq!(buffered, 0);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    crypto::{Address, KeyPair},
+    params::Network,
+};
+
+fn key(args: &[&str]) -> serde_json::Value {
+    json_output(tiny_crypto().arg("key").args(args))
+}
+
+#[test]
+fn test_key_inspect() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_pair = KeyPair::generate();
+    let plaintext = dir.path().join("plain.key");
+    let encrypted = dir.path().join("encrypted.key");
+    key_pair.save_plaintext(&plaintext).unwrap();
+    key_pair.save_encrypted(&encrypted, "hunter2").unwrap();
+
+    let address = Address::from_public_key(&key_pair.public_key).to_string();
+    let testnet_wif = key_pair.to_wif(Network::Testnet);
+    let secret_hex = key_pair.secret_key.display_secret().to_string();
+
+    for args in [
+        vec!["--wif", &key_pair.to_wif(Network::Mainnet)],
+        vec!["--wif", &testnet_wif],
+        vec!["--secret-hex", &secret_hex],
+        vec!["--keyfile", plaintext.to_str().unwrap()],
+        vec![
+            "--keyfile",
+            encrypted.to_str().unwrap(),
+            "--passphrase",
+            "hunter2",
+        ],
+    ] {
+        let info = key(&[&["inspect"], args.as_slice()].concat());
+        assert_eq!(info["addresses"][0]["address"], address, "{args:?}");
+        assert!(info.get("wif").is_none());
+    }
+
+    // the WIF is printed for the network it was given for
+    let info = key(&["inspect", "--wif", &testnet_wif, "--yes-print-secrets"]);
+    assert_eq!(info["network"], "testnet");
+    assert_eq!(info["wif"], testnet_wif);
+
+    tiny_crypto()
+        .args(["key", "inspect", "--secret-hex", "00ff"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Secret key is 2 bytes, not 32"));
+}
+
+#[test]
+fn test_key_convert() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_pair = KeyPair::generate();
+    let wif = key_pair.to_wif(Network::Mainnet);
+
+    // secrets are only printed when asked for
+    tiny_crypto()
+        .args(["key", "convert", "--to", "hex", "--wif", &wif])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--yes-print-secrets"));
+
+    let hex = key(&[
+        "convert",
+        "--to",
+        "hex",
+        "--wif",
+        &wif,
+        "--yes-print-secrets",
+    ]);
+    let secret_hex = hex["secret"].as_str().unwrap();
+    assert_eq!(secret_hex, key_pair.secret_key.display_secret().to_string());
+
+    let key_file = dir.path().join("converted.key");
+    let saved = key(&[
+        "convert",
+        "--to",
+        "keyfile",
+        "--secret-hex",
+        secret_hex,
+        "--out",
+        key_file.to_str().unwrap(),
+        "--new-passphrase",
+        "hunter2",
+    ]);
+    assert!(saved.get("secret").is_none());
+
+    let converted = key(&[
+        "convert",
+        "--to",
+        "wif",
+        "--keyfile",
+        key_file.to_str().unwrap(),
+        "--passphrase",
+        "hunter2",
+        "--yes-print-secrets",
+    ]);
+    assert_eq!(converted["secret"], wif);
+    assert_eq!(converted["address"], saved["address"]);
+}
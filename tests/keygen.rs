@@ -0,0 +1,73 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    crypto::{Address, KeyPair},
+    params::Network,
+};
+
+const SEED_HEX: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+
+fn keygen() -> Command {
+    let mut command = tiny_crypto();
+    command.arg("keygen");
+    command
+}
+
+#[test]
+fn test_keygen_saves_encrypted_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("node.key");
+
+    let output = json_output(
+        keygen()
+            .args(["--seed-hex", SEED_HEX, "--network", "testnet"])
+            .arg("--out")
+            .arg(&path)
+            .env("TINY_CRYPTO_PASSPHRASE", "hunter2"),
+    );
+
+    let key_pair = KeyPair::load_encrypted(&path, "hunter2").unwrap();
+    let address = Address::from_public_key_for(&key_pair.public_key, Network::Testnet);
+    assert_eq!(output["address"], address.to_string());
+    assert_eq!(
+        output["public_key"],
+        hex::encode(key_pair.public_key.serialize())
+    );
+    assert_eq!(
+        key_pair.public_key,
+        KeyPair::from_seed(&[1; 32]).unwrap().public_key
+    );
+    // the secret is only in the key file
+    assert!(output.get("secret_key").is_none());
+    assert!(output.get("wif").is_none());
+
+    keygen()
+        .arg("--insecure-plaintext")
+        .arg("--out")
+        .arg(&path)
+        .assert()
+        .failure();
+    assert!(KeyPair::load_encrypted(&path, "hunter2").is_ok());
+}
+
+#[test]
+fn test_keygen_plaintext_and_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dev.key");
+    std::fs::write(&path, "").unwrap();
+
+    let output = json_output(
+        keygen()
+            .args(["--insecure-plaintext", "--force"])
+            .arg("--out")
+            .arg(&path),
+    );
+
+    let key_pair = KeyPair::load_plaintext(&path).unwrap();
+    assert_eq!(
+        output["address"],
+        Address::from_public_key(&key_pair.public_key).to_string()
+    );
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::{Address, KeyPair},
+    node::{Message, Node, UnspentOutput, build_payment},
+    params::{Network, NetworkParams},
+    rpc::RpcServer,
+    shared_node::SharedNode,
+};
+
+fn mempool(url: &str, args: &[&str]) -> serde_json::Value {
+    json_output(tiny_crypto().args(["mempool", "--rpc", url]).args(args))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_mempool_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_pair = KeyPair::generate();
+    let mut node = Node::new(
+        NodeConfig::builder(key_pair.clone())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    let block = node.create_block().unwrap();
+    node.handle_message(Message::NewBlock(block)).unwrap();
+
+    // the second payment spends the first's change
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+    let unspent = node.list_unspent(&node.address()).unwrap();
+    let parent = build_payment(&key_pair, Network::Regtest, unspent, to.clone(), 7, 1, 1).unwrap();
+    let change = UnspentOutput {
+        outpoint: parent.output_reference(1).unwrap(),
+        value: parent.body.outputs[1].value,
+        confirmations: 0,
+    };
+    let child = build_payment(&key_pair, Network::Regtest, vec![change], to, 3, 5, 1).unwrap();
+    // txids are printed in JSON without the 0x they're displayed with
+    let (parent_id, child_id) = (
+        serde_json::to_value(parent.id().unwrap()).unwrap(),
+        serde_json::to_value(child.id().unwrap()).unwrap(),
+    );
+    node.handle_message(Message::NewTransaction(parent))
+        .unwrap();
+    node.handle_message(Message::NewTransaction(child)).unwrap();
+
+    let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+        .await
+        .unwrap();
+    let url = format!("http://{}", server.local_addr().unwrap());
+    let rpc = server.spawn();
+
+    let (listed, parent_entry, child_entry, stats) = tokio::task::spawn_blocking({
+        let parent_id = parent_id.as_str().unwrap().to_string();
+        let child_id = child_id.as_str().unwrap().to_string();
+        move || {
+            (
+                mempool(&url, &["list"]),
+                mempool(&url, &["get", &parent_id]),
+                mempool(&url, &["get", &child_id]),
+                mempool(&url, &["stats"]),
+            )
+        }
+    })
+    .await
+    .unwrap();
+
+    let listed = listed["transactions"].as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["txid"], child_id);
+    assert_eq!(listed[1]["txid"], parent_id);
+    assert!(listed[0]["fee_rate"].as_f64() > listed[1]["fee_rate"].as_f64());
+
+    assert_eq!(parent_entry["fee"], 1);
+    assert_eq!(parent_entry["descendants"], serde_json::json!([child_id]));
+    assert_eq!(child_entry["ancestors"], serde_json::json!([parent_id]));
+    assert_eq!(child_entry["outputs"][0]["value"], 3);
+
+    assert_eq!(stats["count"], 2);
+    assert_eq!(stats["total_fees"], 6);
+    let histogram = stats["histogram"].as_array().unwrap();
+    assert_eq!(
+        histogram
+            .iter()
+            .map(|bucket| bucket["count"].as_u64().unwrap())
+            .sum::<u64>(),
+        2
+    );
+
+    rpc.shutdown().await.unwrap();
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::{Address, KeyPair},
+    node::{Message, Node},
+    params::{Network, NetworkParams},
+};
+
+fn merkle_proof() -> Command {
+    let mut command = tiny_crypto();
+    command.arg("merkle-proof");
+    command
+}
+
+#[test]
+fn test_prove_and_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut node = Node::new(
+        NodeConfig::builder(KeyPair::generate())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    for _ in 0..2 {
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+    }
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+    let id = node.send(to, 10, 0).unwrap();
+    let block = node.create_block().unwrap();
+    let (merkle_root, hash) = (block.header.merkle_root, block.header.hash().unwrap());
+    node.handle_message(Message::NewBlock(block)).unwrap();
+    drop(node);
+
+    let proof = json_output(
+        merkle_proof()
+            .args(["--tx", &id.to_string()])
+            .arg("--data-dir")
+            .arg(dir.path()),
+    );
+    assert_eq!(proof["block_hash"], hex::encode(hash));
+    assert_eq!(proof["height"], 4);
+    assert_eq!(proof["index"], 1);
+    assert_eq!(proof["transaction_count"], 2);
+
+    let verify = |proof: &str| {
+        merkle_proof()
+            .args(["verify", "--txid", &id.to_string(), "--count", "2"])
+            .args(["--root", &hex::encode(merkle_root), "--proof", proof])
+            .assert()
+    };
+    let hex = proof["proof"].as_str().unwrap();
+    verify(hex).success().stdout("valid\n");
+
+    let mut flipped = hex::decode(hex).unwrap();
+    *flipped.last_mut().unwrap() ^= 1;
+    verify(&hex::encode(flipped)).failure().stdout("invalid\n");
+
+    // a transaction that isn't confirmed can't be proven
+    merkle_proof()
+        .args(["--tx", &hex::encode([0; 32])])
+        .arg("--data-dir")
+        .arg(dir.path())
+        .assert()
+        .failure();
+}
@@ -0,0 +1,66 @@
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::KeyPair,
+    node::{Message, Node},
+    params::NetworkParams,
+    rpc::RpcServer,
+    shared_node::SharedNode,
+};
+
+/// The value of the unlabelled series `name`, if it's in `text`.
+fn series(text: &str, name: &str) -> Option<f64> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut node = Node::new(
+        NodeConfig::builder(KeyPair::generate())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    let block = node.create_block().unwrap();
+    node.handle_message(Message::NewBlock(block)).unwrap();
+
+    let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+        .await
+        .unwrap();
+    let url = format!("http://{}/metrics", server.local_addr().unwrap());
+    let rpc = server.spawn();
+
+    let response = reqwest::get(&url).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain")
+    );
+    let text = response.text().await.unwrap();
+
+    assert_eq!(series(&text, "tiny_crypto_chain_height"), Some(2.0));
+    for name in [
+        "tiny_crypto_blocks_validated_total",
+        "tiny_crypto_blocks_connected_total",
+        "tiny_crypto_utxo_count",
+        "tiny_crypto_utxo_total_value",
+    ] {
+        let value = series(&text, name).unwrap_or_else(|| panic!("{name} missing:\n{text}"));
+        assert!(value > 0.0, "{name} is {value}");
+    }
+    for name in [
+        "tiny_crypto_reorgs_total",
+        "tiny_crypto_mempool_transactions",
+        "tiny_crypto_p2p_bytes_received_total",
+        "tiny_crypto_peers",
+    ] {
+        assert!(series(&text, name).is_some(), "{name} missing:\n{text}");
+    }
+
+    rpc.shutdown().await.unwrap();
+}
@@ -0,0 +1,56 @@
+use assert_cmd::Command;
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::{Address, KeyPair},
+    node::Node,
+    params::{Network, NetworkParams},
+};
+
+fn open_node(data_dir: &std::path::Path) -> Node {
+    let config = NodeConfig::builder(KeyPair::generate())
+        .data_dir(data_dir)
+        .network(NetworkParams::regtest())
+        .build();
+    Node::new(config).unwrap()
+}
+
+#[test]
+fn test_mine_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let key = dir.path().join("miner.key");
+
+    let key_pair = KeyPair::generate();
+    key_pair.save_plaintext(&key).unwrap();
+    let height = open_node(&data_dir).state.chain.height();
+
+    for _ in 0..2 {
+        Command::cargo_bin("tiny-crypto")
+            .unwrap()
+            .args([
+                "mine",
+                "--network",
+                "regtest",
+                "--count",
+                "3",
+                "--threads",
+                "2",
+            ])
+            .arg("--data-dir")
+            .arg(&data_dir)
+            .arg("--key")
+            .arg(&key)
+            .assert()
+            .success();
+    }
+
+    let node = open_node(&data_dir);
+    assert_eq!(node.state.chain.height(), height + 6);
+    let address = Address::from_public_key_for(&key_pair.public_key, Network::Regtest);
+    assert_eq!(
+        node.get_confirmed_balance(&address),
+        (2..=7)
+            .map(|height| node.state.params.block_reward(height))
+            .sum::<u64>()
+    );
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Child, ChildStderr, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    crypto::KeyPair,
+    params::Network,
+    rpc::{MemPoolInfo, RpcClient},
+};
+
+/// Kills the node when dropped, so a failing test doesn't leave it running.
+struct RunningNode {
+    child: Child,
+    stderr: BufReader<ChildStderr>,
+}
+
+impl RunningNode {
+    fn start(data_dir: &Path, config: &Path, args: &[&str]) -> Self {
+        let mut child = Command::new(assert_cmd::cargo::cargo_bin("tiny-crypto"))
+            .args(["node", "run", "--network", "regtest"])
+            .arg("--data-dir")
+            .arg(data_dir)
+            .arg("--config")
+            .arg(config)
+            .args(args)
+            .env_remove("TINY_CRYPTO_PASSPHRASE")
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stderr = BufReader::new(child.stderr.take().unwrap());
+        Self { child, stderr }
+    }
+
+    /// Reads the startup log up to the line starting with `prefix`, returning the rest of it.
+    fn logged(&mut self, prefix: &str) -> String {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            assert!(self.stderr.read_line(&mut line).unwrap() > 0, "node exited");
+            if let Some(rest) = line.trim().strip_prefix(prefix) {
+                return rest.to_string();
+            }
+        }
+    }
+}
+
+impl Drop for RunningNode {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn tip(data_dir: &Path) -> serde_json::Value {
+    json_output(
+        tiny_crypto()
+            .args(["chain", "info", "--data-dir"])
+            .arg(data_dir),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_two_nodes_sync_a_mined_block() {
+    let dir = tempfile::tempdir().unwrap();
+    let (dir_a, dir_b) = (dir.path().join("a"), dir.path().join("b"));
+    // other tests' regtest nodes on this machine mustn't be discovered
+    let config = dir.path().join("node.toml");
+    std::fs::write(
+        &config,
+        "[p2p]\nlisten_addr = \"/ip4/127.0.0.1/tcp/0\"\nenable_mdns = false\n",
+    )
+    .unwrap();
+
+    let key = dir.path().join("miner.key");
+    KeyPair::generate().save_plaintext(&key).unwrap();
+    common::mine(&dir_a, &key, Network::Regtest, 1);
+    let mined = tip(&dir_a);
+    assert_eq!(mined["height"], 2);
+
+    let mut node_a = RunningNode::start(&dir_a, &config, &[]);
+    assert_eq!(node_a.logged("Network: "), "regtest");
+    let peer_id = node_a.logged("Peer ID: ");
+    let address = node_a.logged("Listening on ");
+    assert!(address.ends_with(&format!("/p2p/{peer_id}")));
+    assert!(node_a.logged("Tip: ").starts_with("height 2 "));
+
+    let mut node_b = RunningNode::start(
+        &dir_b,
+        &config,
+        &["--peer", &address, "--rpc-listen", "127.0.0.1:0"],
+    );
+    let url = node_b.logged("RPC listening on ");
+    let info: MemPoolInfo = RpcClient::new(url)
+        .unwrap()
+        .call("getmempoolinfo", serde_json::json!([]))
+        .await
+        .unwrap();
+    assert_eq!(info.size, 0);
+
+    let started = Instant::now();
+    while tip(&dir_b)["tip_hash"] != mined["tip_hash"] {
+        assert!(
+            started.elapsed() < Duration::from_secs(20),
+            "B never synced"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
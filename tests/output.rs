@@ -0,0 +1,72 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{json_output, tiny_crypto};
+use tiny_crypto::crypto::{Address, KeyPair};
+
+const PUBLIC_KEY: &str = "035fe61fefdd77e3f8065c57ce7750d4b4aa7bc881ebb8875d1a211c28d08ca111";
+
+fn json_stdout(args: &[&str], prepare: impl FnOnce(&mut Command)) -> serde_json::Value {
+    let mut command = tiny_crypto();
+    command.args(args);
+    prepare(&mut command);
+    json_output(&mut command)
+}
+
+#[test]
+fn test_json_output() {
+    let hashed = json_stdout(&["hash", "--input", "abc", "--algo", "sha256d"], |_| {});
+    assert_eq!(
+        hashed,
+        serde_json::json!({
+            "algo": "sha256d",
+            "encoding": "hex",
+            "hash": "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358",
+        })
+    );
+
+    let address = json_stdout(&["address", "--from-pubkey", PUBLIC_KEY], |_| {});
+    assert_eq!(address["address"], "1KYYpnPHa2fpyfrGmug6pprexoJU74ihwW");
+
+    let verdict = json_stdout(&["verify-message", "--address"], |command| {
+        let key_pair = KeyPair::generate();
+        let signature = key_pair.sign_message(b"hello");
+        command
+            .arg(Address::from_public_key(&key_pair.public_key).to_string())
+            .args(["--message", "hello", "--signature", &signature.to_string()]);
+    });
+    assert_eq!(verdict, serde_json::json!({ "valid": true }));
+}
+
+#[test]
+fn test_json_output_with_diagnostics() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("miner.key");
+    let data_dir = dir.path().join("data");
+
+    // the plaintext warning goes to stderr
+    let generated = json_stdout(&["keygen", "--insecure-plaintext", "--out"], |command| {
+        command.arg(&key);
+    });
+    assert!(generated.get("wif").is_none());
+
+    // as does the mining progress
+    let mined = json_stdout(
+        &["mine", "--network", "regtest", "--count", "2"],
+        |command| {
+            command
+                .arg("--data-dir")
+                .arg(&data_dir)
+                .arg("--key")
+                .arg(&key);
+        },
+    );
+    let blocks = mined["blocks"].as_array().unwrap();
+    assert_eq!(blocks.len(), 2);
+
+    let info = json_stdout(&["chain", "info"], |command| {
+        command.arg("--data-dir").arg(&data_dir);
+    });
+    assert_eq!(info["tip_hash"], blocks[1]["hash"]);
+    assert_eq!(info["height"], blocks[1]["height"]);
+}
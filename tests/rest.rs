@@ -0,0 +1,172 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::{Address, HashExt, KeyPair},
+    node::{Message, Node},
+    params::{Network, NetworkParams},
+    rpc::RpcServer,
+    shared_node::SharedNode,
+};
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl Client {
+    async fn get(&self, path: &str) -> (StatusCode, Value) {
+        let response = self
+            .http
+            .get(format!("{}{path}", self.url))
+            .send()
+            .await
+            .unwrap();
+        (response.status(), response.json().await.unwrap())
+    }
+
+    async fn ok(&self, path: &str) -> Value {
+        let (status, body) = self.get(path).await;
+        assert_eq!(status, StatusCode::OK, "{path}: {body}");
+        body
+    }
+}
+
+#[tokio::test]
+async fn test_rest_routes() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut node = Node::new(
+        NodeConfig::builder(KeyPair::generate())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    for _ in 0..2 {
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+    }
+    let tip_hash = node.tip_hash().unwrap().unwrap().display().to_string();
+    let address = node.address();
+    let coinbase_id = node
+        .state
+        .block_manager
+        .get_block(&node.tip_hash().unwrap().unwrap())
+        .unwrap()
+        .transactions[0]
+        .id()
+        .unwrap();
+    let coinbase_id = serde_json::to_value(coinbase_id).unwrap();
+
+    let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+        .await
+        .unwrap();
+    let client = Client {
+        http: reqwest::Client::new(),
+        url: format!("http://{}", server.local_addr().unwrap()),
+    };
+    let rpc = server.spawn();
+
+    let tip = client.ok("/blocks/tip").await;
+    assert_eq!(tip["hash"], tip_hash);
+    assert_eq!(tip["height"], 3);
+    assert_eq!(
+        client.ok(&format!("/blocks/{tip_hash}")).await["hash"],
+        tip_hash
+    );
+    let genesis = client.ok("/blocks/height/1").await;
+    assert_eq!(genesis["height"], 1);
+    assert_eq!(genesis["confirmations"], 3);
+
+    let transaction = client
+        .ok(&format!("/txs/{}", coinbase_id.as_str().unwrap()))
+        .await;
+    assert_eq!(transaction["txid"], coinbase_id);
+    assert_eq!(transaction["confirmations"], 1);
+    assert_eq!(transaction["block_hash"], tip_hash);
+    assert_eq!(transaction["proof"]["index"], 0);
+
+    let balance = client.ok(&format!("/addresses/{address}/balance")).await;
+    assert!(balance["confirmed"].as_u64().unwrap() > 0);
+    let utxos = client
+        .ok(&format!("/addresses/{address}/utxos?limit=1"))
+        .await;
+    assert_eq!(utxos["total"], 2);
+    assert_eq!(utxos["items"].as_array().unwrap().len(), 1);
+    let rest = client
+        .ok(&format!("/addresses/{address}/utxos?offset=1"))
+        .await;
+    assert_ne!(rest["items"][0], utxos["items"][0]);
+
+    let unknown = "00".repeat(32);
+    let mainnet = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Mainnet);
+    for (path, expected) in [
+        (format!("/blocks/{unknown}"), StatusCode::NOT_FOUND),
+        ("/blocks/height/4".to_string(), StatusCode::NOT_FOUND),
+        (format!("/txs/{unknown}"), StatusCode::NOT_FOUND),
+        ("/blocks/xyz".to_string(), StatusCode::BAD_REQUEST),
+        ("/blocks/height/-1".to_string(), StatusCode::BAD_REQUEST),
+        ("/txs/00ff".to_string(), StatusCode::BAD_REQUEST),
+        (
+            format!("/addresses/{mainnet}/balance"),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            format!("/addresses/{address}/utxos?limit=0"),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            format!("/addresses/{address}/history"),
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        let (status, body) = client.get(&path).await;
+        assert_eq!(status, expected, "{path}");
+        assert!(body["error"].is_string(), "{path}: {body}");
+    }
+
+    rpc.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_address_history_route() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut node = Node::new(
+        NodeConfig::builder(KeyPair::generate())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .index_address_history(true)
+            .build(),
+    )
+    .unwrap();
+    for _ in 0..2 {
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+    }
+    let address = node.address();
+
+    let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+        .await
+        .unwrap();
+    let client = Client {
+        http: reqwest::Client::new(),
+        url: format!("http://{}", server.local_addr().unwrap()),
+    };
+    let rpc = server.spawn();
+
+    let history = client
+        .ok(&format!("/addresses/{address}/history?offset=1"))
+        .await;
+    assert_eq!(history["total"], 2);
+    let items = history["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["height"], 3);
+    assert!(items[0]["delta"].as_i64().unwrap() > 0);
+
+    let (status, _) = client
+        .get(&format!("/addresses/{address}/history?limit=0"))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    rpc.shutdown().await.unwrap();
+}
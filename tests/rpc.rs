@@ -0,0 +1,357 @@
+use serde_json::{Value, json};
+use tiny_crypto::{
+    block::BlockValidationError,
+    config::{MemPoolConfig, NodeConfig},
+    crypto::{Address, HashExt, KeyPair},
+    node::{Message, Node, NodeEvent, build_payment},
+    outcome::RejectReason,
+    params::{Network, NetworkParams},
+    rpc::{
+        BLOCK_REJECTED, INVALID_PARAMS, METHOD_NOT_FOUND, REJECTED, RpcClient, RpcClientError,
+        RpcServer,
+    },
+    shared_node::SharedNode,
+};
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl Client {
+    async fn post(&self, token: Option<&str>, method: &str, params: Value) -> reqwest::Response {
+        let mut request = self.http.post(&self.url).json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        }));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.unwrap()
+    }
+
+    /// The response body for a call with the right token.
+    async fn call(&self, method: &str, params: Value) -> Value {
+        let response = self.post(Some("hunter2"), method, params).await;
+        assert!(response.status().is_success());
+        response.json().await.unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_rpc_server() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_pair = KeyPair::generate();
+    let mut node = Node::new(
+        NodeConfig::builder(key_pair.clone())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    let block = node.create_block().unwrap();
+    node.handle_message(Message::NewBlock(block)).unwrap();
+    let tip_hash = node.tip_hash().unwrap().unwrap().display().to_string();
+    let address = node.address();
+    let unspent = node.list_unspent(&address).unwrap();
+
+    let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+        .await
+        .unwrap()
+        .with_auth_token("hunter2");
+    let client = Client {
+        http: reqwest::Client::new(),
+        url: format!("http://{}", server.local_addr().unwrap()),
+    };
+    let rpc = server.spawn();
+
+    for token in [None, Some("hunter3")] {
+        let response = client.post(token, "getblockcount", json!([])).await;
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+
+    assert_eq!(client.call("getblockcount", json!([])).await["result"], 2);
+    assert_eq!(
+        client.call("getbestblockhash", json!([])).await["result"],
+        tip_hash
+    );
+    let by_height = client.call("getblock", json!([2])).await["result"].clone();
+    assert_eq!(by_height["hash"], tip_hash);
+    assert_eq!(by_height["confirmations"], 1);
+    let by_hash = client.call("getblock", json!([tip_hash])).await["result"].clone();
+    assert_eq!(by_hash, by_height);
+
+    let balance = client
+        .call("getbalance", json!([address.to_string()]))
+        .await["result"]
+        .clone();
+    assert!(balance["confirmed"].as_u64().unwrap() > 0);
+    assert_eq!(
+        client.call("getpeerinfo", json!([])).await["result"],
+        json!([])
+    );
+
+    let error = |response: Value| response["error"]["code"].clone();
+    assert_eq!(
+        error(client.call("getnothing", json!([])).await),
+        METHOD_NOT_FOUND
+    );
+    assert_eq!(
+        error(client.call("getblock", json!([true])).await),
+        INVALID_PARAMS
+    );
+    assert_eq!(
+        error(client.call("getbalance", json!([])).await),
+        INVALID_PARAMS
+    );
+
+    // paying more than was signed for breaks the signature
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+    let mut payment = build_payment(&key_pair, Network::Regtest, unspent, to, 7, 1, 1).unwrap();
+    payment.body.outputs[0].value += 1;
+    let response = client
+        .call("sendrawtransaction", json!([payment.to_hex().unwrap()]))
+        .await;
+    assert_eq!(error(response), REJECTED);
+
+    rpc.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_external_mining() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_pair = KeyPair::generate();
+    let mut node = Node::new(
+        NodeConfig::builder(key_pair.clone())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .build(),
+    )
+    .unwrap();
+    let block = node.create_block().unwrap();
+    node.handle_message(Message::NewBlock(block)).unwrap();
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+    let unspent = node.list_unspent(&node.address()).unwrap();
+    let payment = build_payment(&key_pair, Network::Regtest, unspent, to, 7, 2, 1).unwrap();
+    node.handle_message(Message::NewTransaction(payment.clone()))
+        .unwrap();
+
+    let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+        .await
+        .unwrap();
+    let url = format!("http://{}", server.local_addr().unwrap());
+    let client = RpcClient::new(url.clone()).unwrap();
+    let rpc = server.spawn();
+
+    let template = client.get_block_template(None).await.unwrap();
+    assert_eq!(template.height, 3);
+    assert_eq!(template.transactions.len(), 1);
+    assert_eq!(template.transactions[0].txid, payment.id().unwrap());
+    assert_eq!(template.transactions[0].fee, 2);
+
+    // a long poll on the current tip answers once the block is in
+    let waiting = tokio::spawn({
+        let client = RpcClient::new(url).unwrap();
+        let longpollid = template.longpollid.clone();
+        async move { client.get_block_template(Some(&longpollid)).await }
+    });
+
+    let miner = KeyPair::generate();
+    let mut block = template.to_block(&miner, Network::Regtest).unwrap();
+    block.mine().unwrap();
+    let hash = client.submit_block(&block).await.unwrap();
+    assert_eq!(hash, block.header.hash().unwrap());
+    assert_eq!(client.get_block_count().await.unwrap(), 3);
+
+    let next = waiting.await.unwrap().unwrap();
+    assert_eq!(next.height, 4);
+    assert_eq!(next.previous_block_hash, hash);
+    assert!(next.transactions.is_empty());
+    let miner_address = Address::from_public_key_for(&miner.public_key, Network::Regtest);
+    assert_eq!(
+        client.get_balance(&miner_address).await.unwrap().confirmed,
+        template.coinbase_value
+    );
+
+    // resubmitting is harmless, but a block claiming too much isn't
+    assert_eq!(client.submit_block(&block).await.unwrap(), hash);
+    let mut greedy = next.clone();
+    greedy.coinbase_value += 1;
+    let mut block = greedy.to_block(&miner, Network::Regtest).unwrap();
+    block.mine().unwrap();
+    match client.submit_block(&block).await {
+        Err(RpcClientError::Rpc(error)) => {
+            assert_eq!(error.code, BLOCK_REJECTED);
+            assert!(error.message.contains("Coinbase"), "{error}");
+            assert_eq!(
+                error.reject_reason(),
+                Some(RejectReason::InvalidBlock(
+                    BlockValidationError::BadCoinbaseValue {
+                        value: next.coinbase_value + 1,
+                        expected: next.coinbase_value,
+                    }
+                ))
+            );
+        }
+        result => panic!("Expected a rejection, got {result:?}"),
+    }
+
+    // nor is one building on a block the node doesn't have
+    let mut detached = next.to_block(&miner, Network::Regtest).unwrap();
+    detached.header.previous_block_hash = [7; 32];
+    detached.mine().unwrap();
+    match client.submit_block(&detached).await {
+        Err(RpcClientError::Rpc(error)) => assert_eq!(
+            error.reject_reason(),
+            Some(RejectReason::InvalidBlock(
+                BlockValidationError::UnknownParent { parent: [7; 32] }
+            ))
+        ),
+        result => panic!("Expected a rejection, got {result:?}"),
+    }
+
+    rpc.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_transaction_submission() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_pair = KeyPair::generate();
+    let mut node = Node::new(
+        NodeConfig::builder(key_pair.clone())
+            .data_dir(dir.path())
+            .network(NetworkParams::regtest())
+            .mempool(MemPoolConfig {
+                min_fee: 2,
+                ..MemPoolConfig::default()
+            })
+            .build(),
+    )
+    .unwrap();
+    for _ in 0..2 {
+        let block = node.create_block().unwrap();
+        node.handle_message(Message::NewBlock(block)).unwrap();
+    }
+    let unspent = node.list_unspent(&node.address()).unwrap();
+    let pay = |unspent, amount, fee| {
+        let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+        build_payment(
+            &key_pair,
+            Network::Regtest,
+            vec![unspent],
+            to,
+            amount,
+            fee,
+            1,
+        )
+        .unwrap()
+    };
+    let payment = pay(unspent[0].clone(), 7, 2);
+    let double_spend = pay(unspent[0].clone(), 8, 2);
+    let cheap = pay(unspent[1].clone(), 7, 1);
+
+    let node = SharedNode::new(node);
+    let mut events = node.subscribe();
+    let server = RpcServer::bind(node.clone(), "127.0.0.1:0").await.unwrap();
+    let client = RpcClient::new(format!("http://{}", server.local_addr().unwrap())).unwrap();
+    let rpc = server.spawn();
+
+    // testing leaves the pool and the event stream alone
+    let acceptance = client.test_mem_pool_accept(&payment).await.unwrap();
+    assert!(acceptance.allowed);
+    assert_eq!(acceptance.fee, Some(2));
+    assert!(events.try_recv().is_err());
+
+    let txid = client.send_raw_transaction(&payment).await.unwrap();
+    assert_eq!(
+        events.try_recv().unwrap(),
+        NodeEvent::TransactionAccepted(txid.clone())
+    );
+    assert!(
+        client
+            .test_mem_pool_accept(&payment)
+            .await
+            .unwrap()
+            .duplicate
+    );
+
+    let conflict = RejectReason::Conflict { txid };
+    let acceptance = client.test_mem_pool_accept(&double_spend).await.unwrap();
+    assert!(!acceptance.allowed);
+    assert_eq!(acceptance.reject_reason, Some(conflict.clone()));
+
+    let rejection = |result| match result {
+        Err(RpcClientError::Rpc(error)) => {
+            assert_eq!(error.code, REJECTED);
+            error
+        }
+        result => panic!("Expected a rejection, got {result:?}"),
+    };
+    let error = rejection(client.send_raw_transaction(&double_spend).await);
+    assert_eq!(error.reject_reason(), Some(conflict));
+    assert_eq!(error.data.unwrap()["reason"], "conflict");
+
+    let error = rejection(client.send_raw_transaction(&cheap).await);
+    assert_eq!(
+        error.reject_reason(),
+        Some(RejectReason::LowFee { fee: 1, min_fee: 2 })
+    );
+    assert_eq!(client.get_mem_pool_info().await.unwrap().size, 1);
+
+    rpc.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_generate() {
+    let serve = async |params: NetworkParams, dir: &std::path::Path| {
+        let node = Node::new(
+            NodeConfig::builder(KeyPair::generate())
+                .data_dir(dir)
+                .network(params)
+                .build(),
+        )
+        .unwrap();
+        let server = RpcServer::bind(SharedNode::new(node), "127.0.0.1:0")
+            .await
+            .unwrap();
+        let client = RpcClient::new(format!("http://{}", server.local_addr().unwrap())).unwrap();
+        (client, server.spawn())
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let (client, rpc) = serve(NetworkParams::regtest(), dir.path()).await;
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+
+    let hashes = client.generate(3, &to).await.unwrap();
+    assert_eq!(hashes.len(), 3);
+    assert_eq!(client.get_block_count().await.unwrap(), 4);
+    assert_eq!(client.get_best_block_hash().await.unwrap(), hashes[2]);
+    assert_eq!(client.get_block_at(2).await.unwrap().hash, hashes[0]);
+    let reward = NetworkParams::regtest().block_reward(2);
+    assert_eq!(client.get_balance(&to).await.unwrap().confirmed, 3 * reward);
+
+    // an address for another network is refused before anything is mined
+    let mainnet = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Mainnet);
+    match client.generate(1, &mainnet).await {
+        Err(RpcClientError::Rpc(error)) => assert_eq!(error.code, INVALID_PARAMS),
+        result => panic!("Expected invalid params, got {result:?}"),
+    }
+    assert_eq!(client.get_block_count().await.unwrap(), 4);
+    rpc.shutdown().await.unwrap();
+
+    // only regtest generates blocks on demand
+    let dir = tempfile::tempdir().unwrap();
+    let (client, rpc) = serve(NetworkParams::testnet(), dir.path()).await;
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Testnet);
+    match client.generate(1, &to).await {
+        Err(RpcClientError::Rpc(error)) => {
+            assert_eq!(error.code, REJECTED);
+            assert!(error.message.contains("regtest"), "{}", error.message);
+        }
+        result => panic!("Expected a rejection, got {result:?}"),
+    }
+    assert_eq!(client.get_block_count().await.unwrap(), 1);
+    rpc.shutdown().await.unwrap();
+}
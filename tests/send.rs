@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use assert_cmd::Command;
+use tiny_crypto::{
+    config::{MemPoolConfig, NodeConfig},
+    crypto::{Address, KeyPair},
+    node::{Message, Node},
+    params::{Network, NetworkParams},
+    rpc::RpcServer,
+    shared_node::SharedNode,
+};
+
+/// A regtest node whose coinbase is paid to `key_pair`, with one block mined.
+fn funded_node(data_dir: &std::path::Path, key_pair: &KeyPair, min_fee: u64) -> SharedNode {
+    let mut node = Node::new(
+        NodeConfig::builder(key_pair.clone())
+            .data_dir(data_dir)
+            .network(NetworkParams::regtest())
+            .mempool(MemPoolConfig {
+                min_fee,
+                ..MemPoolConfig::default()
+            })
+            .build(),
+    )
+    .unwrap();
+    let block = node.create_block().unwrap();
+    node.handle_message(Message::NewBlock(block)).unwrap();
+    SharedNode::new(node)
+}
+
+fn send(url: &str, key: &std::path::Path, args: &[&str]) -> std::process::Output {
+    Command::cargo_bin("tiny-crypto")
+        .unwrap()
+        .args(["send", "--network", "regtest", "--rpc", url])
+        .arg("--key")
+        .arg(key)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_and_wait_confirm() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("payer.key");
+    let key_pair = KeyPair::generate();
+    key_pair.save_plaintext(&key).unwrap();
+
+    let node = funded_node(&dir.path().join("data"), &key_pair, 0);
+    let server = RpcServer::bind(node.clone(), "127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", server.local_addr().unwrap());
+    let rpc = server.spawn();
+
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+    let sending = tokio::task::spawn_blocking({
+        let to = to.to_string();
+        move || {
+            send(
+                &url,
+                &key,
+                &["--to", &to, "--amount", "7", "--wait-confirm", "1"],
+            )
+        }
+    });
+
+    // mine once the payment is pending, which the waiting command then sees confirmed
+    while node
+        .read()
+        .await
+        .state
+        .mem_pool
+        .pending_transactions
+        .is_empty()
+    {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let paid = node.read().await.state.mem_pool.pending_transactions[0]
+        .id()
+        .unwrap();
+    let block = node.write().await.create_block().unwrap();
+    node.handle_message(Message::NewBlock(block)).await.unwrap();
+
+    let output = sending.await.unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let printed = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(printed.trim(), paid.to_string());
+    assert_eq!(node.get_confirmed_balance(&to).await, 7);
+
+    rpc.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("payer.key");
+    let key_pair = KeyPair::generate();
+    key_pair.save_plaintext(&key).unwrap();
+    let to = Address::from_public_key_for(&key_pair.public_key, Network::Regtest).to_string();
+
+    let node = funded_node(&dir.path().join("data"), &key_pair, 5);
+    let server = RpcServer::bind(node, "127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", server.local_addr().unwrap());
+    let rpc = server.spawn();
+
+    let stderr = |output: std::process::Output| {
+        assert!(!output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let rejected = tokio::task::spawn_blocking({
+        let (url, key, to) = (url.clone(), key.clone(), to.clone());
+        move || send(&url, &key, &["--to", &to, "--amount", "1", "--fee", "1"])
+    });
+    assert!(
+        stderr(rejected.await.unwrap()).contains(
+            "Node rejected the transaction: Transaction fee of 1 is below the minimum of 5"
+        )
+    );
+
+    let unreachable = tokio::task::spawn_blocking(move || {
+        send("http://127.0.0.1:1", &key, &["--to", &to, "--amount", "1"])
+    });
+    assert!(stderr(unreachable.await.unwrap()).contains("Can't reach the node"));
+
+    rpc.shutdown().await.unwrap();
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{json_output, tiny_crypto};
+use predicates::str::contains;
+use tiny_crypto::{
+    crypto::{KeyPair, from_hex, to_hex},
+    params::Network,
+    signed_message::message_digest,
+};
+
+const SEED_HEX: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+
+#[test]
+fn test_sign_then_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("node.key");
+    tiny_crypto()
+        .args(["keygen", "--seed-hex", SEED_HEX, "--out"])
+        .arg(&key)
+        .env("TINY_CRYPTO_PASSPHRASE", "hunter2")
+        .assert()
+        .success();
+
+    let signed = json_output(
+        tiny_crypto()
+            .args(["sign", "--message", "I control this address", "--key"])
+            .arg(&key)
+            .env("TINY_CRYPTO_PASSPHRASE", "hunter2"),
+    );
+    assert_eq!(
+        signed["digest"],
+        to_hex(message_digest("I control this address"))
+    );
+
+    let verify = |signature: &str, message: &str| {
+        let mut command = tiny_crypto();
+        command
+            .args(["verify", "--signature", signature, "--message", message])
+            .args(["--address", signed["address"].as_str().unwrap()]);
+        command
+    };
+    let signature = signed["signature"].as_str().unwrap();
+
+    verify(signature, "I control this address")
+        .assert()
+        .success()
+        .stdout("valid\n");
+    verify(signature, "I control this address.")
+        .assert()
+        .failure()
+        .stdout("invalid\n");
+}
+
+#[test]
+fn test_sign_binary_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("dev.key");
+    let fixture = dir.path().join("message.bin");
+    let message = [0xff, 0x00, 0xfe, b'\n'];
+    std::fs::write(&fixture, message).unwrap();
+
+    tiny_crypto()
+        .args([
+            "keygen",
+            "--seed-hex",
+            SEED_HEX,
+            "--insecure-plaintext",
+            "--out",
+        ])
+        .arg(&key)
+        .assert()
+        .success();
+
+    let from_file = json_output(
+        tiny_crypto()
+            .args(["sign", "--encoding", "hex", "--key"])
+            .arg(&key)
+            .arg("--file")
+            .arg(&fixture),
+    );
+    let from_stdin = json_output(
+        tiny_crypto()
+            .args(["sign", "--encoding", "hex", "--key"])
+            .arg(&key)
+            .write_stdin(message),
+    );
+
+    assert_eq!(from_file, from_stdin);
+    assert_eq!(from_file["digest"], to_hex(message_digest(message)));
+    assert_eq!(from_file["signature"].as_str().unwrap().len(), 130);
+}
+
+#[test]
+fn test_verify_flipped_signature() {
+    let public_key = KeyPair::from_seed(&[1; 32]).unwrap().public_key.to_string();
+    let signed = json_output(
+        tiny_crypto()
+            .args(["sign", "--message", "challenge", "--encoding", "hex"])
+            .env(
+                "TINY_CRYPTO_WIF",
+                KeyPair::from_seed(&[1; 32])
+                    .unwrap()
+                    .to_wif(Network::Mainnet),
+            ),
+    );
+    let signature = signed["signature"].as_str().unwrap();
+
+    let verify = |signature: &str| {
+        let mut command = tiny_crypto();
+        command.args(["verify", "--signature", signature, "--message", "challenge"]);
+        command
+    };
+
+    verify(signature)
+        .args(["--pubkey", &public_key])
+        .assert()
+        .success()
+        .stdout("valid\n");
+
+    let mut flipped = from_hex(signature).unwrap();
+    flipped[40] ^= 1;
+    verify(&to_hex(&flipped))
+        .args(["--pubkey", &public_key])
+        .assert()
+        .failure()
+        .stdout("invalid\n");
+    verify(&to_hex(&flipped))
+        .args(["--address", signed["address"].as_str().unwrap()])
+        .assert()
+        .failure();
+
+    verify("not a signature")
+        .args(["--pubkey", &public_key])
+        .assert()
+        .failure()
+        .stderr(contains("neither base64 nor hex"));
+    verify(signature)
+        .args([
+            "--address",
+            signed["address"].as_str().unwrap(),
+            "--network",
+            "testnet",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("another network"));
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use std::path::Path;
+
+use assert_cmd::Command;
+use common::{json_output, tiny_crypto};
+use predicates::str::contains;
+use tiny_crypto::{
+    crypto::{Address, KeyPair},
+    params::Network,
+    transaction::{Transaction, TransactionInput},
+};
+
+fn tx_create(dir: &Path, key: &Path, to: &str, amount: &str) -> Command {
+    let mut command = tiny_crypto();
+    command
+        .args(["tx", "create", "--network", "regtest", "--to", to])
+        .args(["--amount", amount, "--fee", "1"])
+        .arg("--data-dir")
+        .arg(dir)
+        .arg("--key")
+        .arg(key);
+    command
+}
+
+#[test]
+fn test_tx_create() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let key = dir.path().join("miner.key");
+    let key_pair = KeyPair::generate();
+    key_pair.save_plaintext(&key).unwrap();
+    common::mine(&data_dir, &key, Network::Regtest, 2);
+
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+    let output = tx_create(&data_dir, &key, &to.to_string(), "10")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let created: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let transaction = Transaction::from_hex(created["hex"].as_str().unwrap()).unwrap();
+    assert!(transaction.verify_signature().unwrap());
+    assert_eq!(
+        created["txid"],
+        serde_json::to_value(transaction.id().unwrap()).unwrap()
+    );
+    assert_eq!(transaction.body.outputs[0].address, to);
+    assert_eq!(transaction.body.outputs[0].value, 10);
+    assert_eq!(
+        transaction.body.outputs[1].address,
+        Address::from_public_key_for(&key_pair.public_key, Network::Regtest)
+    );
+    assert_eq!(created["fee"], 1);
+    assert_eq!(
+        created["inputs"][0]["value"],
+        transaction.output_value() + 1
+    );
+    assert!(matches!(
+        transaction.body.input,
+        TransactionInput::Reference {
+            relative_lock: None,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_tx_create_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let key = dir.path().join("miner.key");
+    let key_pair = KeyPair::generate();
+    key_pair.save_plaintext(&key).unwrap();
+    common::mine(&data_dir, &key, Network::Regtest, 2);
+
+    let to = Address::from_public_key_for(&key_pair.public_key, Network::Regtest).to_string();
+    tx_create(&data_dir, &key, &to, "1000000000000")
+        .assert()
+        .failure()
+        .stderr(contains("Insufficient funds"));
+
+    let mainnet = Address::from_public_key(&key_pair.public_key).to_string();
+    tx_create(&data_dir, &key, &mainnet, "10")
+        .assert()
+        .failure()
+        .stderr(contains("Invalid destination address"));
+}
+
+#[test]
+fn test_tx_decode() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let key = dir.path().join("miner.key");
+    KeyPair::generate().save_plaintext(&key).unwrap();
+    common::mine(&data_dir, &key, Network::Regtest, 2);
+
+    let to = Address::from_public_key_for(&KeyPair::generate().public_key, Network::Regtest);
+    let output = tx_create(&data_dir, &key, &to.to_string(), "10")
+        .output()
+        .unwrap();
+    let created: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let hex = created["hex"].as_str().unwrap();
+
+    let decode = |prepare: &dyn Fn(&mut Command)| -> serde_json::Value {
+        let mut command = tiny_crypto();
+        command.args(["tx", "decode"]);
+        prepare(&mut command);
+        json_output(&mut command)
+    };
+
+    let decoded = decode(&|command| {
+        command.arg(hex);
+    });
+    assert_eq!(decoded["txid"], created["txid"]);
+    assert_eq!(decoded["outputs"], created["outputs"]);
+    assert_eq!(
+        decoded["input"]["Reference"]["outpoint"],
+        created["inputs"][0]["outpoint"]
+    );
+    assert_eq!(decoded["size"], hex.len() / 2);
+    assert_eq!(decoded["signature_valid"], true);
+    assert!(decoded.get("fee").is_none());
+
+    let resolved = decode(&|command| {
+        command
+            .write_stdin(format!("{hex}\n"))
+            .args(["--network", "regtest", "--data-dir"])
+            .arg(&data_dir);
+    });
+    assert_eq!(resolved["fee"], 1);
+    assert_eq!(resolved["input_value"], created["inputs"][0]["value"]);
+
+    tiny_crypto()
+        .args(["tx", "decode", &hex[..hex.len() - 10]])
+        .assert()
+        .failure()
+        .stderr(contains("truncated"));
+    tiny_crypto()
+        .args(["tx", "decode", &format!("{}zz", &hex[..20])])
+        .assert()
+        .failure()
+        .stderr(contains("at offset 20"));
+    tiny_crypto()
+        .args(["tx", "decode", &format!("{hex}00")])
+        .assert()
+        .failure()
+        .stderr(contains(format!("offset {}", hex.len())));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::tiny_crypto;
+use tiny_crypto::{crypto::KeyPair, params::Network};
+
+fn validate_chain(data_dir: &Path, args: &[&str]) -> (bool, serde_json::Value) {
+    let output = tiny_crypto()
+        .args(["validate-chain", "--network", "regtest", "--output", "json"])
+        .arg("--data-dir")
+        .arg(data_dir)
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        serde_json::from_slice(&output.stdout).unwrap(),
+    )
+}
+
+/// The file holding the stored block at `height`.
+fn block_file(data_dir: &Path, height: u64) -> PathBuf {
+    std::fs::read_dir(data_dir.join("blocks"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            let block: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+            block["height"] == height
+        })
+        .unwrap()
+}
+
+#[test]
+fn test_validate_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+    let key = dir.path().join("miner.key");
+    KeyPair::generate().save_plaintext(&key).unwrap();
+    common::mine(&data_dir, &key, Network::Regtest, 4);
+
+    let (valid, report) = validate_chain(&data_dir, &["--check-utxo"]);
+    assert!(valid, "{report}");
+    assert_eq!(report["validated"], 5);
+    assert_eq!(report["supply"]["actual"], report["supply"]["expected"]);
+
+    let (valid, report) = validate_chain(&data_dir, &["--from-height", "4"]);
+    assert!(valid);
+    assert_eq!(report["validated"], 2);
+
+    // a coinbase claiming one more coin than the header commits to
+    let corrupted = block_file(&data_dir, 3);
+    let mut block: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&corrupted).unwrap()).unwrap();
+    let value = &mut block["transactions"][0]["body"]["outputs"][0]["value"];
+    *value = (value.as_u64().unwrap() + 1).into();
+    std::fs::write(&corrupted, serde_json::to_vec(&block).unwrap()).unwrap();
+
+    let (valid, report) = validate_chain(&data_dir, &["--check-utxo"]);
+    assert!(!valid);
+    assert_eq!(report["failure"]["height"], 3);
+    assert_eq!(report["validated"], 2);
+    assert!(report.get("supply").is_none());
+
+    // an unreadable file leaves the blocks after it orphaned
+    let truncated = block_file(&data_dir, 4);
+    let bytes = std::fs::read(&truncated).unwrap();
+    std::fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+
+    let (valid, report) = validate_chain(&data_dir, &[]);
+    assert!(!valid);
+    let problems = report["storage_problems"].as_array().unwrap();
+    assert_eq!(problems.len(), 2, "{problems:?}");
+}
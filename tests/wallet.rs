@@ -0,0 +1,73 @@
+mod common;
+
+use common::{json_output, tiny_crypto};
+use tiny_crypto::{
+    mnemonic::Mnemonic,
+    params::{Network, NetworkParams},
+    wallet::Wallet,
+};
+
+const PHRASE: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+fn wallet(args: &[&str], path: &std::path::Path) -> serde_json::Value {
+    json_output(
+        tiny_crypto()
+            .arg("wallet")
+            .args(args)
+            .arg("--wallet")
+            .arg(path)
+            .env("TINY_CRYPTO_PASSPHRASE", "hunter2"),
+    )
+}
+
+#[test]
+fn test_create_new_address_rescan() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("wallet");
+    let data_dir = dir.path().join("data");
+
+    let created = wallet(
+        &["create", "--network", "regtest", "--recover", PHRASE],
+        &path,
+    );
+    assert_eq!(created["network"], "regtest");
+    assert_eq!(created["next_index"], 0);
+
+    let first = wallet(&["new-address", "--label", "mining"], &path);
+    assert_eq!(first["path"], "m/44'/0'/0'/0/0");
+    assert_eq!(first["label"], "mining");
+    let second = wallet(&["new-address"], &path);
+    assert_eq!(second["index"], 1);
+
+    // fund the first address by mining to its key
+    let restored =
+        Wallet::from_mnemonic(&Mnemonic::parse(PHRASE).unwrap(), "", Network::Regtest).unwrap();
+    assert_eq!(first["address"], restored.address(0).unwrap().to_string());
+    let key = dir.path().join("first.key");
+    restored.keypair(0).unwrap().save_plaintext(&key).unwrap();
+    common::mine(&data_dir, &key, Network::Regtest, 2);
+
+    let rescanned = wallet(&["rescan", "--data-dir", data_dir.to_str().unwrap()], &path);
+    let params = NetworkParams::regtest();
+    assert_eq!(
+        rescanned["balance"],
+        params.block_reward(2) + params.block_reward(3)
+    );
+    assert_eq!(rescanned["scanned_height"], 3);
+    assert_eq!(rescanned["outputs"].as_array().unwrap().len(), 2);
+    assert_eq!(rescanned["outputs"][0]["path"], "m/44'/0'/0'/0/0");
+
+    // the cache and labels survive in the wallet file
+    let info = wallet(&["info"], &path);
+    assert_eq!(info["balance"], rescanned["balance"]);
+    assert_eq!(info["addresses"][0]["label"], "mining");
+    assert_eq!(info["addresses"].as_array().unwrap().len(), 2);
+
+    tiny_crypto()
+        .args(["wallet", "unlock", "--passphrase", "hunter3"])
+        .arg("--wallet")
+        .arg(&path)
+        .assert()
+        .failure();
+}
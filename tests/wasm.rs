@@ -0,0 +1,57 @@
+//! The core modules build for `wasm32-unknown-unknown` without the `std-node` feature,
+//! and so do the browser bindings in `examples/wasm`. secp256k1 compiles C, so this
+//! needs `clang` (or `CC_wasm32_unknown_unknown`) as well as the target. It's ignored
+//! unless asked for with `--ignored`, and fails without either; CI makes the same checks
+//! in a job of its own.
+
+use std::{env, path::Path, process::Command};
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+fn toolchain_missing() -> Option<&'static str> {
+    let target_installed = Command::new("rustc")
+        .args(["--print", "target-libdir", "--target", TARGET])
+        .output()
+        .is_ok_and(|output| {
+            output.status.success()
+                && Path::new(String::from_utf8_lossy(&output.stdout).trim()).exists()
+        });
+    if !target_installed {
+        return Some("the wasm32-unknown-unknown target isn't installed");
+    }
+
+    let has_compiler = env::var_os("CC_wasm32_unknown_unknown").is_some()
+        || Command::new("clang")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success());
+    if !has_compiler {
+        return Some("there's no clang to build secp256k1 with");
+    }
+    None
+}
+
+fn cargo_check(args: &[&str]) {
+    let status = Command::new(env!("CARGO"))
+        .args(["check", "--target", TARGET])
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join("wasm"),
+        )
+        .status()
+        .unwrap();
+    assert!(status.success(), "cargo check {args:?} failed");
+}
+
+#[test]
+#[ignore = "needs the wasm32-unknown-unknown target and clang"]
+fn test_wasm_check() {
+    if let Some(reason) = toolchain_missing() {
+        panic!("can't run the wasm32 check: {reason}");
+    }
+
+    cargo_check(&["--lib", "--no-default-features"]);
+    cargo_check(&["--manifest-path", "examples/wasm/Cargo.toml"]);
+}
@@ -0,0 +1,72 @@
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tiny_crypto::{
+    config::NodeConfig,
+    crypto::{HashExt, KeyPair},
+    node::{Message, Node},
+    params::NetworkParams,
+    rpc::RpcServer,
+    shared_node::SharedNode,
+};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+#[tokio::test]
+async fn test_ws_notifications() {
+    let dir = tempfile::tempdir().unwrap();
+    let node = SharedNode::new(
+        Node::new(
+            NodeConfig::builder(KeyPair::generate())
+                .data_dir(dir.path())
+                .network(NetworkParams::regtest())
+                .build(),
+        )
+        .unwrap(),
+    );
+    let address = node.read().await.address();
+    let server = RpcServer::bind(node.clone(), "127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", server.local_addr().unwrap());
+    let rpc = server.spawn();
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let mut request = async |request: Value| {
+        socket
+            .send(WsMessage::text(request.to_string()))
+            .await
+            .unwrap();
+        next(&mut socket).await
+    };
+    let error = request(json!({ "subscribe": ["blocks", "nothing"] })).await;
+    assert_eq!(error["type"], "error");
+    let subscribed =
+        request(json!({ "subscribe": ["blocks", format!("address:{address}")] })).await;
+    assert_eq!(subscribed["channels"].as_array().unwrap().len(), 2);
+
+    let block = node.write().await.create_block().unwrap();
+    let hash = block.header.hash().unwrap().display().to_string();
+    node.handle_message(Message::NewBlock(block)).await.unwrap();
+
+    // the block's coinbase pays the node before the tip moves to it
+    let received = next(&mut socket).await;
+    assert_eq!(received["type"], "received");
+    assert_eq!(received["address"], address.to_string());
+    assert_eq!(received["pending"], false);
+    let tip = next(&mut socket).await;
+    assert_eq!(tip["type"], "tip");
+    assert_eq!(tip["hash"], hash);
+    assert_eq!(tip["height"], 2);
+
+    // shutting down closes the socket rather than waiting on it
+    rpc.shutdown().await.unwrap();
+}
+
+async fn next<S>(socket: &mut S) -> Value
+where
+    S: StreamExt<Item = Result<WsMessage, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            WsMessage::Text(text) => return serde_json::from_str(&text).unwrap(),
+            _ => continue,
+        }
+    }
+}